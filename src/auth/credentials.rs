@@ -0,0 +1,132 @@
+use anyhow::{Result, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::keyring;
+use crate::config;
+
+/// A credential saved by `ai-cli auth login`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredCredential {
+    /// Empty when the key is kept in the OS keyring
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_key: String,
+    /// The key is in the OS keyring under the provider's name
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keyring: bool,
+}
+
+/// File-backed credential store (`credentials.toml`, readable only by the owner)
+///
+/// Keys saved with `auth login --keyring` live in the OS keyring; the file only
+/// records that they are there.
+pub struct CredentialStore {
+    path: PathBuf,
+    entries: BTreeMap<String, StoredCredential>,
+}
+
+impl CredentialStore {
+    /// Load the store from a file, starting empty if it does not exist
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = if path.exists() {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            toml::from_str(&text)
+                .with_context(|| format!("Invalid credentials file {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { path, entries })
+    }
+
+    /// Open the store in the user config directory
+    pub fn open_default() -> Result<Self> {
+        Self::load(config::config_dir()?.join("credentials.toml"))
+    }
+
+    /// Get the store file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the API key stored in the file for a provider
+    pub fn get(&self, provider: &str) -> Option<&str> {
+        self.entries.get(provider).map(|c| c.api_key.as_str()).filter(|key| !key.is_empty())
+    }
+
+    /// Whether a provider's key was saved in the OS keyring
+    pub fn in_keyring(&self, provider: &str) -> bool {
+        self.entries.get(provider).is_some_and(|c| c.keyring)
+    }
+
+    /// Store an API key for a provider
+    pub fn set(&mut self, provider: impl Into<String>, api_key: impl Into<String>) {
+        self.entries.insert(provider.into(), StoredCredential { api_key: api_key.into(), keyring: false });
+    }
+
+    /// Store an API key for a provider in the OS keyring, recording only that it is there
+    pub fn set_in_keyring(&mut self, provider: impl Into<String>, api_key: &str) -> Result<()> {
+        let provider = provider.into();
+        keyring::store(&provider, &format!("ai-cli {} API key", provider), api_key)?;
+        self.entries.insert(provider, StoredCredential { api_key: String::new(), keyring: true });
+        Ok(())
+    }
+
+    /// Remove a provider's credential, returning whether one was stored
+    ///
+    /// A key in the OS keyring is deleted from it as well.
+    pub fn remove(&mut self, provider: &str) -> Result<bool> {
+        let removed = self.entries.remove(provider);
+        if removed.as_ref().is_some_and(|c| c.keyring) {
+            keyring::delete(provider)?;
+        }
+        Ok(removed.is_some())
+    }
+
+    /// Providers with stored credentials
+    pub fn providers(&self) -> Vec<&str> {
+        self.entries.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// Persist the store, readable only by the owner on Unix
+    ///
+    /// The file is written to a temporary file created with mode 0600 and renamed
+    /// over the old one, so keys are never readable by others, not even briefly.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let temp = self.path.with_extension(format!("toml.{}.tmp", std::process::id()));
+        let written = write_private(&temp, toml::to_string(&self.entries)?.as_bytes())
+            .and_then(|()| std::fs::rename(&temp, &self.path))
+            .with_context(|| format!("Failed to write {}", self.path.display()));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        written
+    }
+}
+
+/// Create `path` readable and writable only by the owner, then write `contents`
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // A leftover temp file from a crashed run keeps its old mode
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()
+}
@@ -0,0 +1,244 @@
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+
+use super::{AuthMethod, TokenRefresher};
+
+const TOKEN_ENDPOINT: &str = "https://oauth2.googleapis.com/token";
+
+/// Refresh tokens this long before they actually expire
+const EXPIRY_SKEW: Duration = Duration::from_secs(60);
+
+/// Lifetime assumed for tokens printed by gcloud, which does not report expiry
+const GCLOUD_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// Kind of Application Default Credentials that were loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdcKind {
+    /// `gcloud auth application-default login` user credentials
+    AuthorizedUser,
+    /// Delegate token minting to `gcloud auth application-default print-access-token`
+    Gcloud,
+}
+
+/// Short-lived OAuth access token
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_at: SystemTime,
+}
+
+impl AccessToken {
+    /// Check whether the token is expired (or about to expire)
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() + EXPIRY_SKEW >= self.expires_at
+    }
+}
+
+#[derive(Debug, Clone)]
+enum AdcSource {
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+    Gcloud,
+}
+
+#[derive(Deserialize)]
+struct AdcFile {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    client_secret: Option<String>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    quota_project_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Google Application Default Credentials with cached token refresh
+pub struct GoogleAdc {
+    source: AdcSource,
+    quota_project: Option<String>,
+    cached: Mutex<Option<AccessToken>>,
+}
+
+impl GoogleAdc {
+    /// Load ADC from GOOGLE_APPLICATION_CREDENTIALS or the gcloud well-known locations
+    pub fn load() -> Result<Self> {
+        let path = Self::credentials_paths()
+            .into_iter()
+            .find(|p| p.exists())
+            .ok_or_else(|| anyhow!("No Google Application Default Credentials found"))?;
+        Self::from_file(&path)
+    }
+
+    /// Load ADC from a specific credentials file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ADC file {}", path.display()))?;
+        Self::from_json(&text)
+    }
+
+    /// Parse ADC from the JSON contents of a credentials file
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: AdcFile = serde_json::from_str(json)
+            .with_context(|| "Failed to parse Application Default Credentials")?;
+
+        let source = match file.kind.as_str() {
+            "authorized_user" => AdcSource::AuthorizedUser {
+                client_id: file.client_id.ok_or_else(|| anyhow!("ADC is missing client_id"))?,
+                client_secret: file.client_secret.ok_or_else(|| anyhow!("ADC is missing client_secret"))?,
+                refresh_token: file.refresh_token.ok_or_else(|| anyhow!("ADC is missing refresh_token"))?,
+            },
+            // Service accounts and external accounts need JWT signing or token
+            // exchange; let gcloud mint tokens for them instead.
+            "service_account" | "external_account" | "impersonated_service_account" => AdcSource::Gcloud,
+            other => return Err(anyhow!("Unsupported ADC credential type: {}", other)),
+        };
+
+        Ok(Self {
+            source,
+            quota_project: file.quota_project_id,
+            cached: Mutex::new(None),
+        })
+    }
+
+    /// Use gcloud to mint access tokens
+    pub fn gcloud() -> Self {
+        Self {
+            source: AdcSource::Gcloud,
+            quota_project: std::env::var("GOOGLE_CLOUD_QUOTA_PROJECT").ok(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Drop the cached access token so the next request mints a new one
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    /// Candidate ADC file locations, in lookup order
+    pub fn credentials_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Ok(path) = std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+            paths.push(PathBuf::from(path));
+        }
+        if let Some(home) = dirs::home_dir() {
+            paths.push(home.join(".config/gcloud/application_default_credentials.json"));
+        }
+        if let Ok(appdata) = std::env::var("APPDATA") {
+            paths.push(PathBuf::from(appdata).join("gcloud/application_default_credentials.json"));
+        }
+        paths
+    }
+
+    /// Get the kind of credentials in use
+    pub fn kind(&self) -> AdcKind {
+        match self.source {
+            AdcSource::AuthorizedUser { .. } => AdcKind::AuthorizedUser,
+            AdcSource::Gcloud => AdcKind::Gcloud,
+        }
+    }
+
+    /// Project billed for API quota, sent as x-goog-user-project
+    pub fn quota_project(&self) -> Option<&str> {
+        self.quota_project.as_deref()
+    }
+
+    /// Get a valid access token, refreshing it when expired
+    pub async fn access_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref()
+            && !token.is_expired()
+        {
+            return Ok(token.token.clone());
+        }
+
+        let token = self.mint().await?;
+        let value = token.token.clone();
+        *cached = Some(token);
+        Ok(value)
+    }
+
+    /// Mint a fresh access token from the configured source
+    async fn mint(&self) -> Result<AccessToken> {
+        match &self.source {
+            AdcSource::AuthorizedUser { client_id, client_secret, refresh_token } => {
+                let request = crate::providers::http::shared_client()
+                    .post(TOKEN_ENDPOINT)
+                    .form(&[
+                        ("client_id", client_id.as_str()),
+                        ("client_secret", client_secret.as_str()),
+                        ("refresh_token", refresh_token.as_str()),
+                        ("grant_type", "refresh_token"),
+                    ]);
+                let resp = crate::providers::http::send("google-oauth", request)
+                    .await
+                    .with_context(|| "Failed to refresh Google OAuth token")?;
+
+                if !resp.status().is_success() {
+                    return Err(crate::providers::http::error_for_status("google-oauth", "Google token refresh failed", resp).await);
+                }
+
+                let parsed: TokenResponse = resp.json().await
+                    .with_context(|| "Failed to parse Google token response")?;
+                let lifetime = Duration::from_secs(parsed.expires_in.unwrap_or(3600));
+                Ok(AccessToken {
+                    token: parsed.access_token,
+                    expires_at: SystemTime::now() + lifetime,
+                })
+            }
+            AdcSource::Gcloud => {
+                let output = tokio::process::Command::new("gcloud")
+                    .args(["auth", "application-default", "print-access-token"])
+                    .output()
+                    .await
+                    .with_context(|| "Failed to run gcloud (is the Google Cloud SDK installed?)")?;
+
+                if !output.status.success() {
+                    return Err(anyhow!(
+                        "gcloud print-access-token failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+
+                let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if token.is_empty() {
+                    return Err(anyhow!("gcloud returned an empty access token"));
+                }
+                Ok(AccessToken {
+                    token,
+                    expires_at: SystemTime::now() + GCLOUD_TOKEN_LIFETIME,
+                })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TokenRefresher for GoogleAdc {
+    /// Mint a new access token, as an account session that expires with it
+    async fn refresh(&self, provider: &str, _current: &AuthMethod) -> Result<AuthMethod> {
+        let token = self.mint().await?;
+        *self.cached.lock().await = Some(token.clone());
+        Ok(AuthMethod::AccountBased {
+            provider: provider.to_string(),
+            session_token: Some(token.token),
+            expires_at: Some(token.expires_at),
+        })
+    }
+}
@@ -0,0 +1,124 @@
+//! Secrets in the OS keyring: the macOS Keychain through `security`, or the
+//! Secret Service through `secret-tool` on other Unix systems
+//!
+//! Entries live under the `ai-cli` service, one account per secret (a
+//! provider name, or `history` for the history encryption key). Secrets are
+//! always passed on stdin, never as arguments visible in the process list.
+
+/// Keyring service of every ai-cli entry
+pub const SERVICE: &str = "ai-cli";
+
+pub use imp::{delete, lookup, store};
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::SERVICE;
+    use anyhow::{Context as AnyhowContext, Result, anyhow};
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    /// The secret stored for `account`, if any
+    pub fn lookup(account: &str) -> Result<Option<String>> {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+            .output()
+            .context("Failed to run `security` to read the Keychain")?;
+        let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success() && !secret.is_empty()).then_some(secret))
+    }
+
+    /// Store `secret` for `account`, replacing any previous one
+    pub fn store(account: &str, _label: &str, secret: &str) -> Result<()> {
+        // `security -i` reads the command from stdin, keeping the secret out of argv
+        let command = format!("add-generic-password -U -s {} -a {} -w {}\n", quote(SERVICE), quote(account), quote(secret));
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("Failed to run `security` to write the Keychain")?;
+        child.stdin.take().expect("piped stdin").write_all(command.as_bytes())?;
+        let status = child.wait()?;
+        status.success().then_some(()).ok_or_else(|| anyhow!("Could not save the secret in the Keychain"))
+    }
+
+    /// Remove the secret for `account`, returning whether there was one
+    pub fn delete(account: &str) -> Result<bool> {
+        let status = Command::new("security")
+            .args(["delete-generic-password", "-s", SERVICE, "-a", account])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to run `security` to update the Keychain")?;
+        Ok(status.success())
+    }
+
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use super::SERVICE;
+    use anyhow::{Context as AnyhowContext, Result, anyhow};
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const HINT: &str = "Failed to run `secret-tool` (install libsecret-tools)";
+
+    /// The secret stored for `account`, if any
+    pub fn lookup(account: &str) -> Result<Option<String>> {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "service", SERVICE, "account", account])
+            .output()
+            .context(HINT)?;
+        let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success() && !secret.is_empty()).then_some(secret))
+    }
+
+    /// Store `secret` for `account`, replacing any previous one
+    pub fn store(account: &str, label: &str, secret: &str) -> Result<()> {
+        let mut child = Command::new("secret-tool")
+            .args(["store", &format!("--label={}", label), "service", SERVICE, "account", account])
+            .stdin(Stdio::piped())
+            .spawn()
+            .context(HINT)?;
+        child.stdin.take().expect("piped stdin").write_all(secret.as_bytes())?;
+        let status = child.wait()?;
+        status.success().then_some(()).ok_or_else(|| anyhow!("Could not save the secret with secret-tool"))
+    }
+
+    /// Remove the secret for `account`, returning whether there was one
+    pub fn delete(account: &str) -> Result<bool> {
+        if lookup(account)?.is_none() {
+            return Ok(false);
+        }
+        let status = Command::new("secret-tool")
+            .args(["clear", "service", SERVICE, "account", account])
+            .status()
+            .context(HINT)?;
+        status.success().then_some(true).ok_or_else(|| anyhow!("Could not remove the secret with secret-tool"))
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use anyhow::{Result, anyhow};
+
+    fn unsupported() -> anyhow::Error {
+        anyhow!("The OS keyring is not supported on this platform")
+    }
+
+    pub fn lookup(_account: &str) -> Result<Option<String>> {
+        Err(unsupported())
+    }
+
+    pub fn store(_account: &str, _label: &str, _secret: &str) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn delete(_account: &str) -> Result<bool> {
+        Err(unsupported())
+    }
+}
@@ -1,13 +1,28 @@
 use anyhow::{Result, anyhow};
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub mod credentials;
+pub mod google;
+pub mod keyring;
+pub mod refresh;
+
+pub use credentials::CredentialStore;
+pub use refresh::{ManagedCredentials, TokenRefresher};
+
+use crate::config::{Profile, ProviderSettings};
 
 #[derive(Debug, Clone)]
 pub enum AuthMethod {
     AccountBased {
         provider: String,
         session_token: Option<String>,
+        /// When the session token expires, if known
+        expires_at: Option<SystemTime>,
     },
     ApiKey {
         key: String,
@@ -18,6 +33,59 @@ pub enum AuthMethod {
     CliAuth,
 }
 
+impl AuthMethod {
+    /// Short label: `api-key`, `account`, `browser` or `cli-session`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthMethod::AccountBased { .. } => "account",
+            AuthMethod::ApiKey { .. } => "api-key",
+            AuthMethod::BrowserAuth { .. } => "browser",
+            AuthMethod::CliAuth => "cli-session",
+        }
+    }
+}
+
+/// Where detected credentials come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthSource {
+    /// The active auth profile in config.toml
+    Profile { name: String },
+    /// An existing CLI/desktop session marker file
+    CliSession { path: PathBuf },
+    /// A key set through `AuthManager::set_api_key`
+    Programmatic,
+    /// An environment variable
+    Env { var: String },
+    /// The credential store written by `ai-cli auth login`
+    CredentialStore,
+    /// The OS keyring, for keys saved with `ai-cli auth login --keyring`
+    Keyring,
+}
+
+impl fmt::Display for AuthSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthSource::Profile { name } => write!(f, "profile '{}'", name),
+            AuthSource::CliSession { path } => write!(f, "CLI session ({})", path.display()),
+            AuthSource::Programmatic => write!(f, "programmatic"),
+            AuthSource::Env { var } => write!(f, "env {}", var),
+            AuthSource::CredentialStore => write!(f, "credential store"),
+            AuthSource::Keyring => write!(f, "keyring"),
+        }
+    }
+}
+
+/// Mask an API key for display, keeping only a short prefix and suffix
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 12 {
+        return "****".to_string();
+    }
+    let prefix: String = chars[..4].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", prefix, suffix)
+}
+
 #[derive(Debug)]
 pub struct ProviderAuth {
     pub provider: String,
@@ -26,45 +94,139 @@ pub struct ProviderAuth {
 
 pub struct AuthManager {
     api_keys: HashMap<String, String>,
+    profile: Option<(String, Profile)>,
+    credentials: Option<CredentialStore>,
+    refreshers: HashMap<String, Arc<dyn TokenRefresher>>,
+}
+
+impl Default for AuthManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AuthManager {
     pub fn new() -> Self {
         Self {
             api_keys: HashMap::new(),
+            profile: None,
+            credentials: None,
+            refreshers: HashMap::new(),
         }
     }
 
+    /// Create a manager that resolves credentials within a named profile
+    pub fn with_profile(name: impl Into<String>, profile: Profile) -> Self {
+        let mut manager = Self::new();
+        manager.set_profile(name, profile);
+        manager
+    }
+
+    /// Activate a named profile
+    pub fn set_profile(&mut self, name: impl Into<String>, profile: Profile) {
+        self.profile = Some((name.into(), profile));
+    }
+
+    /// Consult a credential store after the other sources
+    pub fn set_credential_store(&mut self, store: CredentialStore) {
+        self.credentials = Some(store);
+    }
+
+    /// Register a hook that renews a provider's credentials when they expire or are rejected
+    pub fn set_refresher(&mut self, provider: impl Into<String>, refresher: Arc<dyn TokenRefresher>) {
+        self.refreshers.insert(provider.into(), refresher);
+    }
+
+    /// Register the built-in hooks: Google ADC mints new tokens for Gemini account sessions
+    pub fn register_default_refreshers(&mut self) {
+        if !self.refreshers.contains_key("gemini")
+            && let Ok(adc) = google::GoogleAdc::load()
+        {
+            self.set_refresher("gemini", Arc::new(adc));
+        }
+    }
+
+    /// Refresh hook registered for a provider
+    pub fn refresher(&self, provider: &str) -> Option<Arc<dyn TokenRefresher>> {
+        self.refreshers.get(provider).cloned()
+    }
+
+    /// Name of the active profile, if any
+    pub fn profile_name(&self) -> Option<&str> {
+        self.profile.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Settings for a provider in the active profile
+    pub fn provider_settings(&self, provider: &str) -> Option<&ProviderSettings> {
+        self.profile.as_ref().and_then(|(_, profile)| profile.provider(provider))
+    }
+
     pub fn set_api_key(&mut self, provider: &str, api_key: &str) {
         self.api_keys.insert(provider.to_string(), api_key.to_string());
     }
 
     pub async fn detect_auth(&self, provider: &str) -> Result<AuthMethod> {
+        self.detect_auth_source(provider).await.map(|(method, _)| method)
+    }
+
+    /// Detect credentials and report which source provided them
+    pub async fn detect_auth_source(&self, provider: &str) -> Result<(AuthMethod, AuthSource)> {
+        let detected = self.find_auth_source(provider).await;
+        match &detected {
+            Ok((_, source)) => tracing::debug!(provider, source = %source, "credentials detected"),
+            Err(e) => tracing::debug!(provider, error = %e, "no credentials detected"),
+        }
+        detected
+    }
+
+    async fn find_auth_source(&self, provider: &str) -> Result<(AuthMethod, AuthSource)> {
+        // 0. Credentials pinned by the active profile win over ambient ones
+        if let Some(key) = self.provider_settings(provider).and_then(|s| s.resolve_api_key()) {
+            let name = self.profile_name().unwrap_or_default().to_string();
+            return Ok((AuthMethod::ApiKey { key }, AuthSource::Profile { name }));
+        }
+
         // 1. Prefer existing CLI/session credentials
-        if self.check_cli_session(provider).await? {
-            return Ok(AuthMethod::CliAuth);
+        if let Some(path) = self.find_cli_session(provider).await? {
+            return Ok((AuthMethod::CliAuth, AuthSource::CliSession { path }));
         }
 
         // 2. Manager-provided API key (programmatic)
         if let Some(api_key) = self.api_keys.get(provider) {
-            return Ok(AuthMethod::ApiKey { key: api_key.clone() });
+            return Ok((AuthMethod::ApiKey { key: api_key.clone() }, AuthSource::Programmatic));
         }
 
         // 3. Environment variables (provider-specific aliases first)
-        if let Some(key) = self.get_env_api_key(provider) {
-            return Ok(AuthMethod::ApiKey { key });
+        if let Some((var, key)) = self.get_env_api_key(provider) {
+            return Ok((AuthMethod::ApiKey { key }, AuthSource::Env { var }));
         }
 
-        // 4. No authentication found
+        // 4. Keys saved with `ai-cli auth login`
+        if let Some(store) = &self.credentials {
+            if let Some(key) = store.get(provider) {
+                return Ok((AuthMethod::ApiKey { key: key.to_string() }, AuthSource::CredentialStore));
+            }
+            // Only providers recorded as keyring-backed are looked up, so detection stays cheap
+            if store.in_keyring(provider) {
+                match keyring::lookup(provider) {
+                    Ok(Some(key)) => return Ok((AuthMethod::ApiKey { key }, AuthSource::Keyring)),
+                    Ok(None) => {}
+                    Err(e) => tracing::debug!(provider, error = %format!("{:#}", e), "keyring lookup failed"),
+                }
+            }
+        }
+
+        // 5. No authentication found
         Err(anyhow!("No authentication found for provider: {}", provider))
     }
 
-    async fn check_cli_session(&self, provider: &str) -> Result<bool> {
-        let candidates = self.get_cli_session_candidates(provider)?;
-        Ok(candidates.into_iter().any(|p| p.exists()))
+    async fn find_cli_session(&self, provider: &str) -> Result<Option<PathBuf>> {
+        let candidates = self.cli_session_candidates(provider)?;
+        Ok(candidates.into_iter().find(|p| p.exists()))
     }
 
-    fn get_cli_session_candidates(&self, provider: &str) -> Result<Vec<PathBuf>> {
+    /// Paths whose existence marks a CLI/desktop session, in lookup order
+    pub fn cli_session_candidates(&self, provider: &str) -> Result<Vec<PathBuf>> {
         let mut paths = Vec::new();
         let home = dirs::home_dir()
             .ok_or_else(|| anyhow!("Could not determine home directory"))?;
@@ -92,6 +254,9 @@ impl AuthManager {
             }
             // For Gemini, prefer gcloud ADC as a sign-in indicator
             "gemini" => {
+                if let Ok(path) = env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+                    paths.push(PathBuf::from(path));
+                }
                 paths.push(home.join(".config/gcloud/application_default_credentials.json"));
                 if let Ok(appdata) = env::var("APPDATA") {
                     paths.push(PathBuf::from(appdata).join("gcloud/application_default_credentials.json"));
@@ -108,18 +273,19 @@ impl AuthManager {
         Ok(paths)
     }
 
-    fn get_env_api_key(&self, provider: &str) -> Option<String> {
+    /// Environment variables that may hold a provider's API key, in lookup order
+    pub fn env_var_names(provider: &str) -> Vec<String> {
         match provider {
-            "claude" => {
-                env::var("ANTHROPIC_API_KEY").ok()
-                    .or_else(|| env::var("CLAUDE_API_KEY").ok())
-            }
-            "gemini" => {
-                env::var("GEMINI_API_KEY").ok()
-                    .or_else(|| env::var("GOOGLE_API_KEY").ok())
-            }
-            "codex" => env::var("CODEX_API_KEY").ok(),
-            other => env::var(format!("{}_API_KEY", other.to_uppercase())).ok(),
+            "claude" => vec!["ANTHROPIC_API_KEY".to_string(), "CLAUDE_API_KEY".to_string()],
+            "gemini" => vec!["GEMINI_API_KEY".to_string(), "GOOGLE_API_KEY".to_string()],
+            "codex" => vec!["CODEX_API_KEY".to_string()],
+            other => vec![format!("{}_API_KEY", other.to_uppercase())],
         }
     }
+
+    fn get_env_api_key(&self, provider: &str) -> Option<(String, String)> {
+        Self::env_var_names(provider)
+            .into_iter()
+            .find_map(|var| env::var(&var).ok().map(|key| (var, key)))
+    }
 }
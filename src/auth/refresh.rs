@@ -0,0 +1,108 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::AuthMethod;
+
+/// Hook that obtains fresh credentials when the current ones expire or are rejected
+#[async_trait]
+pub trait TokenRefresher: Send + Sync {
+    /// Return replacement credentials for `provider`, given the ones that stopped working
+    async fn refresh(&self, provider: &str, current: &AuthMethod) -> Result<AuthMethod>;
+}
+
+/// Credentials that refresh themselves through a hook shortly before they expire
+pub struct ManagedCredentials {
+    provider: String,
+    method: tokio::sync::RwLock<AuthMethod>,
+    refresher: Option<Arc<dyn TokenRefresher>>,
+    skew: Duration,
+}
+
+impl ManagedCredentials {
+    /// Wrap credentials; without a refresher they are used until rejected
+    pub fn new(provider: impl Into<String>, method: AuthMethod, refresher: Option<Arc<dyn TokenRefresher>>) -> Self {
+        Self {
+            provider: provider.into(),
+            method: tokio::sync::RwLock::new(method),
+            refresher,
+            skew: Duration::from_secs(60),
+        }
+    }
+
+    /// Refresh this long before the recorded expiry (default 60s)
+    pub fn with_skew(mut self, skew: Duration) -> Self {
+        self.skew = skew;
+        self
+    }
+
+    /// Attach or replace the refresh hook
+    pub fn with_refresher(mut self, refresher: Arc<dyn TokenRefresher>) -> Self {
+        self.refresher = Some(refresher);
+        self
+    }
+
+    /// Check if a refresh hook is attached
+    pub fn can_refresh(&self) -> bool {
+        self.refresher.is_some()
+    }
+
+    /// Current credentials, refreshed first if they are about to expire or have no token yet
+    pub async fn current(&self) -> Result<AuthMethod> {
+        let method = self.method.read().await.clone();
+        if self.refresher.is_some() && (method.secret().is_none() || method.expires_within(self.skew)) {
+            return self.refresh().await;
+        }
+        Ok(method)
+    }
+
+    /// Replace the credentials using the refresh hook
+    pub async fn refresh(&self) -> Result<AuthMethod> {
+        let refresher = self.refresher.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No token refresher configured for {}", self.provider))?;
+        let mut method = self.method.write().await;
+        let fresh = refresher.refresh(&self.provider, &method).await?;
+        *method = fresh.clone();
+        Ok(fresh)
+    }
+}
+
+impl AuthMethod {
+    /// When these credentials stop being valid, if known
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        match self {
+            AuthMethod::AccountBased { expires_at, .. } => *expires_at,
+            _ => None,
+        }
+    }
+
+    /// Check if the credentials have already expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::ZERO)
+    }
+
+    /// Check if the credentials expire within `window` from now
+    pub fn expires_within(&self, window: Duration) -> bool {
+        self.expires_at()
+            .is_some_and(|at| at <= SystemTime::now() + window)
+    }
+
+    /// The secret to send with requests (API key or session token)
+    pub fn secret(&self) -> Option<&str> {
+        match self {
+            AuthMethod::ApiKey { key } => Some(key),
+            AuthMethod::AccountBased { session_token, .. } => session_token.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Attach the secret to `request`: an API key in `key_header`, a session token as `Authorization: Bearer`
+    pub fn authorize(&self, request: reqwest::RequestBuilder, key_header: &str) -> reqwest::RequestBuilder {
+        match self {
+            AuthMethod::ApiKey { key } => request.header(key_header, key),
+            AuthMethod::AccountBased { session_token: Some(token), .. } => request.bearer_auth(token),
+            _ => request,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Shell completion scripts (`ai-cli completions <shell>`)
+//!
+//! The default script calls back into `ai-cli` (as `COMPLETE=<shell> ai-cli`)
+//! while completing, so provider and saved pipeline names stay current.
+//! `--static` emits a self-contained script without those names.
+
+use anyhow::{Result, anyhow};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use std::io::Write;
+
+use super::CliArgs;
+use crate::pipeline::PipelineStore;
+use crate::providers::KNOWN_PROVIDERS;
+
+/// Environment variable that puts `ai-cli` in completion mode
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Answer a completion request from the shell and exit; returns when there is none
+pub fn complete_from_env() {
+    clap_complete::CompleteEnv::with_factory(CliArgs::command).var(COMPLETE_VAR).complete();
+}
+
+/// Write the completion script for `shell`
+pub fn write_script(shell: Shell, dynamic: bool, out: &mut dyn Write) -> Result<()> {
+    let mut cmd = CliArgs::command();
+    let name = cmd.get_name().to_string();
+    if !dynamic {
+        clap_complete::generate(shell, &mut cmd, &name, out);
+        return Ok(());
+    }
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .ok_or_else(|| anyhow!("Dynamic completion is not available for {}; use --static", shell))?;
+    completer.write_registration(COMPLETE_VAR, &name, &name, &name, out)?;
+    Ok(())
+}
+
+/// Provider names, for arguments that take one
+pub fn provider_candidates() -> Vec<CompletionCandidate> {
+    KNOWN_PROVIDERS.iter().map(|name| CompletionCandidate::new(*name)).collect()
+}
+
+/// Saved pipeline names with their descriptions
+pub fn pipeline_candidates() -> Vec<CompletionCandidate> {
+    let Ok(store) = PipelineStore::open_default() else { return Vec::new() };
+    store
+        .list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| {
+            let help = store.load(&name).ok().and_then(|d| d.description).map(Into::into);
+            CompletionCandidate::new(name).help(help)
+        })
+        .collect()
+}
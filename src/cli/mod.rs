@@ -1,19 +1,90 @@
-use clap::{Parser, Subcommand};
+pub mod completion;
+
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use clap_complete::ArgValueCandidates;
+use std::path::PathBuf;
+
+use crate::pipeline::{CompareView, Metric, PipelineParser};
+use crate::pipeline::template::parse_env_pair;
+use crate::context::DiffSource;
+use crate::history::stats::GroupBy;
+use crate::diagnostics;
+use crate::i18n::{self, Lang};
+use crate::logging::LogFormat;
+use crate::render::RenderMode;
+use crate::providers::{DEFAULT_SEED, JsonMode, KNOWN_PROVIDERS, ProviderId, ProviderOptions};
 
 /// AI CLI Aggregator - Unifying multiple AI CLI tools
 #[derive(Parser, Debug)]
 #[command(name = "ai-cli")]
 #[command(author, version, about, long_about = None)]
 #[command(arg_required_else_help = true)]
+#[command(after_long_help = crate::error::EXIT_CODES_HELP)]
 pub struct CliArgs {
-    /// Enable verbose logging
-    #[arg(short, long, global = true)]
-    pub verbose: bool,
+    /// Log more detail: -v info, -vv debug (HTTP requests), -vvv trace
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    
+    /// Format of log lines
+    #[arg(long = "log-format", value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    
+    /// Append logs to this file instead of stderr
+    #[arg(long = "log-file", value_name = "PATH", global = true)]
+    pub log_file: Option<std::path::PathBuf>,
+    
+    /// Export pipeline, step and HTTP call spans as OpenTelemetry traces to this
+    /// OTLP/HTTP collector, e.g. http://localhost:4318
+    #[arg(long = "otlp-endpoint", value_name = "URL", global = true, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+    
+    /// Dump sanitized HTTP requests and responses to stderr, or append them to PATH with --debug-http=PATH
+    #[arg(long = "debug-http", value_name = "PATH", global = true, num_args = 0..=1, require_equals = true)]
+    pub debug_http: Option<Option<PathBuf>>,
+    
+    /// Forbid network access: steps fail at once unless served locally (also AI_CLI_OFFLINE=1)
+    #[arg(long, global = true, env = "AI_CLI_OFFLINE", value_parser = clap::builder::BoolishValueParser::new())]
+    pub offline: bool,
+    
+    /// Replace emails, absolute paths, user and host names with placeholders before
+    /// anything is sent, and put the originals back in replies
+    #[arg(long, global = true)]
+    pub privacy: bool,
     
     /// Suppress non-essential output
     #[arg(short, long, global = true)]
     pub quiet: bool,
     
+    /// Re-probe provider capabilities instead of using cached values
+    #[arg(long, global = true)]
+    pub reprobe: bool,
+    
+    /// Auth profile to use (credentials, default models, base URLs)
+    #[arg(long, global = true, env = "AI_CLI_PROFILE")]
+    pub profile: Option<String>,
+    
+    /// Print token usage and cost after execute/pipeline runs
+    #[arg(long = "show-cost", global = true)]
+    pub show_cost: bool,
+    
+    /// Limit context to one package of a Cargo/npm/Python monorepo
+    #[arg(long, global = true)]
+    pub package: Option<String>,
+    
+    /// How to print model output (default: markdown on a terminal, raw when piped)
+    #[arg(long, value_enum, global = true)]
+    pub render: Option<RenderMode>,
+    
+    /// Copy the final output to the clipboard, or step N's with --copy=N
+    #[arg(long, value_name = "N", global = true, num_args = 0..=1, require_equals = true)]
+    pub copy: Option<Option<usize>>,
+    
+    /// Language of messages and help (default: from LANG)
+    #[arg(long, value_enum, global = true)]
+    pub lang: Option<Lang>,
+    
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -23,8 +94,8 @@ pub enum Command {
     /// Execute a single AI prompt
     Execute {
         /// AI provider to use (claude, gemini, codex)
-        #[arg(short, long)]
-        provider: String,
+        #[arg(short, long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: ProviderId,
         
         /// The prompt to send to the AI
         #[arg(short = 'P', long)]
@@ -34,43 +105,879 @@ pub enum Command {
         #[arg(long)]
         api_key: Option<String>,
         
-        /// Context file to include with the prompt
+        /// File, directory or glob to include as context (repeatable)
         #[arg(short, long)]
-        context: Option<String>,
+        context: Vec<String>,
         
         /// Disable streaming output
         #[arg(long = "no-stream")]
         no_stream: bool,
+        
+        /// Print where each piece of context came from after the run
+        #[arg(long = "explain-context")]
+        explain_context: bool,
+        
+        /// Set a variable for `{{env.NAME}}` prompt placeholders (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_pair)]
+        env: Vec<(String, String)>,
+        
+        /// Pass environment variables matching GLOB to `{{env.NAME}}` placeholders (repeatable);
+        /// none pass by default and wildcards skip names that look like secrets
+        #[arg(long = "env-passthrough", value_name = "GLOB")]
+        env_passthrough: Vec<glob::Pattern>,
+        
+        /// Add the K chunks from `ai-cli index` most relevant to each step's prompt
+        #[arg(long, value_name = "K")]
+        retrieve: Option<usize>,
+        
+        /// Continue a named conversation, saving this exchange to it
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+        
+        /// Image file (png, jpeg, gif, webp) to send with the prompt (repeatable)
+        #[arg(long = "image", value_name = "PATH")]
+        images: Vec<PathBuf>,
+        
+        #[command(flatten)]
+        git: GitContextArgs,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
     },
     
     /// Execute a pipeline of AI operations
+    #[command(args_conflicts_with_subcommands = true)]
     Pipeline {
-        /// Pipeline chain (e.g., "claude:設計 -> gemini:実装 -> codex:レビュー")
-        #[arg(long = "chain")]
-        chain: String,
+        /// Pipeline chain (e.g., "claude:設計 -> gemini:実装 -> codex:レビュー");
+        /// defaults to `default_chain` from config
+        #[arg(long = "chain", value_parser = parse_chain)]
+        chain: Option<String>,
+        
+        /// Read the chain from a file, one step per line (`#` starts a comment line)
+        #[arg(long = "chain-file", value_name = "PATH", conflicts_with = "chain")]
+        chain_file: Option<PathBuf>,
+        
+        /// File, directory or glob to include as context (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        /// Disable streaming output
+        #[arg(long = "no-stream")]
+        no_stream: bool,
+        
+        /// Print where each piece of context came from after the run
+        #[arg(long = "explain-context")]
+        explain_context: bool,
+        
+        /// Set a variable for `{{env.NAME}}` prompt placeholders (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_pair)]
+        env: Vec<(String, String)>,
+        
+        /// Pass environment variables matching GLOB to `{{env.NAME}}` placeholders (repeatable);
+        /// none pass by default and wildcards skip names that look like secrets
+        #[arg(long = "env-passthrough", value_name = "GLOB")]
+        env_passthrough: Vec<glob::Pattern>,
+        
+        /// Add the K chunks from `ai-cli index` most relevant to each step's prompt
+        #[arg(long, value_name = "K")]
+        retrieve: Option<usize>,
+        
+        /// Run the chain once per line of a JSONL file (a string or
+        /// `{"id", "input", "env"}`) and print one JSON result per line
+        #[arg(long = "input-file", value_name = "PATH")]
+        input_file: Option<std::path::PathBuf>,
         
-        /// Context file to include with the pipeline
+        /// Number of inputs from --input-file to run at once
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "input_file",
+              value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: u16,
+        
+        /// Start at most this many inputs from --input-file per minute
+        #[arg(long = "rate-limit", value_name = "PER_MINUTE", requires = "input_file",
+              value_parser = clap::value_parser!(u32).range(1..))]
+        rate_limit: Option<u32>,
+        
+        /// Serve Prometheus metrics on ADDR (e.g. 127.0.0.1:9464) at /metrics while --input-file runs
+        #[arg(long = "metrics-addr", value_name = "ADDR", requires = "input_file")]
+        metrics_addr: Option<std::net::SocketAddr>,
+        
+        /// Follow the run in a terminal UI where failed steps can be retried or skipped
+        #[arg(long, conflicts_with = "input_file")]
+        tui: bool,
+        
+        /// Pause after each step to accept, edit (in $EDITOR), retry with a tweak or abort
+        #[arg(long = "confirm-each-step", conflicts_with_all = ["input_file", "tui"])]
+        confirm_each_step: bool,
+        
+        /// Open each step's output in $EDITOR before the next step sees it
+        #[arg(long = "edit-before-next", conflicts_with_all = ["input_file", "tui", "confirm_each_step"])]
+        edit_before_next: bool,
+        
+        /// Write each step's output, transformed output and metadata to numbered files in DIR
+        #[arg(long = "artifacts-dir", value_name = "DIR", conflicts_with = "input_file")]
+        artifacts_dir: Option<PathBuf>,
+        
+        /// Write the final output to PATH, relative to the project root
+        #[arg(long, value_name = "PATH", conflicts_with = "input_file")]
+        out: Option<PathBuf>,
+        
+        /// Write files into the run's sandbox instead of the project and print a summary
+        /// diff; `ai-cli apply <run-id>` copies them over
+        #[arg(long, conflicts_with = "input_file")]
+        sandbox: bool,
+        
+        #[command(flatten)]
+        git: GitContextArgs,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+
+        #[command(subcommand)]
+        action: Option<PipelineAction>,
+    },
+    
+    /// Run a saved pipeline by name
+    Run {
+        /// Name the pipeline was saved under
+        #[arg(add = ArgValueCandidates::new(completion::pipeline_candidates))]
+        name: String,
+        
+        /// File, directory or glob to include as context (repeatable)
         #[arg(short, long)]
-        context: Option<String>,
+        context: Vec<String>,
         
         /// Disable streaming output
         #[arg(long = "no-stream")]
         no_stream: bool,
+        
+        /// Print where each piece of context came from after the run
+        #[arg(long = "explain-context")]
+        explain_context: bool,
+        
+        /// Set a variable for `{{env.NAME}}` prompt placeholders (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_pair)]
+        env: Vec<(String, String)>,
+        
+        /// Pass environment variables matching GLOB to `{{env.NAME}}` placeholders (repeatable);
+        /// none pass by default and wildcards skip names that look like secrets
+        #[arg(long = "env-passthrough", value_name = "GLOB")]
+        env_passthrough: Vec<glob::Pattern>,
+        
+        /// Add the K chunks from `ai-cli index` most relevant to each step's prompt
+        #[arg(long, value_name = "K")]
+        retrieve: Option<usize>,
+        
+        /// Pause after each step to accept, edit (in $EDITOR), retry with a tweak or abort
+        #[arg(long = "confirm-each-step")]
+        confirm_each_step: bool,
+        
+        /// Open each step's output in $EDITOR before the next step sees it
+        #[arg(long = "edit-before-next", conflicts_with = "confirm_each_step")]
+        edit_before_next: bool,
+        
+        /// Write each step's output, transformed output and metadata to numbered files in DIR
+        #[arg(long = "artifacts-dir", value_name = "DIR")]
+        artifacts_dir: Option<PathBuf>,
+        
+        /// Write the final output to PATH, relative to the project root
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+        
+        /// Write files into the run's sandbox instead of the project and print a summary
+        /// diff; `ai-cli apply <run-id>` copies them over
+        #[arg(long)]
+        sandbox: bool,
+        
+        #[command(flatten)]
+        git: GitContextArgs,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
     },
     
-    /// List available AI providers
+    /// Re-run a pipeline whenever watched files change, with the changed files as context
+    Watch {
+        /// Pipeline chain; defaults to `default_chain` from config
+        #[arg(long = "chain", value_parser = parse_chain)]
+        chain: Option<String>,
+        
+        /// Files, directories or globs to watch (repeatable)
+        #[arg(long = "paths", value_name = "PATH", num_args = 1.., default_value = ".")]
+        paths: Vec<String>,
+        
+        /// File, directory or glob to include as context on every run (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        /// Set a variable for `{{env.NAME}}` prompt placeholders (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_pair)]
+        env: Vec<(String, String)>,
+        
+        /// Wait until no file has changed for this long before re-running
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce: u64,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Write a Conventional Commits message for the staged changes
+    #[command(name = "commit-msg")]
+    CommitMsg {
+        /// Pipeline chain run over the staged diff; defaults to `[commit_msg] chain`
+        #[arg(long = "chain", value_parser = parse_chain)]
+        chain: Option<String>,
+        
+        /// Write the message to a commit message file instead of printing it
+        /// (default: .git/COMMIT_EDITMSG)
+        #[arg(long, value_name = "PATH", num_args = 0..=1)]
+        write: Option<Option<PathBuf>>,
+        
+        /// Install a prepare-commit-msg hook that fills in the message on `git commit`
+        #[arg(long = "install-hook", conflicts_with_all = ["chain", "write"])]
+        install_hook: bool,
+        
+        /// Replace an existing prepare-commit-msg hook
+        #[arg(long, requires = "install_hook")]
+        force: bool,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Review a GitHub pull request file by file
+    Review {
+        /// Pull request URL, OWNER/REPO#N, or a number in the `origin` repository
+        #[arg(long, value_name = "URL|NUMBER")]
+        pr: String,
+        
+        /// Pipeline chain run over each file or chunk; defaults to `[review] chain`
+        #[arg(long = "chain", value_parser = parse_chain)]
+        chain: Option<String>,
+        
+        /// Output format
+        #[arg(long, value_enum, default_value_t = crate::pipeline::review::ReviewFormat::Markdown)]
+        format: crate::pipeline::review::ReviewFormat,
+        
+        /// Write the review to a file instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Ask several providers the same question and compare or reconcile their answers
+    Consensus {
+        /// The question to ask every provider
+        #[arg(short = 'P', long)]
+        prompt: String,
+        
+        /// Providers to ask, comma-separated
+        #[arg(long, value_delimiter = ',', required = true,
+              add = ArgValueCandidates::new(completion::provider_candidates))]
+        providers: Vec<ProviderId>,
+        
+        /// Provider that synthesizes a consensus with agreements and disagreements;
+        /// without one the answers are shown side by side
+        #[arg(long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        arbiter: Option<ProviderId>,
+        
+        /// File, directory or glob to include as context (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Run one prompt on several providers, or several prompts on one, and show the answers side by side
+    Compare {
+        /// The prompt every provider answers
+        #[arg(short = 'P', long, conflicts_with = "prompts", required_unless_present = "prompts")]
+        prompt: Option<String>,
+        
+        /// Prompts to compare (repeatable)
+        #[arg(long = "prompts", value_name = "PROMPT", num_args = 1..)]
+        prompts: Vec<String>,
+        
+        /// Providers to compare, comma-separated
+        #[arg(long, value_delimiter = ',', conflicts_with = "provider", required_unless_present = "provider",
+              add = ArgValueCandidates::new(completion::provider_candidates))]
+        providers: Vec<ProviderId>,
+        
+        /// Provider answering every prompt
+        #[arg(short, long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: Option<ProviderId>,
+        
+        /// Show the answers as columns or as a diff against the first
+        #[arg(long, value_enum, default_value_t = CompareView::Columns)]
+        view: CompareView,
+        
+        /// File, directory or glob to include as context (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        /// Print the answers, timings and usage as JSON
+        #[arg(long)]
+        json: bool,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Score a pipeline on JSONL test cases (`{"input", "expected", "contains", "criteria"}`)
+    /// and compare providers
+    Eval {
+        /// JSONL file of test cases
+        cases: PathBuf,
+        
+        /// Pipeline chain to evaluate; defaults to `default_chain` from config
+        #[arg(long = "chain", value_parser = parse_chain)]
+        chain: Option<String>,
+        
+        /// Run the chain once per provider, each taking every step, and compare them
+        #[arg(long, value_delimiter = ',', add = ArgValueCandidates::new(completion::provider_candidates))]
+        providers: Vec<ProviderId>,
+        
+        /// How `expected` is compared with the output
+        #[arg(long, value_enum, default_value_t = Metric::Contains)]
+        metric: Metric,
+        
+        /// Lowest `similarity` score that passes (0-1)
+        #[arg(long, default_value_t = crate::pipeline::eval::DEFAULT_THRESHOLD)]
+        threshold: f64,
+        
+        /// Provider that grades each case's `criteria` as PASS or FAIL
+        #[arg(long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        judge: Option<ProviderId>,
+        
+        /// Number of cases to run at once
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: u16,
+        
+        /// File, directory or glob to include as context (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        /// Print every case result and the summary as JSON
+        #[arg(long)]
+        json: bool,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Serve the configured providers and saved pipelines as an HTTP API
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        
+        /// Address to bind; 0.0.0.0 accepts connections from other machines and needs --token
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+        
+        /// Bearer token clients must send
+        #[arg(long, env = "AI_CLI_SERVER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        
+        /// Number of inputs from queued jobs (`POST /v1/jobs`) to run at once
+        #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: u16,
+    },
+    
+    /// List known providers with their auth, default model and capabilities
     #[command(name = "list-providers")]
-    ListProviders,
+    ListProviders {
+        /// Print the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// List models offered by the authenticated providers
+    Models {
+        /// Only query this provider
+        #[arg(short, long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: Option<ProviderId>,
+    },
     
     /// Check authentication status for a provider
     #[command(name = "check-auth")]
     CheckAuth {
         /// Provider to check authentication for
-        provider: String,
+        #[arg(add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: ProviderId,
+        
+        /// Make a live API call to prove the credentials work
+        #[arg(long)]
+        validate: bool,
+    },
+    
+    /// Diagnose auth, network reachability, latency and config per provider
+    Doctor {
+        /// Only check this provider
+        #[arg(short, long, add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: Option<ProviderId>,
+        
+        /// Seconds to wait for each network check
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+        
+        /// Print the diagnosis as JSON
+        #[arg(long)]
+        json: bool,
     },
     
     /// Show version information
     Version,
+    
+    /// Remove stored credentials for a provider
+    Logout {
+        /// Provider to log out from
+        #[arg(add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: ProviderId,
+    },
+    
+    /// Inspect and manage stored credentials
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    
+    /// Browse artifacts of previous runs
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    
+    /// Run a recorded run again with the chain, options, context and env from its manifest
+    Rerun {
+        /// Run id, unique id prefix, or `last`
+        id: String,
+    },
+    
+    /// Copy the files a `--sandbox` run wrote into the project
+    Apply {
+        /// Run id, unique id prefix, or `last`
+        id: String,
+        
+        /// Print the diff against the project without applying it
+        #[arg(long)]
+        diff: bool,
+        
+        /// Overwrite files changed in the project since the run wrote them
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Summarize requests, tokens, cost, errors and latency from the run history
+    Stats {
+        /// Only runs started since this time: a duration ago (24h, 7d, 4w) or a date (2026-10-01)
+        #[arg(long)]
+        since: Option<String>,
+        
+        /// Only runs started before this time, in the same formats as --since
+        #[arg(long)]
+        until: Option<String>,
+        
+        /// Group by provider or by provider and model
+        #[arg(long, value_enum, default_value_t = GroupBy::Model)]
+        by: GroupBy,
+        
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Run one prompt over many inputs through a provider batch API, at half price
+    Batch {
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+    
+    /// Embed project files for `--retrieve`
+    Index {
+        /// Files, directories or globs to index (default: the project root)
+        paths: Vec<String>,
+        
+        /// Embedding model: `local` (offline), `gemini` or `openai`
+        #[arg(long, default_value = "local")]
+        embedder: String,
+        
+        /// Lines per chunk
+        #[arg(long = "chunk-lines", default_value_t = crate::context::index::DEFAULT_CHUNK_LINES)]
+        chunk_lines: usize,
+        
+        /// Discard the existing index instead of updating it
+        #[arg(long)]
+        rebuild: bool,
+    },
+    
+    /// Print embedding vectors for a file or stdin
+    Embed {
+        /// File to embed; reads stdin when omitted or `-`
+        file: Option<PathBuf>,
+        
+        /// Embedding model: `gemini`, `openai` or `local` (offline)
+        #[arg(long, default_value = "gemini")]
+        embedder: String,
+        
+        /// Embed each non-empty line separately instead of the whole input
+        #[arg(long)]
+        lines: bool,
+        
+        /// Output format
+        #[arg(long, value_enum, default_value_t = crate::context::EmbedFormat::Json)]
+        format: crate::context::EmbedFormat,
+    },
+    
+    /// Manage conversations saved with `execute --session`
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    
+    /// Read the audit log of provider calls (enabled with `[audit] enabled = true`)
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+    
+    /// Print a shell completion script
+    ///
+    /// Load it from your shell's startup file, e.g. `source <(ai-cli completions bash)`.
+    Completions {
+        /// Shell to generate the script for
+        shell: clap_complete::Shell,
+        
+        /// Self-contained script that does not call ai-cli to complete
+        /// provider and pipeline names
+        #[arg(long = "static")]
+        static_script: bool,
+    },
+}
+
+/// Generation parameter flags shared by commands that call providers
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+pub struct GenerationArgs {
+    /// System prompt, sent through the provider's native system role
+    #[arg(long)]
+    pub system: Option<String>,
+    
+    /// Sampling temperature (0-2)
+    #[arg(long)]
+    pub temperature: Option<f32>,
+    
+    /// Nucleus sampling probability mass (0-1)
+    #[arg(long = "top-p")]
+    pub top_p: Option<f32>,
+    
+    /// Maximum tokens to generate
+    #[arg(long = "max-tokens")]
+    pub max_tokens: Option<u32>,
+    
+    /// Stop generating at this sequence (repeatable)
+    #[arg(long = "stop", value_name = "SEQUENCE")]
+    pub stop: Vec<String>,
+    
+    /// Require a JSON reply matching this JSON Schema file
+    #[arg(long = "json-schema", value_name = "FILE", value_parser = parse_json_schema)]
+    pub json_schema: Option<JsonMode>,
+    
+    /// Times to ask again, quoting the validation error, after a reply that does not match
+    #[arg(long = "json-retries", value_name = "N", default_value_t = crate::providers::structured::DEFAULT_JSON_RETRIES, requires = "json_schema")]
+    pub json_retries: u32,
+    
+    /// Sampling seed, passed to providers that support one
+    #[arg(long)]
+    pub seed: Option<u64>,
+    
+    /// Make runs repeatable where providers allow: temperature 0 and a fixed seed unless given
+    #[arg(long)]
+    pub deterministic: bool,
+}
+
+impl GenerationArgs {
+    /// Convert the flags into provider options
+    pub fn to_options(&self) -> ProviderOptions {
+        ProviderOptions {
+            model: None,
+            temperature: self.temperature.or(self.deterministic.then_some(0.0)),
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            system: self.system.clone(),
+            stop: self.stop.clone(),
+            json: self.json_schema.clone().map(|mode| mode.with_retries(self.json_retries)),
+            seed: self.seed.or(self.deterministic.then_some(DEFAULT_SEED)),
+            idempotency_key: None,
+        }
+    }
+}
+
+impl CliArgs {
+    /// Parse `args` (including the program name) with help text in the `--lang` or locale language;
+    /// exits with clap's usage error on failure
+    pub fn parse_localized(args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let matches = localized_command(Lang::detect(lang_arg(&args))).get_matches_from(args);
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+/// The clap command with its about and global option help in `lang`
+pub fn localized_command(lang: Lang) -> clap::Command {
+    let mut command = CliArgs::command();
+    if lang == Lang::En {
+        return command;
+    }
+    if let Some(about) = i18n::help_ja("ai-cli") {
+        command = command.about(about);
+    }
+    let subcommands: Vec<String> = command.get_subcommands().map(|c| c.get_name().to_string()).collect();
+    for name in subcommands {
+        if let Some(about) = i18n::help_ja(&name) {
+            command = command.mut_subcommand(&name, |c| c.about(about).long_about(None));
+        }
+    }
+    let options: Vec<String> = command.get_arguments().filter(|a| a.is_global_set()).map(|a| a.get_id().to_string()).collect();
+    for id in options {
+        if let Some(help) = i18n::help_ja(&id.replace('_', "-")) {
+            command = command.mut_arg(&id, |a| a.help(help));
+        }
+    }
+    command
+}
+
+/// `--lang` from raw arguments, before they are parsed
+fn lang_arg(args: &[OsString]) -> Option<Lang> {
+    let mut args = args.iter().filter_map(|a| a.to_str());
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--lang") {
+            Some("") => args.next(),
+            Some(rest) => rest.strip_prefix('='),
+            None => continue,
+        };
+        return value.and_then(|v| <Lang as clap::ValueEnum>::from_str(v, true).ok());
+    }
+    None
+}
+
+/// Accept a chain that parses and uses only known providers
+pub fn parse_chain(chain: &str) -> Result<String, String> {
+    PipelineParser::parse_known(chain, KNOWN_PROVIDERS)
+        .map_err(|e| diagnostics::render_error(&e, std::io::stderr().is_terminal()))?;
+    Ok(chain.to_string())
+}
+
+/// Load a `--json-schema` file
+fn parse_json_schema(path: &str) -> Result<JsonMode, String> {
+    JsonMode::from_file(std::path::Path::new(path)).map_err(|e| e.to_string())
+}
+
+/// Flags that add git changes to the context
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+pub struct GitContextArgs {
+    /// Include unstaged working-tree changes as per-file diffs
+    #[arg(long = "context-git-diff")]
+    pub diff: bool,
+    
+    /// Include staged changes as per-file diffs
+    #[arg(long = "context-git-staged")]
+    pub staged: bool,
+    
+    /// Include the changes in a commit range (e.g. main..HEAD)
+    #[arg(long = "context-git-range", value_name = "RANGE")]
+    pub range: Option<String>,
+}
+
+impl GitContextArgs {
+    /// Requested diff sources, in flag order
+    pub fn sources(&self) -> Vec<DiffSource> {
+        let mut sources = Vec::new();
+        if self.diff {
+            sources.push(DiffSource::WorkingTree);
+        }
+        if self.staged {
+            sources.push(DiffSource::Staged);
+        }
+        if let Some(range) = &self.range {
+            sources.push(DiffSource::Range(range.clone()));
+        }
+        sources
+    }
+}
+
+/// Subcommands for credential management
+#[derive(Subcommand, Debug)]
+pub enum AuthAction {
+    /// Show which credentials each provider would use
+    Status,
+    
+    /// Save an API key in the credential store
+    Login {
+        /// Provider to store the key for
+        #[arg(add = ArgValueCandidates::new(completion::provider_candidates))]
+        provider: ProviderId,
+        
+        /// API key (read from stdin when omitted)
+        #[arg(long)]
+        api_key: Option<String>,
+        
+        /// Keep the key in the OS keyring (Keychain, Secret Service) instead of the credentials file
+        #[arg(long)]
+        keyring: bool,
+    },
+}
+
+/// Subcommands for run history
+#[derive(Subcommand, Debug)]
+pub enum HistoryAction {
+    /// List recorded runs
+    List,
+    
+    /// Reveal a run's artifacts directory
+    Open {
+        /// Run id, unique id prefix, or `last`
+        id: String,
+    },
+}
+
+/// Subcommands for conversation sessions
+#[derive(Subcommand, Debug)]
+pub enum SessionAction {
+    /// List sessions, most recently used first
+    List,
+    
+    /// Print a session's conversation
+    Show {
+        /// Session name
+        name: String,
+    },
+    
+    /// Delete a session
+    Delete {
+        /// Session name
+        name: String,
+    },
+    
+    /// Forget a session's conversation but keep the session
+    Clear {
+        /// Session name
+        name: String,
+    },
+}
+
+/// Subcommands for the audit log
+#[derive(Subcommand, Debug)]
+pub enum AuditAction {
+    /// Print logged provider calls, oldest first
+    Show {
+        /// Only calls since this time: a duration ago (24h, 7d, 4w) or a date (2026-10-01)
+        #[arg(long)]
+        since: Option<String>,
+        
+        /// Only calls before this time, in the same formats as --since
+        #[arg(long)]
+        until: Option<String>,
+        
+        /// Only calls to this provider
+        #[arg(short, long)]
+        provider: Option<String>,
+        
+        /// Show at most the last N calls
+        #[arg(short = 'n', long, value_name = "N")]
+        limit: Option<usize>,
+        
+        /// Print the entries as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Subcommands for provider batch jobs
+#[derive(Subcommand, Debug)]
+pub enum BatchAction {
+    /// Submit the prompt once per line of an input file; prints the batch id
+    Submit {
+        /// Batch API to use
+        #[arg(value_parser = ["claude", "openai"])]
+        provider: String,
+        
+        /// Prompt for every input; `{{env.INPUT}}` is replaced by the input
+        prompt: String,
+        
+        /// JSONL file of inputs, as for `pipeline --input-file`
+        #[arg(long = "input-file", value_name = "FILE")]
+        input_file: PathBuf,
+        
+        /// Model to run the batch on
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// File, directory or glob to include as context (repeatable)
+        #[arg(short, long)]
+        context: Vec<String>,
+        
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    
+    /// Show a batch's progress, or list submitted batches
+    Status {
+        /// Batch id printed by `batch submit`
+        id: Option<String>,
+    },
+    
+    /// Write a finished batch's results as JSONL, one line per input
+    Fetch {
+        /// Batch id printed by `batch submit`
+        id: String,
+        
+        /// Write the results to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        
+        /// Poll until the batch has finished instead of failing while it runs
+        #[arg(long)]
+        wait: bool,
+        
+        /// Seconds between polls with --wait
+        #[arg(long, value_name = "SECS", default_value_t = 60, requires = "wait")]
+        poll_interval: u64,
+    },
+}
+
+/// Subcommands for managing pipelines
+#[derive(Subcommand, Debug)]
+pub enum PipelineAction {
+    /// Interactively compose and save a new pipeline
+    New,
+    
+    /// Save a chain under a name for `ai-cli run <name>`
+    Save {
+        /// Name to save the pipeline under
+        name: String,
+        
+        /// Pipeline chain (e.g., "claude:analyze -> codex:review")
+        #[arg(value_parser = parse_chain)]
+        chain: String,
+        
+        /// Short description shown by `pipeline list`
+        #[arg(short, long)]
+        description: Option<String>,
+        
+        /// Replace an existing pipeline with the same name
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// List saved pipelines
+    List,
 }
 
 /// Helper struct for Execute command
@@ -79,7 +986,7 @@ pub struct ExecuteCommand {
     pub provider: String,
     pub prompt: String,
     pub api_key: Option<String>,
-    pub context: Option<String>,
+    pub context: Vec<String>,
     pub stream: bool,
     pub no_stream: bool,
 }
@@ -89,7 +996,7 @@ impl ExecuteCommand {
         provider: String,
         prompt: String,
         api_key: Option<String>,
-        context: Option<String>,
+        context: Vec<String>,
         no_stream: bool,
     ) -> Self {
         Self {
@@ -102,8 +1009,9 @@ impl ExecuteCommand {
         }
     }
     
+    /// First context spec, if any
     pub fn context_file(&self) -> Option<String> {
-        self.context.clone()
+        self.context.first().cloned()
     }
 }
 
@@ -111,7 +1019,7 @@ impl ExecuteCommand {
 #[derive(Debug)]
 pub struct PipelineCommand {
     pub chain: String,
-    pub context: Option<String>,
+    pub context: Vec<String>,
     pub stream: bool,
     pub no_stream: bool,
 }
@@ -119,7 +1027,7 @@ pub struct PipelineCommand {
 impl PipelineCommand {
     pub fn from_command(
         chain: String,
-        context: Option<String>,
+        context: Vec<String>,
         no_stream: bool,
     ) -> Self {
         Self {
@@ -130,104 +1038,9 @@ impl PipelineCommand {
         }
     }
     
+    /// First context spec, if any
     pub fn context_file(&self) -> Option<String> {
-        self.context.clone()
-    }
-}
-
-impl CliArgs {
-    /// Parse command-line arguments for testing
-    pub fn parse_from<I, T>(itr: I) -> Self
-    where
-        I: IntoIterator<Item = T>,
-        T: Into<std::ffi::OsString> + Clone,
-    {
-        let args: Vec<String> = itr.into_iter()
-            .map(|s| s.into().into_string().unwrap())
-            .collect();
-        
-        // Special handling for test cases with simpler syntax
-        let mut cli_args = Self {
-            verbose: args.contains(&"--verbose".to_string()),
-            quiet: args.contains(&"--quiet".to_string()),
-            command: None,
-        };
-        
-        // Check for special test commands
-        if args.contains(&"--list-providers".to_string()) {
-            cli_args.command = Some(Command::ListProviders);
-            return cli_args;
-        }
-        
-        if args.contains(&"--version".to_string()) {
-            cli_args.command = Some(Command::Version);
-            return cli_args;
-        }
-        
-        if let Some(idx) = args.iter().position(|x| x == "--check-auth") {
-            if idx + 1 < args.len() {
-                cli_args.command = Some(Command::CheckAuth {
-                    provider: args[idx + 1].clone(),
-                });
-                return cli_args;
-            }
-        }
-        
-        // Check for pipeline command
-        if let Some(idx) = args.iter().position(|x| x == "--chain") {
-            let chain = if idx + 1 < args.len() {
-                args[idx + 1].clone()
-            } else {
-                String::new()
-            };
-            
-            let context = args.iter()
-                .position(|x| x == "--context")
-                .and_then(|idx| args.get(idx + 1))
-                .map(|s| s.clone());
-            
-            let no_stream = args.contains(&"--no-stream".to_string());
-            
-            cli_args.command = Some(Command::Pipeline {
-                chain,
-                context,
-                no_stream,
-            });
-            return cli_args;
-        }
-        
-        // Default to execute command for test compatibility
-        if let Some(idx) = args.iter().position(|x| x == "--provider") {
-            let provider = args.get(idx + 1).unwrap_or(&String::new()).clone();
-            
-            let prompt = args.iter()
-                .position(|x| x == "--prompt")
-                .and_then(|idx| args.get(idx + 1))
-                .unwrap_or(&String::new())
-                .clone();
-            
-            let api_key = args.iter()
-                .position(|x| x == "--api-key")
-                .and_then(|idx| args.get(idx + 1))
-                .map(|s| s.clone());
-            
-            let context = args.iter()
-                .position(|x| x == "--context")
-                .and_then(|idx| args.get(idx + 1))
-                .map(|s| s.clone());
-            
-            let no_stream = args.contains(&"--no-stream".to_string());
-            
-            cli_args.command = Some(Command::Execute {
-                provider,
-                prompt,
-                api_key,
-                context,
-                no_stream,
-            });
-        }
-        
-        cli_args
+        self.context.first().cloned()
     }
 }
 
@@ -235,9 +1048,9 @@ impl CliArgs {
 impl Command {
     pub fn as_execute(&self) -> Option<ExecuteCommand> {
         match self {
-            Command::Execute { provider, prompt, api_key, context, no_stream } => {
+            Command::Execute { provider, prompt, api_key, context, no_stream, .. } => {
                 Some(ExecuteCommand::from_command(
-                    provider.clone(),
+                    provider.to_string(),
                     prompt.clone(),
                     api_key.clone(),
                     context.clone(),
@@ -250,9 +1063,9 @@ impl Command {
     
     pub fn as_pipeline(&self) -> Option<PipelineCommand> {
         match self {
-            Command::Pipeline { chain, context, no_stream } => {
+            Command::Pipeline { chain, context, no_stream, .. } => {
                 Some(PipelineCommand::from_command(
-                    chain.clone(),
+                    chain.clone().unwrap_or_default(),
                     context.clone(),
                     *no_stream,
                 ))
@@ -0,0 +1,23 @@
+//! `--copy`: put a run's output on the system clipboard
+
+use anyhow::{Result, anyhow};
+
+use crate::providers::Response;
+
+/// Output of `step` (1-based), or of the last step when `None`
+pub fn pick(responses: &[Response], step: Option<usize>) -> Result<&str> {
+    let response = match step {
+        None => responses.last(),
+        Some(0) => return Err(anyhow!("Steps are numbered from 1")),
+        Some(n) => responses.get(n - 1),
+    };
+    response
+        .map(|r| r.content.as_str())
+        .ok_or_else(|| anyhow!("No step {} to copy; the run has {} steps", step.unwrap_or(0), responses.len()))
+}
+
+/// Replace the clipboard's contents with `text`
+pub fn copy(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| anyhow!("Clipboard unavailable: {}", e))?;
+    clipboard.set_text(text).map_err(|e| anyhow!("Failed to copy to the clipboard: {}", e))
+}
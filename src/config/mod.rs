@@ -0,0 +1,438 @@
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::ConfigError;
+
+/// Directory holding user-level ai-cli configuration and saved pipelines
+///
+/// Honors `AI_CLI_CONFIG_DIR`, falling back to the platform config dir.
+pub fn config_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("AI_CLI_CONFIG_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("ai-cli"))
+        .ok_or_else(|| anyhow!("Could not determine config directory"))
+}
+
+/// Directory for caches that can be safely deleted (probe results, etc.)
+///
+/// Honors `AI_CLI_CACHE_DIR`, falling back to the platform cache dir.
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("AI_CLI_CACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    dirs::cache_dir()
+        .map(|dir| dir.join("ai-cli"))
+        .ok_or_else(|| anyhow!("Could not determine cache directory"))
+}
+
+/// Directory for user data worth keeping (run artifacts, etc.)
+///
+/// Honors `AI_CLI_DATA_DIR`, falling back to the platform data dir.
+pub fn data_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("AI_CLI_DATA_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    dirs::data_dir()
+        .map(|dir| dir.join("ai-cli"))
+        .ok_or_else(|| anyhow!("Could not determine data directory"))
+}
+
+/// File name of the per-repository config discovered from the working directory
+pub const PROJECT_FILE: &str = ".ai-cli.toml";
+
+/// Top-level keys a project file may set: pipelines, context globs, provider preferences and prompt prefixes
+///
+/// Everything else (hooks, HTTP proxies, redaction, safety, audit, encryption, ...)
+/// stays in the user config, so a cloned repository cannot run commands, reroute
+/// requests or switch off protections.
+const PROJECT_ALLOWED_KEYS: &[&str] =
+    &["default_chain", "commit_msg", "review", "context", "context_policy", "prompt_prefix", "providers", "profiles"];
+
+/// Provider keys a project file may not set: credentials, endpoints and local files stay in the user config
+const PROJECT_FORBIDDEN_PROVIDER_KEYS: &[&str] = &["api_key", "api_key_env", "base_url", "tokenizer"];
+
+/// User configuration loaded from `config.toml`, optionally merged with a project `.ai-cli.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Profile used when `--profile` is not given
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// Diagnosis step run after a pipeline fails
+    #[serde(default)]
+    pub post_mortem: PostMortemSettings,
+    /// Commands, webhooks and notifications fired when a pipeline finishes
+    #[serde(default)]
+    pub hooks: Vec<crate::pipeline::hooks::HookSettings>,
+    /// Pipeline used by `commit-msg`
+    #[serde(default)]
+    pub commit_msg: crate::pipeline::commit_msg::CommitMsgSettings,
+    /// Pipeline used by `review --pr`
+    #[serde(default)]
+    pub review: crate::pipeline::review::ReviewSettings,
+    /// Chain run by `pipeline` when `--chain` is not given
+    #[serde(default)]
+    pub default_chain: Option<String>,
+    /// Glob patterns of files always added to the context, relative to the project root
+    #[serde(default)]
+    pub context: Vec<String>,
+    /// Size caps for files pulled into the context
+    #[serde(default)]
+    pub context_limits: crate::context::ContextLimits,
+    /// Whether files over the size limit are chunked instead of skipped
+    #[serde(default)]
+    pub chunking: crate::context::ChunkingSettings,
+    /// What directory walks and globs leave out of the context
+    #[serde(default)]
+    pub ignore: crate::context::IgnoreSettings,
+    /// Secret redaction applied before provider calls
+    #[serde(default)]
+    pub redaction: crate::context::RedactionSettings,
+    /// Content safety rules checked around provider calls
+    #[serde(default)]
+    pub safety: crate::pipeline::SafetySettings,
+    /// Append-only log of every provider call
+    #[serde(default)]
+    pub audit: crate::history::audit::AuditSettings,
+    /// At-rest encryption of sessions and run history
+    #[serde(default)]
+    pub encryption: crate::history::encryption::EncryptionSettings,
+    /// When `--session` history gets compacted
+    #[serde(default)]
+    pub session: crate::history::session::SessionSettings,
+    /// When and with which model pipeline context gets summarized
+    #[serde(default)]
+    pub compaction: crate::context::CompactionSettings,
+    /// Text prepended to every step's prompt
+    #[serde(default)]
+    pub prompt_prefix: Option<String>,
+    /// Conversation history sent with each step: `full`, `last:N`, `summary` or `none`
+    #[serde(default)]
+    pub context_policy: Option<crate::context::ContextPolicy>,
+    /// Per-provider preferences (model) that apply regardless of profile
+    #[serde(default)]
+    pub providers: HashMap<String, ProviderSettings>,
+    /// Model prices (USD per million tokens) keyed by model id prefix, overriding the built-in table
+    #[serde(default)]
+    pub pricing: HashMap<String, crate::providers::pricing::ModelPrice>,
+    /// Timeouts and connection pooling of the HTTP client shared by all providers
+    #[serde(default)]
+    pub http: crate::providers::http::HttpSettings,
+    /// Per-provider request limits, enforced on every call and used when scheduling queued jobs
+    #[serde(default)]
+    pub rate_limits: HashMap<String, crate::scheduler::RateLimit>,
+    /// Project file merged into this config, if any
+    #[serde(skip)]
+    pub project_path: Option<PathBuf>,
+}
+
+/// `[post_mortem]` section: explain pipeline failures with a provider
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostMortemSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Provider asked for the diagnosis; defaults to the first available one
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+/// Named set of per-provider credentials and defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(flatten)]
+    pub providers: HashMap<String, ProviderSettings>,
+}
+
+impl Profile {
+    /// Get the settings for a provider in this profile
+    pub fn provider(&self, name: &str) -> Option<&ProviderSettings> {
+        self.providers.get(name)
+    }
+}
+
+/// Credentials, model, endpoint and generation defaults for one provider
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderSettings {
+    /// API key; `${VAR}` references are expanded from the environment
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Name of an environment variable holding the API key
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// tiktoken rank file (e.g. `cl100k_base.tiktoken`) for exact token counts with OpenAI-family models
+    #[serde(default)]
+    pub tokenizer: Option<PathBuf>,
+    /// Text put before every prompt sent to this provider (e.g. team style rules)
+    #[serde(default)]
+    pub prompt_prefix: Option<String>,
+    /// Text put after every prompt sent to this provider
+    #[serde(default)]
+    pub prompt_suffix: Option<String>,
+    /// Conversation history this provider is sent, overriding the top-level `context_policy`
+    #[serde(default)]
+    pub context_policy: Option<crate::context::ContextPolicy>,
+    /// Let the provider cache the system prompt and attached files across calls (claude only)
+    #[serde(default)]
+    pub prompt_caching: Option<bool>,
+    /// Attached files at least this many bytes are uploaded instead of inlined (gemini only; `0` always inlines)
+    #[serde(default)]
+    pub upload_threshold_bytes: Option<usize>,
+    /// Default generation parameters (temperature, max_tokens, system, ...)
+    #[serde(flatten)]
+    pub options: crate::providers::ProviderOptions,
+}
+
+impl ProviderSettings {
+    /// Resolve the API key from `api_key` or `api_key_env`
+    pub fn resolve_api_key(&self) -> Option<String> {
+        if let Some(key) = &self.api_key {
+            let expanded = expand_env(key);
+            if !expanded.is_empty() {
+                return Some(expanded);
+            }
+        }
+        self.api_key_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+            .filter(|key| !key.is_empty())
+    }
+}
+
+impl Config {
+    /// Path of the user config file
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("config.toml"))
+    }
+
+    /// Load the user config file, returning defaults when it does not exist
+    pub fn load_default() -> Result<Self> {
+        let path = Self::default_path()?;
+        if path.exists() {
+            Self::load(&path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Load config from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        Self::from_toml(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Parse config from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| ConfigError::new(None, e.to_string()).into())
+    }
+
+    /// Load the user config merged with the nearest `.ai-cli.toml` at or above `dir`
+    pub fn load_for_dir(dir: &Path) -> Result<Self> {
+        let user_path = Self::default_path()?;
+        let user = if user_path.exists() {
+            Some(std::fs::read_to_string(&user_path)
+                .with_context(|| format!("Failed to read config {}", user_path.display()))?)
+        } else {
+            None
+        };
+
+        match find_project_config(dir) {
+            Some(project_path) => {
+                let project = std::fs::read_to_string(&project_path)
+                    .with_context(|| format!("Failed to read config {}", project_path.display()))?;
+                Self::layered(user.as_deref(), &project, &project_path)
+            }
+            None => match user {
+                Some(text) => Self::from_toml(&text)
+                    .with_context(|| format!("Invalid config file {}", user_path.display())),
+                None => Ok(Self::default()),
+            },
+        }
+    }
+
+    /// Merge project TOML over user TOML; tables merge recursively, other values replace
+    pub fn layered(user: Option<&str>, project: &str, project_path: &Path) -> Result<Self> {
+        let mut base: toml::Table = match user {
+            Some(text) => toml::from_str(text)
+                .map_err(|e| ConfigError::new(None, format!("Invalid user config: {}", e)))?,
+            None => toml::Table::new(),
+        };
+        let invalid = |message: String| ConfigError::new(Some(project_path.to_path_buf()), message);
+        let overlay: toml::Table = toml::from_str(project).map_err(|e| invalid(e.to_string()))?;
+        check_project_table(&overlay).map_err(invalid)?;
+
+        merge_tables(&mut base, overlay);
+        let mut config: Config = toml::Value::Table(base).try_into()
+            .map_err(|e| invalid(format!("invalid after merging with the user config: {}", e)))?;
+        config.project_path = Some(project_path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Directory context globs are relative to (the project root, else the current dir)
+    pub fn project_root(&self) -> Option<&Path> {
+        self.project_path.as_deref().and_then(Path::parent)
+    }
+
+    /// Files matched by the `context` globs, sorted and deduplicated
+    ///
+    /// Globs are confined to the root: absolute or `..` patterns are rejected and
+    /// matches that resolve (through symlinks) outside it are skipped.
+    pub fn context_files(&self, cwd: &Path) -> Result<Vec<PathBuf>> {
+        let root = self.project_root().unwrap_or(cwd);
+        let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let mut files = Vec::new();
+        for pattern in &self.context {
+            let relative = Path::new(pattern);
+            if relative.has_root() || relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+                return Err(ConfigError::new(
+                    self.project_path.clone(),
+                    format!("Context glob '{}' must stay inside {}", pattern, root.display()),
+                ).into());
+            }
+            let full = root.join(pattern);
+            let matches = glob::glob(&full.to_string_lossy())
+                .map_err(|e| ConfigError::new(self.project_path.clone(), format!("Invalid context glob '{}': {}", pattern, e)))?;
+            files.extend(matches.filter_map(|m| m.ok()).filter(|p| {
+                p.is_file() && p.canonicalize().is_ok_and(|real| real.starts_with(&canonical_root))
+            }));
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// Provider preferences from the top-level `[providers.<name>]` table
+    pub fn provider_preferences(&self, name: &str) -> Option<&ProviderSettings> {
+        self.providers.get(name)
+    }
+
+    /// Look up a profile by name
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let mut known: Vec<&str> = self.profiles.keys().map(|k| k.as_str()).collect();
+            known.sort();
+            ConfigError::new(None, format!("Unknown profile '{}'. Known profiles: {:?}", name, known)).into()
+        })
+    }
+
+    /// Name of the profile to activate: explicit choice, then `default_profile`
+    pub fn active_profile_name(&self, requested: Option<&str>) -> Option<String> {
+        requested
+            .map(|name| name.to_string())
+            .or_else(|| self.default_profile.clone())
+    }
+}
+
+/// Find the nearest `.ai-cli.toml` in `start` or one of its ancestors
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => merge_tables(existing, table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn check_project_table(table: &toml::Table) -> std::result::Result<(), String> {
+    for (key, value) in table {
+        if !PROJECT_ALLOWED_KEYS.contains(&key.as_str()) {
+            return Err(format!(
+                "project config may not set '{}'; it may only set {}",
+                key,
+                PROJECT_ALLOWED_KEYS.join(", ")
+            ));
+        }
+        // `[providers.<name>]` and `[profiles.<profile>.<name>]`
+        let providers: Vec<(String, &toml::Value)> = match (key.as_str(), value) {
+            ("providers", toml::Value::Table(providers)) => {
+                providers.iter().map(|(name, settings)| (format!("providers.{}", name), settings)).collect()
+            }
+            ("profiles", toml::Value::Table(profiles)) => profiles
+                .iter()
+                .filter_map(|(profile, providers)| providers.as_table().map(|providers| (profile, providers)))
+                .flat_map(|(profile, providers)| {
+                    providers.iter().map(move |(name, settings)| (format!("profiles.{}.{}", profile, name), settings))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        for (path, settings) in providers {
+            let Some(settings) = settings.as_table() else { continue };
+            if let Some(forbidden) = settings.keys().find(|k| PROJECT_FORBIDDEN_PROVIDER_KEYS.contains(&k.as_str())) {
+                return Err(format!(
+                    "project config may not set '{}.{}'; keep credentials and endpoints in the user config",
+                    path, forbidden
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expand `${VAR}` references from the environment; unset variables expand to ""
+pub fn expand_env(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        match rest[start + 2..].find('}') {
+            Some(end) => {
+                let var = &rest[start + 2..start + 2 + end];
+                out.push_str(&std::env::var(var).unwrap_or_default());
+                rest = &rest[start + 2 + end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Remove a provider's `api_key` from a profile in a config file, preserving formatting
+///
+/// Returns whether a key was removed.
+pub fn remove_profile_api_key(path: &Path, profile: &str, provider: &str) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    let mut doc: toml_edit::DocumentMut = text.parse()
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let removed = doc
+        .get_mut("profiles")
+        .and_then(|profiles| profiles.get_mut(profile))
+        .and_then(|profile| profile.get_mut(provider))
+        .and_then(|settings| settings.as_table_like_mut())
+        .and_then(|settings| settings.remove("api_key"))
+        .is_some();
+
+    if removed {
+        std::fs::write(path, doc.to_string())
+            .with_context(|| format!("Failed to write config {}", path.display()))?;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,312 @@
+//! Splitting source files along syntactic boundaries
+//!
+//! Files too large for one request are cut between top-level items —
+//! functions, classes, impl blocks — rather than at an arbitrary byte. Rust,
+//! Python, JavaScript, TypeScript, Go, Java and C are parsed with tree-sitter
+//! (the default `tree-sitter` feature): items are the syntax tree's top-level
+//! nodes, and an item larger than the limit is cut between its own members,
+//! such as the methods of a class. Other languages, files that do not parse
+//! cleanly and builds without the feature use a lightweight scan instead: in
+//! brace languages a line at column 0 with no open brace starts an item, in
+//! Python and other indentation languages any line at column 0 does. Either
+//! way, comments, attributes and decorators directly above an item stay with
+//! it, items are packed into chunks up to a byte limit, and what is still too
+//! large is split at blank lines, then at line ends.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Chunk size when none is configured
+pub const DEFAULT_CHUNK_BYTES: usize = 16 * 1024;
+
+/// `[chunking]` section
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkingSettings {
+    /// Load files over `[context_limits] max_file_bytes` as chunks of at most
+    /// that size instead of skipping them
+    #[serde(default)]
+    pub large_files: bool,
+}
+
+/// Consecutive lines of a file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chunk {
+    pub path: PathBuf,
+    /// First line, from 1
+    pub start_line: usize,
+    /// Last line, inclusive
+    pub end_line: usize,
+    pub text: String,
+}
+
+impl Chunk {
+    /// Context key of the chunk: `src/big.rs#L120-240`
+    pub fn key(&self) -> PathBuf {
+        PathBuf::from(format!("{}#L{}-{}", self.path.display(), self.start_line, self.end_line))
+    }
+}
+
+/// How item boundaries are recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Braces,
+    Indent,
+    /// Prose and data: only blank lines are boundaries
+    Plain,
+}
+
+fn syntax_for(path: &Path) -> Syntax {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or_default() {
+        "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "java" | "js" | "jsx" | "mjs" | "ts" | "tsx"
+        | "kt" | "kts" | "scala" | "swift" | "php" | "dart" => Syntax::Braces,
+        "py" | "rb" | "ex" | "exs" | "lua" | "sh" | "bash" | "zsh" => Syntax::Indent,
+        _ => Syntax::Plain,
+    }
+}
+
+/// Line that belongs to the item below it
+fn is_prefix(line: &str) -> bool {
+    let line = line.trim_start();
+    ["//", "/*", "*", "#[", "#!", "@", "#", "--", "\"\"\""].iter().any(|p| line.starts_with(p))
+}
+
+/// Net `{` minus `}` on a line, ignoring string contents and `//` comments
+fn brace_delta(line: &str) -> i64 {
+    let mut delta = 0;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '`') => quote = Some(c),
+            (None, '/') if chars.peek() == Some(&'/') => break,
+            (None, '{') => delta += 1,
+            (None, '}') => delta -= 1,
+            _ => {}
+        }
+    }
+    delta
+}
+
+/// Line indexes where top-level items start, always including 0
+fn item_starts(lines: &[&str], syntax: Syntax) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut depth: i64 = 0;
+    let mut item_open = false;
+    for (index, line) in lines.iter().enumerate() {
+        let top_level = !line.is_empty() && !line.starts_with(char::is_whitespace);
+        let boundary = match syntax {
+            Syntax::Braces => depth == 0 && top_level && !line.starts_with(['}', ')', ']']),
+            Syntax::Indent => top_level && !["else", "elif", "except", "finally", "end", "rescue", "ensure"]
+                .iter()
+                .any(|k| line.starts_with(k)),
+            Syntax::Plain => index > 0 && lines[index - 1].trim().is_empty() && !line.trim().is_empty(),
+        };
+        // An item begins at its first comment or attribute line, not at the declaration
+        let attached = index > 0 && is_prefix(lines[index - 1]) && !lines[index - 1].starts_with(char::is_whitespace);
+        if boundary && index > 0 && !(attached && item_open) {
+            starts.push(index);
+        }
+        item_open = boundary || (item_open && !line.trim().is_empty());
+        if syntax == Syntax::Braces {
+            depth = (depth + brace_delta(line)).max(0);
+        }
+    }
+    starts.dedup();
+    starts
+}
+
+/// Split `text` (the contents of `path`) into chunks of at most `max_bytes`
+///
+/// Chunks cover every line exactly once, in order.
+pub fn chunk_source(path: &Path, text: &str, max_bytes: usize) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    let max_bytes = max_bytes.max(1);
+    let items = tree::items(path, text, &lines, max_bytes).unwrap_or_else(|| scanned_items(&lines, syntax_for(path), max_bytes));
+
+    let mut chunks = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    for (start, end) in items {
+        current = match current {
+            Some((from, _)) if span_bytes(&lines[from..end]) <= max_bytes => Some((from, end)),
+            Some(done) => {
+                chunks.push(done);
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    chunks.extend(current);
+    chunks
+        .into_iter()
+        .map(|(start, end)| Chunk {
+            path: path.to_path_buf(),
+            start_line: start + 1,
+            end_line: end,
+            text: lines[start..end].join("\n"),
+        })
+        .collect()
+}
+
+/// Line ranges of the items found by the line scan, oversized ones split
+fn scanned_items(lines: &[&str], syntax: Syntax, max_bytes: usize) -> Vec<(usize, usize)> {
+    let starts = item_starts(lines, syntax);
+    let mut items = Vec::new();
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(lines.len());
+        if span_bytes(&lines[start..end]) > max_bytes {
+            items.extend(split_large(lines, start, end, max_bytes));
+        } else {
+            items.push((start, end));
+        }
+    }
+    items
+}
+
+/// Bytes of lines joined with newlines
+fn span_bytes(lines: &[&str]) -> usize {
+    lines.iter().map(|l| l.len() + 1).sum::<usize>().saturating_sub(1)
+}
+
+/// Split an oversized item at blank lines where possible, at any line otherwise
+fn split_large(lines: &[&str], start: usize, end: usize, max_bytes: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut from = start;
+    while from < end {
+        let mut to = from + 1;
+        let mut last_blank = None;
+        while to < end && span_bytes(&lines[from..to + 1]) <= max_bytes {
+            if lines[to].trim().is_empty() {
+                last_blank = Some(to + 1);
+            }
+            to += 1;
+        }
+        if to < end && let Some(blank) = last_blank {
+            to = blank;
+        }
+        pieces.push((from, to));
+        from = to;
+    }
+    pieces
+}
+
+#[cfg(feature = "tree-sitter")]
+mod tree {
+    use super::{is_prefix, span_bytes, split_large};
+    use std::path::Path;
+    use tree_sitter::{Language, Node, Parser};
+
+    fn language_for(path: &Path) -> Option<Language> {
+        let language = match path.extension().and_then(|e| e.to_str())? {
+            "rs" => tree_sitter_rust::LANGUAGE,
+            "py" => tree_sitter_python::LANGUAGE,
+            "js" | "jsx" | "mjs" | "cjs" => tree_sitter_javascript::LANGUAGE,
+            "ts" | "mts" | "cts" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+            "tsx" => tree_sitter_typescript::LANGUAGE_TSX,
+            "go" => tree_sitter_go::LANGUAGE,
+            "java" => tree_sitter_java::LANGUAGE,
+            "c" | "h" => tree_sitter_c::LANGUAGE,
+            _ => return None,
+        };
+        Some(language.into())
+    }
+
+    /// Line ranges of the syntax tree's items, or `None` without a grammar or a clean parse
+    pub(super) fn items(path: &Path, text: &str, lines: &[&str], max_bytes: usize) -> Option<Vec<(usize, usize)>> {
+        let mut parser = Parser::new();
+        parser.set_language(&language_for(path)?).ok()?;
+        let tree = parser.parse(text, None)?;
+        let root = tree.root_node();
+        if root.has_error() {
+            return None;
+        }
+        let mut items = Vec::new();
+        split_nodes(lines, &named_children(root), 0, lines.len(), max_bytes, &mut items);
+        Some(items)
+    }
+
+    fn named_children(node: Node<'_>) -> Vec<Node<'_>> {
+        let mut cursor = node.walk();
+        node.named_children(&mut cursor).collect()
+    }
+
+    fn is_comment(node: Node<'_>) -> bool {
+        node.kind().contains("comment") || node.kind().ends_with("attribute_item")
+    }
+
+    /// Lines where `nodes` start, taking in the comment and attribute lines directly above
+    ///
+    /// Lines are checked rather than comment nodes, since grammars often put a
+    /// comment in the body of the item before the one it documents.
+    fn node_starts(lines: &[&str], nodes: &[Node<'_>], from: usize) -> Vec<usize> {
+        let mut starts = Vec::new();
+        let mut floor = from;
+        for &node in nodes.iter().filter(|node| !is_comment(**node)) {
+            let mut start = node.start_position().row;
+            if start < floor {
+                continue;
+            }
+            let own_start = start;
+            while start > floor && is_prefix(lines[start - 1]) {
+                start -= 1;
+            }
+            starts.push(start);
+            floor = own_start + 1;
+        }
+        starts
+    }
+
+    /// Members of a node worth splitting it at: the first children that start on different lines
+    fn members(node: Node<'_>) -> Vec<Node<'_>> {
+        let children = named_children(node);
+        let first_row = children.first().map(|child| child.start_position().row);
+        if children.iter().any(|child| Some(child.start_position().row) != first_row) {
+            return children;
+        }
+        match children.into_iter().max_by_key(|child| child.named_child_count()) {
+            Some(body) => members(body),
+            None => Vec::new(),
+        }
+    }
+
+    /// Cut lines `from..to`, made of `nodes`, between nodes; oversized nodes are cut between their members
+    fn split_nodes(lines: &[&str], nodes: &[Node<'_>], from: usize, to: usize, max_bytes: usize, items: &mut Vec<(usize, usize)>) {
+        let mut starts = vec![from];
+        starts.extend(node_starts(lines, nodes, from).into_iter().filter(|start| *start < to));
+        starts.sort_unstable();
+        starts.dedup();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(to);
+            if span_bytes(&lines[start..end]) <= max_bytes {
+                items.push((start, end));
+                continue;
+            }
+            let inner = nodes
+                .iter()
+                .find(|node| !is_comment(**node) && (start..end).contains(&node.start_position().row))
+                .map(|node| members(*node))
+                .unwrap_or_default();
+            if inner.is_empty() {
+                items.extend(split_large(lines, start, end, max_bytes));
+            } else {
+                split_nodes(lines, &inner, start, end, max_bytes, items);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+mod tree {
+    use std::path::Path;
+
+    pub(super) fn items(_path: &Path, _text: &str, _lines: &[&str], _max_bytes: usize) -> Option<Vec<(usize, usize)>> {
+        None
+    }
+}
@@ -0,0 +1,156 @@
+//! Summarizing old conversation turns once a context outgrows its budget
+//!
+//! The executor runs its [`Compactor`] before each step: when the conversation
+//! exceeds `max_tokens`, everything but the `keep_recent` newest messages is
+//! replaced by one System message written by a (cheap) model.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::Provenance;
+use crate::providers::{AIProvider, Context, Message, MessageRole, ProviderOptions};
+
+/// Heading of the summary message a compaction leaves behind
+pub const SUMMARY_HEADING: &str = "Earlier in this conversation (summarized):";
+
+/// Instruction sent with the transcript being compacted
+const SUMMARY_PROMPT: &str = "Summarize the conversation below for a model that will continue it. \
+Keep decisions, facts, names, file paths and open questions; drop pleasantries. Reply with the summary only.";
+
+/// `[compaction]` section: when and with which model to compact pipeline context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactionSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Compact once the conversation exceeds this many tokens
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    /// Most recent messages always kept verbatim
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+    /// Provider that writes the summary; defaults to the first available one
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model that writes the summary, e.g. a small, cheap one
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+fn default_max_tokens() -> usize { 16000 }
+fn default_keep_recent() -> usize { 10 }
+
+impl Default for CompactionSettings {
+    fn default() -> Self {
+        Self { enabled: false, max_tokens: default_max_tokens(), keep_recent: default_keep_recent(), provider: None, model: None }
+    }
+}
+
+/// Folds old messages of a context into a model-written summary
+pub struct Compactor {
+    provider: Arc<dyn AIProvider>,
+    model: Option<String>,
+    max_tokens: usize,
+    keep_recent: usize,
+}
+
+impl Compactor {
+    /// Summarize with `provider`'s default model, using the default thresholds
+    pub fn new(provider: Arc<dyn AIProvider>) -> Self {
+        Self { provider, model: None, max_tokens: default_max_tokens(), keep_recent: default_keep_recent() }
+    }
+
+    /// Compactor for the `[compaction]` thresholds and model
+    pub fn from_settings(provider: Arc<dyn AIProvider>, settings: &CompactionSettings) -> Self {
+        Self {
+            provider,
+            model: settings.model.clone(),
+            max_tokens: settings.max_tokens,
+            keep_recent: settings.keep_recent,
+        }
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Whether the conversation in `context` is over budget
+    pub fn needs_compaction(&self, context: &Context) -> bool {
+        let tokenizer = self.provider.tokenizer();
+        let tokens: usize = context.conversation_history.iter().map(|m| tokenizer.count(&m.content)).sum();
+        tokens > self.max_tokens && self.foldable(context) > 0
+    }
+
+    /// Replace old messages with a summary when over budget; returns how many were folded
+    ///
+    /// Instructions (System messages other than earlier summaries) are kept, and a
+    /// reply is never separated from its prompt. On error the context is unchanged.
+    pub async fn compact(&self, context: &mut Context) -> Result<usize> {
+        if !self.needs_compaction(context) {
+            return Ok(0);
+        }
+        let mut remaining = self.foldable(context);
+        let (folded, mut kept): (Vec<Message>, Vec<Message>) = context.conversation_history.iter().cloned().partition(|message| {
+            if is_summary(message) {
+                return true;
+            }
+            let fold = message.role != MessageRole::System && remaining > 0;
+            if fold {
+                remaining -= 1;
+            }
+            fold
+        });
+
+        let transcript: Vec<String> = folded.iter().map(|m| {
+            let role = match m.role {
+                MessageRole::User => "user",
+                MessageRole::Assistant => "assistant",
+                MessageRole::System => "summary",
+            };
+            format!("{}: {}", role, m.content.strip_prefix(SUMMARY_HEADING).unwrap_or(&m.content).trim())
+        }).collect();
+        let options = ProviderOptions { model: self.model.clone(), ..ProviderOptions::default() };
+        let prompt = format!("{}\n\n{}", SUMMARY_PROMPT, transcript.join("\n\n"));
+        let summary = self.provider.execute_with_options(&prompt, &Context::new(), &options).await
+            .map_err(|e| e.context(format!("Compacting context with {} failed", self.provider.name())))?
+            .content;
+        if summary.trim().is_empty() {
+            return Err(anyhow!("{} returned an empty summary", self.provider.name()));
+        }
+
+        let count = folded.iter().filter(|m| !is_summary(m)).count();
+        let message = Message::new(MessageRole::System, format!("{}\n{}", SUMMARY_HEADING, summary.trim()))
+            .with_provenance(Provenance::Compaction { messages: count });
+        // The summary goes after the instructions, where the folded turns began
+        let at = kept.iter().position(|m| m.role != MessageRole::System).unwrap_or(kept.len());
+        kept.insert(at, message);
+        context.conversation_history = kept;
+        Ok(count)
+    }
+
+    /// Number of user and assistant messages older than the ones kept verbatim
+    fn foldable(&self, context: &Context) -> usize {
+        let turns: Vec<&Message> = context.conversation_history.iter().filter(|m| m.role != MessageRole::System).collect();
+        let mut count = turns.len().saturating_sub(self.keep_recent);
+        // Keep the prompt of the oldest reply kept
+        while count > 0 && count < turns.len() && turns[count].role == MessageRole::Assistant {
+            count -= 1;
+        }
+        count
+    }
+}
+
+fn is_summary(message: &Message) -> bool {
+    matches!(message.provenance, Some(Provenance::Compaction { .. }))
+}
@@ -0,0 +1,69 @@
+//! Vectors produced by `ai-cli embed`
+//!
+//! JSON output is `{"embedder", "dimensions", "embeddings": [[...], ...]}`.
+//! Binary output is two little-endian `u32`s, the vector count and the
+//! dimensions, followed by every vector as little-endian `f32`s.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::io::Write;
+
+use super::index::{Embedder, embed_all};
+
+/// How `ai-cli embed` writes vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum EmbedFormat {
+    #[default]
+    Json,
+    Binary,
+}
+
+/// Vectors for a list of inputs, in input order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Embeddings {
+    pub embedder: String,
+    pub dimensions: usize,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+impl Embeddings {
+    /// Embed `inputs`, checking the vectors share one dimension
+    pub async fn compute(embedder: &dyn Embedder, inputs: &[String]) -> Result<Self> {
+        let embeddings = embed_all(embedder, inputs).await?;
+        let dimensions = embeddings.first().map_or(0, Vec::len);
+        if embeddings.iter().any(|v| v.len() != dimensions) {
+            return Err(anyhow!("Embedder '{}' returned vectors of different lengths", embedder.name()));
+        }
+        Ok(Self { embedder: embedder.name().to_string(), dimensions, embeddings })
+    }
+
+    /// Count, dimensions, then the vectors, all little-endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + 4 * self.dimensions * self.embeddings.len());
+        bytes.extend((self.embeddings.len() as u32).to_le_bytes());
+        bytes.extend((self.dimensions as u32).to_le_bytes());
+        for value in self.embeddings.iter().flatten() {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn write(&self, format: EmbedFormat, out: &mut impl Write) -> Result<()> {
+        match format {
+            EmbedFormat::Json => writeln!(out, "{}", serde_json::to_string(self)?)?,
+            EmbedFormat::Binary => out.write_all(&self.to_bytes())?,
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// The texts to embed: the whole input, or each non-empty line of it
+pub fn split_inputs(text: &str, per_line: bool) -> Vec<String> {
+    if per_line {
+        text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect()
+    } else {
+        vec![text.to_string()]
+    }
+}
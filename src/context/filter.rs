@@ -0,0 +1,102 @@
+//! Per-provider adjustments made to a context before a provider sees it
+//!
+//! Filters are registered under a provider name and run in registration order:
+//!
+//! ```
+//! # use ai_cli::context::{ContextFilters, ContextPolicy, PolicyFilter};
+//! # use ai_cli::providers::Context;
+//! let mut filters = ContextFilters::builtin();
+//! filters.register("ollama", PolicyFilter(ContextPolicy::Last(4)));
+//! filters.register("ollama", |context: &mut Context| context.file_contents.clear());
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::ContextPolicy;
+use crate::providers::Context;
+
+/// Adjusts a context for one provider
+pub trait ContextFilter: Send + Sync {
+    fn filter(&self, context: &mut Context);
+}
+
+impl<F> ContextFilter for F
+where
+    F: Fn(&mut Context) + Send + Sync,
+{
+    fn filter(&self, context: &mut Context) {
+        self(context)
+    }
+}
+
+/// Keeps the part of the conversation a [`ContextPolicy`] allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyFilter(pub ContextPolicy);
+
+impl ContextFilter for PolicyFilter {
+    fn filter(&self, context: &mut Context) {
+        self.0.apply(context);
+    }
+}
+
+/// Marks the context as code-focused (`focus_mode = "code"` in its metadata)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodeFocusFilter;
+
+impl ContextFilter for CodeFocusFilter {
+    fn filter(&self, context: &mut Context) {
+        context.metadata.insert("focus_mode".to_string(), serde_json::json!("code"));
+    }
+}
+
+/// Filters registered per provider name
+#[derive(Clone, Default)]
+pub struct ContextFilters {
+    filters: HashMap<String, Vec<Arc<dyn ContextFilter>>>,
+}
+
+impl ContextFilters {
+    /// No filters for any provider
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gemini sees the last 10 messages; codex contexts are code-focused
+    pub fn builtin() -> Self {
+        let mut filters = Self::new();
+        // Gemini gets truncated history for performance
+        filters.register("gemini", PolicyFilter(ContextPolicy::Last(10)));
+        filters.register("codex", CodeFocusFilter);
+        filters
+    }
+
+    /// Run `filter` on contexts for `provider`, after any registered before
+    pub fn register(&mut self, provider: impl Into<String>, filter: impl ContextFilter + 'static) {
+        self.filters.entry(provider.into()).or_default().push(Arc::new(filter));
+    }
+
+    /// Remove every filter for `provider`, e.g. to replace a built-in one
+    pub fn clear(&mut self, provider: &str) {
+        self.filters.remove(provider);
+    }
+
+    /// Whether any filter is registered for `provider`
+    pub fn has_filters(&self, provider: &str) -> bool {
+        self.filters.get(provider).is_some_and(|filters| !filters.is_empty())
+    }
+
+    /// Run the filters for `provider` on `context`, in registration order
+    pub fn apply(&self, provider: &str, context: &mut Context) {
+        for filter in self.filters.get(provider).into_iter().flatten() {
+            filter.filter(context);
+        }
+    }
+}
+
+impl std::fmt::Debug for ContextFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts: HashMap<&str, usize> = self.filters.iter().map(|(name, filters)| (name.as_str(), filters.len())).collect();
+        f.debug_struct("ContextFilters").field("filters", &counts).finish()
+    }
+}
@@ -0,0 +1,175 @@
+//! Git diffs as context for "review my changes" pipelines
+//!
+//! Shells out to `git diff` and splits the patch into one entry per file so
+//! each change can be attributed and sized independently.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::context::Provenance;
+use crate::providers::Context;
+
+/// Which changes to diff
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffSource {
+    /// Unstaged changes in the working tree
+    WorkingTree,
+    /// Changes staged in the index
+    Staged,
+    /// A commit range such as `main..HEAD`
+    Range(String),
+}
+
+impl fmt::Display for DiffSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffSource::WorkingTree => write!(f, "working tree"),
+            DiffSource::Staged => write!(f, "staged"),
+            DiffSource::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
+
+/// How a file changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::Added => write!(f, "added"),
+            ChangeKind::Modified => write!(f, "modified"),
+            ChangeKind::Deleted => write!(f, "deleted"),
+            ChangeKind::Renamed => write!(f, "renamed"),
+        }
+    }
+}
+
+/// The patch for a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    /// Path after the change (before it, for deletions)
+    pub path: PathBuf,
+    /// Original path of a renamed file
+    pub old_path: Option<PathBuf>,
+    pub kind: ChangeKind,
+    /// Patch text starting at the `diff --git` header
+    pub patch: String,
+}
+
+/// Top-level directory of the repository containing `dir`; diff paths are relative to it
+pub fn repo_root(dir: &Path) -> Result<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .with_context(|| "Failed to run git; is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!("{} is not inside a git repository", dir.display()));
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Path of `name` inside the repository's git directory, e.g. `hooks` or `COMMIT_EDITMSG`
+///
+/// Honors worktrees and `core.hooksPath`.
+pub fn git_path(repo: &Path, name: &str) -> Result<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C").arg(repo)
+        .args(["rev-parse", "--git-path", name])
+        .output()
+        .with_context(|| "Failed to run git; is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!("{} is not inside a git repository", repo.display()));
+    }
+    Ok(repo.join(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Run `git diff` in `repo` and split the output per file
+pub fn collect_diff(repo: &Path, source: &DiffSource) -> Result<Vec<FileDiff>> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo).args(["diff", "--no-color", "--no-ext-diff", "--find-renames"]);
+    match source {
+        DiffSource::WorkingTree => {}
+        DiffSource::Staged => {
+            command.arg("--cached");
+        }
+        DiffSource::Range(range) => {
+            // `--end-of-options` keeps a range like `--output=x` from being read as a flag
+            command.args(["--end-of-options", range]);
+        }
+    }
+
+    let output = command.output().with_context(|| "Failed to run git; is it installed?")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git diff ({}) failed: {}", source, stderr.trim()));
+    }
+    Ok(parse_diff(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Split unified `git diff` output into per-file patches
+pub fn parse_diff(text: &str) -> Vec<FileDiff> {
+    let mut diffs = Vec::new();
+    let mut current: Option<FileDiff> = None;
+
+    for line in text.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            diffs.extend(current.take());
+            let path = header_path(header.trim_end()).unwrap_or_default();
+            current = Some(FileDiff { path, old_path: None, kind: ChangeKind::Modified, patch: String::new() });
+        }
+        let Some(diff) = current.as_mut() else { continue };
+        diff.patch.push_str(line);
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with("new file mode") {
+            diff.kind = ChangeKind::Added;
+        } else if line.starts_with("deleted file mode") {
+            diff.kind = ChangeKind::Deleted;
+        } else if let Some(from) = line.strip_prefix("rename from ") {
+            diff.kind = ChangeKind::Renamed;
+            diff.old_path = Some(PathBuf::from(from));
+        } else if let Some(to) = line.strip_prefix("rename to ") {
+            diff.path = PathBuf::from(to);
+        }
+    }
+    diffs.extend(current);
+    diffs
+}
+
+/// Path from a `a/<path> b/<path>` header; the b-side wins
+fn header_path(header: &str) -> Option<PathBuf> {
+    let (_, b) = header.split_once(" b/")?;
+    Some(PathBuf::from(b.trim_matches('"')))
+}
+
+/// Add each file's patch to the context as `<path>.diff`, returning how many were added
+///
+/// A file already diffed from another source gets `<path> (<source>).diff`.
+pub fn add_diffs_to_context(context: &mut Context, diffs: Vec<FileDiff>, source: &DiffSource) -> usize {
+    let count = diffs.len();
+    for diff in diffs {
+        let mut key = PathBuf::from(format!("{}.diff", diff.path.display()));
+        if context.file_contents.contains_key(&key) {
+            key = PathBuf::from(format!("{} ({}).diff", diff.path.display(), source));
+        }
+        let renamed = diff.old_path.as_ref()
+            .map(|old| format!(" from {}", old.display()))
+            .unwrap_or_default();
+        let header = format!("# {}{} ({} changes)\n", diff.kind, renamed, source);
+        context.add_file_with_provenance(
+            key,
+            format!("{}{}", header, diff.patch),
+            Provenance::GitDiff { source: source.to_string() },
+        );
+    }
+    count
+}
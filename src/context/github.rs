@@ -0,0 +1,216 @@
+//! GitHub pull requests as context for `ai-cli review --pr`
+//!
+//! Fetches a pull request and its per-file patches through the REST API. A
+//! token from `GITHUB_TOKEN` or `GH_TOKEN` is sent when set; public
+//! repositories work without one, at a lower rate limit.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::git::{ChangeKind, FileDiff};
+use crate::providers::http;
+
+const API_BASE: &str = "https://api.github.com";
+
+/// Files fetched per page; GitHub caps a pull request's file list at 3000
+const PER_PAGE: usize = 100;
+
+/// Environment variables read for the API token, in order
+pub const TOKEN_VARS: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+
+/// Which pull request to review
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRef {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+impl PullRef {
+    /// Parse `https://github.com/OWNER/REPO/pull/N`, `OWNER/REPO#N`, or a bare
+    /// number resolved against `remote` (the `origin` URL of the checkout)
+    pub fn parse(spec: &str, remote: Option<&str>) -> Result<Self> {
+        let spec = spec.trim();
+        if let Ok(number) = spec.trim_start_matches('#').parse::<u64>() {
+            let remote = remote.ok_or_else(|| anyhow!("PR number {} needs a GitHub `origin` remote, or pass the PR URL", number))?;
+            let (owner, repo) = parse_remote(remote)
+                .ok_or_else(|| anyhow!("origin remote '{}' is not a GitHub repository", remote))?;
+            return Ok(Self { owner, repo, number });
+        }
+        if let Some((slug, number)) = spec.split_once('#')
+            && let Some((owner, repo)) = slug.split_once('/')
+            && let Ok(number) = number.parse()
+        {
+            return Ok(Self { owner: owner.to_string(), repo: repo.to_string(), number });
+        }
+        let path = spec
+            .strip_prefix("https://github.com/")
+            .or_else(|| spec.strip_prefix("http://github.com/"))
+            .ok_or_else(|| anyhow!("'{}' is not a pull request URL, OWNER/REPO#N or number", spec))?;
+        let parts: Vec<&str> = path.split('/').collect();
+        match parts.as_slice() {
+            [owner, repo, "pull", number, ..] => Ok(Self {
+                owner: owner.to_string(),
+                repo: repo.to_string(),
+                number: number.parse().map_err(|_| anyhow!("'{}' is not a pull request URL", spec))?,
+            }),
+            _ => Err(anyhow!("'{}' is not a pull request URL", spec)),
+        }
+    }
+}
+
+impl std::fmt::Display for PullRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}#{}", self.owner, self.repo, self.number)
+    }
+}
+
+/// `OWNER/REPO` of an SSH or HTTPS GitHub remote URL
+pub fn parse_remote(url: &str) -> Option<(String, String)> {
+    let path = url
+        .strip_prefix("git@github.com:")
+        .or_else(|| url.strip_prefix("ssh://git@github.com/"))
+        .or_else(|| url.strip_prefix("https://github.com/"))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (owner, repo) = path.split_once('/')?;
+    (!owner.is_empty() && !repo.is_empty() && !repo.contains('/')).then(|| (owner.to_string(), repo.to_string()))
+}
+
+/// A pull request and the patch of every changed file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    /// Commit the review comments refer to
+    pub head_sha: String,
+    /// Changed files; binary and very large files have an empty patch
+    pub files: Vec<FileDiff>,
+}
+
+#[derive(Deserialize)]
+struct PullResponse {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    head: HeadResponse,
+}
+
+#[derive(Deserialize)]
+struct HeadResponse {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct FileResponse {
+    filename: String,
+    status: String,
+    #[serde(default)]
+    previous_filename: Option<String>,
+    #[serde(default)]
+    patch: Option<String>,
+}
+
+impl FileResponse {
+    fn into_diff(self) -> FileDiff {
+        let kind = match self.status.as_str() {
+            "added" => ChangeKind::Added,
+            "removed" => ChangeKind::Deleted,
+            "renamed" => ChangeKind::Renamed,
+            _ => ChangeKind::Modified,
+        };
+        let path = PathBuf::from(&self.filename);
+        let old = self.previous_filename.as_deref().unwrap_or(&self.filename);
+        let patch = match &self.patch {
+            Some(patch) => format!("diff --git a/{} b/{}\n{}\n", old, self.filename, patch.trim_end()),
+            None => String::new(),
+        };
+        FileDiff { path, old_path: self.previous_filename.map(PathBuf::from), kind, patch }
+    }
+}
+
+/// Read-only client for the GitHub REST API
+pub struct GitHubClient {
+    token: Option<String>,
+    base_url: String,
+    transport: Arc<dyn http::Transport>,
+}
+
+impl GitHubClient {
+    /// Client authenticated with the first token found in [`TOKEN_VARS`]
+    pub fn from_env() -> Self {
+        let token = TOKEN_VARS.iter().find_map(|var| std::env::var(var).ok().filter(|t| !t.is_empty()));
+        Self { token, base_url: API_BASE.to_string(), transport: http::default_transport() }
+    }
+
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Use a different API root, e.g. GitHub Enterprise's `https://HOST/api/v3`
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the network
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Fetch the pull request and all of its changed files
+    pub async fn pull_request(&self, pull: &PullRef) -> Result<PullRequest> {
+        let root = format!("{}/repos/{}/{}/pulls/{}", self.base_url, pull.owner, pull.repo, pull.number);
+        let info: PullResponse = self.get(&root).await?;
+        let mut files = Vec::new();
+        for page in 1.. {
+            let batch: Vec<FileResponse> = self.get(&format!("{}/files?per_page={}&page={}", root, PER_PAGE, page)).await?;
+            let last = batch.len() < PER_PAGE;
+            files.extend(batch.into_iter().map(FileResponse::into_diff));
+            if last {
+                break;
+            }
+        }
+        Ok(PullRequest {
+            number: info.number,
+            title: info.title,
+            body: info.body.unwrap_or_default(),
+            head_sha: info.head.sha,
+            files,
+        })
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut request = http::shared_client()
+            .get(url)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "ai-cli");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let resp = http::send_via(self.transport.as_ref(), "github", request)
+            .await
+            .with_context(|| "Failed to reach the GitHub API")?;
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("github", "GitHub API request failed", resp).await);
+        }
+        resp.json().await.with_context(|| format!("Unexpected GitHub API response from {}", url))
+    }
+}
+
+/// `origin` URL of the repository at `repo`, if it has one
+pub fn origin_url(repo: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C").arg(repo)
+        .args(["remote", "get-url", "origin"])
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
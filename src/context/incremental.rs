@@ -0,0 +1,161 @@
+use similar::TextDiff;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::Provenance;
+use crate::providers::{Context, Message, MessageRole};
+
+/// How a file changed between two runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileChange {
+    /// New file; sent in full
+    Added { path: PathBuf, content: String },
+    /// Changed file; sent as a unified diff
+    Modified { path: PathBuf, diff: String },
+    /// Changed so much that the diff would be larger than the file; sent in full
+    Rewritten { path: PathBuf, content: String },
+    /// File no longer present
+    Removed { path: PathBuf },
+}
+
+impl FileChange {
+    /// Path of the changed file
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            FileChange::Added { path, .. }
+            | FileChange::Modified { path, .. }
+            | FileChange::Rewritten { path, .. }
+            | FileChange::Removed { path } => path,
+        }
+    }
+}
+
+/// Compare two sets of file contents, ordered by path
+pub fn diff_files(previous: &HashMap<PathBuf, String>, current: &HashMap<PathBuf, String>) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+
+    for (path, content) in current {
+        match previous.get(path) {
+            None => changes.push(FileChange::Added { path: path.clone(), content: content.clone() }),
+            Some(old) if old != content => {
+                let diff = unified_diff(path, old, content);
+                if diff.len() < content.len() {
+                    changes.push(FileChange::Modified { path: path.clone(), diff });
+                } else {
+                    changes.push(FileChange::Rewritten { path: path.clone(), content: content.clone() });
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for path in previous.keys() {
+        if !current.contains_key(path) {
+            changes.push(FileChange::Removed { path: path.clone() });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
+
+/// Unified diff of a file with three lines of context
+pub fn unified_diff(path: &std::path::Path, old: &str, new: &str) -> String {
+    let name = path.display().to_string();
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string()
+}
+
+/// Render changes as a short recap followed by the per-file updates
+pub fn render_update(changes: &[FileChange], unchanged: usize) -> String {
+    if changes.is_empty() {
+        return format!("Incremental update: no files changed since the last run ({} unchanged).", unchanged);
+    }
+
+    let count = |f: fn(&FileChange) -> bool| changes.iter().filter(|c| f(c)).count();
+    let modified = count(|c| matches!(c, FileChange::Modified { .. } | FileChange::Rewritten { .. }));
+    let added = count(|c| matches!(c, FileChange::Added { .. }));
+    let removed = count(|c| matches!(c, FileChange::Removed { .. }));
+
+    let mut out = format!(
+        "Incremental update: {} modified, {} added, {} removed; {} unchanged since the last run.\n",
+        modified, added, removed, unchanged
+    );
+    for change in changes {
+        out.push('\n');
+        match change {
+            FileChange::Added { path, content } => {
+                out.push_str(&format!("=== added {} ===\n{}\n", path.display(), content));
+            }
+            FileChange::Modified { diff, .. } => out.push_str(diff),
+            FileChange::Rewritten { path, content } => {
+                out.push_str(&format!("=== rewritten {} ===\n{}\n", path.display(), content));
+            }
+            FileChange::Removed { path } => out.push_str(&format!("=== removed {} ===\n", path.display())),
+        }
+    }
+    out
+}
+
+/// Sends full file contents on the first run and only changes on later runs
+///
+/// Used by watch mode, which re-runs a pipeline as files change.
+#[derive(Debug, Default)]
+pub struct IncrementalContext {
+    sent: Option<HashMap<PathBuf, String>>,
+    /// What was sent before the last `prepare`, kept for `rollback`
+    previous: Option<HashMap<PathBuf, String>>,
+}
+
+impl IncrementalContext {
+    /// Create a tracker with nothing sent yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if a previous run's files are known
+    pub fn has_baseline(&self) -> bool {
+        self.sent.is_some()
+    }
+
+    /// Forget what was sent so the next run includes every file again
+    pub fn reset(&mut self) {
+        self.sent = None;
+        self.previous = None;
+    }
+
+    /// Go back to the baseline before the last `prepare`, when its update never reached the provider
+    ///
+    /// The next run then diffs against what the provider last saw, so no change is lost.
+    pub fn rollback(&mut self) {
+        self.sent = self.previous.take();
+    }
+
+    /// Build the context to send for this run and remember its files
+    ///
+    /// After the first run, file contents are replaced by a system message with a
+    /// recap and diffs of the files that changed since the previous run.
+    pub fn prepare(&mut self, context: &Context) -> Context {
+        let current = context.file_contents.clone();
+        self.previous = self.sent.replace(current.clone());
+        let Some(previous) = &self.previous else {
+            return context.clone();
+        };
+
+        let changes = diff_files(previous, &current);
+        let unchanged = current.len() - changes.iter()
+            .filter(|c| !matches!(c, FileChange::Removed { .. }))
+            .count();
+
+        let mut update = context.clone();
+        update.file_contents.clear();
+        update.file_provenance.clear();
+        update.add_message(
+            Message::new(MessageRole::System, render_update(&changes, unchanged))
+                .with_provenance(Provenance::IncrementalUpdate { changed_files: changes.len() }),
+        );
+        update
+    }
+}
@@ -0,0 +1,390 @@
+//! Embedding index for retrieving relevant chunks instead of whole files
+//!
+//! `ai-cli index` splits project files into line-based chunks, embeds them and
+//! stores the vectors in `<cache dir>/index/<root hash>.json`. With `--retrieve`,
+//! each step's prompt is embedded and the closest chunks are added to its context.
+//! Files whose content hash is unchanged keep their vectors on re-indexing.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config;
+use crate::context::{Provenance, Redactor};
+use crate::providers::Context;
+
+/// Lines per chunk when none is configured
+pub const DEFAULT_CHUNK_LINES: usize = 40;
+
+/// Texts sent per embedding request
+const EMBED_BATCH: usize = 64;
+
+/// Turns text into vectors whose dot products measure relevance
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Identifies the model; vectors from different embedders are not comparable
+    fn name(&self) -> &str;
+
+    /// Embed each text, returning vectors in the same order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embed any number of texts in batches, checking every text got a vector
+pub async fn embed_all(embedder: &dyn Embedder, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBED_BATCH) {
+        let embedded = embedder.embed(batch).await?;
+        if embedded.len() != batch.len() {
+            return Err(anyhow!("Embedder returned {} vectors for {} texts", embedded.len(), batch.len()));
+        }
+        vectors.extend(embedded);
+    }
+    Ok(vectors)
+}
+
+/// Offline embedder using hashed identifier and word features
+///
+/// Identifiers are split on camelCase and snake_case so `parseConfig` matches a
+/// prompt about "config parsing". Needs no credentials and no network.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    dimensions: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions: dimensions.max(1) }
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut counts: HashMap<String, f32> = HashMap::new();
+        for term in terms(text) {
+            *counts.entry(term).or_default() += 1.0;
+        }
+        let mut vector = vec![0.0f32; self.dimensions];
+        for (term, count) in counts {
+            let hash = fnv1a(term.as_bytes());
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimensions as u64) as usize] += sign * (1.0 + count.ln());
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self { Self::new(512) }
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+/// Lowercased words, with identifiers also split into their parts
+fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        if word.is_empty() {
+            continue;
+        }
+        let lower = word.to_lowercase();
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut previous_lower = false;
+        for c in word.chars() {
+            if (c == '_' || (c.is_uppercase() && previous_lower)) && !current.is_empty() {
+                parts.push(std::mem::take(&mut current).to_lowercase());
+            }
+            if c != '_' {
+                current.push(c);
+            }
+            previous_lower = c.is_lowercase() || c.is_numeric();
+        }
+        if !current.is_empty() {
+            parts.push(current.to_lowercase());
+        }
+        if parts.len() > 1 {
+            terms.extend(parts.into_iter().filter(|p| p.len() > 1));
+        }
+        if lower.len() > 1 {
+            terms.push(lower);
+        }
+    }
+    terms
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// Stable 64-bit FNV-1a hash; used for file fingerprints and feature hashing
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Split text into chunks of at most `max_lines` lines, preferring to break at blank lines
+///
+/// Returns `(first_line, last_line, text)` with 1-based inclusive line numbers.
+pub fn chunk_text(text: &str, max_lines: usize) -> Vec<(usize, usize, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let max_lines = max_lines.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = (start + max_lines).min(lines.len());
+        if end < lines.len() {
+            // Break after the last blank line in the second half of the window
+            if let Some(blank) = (start + max_lines / 2..end).rev().find(|&i| lines[i].trim().is_empty()) {
+                end = blank + 1;
+            }
+        }
+        let chunk = lines[start..end].join("\n");
+        if !chunk.trim().is_empty() {
+            chunks.push((start + 1, end, chunk));
+        }
+        start = end;
+    }
+    chunks
+}
+
+/// One embedded chunk of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+/// Chunks of one file, with the content hash they were computed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Path relative to the index root
+    pub path: PathBuf,
+    pub hash: u64,
+    pub chunks: Vec<IndexedChunk>,
+}
+
+/// What a build changed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexStats {
+    /// Files chunked and embedded
+    pub embedded: usize,
+    /// Unchanged files whose vectors were kept
+    pub reused: usize,
+    /// Files dropped because they no longer exist
+    pub removed: usize,
+}
+
+/// A chunk returned for a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievedChunk {
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    /// Cosine similarity to the query
+    pub score: f32,
+}
+
+/// On-disk vector store for one project root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorIndex {
+    pub root: PathBuf,
+    /// Name of the embedder that produced the vectors
+    pub embedder: String,
+    pub chunk_lines: usize,
+    pub files: Vec<IndexedFile>,
+}
+
+impl VectorIndex {
+    /// Empty index for a project root
+    pub fn new(root: impl Into<PathBuf>, embedder: impl Into<String>) -> Self {
+        Self { root: root.into(), embedder: embedder.into(), chunk_lines: DEFAULT_CHUNK_LINES, files: Vec::new() }
+    }
+
+    /// Use a different chunk size for files embedded from now on
+    pub fn with_chunk_lines(mut self, chunk_lines: usize) -> Self {
+        self.chunk_lines = chunk_lines.max(1);
+        self
+    }
+
+    /// Default location of the index for a project root
+    pub fn default_path(root: &Path) -> Result<PathBuf> {
+        let key = fnv1a(root.to_string_lossy().as_bytes());
+        Ok(config::cache_dir()?.join("index").join(format!("{:016x}.json", key)))
+    }
+
+    /// Read an index file
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(anyhow!("No index at {}; run `ai-cli index` first", path.display()));
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid index file {}", path.display()))
+    }
+
+    /// Write the index, creating parent directories
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Total number of chunks
+    pub fn chunk_count(&self) -> usize {
+        self.files.iter().map(|f| f.chunks.len()).sum()
+    }
+
+    /// Bring the index in line with `files` (root-relative path and content)
+    ///
+    /// Unchanged files keep their vectors; files not listed are dropped.
+    pub async fn update(&mut self, files: Vec<(PathBuf, String)>, embedder: &dyn Embedder) -> Result<IndexStats> {
+        self.update_redacted(files, embedder, None).await
+    }
+
+    /// [`update`](Self::update), passing the text of changed files through `redactor` before it is chunked and embedded
+    ///
+    /// Unchanged files are still recognized by the hash of their original content.
+    pub async fn update_redacted(&mut self, files: Vec<(PathBuf, String)>, embedder: &dyn Embedder, redactor: Option<&Redactor>) -> Result<IndexStats> {
+        if embedder.name() != self.embedder {
+            return Err(anyhow!(
+                "Index was built with the '{}' embedder, not '{}'; rebuild it",
+                self.embedder,
+                embedder.name()
+            ));
+        }
+        let mut previous: HashMap<PathBuf, IndexedFile> =
+            self.files.drain(..).map(|f| (f.path.clone(), f)).collect();
+        let mut stats = IndexStats::default();
+        let mut pending: Vec<(IndexedFile, Vec<String>)> = Vec::new();
+
+        for (path, content) in files {
+            let hash = fnv1a(content.as_bytes());
+            match previous.remove(&path) {
+                Some(existing) if existing.hash == hash => {
+                    self.files.push(existing);
+                    stats.reused += 1;
+                }
+                _ => {
+                    let content = match redactor {
+                        Some(redactor) => redactor.redact_text(&content, &path.display().to_string()),
+                        None => content,
+                    };
+                    let chunks = chunk_text(&content, self.chunk_lines);
+                    let texts = chunks.iter().map(|(_, _, text)| text.clone()).collect();
+                    let chunks = chunks
+                        .into_iter()
+                        .map(|(start_line, end_line, text)| IndexedChunk { start_line, end_line, text, vector: Vec::new() })
+                        .collect();
+                    pending.push((IndexedFile { path, hash, chunks }, texts));
+                }
+            }
+        }
+        stats.removed = previous.len();
+
+        let texts: Vec<String> = pending.iter().flat_map(|(_, texts)| texts.iter().cloned()).collect();
+        let mut vectors = embed_all(embedder, &texts).await?.into_iter();
+        for (mut file, _) in pending {
+            for chunk in &mut file.chunks {
+                chunk.vector = vectors.next().unwrap_or_default();
+            }
+            self.files.push(file);
+            stats.embedded += 1;
+        }
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(stats)
+    }
+
+    /// The `k` chunks most similar to a query vector, best first
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<RetrievedChunk> {
+        let mut scored: Vec<RetrievedChunk> = self.files
+            .iter()
+            .flat_map(|file| file.chunks.iter().map(move |chunk| (file, chunk)))
+            .map(|(file, chunk)| RetrievedChunk {
+                path: file.path.clone(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                text: chunk.text.clone(),
+                score: cosine(query, &chunk.vector),
+            })
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(k);
+        scored
+    }
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norm > 0.0 { dot / norm } else { 0.0 }
+}
+
+/// Adds the chunks most relevant to a prompt to a context
+pub struct Retriever {
+    index: VectorIndex,
+    embedder: Arc<dyn Embedder>,
+    top_k: usize,
+}
+
+impl Retriever {
+    /// Query `index` with the embedder it was built with
+    pub fn new(index: VectorIndex, embedder: Arc<dyn Embedder>, top_k: usize) -> Result<Self> {
+        if embedder.name() != index.embedder {
+            return Err(anyhow!(
+                "Index was built with the '{}' embedder, not '{}'",
+                index.embedder,
+                embedder.name()
+            ));
+        }
+        Ok(Self { index, embedder, top_k })
+    }
+
+    /// Chunks most relevant to a query, best first
+    pub async fn retrieve(&self, query: &str) -> Result<Vec<RetrievedChunk>> {
+        let vector = self.embedder
+            .embed(&[query.to_string()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Embedder returned no vector for the query"))?;
+        Ok(self.index.search(&vector, self.top_k))
+    }
+
+    /// Add the relevant chunks to a context as `path#Lstart-Lend` entries, returning how many
+    ///
+    /// Chunks of files already in the context in full are skipped.
+    pub async fn inject(&self, query: &str, context: &mut Context) -> Result<usize> {
+        let mut added = 0;
+        for chunk in self.retrieve(query).await? {
+            if context.file_contents.contains_key(&chunk.path) {
+                continue;
+            }
+            let key = PathBuf::from(format!("{}#L{}-L{}", chunk.path.display(), chunk.start_line, chunk.end_line));
+            let source = format!("index ({}, score {:.2})", self.index.embedder, chunk.score);
+            context.add_file_with_provenance(key, chunk.text, Provenance::Retrieval { source });
+            added += 1;
+        }
+        Ok(added)
+    }
+}
@@ -0,0 +1,470 @@
+//! Loading files, directories and globs into a `Context`
+//!
+//! Directory walks and glob matches honor `.aiignore` (gitignore syntax) at the
+//! project root and skip `.git`; files named explicitly are always read. Binary
+//! files and files over the size limits are skipped and reported, never mangled.
+//!
+//! With [`IgnoreSettings`] applied, walks also skip build output, dependency
+//! directories, lockfiles and common binary formats, plus whatever `.gitignore`
+//! files list. Rules are layered with the last match winning: built-in
+//! defaults, then `.gitignore` files from the root down, then `.aiignore`, then
+//! `[ignore] patterns`, so `!Cargo.lock` in `.aiignore` brings a lockfile back.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::context::Provenance;
+use crate::context::chunking::{ChunkingSettings, chunk_source};
+use crate::providers::Context;
+
+/// Name of the ignore file read from the project root
+pub const IGNORE_FILE: &str = ".aiignore";
+
+/// Name of the git ignore files honored in every directory
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// Ignored in every project: dependency and cache directories, lockfiles and
+/// binary formats that would only waste tokens
+const COMMON_IGNORES: &str = "\
+node_modules/
+__pycache__/
+.venv/
+.tox/
+.mypy_cache/
+.pytest_cache/
+.ruff_cache/
+.gradle/
+.next/
+.nuxt/
+.terraform/
+.DS_Store
+Cargo.lock
+package-lock.json
+npm-shrinkwrap.json
+yarn.lock
+pnpm-lock.yaml
+bun.lockb
+poetry.lock
+Pipfile.lock
+uv.lock
+composer.lock
+Gemfile.lock
+go.sum
+mix.lock
+pubspec.lock
+Podfile.lock
+flake.lock
+*.min.js
+*.min.css
+*.pyc
+*.class
+*.jar
+*.o
+*.a
+*.so
+*.dylib
+*.dll
+*.exe
+*.wasm
+*.png
+*.jpg
+*.jpeg
+*.gif
+*.webp
+*.ico
+*.pdf
+*.zip
+*.gz
+*.tgz
+*.xz
+*.7z
+*.tar
+*.woff
+*.woff2
+*.ttf
+*.otf
+*.mp3
+*.mp4
+*.sqlite
+*.db
+";
+
+/// Build output ignored only when a marker file at the project root shows the
+/// language, since names like `build/` and `vendor/` are sources elsewhere
+const LANGUAGE_IGNORES: &[(&[&str], &str)] = &[
+    (&["Cargo.toml"], "target/"),
+    (&["pom.xml"], "target/"),
+    (&["build.gradle", "build.gradle.kts"], "build/"),
+    (&["package.json"], "dist/\ncoverage/"),
+    (&["pyproject.toml", "setup.py", "setup.cfg"], "build/\ndist/\n*.egg-info/"),
+    (&["go.mod", "composer.json"], "/vendor/"),
+    (&["mix.exs"], "_build/\ndeps/"),
+    (&["pubspec.yaml"], ".dart_tool/"),
+];
+
+/// Bytes inspected for NUL when detecting binary files
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// `[context_limits]` section: caps on ingested file sizes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContextLimits {
+    /// Files larger than this are skipped
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Files are skipped once the context would grow past this
+    #[serde(default = "default_max_total_bytes")]
+    pub max_total_bytes: u64,
+}
+
+fn default_max_file_bytes() -> u64 { 256 * 1024 }
+fn default_max_total_bytes() -> u64 { 2 * 1024 * 1024 }
+
+impl Default for ContextLimits {
+    fn default() -> Self {
+        Self { max_file_bytes: default_max_file_bytes(), max_total_bytes: default_max_total_bytes() }
+    }
+}
+
+/// `[ignore]` section: what directory walks and globs leave out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IgnoreSettings {
+    /// Skip build output, dependency directories, lockfiles and binary formats
+    #[serde(default = "default_true")]
+    pub defaults: bool,
+    /// Honor `.gitignore` files
+    #[serde(default = "default_true")]
+    pub gitignore: bool,
+    /// More gitignore-style patterns, applied after `.aiignore`
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+fn default_true() -> bool { true }
+
+impl Default for IgnoreSettings {
+    fn default() -> Self {
+        Self { defaults: true, gitignore: true, patterns: Vec::new() }
+    }
+}
+
+/// Built-in rules for a project at `root`: the common ones plus the build
+/// output of each language whose marker file is present
+pub fn default_ignores(root: &Path) -> IgnoreRules {
+    let mut text = COMMON_IGNORES.to_string();
+    for (markers, patterns) in LANGUAGE_IGNORES {
+        if markers.iter().any(|marker| root.join(marker).is_file()) {
+            text.push_str(patterns);
+            text.push('\n');
+        }
+    }
+    IgnoreRules::parse(&text).expect("built-in ignore patterns are valid")
+}
+
+/// Why a matched file was left out of the context
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    Binary,
+    TooLarge { size: u64, limit: u64 },
+    BudgetExhausted { limit: u64 },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Binary => write!(f, "binary or non-UTF-8"),
+            SkipReason::TooLarge { size, limit } => write!(f, "{} bytes exceeds the {} byte file limit", size, limit),
+            SkipReason::BudgetExhausted { limit } => write!(f, "context already holds {} bytes", limit),
+        }
+    }
+}
+
+/// Gitignore-style rules, matched by the `ignore` crate exactly as git does:
+/// `#` comments, `!` negation, trailing `/` for directories, and patterns
+/// without a `/` matching at any depth
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// Later layers take precedence
+    layers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Parse ignore-file text, failing on any pattern that cannot be parsed
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new("");
+        for line in text.lines() {
+            builder.add_line(None, line).map_err(|e| anyhow!("Invalid ignore pattern '{}': {}", line.trim_end(), e))?;
+        }
+        Self::build(builder)
+    }
+
+    /// Parse ignore-file text the way git reads it: lines that cannot be parsed
+    /// are skipped and the rest still apply
+    pub fn parse_lenient(text: &str) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for line in text.lines() {
+            if let Err(e) = builder.add_line(None, line) {
+                tracing::debug!(line, error = %e, "skipping ignore pattern");
+            }
+        }
+        Self::build(builder).unwrap_or_default()
+    }
+
+    fn build(builder: GitignoreBuilder) -> Result<Self> {
+        let rules = builder.build().map_err(|e| anyhow!("Invalid ignore patterns: {}", e))?;
+        Ok(Self { layers: vec![rules] })
+    }
+
+    /// Load rules from a file, returning no rules when it does not exist;
+    /// unparsable lines are skipped
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::parse_lenient(&text))
+    }
+
+    /// Append `other`'s rules, which then take precedence
+    pub fn extend(&mut self, other: IgnoreRules) {
+        self.layers.extend(other.layers);
+    }
+
+    /// Check a root-relative path; a path under an ignored directory is ignored
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        under_ignored(relative, is_dir, |path, is_dir| self.verdict(path, is_dir).unwrap_or(false))
+    }
+
+    /// Whether the last matching rule ignores `path`; `None` when none matches
+    fn verdict(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        self.layers.iter().rev().find_map(|layer| match layer.matched(path, is_dir) {
+            Match::None => None,
+            Match::Ignore(_) => Some(true),
+            Match::Whitelist(_) => Some(false),
+        })
+    }
+}
+
+/// Whether `ignored` holds for `relative` or any directory above it
+fn under_ignored(relative: &Path, is_dir: bool, ignored: impl Fn(&Path, bool) -> bool) -> bool {
+    let mut prefix = PathBuf::new();
+    let components: Vec<_> = relative.components().collect();
+    for (index, component) in components.iter().enumerate() {
+        prefix.push(component);
+        let last = index + 1 == components.len();
+        if ignored(&prefix, !last || is_dir) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Resolves `--context` specs and reads matched files into a context
+#[derive(Debug)]
+pub struct ContextLoader {
+    root: PathBuf,
+    /// Built-in rules, overridden by everything else
+    defaults: IgnoreRules,
+    /// Rules of each directory's `.gitignore`, keyed by root-relative directory;
+    /// `None` until [`ContextLoader::with_ignore_settings`] turns them on
+    gitignores: Option<Mutex<HashMap<PathBuf, IgnoreRules>>>,
+    /// `.aiignore` followed by `[ignore] patterns`
+    ignore: IgnoreRules,
+    limits: ContextLimits,
+    chunking: ChunkingSettings,
+    total_bytes: u64,
+}
+
+impl ContextLoader {
+    /// Create a loader rooted at the project root, reading its `.aiignore`
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let ignore = IgnoreRules::load(&root.join(IGNORE_FILE))?;
+        Ok(Self {
+            root,
+            defaults: IgnoreRules::default(),
+            gitignores: None,
+            ignore,
+            limits: ContextLimits::default(),
+            chunking: ChunkingSettings::default(),
+            total_bytes: 0,
+        })
+    }
+
+    /// Add the built-in, `.gitignore` and configured rules of `[ignore]`
+    pub fn with_ignore_settings(mut self, settings: &IgnoreSettings) -> Result<Self> {
+        if settings.defaults {
+            self.defaults = default_ignores(&self.root);
+        }
+        if settings.gitignore {
+            self.gitignores = Some(Mutex::new(HashMap::new()));
+        }
+        let patterns = IgnoreRules::parse(&settings.patterns.join("\n")).context("Invalid [ignore] patterns")?;
+        self.ignore.extend(patterns);
+        Ok(self)
+    }
+
+    /// Use specific size limits
+    pub fn with_limits(mut self, limits: ContextLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Chunk files over the size limit instead of skipping them
+    pub fn with_chunking(mut self, chunking: ChunkingSettings) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Replace the ignore rules
+    pub fn with_ignore(mut self, ignore: IgnoreRules) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Expand a file, directory or glob spec (relative to `base`) into files
+    ///
+    /// A spec that matches nothing is an error so typos do not go unnoticed.
+    pub fn expand(&self, spec: &str, base: &Path) -> Result<Vec<PathBuf>> {
+        let path = base.join(spec);
+        let mut files = Vec::new();
+        if spec.contains(['*', '?', '[']) {
+            let matches = glob::glob(&path.to_string_lossy())
+                .map_err(|e| anyhow!("Invalid context glob '{}': {}", spec, e))?;
+            for entry in matches {
+                let entry = entry.with_context(|| format!("Failed to read a match of '{}'", spec))?;
+                if entry.is_file() && !self.is_ignored(&entry, false) {
+                    files.push(entry);
+                }
+            }
+        } else if path.is_dir() {
+            self.walk(&path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path);
+        } else {
+            return Err(anyhow!("Context path '{}' does not exist", spec));
+        }
+
+        if files.is_empty() {
+            return Err(anyhow!("Context '{}' matched no files", spec));
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// Read files into the context, returning the ones skipped and why
+    ///
+    /// Files under the root are keyed by their root-relative path.
+    pub fn load_into(&mut self, context: &mut Context, files: Vec<PathBuf>, provenance: Provenance) -> Result<Vec<(PathBuf, SkipReason)>> {
+        let mut skipped = Vec::new();
+        for file in files {
+            let key = file.strip_prefix(&self.root).map(Path::to_path_buf).unwrap_or_else(|_| file.clone());
+            if context.file_contents.contains_key(&key) {
+                continue;
+            }
+            let size = std::fs::metadata(&file)
+                .with_context(|| format!("Failed to read context file {}", file.display()))?
+                .len();
+            let oversized = size > self.limits.max_file_bytes;
+            if oversized && !self.chunking.large_files {
+                skipped.push((file, SkipReason::TooLarge { size, limit: self.limits.max_file_bytes }));
+                continue;
+            }
+            if !oversized && self.total_bytes + size > self.limits.max_total_bytes {
+                skipped.push((file, SkipReason::BudgetExhausted { limit: self.limits.max_total_bytes }));
+                continue;
+            }
+
+            let bytes = std::fs::read(&file)
+                .with_context(|| format!("Failed to read context file {}", file.display()))?;
+            let text = match String::from_utf8(bytes) {
+                Ok(text) if !text.as_bytes()[..text.len().min(BINARY_SNIFF_BYTES)].contains(&0) => text,
+                _ => {
+                    skipped.push((file, SkipReason::Binary));
+                    continue;
+                }
+            };
+            if oversized {
+                // A file only partly loaded is reported too, so the cut is never silent
+                if !self.load_chunks(context, &key, &text, &provenance) {
+                    skipped.push((file, SkipReason::BudgetExhausted { limit: self.limits.max_total_bytes }));
+                }
+                continue;
+            }
+            self.total_bytes += size;
+            context.add_file_with_provenance(key, text, provenance.clone());
+        }
+        Ok(skipped)
+    }
+
+    /// Add an oversized file as `path#Lx-y` chunks of at most `max_file_bytes`
+    /// while the budget lasts; returns whether every chunk fit
+    fn load_chunks(&mut self, context: &mut Context, key: &Path, text: &str, provenance: &Provenance) -> bool {
+        let max_bytes = usize::try_from(self.limits.max_file_bytes).unwrap_or(usize::MAX);
+        for chunk in chunk_source(key, text, max_bytes) {
+            let size = chunk.text.len() as u64;
+            if self.total_bytes + size > self.limits.max_total_bytes {
+                return false;
+            }
+            self.total_bytes += size;
+            context.add_file_with_provenance(chunk.key(), chunk.text, provenance.clone());
+        }
+        true
+    }
+
+    /// Check whether the ignore rules exclude a path under the root
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => under_ignored(relative, is_dir, |path, is_dir| self.verdict(path, is_dir)),
+            Err(_) => false,
+        }
+    }
+
+    /// Combined verdict of every rule layer for one root-relative path
+    fn verdict(&self, relative: &Path, is_dir: bool) -> bool {
+        let mut ignored = self.defaults.verdict(relative, is_dir);
+        if let Some(gitignores) = &self.gitignores {
+            let mut cache = gitignores.lock().unwrap_or_else(|e| e.into_inner());
+            // Deeper .gitignore files override shallower ones
+            for dir in relative.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+                let rules = cache
+                    .entry(dir.to_path_buf())
+                    // Like git, bad lines are skipped; an unreadable file just adds no rules
+                    .or_insert_with(|| IgnoreRules::load(&self.root.join(dir).join(GITIGNORE_FILE)).unwrap_or_default());
+                if let Ok(within) = relative.strip_prefix(dir) {
+                    ignored = rules.verdict(within, is_dir).or(ignored);
+                }
+            }
+        }
+        self.ignore.verdict(relative, is_dir).or(ignored).unwrap_or(false)
+    }
+
+    fn walk(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let mut entries: Vec<_> = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            // Symlinked directories are not followed, which also rules out cycles
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if entry.file_name() != ".git" && !self.is_ignored(&path, true) {
+                    self.walk(&path, files)?;
+                }
+            } else if path.is_file() && !self.is_ignored(&path, false) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+pub mod chunking;
+pub mod compact;
+pub mod embed;
+pub mod filter;
+pub mod git;
+pub mod github;
+pub mod incremental;
+pub mod index;
+pub mod ingest;
+pub mod policy;
+pub mod privacy;
+pub mod provenance;
+pub mod redact;
+pub mod watch;
+pub mod workspace;
+
+pub use chunking::{Chunk, ChunkingSettings};
+pub use compact::{CompactionSettings, Compactor};
+pub use embed::{EmbedFormat, Embeddings};
+pub use filter::{CodeFocusFilter, ContextFilter, ContextFilters, PolicyFilter};
+pub use git::{DiffSource, FileDiff};
+pub use incremental::{FileChange, IncrementalContext};
+pub use index::{Embedder, HashEmbedder, Retriever, VectorIndex};
+pub use ingest::{ContextLimits, ContextLoader, IgnoreRules, IgnoreSettings, SkipReason};
+pub use policy::ContextPolicy;
+pub use privacy::{Anonymizer, ChunkRestorer, Sensitive};
+pub use provenance::Provenance;
+pub use redact::{Redaction, RedactionSettings, Redactor};
+pub use watch::FileWatcher;
+pub use workspace::{Package, PackageKind, Workspace};
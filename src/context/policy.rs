@@ -0,0 +1,97 @@
+//! How much conversation history reaches a provider
+//!
+//! A policy is written `full`, `last:N`, `summary` or `none`, e.g. in config:
+//!
+//! ```toml
+//! context_policy = "last:20"
+//!
+//! [providers.gemini]
+//! context_policy = "summary"
+//! ```
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::providers::{Context, MessageRole};
+
+/// Conversation history a provider is sent; files and environment are unaffected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ContextPolicy {
+    /// Every message
+    #[default]
+    Full,
+    /// System messages plus the last N user and assistant messages
+    Last(usize),
+    /// System messages only: instructions and summaries of earlier turns
+    SummaryOnly,
+    /// No conversation history
+    None,
+}
+
+impl ContextPolicy {
+    /// Drop the history this policy withholds from `context`
+    pub fn apply(self, context: &mut Context) {
+        let history = &mut context.conversation_history;
+        match self {
+            ContextPolicy::Full => {}
+            ContextPolicy::Last(n) => {
+                let others = history.iter().filter(|m| m.role != MessageRole::System).count();
+                let mut skip = others.saturating_sub(n);
+                history.retain(|m| {
+                    if m.role == MessageRole::System || skip == 0 {
+                        return true;
+                    }
+                    skip -= 1;
+                    false
+                });
+            }
+            ContextPolicy::SummaryOnly => history.retain(|m| m.role == MessageRole::System),
+            ContextPolicy::None => history.clear(),
+        }
+    }
+}
+
+impl fmt::Display for ContextPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextPolicy::Full => write!(f, "full"),
+            ContextPolicy::Last(n) => write!(f, "last:{}", n),
+            ContextPolicy::SummaryOnly => write!(f, "summary"),
+            ContextPolicy::None => write!(f, "none"),
+        }
+    }
+}
+
+impl FromStr for ContextPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "full" => Ok(ContextPolicy::Full),
+            "summary" => Ok(ContextPolicy::SummaryOnly),
+            "none" => Ok(ContextPolicy::None),
+            other => other
+                .strip_prefix("last:")
+                .and_then(|n| n.trim().parse().ok())
+                .map(ContextPolicy::Last)
+                .ok_or_else(|| anyhow!("Invalid context policy '{}': expected full, last:<n>, summary or none", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for ContextPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<ContextPolicy> for String {
+    fn from(policy: ContextPolicy) -> Self {
+        policy.to_string()
+    }
+}
@@ -0,0 +1,236 @@
+//! Reversible anonymization for `--privacy`
+//!
+//! Emails, absolute paths, the local user name and the host name are replaced
+//! with placeholders such as `[EMAIL_1]` or `[PATH_2]/main.rs` before a
+//! request leaves the machine. The mapping never leaves the machine: replies
+//! are passed through [`Anonymizer::restore`] so output shows the originals,
+//! streamed replies chunk by chunk through a [`ChunkRestorer`], and batch jobs keep it in their local job file until their results are fetched.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+use crate::providers::Context;
+
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9\-]+(?:\.[A-Za-z0-9\-]+)*\.[A-Za-z]{2,}\b").expect("valid regex"));
+/// Two or more components after a `/` that is not part of a URL or a relative path
+static UNIX_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|[^\w/:.~\-\]])((?:/[\w.@+\-]+){2,}/?)").expect("valid regex"));
+static WINDOWS_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b([A-Za-z]:\\(?:[\w.@+\-]+\\)*[\w.@+\-]+\\?)").expect("valid regex"));
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(?:EMAIL|PATH|USER|HOST)_\d+\]").expect("valid regex"));
+
+/// Names too generic to anonymize without mangling ordinary text
+const IGNORED_TERMS: &[&str] = &["localhost", "unknown", "root", "user"];
+
+/// What a placeholder stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sensitive {
+    Email,
+    Path,
+    User,
+    Host,
+}
+
+impl Sensitive {
+    fn label(self) -> &'static str {
+        match self {
+            Sensitive::Email => "EMAIL",
+            Sensitive::Path => "PATH",
+            Sensitive::User => "USER",
+            Sensitive::Host => "HOST",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Mapping {
+    placeholders: HashMap<(Sensitive, String), String>,
+    originals: HashMap<String, String>,
+    counts: HashMap<Sensitive, usize>,
+}
+
+/// Replaces personal details with placeholders and puts them back in replies
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    /// User and host names, matched as whole words
+    terms: Vec<(Sensitive, Regex)>,
+    mapping: Mutex<Mapping>,
+}
+
+impl Anonymizer {
+    /// Anonymizer for emails and absolute paths only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also anonymize this machine's user name and host name
+    pub fn for_local_machine() -> Self {
+        let host = crate::history::audit::current_host();
+        // `box.example.com` is often written as just `box`
+        let short_host = host.split('.').next().unwrap_or_default().to_string();
+        Self::new()
+            .with_term(Sensitive::User, &crate::history::audit::current_user())
+            .with_term(Sensitive::Host, &host)
+            .with_term(Sensitive::Host, &short_host)
+    }
+
+    /// Anonymizer that knows the placeholders of an earlier [`Anonymizer::mapping`]
+    pub fn from_mapping(pairs: impl IntoIterator<Item = (String, String)>) -> Self {
+        let anonymizer = Self::new();
+        {
+            let mut mapping = anonymizer.mapping.lock().unwrap_or_else(|e| e.into_inner());
+            for (placeholder, original) in pairs {
+                let Some((label, count)) = placeholder.trim_matches(['[', ']']).split_once('_') else { continue };
+                let Some(kind) = [Sensitive::Email, Sensitive::Path, Sensitive::User, Sensitive::Host].into_iter().find(|k| k.label() == label) else {
+                    continue;
+                };
+                let count = count.parse().unwrap_or(0);
+                let highest = mapping.counts.entry(kind).or_default();
+                *highest = (*highest).max(count);
+                mapping.placeholders.insert((kind, original.clone()), placeholder.clone());
+                mapping.originals.insert(placeholder, original);
+            }
+        }
+        anonymizer
+    }
+
+    /// Anonymize every whole-word occurrence of `term`; short or generic names are ignored
+    pub fn with_term(mut self, kind: Sensitive, term: &str) -> Self {
+        let term = term.trim();
+        if term.len() < 3 || IGNORED_TERMS.contains(&term.to_lowercase().as_str()) {
+            return self;
+        }
+        let pattern = format!(r"\b{}\b", regex::escape(term));
+        if !self.terms.iter().any(|(_, regex)| regex.as_str() == pattern) {
+            self.terms.push((kind, Regex::new(&pattern).expect("escaped term is a valid regex")));
+        }
+        self
+    }
+
+    /// `text` with every email, absolute path, user and host name replaced
+    pub fn anonymize(&self, text: &str) -> String {
+        let mut mapping = self.mapping.lock().unwrap_or_else(|e| e.into_inner());
+        let text = EMAIL.replace_all(text, |c: &regex::Captures| mapping.placeholder(Sensitive::Email, &c[0]));
+        let text = replace_paths(&UNIX_PATH, &text, '/', &mut mapping);
+        let mut text = replace_paths(&WINDOWS_PATH, &text, '\\', &mut mapping);
+        for (kind, regex) in &self.terms {
+            text = regex.replace_all(&text, |c: &regex::Captures| mapping.placeholder(*kind, &c[0])).into_owned();
+        }
+        text
+    }
+
+    /// Copy of a context with file names, file contents, messages and environment values anonymized
+    pub fn anonymize_context(&self, context: &Context) -> Context {
+        let mut anonymized = context.clone();
+        let path = |path: &PathBuf| PathBuf::from(self.anonymize(&path.display().to_string()));
+        anonymized.file_contents = context.file_contents.iter().map(|(p, content)| (path(p), self.anonymize(content))).collect();
+        anonymized.file_provenance = context.file_provenance.iter().map(|(p, provenance)| (path(p), provenance.clone())).collect();
+        anonymized.current_files = context.current_files.iter().map(path).collect();
+        // Earlier replies were restored, so they are anonymized again like everything else
+        for message in anonymized.conversation_history.iter_mut() {
+            message.content = self.anonymize(&message.content);
+        }
+        for value in anonymized.environment.values_mut() {
+            *value = self.anonymize(value);
+        }
+        anonymized
+    }
+
+    /// `text` with every known placeholder replaced by its original; unknown ones are left as they are
+    pub fn restore(&self, text: &str) -> String {
+        let mapping = self.mapping.lock().unwrap_or_else(|e| e.into_inner());
+        PLACEHOLDER
+            .replace_all(text, |c: &regex::Captures| mapping.originals.get(&c[0]).cloned().unwrap_or_else(|| c[0].to_string()))
+            .into_owned()
+    }
+
+    /// [`Anonymizer::restore`] applied to every string in a JSON value
+    pub fn restore_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => *text = self.restore(text),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| self.restore_json(item)),
+            serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| self.restore_json(field)),
+            _ => {}
+        }
+    }
+
+    /// A [`ChunkRestorer`] for one streamed reply
+    pub fn chunk_restorer(&self) -> ChunkRestorer<'_> {
+        ChunkRestorer { anonymizer: self, pending: String::new() }
+    }
+
+    /// Placeholder and original for everything anonymized so far, sorted by placeholder
+    pub fn mapping(&self) -> Vec<(String, String)> {
+        let mapping = self.mapping.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pairs: Vec<(String, String)> = mapping.originals.iter().map(|(p, o)| (p.clone(), o.clone())).collect();
+        pairs.sort();
+        pairs
+    }
+}
+
+/// Restores a streamed reply chunk by chunk, holding back a trailing `[...`
+/// until the next chunk shows whether it is a placeholder
+#[derive(Debug)]
+pub struct ChunkRestorer<'a> {
+    anonymizer: &'a Anonymizer,
+    pending: String,
+}
+
+impl ChunkRestorer<'_> {
+    /// The restored text that can be shown once `chunk` has arrived
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        let split = match self.pending.rfind('[') {
+            Some(start) if could_be_placeholder(&self.pending[start..]) => start,
+            _ => self.pending.len(),
+        };
+        let ready: String = self.pending.drain(..split).collect();
+        self.anonymizer.restore(&ready)
+    }
+
+    /// Whatever is still held back once the reply has ended
+    pub fn finish(self) -> String {
+        self.anonymizer.restore(&self.pending)
+    }
+}
+
+/// Whether `tail`, starting at a `[`, is the unfinished start of a placeholder
+fn could_be_placeholder(tail: &str) -> bool {
+    tail.len() <= "[EMAIL_".len() + 10 && tail[1..].chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl Mapping {
+    /// The placeholder for `original`, the same one every time it is seen
+    fn placeholder(&mut self, kind: Sensitive, original: &str) -> String {
+        if let Some(placeholder) = self.placeholders.get(&(kind, original.to_string())) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        let placeholder = format!("[{}_{}]", kind.label(), count);
+        self.placeholders.insert((kind, original.to_string()), placeholder.clone());
+        self.originals.insert(placeholder.clone(), original.to_string());
+        placeholder
+    }
+}
+
+/// Replace the directory of each absolute path in `text`, keeping the file name readable
+fn replace_paths(regex: &Regex, text: &str, separator: char, mapping: &mut Mapping) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for captures in regex.captures_iter(text) {
+        let Some(path) = captures.get(1) else { continue };
+        let trimmed = path.as_str().trim_end_matches(separator);
+        let Some(split) = trimmed.rfind(separator).filter(|&i| i > 0 && !trimmed[..i].ends_with(':')) else { continue };
+        out.push_str(&text[last..path.start()]);
+        out.push_str(&mapping.placeholder(Sensitive::Path, &trimmed[..split]));
+        out.push_str(&path.as_str()[split..]);
+        last = path.end();
+    }
+    out.push_str(&text[last..]);
+    out
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where a piece of context came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Provenance {
+    /// Supplied on the command line (e.g. `--context file.txt`)
+    CliFlag {
+        flag: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+    },
+    /// Selected by a retrieval step
+    Retrieval { source: String },
+    /// Output of an earlier pipeline step (0-based index)
+    StepOutput { step_index: usize, provider: String },
+    /// Result of a tool invocation
+    ToolCall { tool: String },
+    /// Matched by a `context` glob in the config file at `path`
+    Config { path: String },
+    /// Summary of file changes since the previous run in watch/daemon modes
+    IncrementalUpdate { changed_files: usize },
+    /// Patch from `git diff` (working tree, staged, or a commit range)
+    GitDiff { source: String },
+    /// Earlier exchange replayed from a `--session`
+    Session { name: String },
+    /// Summary written in place of `messages` older messages when the context was compacted
+    Compaction { messages: usize },
+    /// Added programmatically through the library API
+    Api,
+}
+
+impl Provenance {
+    /// Provenance for a value passed through a CLI flag
+    pub fn cli_flag(flag: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::CliFlag { flag: flag.into(), value: Some(value.into()) }
+    }
+
+    /// Provenance for the output of a pipeline step
+    pub fn step_output(step_index: usize, provider: impl Into<String>) -> Self {
+        Self::StepOutput { step_index, provider: provider.into() }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provenance::CliFlag { flag, value: Some(value) } => write!(f, "cli {} {}", flag, value),
+            Provenance::CliFlag { flag, value: None } => write!(f, "cli {}", flag),
+            Provenance::Retrieval { source } => write!(f, "retrieval from {}", source),
+            Provenance::StepOutput { step_index, provider } => {
+                write!(f, "step {} output ({})", step_index + 1, provider)
+            }
+            Provenance::ToolCall { tool } => write!(f, "tool call {}", tool),
+            Provenance::Config { path } => write!(f, "config {}", path),
+            Provenance::IncrementalUpdate { changed_files } => {
+                write!(f, "incremental update ({} files changed)", changed_files)
+            }
+            Provenance::GitDiff { source } => write!(f, "git diff ({})", source),
+            Provenance::Session { name } => write!(f, "session {}", name),
+            Provenance::Compaction { messages } => write!(f, "summary of {} earlier messages", messages),
+            Provenance::Api => write!(f, "library api"),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Secret redaction before anything is sent to a provider
+//!
+//! Built-in rules cover common API key formats, AWS credentials, PEM private
+//! keys, secret-looking assignments and every value in `.env` files; more
+//! patterns come from the `[redaction]` config section. Matches are replaced
+//! with `[REDACTED:<rule>]` and recorded (without the secret) in an audit log.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::providers::{Context, MessageRole};
+
+/// A user-supplied pattern from `[[redaction.patterns]]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedactionPattern {
+    /// Rule name shown in the placeholder and the audit log
+    pub name: String,
+    /// Regular expression; when it has a capture group, only the first group is redacted
+    pub regex: String,
+}
+
+/// `[redaction]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedactionSettings {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub patterns: Vec<RedactionPattern>,
+}
+
+fn default_enabled() -> bool { true }
+
+impl Default for RedactionSettings {
+    fn default() -> Self {
+        Self { enabled: default_enabled(), patterns: Vec::new() }
+    }
+}
+
+/// One redacted secret; the secret itself is never recorded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redaction {
+    /// File path, `prompt` or `message <n>`
+    pub location: String,
+    pub rule: String,
+    /// 1-based line of the match within its text
+    pub line: usize,
+}
+
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    regex: Regex,
+    /// Only applied to `.env` files
+    env_files_only: bool,
+    /// Unquoted values that are code, like `tokenizer.count(text)`, are not secrets
+    skip_code: bool,
+}
+
+/// Applies redaction rules and keeps an audit log of what was replaced
+#[derive(Debug)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+    log: Mutex<Vec<Redaction>>,
+}
+
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    ("anthropic_api_key", r"\bsk-ant-[A-Za-z0-9_\-]{20,}"),
+    ("openai_api_key", r"\bsk-(?:proj-)?[A-Za-z0-9_\-]{20,}"),
+    ("google_api_key", r"\bAIza[0-9A-Za-z_\-]{35}"),
+    ("github_token", r"\bgh[pousr]_[A-Za-z0-9]{36,}"),
+    ("slack_token", r"\bxox[abpors]-[A-Za-z0-9\-]{10,}"),
+    ("aws_access_key_id", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("aws_secret_access_key", r#"(?i)aws_secret_access_key["']?\s*[=:]\s*["']?([A-Za-z0-9/+=]{40})"#),
+    // `=`, `:=` or a single `:`; never `==`, `=>` or a `::` path
+    (
+        "secret_assignment",
+        r#"(?i)\b[a-z0-9_\-]*(?:secret|password|passwd|token|api_?key)[a-z0-9_\-]*["']?[ \t]*(?::=|=|:)[ \t]*["']?([^\s"',;:=>][^\s"',;]{7,})"#,
+    ),
+    // Last, so collapsing a multi-line key does not shift the lines reported for other rules
+    ("private_key", r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----"),
+];
+
+/// Every value assigned in a `.env` file
+const ENV_VALUE_RULE: &str = r#"(?m)^[ \t]*(?:export[ \t]+)?[A-Za-z_][A-Za-z0-9_]*[ \t]*=[ \t]*([^\r\n]+)"#;
+
+/// Extensions of source files, where a bare identifier or type after `token =` is code
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "mjs", "cjs", "ts", "tsx", "go", "java", "kt", "kts", "scala", "rb", "php", "c", "h", "cc",
+    "cpp", "hpp", "cs", "swift", "m", "mm", "dart", "lua", "ex", "exs", "hs", "ml", "clj", "zig",
+];
+
+/// Extensions of config files, where every secret-looking assignment is redacted
+const CONFIG_EXTENSIONS: &[&str] = &["yaml", "yml", "toml", "ini", "cfg", "conf", "json", "properties"];
+
+/// Whether an unquoted value is code rather than a secret
+///
+/// Field accesses, paths and calls such as `tokenizer.count(text)`,
+/// `ApproxTokenizer::claude()` or `fn() -> T` are code anywhere. A bare
+/// identifier or type like `fresh_token` or `Option<String>` is code only in a
+/// source file; in configs and prompts it is a value. Long segments with digits
+/// (like the parts of a JWT) never count, so generated secrets are still caught.
+fn is_code(value: &str, source_file: bool) -> bool {
+    let value = value.trim_start_matches(['&', '*']);
+    let end = value
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == ':'))
+        .unwrap_or(value.len());
+    let (path, rest) = value.split_at(end);
+    let segments: Vec<&str> = path.split(['.', ':']).filter(|s| !s.is_empty()).collect();
+    let has_digit = |s: &str| s.contains(|c: char| c.is_ascii_digit());
+    let identifiers = !segments.is_empty()
+        && segments
+            .iter()
+            .all(|s| s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && !(s.len() > 24 && has_digit(s)));
+    if !identifiers {
+        return false;
+    }
+    let syntax = segments.len() > 1 || rest.starts_with('(') || rest.contains("->");
+    let code_follows = rest.is_empty() || rest.starts_with(['(', '<', '[', '?', ')', '{']);
+    syntax || (source_file && code_follows && (!rest.is_empty() || !has_digit(path)))
+}
+
+impl Redactor {
+    /// Redactor with the built-in rules
+    pub fn new() -> Self {
+        let mut rules: Vec<Rule> = BUILTIN_RULES
+            .iter()
+            .map(|(name, pattern)| Rule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).expect("valid built-in rule"),
+                env_files_only: false,
+                skip_code: *name == "secret_assignment",
+            })
+            .collect();
+        rules.push(Rule {
+            name: "env_value".to_string(),
+            regex: Regex::new(ENV_VALUE_RULE).expect("valid built-in rule"),
+            env_files_only: true,
+            skip_code: false,
+        });
+        Self { rules, log: Mutex::new(Vec::new()) }
+    }
+
+    /// Built-in rules plus the configured patterns
+    pub fn from_settings(settings: &RedactionSettings) -> Result<Self> {
+        let mut redactor = Self::new();
+        for pattern in &settings.patterns {
+            redactor = redactor.with_pattern(pattern)?;
+        }
+        Ok(redactor)
+    }
+
+    /// Add a custom pattern
+    pub fn with_pattern(mut self, pattern: &RedactionPattern) -> Result<Self> {
+        let regex = Regex::new(&pattern.regex)
+            .map_err(|e| anyhow!("Invalid redaction pattern '{}': {}", pattern.name, e))?;
+        self.rules.push(Rule { name: pattern.name.clone(), regex, env_files_only: false, skip_code: false });
+        Ok(self)
+    }
+
+    /// Redact one piece of text, recording matches under `location`
+    pub fn redact_text(&self, text: &str, location: &str) -> String {
+        let env_file = Path::new(location)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n == ".env" || n.starts_with(".env."));
+        let extension = Path::new(location).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let source_file = SOURCE_EXTENSIONS.contains(&extension);
+        let config_file = CONFIG_EXTENSIONS.contains(&extension);
+
+        let mut text = text.to_string();
+        let mut found = Vec::new();
+        for rule in self.rules.iter().filter(|r| env_file || !r.env_files_only) {
+            let mut redacted = String::with_capacity(text.len());
+            let mut last = 0;
+            for captures in rule.regex.captures_iter(&text) {
+                // Redact the first capture group when there is one, else the whole match
+                let Some(secret) = captures.get(1).or_else(|| captures.get(0)) else { continue };
+                if secret.as_str().starts_with("[REDACTED:") {
+                    continue;
+                }
+                let quoted = text[..secret.start()].ends_with(['"', '\'']);
+                if rule.skip_code && !config_file && !quoted && is_code(secret.as_str(), source_file) {
+                    continue;
+                }
+                found.push(Redaction {
+                    location: location.to_string(),
+                    rule: rule.name.clone(),
+                    line: text[..secret.start()].matches('\n').count() + 1,
+                });
+                redacted.push_str(&text[last..secret.start()]);
+                redacted.push_str(&format!("[REDACTED:{}]", rule.name));
+                last = secret.end();
+            }
+            if last > 0 {
+                redacted.push_str(&text[last..]);
+                text = redacted;
+            }
+        }
+        if !found.is_empty() {
+            let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+            for redaction in found {
+                if !log.contains(&redaction) {
+                    log.push(redaction);
+                }
+            }
+        }
+        text
+    }
+
+    /// Copy of a context with every file, message and environment value redacted
+    pub fn redact_context(&self, context: &Context) -> Context {
+        let mut redacted = context.clone();
+        for (path, content) in redacted.file_contents.iter_mut() {
+            *content = self.redact_text(content, &path.display().to_string());
+        }
+        for (index, message) in redacted.conversation_history.iter_mut().enumerate() {
+            // Provider output was produced from redacted input and is left alone
+            if message.role != MessageRole::Assistant {
+                message.content = self.redact_text(&message.content, &format!("message {}", index + 1));
+            }
+        }
+        for (key, value) in redacted.environment.iter_mut() {
+            *value = self.redact_text(value, &format!("env {}", key));
+        }
+        redacted
+    }
+
+    /// Everything redacted so far, each secret location listed once
+    pub fn log(&self) -> Vec<Redaction> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self { Self::new() }
+}
+
+/// Default audit log location: `<data dir>/redactions.jsonl`
+pub fn default_audit_log() -> Result<PathBuf> {
+    Ok(crate::config::data_dir()?.join("redactions.jsonl"))
+}
+
+/// Append redactions to a JSON-lines audit log, stamped with the time and command
+pub fn append_audit_log(path: &Path, command: &str, redactions: &[Redaction]) -> Result<()> {
+    if redactions.is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    for redaction in redactions {
+        let entry = serde_json::json!({
+            "timestamp": timestamp,
+            "command": command,
+            "location": redaction.location,
+            "rule": redaction.rule,
+            "line": redaction.line,
+        });
+        writeln!(file, "{}", entry).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
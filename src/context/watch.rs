@@ -0,0 +1,107 @@
+//! Polling file watcher for `ai-cli watch`
+//!
+//! Watched specs are files, directories or globs expanded like `--context`,
+//! so `.aiignore` and `.git` are skipped the same way. Each poll compares
+//! modification times and sizes with the previous one; added and removed
+//! files count as changes too.
+
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::ContextLoader;
+
+/// How often watched files are checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What a poll remembers about a file
+type Stamp = (Option<SystemTime>, u64);
+
+/// Reports which watched files changed since the previous poll
+#[derive(Debug)]
+pub struct FileWatcher {
+    loader: ContextLoader,
+    base: PathBuf,
+    specs: Vec<String>,
+    stamps: HashMap<PathBuf, Stamp>,
+}
+
+impl FileWatcher {
+    /// Watch `specs` (relative to `base`); a spec that matches nothing is an error
+    pub fn new(loader: ContextLoader, base: impl Into<PathBuf>, specs: Vec<String>) -> Result<Self> {
+        let base = base.into();
+        let mut files = Vec::new();
+        for spec in &specs {
+            files.extend(loader.expand(spec, &base)?);
+        }
+        let stamps = files.into_iter().filter_map(|file| stamp(&file).map(|s| (file, s))).collect();
+        Ok(Self { loader, base, specs, stamps })
+    }
+
+    /// Number of files being watched
+    pub fn len(&self) -> usize {
+        self.stamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stamps.is_empty()
+    }
+
+    /// Files being watched, sorted
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.stamps.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Files added, modified or removed since the previous poll, sorted
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut current = HashMap::new();
+        for spec in &self.specs {
+            // A spec whose files were all deleted matches nothing until they come back
+            for file in self.loader.expand(spec, &self.base).unwrap_or_default() {
+                if let Some(stamp) = stamp(&file) {
+                    current.insert(file, stamp);
+                }
+            }
+        }
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(file, stamp)| self.stamps.get(*file) != Some(stamp))
+            .map(|(file, _)| file.clone())
+            .chain(self.stamps.keys().filter(|file| !current.contains_key(*file)).cloned())
+            .collect();
+        changed.sort();
+        self.stamps = current;
+        changed
+    }
+
+    /// Wait for a change, then until no file has changed for `debounce`
+    ///
+    /// Returns every file that changed in that burst, so a save that touches
+    /// several files triggers one run.
+    pub async fn next_change(&mut self, debounce: Duration) -> Vec<PathBuf> {
+        let mut changed = BTreeSet::new();
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            changed.extend(self.poll());
+            if !changed.is_empty() {
+                break;
+            }
+        }
+        loop {
+            tokio::time::sleep(debounce).await;
+            let more = self.poll();
+            if more.is_empty() {
+                return changed.into_iter().collect();
+            }
+            changed.extend(more);
+        }
+    }
+}
+
+fn stamp(file: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(file).ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
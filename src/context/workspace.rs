@@ -0,0 +1,213 @@
+//! Monorepo package discovery for `--package` scoping
+//!
+//! Packages come from Cargo workspace members, npm/yarn/pnpm `workspaces`
+//! and uv workspace members in `pyproject.toml`; a root manifest with its own
+//! package section counts as a package too.
+
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Manifest format a package was discovered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageKind {
+    Cargo,
+    Npm,
+    Python,
+}
+
+impl fmt::Display for PackageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageKind::Cargo => write!(f, "cargo"),
+            PackageKind::Npm => write!(f, "npm"),
+            PackageKind::Python => write!(f, "python"),
+        }
+    }
+}
+
+/// A package within a workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub root: PathBuf,
+    pub kind: PackageKind,
+}
+
+impl Package {
+    /// Check whether a path lies inside this package
+    ///
+    /// Nested member packages are not excluded; a package rooted at the workspace
+    /// root therefore contains the whole workspace.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+}
+
+/// A monorepo root and the packages declared in it
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub packages: Vec<Package>,
+}
+
+impl Workspace {
+    /// Find the outermost workspace enclosing `start`
+    ///
+    /// Falls back to the nearest directory with any manifest so single-package
+    /// repositories still resolve their own name.
+    pub fn discover(start: &Path) -> Result<Self> {
+        let root = start
+            .ancestors()
+            .filter(|dir| declares_workspace(dir))
+            .last()
+            .or_else(|| start.ancestors().find(|dir| has_manifest(dir)))
+            .ok_or_else(|| anyhow!("No Cargo.toml, package.json or pyproject.toml found above {}", start.display()))?;
+        Self::load(root)
+    }
+
+    /// Read the packages declared at a workspace root
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut packages = Vec::new();
+        cargo_packages(root, &mut packages)?;
+        npm_packages(root, &mut packages)?;
+        python_packages(root, &mut packages)?;
+        packages.sort_by(|a, b| a.name.cmp(&b.name).then(a.root.cmp(&b.root)));
+        packages.dedup_by(|a, b| a.name == b.name && a.root == b.root);
+        Ok(Self { root: root.to_path_buf(), packages })
+    }
+
+    /// Look up a package by name
+    pub fn package(&self, name: &str) -> Result<&Package> {
+        self.packages.iter().find(|p| p.name == name).ok_or_else(|| {
+            let known: Vec<&str> = self.packages.iter().map(|p| p.name.as_str()).collect();
+            anyhow!("Unknown package '{}' in {}. Known packages: {:?}", name, self.root.display(), known)
+        })
+    }
+}
+
+fn has_manifest(dir: &Path) -> bool {
+    ["Cargo.toml", "package.json", "pyproject.toml"].iter().any(|f| dir.join(f).is_file())
+}
+
+fn declares_workspace(dir: &Path) -> bool {
+    let cargo = read_toml(&dir.join("Cargo.toml")).is_some_and(|t| t.contains_key("workspace"));
+    let npm = read_json(&dir.join("package.json")).is_some_and(|v| v.get("workspaces").is_some());
+    let python = read_toml(&dir.join("pyproject.toml"))
+        .is_some_and(|t| t.get("tool").and_then(|t| t.get("uv")).and_then(|t| t.get("workspace")).is_some());
+    cargo || npm || python || dir.join("pnpm-workspace.yaml").is_file()
+}
+
+fn read_toml(path: &Path) -> Option<toml::Table> {
+    std::fs::read_to_string(path).ok()?.parse().ok()
+}
+
+fn read_json(path: &Path) -> Option<serde_json::Value> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// Expand member globs relative to the root, returning matching directories
+fn expand_members(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        let full = root.join(pattern.trim_start_matches("./"));
+        let matches = glob::glob(&full.to_string_lossy())
+            .map_err(|e| anyhow!("Invalid workspace member pattern '{}': {}", pattern, e))?;
+        dirs.extend(matches.filter_map(|m| m.ok()).filter(|p| p.is_dir()));
+    }
+    Ok(dirs)
+}
+
+fn string_list(value: Option<&toml::Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|items| items.iter().filter_map(|i| i.as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+fn cargo_packages(root: &Path, packages: &mut Vec<Package>) -> Result<()> {
+    let Some(manifest) = read_toml(&root.join("Cargo.toml")) else { return Ok(()) };
+    let name_of = |table: &toml::Table| {
+        table.get("package").and_then(|p| p.get("name")).and_then(|n| n.as_str()).map(str::to_string)
+    };
+    if let Some(name) = name_of(&manifest) {
+        packages.push(Package { name, root: root.to_path_buf(), kind: PackageKind::Cargo });
+    }
+
+    let workspace = manifest.get("workspace");
+    let members = string_list(workspace.and_then(|w| w.get("members")));
+    let excluded: Vec<PathBuf> = string_list(workspace.and_then(|w| w.get("exclude")))
+        .iter()
+        .map(|e| root.join(e))
+        .collect();
+    for dir in expand_members(root, &members)? {
+        if excluded.iter().any(|e| dir.starts_with(e)) {
+            continue;
+        }
+        if let Some(name) = read_toml(&dir.join("Cargo.toml")).as_ref().and_then(name_of) {
+            packages.push(Package { name, root: dir, kind: PackageKind::Cargo });
+        }
+    }
+    Ok(())
+}
+
+fn npm_packages(root: &Path, packages: &mut Vec<Package>) -> Result<()> {
+    let name_of = |dir: &Path| {
+        read_json(&dir.join("package.json"))
+            .and_then(|v| v.get("name").and_then(|n| n.as_str()).map(str::to_string))
+    };
+    let Some(manifest) = read_json(&root.join("package.json")) else { return Ok(()) };
+    if let Some(name) = name_of(root) {
+        packages.push(Package { name, root: root.to_path_buf(), kind: PackageKind::Npm });
+    }
+
+    // `workspaces` is either a list or `{ "packages": [...] }` (yarn classic)
+    let workspaces = manifest.get("workspaces");
+    let list = workspaces.and_then(|w| w.as_array().or_else(|| w.get("packages").and_then(|p| p.as_array())));
+    let mut members: Vec<String> = list
+        .map(|items| items.iter().filter_map(|i| i.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    members.extend(pnpm_members(root));
+
+    for dir in expand_members(root, &members)? {
+        if let Some(name) = name_of(&dir) {
+            packages.push(Package { name, root: dir, kind: PackageKind::Npm });
+        }
+    }
+    Ok(())
+}
+
+/// Member globs from `pnpm-workspace.yaml`; negated patterns are ignored
+fn pnpm_members(root: &Path) -> Vec<String> {
+    #[derive(serde::Deserialize)]
+    struct PnpmWorkspace {
+        #[serde(default)]
+        packages: Vec<String>,
+    }
+    std::fs::read_to_string(root.join("pnpm-workspace.yaml"))
+        .ok()
+        .and_then(|text| serde_yaml::from_str::<PnpmWorkspace>(&text).ok())
+        .map(|ws| ws.packages.into_iter().filter(|p| !p.starts_with('!')).collect())
+        .unwrap_or_default()
+}
+
+fn python_packages(root: &Path, packages: &mut Vec<Package>) -> Result<()> {
+    let name_of = |table: &toml::Table| {
+        let project = table.get("project").and_then(|p| p.get("name"));
+        let poetry = table.get("tool").and_then(|t| t.get("poetry")).and_then(|p| p.get("name"));
+        project.or(poetry).and_then(|n| n.as_str()).map(str::to_string)
+    };
+    let Some(manifest) = read_toml(&root.join("pyproject.toml")) else { return Ok(()) };
+    if let Some(name) = name_of(&manifest) {
+        packages.push(Package { name, root: root.to_path_buf(), kind: PackageKind::Python });
+    }
+
+    let workspace = manifest.get("tool").and_then(|t| t.get("uv")).and_then(|u| u.get("workspace"));
+    let members = string_list(workspace.and_then(|w| w.get("members")));
+    for dir in expand_members(root, &members)? {
+        if let Some(name) = read_toml(&dir.join("pyproject.toml")).as_ref().and_then(name_of) {
+            packages.push(Package { name, root: dir, kind: PackageKind::Python });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Pointed error messages: the offending span of some input underlined with carets, plus a suggested fix
+
+use colored::Colorize;
+use std::fmt;
+use std::ops::Range;
+
+/// An error located in a source string, e.g. a pipeline chain
+///
+/// ```text
+/// Action cannot be empty in step 'claude:'
+///   |
+/// 1 | claude: -> gemini:test
+///   |        ^ action expected here
+///   |
+///   = help: add what claude should do after ':', e.g. 'claude:review'
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Text the error was found in
+    pub input: String,
+    /// File `input` came from, shown as `--> path:line:column`
+    pub origin: Option<String>,
+    /// Byte range of `input`; empty to point between two characters
+    pub span: Range<usize>,
+    /// Text after the carets
+    pub label: Option<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, input: impl Into<String>, span: Range<usize>) -> Self {
+        Self { message: message.into(), input: input.into(), origin: None, span, label: None, help: None }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// The diagnostic as lines of text, with ANSI colors when `color` is set
+    pub fn render(&self, color: bool) -> String {
+        let paint = |text: &str, style: fn(&str) -> colored::ColoredString| {
+            if color { style(text).to_string() } else { text.to_string() }
+        };
+        let start = floor_char_boundary(&self.input, self.span.start.min(self.input.len()));
+        let end = floor_char_boundary(&self.input, self.span.end.clamp(start, self.input.len()));
+        let line_start = self.input[..start].rfind('\n').map_or(0, |pos| pos + 1);
+        let line_end = self.input[end..].find('\n').map_or(self.input.len(), |pos| end + pos);
+        let line_number = (self.input[..line_start].matches('\n').count() + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let bar = paint("|", |s| s.blue().bold());
+
+        let mut carets = " ".repeat(display_width(&self.input[line_start..start]));
+        let underlined = &self.input[start..end.min(line_end)];
+        carets.push_str(&"^".repeat(display_width(underlined).max(1)));
+        if let Some(label) = &self.label {
+            carets.push(' ');
+            carets.push_str(label);
+        }
+
+        let mut out = format!("{}\n", paint(&self.message, |s| s.bold()));
+        if let Some(origin) = &self.origin {
+            let column = self.input[line_start..start].chars().count() + 1;
+            out.push_str(&format!("{}{} {}:{}:{}\n", gutter, paint("-->", |s| s.blue().bold()), origin, line_number, column));
+        }
+        out.push_str(&format!("{} {}\n", gutter, bar));
+        out.push_str(&format!("{} {} {}\n", paint(&line_number, |s| s.blue().bold()), bar, &self.input[line_start..line_end]));
+        out.push_str(&format!("{} {} {}", gutter, bar, paint(&carets, |s| s.red().bold())));
+        if let Some(help) = &self.help {
+            out.push_str(&format!("\n{} {}\n{} = {} {}", gutter, bar, gutter, paint("help:", |s| s.cyan().bold()), help));
+        }
+        out
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(false))
+    }
+}
+
+/// Render `error` as a pointed diagnostic if it is one, else as its message chain
+pub fn render_error(error: &anyhow::Error, color: bool) -> String {
+    match error.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => diagnostic.render(color),
+        None => format!("{:#}", error),
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Terminal columns taken by `text`, counting East Asian wide characters as two
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6 | 0x1F300..=0x1FAFF | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
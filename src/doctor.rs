@@ -0,0 +1,272 @@
+//! `ai-cli doctor`: diagnose why a provider is unavailable
+//!
+//! For each provider the doctor checks whether credentials are detected,
+//! which CLI/desktop session markers exist, whether the API endpoint answers
+//! at all, and how long a minimal authenticated round trip takes. Config
+//! files are checked for validity. Every check becomes one row of the
+//! [`DoctorReport`] table.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::auth::AuthManager;
+use crate::config::{Config, find_project_config};
+use crate::error::Error;
+use crate::pipeline::PipelineExecutor;
+use crate::providers::http::{self, Transport};
+use crate::providers::{claude, gemini};
+
+/// Round trips slower than this are reported as a warning
+const SLOW_ROUND_TRIP: Duration = Duration::from_secs(5);
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+    /// Not applicable, or not possible without an earlier check passing
+    Skip,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        })
+    }
+}
+
+/// One row of the diagnosis
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    /// Provider name, or `config`
+    pub subject: String,
+    /// What was checked: `auth`, `session`, `endpoint`, `round-trip`, `user`, `project`
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Files the check looked at
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<PathBuf>,
+}
+
+impl Check {
+    pub fn new(subject: impl Into<String>, check: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            check: check.into(),
+            status,
+            detail: detail.into(),
+            latency_ms: None,
+            paths: Vec::new(),
+        }
+    }
+
+    fn with_latency(mut self, elapsed: Duration) -> Self {
+        self.latency_ms = Some(elapsed.as_millis() as u64);
+        self
+    }
+
+    fn with_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.paths = paths;
+        self
+    }
+}
+
+/// Every check the doctor ran, in order
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Whether no check failed
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|c| c.status != Status::Fail)
+    }
+
+    /// Checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|c| c.status == Status::Fail)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<8} {:<10} {:<6} DETAIL", "SUBJECT", "CHECK", "STATUS")?;
+        for check in &self.checks {
+            writeln!(f, "{:<8} {:<10} {:<6} {}", check.subject, check.check, check.status, check.detail)?;
+            if check.status != Status::Ok {
+                for path in &check.paths {
+                    writeln!(f, "{:<26} {}", "", path.display())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validity of a config file; a missing file is skipped
+pub fn check_config_file(check: &str, path: &Path) -> Check {
+    if !path.exists() {
+        return Check::new("config", check, Status::Skip, format!("{} not found; using defaults", path.display()));
+    }
+    match Config::load(path) {
+        Ok(_) => Check::new("config", check, Status::Ok, path.display().to_string()),
+        Err(e) => Check::new("config", check, Status::Fail, format!("{:#}", e)),
+    }
+}
+
+/// Validity of the user config and of the project config found from `cwd`
+pub fn check_config(cwd: &Path) -> Vec<Check> {
+    let mut checks = vec![match Config::default_path() {
+        Ok(path) => check_config_file("user", &path),
+        Err(e) => Check::new("config", "user", Status::Fail, e.to_string()),
+    }];
+    if let Some(project) = find_project_config(cwd) {
+        // Project files are only valid merged over the user config
+        checks.push(match Config::load_for_dir(cwd) {
+            Ok(_) => Check::new("config", "project", Status::Ok, project.display().to_string()),
+            Err(e) => Check::new("config", "project", Status::Fail, format!("{:#}", e)),
+        });
+    }
+    checks
+}
+
+/// API base URL a provider calls when no profile overrides it
+pub fn default_endpoint(provider: &str) -> Option<&'static str> {
+    match provider {
+        "claude" => Some(claude::DEFAULT_BASE_URL),
+        "gemini" => Some(gemini::API_BASE),
+        _ => None,
+    }
+}
+
+/// Runs the per-provider checks
+pub struct Doctor<'a> {
+    auth: &'a AuthManager,
+    executor: &'a PipelineExecutor,
+    transport: Arc<dyn Transport>,
+    timeout: Duration,
+}
+
+impl<'a> Doctor<'a> {
+    /// Check providers registered on `executor` with credentials from `auth`
+    pub fn new(auth: &'a AuthManager, executor: &'a PipelineExecutor) -> Self {
+        Self { auth, executor, transport: http::default_transport(), timeout: Duration::from_secs(10) }
+    }
+
+    /// Send reachability probes through `transport`, e.g. a fake in tests
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Give up on each network check after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// All checks for each of `providers`
+    pub async fn diagnose(&self, providers: &[&str]) -> Vec<Check> {
+        let mut checks = Vec::new();
+        for provider in providers {
+            checks.push(self.check_auth(provider).await);
+            checks.push(self.check_session(provider));
+            checks.push(self.check_endpoint(provider).await);
+            checks.push(self.check_round_trip(provider).await);
+        }
+        checks
+    }
+
+    /// Whether credentials are detected, and from where
+    pub async fn check_auth(&self, provider: &str) -> Check {
+        match self.auth.detect_auth_source(provider).await {
+            Ok((_, source)) => Check::new(provider, "auth", Status::Ok, source.to_string()),
+            Err(_) => {
+                let vars = AuthManager::env_var_names(provider).join(" or ");
+                Check::new(
+                    provider,
+                    "auth",
+                    Status::Fail,
+                    format!("no credentials; set {} or run `ai-cli auth login {}`", vars, provider),
+                )
+            }
+        }
+    }
+
+    /// Which CLI/desktop session markers exist
+    pub fn check_session(&self, provider: &str) -> Check {
+        let candidates = match self.auth.cli_session_candidates(provider) {
+            Ok(candidates) => candidates,
+            Err(e) => return Check::new(provider, "session", Status::Skip, e.to_string()),
+        };
+        match candidates.iter().find(|p| p.exists()) {
+            Some(found) => Check::new(provider, "session", Status::Ok, found.display().to_string()),
+            None => Check::new(provider, "session", Status::Skip, format!("no CLI session ({} locations checked)", candidates.len()))
+                .with_paths(candidates),
+        }
+    }
+
+    /// Whether the API endpoint answers; any HTTP status counts as reachable
+    pub async fn check_endpoint(&self, provider: &str) -> Check {
+        let endpoint = self
+            .executor
+            .get_provider(provider)
+            .and_then(|p| p.endpoint().map(str::to_string))
+            .or_else(|| default_endpoint(provider).map(str::to_string));
+        let Some(url) = endpoint else {
+            return Check::new(provider, "endpoint", Status::Skip, "no HTTP API");
+        };
+        let request = http::shared_client().get(&url).timeout(self.timeout);
+        let started = Instant::now();
+        match http::send_via(self.transport.as_ref(), provider, request).await {
+            Ok(response) => Check::new(provider, "endpoint", Status::Ok, format!("{} (HTTP {})", url, response.status().as_u16()))
+                .with_latency(started.elapsed()),
+            Err(e) => Check::new(provider, "endpoint", Status::Fail, format!("{} unreachable: {}", url, e)),
+        }
+    }
+
+    /// Latency of a minimal authenticated call (the model listing)
+    pub async fn check_round_trip(&self, provider: &str) -> Check {
+        let Some(prov) = self.executor.get_provider(provider) else {
+            return Check::new(provider, "round-trip", Status::Skip, "provider not registered (no credentials)");
+        };
+        if prov.endpoint().is_none() {
+            return Check::new(provider, "round-trip", Status::Skip, "no HTTP API");
+        }
+        let started = Instant::now();
+        let result: Result<_> = match tokio::time::timeout(self.timeout, prov.validate_auth()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("no answer within {} s", self.timeout.as_secs())),
+        };
+        let elapsed = started.elapsed();
+        match result {
+            Ok(report) => {
+                let status = if elapsed > SLOW_ROUND_TRIP { Status::Warn } else { Status::Ok };
+                let mut detail = format!("{} ms, {} models", elapsed.as_millis(), report.models.len());
+                if let (Some(model), Some(false)) = (prov.model(), report.model_accessible) {
+                    detail.push_str(&format!("; model {} NOT accessible", model));
+                }
+                Check::new(provider, "round-trip", status, detail).with_latency(elapsed)
+            }
+            // Untyped errors mean the check itself could not run, e.g. a CLI session without a key
+            Err(e) => {
+                let status = if Error::classify(&e).is_some() { Status::Fail } else { Status::Warn };
+                Check::new(provider, "round-trip", status, format!("{:#}", e))
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Typed errors for library consumers
+//!
+//! Fallible APIs return `anyhow::Result`, but the failures callers react to
+//! (rejected credentials, provider HTTP errors, failed steps and transforms,
+//! bad config) carry one of the types below. [`Error::classify`] recovers
+//! them from an `anyhow::Error` so callers can match instead of parsing
+//! messages.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use crate::pipeline::{FailureKind, PipelineFailure, SafetyError, TransformError};
+
+/// Failure a caller can tell apart by kind
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+    #[error(transparent)]
+    Pipeline(#[from] PipelineFailure),
+    #[error(transparent)]
+    Transform(#[from] TransformError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    ContextOverflow(#[from] ContextOverflowError),
+    #[error(transparent)]
+    Safety(#[from] SafetyError),
+}
+
+impl Error {
+    /// Find the typed error in an `anyhow` error or its causes
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<Error>() {
+                Some(e.clone())
+            } else if let Some(e) = cause.downcast_ref::<AuthError>() {
+                Some(Error::Auth(e.clone()))
+            } else if let Some(e) = cause.downcast_ref::<ProviderError>() {
+                Some(Error::Provider(e.clone()))
+            } else if let Some(e) = cause.downcast_ref::<PipelineFailure>() {
+                Some(Error::Pipeline(e.clone()))
+            } else if let Some(e) = cause.downcast_ref::<TransformError>() {
+                Some(Error::Transform(e.clone()))
+            } else if let Some(e) = cause.downcast_ref::<ConfigError>() {
+                Some(Error::Config(e.clone()))
+            } else if let Some(e) = cause.downcast_ref::<ContextOverflowError>() {
+                Some(Error::ContextOverflow(e.clone()))
+            } else {
+                cause.downcast_ref::<SafetyError>().map(|e| Error::Safety(e.clone()))
+            }
+        })
+    }
+
+    /// Whether repeating the same request may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Provider(e) => e.retryable,
+            _ => false,
+        }
+    }
+
+    /// Exit code the CLI reports for this error
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Auth(_) => ExitCode::Auth,
+            Error::Provider(e) if e.timed_out => ExitCode::Timeout,
+            Error::Provider(_) => ExitCode::Provider,
+            Error::Pipeline(failure) => match failure.kind {
+                FailureKind::Auth => ExitCode::Auth,
+                FailureKind::RateLimit | FailureKind::BadPrompt | FailureKind::Network => ExitCode::Provider,
+                FailureKind::Timeout | FailureKind::ContextTooLarge => ExitCode::Timeout,
+                FailureKind::Unknown => ExitCode::Failure,
+            },
+            Error::Transform(_) => ExitCode::Failure,
+            Error::Config(_) => ExitCode::Usage,
+            Error::ContextOverflow(_) => ExitCode::Timeout,
+            Error::Safety(_) => ExitCode::Failure,
+        }
+    }
+}
+
+/// Process exit codes of the `ai-cli` binary
+///
+/// Argument errors reported by clap also exit with [`ExitCode::Usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Any failure without a more specific code
+    Failure = 1,
+    /// Invalid arguments, flags or configuration
+    Usage = 2,
+    /// Missing or rejected credentials
+    Auth = 3,
+    /// A provider API returned an error or could not be reached
+    Provider = 4,
+    /// A chain or saved pipeline is invalid (bad syntax, unknown provider)
+    Pipeline = 5,
+    /// A request timed out or a context or token budget was exceeded
+    Timeout = 6,
+}
+
+impl ExitCode {
+    /// Exit code for any error, from its typed cause when it has one
+    pub fn for_error(error: &anyhow::Error) -> Self {
+        Error::classify(error).map_or(ExitCode::Failure, |e| e.exit_code())
+    }
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// Exit code table shown in `--help`
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  other failure
+  2  usage error (invalid arguments, flags or config)
+  3  authentication failure
+  4  provider or API error
+  5  pipeline validation failure (bad chain, unknown provider)
+  6  timeout or budget exceeded";
+
+/// A provider or token endpoint rejected the credentials
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{provider} rejected the credentials (401 Unauthorized): {detail}")]
+pub struct AuthError {
+    pub provider: String,
+    pub detail: String,
+}
+
+/// A provider request failed, with the HTTP status when a response arrived
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{message}")]
+pub struct ProviderError {
+    pub provider: String,
+    /// `None` when the request never got a response
+    pub status: Option<u16>,
+    /// Whether repeating the request may succeed (timeouts, 408, 429, 5xx)
+    pub retryable: bool,
+    /// The request or the provider's gateway timed out
+    pub timed_out: bool,
+    pub message: String,
+    /// How long the provider asked callers to wait before retrying (`Retry-After`)
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderError {
+    /// Error for a non-success HTTP status
+    pub fn from_status(provider: impl Into<String>, status: u16, message: impl Into<String>) -> Self {
+        Self {
+            provider: provider.into(),
+            status: Some(status),
+            retryable: matches!(status, 408 | 429) || status >= 500,
+            timed_out: matches!(status, 408 | 504),
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Wait at least `retry_after` before the next attempt
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The provider throttled the request (429 Too Many Requests)
+    pub fn is_rate_limited(&self) -> bool {
+        self.status == Some(429)
+    }
+
+    /// Error for a request that got no response
+    pub fn network(provider: impl Into<String>, retryable: bool, message: impl Into<String>) -> Self {
+        Self { provider: provider.into(), status: None, retryable, timed_out: false, message: message.into(), retry_after: None }
+    }
+
+    /// Error for a request that got no response in time
+    pub fn timeout(provider: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { provider: provider.into(), status: None, retryable: true, timed_out: true, message: message.into(), retry_after: None }
+    }
+}
+
+/// A prompt was refused before sending because it cannot fit the model's context window
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct ContextOverflowError {
+    pub provider: String,
+    /// Model the window belongs to, when the step or provider names one
+    pub model: Option<String>,
+    /// Tokens in the prompt, system prompt and context
+    pub prompt_tokens: usize,
+    /// Tokens reserved for the reply (`max_tokens`)
+    pub reserved_tokens: usize,
+    pub context_window: usize,
+}
+
+impl ContextOverflowError {
+    /// Tokens that have to go for the request to fit
+    pub fn excess(&self) -> usize {
+        (self.prompt_tokens + self.reserved_tokens).saturating_sub(self.context_window)
+    }
+}
+
+impl fmt::Display for ContextOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match &self.model {
+            Some(model) => format!("{}/{}", self.provider, model),
+            None => self.provider.clone(),
+        };
+        write!(
+            f,
+            "prompt exceeds {} context by {} tokens ({} prompt + {} reserved for output > {})",
+            target, self.excess(), self.prompt_tokens, self.reserved_tokens, self.context_window
+        )?;
+        write!(
+            f,
+            "\n  - truncate: attach fewer files, set [context_limits] or a `last:N` context_policy\
+             \n  - summarize: enable [compaction] or use context_policy = \"summary\"\
+             \n  - choose a model with a bigger context window (see `ai-cli models`)"
+        )
+    }
+}
+
+/// A config file or setting is invalid
+#[derive(Debug, Clone, thiserror::Error)]
+pub struct ConfigError {
+    /// File the problem was found in, when known
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(path: Option<PathBuf>, message: impl Into<String>) -> Self {
+        Self { path, message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Append-only audit log of provider calls
+//!
+//! With `[audit] enabled = true` every attempt the executor makes against a
+//! provider appends one JSON line to `<data dir>/audit.jsonl`: who ran it and
+//! when, the provider and model, hashes of the prompt and reply, token usage,
+//! cost and outcome. Tool results sent back to the model get an entry of their
+//! own. The prompt and reply text are stored too unless `hash_content` is
+//! set, in which case only their hashes are kept.
+//!
+//! Once the log exceeds `max_size_kb` it is rotated to `audit.1.jsonl`,
+//! shifting older files up to `audit.<keep>.jsonl`; `ai-cli audit show`
+//! reads all of them, oldest first.
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::stats::TimeRange;
+use super::unix_now;
+use crate::providers::Response;
+use crate::providers::idempotency::content_hash;
+use crate::providers::pricing::Usage;
+
+/// File name of the current audit log in the data directory
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// `[audit]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Log file instead of `<data dir>/audit.jsonl`
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Record only hashes of prompts and replies, never their text
+    #[serde(default)]
+    pub hash_content: bool,
+    /// Rotate the log once it grows past this size
+    #[serde(default = "default_max_size_kb")]
+    pub max_size_kb: u64,
+    /// Rotated files kept besides the current one
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_max_size_kb() -> u64 { 10 * 1024 }
+fn default_keep() -> usize { 5 }
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self { enabled: false, path: None, hash_content: false, max_size_kb: default_max_size_kb(), keep: default_keep() }
+    }
+}
+
+/// One provider call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix seconds
+    pub timestamp: u64,
+    /// Local user who ran ai-cli
+    pub user: String,
+    pub host: String,
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Step of the pipeline, from 1; 0 for calls outside a pipeline, such as a post-mortem
+    pub step: usize,
+    /// Attempt of the step, from 1; retries share the idempotency key
+    pub attempt: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// FNV-1a of the system prompt and prompt sent
+    pub prompt_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// `ok`, `error`, `submitted` for batch requests, or `tool:<name>` for a tool result sent back to the model
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What the executor knows about a call when it is audited
+#[derive(Debug, Clone, Copy)]
+pub struct AuditCall<'a> {
+    pub provider: &'a str,
+    pub model: Option<&'a str>,
+    pub step: usize,
+    pub attempt: usize,
+    pub idempotency_key: Option<&'a str>,
+    pub system: Option<&'a str>,
+    pub prompt: &'a str,
+}
+
+/// The audit log file and its rotation policy
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    hash_content: bool,
+    max_bytes: u64,
+    keep: usize,
+    /// Serializes appends and rotation within the process
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    /// Log to `path` with the default rotation policy, storing content
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let defaults = AuditSettings::default();
+        Self { path: path.into(), hash_content: false, max_bytes: defaults.max_size_kb * 1024, keep: defaults.keep, lock: Mutex::new(()) }
+    }
+
+    /// The log configured by `[audit]`
+    pub fn from_settings(settings: &AuditSettings) -> Result<Self> {
+        let path = match &settings.path {
+            Some(path) => path.clone(),
+            None => default_path()?,
+        };
+        Ok(Self::new(path).hash_content(settings.hash_content).rotate_at(settings.max_size_kb * 1024, settings.keep))
+    }
+
+    /// Keep only hashes of prompts and replies
+    pub fn hash_content(mut self, hash_content: bool) -> Self {
+        self.hash_content = hash_content;
+        self
+    }
+
+    /// Rotate once the log reaches `max_bytes`, keeping `keep` old files
+    pub fn rotate_at(mut self, max_bytes: u64, keep: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.keep = keep;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Entry for `call` and its outcome
+    pub fn entry(&self, call: &AuditCall<'_>, outcome: std::result::Result<&Response, &anyhow::Error>) -> AuditEntry {
+        let sent = match call.system {
+            Some(system) => format!("{}\n\n{}", system, call.prompt),
+            None => call.prompt.to_string(),
+        };
+        let mut entry = AuditEntry {
+            timestamp: unix_now(),
+            user: current_user(),
+            host: current_host(),
+            provider: call.provider.to_string(),
+            model: call.model.map(str::to_string),
+            step: call.step,
+            attempt: call.attempt,
+            idempotency_key: call.idempotency_key.map(str::to_string),
+            prompt_hash: content_hash(&[sent.as_bytes()]),
+            prompt: (!self.hash_content).then_some(sent),
+            response_hash: None,
+            response: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            cost_usd: None,
+            status: "ok".to_string(),
+            error: None,
+        };
+        match outcome {
+            Ok(response) => {
+                entry.model = response.metadata.get("model").cloned().or(entry.model);
+                entry.response_hash = Some(content_hash(&[response.content.as_bytes()]));
+                entry.response = (!self.hash_content).then(|| response.content.clone());
+                let usage = Usage::from_response(response);
+                entry.prompt_tokens = usage.map(|u| u.prompt_tokens);
+                entry.completion_tokens = usage.map(|u| u.completion_tokens);
+                entry.cost_usd = response.metadata.get("cost_usd").and_then(|c| c.parse().ok());
+            }
+            Err(error) => {
+                entry.status = "error".to_string();
+                entry.error = Some(format!("{:#}", error));
+            }
+        }
+        entry
+    }
+
+    /// Append `entry`, rotating first if the log is full
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        if std::fs::metadata(&self.path).is_ok_and(|m| m.len() >= self.max_bytes) {
+            self.rotate()?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?).with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Record a batch request whose reply is fetched later, with status `submitted`
+    pub fn record_submission(&self, call: &AuditCall<'_>) {
+        let mut entry = self.entry(call, Ok(&Response::new("")));
+        entry.status = "submitted".to_string();
+        entry.response_hash = None;
+        entry.response = None;
+        if let Err(e) = self.append(&entry) {
+            tracing::warn!(path = %self.path.display(), error = %format!("{:#}", e), "writing the audit log failed");
+        }
+    }
+
+    /// Record the result of tool `tool` sent back to the model as `call.prompt`, with status `tool:<name>`
+    ///
+    /// A failed tool call keeps its error; its message is what the model was sent.
+    pub fn record_tool(&self, call: &AuditCall<'_>, tool: &str, error: Option<&anyhow::Error>) {
+        let mut entry = match error {
+            Some(error) => self.entry(call, Err(error)),
+            None => self.entry(call, Ok(&Response::new(""))),
+        };
+        entry.status = format!("tool:{}", tool);
+        entry.response_hash = None;
+        entry.response = None;
+        if let Err(e) = self.append(&entry) {
+            tracing::warn!(path = %self.path.display(), error = %format!("{:#}", e), "writing the audit log failed");
+        }
+    }
+
+    /// Record a call, warning rather than failing it when the log cannot be written
+    pub fn record(&self, call: &AuditCall<'_>, outcome: std::result::Result<&Response, &anyhow::Error>) {
+        if let Err(e) = self.append(&self.entry(call, outcome)) {
+            tracing::warn!(path = %self.path.display(), error = %format!("{:#}", e), "writing the audit log failed");
+        }
+    }
+
+    /// Shift `audit.<n>.jsonl` to `<n+1>`, dropping the oldest, and move the current log to `.1`
+    fn rotate(&self) -> Result<()> {
+        if self.keep == 0 {
+            return std::fs::remove_file(&self.path).with_context(|| format!("Failed to rotate {}", self.path.display()));
+        }
+        let _ = std::fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated(n + 1)).with_context(|| format!("Failed to rotate {}", from.display()))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1)).with_context(|| format!("Failed to rotate {}", self.path.display()))
+    }
+
+    /// Path of the `n`th rotated file, e.g. `audit.1.jsonl`
+    fn rotated(&self, n: usize) -> PathBuf {
+        let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("audit");
+        let name = match self.path.extension().and_then(|e| e.to_str()) {
+            Some(extension) => format!("{}.{}.{}", stem, n, extension),
+            None => format!("{}.{}", stem, n),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Every entry in the rotated files and the current log, oldest first
+    pub fn read(&self) -> Result<Vec<AuditEntry>> {
+        let mut files: Vec<PathBuf> = (1..=self.keep.max(1)).rev().map(|n| self.rotated(n)).collect();
+        files.push(self.path.clone());
+        let mut entries = Vec::new();
+        for file in files.iter().filter(|f| f.exists()) {
+            let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+            for (number, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+                let entry = serde_json::from_str(line)
+                    .with_context(|| format!("Invalid audit entry at {}:{}", file.display(), number + 1))?;
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Entries in `range`, for `provider` if given, keeping the last `limit`
+    pub fn query(&self, range: TimeRange, provider: Option<&str>, limit: Option<usize>) -> Result<Vec<AuditEntry>> {
+        let mut entries: Vec<AuditEntry> = self
+            .read()?
+            .into_iter()
+            .filter(|e| range.contains(e.timestamp) && provider.is_none_or(|p| e.provider == p))
+            .collect();
+        if let Some(limit) = limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+}
+
+/// `<data dir>/audit.jsonl`
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::config::data_dir()?.join(AUDIT_FILE))
+}
+
+/// Login name of whoever runs ai-cli
+pub(crate) fn current_user() -> String {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Name of this machine
+pub(crate) fn current_host() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok().map(|h| h.trim().to_string()))
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// One line per entry: time, user, provider/model, tokens, cost, status and prompt hash
+pub fn format_table(entries: &[AuditEntry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        let model = entry.model.as_deref().map(|m| format!("/{}", m)).unwrap_or_default();
+        let tokens = match (entry.prompt_tokens, entry.completion_tokens) {
+            (Some(prompt), Some(completion)) => format!("{}+{}", prompt, completion),
+            _ => "-".to_string(),
+        };
+        let cost = entry.cost_usd.map(|c| format!("${:.4}", c)).unwrap_or_else(|| "-".to_string());
+        let status = match &entry.error {
+            Some(error) => format!("error: {}", error.lines().next().unwrap_or_default()),
+            None => entry.status.clone(),
+        };
+        out.push_str(&format!(
+            "{}  {}@{}  {}{}  step {} #{}  {}  {}  {}  {}\n",
+            super::utc_timestamp(entry.timestamp),
+            entry.user,
+            entry.host,
+            entry.provider,
+            model,
+            entry.step,
+            entry.attempt,
+            tokens,
+            cost,
+            entry.prompt_hash,
+            status
+        ));
+    }
+    out
+}
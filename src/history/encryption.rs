@@ -0,0 +1,251 @@
+//! At-rest encryption of sessions and run history
+//!
+//! With `[encryption] enabled = true`, session files and the records, step
+//! outputs, context and manifest of each run are written as ChaCha20-Poly1305
+//! ciphertext under a key derived (PBKDF2-HMAC-SHA256) from a passphrase in
+//! `AI_CLI_PASSPHRASE` or from a random secret kept in the OS keyring.
+//! One salt is kept per data directory in `encryption.check`, along with a
+//! sealed check value, so the slow derivation runs once per process and a
+//! wrong passphrase is caught before anything is written.
+//! Reading is transparent: encrypted files are recognized by their header and
+//! files written before encryption was enabled are still read as they are.
+
+use crate::auth::keyring;
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Environment variable holding the passphrase
+pub const PASSPHRASE_ENV: &str = "AI_CLI_PASSPHRASE";
+
+/// Keyring account of the generated secret
+pub const KEYRING_ACCOUNT: &str = "history";
+
+/// File in the data directory holding the salt and key check
+pub const KEY_CHECK_FILE: &str = "encryption.check";
+/// Plaintext sealed in the key check file
+const KEY_CHECK: &[u8] = b"ai-cli key check";
+
+/// First bytes of every encrypted file
+const MAGIC: &[u8; 8] = b"AICLIENC";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+/// Magic, version, iteration count and salt; authenticated along with the content
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + SALT_LEN;
+
+/// PBKDF2 rounds for new files
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+/// Files claiming more rounds than this are rejected rather than stalling the CLI
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+/// Where the key comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// `AI_CLI_PASSPHRASE`
+    #[default]
+    Passphrase,
+    /// A random secret stored in the macOS Keychain or the Secret Service (`secret-tool`), created on first use
+    Keyring,
+}
+
+/// `[encryption]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub key: KeySource,
+}
+
+/// Keys by iteration count and salt, since each derivation is deliberately slow
+type KeyCache = HashMap<(u32, [u8; SALT_LEN]), Arc<LessSafeKey>>;
+
+/// Encrypts and decrypts stored files with one secret
+pub struct Encryption {
+    secret: Vec<u8>,
+    iterations: NonZeroU32,
+    /// Salt of files this process writes; shared through the key check file
+    salt: [u8; SALT_LEN],
+    keys: Mutex<KeyCache>,
+    rng: SystemRandom,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption").field("iterations", &self.iterations).finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    /// Encryption keyed by `passphrase`
+    pub fn from_passphrase(passphrase: &str) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(anyhow!("The encryption passphrase cannot be empty"));
+        }
+        let rng = SystemRandom::new();
+        let mut salt = [0; SALT_LEN];
+        rng.fill(&mut salt).map_err(|_| anyhow!("No system randomness for an encryption salt"))?;
+        Ok(Self {
+            secret: passphrase.as_bytes().to_vec(),
+            iterations: NonZeroU32::new(DEFAULT_ITERATIONS).expect("non-zero"),
+            salt,
+            keys: Mutex::default(),
+            rng,
+        })
+    }
+
+    /// PBKDF2 rounds for files written from now on; reading uses each file's own count
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = NonZeroU32::new(iterations.clamp(1, MAX_ITERATIONS)).expect("non-zero");
+        self
+    }
+
+    /// Encryption keyed as `settings` say: the passphrase variable or the OS keyring,
+    /// checked against the data directory's key check file
+    pub fn from_settings(settings: &EncryptionSettings) -> Result<Self> {
+        let encryption = match settings.key {
+            KeySource::Passphrase => {
+                let passphrase = std::env::var(PASSPHRASE_ENV).map_err(|_| {
+                    anyhow!("[encryption] is enabled: set {} or use key = \"keyring\"", PASSPHRASE_ENV)
+                })?;
+                Self::from_passphrase(&passphrase)?
+            }
+            KeySource::Keyring => Self::from_passphrase(&keyring_secret()?)?,
+        };
+        encryption.with_key_check(&crate::config::data_dir()?.join(KEY_CHECK_FILE))
+    }
+
+    /// Adopt the salt and rounds of the key check file at `path`, creating it on first use;
+    /// fails when the secret does not open it
+    pub fn with_key_check(mut self, path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => {
+                let (iterations, salt) = header(&data).with_context(|| format!("Invalid key check file {}", path.display()))?;
+                let check = self
+                    .decrypt(&data)
+                    .with_context(|| format!("The encryption key does not match {}", path.display()))?;
+                if check != KEY_CHECK {
+                    return Err(anyhow!("Invalid key check file {}", path.display()));
+                }
+                self.iterations = NonZeroU32::new(iterations).expect("checked non-zero");
+                self.salt = salt;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+                }
+                write(path, KEY_CHECK, Some(&self))?;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+        Ok(self)
+    }
+
+    /// `plaintext` as an encrypted file body
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + CHACHA20_POLY1305.tag_len());
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.extend_from_slice(&self.iterations.get().to_be_bytes());
+        data.extend_from_slice(&self.salt);
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| anyhow!("No system randomness for an encryption nonce"))?;
+        data.extend_from_slice(&nonce);
+
+        let mut sealed = plaintext.to_vec();
+        self.key(self.iterations.get(), self.salt)
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(&data[..HEADER_LEN]), &mut sealed)
+            .map_err(|_| anyhow!("Encryption failed"))?;
+        data.extend_from_slice(&sealed);
+        Ok(data)
+    }
+
+    /// The plaintext of an encrypted file body
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (iterations, salt) = header(data)?;
+        let nonce: [u8; NONCE_LEN] = data[HEADER_LEN..HEADER_LEN + NONCE_LEN].try_into().expect("nonce length");
+
+        let mut opened = data[HEADER_LEN + NONCE_LEN..].to_vec();
+        let plaintext = self
+            .key(iterations, salt)
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(&data[..HEADER_LEN]), &mut opened)
+            .map_err(|_| anyhow!("Decryption failed: wrong passphrase or corrupted file"))?;
+        Ok(plaintext.to_vec())
+    }
+
+    fn key(&self, iterations: u32, salt: [u8; SALT_LEN]) -> Arc<LessSafeKey> {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        keys.entry((iterations, salt))
+            .or_insert_with(|| {
+                let mut key = [0; 32];
+                let rounds = NonZeroU32::new(iterations).expect("checked non-zero");
+                pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, rounds, &salt, &self.secret, &mut key);
+                Arc::new(LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).expect("32-byte key")))
+            })
+            .clone()
+    }
+}
+
+/// Iteration count and salt of an encrypted file body
+fn header(data: &[u8]) -> Result<(u32, [u8; SALT_LEN])> {
+    if !is_encrypted(data) || data.len() < HEADER_LEN + NONCE_LEN {
+        return Err(anyhow!("Not an ai-cli encrypted file"));
+    }
+    if data[MAGIC.len()] != VERSION {
+        return Err(anyhow!("Unsupported encrypted file version {}", data[MAGIC.len()]));
+    }
+    let at = MAGIC.len() + 1;
+    let iterations = u32::from_be_bytes(data[at..at + 4].try_into().expect("4 bytes"));
+    if iterations == 0 || iterations > MAX_ITERATIONS {
+        return Err(anyhow!("Encrypted file has an invalid key derivation setting"));
+    }
+    Ok((iterations, data[at + 4..HEADER_LEN].try_into().expect("salt length")))
+}
+
+/// Whether `data` was written by [`Encryption::encrypt`]
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Write `contents` to `path`, encrypted when `encryption` is given
+pub fn write(path: &Path, contents: impl AsRef<[u8]>, encryption: Option<&Encryption>) -> Result<()> {
+    let data = match encryption {
+        Some(encryption) => encryption.encrypt(contents.as_ref())?,
+        None => contents.as_ref().to_vec(),
+    };
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Read `path` as text, decrypting it when it is encrypted
+pub fn read_to_string(path: &Path, encryption: Option<&Encryption>) -> Result<String> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !is_encrypted(&data) {
+        return String::from_utf8(data).with_context(|| format!("{} is not UTF-8 text", path.display()));
+    }
+    let encryption = encryption
+        .ok_or_else(|| anyhow!("{} is encrypted; enable [encryption] with its key to read it", path.display()))?;
+    let plaintext = encryption.decrypt(&data).with_context(|| format!("Failed to decrypt {}", path.display()))?;
+    String::from_utf8(plaintext).with_context(|| format!("{} is not UTF-8 text", path.display()))
+}
+
+/// The keyring secret, generated and stored on first use
+fn keyring_secret() -> Result<String> {
+    if let Some(secret) = keyring::lookup(KEYRING_ACCOUNT)? {
+        return Ok(secret);
+    }
+    let mut bytes = [0; 32];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| anyhow!("No system randomness for a keyring secret"))?;
+    let secret = BASE64.encode(bytes);
+    keyring::store(KEYRING_ACCOUNT, "ai-cli history key", &secret)?;
+    Ok(secret)
+}
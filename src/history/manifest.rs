@@ -0,0 +1,176 @@
+//! `manifest.json`: what a run was started with, so `ai-cli rerun <id>` can start it again
+
+use anyhow::{Context as _, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::encryption::{self, Encryption};
+use crate::config::Config;
+use crate::pipeline::{PipelineParser, PipelineStep};
+use crate::providers::pricing::Usage;
+use crate::providers::{ProviderOptions, Response};
+
+/// Name of the manifest within a run directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Inputs and per-step results of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// ai-cli version that made the run
+    pub version: String,
+    /// Command that started the run (`execute`, `pipeline`)
+    pub command: String,
+    pub chain: String,
+    /// Hash of the effective configuration, to notice config changes before a rerun
+    pub config_hash: String,
+    /// Run this one re-executes, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerun_of: Option<String>,
+    /// Generation options given on the command line
+    #[serde(default, skip_serializing_if = "ProviderOptions::is_empty")]
+    pub options: ProviderOptions,
+    /// `--context` specs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+    /// `--env` values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// `--env-passthrough` globs, matched again against the environment on rerun
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_passthrough: Vec<String>,
+    /// `--out` file the final output is written to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out: Option<PathBuf>,
+    /// Whether files were written to the run's sandbox instead of the project
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sandbox: bool,
+    #[serde(default)]
+    pub steps: Vec<ManifestStep>,
+}
+
+/// One top-level step: what it asked and, once it ran, how it went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestStep {
+    pub provider: String,
+    /// Prompt as written in the chain, before context and placeholders are filled in
+    pub action: String,
+    /// Step options layered over the run's, e.g. temperature
+    #[serde(default, skip_serializing_if = "ProviderOptions::is_empty")]
+    pub options: ProviderOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+impl RunManifest {
+    /// Manifest for a run of `steps` that has not started yet
+    pub fn new(command: &str, steps: &[PipelineStep], options: ProviderOptions, config: &Config) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command: command.to_string(),
+            chain: PipelineParser::format(steps),
+            config_hash: config_hash(config),
+            rerun_of: None,
+            steps: steps
+                .iter()
+                .map(|step| ManifestStep {
+                    provider: step.provider.clone(),
+                    action: step.action.clone(),
+                    options: options.merged(step.options()),
+                    model: None,
+                    duration_ms: None,
+                    usage: None,
+                    cost_usd: None,
+                })
+                .collect(),
+            options,
+            context: Vec::new(),
+            env: BTreeMap::new(),
+            env_passthrough: Vec::new(),
+            out: None,
+            sandbox: false,
+        }
+    }
+
+    /// Record the inputs that shaped the initial context
+    pub fn with_inputs(mut self, context: &[String], env: &[(String, String)], env_passthrough: &[glob::Pattern]) -> Self {
+        self.context = context.to_vec();
+        self.env = env.iter().cloned().collect();
+        self.env_passthrough = env_passthrough.iter().map(|p| p.as_str().to_string()).collect();
+        self
+    }
+
+    /// Record where the run writes files
+    pub fn with_outputs(mut self, out: Option<PathBuf>, sandbox: bool) -> Self {
+        self.out = out;
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Fill in the model, duration, usage and cost of step `step_index` from its response
+    pub fn record_step(&mut self, step_index: usize, response: &Response) {
+        let Some(step) = self.steps.get_mut(step_index) else { return };
+        let metadata = &response.metadata;
+        step.model = metadata.get("model").cloned();
+        step.duration_ms = metadata.get("latency_ms").and_then(|l| l.parse().ok());
+        step.usage = Usage::from_response(response);
+        step.cost_usd = metadata.get("cost_usd").and_then(|c| c.parse().ok());
+    }
+
+    /// Read the manifest of the run in `run_dir`
+    pub fn load(run_dir: &Path) -> Result<Self> {
+        Self::load_with(run_dir, None)
+    }
+
+    /// Read the manifest of the run in `run_dir`, decrypting it with `encryption` if it is encrypted
+    pub fn load_with(run_dir: &Path, encryption: Option<&Encryption>) -> Result<Self> {
+        let path = run_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            bail!("No manifest at {} (runs recorded before manifests cannot be rerun)", path.display());
+        }
+        let text = encryption::read_to_string(&path, encryption)?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    /// Write the manifest into `run_dir`
+    pub fn save(&self, run_dir: &Path) -> Result<()> {
+        self.save_with(run_dir, None)
+    }
+
+    /// Write the manifest into `run_dir`, encrypted when `encryption` is given
+    pub fn save_with(&self, run_dir: &Path, encryption: Option<&Encryption>) -> Result<()> {
+        encryption::write(&run_dir.join(MANIFEST_FILE), serde_json::to_string_pretty(self)?, encryption)
+    }
+
+    /// Ways the current setup differs from the one this run was made with
+    pub fn drift(&self, config: &Config, models: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut notes = Vec::new();
+        if self.version != env!("CARGO_PKG_VERSION") {
+            notes.push(format!("recorded with ai-cli {}, now {}", self.version, env!("CARGO_PKG_VERSION")));
+        }
+        if self.config_hash != config_hash(config) {
+            notes.push("configuration changed since the run".to_string());
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            if let (Some(recorded), Some(current)) = (&step.model, models(&step.provider))
+                && *recorded != current
+            {
+                notes.push(format!("step {}: {} now uses {} instead of {}", index + 1, step.provider, current, recorded));
+            }
+        }
+        notes
+    }
+}
+
+/// Stable hash of the effective configuration (FNV-1a over its JSON form, keys sorted)
+pub fn config_hash(config: &Config) -> String {
+    let text = serde_json::to_value(config).map(|value| value.to_string()).unwrap_or_default();
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3));
+    format!("{:016x}", hash)
+}
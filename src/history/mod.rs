@@ -0,0 +1,385 @@
+//! Per-run artifact directories
+//!
+//! Every execute/pipeline run gets a directory under `<data dir>/runs/<id>`, where
+//! ids sort chronologically:
+//!
+//! ```text
+//! run.json                    run record (command, chain, status, timings)
+//! manifest.json               inputs, versions and per-step models, options and costs for `rerun`
+//! context.txt                 final context with provenance
+//! steps/01-claude/action.txt  step action as written in the chain
+//! steps/01-claude/response.md provider output, before any transform
+//! steps/01-claude/transformed.md step output after its transform, when it has one
+//! steps/01-claude/metadata.json response metadata, with token usage and cost when reported
+//! reports/                    reports produced about the run
+//! recordings/                 raw recordings of provider traffic
+//! sandbox/, sandbox.json       files written by a `--sandbox` run, for `ai-cli apply`
+//! ```
+//!
+//! With [`RunStore::with_encryption`], everything above is encrypted at rest
+//! (see [`encryption`]); the sandbox when it is opened with the same key.
+
+pub mod audit;
+pub mod encryption;
+pub mod manifest;
+pub mod session;
+pub mod stats;
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use encryption::Encryption;
+use manifest::RunManifest;
+use crate::pipeline::PipelineStep;
+use crate::providers::pricing::{CostSummary, Usage};
+use crate::providers::{Context, Response};
+
+/// Outcome of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Summary of a run stored as `run.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: String,
+    /// Command that started the run (`execute`, `pipeline`)
+    pub command: String,
+    /// Steps in chain DSL form
+    pub chain: String,
+    /// Unix timestamps in seconds
+    pub started_at: u64,
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    pub status: RunStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Tokens used by the steps that reported usage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Cost of the steps that could be priced, in USD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// One entry per provider call, for `ai-cli stats`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepRecord>,
+}
+
+/// Outcome of one provider call within a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(default)]
+    pub error: bool,
+}
+
+impl StepRecord {
+    /// Summarize a step from the metadata of its response
+    pub fn from_response(provider: &str, response: &Response) -> Self {
+        let metadata = &response.metadata;
+        Self {
+            provider: provider.to_string(),
+            model: metadata.get("model").cloned(),
+            usage: Usage::from_response(response),
+            cost_usd: metadata.get("cost_usd").and_then(|c| c.parse().ok()),
+            latency_ms: metadata.get("latency_ms").and_then(|l| l.parse().ok()),
+            error: metadata.get("error").is_some_and(|e| e == "true"),
+        }
+    }
+
+    /// A step that failed without producing a response
+    pub fn failed(provider: &str) -> Self {
+        Self { provider: provider.to_string(), model: None, usage: None, cost_usd: None, latency_ms: None, error: true }
+    }
+}
+
+/// File-backed collection of run artifact directories
+pub struct RunStore {
+    dir: PathBuf,
+    encryption: Option<Arc<Encryption>>,
+}
+
+impl RunStore {
+    /// Create a store rooted at a directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), encryption: None }
+    }
+
+    /// Encrypt the records and outputs of new runs; encrypted runs are only readable with it
+    pub fn with_encryption(mut self, encryption: Arc<Encryption>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Encryption applied to stored runs, if any
+    pub fn encryption(&self) -> Option<&Encryption> {
+        self.encryption.as_deref()
+    }
+
+    /// Open the store in the user data directory
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(config::data_dir()?.join("runs")))
+    }
+
+    /// Get the store directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Start a new run and create its directory layout
+    pub fn create(&self, command: &str, chain: &str) -> Result<RunArtifacts> {
+        let started_at = unix_now();
+        let base = new_run_id();
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.dir.join(&id).exists() {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+
+        let dir = self.dir.join(&id);
+        for sub in ["steps", "reports", "recordings"] {
+            std::fs::create_dir_all(dir.join(sub))
+                .with_context(|| format!("Failed to create {}", dir.join(sub).display()))?;
+        }
+
+        let run = RunArtifacts {
+            dir,
+            record: RunRecord {
+                id,
+                command: command.to_string(),
+                chain: chain.to_string(),
+                started_at,
+                finished_at: None,
+                status: RunStatus::Running,
+                error: None,
+                usage: None,
+                cost_usd: None,
+                steps: Vec::new(),
+            },
+            manifest: None,
+            encryption: self.encryption.clone(),
+        };
+        run.write_record()?;
+        Ok(run)
+    }
+
+    /// List recorded runs, oldest first
+    pub fn list(&self) -> Result<Vec<RunRecord>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path().join("run.json");
+            // Directories without a record are not runs, but an encrypted one that
+            // cannot be opened is reported rather than hidden
+            let Ok(data) = std::fs::read(&path) else { continue };
+            let text = match encryption::is_encrypted(&data) {
+                true => encryption::read_to_string(&path, self.encryption())?,
+                false => String::from_utf8_lossy(&data).into_owned(),
+            };
+            if let Ok(record) = serde_json::from_str::<RunRecord>(&text) {
+                records.push(record);
+            }
+        }
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(records)
+    }
+
+    /// Resolve a run id, unique id prefix or `last` to its directory
+    pub fn find(&self, id: &str) -> Result<PathBuf> {
+        let records = self.list()?;
+        if id == "last" {
+            return records
+                .last()
+                .map(|r| self.dir.join(&r.id))
+                .ok_or_else(|| anyhow!("No runs recorded yet"));
+        }
+
+        if records.iter().any(|r| r.id == id) {
+            return Ok(self.dir.join(id));
+        }
+        let matches: Vec<&RunRecord> = records.iter().filter(|r| r.id.starts_with(id)).collect();
+        match matches.as_slice() {
+            [] => Err(anyhow!("No run with id '{}'", id)),
+            [record] => Ok(self.dir.join(&record.id)),
+            _ => Err(anyhow!("Run id '{}' is ambiguous ({} matches)", id, matches.len())),
+        }
+    }
+}
+
+/// Artifact directory of a single run
+pub struct RunArtifacts {
+    dir: PathBuf,
+    record: RunRecord,
+    manifest: Option<RunManifest>,
+    encryption: Option<Arc<Encryption>>,
+}
+
+impl RunArtifacts {
+    /// Get the run id
+    pub fn id(&self) -> &str {
+        &self.record.id
+    }
+
+    /// Get the run directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the run record as last written
+    pub fn record(&self) -> &RunRecord {
+        &self.record
+    }
+
+    /// Keep a manifest of the run, completed by `record_step` and written by `finish`
+    pub fn set_manifest(&mut self, manifest: RunManifest) {
+        self.manifest = Some(manifest);
+    }
+
+    /// Directory for reports produced about the run
+    pub fn reports_dir(&self) -> PathBuf {
+        self.dir.join("reports")
+    }
+
+    /// Save a report about the run as `reports/<name>`
+    pub fn record_report(&self, name: &str, contents: &str) -> Result<PathBuf> {
+        let dir = self.reports_dir();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(name);
+        encryption::write(&path, contents, self.encryption.as_deref())?;
+        Ok(path)
+    }
+
+    /// Directory for raw recordings of provider traffic
+    pub fn recordings_dir(&self) -> PathBuf {
+        self.dir.join("recordings")
+    }
+
+    /// Directory holding a step's artifacts (`steps/01-claude`)
+    pub fn step_dir(&self, step_index: usize, provider: &str) -> PathBuf {
+        self.dir.join("steps").join(format!("{:02}-{}", step_index + 1, provider))
+    }
+
+    /// Save a step's action and output
+    pub fn record_step(&mut self, step_index: usize, step: &PipelineStep, response: &Response) -> Result<PathBuf> {
+        let dir = self.step_dir(step_index, &step.provider);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let encryption = self.encryption.as_deref();
+        encryption::write(&dir.join("action.txt"), &step.action, encryption)?;
+        encryption::write(&dir.join("response.md"), response.raw.as_deref().unwrap_or(&response.content), encryption)?;
+        if step.has_transform() {
+            encryption::write(&dir.join("transformed.md"), &response.content, encryption)?;
+        }
+        encryption::write(&dir.join("metadata.json"), serde_json::to_string_pretty(&response.metadata)?, encryption)?;
+        self.record.steps.push(StepRecord::from_response(&step.provider, response));
+        if let Some(manifest) = &mut self.manifest {
+            manifest.record_step(step_index, response);
+        }
+        Ok(dir)
+    }
+
+    /// Note a step that failed without a response; written by `finish`
+    pub fn record_failed_step(&mut self, provider: &str) {
+        self.record.steps.push(StepRecord::failed(provider));
+    }
+
+    /// Save the final context with provenance
+    pub fn record_context(&self, context: &Context) -> Result<()> {
+        encryption::write(&self.dir.join("context.txt"), context.explain(), self.encryption.as_deref())
+    }
+
+    /// Store the run's token usage and cost; written by `finish`
+    pub fn record_cost(&mut self, summary: &CostSummary) {
+        if summary.steps.iter().any(|s| s.usage.is_some()) {
+            self.record.usage = Some(summary.usage());
+        }
+        if summary.steps.iter().any(|s| s.cost_usd.is_some()) {
+            self.record.cost_usd = Some(summary.cost_usd());
+        }
+    }
+
+    /// Mark the run finished, with the error message if it failed
+    pub fn finish(&mut self, error: Option<String>) -> Result<()> {
+        self.record.finished_at = Some(unix_now());
+        self.record.status = if error.is_some() { RunStatus::Failed } else { RunStatus::Succeeded };
+        self.record.error = error;
+        if let Some(manifest) = &self.manifest {
+            manifest.save_with(&self.dir, self.encryption.as_deref())?;
+        }
+        self.write_record()
+    }
+
+    fn write_record(&self) -> Result<()> {
+        encryption::write(&self.dir.join("run.json"), serde_json::to_string_pretty(&self.record)?, self.encryption.as_deref())
+    }
+}
+
+/// Current Unix time in seconds
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Chronologically sortable id like `20261015-093012-042` (UTC time with milliseconds)
+fn new_run_id() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year, month, day, rem / 3600, (rem / 60) % 60, rem % 60, now.subsec_millis()
+    )
+}
+
+/// `2026-10-15 09:30:12` (UTC) for Unix seconds
+pub(crate) fn utc_timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, rem / 3600, (rem / 60) % 60, rem % 60)
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(20_741), (2026, 10, 15));
+    }
+}
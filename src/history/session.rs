@@ -0,0 +1,254 @@
+//! Named conversations shared across invocations (`--session <name>`)
+//!
+//! Each session is `<data dir>/sessions/<name>.json` holding the user/assistant
+//! exchanges so far. Context files are not stored; they are re-read on every run.
+//! When a session outgrows its token budget, the oldest exchanges are folded
+//! into a summary message so the conversation can continue indefinitely.
+//! Stores opened [`SessionStore::with_encryption`] keep the files encrypted.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::encryption::{self, Encryption};
+use super::unix_now;
+use crate::config;
+use crate::context::Provenance;
+use crate::providers::tokenizer::Tokenizer;
+use crate::providers::{Context, Message, MessageRole};
+
+/// Tokens kept from each message when it is folded into the summary
+const SUMMARY_LINE_TOKENS: usize = 40;
+
+/// `[session]` section: when to compact session history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SessionSettings {
+    /// Compact once the stored history exceeds this many tokens
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    /// Most recent messages always kept verbatim
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+}
+
+fn default_max_tokens() -> usize { 8000 }
+fn default_keep_recent() -> usize { 10 }
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self { max_tokens: default_max_tokens(), keep_recent: default_keep_recent() }
+    }
+}
+
+/// A named conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    /// Unix timestamps in seconds
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Summary of compacted exchanges, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub summary: Vec<String>,
+    #[serde(default)]
+    pub messages: Vec<Message>,
+}
+
+impl Session {
+    /// Start an empty session
+    pub fn new(name: impl Into<String>) -> Self {
+        let now = unix_now();
+        Self { name: name.into(), created_at: now, updated_at: now, summary: Vec::new(), messages: Vec::new() }
+    }
+
+    /// Replay the summary and stored exchanges into a context
+    pub fn apply_to(&self, context: &mut Context) {
+        let provenance = Provenance::Session { name: self.name.clone() };
+        if let Some(summary) = self.summary_message() {
+            context.add_message(summary.with_provenance(provenance.clone()));
+        }
+        for message in &self.messages {
+            context.add_message(message.clone().with_provenance(provenance.clone()));
+        }
+    }
+
+    /// Append a prompt and the response it got
+    pub fn record(&mut self, prompt: &str, response: &str) {
+        self.messages.push(Message::new(MessageRole::User, prompt));
+        self.messages.push(Message::new(MessageRole::Assistant, response));
+        self.updated_at = unix_now();
+    }
+
+    /// Forget the conversation but keep the session
+    pub fn clear(&mut self) {
+        self.summary.clear();
+        self.messages.clear();
+        self.updated_at = unix_now();
+    }
+
+    /// Tokens the session adds to each request
+    pub fn count_tokens(&self, tokenizer: &dyn Tokenizer) -> usize {
+        let mut context = Context::new();
+        self.apply_to(&mut context);
+        context.count_tokens(tokenizer)
+    }
+
+    /// Fold the oldest messages into the summary until the session fits the budget
+    ///
+    /// The `keep_recent` newest messages are kept verbatim, except that a reply is
+    /// never separated from its prompt. Returns how many messages were folded.
+    pub fn compact(&mut self, tokenizer: &dyn Tokenizer, settings: &SessionSettings) -> usize {
+        let mut folded = 0;
+        loop {
+            let over_budget = self.messages.len() > settings.keep_recent && self.count_tokens(tokenizer) > settings.max_tokens;
+            let mid_exchange = folded > 0 && self.messages.first().is_some_and(|m| m.role == MessageRole::Assistant);
+            if !over_budget && !mid_exchange {
+                break;
+            }
+            let message = self.messages.remove(0);
+            let role = match message.role {
+                MessageRole::User => "user",
+                MessageRole::Assistant => "assistant",
+                MessageRole::System => "system",
+            };
+            let first_line = message.content.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+            let mut line = tokenizer.truncate(first_line, SUMMARY_LINE_TOKENS).to_string();
+            if line.len() < message.content.trim().len() {
+                line.push_str(" ...");
+            }
+            self.summary.push(format!("{}: {}", role, line));
+            folded += 1;
+        }
+        // The summary itself is bounded too: oldest lines go first
+        while self.summary.len() > 1 && self.count_tokens(tokenizer) > settings.max_tokens {
+            self.summary.remove(0);
+        }
+        if folded > 0 {
+            self.updated_at = unix_now();
+        }
+        folded
+    }
+
+    fn summary_message(&self) -> Option<Message> {
+        if self.summary.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = self.summary.iter().map(|line| format!("- {}", line)).collect();
+        Some(Message::new(
+            MessageRole::System,
+            format!("Earlier in this conversation (condensed):\n{}", lines.join("\n")),
+        ))
+    }
+}
+
+/// File-backed storage for sessions (`<dir>/<name>.json`)
+pub struct SessionStore {
+    dir: PathBuf,
+    encryption: Option<Arc<Encryption>>,
+}
+
+impl SessionStore {
+    /// Create a store rooted at a directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), encryption: None }
+    }
+
+    /// Encrypt sessions when they are saved; plain files are still read
+    pub fn with_encryption(mut self, encryption: Arc<Encryption>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Open the store in the user data directory
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(config::data_dir()?.join("sessions")))
+    }
+
+    /// Get the store directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file holding a session
+    pub fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Load a session by name
+    pub fn load(&self, name: &str) -> Result<Session> {
+        validate_session_name(name)?;
+        let path = self.path_for(name);
+        if !path.exists() {
+            return Err(anyhow!("No session named '{}'", name));
+        }
+        let text = encryption::read_to_string(&path, self.encryption.as_deref())?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid session file {}", path.display()))
+    }
+
+    /// Load a session, starting a new one when none exists yet
+    pub fn load_or_new(&self, name: &str) -> Result<Session> {
+        validate_session_name(name)?;
+        if self.path_for(name).exists() {
+            self.load(name)
+        } else {
+            Ok(Session::new(name))
+        }
+    }
+
+    /// Save a session, replacing the stored copy
+    pub fn save(&self, session: &Session) -> Result<PathBuf> {
+        validate_session_name(&session.name)?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path_for(&session.name);
+        encryption::write(&path, serde_json::to_string_pretty(session)?, self.encryption.as_deref())?;
+        Ok(path)
+    }
+
+    /// Delete a session
+    pub fn delete(&self, name: &str) -> Result<()> {
+        validate_session_name(name)?;
+        let path = self.path_for(name);
+        if !path.exists() {
+            return Err(anyhow!("No session named '{}'", name));
+        }
+        std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))
+    }
+
+    /// List sessions, most recently used first
+    pub fn list(&self) -> Result<Vec<Session>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut sessions = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            // Plain files that do not parse are not sessions; encrypted ones that cannot be opened are errors
+            let data = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let text = match encryption::is_encrypted(&data) {
+                true => encryption::read_to_string(&path, self.encryption.as_deref())?,
+                false => String::from_utf8_lossy(&data).into_owned(),
+            };
+            if let Ok(session) = serde_json::from_str::<Session>(&text) {
+                sessions.push(session);
+            }
+        }
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(a.name.cmp(&b.name)));
+        Ok(sessions)
+    }
+}
+
+/// Session names become file names, so they are limited to letters, digits, `-` and `_`
+pub fn validate_session_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("Session name cannot be empty"));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!("Invalid session name '{}': use letters, digits, '-' or '_'", name));
+    }
+    Ok(())
+}
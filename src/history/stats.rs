@@ -0,0 +1,191 @@
+//! Usage statistics aggregated from the run history (`ai-cli stats`)
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{RunRecord, StepRecord};
+
+/// How steps are grouped in a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GroupBy {
+    Provider,
+    #[default]
+    Model,
+}
+
+/// Runs started within `[since, until)`, as Unix timestamps in seconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TimeRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+}
+
+impl TimeRange {
+    /// Range from `--since`/`--until` values, relative to `now`
+    pub fn parse(since: Option<&str>, until: Option<&str>, now: u64) -> Result<Self> {
+        let range = Self {
+            since: since.map(|s| parse_time_bound(s, now)).transpose()?,
+            until: until.map(|s| parse_time_bound(s, now)).transpose()?,
+        };
+        if let (Some(since), Some(until)) = (range.since, range.until)
+            && since >= until
+        {
+            return Err(anyhow!("--since must be earlier than --until"));
+        }
+        Ok(range)
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.since.is_none_or(|since| timestamp >= since) && self.until.is_none_or(|until| timestamp < until)
+    }
+}
+
+/// Parse a duration ago (`30m`, `24h`, `7d`, `4w`) or a UTC date (`2026-10-01`)
+pub fn parse_time_bound(text: &str, now: u64) -> Result<u64> {
+    let invalid = || anyhow!("Invalid time '{}': use a duration like 24h, 7d or 4w, or a date like 2026-10-01", text);
+    if let [year, month, day] = text.split('-').collect::<Vec<_>>()[..] {
+        let year: i64 = year.parse().map_err(|_| invalid())?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        let day: u32 = day.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(invalid());
+        }
+        let days = days_from_civil(year, month, day);
+        return u64::try_from(days * 86_400).map_err(|_| invalid());
+    }
+
+    let unit = text.chars().last().ok_or_else(invalid)?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        'w' => 604_800,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = text[..text.len() - 1].parse().map_err(|_| invalid())?;
+    Ok(now.saturating_sub(count * seconds))
+}
+
+/// Days since the Unix epoch of a civil date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Usage of one provider or provider/model pair
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageStats {
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub requests: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_p50_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_p90_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_p99_ms: Option<u64>,
+}
+
+/// Aggregated usage over a time range
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsReport {
+    pub range: TimeRange,
+    pub runs: usize,
+    pub groups: Vec<UsageStats>,
+}
+
+impl StatsReport {
+    /// Aggregate the steps of runs started within `range`
+    pub fn aggregate(records: &[RunRecord], range: TimeRange, group_by: GroupBy) -> Self {
+        let runs: Vec<&RunRecord> = records.iter().filter(|r| range.contains(r.started_at)).collect();
+        let mut groups: BTreeMap<(String, Option<String>), Vec<&StepRecord>> = BTreeMap::new();
+        for step in runs.iter().flat_map(|r| &r.steps) {
+            let model = match group_by {
+                GroupBy::Provider => None,
+                GroupBy::Model => Some(step.model.clone().unwrap_or_else(|| "unknown".to_string())),
+            };
+            groups.entry((step.provider.clone(), model)).or_default().push(step);
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|((provider, model), steps)| {
+                let errors = steps.iter().filter(|s| s.error).count();
+                let mut latencies: Vec<u64> = steps.iter().filter_map(|s| s.latency_ms).collect();
+                latencies.sort_unstable();
+                UsageStats {
+                    provider,
+                    model,
+                    requests: steps.len(),
+                    errors,
+                    error_rate: errors as f64 / steps.len() as f64,
+                    prompt_tokens: steps.iter().filter_map(|s| s.usage).map(|u| u.prompt_tokens).sum(),
+                    completion_tokens: steps.iter().filter_map(|s| s.usage).map(|u| u.completion_tokens).sum(),
+                    cost_usd: steps.iter().filter_map(|s| s.cost_usd).sum(),
+                    latency_p50_ms: percentile(&latencies, 50),
+                    latency_p90_ms: percentile(&latencies, 90),
+                    latency_p99_ms: percentile(&latencies, 99),
+                }
+            })
+            .collect();
+        Self { range, runs: runs.len(), groups }
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], p: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.groups.is_empty() {
+            return write!(f, "No provider calls recorded in {} run(s).", self.runs);
+        }
+        let ms = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+        writeln!(
+            f,
+            "{:<10} {:<28} {:>8} {:>7} {:>12} {:>12} {:>10} {:>8} {:>8} {:>8}",
+            "PROVIDER", "MODEL", "REQUESTS", "ERRORS", "PROMPT_TOK", "OUTPUT_TOK", "COST_USD", "P50_MS", "P90_MS", "P99_MS"
+        )?;
+        for group in &self.groups {
+            writeln!(
+                f,
+                "{:<10} {:<28} {:>8} {:>6.1}% {:>12} {:>12} {:>10.4} {:>8} {:>8} {:>8}",
+                group.provider,
+                group.model.as_deref().unwrap_or("-"),
+                group.requests,
+                group.error_rate * 100.0,
+                group.prompt_tokens,
+                group.completion_tokens,
+                group.cost_usd,
+                ms(group.latency_p50_ms),
+                ms(group.latency_p90_ms),
+                ms(group.latency_p99_ms),
+            )?;
+        }
+        let cost: f64 = self.groups.iter().map(|g| g.cost_usd).sum();
+        let requests: usize = self.groups.iter().map(|g| g.requests).sum();
+        write!(f, "{} run(s), {} request(s), ${:.4} total", self.runs, requests, cost)
+    }
+}
@@ -0,0 +1,184 @@
+//! Localized CLI messages: English and Japanese catalogs, selected by `--lang` or the locale
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Language of CLI messages and help text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(Lang::En as u8);
+
+impl Lang {
+    /// Language of a POSIX locale such as `ja_JP.UTF-8`; `None` for `C`, `POSIX` and unsupported languages
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale.split(['_', '.', '@', '-']).next().unwrap_or("").to_ascii_lowercase();
+        match language.as_str() {
+            "ja" => Some(Lang::Ja),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    /// `chosen` (from `--lang`) if given, else the first of `LC_ALL`, `LC_MESSAGES`, `LANG` that is set
+    pub fn detect(chosen: Option<Lang>) -> Lang {
+        chosen.unwrap_or_else(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .and_then(|locale| Lang::from_locale(&locale))
+                .unwrap_or_default()
+        })
+    }
+
+    /// Language messages are currently printed in
+    pub fn current() -> Lang {
+        match CURRENT.load(Ordering::Relaxed) {
+            1 => Lang::Ja,
+            _ => Lang::En,
+        }
+    }
+
+    /// Print messages in this language from now on
+    pub fn set_current(self) {
+        CURRENT.store(self as u8, Ordering::Relaxed);
+    }
+}
+
+/// A user-facing message, displayed in `Lang::current()`
+#[derive(Clone, Copy)]
+pub enum Msg<'a> {
+    Warning(&'a dyn Display),
+    ExecutionFailed(&'a dyn Display),
+    PipelineFailed(&'a dyn Display),
+    ConsensusFailed(&'a dyn Display),
+    ProviderUnavailable(&'a str),
+    NoProviders,
+    AuthNotFound(&'a str),
+    InvalidChain(&'a dyn Display),
+    NoChain,
+    SavedPipelinesTip,
+    PipelineSaved(&'a str, &'a dyn Display),
+    PipelineSaveFailed(&'a dyn Display),
+    PipelineDiscarded,
+    NoSavedPipelines,
+    NoRuns,
+    Listening(&'a dyn Display),
+    StepHeader(usize, &'a str),
+    ReviewChoice,
+    ReviewTweak,
+    ReviewNothingToAdd,
+    PipelineAborted(usize),
+}
+
+impl Display for Msg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ja = Lang::current() == Lang::Ja;
+        match *self {
+            Msg::Warning(e) if ja => write!(f, "警告: {}", e),
+            Msg::Warning(e) => write!(f, "Warning: {}", e),
+            Msg::ExecutionFailed(e) if ja => write!(f, "実行に失敗しました: {}", e),
+            Msg::ExecutionFailed(e) => write!(f, "Execution failed: {}", e),
+            Msg::PipelineFailed(e) if ja => write!(f, "パイプラインが失敗しました: {}", e),
+            Msg::PipelineFailed(e) => write!(f, "Pipeline failed: {}", e),
+            Msg::ConsensusFailed(e) if ja => write!(f, "合議に失敗しました: {}", e),
+            Msg::ConsensusFailed(e) => write!(f, "Consensus failed: {}", e),
+            Msg::ProviderUnavailable(name) if ja => {
+                write!(f, "プロバイダ '{}' は利用できません。--api-key を指定するか認証を設定してください。", name)
+            }
+            Msg::ProviderUnavailable(name) => write!(f, "Provider '{}' not available. Use --api-key or configure auth.", name),
+            Msg::NoProviders if ja => f.write_str(
+                "利用可能なプロバイダがありません（認証が検出されません）。execute/pipeline で --api-key を指定するか `ai-cli auth login` を実行してください。",
+            ),
+            Msg::NoProviders => f.write_str(
+                "No providers available (auth not detected). Use --api-key on execute/pipeline or `ai-cli auth login`.",
+            ),
+            Msg::AuthNotFound(name) if ja => write!(f, "{}: 認証が見つかりません", name),
+            Msg::AuthNotFound(name) => write!(f, "{}: auth not found", name),
+            Msg::InvalidChain(e) if ja => write!(f, "チェーンが不正です: {}", e),
+            Msg::InvalidChain(e) => write!(f, "Invalid chain: {}", e),
+            Msg::NoChain if ja => f.write_str("--chain も --chain-file も指定されておらず、default_chain も設定されていません。"),
+            Msg::NoChain => f.write_str("No --chain or --chain-file given and no default_chain configured."),
+            Msg::SavedPipelinesTip if ja => f.write_str("ヒント: 保存済みパイプラインは `ai-cli pipeline list` で確認できます。"),
+            Msg::SavedPipelinesTip => f.write_str("Tip: list saved pipelines with `ai-cli pipeline list`."),
+            Msg::PipelineSaved(name, path) if ja => write!(f, "パイプライン '{}' を {} に保存しました", name, path),
+            Msg::PipelineSaved(name, path) => write!(f, "Saved pipeline '{}' to {}", name, path),
+            Msg::PipelineSaveFailed(e) if ja => write!(f, "パイプラインの保存に失敗しました: {}", e),
+            Msg::PipelineSaveFailed(e) => write!(f, "Failed to save pipeline: {}", e),
+            Msg::PipelineDiscarded if ja => f.write_str("パイプラインを破棄しました。"),
+            Msg::PipelineDiscarded => f.write_str("Pipeline discarded."),
+            Msg::NoSavedPipelines if ja => f.write_str("保存されたパイプラインはありません。"),
+            Msg::NoSavedPipelines => f.write_str("No saved pipelines."),
+            Msg::NoRuns if ja => f.write_str("実行履歴はまだありません。"),
+            Msg::NoRuns => f.write_str("No runs recorded yet."),
+            Msg::Listening(addr) if ja => write!(f, "http://{} で待ち受けています", addr),
+            Msg::Listening(addr) => write!(f, "Listening on http://{}", addr),
+            Msg::StepHeader(step, provider) if ja => write!(f, "── ステップ {} ({}) ──", step, provider),
+            Msg::StepHeader(step, provider) => write!(f, "── Step {} ({}) ──", step, provider),
+            Msg::ReviewChoice if ja => f.write_str("[a]採用, [e]編集, [r]指示を足して再実行, [q]中止? "),
+            Msg::ReviewChoice => f.write_str("[a]ccept, [e]dit, [r]etry with a tweak, [q]uit? "),
+            Msg::ReviewTweak if ja => f.write_str("プロンプトに追加する指示: "),
+            Msg::ReviewTweak => f.write_str("Add to the prompt: "),
+            Msg::ReviewNothingToAdd if ja => f.write_str("追加する指示がありません。もう一度選んでください。"),
+            Msg::ReviewNothingToAdd => f.write_str("Nothing to add; choose again."),
+            Msg::PipelineAborted(step) if ja => write!(f, "ステップ {} の後でパイプラインを中止しました", step),
+            Msg::PipelineAborted(step) => write!(f, "Pipeline aborted after step {}", step),
+        }
+    }
+}
+
+/// Japanese help for a top-level subcommand or global option, keyed by its name
+pub fn help_ja(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "ai-cli" => "AI CLI アグリゲーター - 複数の AI CLI ツールを一つに",
+        "execute" => "単一の AI プロンプトを実行する",
+        "pipeline" => "AI 処理のパイプラインを実行する",
+        "run" => "保存したパイプラインを名前で実行する",
+        "watch" => "監視中のファイルが変わるたびに、変更ファイルをコンテキストにしてパイプラインを再実行する",
+        "commit-msg" => "ステージ済みの変更から Conventional Commits 形式のコミットメッセージを書く",
+        "review" => "GitHub のプルリクエストをファイルごとにレビューする",
+        "consensus" => "複数のプロバイダに同じ質問をし、回答を比較・統合する",
+        "serve" => "設定済みのプロバイダと保存済みパイプラインを HTTP API として提供する",
+        "list-providers" => "既知のプロバイダと認証・既定モデル・機能を一覧表示する",
+        "models" => "認証済みプロバイダが提供するモデルを一覧表示する",
+        "check-auth" => "プロバイダの認証状態を確認する",
+        "doctor" => "プロバイダごとの認証・ネットワーク疎通・レイテンシ・設定を診断する",
+        "version" => "バージョン情報を表示する",
+        "logout" => "プロバイダの保存済み認証情報を削除する",
+        "auth" => "保存済み認証情報を確認・管理する",
+        "history" => "過去の実行の成果物を閲覧する",
+        "rerun" => "記録された実行を manifest のチェーン・オプション・コンテキスト・環境変数でもう一度実行する",
+        "apply" => "--sandbox で実行したときに書き出されたファイルをプロジェクトへ反映する",
+        "compare" => "1 つのプロンプトを複数プロバイダで、または複数のプロンプトを 1 つのプロバイダで実行し、回答を並べて比較する",
+        "eval" => "JSONL のテストケースでパイプラインを採点し、プロバイダを比較する",
+        "stats" => "実行履歴からリクエスト数・トークン・コスト・エラー・レイテンシを集計する",
+        "batch" => "プロバイダのバッチ API で 1 つのプロンプトを多数の入力に対して半額で実行する",
+        "index" => "--retrieve 用にプロジェクトのファイルを埋め込む",
+        "embed" => "ファイルまたは標準入力の埋め込みベクトルを出力する",
+        "session" => "`execute --session` で保存した会話を管理する",
+        "audit" => "プロバイダ呼び出しの監査ログを表示する（`[audit] enabled = true` で記録）",
+        "completions" => "シェル補完スクリプトを出力する",
+        "verbose" => "詳細なログを出力する: -v info, -vv debug (HTTP リクエスト), -vvv trace",
+        "log-format" => "ログ行の形式",
+        "log-file" => "ログを標準エラーではなくこのファイルに追記する",
+        "otlp-endpoint" => "pipeline・step・HTTP 呼び出しのスパンを OpenTelemetry トレースとしてこの OTLP/HTTP コレクターへ送る（例: http://localhost:4318）",
+        "debug-http" => "秘匿情報を除いた HTTP リクエスト/レスポンスを標準エラーに出力する（--debug-http=PATH でファイルに追記）",
+        "offline" => "ネットワークアクセスを禁止する: ローカルで処理できないステップは即座に失敗する（AI_CLI_OFFLINE=1 でも可）",
+        "privacy" => "送信前にメールアドレス・絶対パス・ユーザー名・ホスト名をプレースホルダーに置き換え、応答では元の値に戻す",
+        "quiet" => "補助的な出力を抑制する",
+        "reprobe" => "キャッシュを使わずにプロバイダの機能を再確認する",
+        "profile" => "使用する認証プロファイル（認証情報・既定モデル・ベース URL）",
+        "show-cost" => "execute/pipeline の実行後にトークン使用量とコストを表示する",
+        "package" => "コンテキストを Cargo/npm/Python モノレポの一パッケージに限定する",
+        "render" => "モデル出力の表示方法（既定: 端末では markdown、パイプ時は raw）",
+        "copy" => "最終出力をクリップボードへコピーする（--copy=N でステップ N の出力）",
+        "lang" => "メッセージとヘルプの言語（既定: LANG から判定）",
+        _ => return None,
+    })
+}
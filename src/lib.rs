@@ -1,4 +1,32 @@
+//! Pipeline engine behind the `ai-cli` binary
+//!
+//! The `cli` feature (on by default) adds the command-line front-end and its
+//! terminal dependencies. Programs embedding the engine can depend on the
+//! crate with `default-features = false` and get providers, pipelines, auth,
+//! context, config and history only.
+
 pub mod providers;
 pub mod auth;
+#[cfg(feature = "cli")]
 pub mod cli;
-pub mod pipeline;
\ No newline at end of file
+#[cfg(feature = "cli")]
+pub mod clipboard;
+pub mod pipeline;
+pub mod config;
+pub mod context;
+pub mod diagnostics;
+pub mod doctor;
+pub mod error;
+pub mod history;
+pub mod i18n;
+#[cfg(feature = "cli")]
+pub mod logging;
+#[cfg(feature = "cli")]
+pub mod render;
+pub mod scheduler;
+#[cfg(feature = "cli")]
+pub mod telemetry;
+#[cfg(feature = "cli")]
+pub mod server;
+#[cfg(feature = "cli")]
+pub mod tui;
@@ -0,0 +1,74 @@
+//! Diagnostic logging via `tracing`
+//!
+//! Logs go to stderr (or `--log-file`) so they never mix with responses on
+//! stdout. `-v` shows info, `-vv` debug (including HTTP requests with
+//! credentials redacted) and `-vvv` trace; `RUST_LOG` overrides the level.
+//! Spans can additionally be exported as OpenTelemetry traces ([`telemetry`]).
+
+use anyhow::{Result, Context as AnyhowContext};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::Level;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::telemetry;
+
+/// Output format of log lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Level for a `-v` count; `--quiet` limits logging to errors
+pub fn level_for(verbosity: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::ERROR;
+    }
+    match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Install the global subscriber, exporting spans to `otlp_endpoint` when given
+///
+/// Trace export has its own filter, so `pipeline`, `step` and `http` spans are
+/// exported whatever the log level.
+pub fn init(verbosity: u8, quiet: bool, format: LogFormat, file: Option<&Path>, otlp_endpoint: Option<&str>) -> Result<()> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(level_for(verbosity, quiet).into())
+        .from_env_lossy();
+    let otlp = otlp_endpoint
+        .map(|endpoint| telemetry::install(endpoint).map(|layer| layer.with_filter(Targets::new().with_target("ai_cli", Level::INFO))))
+        .transpose()?;
+    let layer = tracing_subscriber::fmt::layer().with_target(verbosity >= 2);
+
+    let fmt: Box<dyn Layer<Registry> + Send + Sync> = match (file, format) {
+        (Some(path), format) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            let layer = layer.with_ansi(false).with_writer(Mutex::new(file));
+            match format {
+                LogFormat::Text => layer.with_filter(filter).boxed(),
+                LogFormat::Json => layer.json().with_filter(filter).boxed(),
+            }
+        }
+        (None, LogFormat::Text) => layer.with_writer(std::io::stderr).with_filter(filter).boxed(),
+        (None, LogFormat::Json) => layer.json().with_writer(std::io::stderr).with_filter(filter).boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(fmt)
+        .with(otlp)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to set up logging: {}", e))
+}
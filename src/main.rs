@@ -1,172 +1,2037 @@
-use ai_cli::auth::AuthManager;
-use ai_cli::cli::{CliArgs, Command};
-use ai_cli::pipeline::{PipelineExecutor, PipelineParser, PipelineStep};
-use ai_cli::providers::{Context};
+use ai_cli::auth::{AuthManager, AuthMethod, CredentialStore, ManagedCredentials, mask_key};
+use ai_cli::auth::google::GoogleAdc;
+use ai_cli::cli::completion;
+use ai_cli::clipboard;
+use ai_cli::cli::{AuditAction, AuthAction, BatchAction, CliArgs, Command, GenerationArgs, HistoryAction, PipelineAction, SessionAction};
+use ai_cli::pipeline::{ArtifactsDir, BatchInput, BatchJob, BatchJobStore, BatchRunner, CompareView, EditorGate, EvalCase, Evaluator, FileWriter, Metrics, Variant, PipelineDefinition, PipelineExecutor, PipelineFailure, PipelineParser, PromptAffixes, PipelineStep, PipelineStore, PipelineWizard, ProblemKind, SafetyFilter, Sandbox, TerminalGate};
+use ai_cli::pipeline::commit_msg;
+use ai_cli::pipeline::hooks::{self, HookSettings, RunOutcome};
+use ai_cli::pipeline::postmortem::run_postmortem;
+use ai_cli::pipeline::review::{self, ReviewFormat};
+use ai_cli::pipeline::sandbox;
+use ai_cli::pipeline::template::passthrough_env;
+use ai_cli::config::{Config, PostMortemSettings, remove_profile_api_key};
+use ai_cli::context::{Anonymizer, Compactor, ContextLimits, ContextLoader, DiffSource, EmbedFormat, Embedder, Embeddings, FileWatcher, HashEmbedder, IncrementalContext, Package, Provenance, Redactor, Retriever, VectorIndex, Workspace};
+use ai_cli::context::embed;
+use ai_cli::context::git::{add_diffs_to_context, collect_diff, git_path, repo_root};
+use ai_cli::context::github::{self, GitHubClient, PullRef};
+use ai_cli::context::redact::{append_audit_log, default_audit_log};
+use ai_cli::diagnostics::render_error;
+use ai_cli::doctor::{self, Doctor, DoctorReport};
+use ai_cli::error::ExitCode;
+use ai_cli::logging;
+use ai_cli::telemetry;
+use ai_cli::render::RenderMode;
+use ai_cli::server::{self, ServerState};
+use ai_cli::tui;
+use ai_cli::i18n::{Lang, Msg};
+use ai_cli::history::{RunArtifacts, RunStatus, RunStore, unix_now};
+use ai_cli::history::audit::{self, AuditLog, AuditSettings};
+use ai_cli::history::manifest::RunManifest;
+use ai_cli::history::encryption::{Encryption, EncryptionSettings};
+use ai_cli::history::session::SessionStore;
+use ai_cli::history::stats::{StatsReport, TimeRange};
+use ai_cli::providers::{AIProvider, Context, KNOWN_PROVIDERS, Message, MessageRole, ProviderOptions, Response, check_model};
+use ai_cli::providers::http;
+use ai_cli::providers::listing::ProviderListing;
+use ai_cli::providers::pricing::{CostSummary, PricingTable};
+use ai_cli::providers::probe::CapabilityCache;
 use ai_cli::providers::claude::ClaudeProvider;
 use ai_cli::providers::gemini::GeminiProvider;
 use ai_cli::providers::codex::CodexProvider;
-use std::sync::Arc;
-use clap::Parser;
+use ai_cli::providers::batch::BatchApi;
+use ai_cli::providers::openai::{OpenAIBatch, OpenAIEmbedder};
+use ai_cli::providers::tokenizer::BpeTokenizer;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use anyhow::Context as _;
+use futures::StreamExt;
 
 #[tokio::main]
 async fn main() {
-    let args = CliArgs::parse();
+    completion::complete_from_env();
+    let args = CliArgs::parse_localized(std::env::args_os());
+    Lang::detect(args.lang).set_current();
 
-    // Basic verbosity handling (placeholder)
-    let _verbose = args.verbose;
-
-    // Auth manager and executor
-    let auth = AuthManager::new();
-    let mut executor = PipelineExecutor::new();
+    if let Err(e) = logging::init(args.verbose, args.quiet, args.log_format, args.log_file.as_deref(), args.otlp_endpoint.as_deref()) {
+        eprintln!("{:#}", e);
+        exit(ExitCode::Usage);
+    }
+    http::set_offline(args.offline);
+    if let Some(target) = &args.debug_http
+        && let Err(e) = http::enable_debug_http(target.as_deref())
+    {
+        eprintln!("{:#}", e);
+        exit(ExitCode::Usage);
+    }
 
-    // Register providers opportunistically via detected auth
-    // Claude
-    if let Ok(method) = auth.detect_auth("claude").await {
-        match method {
-            ai_cli::auth::AuthMethod::ApiKey { key } => {
-                let prov = ClaudeProvider::new(key);
-                executor.register_provider("claude", Arc::new(prov));
-            }
-            ai_cli::auth::AuthMethod::CliAuth => {
-                // Assume detected session is usable and register provider
-                let prov = ClaudeProvider::from_detected_cli_session();
-                executor.register_provider("claude", Arc::new(prov));
-            }
-            _ => {}
+    // Load user config (merged with any project .ai-cli.toml) and resolve the active auth profile
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let config = match Config::load_for_dir(&cwd) {
+        Ok(config) => config,
+        // doctor reports the invalid config itself
+        Err(_) if matches!(args.command, Some(Command::Doctor { .. })) => Config::default(),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(ExitCode::for_error(&e));
         }
+    };
+    let _ = ENCRYPTION.set(config.encryption.clone());
+    if let Err(e) = http::init_shared_client(&config.http.clone().with_env(|var| std::env::var(var).ok())) {
+        eprintln!("{:#}", e);
+        exit(ExitCode::Usage);
     }
-    // Gemini
-    if let Ok(method) = auth.detect_auth("gemini").await {
-        match method {
-            ai_cli::auth::AuthMethod::ApiKey { key } => {
-                let prov = GeminiProvider::new(key);
-                executor.register_provider("gemini", Arc::new(prov));
+    let mut auth = match config.active_profile_name(args.profile.as_deref()) {
+        Some(name) => match config.profile(&name) {
+            Ok(profile) => AuthManager::with_profile(name, profile.clone()),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(ExitCode::for_error(&e));
             }
-            ai_cli::auth::AuthMethod::CliAuth => {
-                let prov = GeminiProvider::from_detected_cli_session();
-                executor.register_provider("gemini", Arc::new(prov));
+        },
+        None => AuthManager::new(),
+    };
+    match CredentialStore::open_default() {
+        Ok(store) => auth.set_credential_store(store),
+        Err(e) => eprintln!("Warning: ignoring credential store: {}", e),
+    }
+    auth.register_default_refreshers();
+    let package = match &args.package {
+        Some(name) => match Workspace::discover(&cwd).and_then(|ws| ws.package(name).cloned()) {
+            Ok(package) => Some(package),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(ExitCode::Usage);
+            }
+        },
+        None => None,
+    };
+    let mut builder = PipelineExecutor::builder()
+        .prompt_prefix(config.prompt_prefix.clone())
+        .pricing(PricingTable::builtin().with_overrides(&config.pricing))
+        .rate_limits(&config.rate_limits)
+        .context_policy(config.context_policy.unwrap_or_default());
+    if args.privacy {
+        builder = builder.anonymizer(Arc::new(Anonymizer::for_local_machine()));
+    }
+    if config.audit.enabled {
+        // The log must not keep what --privacy kept from the provider
+        let settings = AuditSettings { hash_content: config.audit.hash_content || args.privacy, ..config.audit.clone() };
+        match AuditLog::from_settings(&settings) {
+            Ok(audit) => builder = builder.audit_log(Arc::new(audit)),
+            // Calls must not go unaudited when auditing was asked for
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Usage);
             }
-            _ => {}
         }
     }
-    // Codex
-    if let Ok(method) = auth.detect_auth("codex").await {
-        match method {
-            ai_cli::auth::AuthMethod::ApiKey { key } => {
-                let prov = CodexProvider::new(key);
-                executor.register_provider("codex", Arc::new(prov));
+    if config.safety.enabled {
+        match SafetyFilter::from_settings(&config.safety) {
+            Ok(safety) => builder = builder.safety_filter(Arc::new(safety)),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Usage);
             }
-            ai_cli::auth::AuthMethod::CliAuth => {
-                let prov = CodexProvider::from_detected_cli_session();
-                executor.register_provider("codex", Arc::new(prov));
+        }
+    }
+    if config.redaction.enabled {
+        match Redactor::from_settings(&config.redaction) {
+            Ok(redactor) => builder = builder.redactor(Arc::new(redactor)),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Usage);
             }
-            _ => {}
         }
     }
 
+    // Project/top-level preferences win over the profile's
+    for name in KNOWN_PROVIDERS {
+        let settings = [config.provider_preferences(name), auth.provider_settings(name)];
+        let affixes = PromptAffixes {
+            prefix: settings.iter().flatten().find_map(|s| s.prompt_prefix.clone()),
+            suffix: settings.iter().flatten().find_map(|s| s.prompt_suffix.clone()),
+        };
+        if affixes != PromptAffixes::default() {
+            builder = builder.provider_prompt(*name, affixes);
+        }
+        if let Some(policy) = settings.iter().flatten().find_map(|s| s.context_policy) {
+            builder = builder.provider_context_policy(*name, policy);
+        }
+    }
+
+    // Register providers opportunistically via detected auth
+    for name in KNOWN_PROVIDERS {
+        if let Ok(method) = auth.detect_auth(name).await
+            && let Some(provider) = build_provider(name, method, &auth, &config)
+        {
+            builder = builder.provider(*name, provider);
+        }
+    }
+    let mut executor = builder.build();
+    executor.set_file_writer(FileWriter::direct(config.project_root().unwrap_or(&cwd)));
+    if config.compaction.enabled {
+        let provider = match &config.compaction.provider {
+            Some(name) => executor.get_provider(name),
+            None => KNOWN_PROVIDERS.iter().find_map(|name| executor.get_provider(name)),
+        };
+        match provider {
+            Some(provider) => executor.set_compactor(Arc::new(Compactor::from_settings(provider, &config.compaction))),
+            None => eprintln!("Warning: context compaction is enabled but its provider is not available"),
+        }
+    }
+
+    let render = RenderMode::resolve(args.render, std::io::stdout().is_terminal());
+    let flags = RunFlags { quiet: args.quiet, reprobe: args.reprobe, show_cost: args.show_cost, render, copy: args.copy };
+
     // Parse command and dispatch
     match args.command {
-        Some(Command::ListProviders) => {
-            let names = executor.get_provider_names();
-            if names.is_empty() {
-                println!("No providers registered (auth not detected). Use --api-key on execute/pipeline.");
+        Some(Command::ListProviders { json }) => {
+            let listing = ProviderListing::collect(&auth, &executor).await;
+            if json {
+                match serde_json::to_string_pretty(&listing) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("Failed to encode provider list: {}", e);
+                        exit(ExitCode::Failure);
+                    }
+                }
             } else {
-                for n in names {
-                    println!("{}", n);
+                print!("{}", listing);
+                if !listing.providers.iter().any(|p| p.available) && !args.quiet {
+                    eprintln!("{}", Msg::NoProviders);
                 }
             }
         }
-        Some(Command::CheckAuth { provider }) => {
+        Some(Command::Models { provider }) => {
+            let names: Vec<String> = match provider {
+                Some(name) if executor.has_provider(&name) => vec![name.to_string()],
+                Some(name) => {
+                    eprintln!("{}", Msg::AuthNotFound(&name));
+                    exit(ExitCode::Auth);
+                }
+                None => {
+                    let mut names = executor.get_provider_names();
+                    names.sort();
+                    names
+                }
+            };
+            let mut failed = false;
+            for name in names {
+                let Some(prov) = executor.get_provider(&name) else { continue };
+                match prov.list_models().await {
+                    Ok(mut models) => {
+                        models.sort_by(|a, b| a.id.cmp(&b.id));
+                        println!("{}:", name);
+                        for model in &models {
+                            let marker = if prov.model() == Some(model.id.as_str()) { "*" } else { " " };
+                            let window = model.context_window.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+                            println!("{} {:<40} {:>9}  {}", marker, model.id, window, model.display_name.as_deref().unwrap_or(""));
+                        }
+                        if let Some(model) = prov.model()
+                            && let Err(e) = check_model(&models, model)
+                        {
+                            eprintln!("Warning: configured {} model: {}", name, e);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("{}: failed to list models ({})", name, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                exit(ExitCode::Provider);
+            }
+        }
+        Some(Command::CheckAuth { provider, validate: false }) => {
             match auth.detect_auth(&provider).await {
                 Ok(_) => println!("{}: authenticated or credentials detected", provider),
                 Err(e) => println!("{}: auth not found ({})", provider, e),
             }
         }
+        Some(Command::CheckAuth { provider, validate: true }) => {
+            let Some(prov) = executor.get_provider(&provider) else {
+                println!("{}", Msg::AuthNotFound(&provider));
+                exit(ExitCode::Auth);
+            };
+            match prov.validate_auth().await {
+                Ok(report) => {
+                    println!("{}: credentials valid", provider);
+                    if let Some(account) = &report.account {
+                        println!("  account: {}", account);
+                    }
+                    println!("  models accessible: {}", report.models.len());
+                    if let (Some(model), Some(accessible)) = (prov.model(), report.model_accessible) {
+                        let status = if accessible { "accessible" } else { "NOT accessible" };
+                        println!("  default model {}: {}", model, status);
+                    }
+                }
+                Err(e) => {
+                    println!("{}: validation failed ({})", provider, e);
+                    exit(match ExitCode::for_error(&e) {
+                        ExitCode::Failure => ExitCode::Auth,
+                        code => code,
+                    });
+                }
+            }
+        }
+        Some(Command::Doctor { provider, timeout, json }) => {
+            let providers: Vec<&str> = match &provider {
+                Some(name) => vec![name.as_str()],
+                None => KNOWN_PROVIDERS.to_vec(),
+            };
+            let doctor = Doctor::new(&auth, &executor).with_timeout(std::time::Duration::from_secs(timeout));
+            let mut report = DoctorReport { checks: doctor::check_config(&cwd) };
+            report.checks.extend(doctor.diagnose(&providers).await);
+            if json {
+                match serde_json::to_string_pretty(&report) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => eprintln!("Failed to serialize diagnosis: {}", e),
+                }
+            } else {
+                print!("{}", report);
+            }
+            if !report.healthy() {
+                exit(ExitCode::Failure);
+            }
+        }
         Some(Command::Version) => {
             println!("ai-cli version {}", env!("CARGO_PKG_VERSION"));
         }
-        Some(Command::Execute { provider, prompt, api_key, context, no_stream: _ }) => {
-            // Ensure provider is registered; for now support only claude natively
-            if !executor.has_provider(&provider) {
-                if let Some(key) = api_key.clone() {
-                    match provider.as_str() {
-                        "claude" => executor.register_provider("claude", Arc::new(ClaudeProvider::new(key))),
-                        "gemini" => executor.register_provider("gemini", Arc::new(GeminiProvider::new(key))),
-                        "codex" => executor.register_provider("codex", Arc::new(CodexProvider::new(key))),
-                        _ => {}
+        Some(Command::Auth { action: AuthAction::Status }) => {
+            if let Some(profile) = auth.profile_name() {
+                println!("Active profile: {}", profile);
+            }
+            for name in KNOWN_PROVIDERS {
+                match auth.detect_auth_source(name).await {
+                    Ok((method, source)) => {
+                        let key = match &method {
+                            AuthMethod::ApiKey { key } => mask_key(key),
+                            _ => "-".to_string(),
+                        };
+                        println!("{:<8} {:<12} {:<14} {}", name, method.kind(), key, source);
+                    }
+                    Err(_) => println!("{:<8} {:<12}", name, "none"),
+                }
+            }
+        }
+        Some(Command::Auth { action: AuthAction::Login { provider, api_key, keyring } }) => {
+            let key = match api_key {
+                Some(key) => key,
+                None => {
+                    let mut line = String::new();
+                    if std::io::stdin().read_line(&mut line).is_err() || line.trim().is_empty() {
+                        eprintln!("No API key provided on stdin.");
+                        exit(ExitCode::Usage);
+                    }
+                    line.trim().to_string()
+                }
+            };
+            let saved = CredentialStore::open_default().and_then(|mut store| {
+                if keyring {
+                    store.set_in_keyring(provider, &key)?;
+                } else {
+                    store.set(provider, key);
+                }
+                store.save()?;
+                Ok(store.path().to_path_buf())
+            });
+            match saved {
+                Ok(_) if keyring => println!("Saved {} credentials to the OS keyring", provider),
+                Ok(path) => println!("Saved {} credentials to {}", provider, path.display()),
+                Err(e) => {
+                    eprintln!("Failed to save credentials: {}", e);
+                    exit(ExitCode::Failure);
+                }
+            }
+        }
+        Some(Command::Logout { provider }) => {
+            let mut removed_any = false;
+
+            match CredentialStore::open_default() {
+                Ok(mut store) => {
+                    let in_keyring = store.in_keyring(&provider);
+                    match store.remove(&provider) {
+                        Ok(true) => {
+                            if let Err(e) = store.save() {
+                                eprintln!("Failed to update credential store: {}", e);
+                                exit(ExitCode::Failure);
+                            }
+                            if in_keyring {
+                                println!("Removed {} key from the OS keyring", provider);
+                            } else {
+                                println!("Removed {} key from {}", provider, store.path().display());
+                            }
+                            removed_any = true;
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            eprintln!("Failed to remove {} key from the OS keyring: {:#}", provider, e);
+                            exit(ExitCode::Failure);
+                        }
                     }
                 }
+                Err(e) => eprintln!("Warning: could not open credential store: {}", e),
+            }
+
+            if let Some(profile) = auth.profile_name()
+                && let Ok(path) = Config::default_path()
+            {
+                match remove_profile_api_key(&path, profile, &provider) {
+                    Ok(true) => {
+                        println!("Removed {} api_key from profile '{}' in {}", provider, profile, path.display());
+                        removed_any = true;
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Warning: could not update config: {}", e),
+                }
+            }
+
+            if !removed_any {
+                println!("No stored credentials found for {}.", provider);
+            }
+
+            // Credentials outside ai-cli's control still apply
+            for var in AuthManager::env_var_names(&provider) {
+                if std::env::var(&var).is_ok() {
+                    println!("Note: {} is still set in your environment.", var);
+                }
+            }
+            if let Ok((AuthMethod::CliAuth, source)) = auth.detect_auth_source(&provider).await {
+                println!("Note: {} remains; log out with the provider's own tool.", source);
+            }
+        }
+        Some(Command::Execute { provider, prompt, api_key, context, no_stream: _, explain_context, env, env_passthrough, retrieve, session, images, git, generation }) => {
+            executor.set_options(generation_options(&generation));
+            set_retriever(&mut executor, retrieve, &auth, &config, &cwd).await;
+            // Ensure provider is registered; for now support only claude natively
+            if !executor.has_provider(&provider)
+                && let Some(key) = api_key.clone()
+                && let Some(prov) = build_provider(&provider, AuthMethod::ApiKey { key }, &auth, &config)
+            {
+                executor.register_provider(provider, prov);
             }
 
             if !executor.has_provider(&provider) {
-                eprintln!("Provider '{}' not available. Use --api-key or configure auth.", provider);
-                std::process::exit(1);
+                eprintln!("{}", Msg::ProviderUnavailable(&provider));
+                exit(ExitCode::Auth);
             }
 
-            let mut ctx = Context::new();
-            if let Some(path) = context {
-                if let Ok(text) = std::fs::read_to_string(&path) {
-                    ctx.add_message(ai_cli::providers::Message::new(
-                        ai_cli::providers::MessageRole::System,
-                        format!("Context file {}:\n{}", path, text),
-                    ));
+            let mut ctx = match initial_context(&context, &git.sources(), &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
                 }
+            };
+            ctx.environment.extend(passthrough_env(&env_passthrough, std::env::vars()));
+            ctx.environment.extend(env.iter().cloned());
+
+            let mut session = match &session {
+                Some(name) => match session_store().and_then(|store| Ok((store.load_or_new(name)?, store))) {
+                    Ok(loaded) => Some(loaded),
+                    Err(e) => {
+                        eprintln!("{:#}", e);
+                        exit(ExitCode::for_error(&e));
+                    }
+                },
+                None => None,
+            };
+            if let Some((session, _)) = &session {
+                session.apply_to(&mut ctx);
             }
 
-            let steps = vec![PipelineStep::new(provider.clone(), prompt)];
-            match executor.execute(&steps, ctx).await {
-                Ok(responses) => {
-                    for r in responses { println!("{}", r.content); }
+            let steps = vec![PipelineStep::new(provider, prompt.clone()).with_images(images)];
+            let manifest = RunManifest::new("execute", &steps, generation_options(&generation), &config)
+                .with_inputs(&context, &env, &env_passthrough);
+            warn_unseeded(&executor, &manifest);
+            let mut run = start_run(manifest, args.quiet);
+            probe_step_capabilities(&mut executor, &steps, args.reprobe).await;
+            let result = executor.execute_with_context(&steps, ctx).await;
+            finish_run(run.as_mut(), &steps, &result);
+            report_redactions(&executor, "execute", args.quiet);
+            match result {
+                Ok((responses, final_ctx)) => {
+                    if args.show_cost {
+                        eprintln!("{}", cost_summary(&steps, &responses));
+                    }
+                    if let Some((session, store)) = session.as_mut()
+                        && let Some(response) = responses.last()
+                    {
+                        session.record(&prompt, &response.content);
+                        if let Some(prov) = executor.get_provider(&provider) {
+                            session.compact(prov.tokenizer().as_ref(), &config.session);
+                        }
+                        if let Err(e) = store.save(session) {
+                            eprintln!("Warning: failed to save session: {:#}", e);
+                        }
+                    }
+                    for r in &responses { println!("{}", render.render(&r.content)); }
+                    copy_output(&responses, flags);
+                    if explain_context {
+                        eprintln!("{}", final_ctx.explain());
+                    }
                 }
                 Err(e) => {
-                    eprintln!("Execution failed: {}", e);
-                    std::process::exit(1);
+                    eprintln!("{}", Msg::ExecutionFailed(&e));
+                    post_mortem(&e, &executor, &config.post_mortem, run.as_ref()).await;
+                    exit(ExitCode::for_error(&e));
                 }
             }
         }
-        Some(Command::Pipeline { chain, context, no_stream: _ }) => {
+        Some(Command::Pipeline { action: Some(PipelineAction::New), .. }) => {
+            let stdin = std::io::stdin();
+            let mut wizard = PipelineWizard::new(stdin.lock(), std::io::stdout());
+            let definition = match wizard.run() {
+                Ok(Some(definition)) => definition,
+                Ok(None) => {
+                    println!("{}", Msg::PipelineDiscarded);
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("Pipeline composer aborted: {}", e);
+                    exit(ExitCode::Failure);
+                }
+            };
+
+            let saved = PipelineStore::open_default().and_then(|store| store.save(&definition));
+            match saved {
+                Ok(path) => println!("{}", Msg::PipelineSaved(&definition.name, &path.display())),
+                Err(e) => {
+                    eprintln!("{}", Msg::PipelineSaveFailed(&e));
+                    exit(ExitCode::Failure);
+                }
+            }
+        }
+        Some(Command::Pipeline { action: Some(PipelineAction::Save { name, chain, description, force }), .. }) => {
+            let saved = PipelineStore::open_default().and_then(|store| {
+                if store.exists(&name) && !force {
+                    return Err(anyhow::anyhow!("Pipeline '{}' already exists; use --force to replace it", name));
+                }
+                let mut definition = PipelineDefinition::from_chain(name.clone(), &chain)?;
+                definition.description = description;
+                definition.validate()?;
+                store.save(&definition)
+            });
+            match saved {
+                Ok(path) => println!("{}", Msg::PipelineSaved(&name, &path.display())),
+                Err(e) => {
+                    eprintln!("{}", Msg::PipelineSaveFailed(&e));
+                    exit(ExitCode::Pipeline);
+                }
+            }
+        }
+        Some(Command::Pipeline { action: Some(PipelineAction::List), .. }) => {
+            let listed = PipelineStore::open_default().and_then(|store| {
+                store.list()?.into_iter().map(|name| store.load(&name)).collect::<anyhow::Result<Vec<_>>>()
+            });
+            match listed {
+                Ok(definitions) if definitions.is_empty() => println!("{}", Msg::NoSavedPipelines),
+                Ok(definitions) => {
+                    for definition in definitions {
+                        println!("{:<16} {}", definition.name, definition.to_chain());
+                        if let Some(description) = &definition.description {
+                            println!("{:<16} {}", "", description);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to list pipelines: {}", e);
+                    exit(ExitCode::Failure);
+                }
+            }
+        }
+        Some(Command::Pipeline { chain, chain_file, context, no_stream: _, explain_context, env, env_passthrough, retrieve, input_file, jobs, rate_limit, metrics_addr, tui, confirm_each_step, edit_before_next, artifacts_dir, out, sandbox, git, generation, action: None }) => {
+            executor.set_options(generation_options(&generation));
+            set_retriever(&mut executor, retrieve, &auth, &config, &cwd).await;
             // Parse pipeline chain
-            let steps = match PipelineParser::parse(&chain) {
+            let parsed = match (chain_file, chain.or_else(|| config.default_chain.clone())) {
+                (Some(path), _) => PipelineParser::parse_file(&path)
+                    .and_then(|steps| PipelineParser::validate_providers(&steps, KNOWN_PROVIDERS).map(|_| steps)),
+                (None, Some(chain)) => PipelineParser::parse(&chain),
+                (None, None) => {
+                    eprintln!("{}", Msg::NoChain);
+                    exit(ExitCode::Usage);
+                }
+            };
+            let steps = match parsed {
                 Ok(s) => s,
                 Err(e) => {
-                    eprintln!("Invalid chain: {}", e);
-                    std::process::exit(1);
+                    eprintln!("{}", Msg::InvalidChain(&render_error(&e, std::io::stderr().is_terminal())));
+                    exit(ExitCode::Pipeline);
                 }
             };
+            set_step_gate(&mut executor, &steps, confirm_each_step, edit_before_next);
+            set_artifacts_dir(&mut executor, artifacts_dir);
 
-            // Validate against currently registered providers
-            let names = executor.get_provider_names();
-            let name_refs: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
-            if let Err(e) = PipelineParser::validate_providers(&steps, &name_refs) {
-                eprintln!("{}", e);
-                eprintln!("Tip: provide API keys or login for missing providers.");
-                std::process::exit(1);
+            let mut ctx = match initial_context(&context, &git.sources(), &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
+            ctx.environment.extend(passthrough_env(&env_passthrough, std::env::vars()));
+            ctx.environment.extend(env.iter().cloned());
+            let manifest = RunManifest::new("pipeline", &steps, generation_options(&generation), &config)
+                .with_inputs(&context, &env, &env_passthrough)
+                .with_outputs(out, sandbox);
+            match input_file {
+                Some(path) => run_batch(&mut executor, &steps, ctx, &path, BatchSettings { jobs: jobs.into(), rate_limit, metrics_addr }, flags).await,
+                None if tui => run_tui(&mut executor, &config, &steps, ctx, manifest, flags).await,
+                None => run_pipeline(&mut executor, &config, &steps, ctx, manifest, explain_context, flags).await,
+            }
+        }
+        Some(Command::Run { name, context, no_stream: _, explain_context, env, env_passthrough, retrieve, confirm_each_step, edit_before_next, artifacts_dir, out, sandbox, git, generation }) => {
+            executor.set_options(generation_options(&generation));
+            set_retriever(&mut executor, retrieve, &auth, &config, &cwd).await;
+            // Tools work in the package with --package, else the project root
+            let tools_root = package.as_ref().map(|p| p.root.as_path()).or(config.project_root()).unwrap_or(&cwd);
+            let steps = match PipelineStore::open_default().and_then(|store| store.load(&name)?.to_steps_in(tools_root)) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    eprintln!("{}", Msg::SavedPipelinesTip);
+                    exit(ExitCode::Pipeline);
+                }
+            };
+            set_step_gate(&mut executor, &steps, confirm_each_step, edit_before_next);
+            set_artifacts_dir(&mut executor, artifacts_dir);
+
+            let mut ctx = match initial_context(&context, &git.sources(), &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
+            ctx.environment.extend(passthrough_env(&env_passthrough, std::env::vars()));
+            ctx.environment.extend(env.iter().cloned());
+            let manifest = RunManifest::new("pipeline", &steps, generation_options(&generation), &config)
+                .with_inputs(&context, &env, &env_passthrough)
+                .with_outputs(out, sandbox);
+            run_pipeline(&mut executor, &config, &steps, ctx, manifest, explain_context, flags).await;
+        }
+        Some(Command::Watch { chain, paths, context, env, debounce, generation }) => {
+            executor.set_options(generation_options(&generation));
+            let Some(chain) = chain.or_else(|| config.default_chain.clone()) else {
+                eprintln!("{}", Msg::NoChain);
+                exit(ExitCode::Usage);
+            };
+            let steps = match PipelineParser::parse(&chain) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("{}", Msg::InvalidChain(&render_error(&e, std::io::stderr().is_terminal())));
+                    exit(ExitCode::Pipeline);
+                }
+            };
+            validate_pipeline(&executor, &steps).await;
+            let watcher = ContextLoader::new(config.project_root().unwrap_or(&cwd))
+                .and_then(|loader| loader.with_ignore_settings(&config.ignore))
+                .and_then(|loader| FileWatcher::new(loader, &cwd, paths.clone()));
+            let watcher = match watcher {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Usage);
+                }
+            };
+            let watch = WatchRun { steps: &steps, context: &context, env: &env, config: &config, cwd: &cwd, package: package.as_ref() };
+            watch_pipeline(&mut executor, watcher, &paths, watch, std::time::Duration::from_millis(debounce), flags).await;
+        }
+        Some(Command::CommitMsg { chain, write, install_hook, force, generation }) => {
+            executor.set_options(generation_options(&generation));
+            if let Err(e) = commit_msg_command(&mut executor, &config, &cwd, chain, write, install_hook, force).await {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Review { pr, chain, format, output, generation }) => {
+            executor.set_options(generation_options(&generation));
+            if let Err(e) = review_command(&executor, &config, &cwd, &pr, chain, format, output.as_deref()).await {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
             }
+        }
+        Some(Command::Consensus { prompt, providers, arbiter, context, json, generation }) => {
+            executor.set_options(generation_options(&generation));
+            let asked: Vec<PipelineStep> = providers.iter().chain(&arbiter).map(|p| PipelineStep::new(*p, "")).collect();
+            validate_step_providers(&executor, &asked);
+            let ctx = match initial_context(&context, &[], &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
+            let providers: Vec<String> = providers.into_iter().map(String::from).collect();
+            let result = executor.consensus(&prompt, &providers, arbiter.as_deref(), ctx).await;
+            report_redactions(&executor, "consensus", flags.quiet);
+            match result {
+                Ok(report) if json => match serde_json::to_string_pretty(&report) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(ExitCode::Failure);
+                    }
+                },
+                Ok(report) => print!("{}", report),
+                Err(e) => {
+                    eprintln!("{}", Msg::ConsensusFailed(&format!("{:#}", e)));
+                    exit(ExitCode::for_error(&e));
+                }
+            }
+        }
+        Some(Command::Compare { prompt, prompts, providers, provider, view, context, json, generation }) => {
+            executor.set_options(generation_options(&generation));
+            let prompts: Vec<String> = prompt.into_iter().chain(prompts).collect();
+            let sides: Vec<PipelineStep> = prompts
+                .iter()
+                .flat_map(|prompt| providers.iter().chain(&provider).map(move |p| PipelineStep::new(*p, prompt.clone())))
+                .collect();
+            if sides.len() < 2 {
+                eprintln!("compare needs two providers (--providers) or two prompts (--prompts)");
+                exit(ExitCode::Usage);
+            }
+            validate_step_providers(&executor, &sides);
+            let ctx = match initial_context(&context, &[], &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
+            let result = executor.compare(&sides, ctx).await;
+            report_redactions(&executor, "compare", flags.quiet);
+            match result {
+                Ok(report) if json => match serde_json::to_string_pretty(&report) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(ExitCode::Failure);
+                    }
+                },
+                Ok(report) => match view {
+                    CompareView::Columns => print!("{}", report.columns(usize::from(termimad::terminal_size().0))),
+                    CompareView::Diff => print!("{}", report.diff()),
+                },
+                Err(e) => {
+                    eprintln!("{}", Msg::ExecutionFailed(&format!("{:#}", e)));
+                    exit(ExitCode::for_error(&e));
+                }
+            }
+        }
+        Some(Command::Eval { cases, chain, providers, metric, threshold, judge, jobs, context, json, generation }) => {
+            executor.set_options(generation_options(&generation));
+            let Some(chain) = chain.or_else(|| config.default_chain.clone()) else {
+                eprintln!("{}", Msg::NoChain);
+                exit(ExitCode::Usage);
+            };
+            let steps = match PipelineParser::parse(&chain) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("{}", Msg::InvalidChain(&render_error(&e, std::io::stderr().is_terminal())));
+                    exit(ExitCode::Pipeline);
+                }
+            };
+            let variants: Vec<Variant> = if providers.is_empty() {
+                vec![Variant::chain(steps)]
+            } else {
+                providers.iter().map(|p| Variant::with_provider(&steps, p)).collect()
+            };
+            for variant in &variants {
+                validate_step_providers(&executor, &variant.steps);
+            }
+            validate_step_providers(&executor, &judge.iter().map(|j| PipelineStep::new(*j, "")).collect::<Vec<_>>());
+            let cases = match EvalCase::read_file(&cases) {
+                Ok(cases) => cases,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Usage);
+                }
+            };
+            let ctx = match initial_context(&context, &[], &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
 
-            let mut ctx = Context::new();
-            if let Some(path) = context {
-                if let Ok(text) = std::fs::read_to_string(&path) {
-                    ctx.add_message(ai_cli::providers::Message::new(
-                        ai_cli::providers::MessageRole::System,
-                        format!("Context file {}:\n{}", path, text),
-                    ));
+            let mut evaluator = Evaluator::new(&executor, jobs.into()).with_metric(metric, threshold);
+            if let Some(judge) = judge {
+                evaluator = evaluator.with_judge(judge);
+            }
+            let report = match evaluator.run(&variants, &ctx, &cases).await {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Usage);
+                }
+            };
+            report_redactions(&executor, "eval", flags.quiet);
+            if json {
+                match serde_json::to_string_pretty(&report) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(ExitCode::Failure);
+                    }
+                }
+            } else {
+                print!("{}", report);
+            }
+            if !report.all_passed() {
+                exit(ExitCode::Failure);
+            }
+        }
+        Some(Command::Serve { port, host, token, jobs }) => {
+            let mut state = ServerState::new(executor).with_job_limits(config.rate_limits.clone(), jobs.into());
+            if let Some(token) = token {
+                state = state.with_token(token);
+            }
+            match PipelineStore::open_default() {
+                Ok(store) => state = state.with_store(store),
+                Err(e) => eprintln!("Warning: saved pipelines unavailable: {}", e),
+            }
+            let addr = std::net::SocketAddr::new(host, port);
+            if !args.quiet {
+                eprintln!("{}", Msg::Listening(&addr));
+            }
+            let served = server::serve(addr, state).await;
+            telemetry::flush().await;
+            if let Err(e) = served {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Failure);
+            }
+        }
+        Some(Command::History { action: HistoryAction::List }) => {
+            let records = match run_store().and_then(|store| store.list()) {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("Failed to read run history: {}", e);
+                    exit(ExitCode::Failure);
+                }
+            };
+            if records.is_empty() {
+                println!("{}", Msg::NoRuns);
+            }
+            for record in records {
+                let status = match record.status {
+                    RunStatus::Running => "running",
+                    RunStatus::Succeeded => "ok",
+                    RunStatus::Failed => "failed",
+                };
+                let cost = record.cost_usd.map(|c| format!("${:.4}", c)).unwrap_or_default();
+                println!("{}  {:<8} {:<7} {:>9}  {}", record.id, record.command, status, cost, record.chain);
+            }
+        }
+        Some(Command::History { action: HistoryAction::Open { id } }) => {
+            let dir = match run_store().and_then(|store| store.find(&id)) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(ExitCode::Failure);
+                }
+            };
+            println!("{}", dir.display());
+            reveal(&dir);
+        }
+        Some(Command::Rerun { id }) => {
+            let (run_id, recorded) = match run_store().and_then(|store| {
+                let dir = store.find(&id)?;
+                let run_id = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or(id);
+                Ok((run_id, RunManifest::load_with(&dir, store.encryption())?))
+            }) {
+                Ok(found) => found,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Failure);
                 }
+            };
+            for note in recorded.drift(&config, |name| executor.get_provider(name).and_then(|p| p.model().map(str::to_string))) {
+                eprintln!("{}", Msg::Warning(&note));
             }
+            let steps = match PipelineParser::parse(&recorded.chain) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("{}", render_error(&e, std::io::stderr().is_terminal()));
+                    exit(ExitCode::Usage);
+                }
+            };
+            executor.set_options(recorded.options.clone());
 
-            match executor.execute(&steps, ctx).await {
-                Ok(responses) => {
-                    for (i, r) in responses.iter().enumerate() {
-                        println!("[{}] {}", i + 1, r.content);
+            let mut ctx = match initial_context(&recorded.context, &[], &config, &cwd, package.as_ref()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::for_error(&e));
+                }
+            };
+            let env_passthrough: Vec<glob::Pattern> = recorded.env_passthrough.iter().filter_map(|p| glob::Pattern::new(p).ok()).collect();
+            let env: Vec<(String, String)> = recorded.env.clone().into_iter().collect();
+            ctx.environment.extend(passthrough_env(&env_passthrough, std::env::vars()));
+            ctx.environment.extend(env.iter().cloned());
+            let mut manifest = RunManifest::new(&recorded.command, &steps, recorded.options.clone(), &config)
+                .with_inputs(&recorded.context, &env, &env_passthrough)
+                .with_outputs(recorded.out.clone(), recorded.sandbox);
+            manifest.rerun_of = Some(run_id);
+            run_pipeline(&mut executor, &config, &steps, ctx, manifest, false, flags).await;
+        }
+        Some(Command::Apply { id, diff, force }) => {
+            let root = config.project_root().unwrap_or(&cwd);
+            let applied = run_store().and_then(|store| store.find(&id)).and_then(|dir| {
+                let sandbox = Sandbox::open_with(dir, storage_encryption())?;
+                if diff {
+                    sandbox.changes(root)
+                } else {
+                    sandbox.apply(root, force)
+                }
+            });
+            match applied {
+                Ok(changes) if changes.is_empty() => eprintln!("Nothing to apply: the project already matches the sandbox."),
+                Ok(changes) if diff => {
+                    for change in &changes {
+                        print!("{}", change.patch);
+                    }
+                }
+                Ok(changes) => {
+                    print!("{}", sandbox::diffstat(&changes));
+                    if !args.quiet {
+                        eprintln!("Applied {} file(s) to {}", changes.len(), root.display());
                     }
                 }
                 Err(e) => {
-                    eprintln!("Pipeline failed: {}", e);
-                    std::process::exit(1);
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Failure);
                 }
             }
         }
+        Some(Command::Stats { since, until, by, json }) => {
+            let report = TimeRange::parse(since.as_deref(), until.as_deref(), unix_now()).and_then(|range| {
+                let records = run_store()?.list()?;
+                Ok(StatsReport::aggregate(&records, range, by))
+            });
+            match report {
+                Ok(report) if json => match serde_json::to_string_pretty(&report) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(ExitCode::Failure);
+                    }
+                },
+                Ok(report) => println!("{}", report),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    exit(ExitCode::Usage);
+                }
+            }
+        }
+        Some(Command::Batch { action }) => {
+            if let Err(e) = batch_command(action, &executor, &auth, &config, &cwd, package.as_ref()).await {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Index { paths, embedder, chunk_lines, rebuild }) => {
+            let indexed = match build_embedder(&embedder, &auth).await {
+                Ok(embedder) => index_command(&paths, embedder.as_ref(), chunk_lines, rebuild, &config, &cwd, executor.redactor().map(Arc::as_ref)).await,
+                Err(e) => Err(e),
+            };
+            report_redactions(&executor, "index", args.quiet);
+            if let Err(e) = indexed {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Embed { file, embedder, lines, format }) => {
+            if let Err(e) = embed_command(file.as_deref(), &embedder, lines, format, &auth).await {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Session { action }) => {
+            if let Err(e) = session_command(action) {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Audit { action }) => {
+            if let Err(e) = audit_command(action, &config) {
+                eprintln!("{:#}", e);
+                exit(ExitCode::for_error(&e));
+            }
+        }
+        Some(Command::Completions { shell, static_script }) => {
+            if let Err(e) = completion::write_script(shell, !static_script, &mut std::io::stdout()) {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Usage);
+            }
+        }
         None => {
             // clap will show help by default due to arg_required_else_help
         }
     }
+    telemetry::flush().await;
+}
+
+/// Exit the process with one of the documented exit codes
+fn exit(code: ExitCode) -> ! {
+    std::process::exit(code.code())
+}
+
+/// Run a `batch` subcommand
+async fn batch_command(action: BatchAction, executor: &PipelineExecutor, auth: &AuthManager, config: &Config, cwd: &Path, package: Option<&Package>) -> anyhow::Result<()> {
+    let mut store = BatchJobStore::open_default()?;
+    if let Some(encryption) = storage_encryption() {
+        store = store.with_encryption(encryption);
+    }
+    match action {
+        BatchAction::Submit { provider, prompt, input_file, model, context, generation } => {
+            let inputs = BatchInput::read_file(&input_file)?;
+            let ctx = initial_context(&context, &[], config, cwd, package)?;
+            let options = ProviderOptions { model, ..generation_options(&generation) };
+            let api = build_batch_api(&provider, auth, config).await?;
+            let job = BatchJob::submit_with(executor, api.as_ref(), &prompt, &ctx, inputs, &options).await;
+            report_redactions(executor, "batch submit", false);
+            let job = job?;
+            store.save(&job)?;
+            eprintln!("Submitted {} inputs to {}; check with `ai-cli batch status {}`", job.inputs.len(), job.provider, job.id);
+            println!("{}", job.id);
+        }
+        BatchAction::Status { id: None } => {
+            let jobs = store.list()?;
+            if jobs.is_empty() {
+                println!("No batches submitted yet.");
+            }
+            for job in jobs {
+                println!("{:<40} {:<8} {:>6} inputs", job.id, job.provider, job.inputs.len());
+            }
+        }
+        BatchAction::Status { id: Some(id) } => {
+            let job = store.load(&id)?;
+            let status = build_batch_api(&job.provider, auth, config).await?.status(&job.id).await?;
+            println!("{} ({}): {}, {} of {} succeeded, {} failed", status.id, job.provider, status.state, status.succeeded, status.total, status.failed);
+        }
+        BatchAction::Fetch { id, output, wait, poll_interval } => {
+            let job = store.load(&id)?;
+            let api = build_batch_api(&job.provider, auth, config).await?;
+            let status = loop {
+                let status = api.status(&job.id).await?;
+                if status.state.is_done() || !wait {
+                    break status;
+                }
+                eprintln!("{}: {} of {} done", job.id, status.succeeded + status.failed, status.total);
+                tokio::time::sleep(std::time::Duration::from_secs(poll_interval)).await;
+            };
+            if !status.state.is_done() {
+                return Err(anyhow::anyhow!("Batch {} is still {}; pass --wait to poll until it finishes", job.id, status.state));
+            }
+            let results = job.results_with(executor, api.results(&job.id).await?);
+            let mut lines = String::new();
+            for result in &results {
+                lines.push_str(&serde_json::to_string(result)?);
+                lines.push('\n');
+            }
+            match output {
+                Some(path) => std::fs::write(&path, lines).with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{}", lines),
+            }
+            let failed = results.iter().filter(|result| !result.ok).count();
+            if failed > 0 {
+                eprintln!("{} of {} inputs failed", failed, results.len());
+                exit(ExitCode::Failure);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Construct the batch API of `claude` or `openai`; batches need API keys, not CLI sessions
+async fn build_batch_api(name: &str, auth: &AuthManager, config: &Config) -> anyhow::Result<Arc<dyn BatchApi>> {
+    let model = config.provider_preferences(name)
+        .and_then(|p| p.model.clone())
+        .or_else(|| auth.provider_settings(name).and_then(|s| s.model.clone()));
+    match name {
+        "claude" => {
+            let method = auth.detect_auth("claude").await?;
+            let mut api = match method {
+                AuthMethod::ApiKey { .. } | AuthMethod::AccountBased { .. } => {
+                    ClaudeProvider::from_credentials(ManagedCredentials::new(name, method, auth.refresher(name)))
+                }
+                _ => return Err(anyhow::anyhow!("Anthropic batches need an API key; a CLI session cannot submit them")),
+            };
+            if let Some(model) = model { api = api.with_model(model); }
+            if let Some(base_url) = auth.provider_settings(name).and_then(|s| s.base_url.clone()) { api = api.with_base_url(base_url); }
+            Ok(Arc::new(api))
+        }
+        "openai" => {
+            let key = match std::env::var("OPENAI_API_KEY") {
+                Ok(key) => key,
+                Err(_) => match auth.detect_auth("codex").await {
+                    Ok(AuthMethod::ApiKey { key }) => key,
+                    _ => return Err(anyhow::anyhow!("openai batches need OPENAI_API_KEY or a codex API key")),
+                },
+            };
+            let mut api = OpenAIBatch::new(key);
+            if let Some(model) = model { api = api.with_model(model); }
+            Ok(Arc::new(api))
+        }
+        other => Err(anyhow::anyhow!("No batch API for '{}'; use 'claude' or 'openai'", other)),
+    }
+}
+
+/// Run a `session` subcommand
+fn audit_command(action: AuditAction, config: &Config) -> anyhow::Result<()> {
+    let AuditAction::Show { since, until, provider, limit, json } = action;
+    let range = TimeRange::parse(since.as_deref(), until.as_deref(), unix_now())?;
+    let log = AuditLog::from_settings(&config.audit)?;
+    let entries = log.query(range, provider.as_deref(), limit)?;
+    if json {
+        for entry in &entries {
+            println!("{}", serde_json::to_string(entry)?);
+        }
+    } else if entries.is_empty() {
+        if !config.audit.enabled {
+            eprintln!("The audit log is off; set `[audit] enabled = true` to record provider calls.");
+        }
+        println!("No audited calls in {}", log.path().display());
+    } else {
+        print!("{}", audit::format_table(&entries));
+    }
+    Ok(())
+}
+
+/// `[encryption]` settings of the loaded config
+static ENCRYPTION: OnceLock<EncryptionSettings> = OnceLock::new();
+
+/// Key for sessions and run history, derived on first use so other commands need no passphrase
+fn storage_encryption() -> Option<Arc<Encryption>> {
+    static KEY: OnceLock<Option<Arc<Encryption>>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        let settings = ENCRYPTION.get().filter(|settings| settings.enabled)?;
+        match Encryption::from_settings(settings) {
+            Ok(encryption) => Some(Arc::new(encryption)),
+            // Writing history in the clear when encryption was asked for is not an option
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Usage);
+            }
+        }
+    })
+    .clone()
+}
+
+fn run_store() -> anyhow::Result<RunStore> {
+    let store = RunStore::open_default()?;
+    Ok(match storage_encryption() {
+        Some(encryption) => store.with_encryption(encryption),
+        None => store,
+    })
+}
+
+fn session_store() -> anyhow::Result<SessionStore> {
+    let store = SessionStore::open_default()?;
+    Ok(match storage_encryption() {
+        Some(encryption) => store.with_encryption(encryption),
+        None => store,
+    })
+}
+
+fn session_command(action: SessionAction) -> anyhow::Result<()> {
+    let store = session_store()?;
+    match action {
+        SessionAction::List => {
+            let sessions = store.list()?;
+            if sessions.is_empty() {
+                println!("No sessions saved yet.");
+            }
+            for session in sessions {
+                let compacted = if session.summary.is_empty() { "" } else { "  (compacted)" };
+                println!("{:<24} {:>4} messages{}", session.name, session.messages.len(), compacted);
+            }
+        }
+        SessionAction::Show { name } => {
+            let session = store.load(&name)?;
+            let mut ctx = Context::new();
+            session.apply_to(&mut ctx);
+            for message in &ctx.conversation_history {
+                let role = match message.role {
+                    MessageRole::System => "system",
+                    MessageRole::User => "user",
+                    MessageRole::Assistant => "assistant",
+                };
+                println!("[{}]\n{}\n", role, message.content);
+            }
+        }
+        SessionAction::Delete { name } => {
+            store.delete(&name)?;
+            println!("Deleted session '{}'", name);
+        }
+        SessionAction::Clear { name } => {
+            let mut session = store.load(&name)?;
+            session.clear();
+            store.save(&session)?;
+            println!("Cleared session '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+/// Embed the project's files into its index, reusing vectors of unchanged files
+async fn index_command(
+    paths: &[String],
+    embedder: &dyn Embedder,
+    chunk_lines: usize,
+    rebuild: bool,
+    config: &Config,
+    cwd: &Path,
+    redactor: Option<&Redactor>,
+) -> anyhow::Result<()> {
+    let root = config.project_root().unwrap_or(cwd);
+    // Files are embedded in chunks, so only the per-file limit applies
+    let limits = ContextLimits { max_file_bytes: config.context_limits.max_file_bytes, max_total_bytes: u64::MAX };
+    let mut loader = ContextLoader::new(root)?.with_ignore_settings(&config.ignore)?.with_limits(limits);
+    let mut loaded = Context::new();
+    let specs = if paths.is_empty() { vec![".".to_string()] } else { paths.to_vec() };
+    for spec in &specs {
+        let files = loader.expand(spec, cwd)?;
+        for (file, reason) in loader.load_into(&mut loaded, files, Provenance::cli_flag("index", spec))? {
+            eprintln!("Warning: skipped {}: {}", file.display(), reason);
+        }
+    }
+
+    let path = VectorIndex::default_path(root)?;
+    let mut index = match VectorIndex::load(&path) {
+        Ok(index) if !rebuild && index.embedder == embedder.name() && index.chunk_lines == chunk_lines => index,
+        _ => VectorIndex::new(root, embedder.name()).with_chunk_lines(chunk_lines),
+    };
+    let files = loaded.file_contents.into_iter().collect();
+    // Embedding APIs see the file text, so secrets are redacted first
+    let stats = index.update_redacted(files, embedder, redactor).await?;
+    index.save(&path)?;
+    println!(
+        "Indexed {} file(s) into {} chunk(s) with '{}': {} embedded, {} unchanged, {} removed",
+        index.files.len(),
+        index.chunk_count(),
+        index.embedder,
+        stats.embedded,
+        stats.reused,
+        stats.removed,
+    );
+    println!("Index: {}", path.display());
+    Ok(())
+}
+
+/// Construct an embedder by name
+async fn build_embedder(name: &str, auth: &AuthManager) -> anyhow::Result<Arc<dyn Embedder>> {
+    match name {
+        "local" => Ok(Arc::new(HashEmbedder::default())),
+        "gemini" => {
+            let method = auth.detect_auth("gemini").await?;
+            build_gemini(method, auth)
+                .map(|prov| Arc::new(prov) as Arc<dyn Embedder>)
+                .ok_or_else(|| anyhow::anyhow!("gemini embeddings need an API key or Google credentials"))
+        }
+        "openai" => {
+            let key = match std::env::var("OPENAI_API_KEY") {
+                Ok(key) => key,
+                Err(_) => match auth.detect_auth("codex").await {
+                    Ok(AuthMethod::ApiKey { key }) => key,
+                    _ => return Err(anyhow::anyhow!("openai embeddings need OPENAI_API_KEY or a codex API key")),
+                },
+            };
+            Ok(Arc::new(OpenAIEmbedder::new(key)))
+        }
+        other => Err(anyhow::anyhow!("Unknown embedder '{}'; use 'local', 'gemini' or 'openai'", other)),
+    }
+}
+
+/// Embed a file or stdin and write the vectors to stdout
+async fn embed_command(file: Option<&Path>, embedder: &str, lines: bool, format: EmbedFormat, auth: &AuthManager) -> anyhow::Result<()> {
+    let text = match file.filter(|path| *path != Path::new("-")) {
+        Some(path) => std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?,
+        None => std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?,
+    };
+    let inputs = embed::split_inputs(&text, lines);
+    let embedder = build_embedder(embedder, auth).await?;
+    let embeddings = Embeddings::compute(embedder.as_ref(), &inputs).await?;
+    embeddings.write(format, &mut std::io::stdout().lock())
+}
+
+/// Inject retrieved index chunks into every step when `--retrieve K` is given; exits on failure
+async fn set_retriever(executor: &mut PipelineExecutor, top_k: Option<usize>, auth: &AuthManager, config: &Config, cwd: &Path) {
+    let Some(top_k) = top_k else { return };
+    let root = config.project_root().unwrap_or(cwd);
+    let retriever = async {
+        let index = VectorIndex::load(&VectorIndex::default_path(root)?)?;
+        let embedder = build_embedder(&index.embedder, auth).await?;
+        Retriever::new(index, embedder, top_k)
+    };
+    match retriever.await {
+        Ok(retriever) => executor.set_retriever(Arc::new(retriever)),
+        Err(e) => {
+            eprintln!("{:#}", e);
+            exit(ExitCode::for_error(&e));
+        }
+    }
+}
+
+/// Global flags that affect how runs report
+#[derive(Clone, Copy)]
+struct RunFlags {
+    quiet: bool,
+    reprobe: bool,
+    show_cost: bool,
+    render: RenderMode,
+    copy: Option<Option<usize>>,
+}
+
+/// Put the selected step's output on the clipboard if `--copy` was given; failures only warn
+fn copy_output(responses: &[Response], flags: RunFlags) {
+    let Some(step) = flags.copy else { return };
+    match clipboard::pick(responses, step).and_then(clipboard::copy) {
+        Ok(()) if !flags.quiet => eprintln!("Copied output to the clipboard."),
+        Ok(()) => {}
+        Err(e) => eprintln!("{}", Msg::Warning(&format!("{:#}", e))),
+    }
+}
+
+/// Review step outputs between steps: interactively, or by opening each in $EDITOR
+fn set_step_gate(executor: &mut PipelineExecutor, steps: &[PipelineStep], confirm_each_step: bool, edit_before_next: bool) {
+    if confirm_each_step {
+        executor.set_gate(Arc::new(TerminalGate));
+    } else if edit_before_next {
+        executor.set_gate(Arc::new(EditorGate::new(steps.len())));
+    }
+}
+
+/// Write step artifacts to `dir` if `--artifacts-dir` was given; exits if it cannot be created
+fn set_artifacts_dir(executor: &mut PipelineExecutor, dir: Option<PathBuf>) {
+    let Some(dir) = dir else { return };
+    match ArtifactsDir::create(dir) {
+        Ok(artifacts) => executor.set_artifacts_dir(artifacts),
+        Err(e) => {
+            eprintln!("{:#}", e);
+            exit(ExitCode::Usage);
+        }
+    }
+}
+
+/// Send the run's file writes to its sandbox; exits when there is no run directory to hold it
+fn start_sandbox(executor: &mut PipelineExecutor, run: Option<&RunArtifacts>) {
+    let Some(run) = run else {
+        eprintln!("--sandbox needs the run history to hold the files; not running");
+        exit(ExitCode::Failure);
+    };
+    match Sandbox::create_with(run.dir(), storage_encryption()) {
+        Ok(sandbox) => {
+            let root = executor.file_writer().root().to_path_buf();
+            executor.set_file_writer(FileWriter::sandboxed(root, sandbox));
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            exit(ExitCode::Failure);
+        }
+    }
+}
+
+/// Write `--out`, then summarize what a sandboxed run would change; exits if the write fails
+fn write_outputs(executor: &PipelineExecutor, run: Option<&RunArtifacts>, out: Option<&Path>, responses: &[Response], quiet: bool) {
+    let writer = executor.file_writer();
+    if let Some(out) = out {
+        let content = responses.last().map(|r| r.content.as_str()).unwrap_or_default();
+        match writer.write(out, content) {
+            Ok(path) if !quiet => eprintln!("Wrote {}", path.display()),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(ExitCode::Failure);
+            }
+        }
+    }
+    let (Some(sandbox), Some(run)) = (writer.sandbox(), run) else { return };
+    match sandbox.changes(writer.root()) {
+        Ok(changes) if changes.is_empty() => eprintln!("Sandbox: no changes to the project."),
+        Ok(changes) => {
+            eprint!("Sandbox changes:\n{}", sandbox::diffstat(&changes));
+            eprintln!("Inspect with `ai-cli apply {0} --diff`, apply with `ai-cli apply {0}`", run.id());
+        }
+        Err(e) => eprintln!("{}", Msg::Warning(&format!("{:#}", e))),
+    }
+}
+
+/// Validate providers, execute the steps and print numbered results; exits on failure
+async fn run_pipeline(
+    executor: &mut PipelineExecutor,
+    config: &Config,
+    steps: &[PipelineStep],
+    ctx: Context,
+    manifest: RunManifest,
+    explain_context: bool,
+    flags: RunFlags,
+) {
+    validate_pipeline(executor, steps).await;
+    warn_unseeded(executor, &manifest);
+
+    let chain = manifest.chain.clone();
+    let (out, sandbox) = (manifest.out.clone(), manifest.sandbox);
+    let started = std::time::Instant::now();
+    let mut run = start_run(manifest, flags.quiet);
+    if sandbox {
+        start_sandbox(executor, run.as_ref());
+    }
+    probe_step_capabilities(executor, steps, flags.reprobe).await;
+    let result = executor.execute_with_context(steps, ctx).await;
+    report_redactions(executor, "pipeline", flags.quiet);
+    finish_run(run.as_mut(), steps, &result);
+    fire_hooks(&config.hooks, "pipeline", &chain, run.as_ref(), steps, started, &result).await;
+    match result {
+        Ok((responses, final_ctx)) => {
+            for (i, r) in responses.iter().enumerate() {
+                println!("[{}] {}", i + 1, flags.render.render(&r.content));
+            }
+            copy_output(&responses, flags);
+            write_outputs(executor, run.as_ref(), out.as_deref(), &responses, flags.quiet);
+            if flags.show_cost {
+                eprintln!("{}", cost_summary(steps, &responses));
+            }
+            if explain_context {
+                eprintln!("{}", final_ctx.explain());
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", Msg::PipelineFailed(&e));
+            post_mortem(&e, executor, &config.post_mortem, run.as_ref()).await;
+            exit(ExitCode::for_error(&e));
+        }
+    }
+}
+
+/// Run the pipeline under the terminal UI, then print the responses; exits on failure
+async fn run_tui(executor: &mut PipelineExecutor, config: &Config, steps: &[PipelineStep], ctx: Context, manifest: RunManifest, flags: RunFlags) {
+    validate_pipeline(executor, steps).await;
+    warn_unseeded(executor, &manifest);
+    let chain = manifest.chain.clone();
+    let (out, sandbox) = (manifest.out.clone(), manifest.sandbox);
+    let started = std::time::Instant::now();
+    let mut run = start_run(manifest, flags.quiet);
+    if sandbox {
+        start_sandbox(executor, run.as_ref());
+    }
+    probe_step_capabilities(executor, steps, flags.reprobe).await;
+    let result = tui::run(executor, steps, ctx).await;
+    report_redactions(executor, "pipeline", flags.quiet);
+    finish_run(run.as_mut(), steps, &result);
+    fire_hooks(&config.hooks, "pipeline", &chain, run.as_ref(), steps, started, &result).await;
+    match result {
+        Ok((responses, _)) => {
+            for (i, r) in responses.iter().enumerate() {
+                println!("[{}] {}", i + 1, flags.render.render(&r.content));
+            }
+            copy_output(&responses, flags);
+            write_outputs(executor, run.as_ref(), out.as_deref(), &responses, flags.quiet);
+            if flags.show_cost {
+                eprintln!("{}", cost_summary(steps, &responses));
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", Msg::PipelineFailed(&e));
+            post_mortem(&e, executor, &config.post_mortem, run.as_ref()).await;
+            exit(ExitCode::for_error(&e));
+        }
+    }
+}
+
+/// `commit-msg`: install the hook, or write a message for the staged diff
+async fn commit_msg_command(
+    executor: &mut PipelineExecutor,
+    config: &Config,
+    cwd: &Path,
+    chain: Option<String>,
+    write: Option<Option<PathBuf>>,
+    install_hook: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    let repo = repo_root(cwd)?;
+    if install_hook {
+        let path = commit_msg::install_hook(&git_path(&repo, "hooks")?, force)?;
+        println!("Installed {}", path.display());
+        return Ok(());
+    }
+
+    let diffs = collect_diff(&repo, &DiffSource::Staged)?;
+    if diffs.is_empty() {
+        return Err(anyhow::anyhow!("No staged changes; stage files with `git add` first"));
+    }
+    let chain = chain
+        .or_else(|| config.commit_msg.chain.clone())
+        .unwrap_or_else(|| commit_msg::DEFAULT_CHAIN.to_string());
+    let steps = PipelineParser::parse(&chain)?;
+    validate_pipeline(executor, &steps).await;
+    let mut ctx = Context::new();
+    add_diffs_to_context(&mut ctx, diffs, &DiffSource::Staged);
+
+    let (responses, _) = executor.execute_with_context(&steps, ctx).await?;
+    let reply = responses.last().map(|r| r.content.as_str()).unwrap_or_default();
+    let message = commit_msg::clean_message(reply);
+    if message.is_empty() {
+        return Err(anyhow::anyhow!("The pipeline returned an empty commit message"));
+    }
+    if !commit_msg::is_conventional(&message) {
+        eprintln!("Warning: the subject line is not a Conventional Commits subject");
+    }
+    match write {
+        Some(path) => {
+            let path = match path {
+                Some(path) => path,
+                None => git_path(&repo, "COMMIT_EDITMSG")?,
+            };
+            commit_msg::write_message(&path, &message)
+        }
+        None => {
+            println!("{}", message);
+            Ok(())
+        }
+    }
+}
+
+/// `review --pr`: fetch the pull request, review each chunk and print the comments
+async fn review_command(
+    executor: &PipelineExecutor,
+    config: &Config,
+    cwd: &Path,
+    pr: &str,
+    chain: Option<String>,
+    format: ReviewFormat,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let remote = repo_root(cwd).ok().and_then(|repo| github::origin_url(&repo));
+    let pull = PullRef::parse(pr, remote.as_deref())?;
+    let chain = chain
+        .or_else(|| config.review.chain.clone())
+        .unwrap_or_else(|| review::DEFAULT_CHAIN.to_string());
+    let steps = PipelineParser::parse(&chain)?;
+    validate_pipeline(executor, &steps).await;
+
+    let request = GitHubClient::from_env().pull_request(&pull).await?;
+    eprintln!("Reviewing {} ({} files)", pull, request.files.len());
+    let max_chunk_bytes = config.review.max_chunk_bytes.unwrap_or(review::DEFAULT_CHUNK_BYTES);
+    let report = review::review_files(executor, &steps, &request.title, &request.body, &request.files, max_chunk_bytes).await?;
+    for (path, error) in &report.failed {
+        eprintln!("Warning: {} was not reviewed: {}", path.display(), error);
+    }
+    let text = match format {
+        ReviewFormat::Markdown => report.to_markdown(&format!("{} {}", pull, request.title)),
+        ReviewFormat::Github => serde_json::to_string_pretty(&report.to_github(&request.head_sha))?,
+    };
+    match output {
+        Some(path) => std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))?,
+        None => println!("{}", text),
+    }
+    if !report.failed.is_empty() {
+        return Err(anyhow::anyhow!("{} chunk(s) could not be reviewed", report.failed.len()));
+    }
+    Ok(())
+}
+
+/// What `watch` runs on every change
+struct WatchRun<'a> {
+    steps: &'a [PipelineStep],
+    context: &'a [String],
+    env: &'a [(String, String)],
+    config: &'a Config,
+    cwd: &'a Path,
+    package: Option<&'a Package>,
+}
+
+/// Re-run the steps after every burst of file changes until interrupted; failed runs are reported, not fatal
+///
+/// Each run gets the `--context` files in full plus a recap with diffs of the
+/// watched files against what the previous run sent.
+async fn watch_pipeline(executor: &mut PipelineExecutor, mut watcher: FileWatcher, paths: &[String], run: WatchRun<'_>, debounce: std::time::Duration, flags: RunFlags) {
+    eprintln!("Watching {} files in {} (Ctrl-C to stop)", watcher.len(), paths.join(", "));
+    let mut watched = Context::new();
+    let mut incremental = IncrementalContext::new();
+    match reload_watched(&mut watched, &watcher.files(), run.config, run.cwd) {
+        // The files as they were when watching started are the first baseline
+        Ok(()) => {
+            incremental.prepare(&watched);
+        }
+        Err(e) => eprintln!("{:#}", e),
+    }
+    loop {
+        let changed = watcher.next_change(debounce).await;
+        let shown: Vec<String> = changed.iter().map(|file| display_relative(file, run.cwd)).collect();
+        eprintln!("\nChanged: {}", shown.join(", "));
+
+        let ctx = reload_watched(&mut watched, &changed, run.config, run.cwd)
+            .and_then(|()| initial_context(run.context, &[], run.config, run.cwd, run.package));
+        let mut ctx = match ctx {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                eprintln!("{:#}", e);
+                continue;
+            }
+        };
+        ctx.conversation_history.extend(incremental.prepare(&watched).conversation_history);
+        ctx.environment.extend(run.env.iter().cloned());
+        ctx.environment.insert("CHANGED_FILES".to_string(), shown.join("\n"));
+
+        match executor.execute_with_context(run.steps, ctx).await {
+            Ok((responses, _)) => {
+                for (i, r) in responses.iter().enumerate() {
+                    println!("[{}] {}", i + 1, flags.render.render(&r.content));
+                }
+                if flags.show_cost {
+                    eprintln!("{}", cost_summary(run.steps, &responses));
+                }
+            }
+            Err(e) => {
+                // The next run diffs against what the provider last saw
+                incremental.rollback();
+                eprintln!("{}", Msg::PipelineFailed(&e));
+            }
+        }
+        report_redactions(executor, "watch", flags.quiet);
+    }
+}
+
+/// Refresh `files` in the watched-file snapshot, dropping the ones that were removed
+fn reload_watched(watched: &mut Context, files: &[PathBuf], config: &Config, cwd: &Path) -> anyhow::Result<()> {
+    let root = config.project_root().unwrap_or(cwd);
+    for file in files {
+        watched.remove_file(&file.strip_prefix(root).unwrap_or(file).to_path_buf());
+    }
+    let existing: Vec<PathBuf> = files.iter().filter(|file| file.is_file()).cloned().collect();
+    let mut loader = ContextLoader::new(root)?.with_limits(config.context_limits).with_chunking(config.chunking);
+    let provenance = Provenance::IncrementalUpdate { changed_files: files.len() };
+    for (file, reason) in loader.load_into(watched, existing, provenance)? {
+        eprintln!("Warning: skipped {}: {}", file.display(), reason);
+    }
+    Ok(())
+}
+
+/// A path relative to `base` when it is under it
+fn display_relative(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path).display().to_string()
+}
+
+/// How `pipeline --input-file` runs its inputs
+struct BatchSettings {
+    jobs: usize,
+    rate_limit: Option<u32>,
+    /// Where to serve Prometheus metrics while the batch runs
+    metrics_addr: Option<std::net::SocketAddr>,
+}
+
+/// Run the steps once per input in a JSONL file, printing a JSON result per line; exits if any input failed
+async fn run_batch(
+    executor: &mut PipelineExecutor,
+    steps: &[PipelineStep],
+    ctx: Context,
+    input_file: &Path,
+    settings: BatchSettings,
+    flags: RunFlags,
+) {
+    let BatchSettings { jobs, rate_limit, metrics_addr } = settings;
+    validate_pipeline(executor, steps).await;
+    let inputs = match BatchInput::read_file(input_file) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            exit(ExitCode::Usage);
+        }
+    };
+    let total = inputs.len();
+    probe_step_capabilities(executor, steps, flags.reprobe).await;
+    if let Some(addr) = metrics_addr {
+        let metrics = Arc::new(Metrics::new());
+        executor.add_observer(metrics.clone());
+        tokio::spawn(async move {
+            if let Err(e) = server::serve_metrics(addr, metrics).await {
+                eprintln!("{:#}", e);
+            }
+        });
+    }
+
+    let mut runner = BatchRunner::new(executor, jobs);
+    if let Some(per_minute) = rate_limit {
+        runner = runner.with_rate_limit(per_minute);
+    }
+    let mut failed = 0;
+    let mut cost = 0.0;
+    let mut results = std::pin::pin!(runner.run(steps, &ctx, inputs));
+    while let Some(result) = results.next().await {
+        if !result.ok {
+            failed += 1;
+        }
+        cost += result.cost_usd.unwrap_or_default();
+        match serde_json::to_string(&result) {
+            Ok(line) => println!("{}", line),
+            Err(e) => eprintln!("Failed to encode result {}: {}", result.index, e),
+        }
+    }
+    report_redactions(executor, "pipeline", flags.quiet);
+    telemetry::flush().await;
+    if flags.show_cost {
+        eprintln!("Total cost: ${:.4}", cost);
+    }
+    if failed > 0 {
+        eprintln!("{} of {} inputs failed", failed, total);
+        exit(ExitCode::Failure);
+    }
+}
+
+/// Warn once per provider that ignores the seed given to its steps
+fn warn_unseeded(executor: &PipelineExecutor, manifest: &RunManifest) {
+    let mut warned = std::collections::HashSet::new();
+    for step in manifest.steps.iter().filter(|step| step.options.seed.is_some()) {
+        if let Some(provider) = executor.get_provider(&step.provider)
+            && !provider.supports_seed()
+            && warned.insert(step.provider.as_str())
+        {
+            eprintln!("{}", Msg::Warning(&format!("{} does not support seeds; its output may differ between runs", step.provider)));
+        }
+    }
+}
+
+/// Exit unless every step's provider is registered
+fn validate_step_providers(executor: &PipelineExecutor, steps: &[PipelineStep]) {
+    let names = executor.get_provider_names();
+    let name_refs: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+    if let Err(e) = PipelineParser::validate_providers(steps, &name_refs) {
+        eprintln!("{}", e);
+        eprintln!("Tip: provide API keys or login for missing providers.");
+        exit(ExitCode::Pipeline);
+    }
+}
+
+/// Exit listing every problem that would stop the steps from running
+async fn validate_pipeline(executor: &PipelineExecutor, steps: &[PipelineStep]) {
+    let report = executor.validate(steps).await;
+    if report.is_ok() {
+        return;
+    }
+    eprintln!("{}", report);
+    if report.problems.iter().any(|p| matches!(p.kind, ProblemKind::UnknownProvider | ProblemKind::MissingAuth)) {
+        eprintln!("Tip: provide API keys or login for missing providers.");
+    }
+    exit(ExitCode::Pipeline);
+}
+
+/// Probe capabilities of the providers used by a pipeline on first use
+async fn probe_step_capabilities(executor: &mut PipelineExecutor, steps: &[PipelineStep], reprobe: bool) {
+    let Ok(mut cache) = CapabilityCache::open_default() else { return };
+    let mut names: Vec<&str> = steps.iter().map(|s| s.provider.as_str()).collect();
+    names.sort();
+    names.dedup();
+    executor.probe_capabilities(&mut cache, &names, reprobe).await;
+    // The cache is an optimization; failing to persist it is not fatal
+    let _ = cache.save();
+}
+
+/// Construct a provider for a detected auth method, applying profile settings and refresh hooks
+fn build_provider(name: &str, method: AuthMethod, auth: &AuthManager, config: &Config) -> Option<Arc<dyn AIProvider>> {
+    let settings = auth.provider_settings(name);
+    // Project/top-level preferences win over the profile's model
+    let model = config.provider_preferences(name)
+        .and_then(|p| p.model.clone())
+        .or_else(|| settings.and_then(|s| s.model.clone()));
+    let base_url = settings.and_then(|s| s.base_url.clone());
+    let options = settings.map(|s| s.options.clone()).unwrap_or_default()
+        .merged(&config.provider_preferences(name).map(|p| p.options.clone()).unwrap_or_default());
+    let refresher = auth.refresher(name);
+    let prompt_caching = config.provider_preferences(name)
+        .and_then(|p| p.prompt_caching)
+        .or_else(|| settings.and_then(|s| s.prompt_caching))
+        .unwrap_or(false);
+
+    match name {
+        "claude" => {
+            let mut prov = match method {
+                AuthMethod::ApiKey { .. } | AuthMethod::AccountBased { .. } => {
+                    ClaudeProvider::from_credentials(ManagedCredentials::new(name, method, refresher))
+                }
+                // Assume detected session is usable and register provider
+                AuthMethod::CliAuth => ClaudeProvider::from_detected_cli_session(),
+                _ => return None,
+            };
+            if let Some(model) = model { prov = prov.with_model(model); }
+            if let Some(base_url) = base_url { prov = prov.with_base_url(base_url); }
+            Some(Arc::new(prov.with_options(options).with_prompt_caching(prompt_caching)))
+        }
+        "gemini" => {
+            let mut prov = build_gemini(method, auth)?;
+            if let Some(model) = model { prov = prov.with_model(model); }
+            if let Some(base_url) = base_url { prov = prov.with_base_url(base_url); }
+            let threshold = config.provider_preferences(name)
+                .and_then(|p| p.upload_threshold_bytes)
+                .or_else(|| settings.and_then(|s| s.upload_threshold_bytes));
+            if let Some(threshold) = threshold { prov = prov.with_upload_threshold((threshold > 0).then_some(threshold)); }
+            Some(Arc::new(prov.with_options(options)))
+        }
+        "codex" => {
+            let mut prov = match method {
+                AuthMethod::ApiKey { .. } | AuthMethod::AccountBased { .. } => {
+                    CodexProvider::from_credentials(ManagedCredentials::new(name, method, refresher))
+                }
+                AuthMethod::CliAuth => CodexProvider::from_detected_cli_session(),
+                _ => return None,
+            };
+            if let Some(model) = model { prov = prov.with_model(model); }
+            if let Some(base_url) = base_url { prov = prov.with_base_url(base_url); }
+            let ranks = config.provider_preferences(name)
+                .and_then(|p| p.tokenizer.clone())
+                .or_else(|| settings.and_then(|s| s.tokenizer.clone()));
+            if let Some(ranks) = ranks {
+                match BpeTokenizer::from_file(&ranks) {
+                    Ok(tokenizer) => prov = prov.with_tokenizer(Arc::new(tokenizer)),
+                    Err(e) => eprintln!("Warning: {:#}; falling back to approximate token counts", e),
+                }
+            }
+            Some(Arc::new(prov.with_options(options)))
+        }
+        _ => None,
+    }
+}
+
+/// Construct a Gemini provider from an auth method, preferring Google OAuth for CLI auth
+fn build_gemini(method: AuthMethod, auth: &AuthManager) -> Option<GeminiProvider> {
+    match method {
+        AuthMethod::ApiKey { .. } | AuthMethod::AccountBased { .. } => {
+            Some(GeminiProvider::from_credentials(ManagedCredentials::new("gemini", method, auth.refresher("gemini"))))
+        }
+        // Prefer Google OAuth via Application Default Credentials
+        AuthMethod::CliAuth => match GoogleAdc::load() {
+            Ok(adc) => Some(GeminiProvider::from_adc(adc)),
+            Err(_) => Some(GeminiProvider::from_detected_cli_session()),
+        },
+        _ => None,
+    }
+}
+
+/// Validate generation flags, exiting on out-of-range values
+fn generation_options(args: &GenerationArgs) -> ProviderOptions {
+    let options = args.to_options();
+    if let Err(e) = options.validate() {
+        eprintln!("{}", e);
+        exit(ExitCode::Usage);
+    }
+    options
+}
+
+/// Build the initial context from config context globs and --context specs, tagging provenance
+fn initial_context(specs: &[String], git: &[DiffSource], config: &Config, cwd: &Path, package: Option<&Package>) -> anyhow::Result<Context> {
+    let mut ctx = Context::new();
+    if let Some(package) = package {
+        ctx.add_message(
+            Message::new(
+                MessageRole::System,
+                format!("Scope: {} package '{}' at {}", package.kind, package.name, package.root.display()),
+            )
+            .with_provenance(Provenance::cli_flag("--package", &package.name)),
+        );
+    }
+    let in_scope = |file: &PathBuf| package.is_none_or(|p| p.contains(file));
+    let mut loader = ContextLoader::new(config.project_root().unwrap_or(cwd))?
+        .with_ignore_settings(&config.ignore)?
+        .with_limits(config.context_limits).with_chunking(config.chunking);
+    let mut skipped = Vec::new();
+
+    let mut files = config.context_files(cwd)?;
+    files.retain(|file| in_scope(file) && !loader.is_ignored(file, false));
+    let source = config.project_path.as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "config.toml".to_string());
+    skipped.extend(loader.load_into(&mut ctx, files, Provenance::Config { path: source })?);
+
+    for spec in specs {
+        let mut files = loader.expand(spec, cwd)?;
+        files.retain(|file| in_scope(file));
+        if files.is_empty() {
+            let package = package.map(|p| p.name.as_str()).unwrap_or_default();
+            eprintln!("Warning: ignoring --context {}: outside package '{}'", spec, package);
+            continue;
+        }
+        skipped.extend(loader.load_into(&mut ctx, files, Provenance::cli_flag("--context", spec))?);
+    }
+
+    for (file, reason) in skipped {
+        eprintln!("Warning: skipped context file {}: {}", file.display(), reason);
+    }
+
+    if !git.is_empty() {
+        let repo = repo_root(cwd)?;
+        for source in git {
+            let mut diffs = collect_diff(&repo, source)?;
+            diffs.retain(|diff| in_scope(&repo.join(&diff.path)));
+            if add_diffs_to_context(&mut ctx, diffs, source) == 0 {
+                eprintln!("Warning: no {} changes to include", source);
+            }
+        }
+    }
+    Ok(ctx)
+}
+
+/// Create the artifacts directory for a run; history is best effort
+fn start_run(manifest: RunManifest, quiet: bool) -> Option<RunArtifacts> {
+    match run_store().and_then(|store| store.create(&manifest.command, &manifest.chain)) {
+        Ok(mut run) => {
+            run.set_manifest(manifest);
+            if !quiet {
+                eprintln!("Run {} artifacts: {}", run.id(), run.dir().display());
+            }
+            Some(run)
+        }
+        Err(e) => {
+            eprintln!("Warning: not recording run artifacts: {}", e);
+            None
+        }
+    }
+}
+
+/// Save step outputs and the final status of a run
+fn finish_run(run: Option<&mut RunArtifacts>, steps: &[PipelineStep], result: &anyhow::Result<(Vec<Response>, Context)>) {
+    let Some(run) = run else { return };
+    match result {
+        Ok((responses, _)) => run.record_cost(&cost_summary(steps, responses)),
+        Err(e) => {
+            if let Some(failure) = e.downcast_ref::<PipelineFailure>() {
+                run.record_cost(&cost_summary(steps, &failure.completed));
+            }
+        }
+    }
+    let recorded = match result {
+        Ok((responses, context)) => steps
+            .iter()
+            .zip(responses)
+            .enumerate()
+            .try_for_each(|(i, (step, response))| run.record_step(i, step, response).map(|_| ()))
+            .and_then(|_| run.record_context(context))
+            .and_then(|_| run.finish(None)),
+        Err(e) => {
+            // Steps that succeeded before the failure keep their outputs
+            let recorded = match e.downcast_ref::<PipelineFailure>() {
+                Some(failure) => steps
+                    .iter()
+                    .zip(&failure.completed)
+                    .enumerate()
+                    .try_for_each(|(i, (step, response))| run.record_step(i, step, response).map(|_| ()))
+                    .map(|()| run.record_failed_step(&failure.provider)),
+                None => Ok(()),
+            };
+            recorded.and_then(|()| run.finish(Some(e.to_string())))
+        }
+    };
+    if let Err(e) = recorded {
+        eprintln!("Warning: failed to record run artifacts: {}", e);
+    }
+}
+
+/// Fire the `[[hooks]]` that apply to a finished run; hook failures only warn
+async fn fire_hooks(
+    hooks: &[HookSettings],
+    command: &str,
+    chain: &str,
+    run: Option<&RunArtifacts>,
+    steps: &[PipelineStep],
+    started: std::time::Instant,
+    result: &anyhow::Result<(Vec<Response>, Context)>,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+    let mut outcome = RunOutcome {
+        succeeded: result.is_ok(),
+        command: command.to_string(),
+        chain: chain.to_string(),
+        run_id: run.map(|run| run.id().to_string()),
+        steps: steps.len(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        ..RunOutcome::default()
+    };
+    match result {
+        Ok((responses, _)) => {
+            let cost = cost_summary(steps, responses);
+            outcome.cost_usd = cost.is_complete().then(|| cost.cost_usd());
+            if let Some(last) = responses.last() {
+                outcome = outcome.with_output(&last.content);
+            }
+        }
+        Err(e) => outcome.error = Some(e.to_string()),
+    }
+    for e in hooks::fire_all(hooks, &outcome).await {
+        eprintln!("Warning: hook failed: {:#}", e);
+    }
+}
+
+/// Token usage and cost of each step, as recorded in its response
+fn cost_summary(steps: &[PipelineStep], responses: &[Response]) -> CostSummary {
+    CostSummary::from_responses(steps.iter().map(|s| s.provider.as_str()).zip(responses))
+}
+
+/// Tell the user what was redacted and append it to the audit log
+fn report_redactions(executor: &PipelineExecutor, command: &str, quiet: bool) {
+    let Some(redactor) = executor.redactor() else { return };
+    let redactions = redactor.log();
+    if redactions.is_empty() {
+        return;
+    }
+    if !quiet {
+        let found: Vec<String> = redactions.iter().map(|r| format!("{} in {}:{}", r.rule, r.location, r.line)).collect();
+        eprintln!("Redacted {} secret(s) before sending: {}", redactions.len(), found.join(", "));
+    }
+    if let Err(e) = default_audit_log().and_then(|path| append_audit_log(&path, command, &redactions)) {
+        eprintln!("Warning: failed to write redaction audit log: {:#}", e);
+    }
+}
+
+/// Diagnose a failed run when `[post_mortem]` is enabled in config
+async fn post_mortem(error: &anyhow::Error, executor: &PipelineExecutor, settings: &PostMortemSettings, run: Option<&RunArtifacts>) {
+    if !settings.enabled {
+        return;
+    }
+    let Some(failure) = error.downcast_ref::<PipelineFailure>() else { return };
+
+    // Prefer the configured provider; otherwise any provider other than the one that failed
+    let names = executor.get_provider_names();
+    let provider = match &settings.provider {
+        Some(name) => executor.has_provider(name).then(|| name.clone()),
+        None => names.iter().find(|name| **name != failure.provider).or(names.first()).cloned(),
+    };
+
+    let report = match provider {
+        Some(provider) => match run_postmortem(executor, &provider, failure).await {
+            Ok(report) => report,
+            Err(e) => format!("{}\n\n(Post-mortem provider failed: {})", failure.summary(), e),
+        },
+        None => failure.summary(),
+    };
+    eprintln!("\nPost-mortem:\n{}", report);
+
+    if let Some(run) = run
+        && let Err(e) = run.record_report("postmortem.md", &report)
+    {
+        eprintln!("Warning: failed to save post-mortem: {:#}", e);
+    }
+}
+
+/// Open a directory in the platform file manager
+fn reveal(dir: &std::path::Path) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    // Printing the path is enough on headless machines
+    let _ = std::process::Command::new(opener)
+        .arg(dir)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
 }
@@ -0,0 +1,98 @@
+//! `--artifacts-dir`: each step's output written to numbered files as the pipeline runs
+//!
+//! ```text
+//! 01-claude-design.md             provider output, before any transform
+//! 01-claude-design.meta.json      step, provider, action and response metadata
+//! 01-claude-design.transformed.md output after the step's transform, when it changed
+//! ```
+
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::PipelineStep;
+use crate::providers::Response;
+
+/// Longest action slug used in file names, in characters
+const MAX_SLUG_CHARS: usize = 40;
+
+/// Directory receiving the artifacts of each top-level step
+#[derive(Debug, Clone)]
+pub struct ArtifactsDir {
+    dir: PathBuf,
+}
+
+#[derive(Serialize)]
+struct StepMeta<'a> {
+    step: usize,
+    provider: &'a str,
+    action: &'a str,
+    transformed: bool,
+    metadata: BTreeMap<&'a str, &'a str>,
+}
+
+impl ArtifactsDir {
+    /// Use `dir`, creating it if needed
+    pub fn create(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File name shared by a step's artifacts, e.g. `01-claude-design`
+    pub fn file_stem(step_index: usize, step: &PipelineStep) -> String {
+        let slug = slug(&step.action);
+        if slug.is_empty() {
+            format!("{:02}-{}", step_index + 1, step.provider)
+        } else {
+            format!("{:02}-{}-{}", step_index + 1, step.provider, slug)
+        }
+    }
+
+    /// Write a step's output and metadata, returning the files written
+    pub fn write_step(&self, step_index: usize, step: &PipelineStep, response: &Response) -> Result<Vec<PathBuf>> {
+        let stem = Self::file_stem(step_index, step);
+        let raw = response.raw.as_deref().unwrap_or(&response.content);
+        let meta = StepMeta {
+            step: step_index + 1,
+            provider: &step.provider,
+            action: &step.action,
+            transformed: response.raw.is_some(),
+            metadata: response.metadata.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect(),
+        };
+
+        let mut files = vec![
+            (self.dir.join(format!("{}.md", stem)), raw.to_string()),
+            (self.dir.join(format!("{}.meta.json", stem)), serde_json::to_string_pretty(&meta)?),
+        ];
+        if response.raw.is_some() {
+            files.push((self.dir.join(format!("{}.transformed.md", stem)), response.content.clone()));
+        }
+        for (path, text) in &files {
+            std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+/// Lowercase words of the first line of `action` joined by `-`, safe in file names
+fn slug(action: &str) -> String {
+    let first_line = action.lines().next().unwrap_or("");
+    let mut slug = String::new();
+    for c in first_line.chars().flat_map(char::to_lowercase) {
+        if slug.chars().count() >= MAX_SLUG_CHARS {
+            break;
+        }
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
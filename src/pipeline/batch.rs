@@ -0,0 +1,225 @@
+//! Run one pipeline over many inputs concurrently
+//!
+//! Inputs come from a JSONL file, one per line: either a JSON string or an
+//! object `{"id": ..., "input": ..., "env": {...}}`. Each input joins the
+//! pipeline's context as a user message and is also available to prompts as
+//! `{{env.INPUT}}`. Runs share one executor (and so its providers) and report
+//! a [`BatchResult`] per input, in input order.
+
+use anyhow::{Result, Context as AnyhowContext};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+use super::{PipelineExecutor, PipelineStep};
+use crate::providers::{Context, Message, MessageRole};
+use crate::providers::pricing::CostSummary;
+use crate::scheduler::RateLimit;
+
+/// One line of a batch input file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum InputLine {
+    Text(String),
+    Object {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        input: String,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+}
+
+/// Input for one run of the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchInput {
+    /// Caller's identifier, echoed in the result
+    pub id: Option<String>,
+    pub input: String,
+    /// Extra `{{env.NAME}}` variables for this run
+    pub env: HashMap<String, String>,
+}
+
+impl BatchInput {
+    /// Parse one JSONL line
+    pub fn parse(line: &str) -> Result<Self> {
+        Ok(match serde_json::from_str::<InputLine>(line)? {
+            InputLine::Text(input) => Self { id: None, input, env: HashMap::new() },
+            InputLine::Object { id, input, env } => {
+                let id = id.map(|id| match id {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                });
+                Self { id, input, env }
+            }
+        })
+    }
+
+    /// Read every non-blank line of a JSONL file
+    pub fn read_file(path: &Path) -> Result<Vec<Self>> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input file {}", path.display()))?;
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                Self::parse(line).with_context(|| format!("{}:{}: invalid batch input", path.display(), i + 1))
+            })
+            .collect()
+    }
+}
+
+/// Outcome of the pipeline for one input, written as a JSONL line
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    /// Position of the input in the file, from 0
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub ok: bool,
+    /// Content of the last step's response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Content of every step's response
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub responses: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    pub elapsed_ms: u64,
+}
+
+/// Spaces out starts so no more than `per_minute` happen in any minute
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / per_minute.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until the next start is allowed
+    pub async fn acquire(&self) {
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start.into()).await;
+    }
+}
+
+/// Holds calls to one provider to its [`RateLimit`]: spaced-out starts and a cap on calls in flight
+pub struct ProviderLimiter {
+    rate: Option<RateLimiter>,
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl ProviderLimiter {
+    pub fn new(limit: &RateLimit) -> Self {
+        Self {
+            rate: limit.requests_per_minute.filter(|rpm| *rpm > 0).map(RateLimiter::per_minute),
+            slots: limit.max_concurrent.map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        }
+    }
+
+    /// Wait for a free slot and the next allowed start; the slot frees when the permit drops
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = match &self.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(rate) = &self.rate {
+            rate.acquire().await;
+        }
+        permit
+    }
+}
+
+/// Runs a pipeline for many inputs with bounded parallelism
+pub struct BatchRunner<'a> {
+    executor: &'a PipelineExecutor,
+    jobs: usize,
+    limiter: Option<RateLimiter>,
+}
+
+impl<'a> BatchRunner<'a> {
+    /// Run at most `jobs` inputs at a time through `executor`
+    pub fn new(executor: &'a PipelineExecutor, jobs: usize) -> Self {
+        Self { executor, jobs: jobs.max(1), limiter: None }
+    }
+
+    /// Start at most `per_minute` inputs per minute
+    pub fn with_rate_limit(mut self, per_minute: u32) -> Self {
+        self.limiter = Some(RateLimiter::per_minute(per_minute));
+        self
+    }
+
+    /// Results in input order, each yielded as soon as it and all before it finish
+    pub fn run<'s>(
+        &'s self,
+        steps: &'s [PipelineStep],
+        context: &'s Context,
+        inputs: Vec<BatchInput>,
+    ) -> impl Stream<Item = BatchResult> + 's {
+        futures::stream::iter(inputs.into_iter().enumerate())
+            .map(move |(index, input)| self.run_one(steps, context, index, input))
+            .buffered(self.jobs)
+    }
+
+    async fn run_one(&self, steps: &[PipelineStep], context: &Context, index: usize, input: BatchInput) -> BatchResult {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        run_input(self.executor, steps, context, index, input).await
+    }
+}
+
+/// Run the pipeline for the `index`th input, with the input added to `context`
+pub async fn run_input(executor: &PipelineExecutor, steps: &[PipelineStep], context: &Context, index: usize, input: BatchInput) -> BatchResult {
+    let mut context = context.clone();
+    context.environment.extend(input.env);
+    context.environment.insert("INPUT".to_string(), input.input.clone());
+    context.add_message(Message::new(MessageRole::User, input.input));
+
+    let started = Instant::now();
+    let result = executor.execute(steps, context).await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(responses) => {
+            let cost = CostSummary::from_responses(steps.iter().map(|s| s.provider.as_str()).zip(&responses));
+            let priced = cost.steps.iter().any(|s| s.cost_usd.is_some());
+            BatchResult {
+                index,
+                id: input.id,
+                ok: true,
+                output: responses.last().map(|r| r.content.clone()),
+                responses: responses.into_iter().map(|r| r.content).collect(),
+                error: None,
+                cost_usd: priced.then(|| cost.cost_usd()),
+                elapsed_ms,
+            }
+        }
+        Err(e) => BatchResult {
+            index,
+            id: input.id,
+            ok: false,
+            output: None,
+            responses: Vec::new(),
+            error: Some(format!("{:#}", e)),
+            cost_usd: None,
+            elapsed_ms,
+        },
+    }
+}
@@ -0,0 +1,252 @@
+//! One prompt over many inputs through a provider batch API
+//!
+//! `ai-cli batch submit` renders the prompt for every line of a batch input
+//! file (see [`BatchInput`]) and submits the lot as one provider batch. The
+//! returned [`BatchJob`] is kept in a [`BatchJobStore`] so `batch status` and
+//! `batch fetch` can find the provider and map results back to the inputs.
+//! Requests pass through the executor's redaction, `[safety]` rules,
+//! `--privacy` anonymization and audit log like any other provider call.
+//! Job files hold the placeholder mapping, so a store opened
+//! [`BatchJobStore::with_encryption`] keeps them encrypted.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::batch::{BatchInput, BatchResult};
+use super::template::render_env;
+use super::{PipelineExecutor, SafetyAction};
+use crate::config;
+use crate::context::Anonymizer;
+use crate::history::audit::AuditCall;
+use crate::history::encryption::{self, Encryption};
+use crate::history::unix_now;
+use crate::providers::batch::{BatchApi, BatchOutput, BatchRequest};
+use crate::providers::{Context, Message, MessageRole, ProviderOptions};
+
+/// A batch submitted from this machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchJob {
+    /// The provider's batch id
+    pub id: String,
+    /// Batch API the job went to: `claude` or `openai`
+    pub provider: String,
+    /// Unix seconds
+    pub submitted_at: u64,
+    /// Caller ids of the inputs, by input position
+    pub inputs: Vec<Option<String>>,
+    /// `--privacy` placeholders and their originals, restored in the results
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub placeholders: Vec<(String, String)>,
+}
+
+impl BatchJob {
+    /// One request per input: the input joins the context as a user message and
+    /// fills `{{env.INPUT}}` in `prompt`, as in `pipeline --input-file`
+    pub fn requests(prompt: &str, context: &Context, inputs: &[BatchInput], options: &ProviderOptions) -> Vec<BatchRequest> {
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let mut context = context.clone();
+                context.environment.extend(input.env.clone());
+                context.environment.insert("INPUT".to_string(), input.input.clone());
+                context.add_message(Message::new(MessageRole::User, input.input.clone()));
+                BatchRequest {
+                    custom_id: custom_id(index),
+                    prompt: render_env(prompt, &context.environment),
+                    context,
+                    options: options.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Submit `prompt` over `inputs` through `api`
+    pub async fn submit(api: &dyn BatchApi, prompt: &str, context: &Context, inputs: Vec<BatchInput>, options: &ProviderOptions) -> Result<Self> {
+        Self::submit_with(&PipelineExecutor::new(), api, prompt, context, inputs, options).await
+    }
+
+    /// [`submit`](Self::submit), preparing and auditing each request as `executor` does its steps
+    pub async fn submit_with(
+        executor: &PipelineExecutor,
+        api: &dyn BatchApi,
+        prompt: &str,
+        context: &Context,
+        inputs: Vec<BatchInput>,
+        options: &ProviderOptions,
+    ) -> Result<Self> {
+        if inputs.is_empty() {
+            return Err(anyhow!("The batch input file has no inputs"));
+        }
+        let mut requests = Vec::with_capacity(inputs.len());
+        for (index, request) in Self::requests(prompt, context, &inputs, options).into_iter().enumerate() {
+            let prepared = executor.prepare_request(request.prompt.clone(), request.options.system.clone(), &request.context)?;
+            for finding in prepared.safety_findings.iter().filter(|f| f.action == SafetyAction::Warn) {
+                tracing::warn!(input = index + 1, rule = %finding.rule, location = %finding.location, line = finding.line, "content filter matched");
+            }
+            let options = ProviderOptions { system: prepared.system, ..request.options.clone() };
+            requests.push(BatchRequest { custom_id: request.custom_id, prompt: prepared.prompt, context: prepared.context.into_owned(), options });
+        }
+
+        let submitted = api.submit(&requests).await;
+        if let Some(audit) = executor.audit_log() {
+            for (index, request) in requests.iter().enumerate() {
+                let call = AuditCall {
+                    provider: api.name(),
+                    model: request.options.model.as_deref(),
+                    step: index + 1,
+                    attempt: 1,
+                    idempotency_key: None,
+                    system: request.options.system.as_deref(),
+                    prompt: &request.prompt,
+                };
+                match &submitted {
+                    Ok(_) => audit.record_submission(&call),
+                    Err(error) => audit.record(&call, Err(error)),
+                }
+            }
+        }
+        Ok(Self {
+            id: submitted?,
+            provider: api.name().to_string(),
+            submitted_at: unix_now(),
+            inputs: inputs.into_iter().map(|input| input.id).collect(),
+            placeholders: executor.anonymizer().map(|anonymizer| anonymizer.mapping()).unwrap_or_default(),
+        })
+    }
+
+    /// [`results`](Self::results) with replies checked against `executor`'s `[safety]` rules
+    ///
+    /// A blocked reply fails its input.
+    pub fn results_with(&self, executor: &PipelineExecutor, outputs: Vec<BatchOutput>) -> Vec<BatchResult> {
+        let mut results = self.results(outputs);
+        let Some(safety) = executor.safety_filter() else { return results };
+        for result in results.iter_mut() {
+            let Some(output) = &result.output else { continue };
+            let mut findings = Vec::new();
+            match safety.check_response(output, &mut findings) {
+                Ok(checked) => result.output = Some(checked),
+                Err(e) => {
+                    result.ok = false;
+                    result.output = None;
+                    result.error = Some(e.to_string());
+                }
+            }
+            for finding in findings.iter().filter(|f| f.action == SafetyAction::Warn) {
+                tracing::warn!(input = result.index + 1, rule = %finding.rule, "content filter matched");
+            }
+        }
+        results
+    }
+
+    /// Results in input order; inputs the provider reported nothing for count as failed
+    pub fn results(&self, outputs: Vec<BatchOutput>) -> Vec<BatchResult> {
+        let anonymizer = (!self.placeholders.is_empty()).then(|| Anonymizer::from_mapping(self.placeholders.clone()));
+        let mut outputs: HashMap<String, Result<String, String>> = outputs
+            .into_iter()
+            .map(|output| {
+                let result = match &anonymizer {
+                    Some(anonymizer) => output.result.map(|text| anonymizer.restore(&text)),
+                    None => output.result,
+                };
+                (output.custom_id, result)
+            })
+            .collect();
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let result = outputs.remove(&custom_id(index)).unwrap_or_else(|| Err("no result in the batch".to_string()));
+                BatchResult {
+                    index,
+                    id: id.clone(),
+                    ok: result.is_ok(),
+                    output: result.as_ref().ok().cloned(),
+                    responses: Vec::new(),
+                    error: result.err(),
+                    cost_usd: None,
+                    elapsed_ms: 0,
+                }
+            })
+            .collect()
+    }
+}
+
+fn custom_id(index: usize) -> String {
+    format!("input-{}", index)
+}
+
+/// File-backed storage for submitted batches (`<dir>/<id>.json`)
+pub struct BatchJobStore {
+    dir: PathBuf,
+    encryption: Option<Arc<Encryption>>,
+}
+
+impl BatchJobStore {
+    /// Create a store rooted at a directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), encryption: None }
+    }
+
+    /// Encrypt job files written from now on and decrypt encrypted ones when reading
+    pub fn with_encryption(mut self, encryption: Arc<Encryption>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Open the store in the user data directory
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(config::data_dir()?.join("batches")))
+    }
+
+    /// Get the store directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path_for(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(anyhow!("Invalid batch id '{}'", id));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    pub fn save(&self, job: &BatchJob) -> Result<PathBuf> {
+        let path = self.path_for(&job.id)?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        encryption::write(&path, serde_json::to_string_pretty(job)?, self.encryption.as_deref())?;
+        Ok(path)
+    }
+
+    /// Load a batch submitted from this machine
+    pub fn load(&self, id: &str) -> Result<BatchJob> {
+        let path = self.path_for(id)?;
+        if !path.exists() {
+            return Err(anyhow!("No batch '{}' was submitted from this machine", id));
+        }
+        let text = encryption::read_to_string(&path, self.encryption.as_deref())?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid batch file {}", path.display()))
+    }
+
+    /// Submitted batches, most recent first
+    pub fn list(&self) -> Result<Vec<BatchJob>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut jobs = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json")
+                && let Some(id) = path.file_stem().and_then(|s| s.to_str())
+            {
+                jobs.push(self.load(id)?);
+            }
+        }
+        jobs.sort_by(|a, b| b.submitted_at.cmp(&a.submitted_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(jobs)
+    }
+}
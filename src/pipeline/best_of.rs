@@ -0,0 +1,203 @@
+//! Best-of-N steps: sample several answers and let a judge choose
+//!
+//! `bestof(3, judge=claude){ gemini:implement ; codex:implement }` runs the
+//! candidate steps three times in turn (gemini, codex, gemini), in parallel,
+//! then asks the judge to pick the best answer by number. With `mode=merge`
+//! the judge instead writes one answer combining the best parts, and that
+//! becomes the step's response.
+
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use super::{PipelineExecutor, PipelineStep, StepResult};
+use crate::providers::{Context, Response};
+
+/// Pseudo-provider name of a best-of step
+pub const BEST_OF_PROVIDER: &str = "bestof";
+
+/// What the judge does with the candidates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JudgeMode {
+    /// Return the candidate the judge picks, unchanged
+    #[default]
+    Pick,
+    /// Return the judge's synthesis of the candidates
+    Merge,
+}
+
+impl fmt::Display for JudgeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JudgeMode::Pick => "pick",
+            JudgeMode::Merge => "merge",
+        })
+    }
+}
+
+impl FromStr for JudgeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pick" => Ok(JudgeMode::Pick),
+            "merge" => Ok(JudgeMode::Merge),
+            other => Err(anyhow!("Invalid bestof mode '{}' (expected pick or merge)", other)),
+        }
+    }
+}
+
+/// Candidate steps sampled `n` times and the provider that judges them
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestOfStep {
+    /// Steps used in turn for each sample
+    pub candidates: Vec<PipelineStep>,
+    /// Number of samples
+    pub n: usize,
+    pub judge: String,
+    pub mode: JudgeMode,
+}
+
+impl BestOfStep {
+    /// One sample per candidate, judged in pick mode
+    pub fn new(candidates: Vec<PipelineStep>, judge: impl Into<String>) -> Self {
+        Self { n: candidates.len().max(1), candidates, judge: judge.into(), mode: JudgeMode::Pick }
+    }
+
+    /// Take `n` samples, cycling through the candidates
+    pub fn with_samples(mut self, n: usize) -> Self {
+        self.n = n.max(1);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: JudgeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Step used for sample `index`
+    fn sample(&self, index: usize) -> &PipelineStep {
+        &self.candidates[index % self.candidates.len()]
+    }
+
+    /// Prompt asking the judge to pick or merge `answers`
+    pub fn judge_prompt(&self, task: &str, answers: &[&str]) -> String {
+        let instruction = match self.mode {
+            JudgeMode::Pick => "Pick the best answer. Reply with only its number.",
+            JudgeMode::Merge => {
+                "Write a single answer that combines the strongest parts of these answers and fixes their mistakes. \
+                 Reply with only that answer."
+            }
+        };
+        let listed: Vec<String> = answers
+            .iter()
+            .enumerate()
+            .map(|(i, answer)| format!("### Answer {}\n{}", i + 1, answer))
+            .collect();
+        format!(
+            "You are judging {} candidate answers to the task below. {}\n\n## Task\n{}\n\n{}",
+            answers.len(),
+            instruction,
+            task,
+            listed.join("\n\n")
+        )
+    }
+}
+
+/// 1-based answer number the judge picked, if its reply names one in range
+pub fn parse_choice(reply: &str, count: usize) -> Option<usize> {
+    reply
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|word| !word.is_empty())
+        .find_map(|word| word.parse::<usize>().ok().filter(|n| (1..=count).contains(n)))
+}
+
+impl PipelineExecutor {
+    /// Sample the candidates in parallel, then judge the answers that succeeded
+    pub(super) async fn execute_best_of(
+        &self,
+        step: &PipelineStep,
+        best_of: &BestOfStep,
+        context: &Context,
+        step_index: usize,
+        streaming: bool,
+    ) -> StepResult {
+        let start_time = Instant::now();
+        let response = self.run_best_of(best_of, context, step_index, streaming).await.map(|response| {
+            response
+                .with_metadata("step_index", step_index.to_string())
+                .with_metadata("latency_ms", start_time.elapsed().as_millis().to_string())
+        });
+        StepResult {
+            step: step.clone(),
+            response,
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            retries: 0,
+            rate_limits: Vec::new(),
+        }
+    }
+
+    async fn run_best_of(&self, best_of: &BestOfStep, context: &Context, step_index: usize, streaming: bool) -> Result<Response> {
+        tracing::info!(samples = best_of.n, judge = %best_of.judge, mode = %best_of.mode, "running best-of step");
+        let samples = futures::future::join_all(
+            (0..best_of.n).map(|i| self.execute_step(best_of.sample(i), context, step_index, streaming)),
+        )
+        .await;
+
+        let mut cost = 0.0;
+        let mut answers: Vec<(usize, Response)> = Vec::new();
+        let mut first_error = None;
+        for (i, sample) in samples.into_iter().enumerate() {
+            match sample.response {
+                Ok(response) => {
+                    cost += cost_of(&response);
+                    answers.push((i, response));
+                }
+                Err(e) => {
+                    tracing::warn!(sample = i + 1, provider = %best_of.sample(i).provider, error = %e, "best-of sample failed");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return Err(first_error.unwrap_or_else(|| anyhow!("bestof step has no candidates")));
+        }
+
+        let (winner, mut response) = if answers.len() == 1 {
+            answers.remove(0)
+        } else {
+            let task = self.build_prompt(best_of.sample(0), context);
+            let contents: Vec<&str> = answers.iter().map(|(_, r)| r.content.as_str()).collect();
+            let judge_step = PipelineStep::new(best_of.judge.clone(), best_of.judge_prompt(&task, &contents));
+            let verdict = self.execute_step(&judge_step, &Context::new(), step_index, false).await.response?;
+            cost += cost_of(&verdict);
+            match best_of.mode {
+                JudgeMode::Merge => (answers[0].0, Response::new(verdict.content)),
+                JudgeMode::Pick => {
+                    let choice = parse_choice(&verdict.content, answers.len()).unwrap_or_else(|| {
+                        tracing::warn!(reply = %verdict.content, "judge did not name an answer; keeping the first");
+                        1
+                    });
+                    answers.swap_remove(choice - 1)
+                }
+            }
+        };
+
+        response.metadata.insert("best_of_samples".to_string(), best_of.n.to_string());
+        response.metadata.insert("best_of_judge".to_string(), best_of.judge.clone());
+        if best_of.mode == JudgeMode::Pick {
+            response.metadata.insert("best_of_winner".to_string(), (winner + 1).to_string());
+        }
+        if cost > 0.0 {
+            response.metadata.insert("cost_usd".to_string(), format!("{:.6}", cost));
+        } else {
+            response.metadata.remove("cost_usd");
+        }
+        Ok(response)
+    }
+}
+
+fn cost_of(response: &Response) -> f64 {
+    response.metadata.get("cost_usd").and_then(|c| c.parse().ok()).unwrap_or_default()
+}
@@ -0,0 +1,103 @@
+//! Commit messages written from the staged diff
+//!
+//! `ai-cli commit-msg` runs a pipeline over `git diff --cached` and prints the
+//! last step's reply as a Conventional Commits message, or writes it to the
+//! commit message file. `--install-hook` sets it up as `prepare-commit-msg`
+//! so a plain `git commit` opens the editor with the message filled in.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Chain used when neither `--chain` nor `[commit_msg]` sets one
+pub const DEFAULT_CHAIN: &str = "claude:Write a Conventional Commits message for the staged changes: \
+a `type(scope): summary` subject under 72 characters, a blank line, then a short body explaining why. \
+Reply with the message only.";
+
+/// First line of hooks written by `--install-hook`, used to recognize them
+pub const HOOK_MARKER: &str = "# Installed by ai-cli commit-msg --install-hook";
+
+/// `[commit_msg]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommitMsgSettings {
+    /// Pipeline chain run over the staged diff
+    #[serde(default)]
+    pub chain: Option<String>,
+}
+
+static CONVENTIONAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(feat|fix|docs|style|refactor|perf|test|build|ci|chore|revert)(\([^()\s]+\))?!?: \S").unwrap()
+});
+
+/// Strip a code fence or quotes the model wrapped the message in
+pub fn clean_message(reply: &str) -> String {
+    let mut text = reply.trim();
+    if let Some(rest) = text.strip_prefix("```") {
+        // Drop the fence's language tag line
+        text = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+        text = text.trim_end().strip_suffix("```").unwrap_or(text);
+    }
+    let text = text.trim();
+    let text = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(text);
+    text.trim().to_string()
+}
+
+/// Whether the subject line follows Conventional Commits
+pub fn is_conventional(message: &str) -> bool {
+    message.lines().next().is_some_and(|subject| CONVENTIONAL.is_match(subject))
+}
+
+/// Put `message` above what the commit message file already holds (git's comment lines)
+pub fn write_message(path: &Path, message: &str) -> Result<()> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let text = if existing.trim().is_empty() {
+        format!("{}\n", message)
+    } else {
+        format!("{}\n\n{}", message, existing)
+    };
+    std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Script installed as `prepare-commit-msg`
+///
+/// Only a plain `git commit` is filled in: `-m`, templates, merges, squashes
+/// and amends already have a message. Failures never block the commit.
+pub fn hook_script() -> String {
+    format!(
+        "#!/bin/sh\n{}\n[ -z \"$2\" ] || exit 0\nai-cli commit-msg --write \"$1\" || true\n",
+        HOOK_MARKER
+    )
+}
+
+/// Write the `prepare-commit-msg` hook into `hooks_dir`
+///
+/// A hook ai-cli did not write is only replaced with `force`.
+pub fn install_hook(hooks_dir: &Path, force: bool) -> Result<PathBuf> {
+    let path = hooks_dir.join("prepare-commit-msg");
+    if let Ok(existing) = std::fs::read_to_string(&path)
+        && !existing.contains(HOOK_MARKER)
+        && !force
+    {
+        return Err(anyhow!("{} already exists; use --force to replace it", path.display()));
+    }
+    std::fs::create_dir_all(hooks_dir)
+        .with_context(|| format!("Failed to create {}", hooks_dir.display()))?;
+    std::fs::write(&path, hook_script())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {} executable", path.display()))?;
+    }
+    Ok(path)
+}
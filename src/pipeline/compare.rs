@@ -0,0 +1,218 @@
+//! A/B comparison: one prompt on several providers, or several prompts on one
+//!
+//! Every side runs in parallel. The report renders as columns, one per side,
+//! as a unified diff of each answer against the first, or as JSON.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::time::Instant;
+
+use super::{PipelineExecutor, PipelineStep};
+use crate::providers::Context;
+use crate::providers::pricing::Usage;
+
+/// Gap between two columns
+const COLUMN_GAP: &str = " │ ";
+
+/// How the report is shown on the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum CompareView {
+    /// Answers side by side
+    #[default]
+    Columns,
+    /// Unified diff of each answer against the first
+    Diff,
+}
+
+/// One provider and prompt and what it answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareSide {
+    /// `A`, `B`, ... in the order the sides were given
+    pub label: String,
+    pub provider: String,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+impl CompareSide {
+    /// `A · claude (1200 ms)`
+    pub fn title(&self) -> String {
+        format!("{} · {} ({} ms)", self.label, self.provider, self.elapsed_ms)
+    }
+
+    fn text(&self) -> String {
+        match (&self.content, &self.error) {
+            (Some(content), _) => content.clone(),
+            (None, error) => format!("(failed: {})", error.as_deref().unwrap_or("no answer")),
+        }
+    }
+}
+
+/// Every side of a comparison, in the order given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareReport {
+    pub sides: Vec<CompareSide>,
+}
+
+impl CompareReport {
+    /// Answers side by side within `width` columns, each headed by its title
+    ///
+    /// The prompts are listed first when they differ between sides.
+    pub fn columns(&self, width: usize) -> String {
+        let mut out = String::new();
+        if self.sides.windows(2).any(|pair| pair[0].prompt != pair[1].prompt) {
+            for side in &self.sides {
+                out.push_str(&format!("{}: {}\n", side.label, side.prompt));
+            }
+            out.push('\n');
+        }
+        let count = self.sides.len().max(1);
+        let column = (width.saturating_sub(COLUMN_GAP.chars().count() * (count - 1)) / count).max(10);
+        let blocks: Vec<Vec<String>> = self
+            .sides
+            .iter()
+            .map(|side| {
+                let mut lines = wrap(&side.title(), column);
+                lines.push("─".repeat(column));
+                lines.extend(wrap(&side.text(), column));
+                lines
+            })
+            .collect();
+        let height = blocks.iter().map(Vec::len).max().unwrap_or(0);
+        for row in 0..height {
+            let cells: Vec<String> = blocks
+                .iter()
+                .map(|lines| {
+                    let line = lines.get(row).map(String::as_str).unwrap_or("");
+                    format!("{}{}", line, " ".repeat(column - line.chars().count()))
+                })
+                .collect();
+            out.push_str(cells.join(COLUMN_GAP).trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Unified diff of every other side's answer against the first side's
+    pub fn diff(&self) -> String {
+        let Some((first, others)) = self.sides.split_first() else {
+            return String::new();
+        };
+        let base = with_newline(first.text());
+        others
+            .iter()
+            .map(|side| {
+                let text = with_newline(side.text());
+                let diff = TextDiff::from_lines(&base, &text)
+                    .unified_diff()
+                    .context_radius(3)
+                    .header(&first.title(), &side.title())
+                    .to_string();
+                if diff.is_empty() { format!("{} and {}: identical answers\n", first.label, side.label) } else { diff }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn with_newline(mut text: String) -> String {
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// Break `text` into lines of at most `width` characters, at spaces where possible
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let needed = line.chars().count() + usize::from(!line.is_empty()) + word.chars().count();
+            if needed > width && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+            while line.chars().count() > width {
+                let split = line.char_indices().nth(width).map_or(line.len(), |(i, _)| i);
+                let rest = line.split_off(split);
+                lines.push(std::mem::replace(&mut line, rest));
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Label of the side at `index`: `A` to `Z`, then `S27`, ...
+fn side_label(index: usize) -> String {
+    match u8::try_from(index).ok().filter(|i| *i < 26) {
+        Some(i) => char::from(b'A' + i).to_string(),
+        None => format!("S{}", index + 1),
+    }
+}
+
+impl PipelineExecutor {
+    /// Run every side in parallel and collect the answers
+    ///
+    /// Fails only if no side answered.
+    pub async fn compare(&self, sides: &[PipelineStep], context: Context) -> Result<CompareReport> {
+        let results = futures::future::join_all(sides.iter().map(|step| async {
+            let started = Instant::now();
+            let result = self.execute_step(step, &context, 0, false).await;
+            (result, started.elapsed().as_millis() as u64)
+        }))
+        .await;
+
+        let mut first_error = None;
+        let sides: Vec<CompareSide> = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, (result, elapsed_ms))| {
+                let mut side = CompareSide {
+                    label: side_label(index),
+                    provider: result.step.provider.clone(),
+                    prompt: result.step.action.clone(),
+                    model: None,
+                    content: None,
+                    error: None,
+                    elapsed_ms,
+                    usage: None,
+                    cost_usd: None,
+                };
+                match result.response {
+                    Ok(response) => {
+                        side.model = response.metadata.get("model").cloned();
+                        side.usage = Usage::from_response(&response);
+                        side.cost_usd = response.metadata.get("cost_usd").and_then(|c| c.parse().ok());
+                        side.content = Some(response.content);
+                    }
+                    Err(e) => {
+                        side.error = Some(format!("{:#}", e));
+                        first_error.get_or_insert(e);
+                    }
+                }
+                side
+            })
+            .collect();
+        if sides.iter().all(|side| side.content.is_none()) {
+            return Err(first_error.unwrap_or_else(|| anyhow!("Nothing to compare")));
+        }
+        Ok(CompareReport { sides })
+    }
+}
@@ -0,0 +1,160 @@
+//! Ask several providers the same question and reconcile their answers
+//!
+//! Every provider is queried in parallel. Without an arbiter the report shows
+//! the answers side by side; with one, the arbiter is asked for a JSON
+//! synthesis listing the consensus answer, the points the answers agree on
+//! and where they disagree.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Instant;
+
+use super::{PipelineExecutor, PipelineStep};
+use crate::providers::Context;
+
+/// One provider's answer, or why it has none
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsensusAnswer {
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// A point the answers disagree on, with each provider's position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disagreement {
+    pub topic: String,
+    #[serde(default)]
+    pub positions: BTreeMap<String, String>,
+}
+
+/// Arbiter's reconciliation of the answers
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Synthesis {
+    pub consensus: String,
+    #[serde(default)]
+    pub agreements: Vec<String>,
+    #[serde(default)]
+    pub disagreements: Vec<Disagreement>,
+}
+
+impl Synthesis {
+    /// Parse the arbiter's reply; a reply that is not the requested JSON becomes the consensus text
+    pub fn parse(reply: &str) -> Self {
+        let trimmed = reply.trim();
+        let json = match (trimmed.find('{'), trimmed.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+            _ => trimmed,
+        };
+        serde_json::from_str(json).unwrap_or_else(|_| Self { consensus: trimmed.to_string(), ..Self::default() })
+    }
+}
+
+/// Answers to one question and, when an arbiter was asked, their synthesis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsensusReport {
+    pub prompt: String,
+    pub answers: Vec<ConsensusAnswer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arbiter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synthesis: Option<Synthesis>,
+}
+
+impl fmt::Display for ConsensusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for answer in &self.answers {
+            writeln!(f, "=== {} ({} ms) ===", answer.provider, answer.elapsed_ms)?;
+            match (&answer.content, &answer.error) {
+                (Some(content), _) => writeln!(f, "{}\n", content.trim_end())?,
+                (None, error) => writeln!(f, "(failed: {})\n", error.as_deref().unwrap_or("no answer"))?,
+            }
+        }
+        if let (Some(arbiter), Some(synthesis)) = (&self.arbiter, &self.synthesis) {
+            writeln!(f, "=== consensus ({}) ===", arbiter)?;
+            writeln!(f, "{}", synthesis.consensus.trim_end())?;
+            if !synthesis.agreements.is_empty() {
+                writeln!(f, "\nAgreements:")?;
+                for point in &synthesis.agreements {
+                    writeln!(f, "  - {}", point)?;
+                }
+            }
+            if !synthesis.disagreements.is_empty() {
+                writeln!(f, "\nDisagreements:")?;
+                for disagreement in &synthesis.disagreements {
+                    writeln!(f, "  - {}", disagreement.topic)?;
+                    for (provider, position) in &disagreement.positions {
+                        writeln!(f, "      {}: {}", provider, position)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prompt asking the arbiter to reconcile `answers` to `question`
+pub fn arbiter_prompt(question: &str, answers: &[ConsensusAnswer]) -> String {
+    let listed: Vec<String> = answers
+        .iter()
+        .filter_map(|a| a.content.as_ref().map(|content| format!("### {}\n{}", a.provider, content)))
+        .collect();
+    format!(
+        "Several assistants answered the question below. Reconcile their answers. \
+         Reply with only a JSON object of the form \
+         {{\"consensus\": \"the best-supported answer\", \"agreements\": [\"point all answers share\"], \
+         \"disagreements\": [{{\"topic\": \"point of conflict\", \"positions\": {{\"<assistant>\": \"its position\"}}}}]}}.\n\n\
+         ## Question\n{}\n\n{}",
+        question,
+        listed.join("\n\n")
+    )
+}
+
+impl PipelineExecutor {
+    /// Ask every provider `prompt` in parallel, then let `arbiter` synthesize a consensus
+    ///
+    /// Fails only if no provider answered or the arbiter call fails.
+    pub async fn consensus(&self, prompt: &str, providers: &[String], arbiter: Option<&str>, context: Context) -> Result<ConsensusReport> {
+        let steps: Vec<PipelineStep> = providers.iter().map(|p| PipelineStep::new(p.clone(), prompt)).collect();
+        let results = futures::future::join_all(steps.iter().map(|step| async {
+            let started = Instant::now();
+            let result = self.execute_step(step, &context, 0, false).await;
+            (result, started.elapsed().as_millis() as u64)
+        }))
+        .await;
+
+        let mut first_error = None;
+        let answers: Vec<ConsensusAnswer> = results
+            .into_iter()
+            .map(|(result, elapsed_ms)| {
+                let (content, error) = match result.response {
+                    Ok(response) => (Some(response.content), None),
+                    Err(e) => {
+                        let message = format!("{:#}", e);
+                        first_error.get_or_insert(e);
+                        (None, Some(message))
+                    }
+                };
+                ConsensusAnswer { provider: result.step.provider, content, error, elapsed_ms }
+            })
+            .collect();
+        if answers.iter().all(|a| a.content.is_none()) {
+            return Err(first_error.unwrap_or_else(|| anyhow!("No providers to ask")));
+        }
+
+        let synthesis = match arbiter {
+            Some(arbiter) => {
+                let step = PipelineStep::new(arbiter, arbiter_prompt(prompt, &answers));
+                let verdict = self.execute_step(&step, &Context::new(), 0, false).await.response?;
+                Some(Synthesis::parse(&verdict.content))
+            }
+            None => None,
+        };
+        Ok(ConsensusReport { prompt: prompt.to_string(), answers, arbiter: arbiter.map(str::to_string), synthesis })
+    }
+}
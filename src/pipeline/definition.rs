@@ -0,0 +1,416 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::tools::{self, ToolRegistry, ToolsDefinition};
+use super::{BestOfStep, JudgeMode, MapSource, MapStep, PipelineParser, PipelineStep, best_of, map, transform};
+use crate::context::ContextPolicy;
+use crate::providers::{ProviderId, ProviderOptions, image};
+
+/// A named, storable pipeline definition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// System prompt for every step that does not set its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub steps: Vec<StepDefinition>,
+}
+
+/// Candidates a stored `bestof` step samples and the provider that judges them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BestOfDefinition {
+    /// Number of samples (default: one per candidate)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+    pub judge: String,
+    /// `pick` (default) or `merge`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    pub candidates: Vec<StepDefinition>,
+}
+
+/// A single step of a stored pipeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepDefinition {
+    pub provider: String,
+    /// Providers tried in order if `provider` still fails after retries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<String>,
+    /// Empty for map steps
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Transform spec, e.g. `summarizer:200` or `json_extractor:data`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Step-scoped variables available as `{{env.NAME}}` in the action and context
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Generation parameters (temperature, max_tokens, ...) for this step
+    #[serde(default, skip_serializing_if = "ProviderOptions::is_empty")]
+    pub options: ProviderOptions,
+    /// Sub-pipeline of a `map` step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<MapDefinition>,
+    /// Candidates and judge of a `bestof` step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<BestOfDefinition>,
+    /// Built-in tools the model may call during this step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<ToolsDefinition>,
+    /// Image files sent with the step's prompt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<PathBuf>,
+    /// Per-attempt timeout, overriding the executor's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Retry count, overriding the executor's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<usize>,
+    /// Conversation history sent with the step: `full`, `last:N`, `summary` or `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_policy: Option<ContextPolicy>,
+}
+
+/// Sub-pipeline a stored `map` step runs once per element of the previous output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapDefinition {
+    /// Elements processed at once (default 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// `chunks` to iterate the context's files instead of the previous output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub over: Option<MapSource>,
+    pub steps: Vec<StepDefinition>,
+}
+
+impl StepDefinition {
+    /// Create a step definition without context or transform
+    pub fn new(provider: impl Into<String>, action: impl Into<String>) -> Self {
+        Self {
+            provider: provider.into(),
+            fallbacks: Vec::new(),
+            action: action.into(),
+            context: None,
+            transform: None,
+            env: BTreeMap::new(),
+            options: ProviderOptions::default(),
+            map: None,
+            best_of: None,
+            tools: None,
+            images: Vec::new(),
+            timeout_ms: None,
+            retries: None,
+            context_policy: None,
+        }
+    }
+
+    /// Create a map step definition
+    pub fn map(steps: Vec<StepDefinition>, jobs: Option<usize>) -> Self {
+        Self { map: Some(MapDefinition { jobs, over: None, steps }), ..Self::new(map::MAP_PROVIDER, "") }
+    }
+
+    /// Create a best-of step definition
+    pub fn best_of(candidates: Vec<StepDefinition>, judge: impl Into<String>, n: Option<usize>, mode: Option<String>) -> Self {
+        Self {
+            best_of: Some(BestOfDefinition { n, judge: judge.into(), mode, candidates }),
+            ..Self::new(best_of::BEST_OF_PROVIDER, "")
+        }
+    }
+
+    /// Definition of `step`; transforms are stored as their spec
+    ///
+    /// Tools are left out: a step only holds their live handler, not the `tools` section.
+    pub fn from_step(step: &PipelineStep) -> Self {
+        if let Some(best_of) = step.best_of_step() {
+            return Self::best_of(
+                best_of.candidates.iter().map(Self::from_step).collect(),
+                best_of.judge.clone(),
+                (best_of.n != best_of.candidates.len()).then_some(best_of.n),
+                (best_of.mode != JudgeMode::Pick).then(|| best_of.mode.to_string()),
+            );
+        }
+        match step.map_step() {
+            Some(map) => {
+                let mut def = Self::map(map.steps.iter().map(Self::from_step).collect(), (map.jobs > 1).then_some(map.jobs));
+                if let Some(definition) = def.map.as_mut() {
+                    definition.over = (map.over != MapSource::Output).then_some(map.over);
+                }
+                def
+            }
+            None => StepDefinition {
+                context: step.get_context(),
+                transform: step.get_transform().map(|transform| transform.spec()),
+                env: step.env().iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+                options: step.options().clone(),
+                fallbacks: step.fallbacks().to_vec(),
+                images: step.images().to_vec(),
+                timeout_ms: step.timeout().map(|timeout| timeout.as_millis() as u64),
+                retries: step.retries(),
+                context_policy: step.context_policy(),
+                ..StepDefinition::new(step.provider.clone(), step.action.clone())
+            },
+        }
+    }
+
+    /// Executable step, with `system` as the system prompt unless the step sets one
+    pub fn to_step(&self, system: Option<&str>) -> Result<PipelineStep> {
+        self.to_step_in(system, &tools::default_root())
+    }
+
+    /// Executable step whose tools are rooted at `root`
+    pub fn to_step_in(&self, system: Option<&str>, root: &Path) -> Result<PipelineStep> {
+        if let Some(map) = &self.map {
+            let steps = map.steps.iter().map(|sub| sub.to_step_in(system, root)).collect::<Result<_>>()?;
+            return Ok(PipelineStep::map(
+                MapStep::new(steps).with_jobs(map.jobs.unwrap_or(1)).with_source(map.over.unwrap_or_default()),
+            ));
+        }
+        if let Some(def) = &self.best_of {
+            let candidates = def.candidates.iter().map(|c| c.to_step_in(system, root)).collect::<Result<Vec<_>>>()?;
+            let mut best_of = BestOfStep::new(candidates, ProviderId::canonical(&def.judge))
+                .with_mode(def.mode.as_deref().unwrap_or("pick").parse()?);
+            if let Some(n) = def.n {
+                best_of = best_of.with_samples(n);
+            }
+            return Ok(PipelineStep::best_of(best_of));
+        }
+        let mut step = PipelineStep::new(ProviderId::canonical(&self.provider), self.action.clone());
+        if let Some(context) = &self.context {
+            step.set_context(context.clone());
+        }
+        if let Some(spec) = &self.transform {
+            step.set_transform(transform::from_spec(spec)?);
+        }
+        for (key, value) in &self.env {
+            step.set_env(key.clone(), value.clone());
+        }
+        let mut options = self.options.clone();
+        if options.system.is_none() {
+            options.system = system.map(str::to_string);
+        }
+        step.set_options(options);
+        step.set_fallbacks(self.fallbacks.iter().map(|name| ProviderId::canonical(name).to_string()).collect());
+        step.set_images(self.images.clone());
+        if let Some(tools) = &self.tools {
+            step.set_tools(ToolRegistry::from_definition(root, tools)?.toolset());
+        }
+        if let Some(ms) = self.timeout_ms {
+            step.set_timeout(Duration::from_millis(ms));
+        }
+        if let Some(retries) = self.retries {
+            step.set_retries(retries);
+        }
+        if let Some(policy) = self.context_policy {
+            step.set_context_policy(policy);
+        }
+        Ok(step)
+    }
+}
+
+/// Steps serialize as their [`StepDefinition`]; transforms resolve through [`transform::from_spec`]
+impl Serialize for PipelineStep {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.tools().is_some() {
+            return Err(serde::ser::Error::custom(
+                "steps with tools cannot be serialized; use a tools section in a pipeline definition",
+            ));
+        }
+        StepDefinition::from_step(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PipelineStep {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        StepDefinition::deserialize(deserializer)?.to_step(None).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+impl PipelineDefinition {
+    /// Create an empty definition with a name
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            system: None,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Build a definition from a chain DSL string
+    pub fn from_chain(name: impl Into<String>, chain: &str) -> Result<Self> {
+        let steps = PipelineParser::parse(chain)?
+            .into_iter()
+            .map(|step| StepDefinition::from_step(&step))
+            .collect();
+        Ok(Self {
+            name: name.into(),
+            description: None,
+            system: None,
+            steps,
+        })
+    }
+
+    /// Render the provider/action chain in DSL form
+    pub fn to_chain(&self) -> String {
+        chain_of(&self.steps)
+    }
+
+    /// Render the full definition as YAML
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// Parse a definition from YAML
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Check the definition is well formed and uses known providers and transforms
+    pub fn validate(&self) -> Result<()> {
+        validate_name(&self.name)?;
+
+        if self.steps.is_empty() {
+            return Err(anyhow!("Pipeline '{}' has no steps", self.name));
+        }
+
+        validate_steps(&self.steps, "")?;
+
+        // The chain form must round-trip through the DSL parser
+        let parsed = PipelineParser::parse(&self.to_chain())?;
+        if parsed.len() != self.steps.len() {
+            return Err(anyhow!("Pipeline '{}' does not round-trip through the chain syntax", self.name));
+        }
+
+        Ok(())
+    }
+
+    /// Convert into executable pipeline steps, with tools rooted at the current directory
+    pub fn to_steps(&self) -> Result<Vec<PipelineStep>> {
+        self.to_steps_in(&tools::default_root())
+    }
+
+    /// Convert into executable pipeline steps whose tools are rooted at `root`
+    pub fn to_steps_in(&self, root: &Path) -> Result<Vec<PipelineStep>> {
+        self.steps.iter().map(|def| def.to_step_in(self.system.as_deref(), root)).collect()
+    }
+}
+
+/// Render steps in DSL form, map steps as `map[jobs=N,over=chunks](...)`
+fn chain_of(steps: &[StepDefinition]) -> String {
+    steps
+        .iter()
+        .map(|step| match (&step.map, &step.best_of) {
+            (Some(MapDefinition { jobs, over, steps }), _) => {
+                let mut options = Vec::new();
+                if let Some(jobs) = jobs.filter(|jobs| *jobs > 1) {
+                    options.push(format!("jobs={}", jobs));
+                }
+                if let Some(over) = over.filter(|over| *over != MapSource::Output) {
+                    options.push(format!("over={}", over));
+                }
+                if options.is_empty() {
+                    format!("{}({})", map::MAP_PROVIDER, chain_of(steps))
+                } else {
+                    format!("{}[{}]({})", map::MAP_PROVIDER, options.join(","), chain_of(steps))
+                }
+            }
+            (None, Some(best_of)) => {
+                let candidates: Vec<String> = best_of.candidates.iter()
+                    .map(|c| chain_of(std::slice::from_ref(c)))
+                    .collect();
+                let mut args = format!("{}, judge={}", best_of.n.unwrap_or(best_of.candidates.len()), best_of.judge);
+                if let Some(mode) = best_of.mode.as_deref().filter(|m| *m != "pick") {
+                    args.push_str(&format!(", mode={}", mode));
+                }
+                format!("{}({}){{ {} }}", best_of::BEST_OF_PROVIDER, args, candidates.join(" ; "))
+            }
+            (None, None) => {
+                let providers: Vec<&str> = std::iter::once(&step.provider).chain(&step.fallbacks).map(String::as_str).collect();
+                format!("{}:{}", providers.join("|"), PipelineParser::quote_action(&step.action))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Check each step, numbering nested map steps like `2.1`
+fn validate_steps(steps: &[StepDefinition], prefix: &str) -> Result<()> {
+    for (index, step) in steps.iter().enumerate() {
+        let label = format!("{}{}", prefix, index + 1);
+        if let Some(map) = &step.map {
+            if map.steps.is_empty() {
+                return Err(anyhow!("Step {}: map step has no steps", label));
+            }
+            if map.jobs == Some(0) {
+                return Err(anyhow!("Step {}: map jobs must be at least 1", label));
+            }
+            validate_steps(&map.steps, &format!("{}.", label))?;
+            continue;
+        }
+        if let Some(best_of) = &step.best_of {
+            if best_of.candidates.is_empty() {
+                return Err(anyhow!("Step {}: bestof step has no candidates", label));
+            }
+            if best_of.n == Some(0) {
+                return Err(anyhow!("Step {}: bestof needs at least 1 sample", label));
+            }
+            if let Err(e) = best_of.judge.parse::<ProviderId>() {
+                return Err(anyhow!("Step {}: judge: {}", label, e));
+            }
+            if let Some(mode) = &best_of.mode {
+                mode.parse::<JudgeMode>().map_err(|e| anyhow!("Step {}: {}", label, e))?;
+            }
+            if best_of.candidates.iter().any(|c| c.map.is_some() || c.best_of.is_some()) {
+                return Err(anyhow!("Step {}: bestof candidates must be provider:action steps", label));
+            }
+            validate_steps(&best_of.candidates, &format!("{}.", label))?;
+            continue;
+        }
+        for provider in std::iter::once(&step.provider).chain(&step.fallbacks) {
+            if let Err(e) = provider.parse::<ProviderId>() {
+                return Err(anyhow!("Step {}: {}", label, e));
+            }
+        }
+        if step.action.trim().is_empty() {
+            return Err(anyhow!("Step {}: action cannot be empty", label));
+        }
+        if step.action.contains("->") {
+            return Err(anyhow!("Step {}: action cannot contain '->'", label));
+        }
+        if let Some(spec) = &step.transform {
+            transform::from_spec(spec).map_err(|e| anyhow!("Step {}: {}", label, e))?;
+        }
+        if let Some(tools) = &step.tools {
+            tools.validate().map_err(|e| anyhow!("Step {}: {}", label, e))?;
+        }
+        if let Some(image) = step.images.iter().find(|path| image::media_type_for(path).is_none()) {
+            return Err(anyhow!("Step {}: {} is not a png, jpeg, gif or webp image", label, image.display()));
+        }
+        if step.env.keys().any(|key| key.trim().is_empty()) {
+            return Err(anyhow!("Step {}: environment variable names cannot be empty", label));
+        }
+        step.options.validate().map_err(|e| anyhow!("Step {}: {}", label, e))?;
+    }
+    Ok(())
+}
+
+/// Validate a pipeline name for use as a file name
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("Pipeline name cannot be empty"));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!(
+            "Invalid pipeline name '{}': use letters, digits, '-' or '_'",
+            name
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,400 @@
+//! Evaluation harness: run a pipeline over test cases and score the outputs
+//!
+//! Cases come from a JSONL file, one object per line:
+//! `{"id": ..., "input": ..., "env": {...}, "expected": ..., "contains": [...], "criteria": ...}`.
+//! Each case runs like a `--input-file` batch input. `expected` is compared
+//! with the final output using the chosen [`Metric`], every `contains` string
+//! must appear in it, and `criteria` is graded by a judge provider that
+//! replies PASS or FAIL. A case passes when its run succeeds and every check
+//! passes. With several variants (the chain run by claude, then by gemini)
+//! each case runs once per variant and the summary compares them.
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use super::{BatchInput, BatchResult, BatchRunner, PipelineExecutor, PipelineParser, PipelineStep};
+use crate::providers::Context;
+
+/// Similarity at or above which a `similarity` comparison passes by default
+pub const DEFAULT_THRESHOLD: f64 = 0.8;
+
+/// One test case
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EvalCase {
+    #[serde(default, deserialize_with = "id_string")]
+    pub id: Option<String>,
+    pub input: String,
+    /// Extra `{{env.NAME}}` variables for this case
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Output the pipeline should produce, compared with the [`Metric`]
+    #[serde(default)]
+    pub expected: Option<String>,
+    /// Strings the output must contain
+    #[serde(default)]
+    pub contains: Vec<String>,
+    /// What a good output does, graded by the judge
+    #[serde(default)]
+    pub criteria: Option<String>,
+}
+
+fn id_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<String>, D::Error> {
+    Ok(Option::<serde_json::Value>::deserialize(deserializer)?.map(|id| match id {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }))
+}
+
+impl EvalCase {
+    /// Parse one JSONL line
+    pub fn parse(line: &str) -> Result<Self> {
+        Ok(serde_json::from_str(line)?)
+    }
+
+    /// Read every non-blank line of a JSONL file
+    pub fn read_file(path: &Path) -> Result<Vec<Self>> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read cases file {}", path.display()))?;
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Self::parse(line).with_context(|| format!("{}:{}: invalid eval case", path.display(), i + 1)))
+            .collect()
+    }
+
+    fn batch_input(&self) -> BatchInput {
+        BatchInput { id: self.id.clone(), input: self.input.clone(), env: self.env.clone() }
+    }
+}
+
+/// How `expected` is compared with the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Same text, ignoring surrounding whitespace
+    Exact,
+    /// Output contains the expected text
+    #[default]
+    Contains,
+    /// Word overlap (F1) at or above the threshold
+    Similarity,
+}
+
+impl Metric {
+    /// Score from 0 to 1 of `output` against `expected`
+    pub fn score(self, output: &str, expected: &str) -> f64 {
+        let hit = |matched: bool| if matched { 1.0 } else { 0.0 };
+        match self {
+            Metric::Exact => hit(output.trim() == expected.trim()),
+            Metric::Contains => hit(output.contains(expected.trim())),
+            Metric::Similarity => word_f1(output, expected),
+        }
+    }
+}
+
+/// F1 of the lowercase words two texts share, counting repeats
+fn word_f1(output: &str, expected: &str) -> f64 {
+    let words = |text: &str| {
+        let mut counts = BTreeMap::new();
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            *counts.entry(word.to_lowercase()).or_insert(0usize) += 1;
+        }
+        counts
+    };
+    let (got, want) = (words(output), words(expected));
+    let (got_total, want_total) = (got.values().sum::<usize>(), want.values().sum::<usize>());
+    if got_total == 0 || want_total == 0 {
+        return if got_total == want_total { 1.0 } else { 0.0 };
+    }
+    let shared: usize = want.iter().map(|(word, n)| (*n).min(got.get(word).copied().unwrap_or(0))).sum();
+    if shared == 0 {
+        return 0.0;
+    }
+    let precision = shared as f64 / got_total as f64;
+    let recall = shared as f64 / want_total as f64;
+    2.0 * precision * recall / (precision + recall)
+}
+
+/// The pipeline under test, under a name shown in the summary
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub steps: Vec<PipelineStep>,
+}
+
+impl Variant {
+    /// The steps as written, named after their chain
+    pub fn chain(steps: Vec<PipelineStep>) -> Self {
+        Self { name: PipelineParser::format(&steps), steps }
+    }
+
+    /// The steps with every plain step sent to `provider` instead, named after it
+    pub fn with_provider(steps: &[PipelineStep], provider: &str) -> Self {
+        let steps = steps
+            .iter()
+            .cloned()
+            .map(|mut step| {
+                if step.composite.is_none() {
+                    step.provider = provider.to_string();
+                }
+                step
+            })
+            .collect();
+        Self { name: provider.to_string(), steps }
+    }
+}
+
+/// Outcome of one check of a case
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    /// `expected`, `contains` or `criteria`
+    pub check: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// One case run by one variant
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub variant: String,
+    /// Position of the case in the file, from 0
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    pub elapsed_ms: u64,
+}
+
+impl CaseResult {
+    /// Case id, or its 1-based line among the cases
+    pub fn label(&self) -> String {
+        self.id.clone().unwrap_or_else(|| format!("#{}", self.index + 1))
+    }
+}
+
+/// Totals of one variant over all cases
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantSummary {
+    pub variant: String,
+    pub cases: usize,
+    pub passed: usize,
+    /// Cases whose pipeline failed before it could be scored
+    pub errors: usize,
+    /// Mean `expected` score over the cases that have one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_score: Option<f64>,
+    pub cost_usd: f64,
+    pub mean_elapsed_ms: u64,
+}
+
+impl VariantSummary {
+    pub fn pass_rate(&self) -> f64 {
+        if self.cases == 0 { 0.0 } else { self.passed as f64 / self.cases as f64 }
+    }
+}
+
+/// Every case result and a summary per variant, in variant order
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub summary: Vec<VariantSummary>,
+    pub results: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    /// Summarize `results` per variant, keeping the order variants first appear in
+    pub fn new(results: Vec<CaseResult>) -> Self {
+        let mut summary: Vec<VariantSummary> = Vec::new();
+        for variant in results.iter().map(|r| &r.variant) {
+            if summary.iter().any(|s| s.variant == *variant) {
+                continue;
+            }
+            let runs: Vec<&CaseResult> = results.iter().filter(|r| r.variant == *variant).collect();
+            let scores: Vec<f64> = runs
+                .iter()
+                .flat_map(|r| r.checks.iter().filter(|c| c.check == "expected").filter_map(|c| c.score))
+                .collect();
+            summary.push(VariantSummary {
+                variant: variant.clone(),
+                cases: runs.len(),
+                passed: runs.iter().filter(|r| r.passed).count(),
+                errors: runs.iter().filter(|r| r.error.is_some()).count(),
+                mean_score: (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+                cost_usd: runs.iter().filter_map(|r| r.cost_usd).sum(),
+                mean_elapsed_ms: runs.iter().map(|r| r.elapsed_ms).sum::<u64>() / runs.len().max(1) as u64,
+            });
+        }
+        Self { summary, results }
+    }
+
+    /// Whether every case passed for every variant
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.summary.iter().map(|s| s.variant.chars().count()).max().unwrap_or(0).max("variant".len());
+        writeln!(f, "{:<width$}  {:>7}  {:>5}  {:>6}  {:>6}  {:>9}  {:>8}", "variant", "passed", "rate", "errors", "score", "cost", "avg ms")?;
+        for s in &self.summary {
+            let score = s.mean_score.map_or("-".to_string(), |score| format!("{:.2}", score));
+            writeln!(
+                f,
+                "{:<width$}  {:>7}  {:>4.0}%  {:>6}  {:>6}  {:>9}  {:>8}",
+                s.variant,
+                format!("{}/{}", s.passed, s.cases),
+                s.pass_rate() * 100.0,
+                s.errors,
+                score,
+                format!("${:.4}", s.cost_usd),
+                s.mean_elapsed_ms
+            )?;
+        }
+        let failures: Vec<&CaseResult> = self.results.iter().filter(|r| !r.passed).collect();
+        if !failures.is_empty() {
+            writeln!(f, "\nFailures:")?;
+        }
+        for result in failures {
+            let reasons: Vec<String> = match &result.error {
+                Some(error) => vec![format!("error: {}", error)],
+                None => result
+                    .checks
+                    .iter()
+                    .filter(|c| !c.passed)
+                    .map(|c| match &c.detail {
+                        Some(detail) => format!("{}: {}", c.check, detail),
+                        None => c.check.clone(),
+                    })
+                    .collect(),
+            };
+            writeln!(f, "  [{}] {}: {}", result.variant, result.label(), reasons.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prompt asking a judge whether `output` meets `criteria`
+pub fn judge_prompt(case: &EvalCase, output: &str, criteria: &str) -> String {
+    let expected = case.expected.as_deref().map(|e| format!("## Reference answer\n{}\n\n", e)).unwrap_or_default();
+    format!(
+        "Grade an assistant's output against the criteria below. \
+         Reply with PASS or FAIL on the first line, then one sentence explaining why.\n\n\
+         ## Criteria\n{}\n\n## Input\n{}\n\n{}## Output\n{}",
+        criteria, case.input, expected, output
+    )
+}
+
+/// Read a judge's reply: whether it starts with PASS, and the rest as the reason
+pub fn parse_verdict(reply: &str) -> (bool, String) {
+    let reply = reply.trim_start();
+    let word: String = reply.chars().take_while(|c| c.is_alphabetic()).collect();
+    let reason = reply[word.len()..].trim_start_matches([':', '-', '.', ' ']).trim();
+    (word.eq_ignore_ascii_case("pass"), reason.to_string())
+}
+
+/// Runs cases through variants and scores the outputs
+pub struct Evaluator<'a> {
+    executor: &'a PipelineExecutor,
+    jobs: usize,
+    metric: Metric,
+    threshold: f64,
+    judge: Option<String>,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Run at most `jobs` cases at a time through `executor`
+    pub fn new(executor: &'a PipelineExecutor, jobs: usize) -> Self {
+        Self { executor, jobs, metric: Metric::default(), threshold: DEFAULT_THRESHOLD, judge: None }
+    }
+
+    /// Compare `expected` with `metric`; scores below `threshold` fail
+    pub fn with_metric(mut self, metric: Metric, threshold: f64) -> Self {
+        self.metric = metric;
+        self.threshold = threshold;
+        self
+    }
+
+    /// Grade `criteria` with this provider
+    pub fn with_judge(mut self, judge: impl Into<String>) -> Self {
+        self.judge = Some(judge.into());
+        self
+    }
+
+    /// Run every case through every variant
+    ///
+    /// Fails up front when a case has criteria but no judge was set.
+    pub async fn run(&self, variants: &[Variant], context: &Context, cases: &[EvalCase]) -> Result<EvalReport> {
+        if self.judge.is_none()
+            && let Some(index) = cases.iter().position(|c| c.criteria.is_some())
+        {
+            return Err(anyhow!("Case {} has criteria but no judge provider was given", index + 1));
+        }
+        let runner = BatchRunner::new(self.executor, self.jobs);
+        let inputs: Vec<BatchInput> = cases.iter().map(EvalCase::batch_input).collect();
+        let mut results = Vec::new();
+        for variant in variants {
+            let batch: Vec<BatchResult> = runner.run(&variant.steps, context, inputs.clone()).collect().await;
+            for run in batch {
+                results.push(self.score(&variant.name, &cases[run.index], run).await);
+            }
+        }
+        Ok(EvalReport::new(results))
+    }
+
+    async fn score(&self, variant: &str, case: &EvalCase, run: BatchResult) -> CaseResult {
+        let mut result = CaseResult {
+            variant: variant.to_string(),
+            index: run.index,
+            id: run.id,
+            passed: false,
+            output: run.output,
+            error: run.error,
+            checks: Vec::new(),
+            cost_usd: run.cost_usd,
+            elapsed_ms: run.elapsed_ms,
+        };
+        let Some(output) = result.output.as_deref().filter(|_| run.ok) else {
+            return result;
+        };
+
+        if let Some(expected) = &case.expected {
+            let score = self.metric.score(output, expected);
+            let passed = score >= self.threshold.min(1.0);
+            let detail = (!passed).then(|| format!("score {:.2} below {:.2}", score, self.threshold));
+            result.checks.push(CheckResult { check: "expected".to_string(), passed, score: Some(score), detail });
+        }
+        for needle in &case.contains {
+            let passed = output.contains(needle.as_str());
+            let detail = (!passed).then(|| format!("'{}' missing", needle));
+            result.checks.push(CheckResult { check: "contains".to_string(), passed, score: None, detail });
+        }
+        if let (Some(criteria), Some(judge)) = (&case.criteria, &self.judge) {
+            let step = PipelineStep::new(judge.clone(), judge_prompt(case, output, criteria));
+            let check = match self.executor.execute_step(&step, &Context::new(), 0, false).await.response {
+                Ok(reply) => {
+                    let (passed, reason) = parse_verdict(&reply.content);
+                    CheckResult { check: "criteria".to_string(), passed, score: None, detail: (!reason.is_empty()).then_some(reason) }
+                }
+                Err(e) => CheckResult { check: "criteria".to_string(), passed: false, score: None, detail: Some(format!("judge failed: {:#}", e)) },
+            };
+            result.checks.push(check);
+        }
+        result.passed = result.checks.iter().all(|c| c.passed);
+        result
+    }
+}
@@ -0,0 +1,59 @@
+//! Progress events for library users that render a running pipeline
+//!
+//! Register a [`PipelineObserver`] with `PipelineExecutor::add_observer`, or
+//! call `PipelineExecutor::subscribe` for a channel of events. Observers run
+//! inline on the executor's task, so they should hand work off rather than block.
+
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::providers::Response;
+
+/// Something that happened while a pipeline ran
+///
+/// Steps inside `map` and `bestof` steps report their own events, indexed
+/// within their sub-pipeline.
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    StepStarted { step_index: usize, provider: String },
+    /// Text streamed by the provider; only sent when executing with streaming
+    Chunk { step_index: usize, text: String },
+    /// A failed attempt will be retried after `delay_ms`; `rate_limited` when the provider throttled it
+    RetryScheduled { step_index: usize, provider: String, attempt: usize, delay_ms: u64, rate_limited: bool, error: String },
+    StepCompleted {
+        step_index: usize,
+        provider: String,
+        elapsed_ms: u64,
+        retries: usize,
+        /// The step's response, or its error message
+        outcome: Result<Response, String>,
+    },
+    PipelineCompleted { steps: usize, elapsed_ms: u64, succeeded: bool },
+}
+
+/// Receives pipeline events as they happen
+pub trait PipelineObserver: Send + Sync {
+    fn on_event(&self, event: &PipelineEvent);
+}
+
+impl<F> PipelineObserver for F
+where
+    F: Fn(&PipelineEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &PipelineEvent) {
+        self(event)
+    }
+}
+
+/// Forwards events to a channel; events sent after the receiver is dropped are discarded
+impl PipelineObserver for mpsc::UnboundedSender<PipelineEvent> {
+    fn on_event(&self, event: &PipelineEvent) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// An observer feeding a new channel
+pub fn channel() -> (Arc<dyn PipelineObserver>, mpsc::UnboundedReceiver<PipelineEvent>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (Arc::new(sender), receiver)
+}
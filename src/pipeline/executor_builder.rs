@@ -0,0 +1,215 @@
+//! Fluent construction of a [`PipelineExecutor`]
+//!
+//! Everything an executor needs is given up front and the built executor is
+//! ready to share, e.g. behind an `Arc` across tasks:
+//!
+//! ```no_run
+//! # use std::sync::{Arc, PoisonError};
+//! # use ai_cli::pipeline::{ExecutionConfig, PipelineExecutor};
+//! # use ai_cli::providers::claude::ClaudeProvider;
+//! # use ai_cli::scheduler::RateLimit;
+//! let executor = PipelineExecutor::builder()
+//!     .provider("claude", Arc::new(ClaudeProvider::new("sk-...".to_string())))
+//!     .config(ExecutionConfig { max_retries: 2, ..ExecutionConfig::default() })
+//!     .rate_limit("claude", RateLimit { requests_per_minute: Some(50), max_concurrent: Some(4) })
+//!     .build();
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError};
+
+use super::{
+    ArtifactsDir, ExecutionConfig, PipelineExecutor, PipelineObserver, PromptAffixes, ProviderLimiter, SafetyFilter, StepCallback,
+    StepGate,
+};
+use crate::auth::AuthManager;
+use crate::history::audit::AuditLog;
+use crate::context::{Anonymizer, Compactor, ContextFilter, ContextFilters, ContextPolicy, Redactor, Retriever};
+use crate::providers::pricing::PricingTable;
+use crate::providers::probe::CapabilityCache;
+use crate::providers::{AIProvider, Capabilities, ProviderOptions};
+use crate::scheduler::RateLimit;
+
+/// Collects providers and settings for a [`PipelineExecutor`]
+pub struct PipelineExecutorBuilder {
+    executor: PipelineExecutor,
+    cache: Option<CapabilityCache>,
+}
+
+impl PipelineExecutorBuilder {
+    pub fn new() -> Self {
+        Self { executor: PipelineExecutor::new(), cache: None }
+    }
+
+    /// Register `provider` under `name`
+    pub fn provider(self, name: impl Into<String>, provider: Arc<dyn AIProvider>) -> Self {
+        self.executor.register_provider(name, provider);
+        self
+    }
+
+    /// Retries, delays, timeouts and error handling
+    pub fn config(self, config: ExecutionConfig) -> Self {
+        self.executor.set_config(config);
+        self
+    }
+
+    /// Credentials providers renew through
+    pub fn auth(mut self, auth: AuthManager) -> Self {
+        self.executor.set_auth_manager(auth);
+        self
+    }
+
+    /// Use capabilities already probed into `cache` for the registered providers
+    ///
+    /// Providers without a cached probe keep their static capabilities.
+    pub fn cache(mut self, cache: CapabilityCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Capabilities of `name`, overriding what the provider declares
+    pub fn capabilities(self, name: impl Into<String>, capabilities: Capabilities) -> Self {
+        self.executor.set_capabilities(name, capabilities);
+        self
+    }
+
+    /// Hold calls to `provider` to `limit`, across every pipeline the executor runs
+    pub fn rate_limit(mut self, provider: impl Into<String>, limit: RateLimit) -> Self {
+        self.executor.limiters.insert(provider.into(), Arc::new(ProviderLimiter::new(&limit)));
+        self
+    }
+
+    /// [`rate_limit`](Self::rate_limit) for each provider, e.g. from `[rate_limits]` in config
+    pub fn rate_limits(self, limits: &HashMap<String, RateLimit>) -> Self {
+        limits.iter().fold(self, |builder, (provider, limit)| builder.rate_limit(provider.clone(), limit.clone()))
+    }
+
+    /// Report progress events to `sink`, after any sinks added before
+    pub fn event_sink(mut self, sink: Arc<dyn PipelineObserver>) -> Self {
+        self.executor.add_observer(sink);
+        self
+    }
+
+    /// Call `callback` with every step's result
+    pub fn step_callback(mut self, callback: StepCallback) -> Self {
+        self.executor.set_step_callback(callback);
+        self
+    }
+
+    /// Review each step's output before the next step sees it
+    pub fn gate(mut self, gate: Arc<dyn StepGate>) -> Self {
+        self.executor.set_gate(gate);
+        self
+    }
+
+    /// Write each top-level step's output to numbered files in `artifacts`
+    pub fn artifacts_dir(mut self, artifacts: ArtifactsDir) -> Self {
+        self.executor.set_artifacts_dir(artifacts);
+        self
+    }
+
+    /// Prepend text to every step's prompt
+    pub fn prompt_prefix(mut self, prefix: Option<String>) -> Self {
+        self.executor.set_prompt_prefix(prefix);
+        self
+    }
+
+    /// Wrap every prompt sent to `provider` in `affixes`
+    pub fn provider_prompt(mut self, provider: impl Into<String>, affixes: PromptAffixes) -> Self {
+        self.executor.set_provider_prompt(provider, affixes);
+        self
+    }
+
+    /// Conversation history sent with every step; provider and step policies still win
+    pub fn context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.executor.set_context_policy(policy);
+        self
+    }
+
+    /// Conversation history sent to `provider`; step policies still win
+    pub fn provider_context_policy(mut self, provider: impl Into<String>, policy: ContextPolicy) -> Self {
+        self.executor.set_provider_context_policy(provider, policy);
+        self
+    }
+
+    /// Run `filter` on the context of every step sent to `provider`, after filters added before
+    pub fn context_filter(mut self, provider: impl Into<String>, filter: impl ContextFilter + 'static) -> Self {
+        self.executor.add_context_filter(provider, filter);
+        self
+    }
+
+    /// Replace the context filters, e.g. with `ContextFilters::builtin()`
+    pub fn context_filters(mut self, filters: ContextFilters) -> Self {
+        self.executor.set_context_filters(filters);
+        self
+    }
+
+    /// Summarize old messages before each step once the context is over `compactor`'s budget
+    pub fn compactor(mut self, compactor: Arc<Compactor>) -> Self {
+        self.executor.set_compactor(compactor);
+        self
+    }
+
+    /// Generation parameters for every step; step options still win
+    pub fn options(mut self, options: ProviderOptions) -> Self {
+        self.executor.set_options(options);
+        self
+    }
+
+    /// Redact secrets from prompts and context before every provider call
+    pub fn redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.executor.set_redactor(redactor);
+        self
+    }
+
+    /// Anonymize prompts and context before every provider call and restore replies
+    pub fn anonymizer(mut self, anonymizer: Arc<Anonymizer>) -> Self {
+        self.executor.set_anonymizer(anonymizer);
+        self
+    }
+
+    /// Check every prompt and reply against `safety`'s rules
+    pub fn safety_filter(mut self, safety: Arc<SafetyFilter>) -> Self {
+        self.executor.set_safety_filter(safety);
+        self
+    }
+
+    /// Append every provider call to `audit`
+    pub fn audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.executor.set_audit_log(audit);
+        self
+    }
+
+    /// Add index chunks relevant to each step's prompt to that step's context
+    pub fn retriever(mut self, retriever: Arc<Retriever>) -> Self {
+        self.executor.set_retriever(retriever);
+        self
+    }
+
+    /// Prices used to turn reported token usage into `cost_usd`
+    pub fn pricing(mut self, pricing: PricingTable) -> Self {
+        self.executor.set_pricing(pricing);
+        self
+    }
+
+    /// The executor, with cached capabilities applied
+    pub fn build(self) -> PipelineExecutor {
+        let mut executor = self.executor;
+        if let Some(cache) = &self.cache {
+            let providers = executor.providers.get_mut().unwrap_or_else(PoisonError::into_inner);
+            let capabilities = executor.capabilities.get_mut().unwrap_or_else(PoisonError::into_inner);
+            for (name, provider) in providers.iter() {
+                if let Some(entry) = cache.get(&CapabilityCache::key_for(provider.as_ref())) {
+                    capabilities.entry(name.clone()).or_insert_with(|| entry.capabilities.clone());
+                }
+            }
+        }
+        executor
+    }
+}
+
+impl Default for PipelineExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,156 @@
+//! Human-in-the-loop review between pipeline steps
+//!
+//! With a [`StepGate`] set on the executor, each step's output is reviewed
+//! before it is added to the context for the next step: it can be accepted,
+//! replaced with an edited version, re-run with an extra instruction, or the
+//! pipeline can be abandoned.
+
+use anyhow::{Context as _, Result, anyhow};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use super::PipelineStep;
+use crate::i18n::Msg;
+use crate::providers::Response;
+
+/// What to do with a step's output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Review {
+    Accept,
+    /// Use this text as the step's output instead
+    Edit(String),
+    /// Run the step again with this instruction added to its prompt
+    Retry(String),
+    Abort,
+}
+
+/// Reviews each step's output before the next step runs
+pub trait StepGate: Send + Sync {
+    fn review(&self, step_index: usize, step: &PipelineStep, response: &Response) -> Review;
+}
+
+impl<F> StepGate for F
+where
+    F: Fn(usize, &PipelineStep, &Response) -> Review + Send + Sync,
+{
+    fn review(&self, step_index: usize, step: &PipelineStep, response: &Response) -> Review {
+        self(step_index, step, response)
+    }
+}
+
+/// Shows the output on stderr and asks what to do; aborts when stdin is not a terminal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalGate;
+
+impl StepGate for TerminalGate {
+    fn review(&self, step_index: usize, step: &PipelineStep, response: &Response) -> Review {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            tracing::warn!(step = step_index + 1, "aborting: no terminal to confirm the step");
+            return Review::Abort;
+        }
+        eprintln!("\n{}\n{}\n", Msg::StepHeader(step_index + 1, &step.provider), response.content);
+        loop {
+            match ask(&Msg::ReviewChoice.to_string()).as_deref() {
+                Some("a" | "accept" | "") => return Review::Accept,
+                Some("e" | "edit") => match edit_in_editor(&response.content) {
+                    Ok(edited) => return Review::Edit(edited),
+                    Err(e) => eprintln!("{:#}", e),
+                },
+                Some("r" | "retry") => match ask(&Msg::ReviewTweak.to_string()) {
+                    Some(tweak) if !tweak.is_empty() => return Review::Retry(tweak),
+                    _ => eprintln!("{}", Msg::ReviewNothingToAdd),
+                },
+                Some("q" | "quit") | None => return Review::Abort,
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Opens every step's output but the last in the user's editor before the next step runs
+#[derive(Debug, Clone)]
+pub struct EditorGate {
+    editor: String,
+    steps: usize,
+}
+
+impl EditorGate {
+    /// Gate for a pipeline of `steps` steps using `$VISUAL`/`$EDITOR`
+    pub fn new(steps: usize) -> Self {
+        Self { editor: editor_command(), steps }
+    }
+
+    /// Use `editor` instead of the environment's editor
+    pub fn with_editor(mut self, editor: impl Into<String>) -> Self {
+        self.editor = editor.into();
+        self
+    }
+}
+
+impl StepGate for EditorGate {
+    fn review(&self, step_index: usize, _step: &PipelineStep, response: &Response) -> Review {
+        if step_index + 1 >= self.steps {
+            return Review::Accept;
+        }
+        match edit_with(&self.editor, &response.content) {
+            Ok(edited) if edited == response.content => Review::Accept,
+            Ok(edited) => Review::Edit(edited),
+            Err(e) => {
+                tracing::error!(step = step_index + 1, error = %format!("{:#}", e), "could not edit step output");
+                Review::Abort
+            }
+        }
+    }
+}
+
+/// Prompt on stderr and read a trimmed line; `None` at end of input
+fn ask(question: &str) -> Option<String> {
+    eprint!("{}", question);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    match std::io::stdin().lock().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_string()),
+    }
+}
+
+/// The user's editor command: `$VISUAL`, then `$EDITOR`, then `vi`
+pub fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Open `text` in the user's editor and return what was saved
+pub fn edit_in_editor(text: &str) -> Result<String> {
+    edit_with(&editor_command(), text)
+}
+
+/// Open `text` with `editor` (a program plus optional arguments, e.g. `code --wait`)
+pub fn edit_with(editor: &str, text: &str) -> Result<String> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("No editor configured"))?;
+    let path = scratch_path();
+    std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    let status = Command::new(program).args(words).arg(&path).status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let status = status.with_context(|| format!("Failed to start editor '{}'", program))?;
+    if !status.success() {
+        return Err(anyhow!("Editor '{}' exited with {}", program, status));
+    }
+    edited.with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn scratch_path() -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    std::env::temp_dir().join(format!("ai-cli-step-{}-{}.md", std::process::id(), nanos))
+}
@@ -0,0 +1,234 @@
+//! Commands, webhooks and desktop notifications fired when a pipeline finishes
+//!
+//! Each `[[hooks]]` entry runs on success, failure or both:
+//!
+//! ```toml
+//! [[hooks]]
+//! on = "failure"
+//! url = "https://hooks.slack.com/services/T000/B000/XXXX"
+//!
+//! [[hooks]]
+//! command = "echo \"$AI_CLI_STATUS $AI_CLI_CHAIN\" >> ~/pipelines.log"
+//! notify = true
+//! ```
+//!
+//! Commands get the [`RunOutcome`] as `AI_CLI_*` variables and as JSON on
+//! stdin; webhooks get it as the JSON body, with a Slack-compatible `text`.
+//! Hooks come from the user config only: a project `.ai-cli.toml` cannot
+//! declare them, so a cloned repository never runs commands or expands
+//! `${VAR}` secrets into URLs and headers.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::providers::http::{self, Transport};
+
+/// How long one hook may take before it is abandoned
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Characters of the final output included in the payload
+const OUTPUT_LIMIT: usize = 2000;
+
+/// Which outcomes fire a hook
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookTrigger {
+    Success,
+    Failure,
+    #[default]
+    Always,
+}
+
+/// One `[[hooks]]` entry; every action it sets runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HookSettings {
+    #[serde(default)]
+    pub on: HookTrigger,
+    /// Shell command (`sh -c`, `cmd /C` on Windows)
+    #[serde(default)]
+    pub command: Option<String>,
+    /// URL the outcome is POSTed to as JSON; `${VAR}` references are expanded
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Extra request headers for `url`, e.g. an authorization token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Show a desktop notification (notify-send, osascript)
+    #[serde(default)]
+    pub notify: bool,
+}
+
+/// What a hook is told about a finished run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunOutcome {
+    pub succeeded: bool,
+    /// Command that ran the pipeline (`pipeline`, `run`)
+    pub command: String,
+    pub chain: String,
+    /// Id of the run's artifacts, when recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    pub steps: usize,
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Start of the last step's output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+impl RunOutcome {
+    /// `success` or `failure`
+    pub fn status(&self) -> &'static str {
+        if self.succeeded { "success" } else { "failure" }
+    }
+
+    /// Keep the start of `output` for the payload
+    pub fn with_output(mut self, output: &str) -> Self {
+        self.output = Some(match output.char_indices().nth(OUTPUT_LIMIT) {
+            Some((end, _)) => format!("{}…", &output[..end]),
+            None => output.to_string(),
+        });
+        self
+    }
+
+    /// One-line summary used for notifications and Slack
+    pub fn summary(&self) -> String {
+        let seconds = self.elapsed_ms as f64 / 1000.0;
+        match &self.error {
+            Some(error) => format!("ai-cli {} failed after {:.1} s: {} ({})", self.command, seconds, error, self.chain),
+            None => format!("ai-cli {} succeeded in {:.1} s: {}", self.command, seconds, self.chain),
+        }
+    }
+
+    /// `AI_CLI_*` variables set for hook commands
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("AI_CLI_STATUS", self.status().to_string()),
+            ("AI_CLI_COMMAND", self.command.clone()),
+            ("AI_CLI_CHAIN", self.chain.clone()),
+            ("AI_CLI_STEPS", self.steps.to_string()),
+            ("AI_CLI_ELAPSED_MS", self.elapsed_ms.to_string()),
+        ];
+        env.extend(self.run_id.clone().map(|id| ("AI_CLI_RUN_ID", id)));
+        env.extend(self.error.clone().map(|error| ("AI_CLI_ERROR", error)));
+        env
+    }
+
+    /// JSON body POSTed to webhooks
+    pub fn payload(&self) -> serde_json::Value {
+        let mut payload = serde_json::to_value(self).unwrap_or_default();
+        payload["status"] = self.status().into();
+        payload["text"] = self.summary().into();
+        payload
+    }
+}
+
+impl HookSettings {
+    /// Whether this hook fires for `outcome`
+    pub fn applies_to(&self, outcome: &RunOutcome) -> bool {
+        match self.on {
+            HookTrigger::Always => true,
+            HookTrigger::Success => outcome.succeeded,
+            HookTrigger::Failure => !outcome.succeeded,
+        }
+    }
+
+    /// Run every action of this hook, reporting the first that failed
+    pub async fn fire(&self, outcome: &RunOutcome) -> Result<()> {
+        self.fire_via(http::default_transport().as_ref(), outcome).await
+    }
+
+    /// [`fire`](Self::fire), sending the webhook through `transport`
+    pub async fn fire_via(&self, transport: &dyn Transport, outcome: &RunOutcome) -> Result<()> {
+        if self.command.is_none() && self.url.is_none() && !self.notify {
+            return Err(anyhow!("hook has no command, url or notify"));
+        }
+        let mut result = Ok(());
+        if let Some(command) = &self.command {
+            result = result.and(run_command(command, outcome).await);
+        }
+        if let Some(url) = &self.url {
+            result = result.and(post(transport, &crate::config::expand_env(url), &self.headers, outcome).await);
+        }
+        if self.notify {
+            result = result.and(notify(outcome).await);
+        }
+        result
+    }
+}
+
+/// Fire the hooks that apply to `outcome`, one after another; returns the failures
+pub async fn fire_all(hooks: &[HookSettings], outcome: &RunOutcome) -> Vec<anyhow::Error> {
+    let mut errors = Vec::new();
+    for hook in hooks.iter().filter(|hook| hook.applies_to(outcome)) {
+        let fired = tokio::time::timeout(HOOK_TIMEOUT, hook.fire(outcome))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("hook did not finish within {} s", HOOK_TIMEOUT.as_secs())));
+        if let Err(e) = fired {
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+async fn run_command(command: &str, outcome: &RunOutcome) -> Result<()> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut child = tokio::process::Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .envs(outcome.env())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start hook `{}`", command))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores stdin may close it early
+        let _ = stdin.write_all(outcome.payload().to_string().as_bytes()).await;
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("hook `{}` exited with {}", command, status));
+    }
+    Ok(())
+}
+
+async fn post(transport: &dyn Transport, url: &str, headers: &BTreeMap<String, String>, outcome: &RunOutcome) -> Result<()> {
+    let mut request = http::shared_client().post(url).json(&outcome.payload());
+    for (name, value) in headers {
+        request = request.header(name, crate::config::expand_env(value));
+    }
+    let resp = http::send_via(transport, "hook", request)
+        .await
+        .with_context(|| "Failed to send webhook")?;
+    if !resp.status().is_success() {
+        return Err(http::error_for_status("hook", "Webhook rejected the payload", resp).await);
+    }
+    Ok(())
+}
+
+async fn notify(outcome: &RunOutcome) -> Result<()> {
+    let title = format!("ai-cli {}", outcome.status());
+    let body = outcome.summary();
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", body, title);
+        let mut command = tokio::process::Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else {
+        let mut command = tokio::process::Command::new("notify-send");
+        command.arg(&title).arg(&body);
+        command
+    };
+    let status = command.status().await.with_context(|| "Failed to show a desktop notification")?;
+    if !status.success() {
+        return Err(anyhow!("desktop notification failed with {}", status));
+    }
+    Ok(())
+}
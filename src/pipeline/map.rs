@@ -0,0 +1,259 @@
+//! `map` steps: fan a JSON array across a sub-pipeline
+//!
+//! In a chain, `map[jobs=4](gemini:review {{env.ITEM}} -> claude:summarize)`
+//! parses the previous step's output as a JSON array and runs the inner chain
+//! once per element, up to `jobs` at a time. Each run sees the element as a
+//! user message and as `{{env.ITEM}}` (with `{{env.ITEM_INDEX}}` from 0). The
+//! step's response is a JSON array of every run's final output, in element order.
+//!
+//! `map[over=chunks](claude:review)` iterates the files in the context instead,
+//! split along syntactic boundaries (see [`chunking`]). Each run sees only its
+//! chunk as a file, with the text in `{{env.ITEM}}` and its location in
+//! `{{env.ITEM_PATH}}` and `{{env.ITEM_LINES}}`.
+//!
+//! [`chunking`]: crate::context::chunking
+
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Instant;
+
+use super::{PipelineExecutor, PipelineStep, StepResult, TransformError};
+use crate::context::chunking::{Chunk, DEFAULT_CHUNK_BYTES, chunk_source};
+use crate::providers::{Context, Message, MessageRole, Response};
+
+/// Pseudo-provider name of a map step
+pub const MAP_PROVIDER: &str = "map";
+
+/// What a map step iterates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapSource {
+    /// Elements of the previous step's JSON array output
+    #[default]
+    Output,
+    /// Chunks of the files in the context
+    Chunks,
+}
+
+impl fmt::Display for MapSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MapSource::Output => "output",
+            MapSource::Chunks => "chunks",
+        })
+    }
+}
+
+impl FromStr for MapSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "output" => Ok(MapSource::Output),
+            "chunks" => Ok(MapSource::Chunks),
+            other => Err(anyhow!("Unknown map source '{}' (expected output or chunks)", other)),
+        }
+    }
+}
+
+/// Sub-pipeline a map step runs once per array element
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapStep {
+    pub steps: Vec<PipelineStep>,
+    /// Elements processed at once
+    pub jobs: usize,
+    pub over: MapSource,
+}
+
+impl MapStep {
+    pub fn new(steps: Vec<PipelineStep>) -> Self {
+        Self { steps, jobs: 1, over: MapSource::Output }
+    }
+
+    /// Process up to `jobs` elements at once
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Iterate `over` instead of the previous step's output
+    pub fn with_source(mut self, over: MapSource) -> Self {
+        self.over = over;
+        self
+    }
+
+    /// Options in DSL form, e.g. `jobs=4,over=chunks`; empty for the defaults
+    pub fn options(&self) -> String {
+        let mut options = Vec::new();
+        if self.jobs > 1 {
+            options.push(format!("jobs={}", self.jobs));
+        }
+        if self.over != MapSource::Output {
+            options.push(format!("over={}", self.over));
+        }
+        options.join(",")
+    }
+}
+
+/// Chunks of every file in `context`, by path; a file that fits in one chunk keeps its key
+pub fn context_chunks(context: &Context) -> Vec<(PathBuf, Chunk)> {
+    let mut files: Vec<_> = context.file_contents.iter().collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    files
+        .into_iter()
+        .flat_map(|(path, content)| {
+            let chunks = chunk_source(path, content, DEFAULT_CHUNK_BYTES);
+            let whole = chunks.len() == 1;
+            chunks.into_iter().map(move |chunk| (if whole { path.clone() } else { chunk.key() }, chunk))
+        })
+        .collect()
+}
+
+/// Elements of the JSON array in `text`, which may be wrapped in prose or a code fence
+pub fn parse_items(text: &str) -> Result<Vec<serde_json::Value>, TransformError> {
+    let trimmed = text.trim();
+    let parsed = serde_json::from_str(trimmed).or_else(|e| {
+        match (trimmed.find('['), trimmed.rfind(']')) {
+            (Some(start), Some(end)) if start < end => serde_json::from_str(&trimmed[start..=end]),
+            _ => Err(e),
+        }
+    });
+    match parsed {
+        Ok(serde_json::Value::Array(items)) => Ok(items),
+        Ok(_) => Err(TransformError::JsonParse("map step expected a JSON array from the previous step".to_string())),
+        Err(e) => Err(TransformError::JsonParse(format!("map step expected a JSON array from the previous step: {}", e))),
+    }
+}
+
+/// Context of the run for one array element
+fn item_context(context: &Context, text: String) -> Context {
+    let mut context = context.clone();
+    context.environment.insert("ITEM".to_string(), text.clone());
+    context.add_message(Message::new(MessageRole::User, text));
+    context
+}
+
+/// Context of the run for one chunk: the chunk replaces every file
+fn chunk_context(context: &Context, key: PathBuf, chunk: Chunk, index: usize, count: usize) -> Context {
+    let mut context = context.clone();
+    let provenance = context.file_provenance.get(&key)
+        .or_else(|| context.file_provenance.get(&chunk.path))
+        .cloned();
+    context.file_contents.clear();
+    context.file_provenance.clear();
+    context.current_files.clear();
+    let lines = format!("{}-{}", chunk.start_line, chunk.end_line);
+    context.environment.insert("ITEM".to_string(), chunk.text.clone());
+    context.environment.insert("ITEM_PATH".to_string(), chunk.path.display().to_string());
+    context.environment.insert("ITEM_LINES".to_string(), lines.clone());
+    context.add_message(Message::new(
+        MessageRole::User,
+        format!("Chunk {} of {}: {} lines {}", index + 1, count, chunk.path.display(), lines),
+    ));
+    match provenance {
+        Some(provenance) => context.add_file_with_provenance(key, chunk.text, provenance),
+        None => context.add_file_with_content(key, chunk.text),
+    }
+    context
+}
+
+/// Element as prompt text: strings unquoted, anything else as JSON
+fn item_text(item: &serde_json::Value) -> String {
+    match item {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Final output as an array element: JSON stays structured, anything else becomes a string
+fn output_value(content: &str) -> serde_json::Value {
+    serde_json::from_str(content.trim()).unwrap_or_else(|_| serde_json::Value::String(content.to_string()))
+}
+
+impl PipelineExecutor {
+    /// Run a map step over the last message in `context`
+    pub(super) async fn execute_map(
+        &self,
+        step: &PipelineStep,
+        map: &MapStep,
+        context: &Context,
+        step_index: usize,
+        streaming: bool,
+    ) -> StepResult {
+        let start_time = Instant::now();
+        let response = self.run_map(map, context, streaming).await.map(|response| {
+            response
+                .with_metadata("step_index", step_index.to_string())
+                .with_metadata("latency_ms", start_time.elapsed().as_millis().to_string())
+        });
+        StepResult {
+            step: step.clone(),
+            response,
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            retries: 0,
+            rate_limits: Vec::new(),
+        }
+    }
+
+    async fn run_map(&self, map: &MapStep, context: &Context, streaming: bool) -> Result<Response> {
+        let items = match map.over {
+            MapSource::Output => {
+                let source = context.conversation_history.last().map(|m| m.content.as_str()).unwrap_or_default();
+                parse_items(source)?.iter().map(|item| item_context(context, item_text(item))).collect()
+            }
+            MapSource::Chunks => {
+                let chunks = context_chunks(context);
+                if chunks.is_empty() {
+                    return Err(anyhow!("map over chunks found no files in the context"));
+                }
+                let count = chunks.len();
+                chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (key, chunk))| chunk_context(context, key, chunk, index, count))
+                    .collect::<Vec<_>>()
+            }
+        };
+        tracing::info!(items = items.len(), jobs = map.jobs, over = %map.over, "running map step");
+
+        // Owned per-item contexts keep the stream's closure free of borrowed arguments,
+        // so the future stays `Send` for callers such as the HTTP server
+        let count = items.len();
+        let runs: Vec<_> = futures::stream::iter(items.into_iter().enumerate())
+            .map(|(index, mut context)| {
+                context.environment.insert("ITEM_INDEX".to_string(), index.to_string());
+                // Boxed because the sub-pipeline may itself contain map steps
+                Box::pin(self.run_steps(&map.steps, context, streaming, false))
+            })
+            .buffered(map.jobs.max(1))
+            .collect()
+            .await;
+
+        let mut outputs = Vec::with_capacity(runs.len());
+        let mut cost = None;
+        for (index, run) in runs.into_iter().enumerate() {
+            let (responses, _) = run.map_err(|e| {
+                let message = format!("map item {}: {}", index + 1, e);
+                e.context(message)
+            })?;
+            let last = responses.last().ok_or_else(|| anyhow!("map sub-pipeline has no steps"))?;
+            outputs.push(output_value(&last.content));
+            for response in &responses {
+                if let Some(c) = response.metadata.get("cost_usd").and_then(|c| c.parse::<f64>().ok()) {
+                    *cost.get_or_insert(0.0) += c;
+                }
+            }
+        }
+
+        let mut response = Response::new(serde_json::Value::Array(outputs).to_string())
+            .with_metadata("map_items", count.to_string());
+        if let Some(cost) = cost {
+            response = response.with_metadata("cost_usd", format!("{:.6}", cost));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,139 @@
+//! Prometheus metrics for long-running use (`serve`, `pipeline --input-file`)
+//!
+//! [`Metrics`] is a [`PipelineObserver`]: registered on an executor it counts
+//! steps, retries, latency and tokens per provider, and renders them in the
+//! Prometheus text exposition format for a `/metrics` endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+use super::events::{PipelineEvent, PipelineObserver};
+use crate::providers::pricing::Usage;
+
+/// Upper bounds, in seconds, of the step latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 9] = [0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+#[derive(Debug, Default)]
+struct ProviderStats {
+    succeeded: u64,
+    failed: u64,
+    retries: u64,
+    rate_limited: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost_usd: f64,
+    /// Steps per latency bucket, by [`LATENCY_BUCKETS`] position, with `+Inf` last
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_seconds: f64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    providers: BTreeMap<String, ProviderStats>,
+    pipelines_succeeded: u64,
+    pipelines_failed: u64,
+}
+
+/// Counters collected from pipeline events
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        let providers = &state.providers;
+
+        header(&mut out, "ai_cli_requests_total", "counter", "Steps run, by provider and outcome");
+        for (provider, stats) in providers {
+            let _ = writeln!(out, "ai_cli_requests_total{{provider=\"{}\",status=\"ok\"}} {}", escape(provider), stats.succeeded);
+            let _ = writeln!(out, "ai_cli_requests_total{{provider=\"{}\",status=\"error\"}} {}", escape(provider), stats.failed);
+        }
+        header(&mut out, "ai_cli_retries_total", "counter", "Failed attempts that were retried, by provider and reason");
+        for (provider, stats) in providers {
+            let errors = stats.retries - stats.rate_limited;
+            let _ = writeln!(out, "ai_cli_retries_total{{provider=\"{}\",reason=\"error\"}} {}", escape(provider), errors);
+            let _ = writeln!(out, "ai_cli_retries_total{{provider=\"{}\",reason=\"rate_limited\"}} {}", escape(provider), stats.rate_limited);
+        }
+        header(&mut out, "ai_cli_tokens_total", "counter", "Tokens providers reported, by provider and kind");
+        for (provider, stats) in providers {
+            let _ = writeln!(out, "ai_cli_tokens_total{{provider=\"{}\",kind=\"prompt\"}} {}", escape(provider), stats.prompt_tokens);
+            let _ = writeln!(out, "ai_cli_tokens_total{{provider=\"{}\",kind=\"completion\"}} {}", escape(provider), stats.completion_tokens);
+        }
+        header(&mut out, "ai_cli_cost_usd_total", "counter", "Estimated spend in USD, by provider");
+        for (provider, stats) in providers {
+            let _ = writeln!(out, "ai_cli_cost_usd_total{{provider=\"{}\"}} {}", escape(provider), stats.cost_usd);
+        }
+        header(&mut out, "ai_cli_request_duration_seconds", "histogram", "Step latency including retries, by provider");
+        for (provider, stats) in providers {
+            let provider = escape(provider);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "ai_cli_request_duration_seconds_bucket{{provider=\"{}\",le=\"{}\"}} {}", provider, bound, cumulative);
+            }
+            let total = stats.succeeded + stats.failed;
+            let _ = writeln!(out, "ai_cli_request_duration_seconds_bucket{{provider=\"{}\",le=\"+Inf\"}} {}", provider, total);
+            let _ = writeln!(out, "ai_cli_request_duration_seconds_sum{{provider=\"{}\"}} {}", provider, stats.latency_seconds);
+            let _ = writeln!(out, "ai_cli_request_duration_seconds_count{{provider=\"{}\"}} {}", provider, total);
+        }
+        header(&mut out, "ai_cli_pipelines_total", "counter", "Pipelines run, by outcome");
+        let _ = writeln!(out, "ai_cli_pipelines_total{{status=\"ok\"}} {}", state.pipelines_succeeded);
+        let _ = writeln!(out, "ai_cli_pipelines_total{{status=\"error\"}} {}", state.pipelines_failed);
+        out
+    }
+}
+
+impl PipelineObserver for Metrics {
+    fn on_event(&self, event: &PipelineEvent) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match event {
+            PipelineEvent::RetryScheduled { provider, rate_limited, .. } => {
+                let stats = state.providers.entry(provider.clone()).or_default();
+                stats.retries += 1;
+                if *rate_limited {
+                    stats.rate_limited += 1;
+                }
+            }
+            PipelineEvent::StepCompleted { provider, elapsed_ms, outcome, .. } => {
+                let stats = state.providers.entry(provider.clone()).or_default();
+                match outcome {
+                    Ok(response) => {
+                        stats.succeeded += 1;
+                        if let Some(usage) = Usage::from_response(response) {
+                            stats.prompt_tokens += usage.prompt_tokens;
+                            stats.completion_tokens += usage.completion_tokens;
+                        }
+                        stats.cost_usd += response.metadata.get("cost_usd").and_then(|c| c.parse::<f64>().ok()).unwrap_or_default();
+                    }
+                    Err(_) => stats.failed += 1,
+                }
+                let seconds = *elapsed_ms as f64 / 1000.0;
+                let bucket = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+                stats.buckets[bucket] += 1;
+                stats.latency_seconds += seconds;
+            }
+            PipelineEvent::PipelineCompleted { succeeded: true, .. } => state.pipelines_succeeded += 1,
+            PipelineEvent::PipelineCompleted { succeeded: false, .. } => state.pipelines_failed += 1,
+            PipelineEvent::StepStarted { .. } | PipelineEvent::Chunk { .. } => {}
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
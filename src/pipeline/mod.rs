@@ -1,15 +1,78 @@
 use anyhow::{Result, anyhow};
+use std::borrow::Cow;
 use std::fmt;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
 
-use crate::providers::{AIProvider, Response, Context, Message, MessageRole};
+use crate::providers::{AIProvider, Capabilities, Image, Response, Context, Message, MessageRole, ProviderId, ProviderOptions, ToolCall, ToolHandler, Toolset};
+use crate::providers::id;
+use crate::providers::idempotency::IdempotencyKeys;
+use crate::history::audit::{AuditCall, AuditLog};
+use crate::providers::probe::CapabilityCache;
+use crate::providers::pricing::{PricingTable, Usage};
+use crate::providers::streaming;
 use crate::auth::AuthManager;
+use crate::context::{Anonymizer, Compactor, ContextFilter, ContextFilters, ContextPolicy, Provenance, Redactor, Retriever};
+use crate::error::{AuthError, ContextOverflowError, ProviderError};
+use crate::diagnostics::Diagnostic;
+use crate::i18n::Msg;
+use async_trait::async_trait;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use tracing::Instrument;
 
+pub mod artifacts;
+pub mod batch;
+pub mod batch_job;
+pub mod best_of;
+pub mod commit_msg;
+pub mod compare;
+pub mod consensus;
+pub mod definition;
+pub mod eval;
+pub mod events;
+pub mod executor_builder;
+pub mod gate;
+pub mod hooks;
+pub mod map;
+pub mod metrics;
+pub mod postmortem;
+pub mod review;
+pub mod safety;
+pub mod sandbox;
+pub mod store;
+pub mod template;
+pub mod tools;
 pub mod transform;
+pub mod validate;
+pub mod wizard;
+pub use artifacts::ArtifactsDir;
+pub use batch::{BatchInput, BatchResult, BatchRunner, ProviderLimiter};
+pub use batch_job::{BatchJob, BatchJobStore};
+pub use eval::{EvalCase, EvalReport, Evaluator, Metric, Variant};
+pub use definition::{BestOfDefinition, MapDefinition, PipelineDefinition, StepDefinition};
+pub use events::{PipelineEvent, PipelineObserver};
+pub use executor_builder::PipelineExecutorBuilder;
+pub use gate::{EditorGate, Review, StepGate, TerminalGate};
+pub use hooks::{HookSettings, HookTrigger, RunOutcome};
+pub use best_of::{BestOfStep, JudgeMode};
+pub use compare::{CompareReport, CompareSide, CompareView};
+pub use consensus::{ConsensusAnswer, ConsensusReport, Synthesis};
+pub use map::{MapSource, MapStep};
+pub use metrics::Metrics;
+pub use postmortem::{FailureKind, PipelineFailure};
+pub use safety::{SafetyAction, SafetyError, SafetyFilter, SafetyFinding, SafetyRule, SafetyScope, SafetySettings};
+pub use sandbox::{FileWriter, Sandbox};
+pub use store::PipelineStore;
+pub use validate::{Problem, ProblemKind, ValidationReport};
+pub use tools::{Approver, BuiltinTool, TerminalApprover, ToolRegistry, ToolsDefinition};
+pub use wizard::PipelineWizard;
 pub use transform::{
-    Transform, TransformError, IdentityTransform, JsonExtractorTransform, 
-    SummarizerTransform, FallbackBehavior, JsonExtractorConfig
+    Transform, TransformError, IdentityTransform, JsonExtractorTransform, MultiFileWriterTransform,
+    SummarizerTransform, FallbackBehavior, JsonExtractorConfig, TransformConstructor, TransformRegistry
 };
 
 /// Represents a single step in the pipeline
@@ -19,6 +82,22 @@ pub struct PipelineStep {
     pub action: String,
     context: Option<String>,
     transform: Option<Arc<dyn Transform>>,
+    env: HashMap<String, String>,
+    options: ProviderOptions,
+    fallbacks: Vec<String>,
+    tools: Option<Toolset>,
+    images: Vec<PathBuf>,
+    timeout: Option<Duration>,
+    retries: Option<usize>,
+    context_policy: Option<ContextPolicy>,
+    composite: Option<Composite>,
+}
+
+/// Step that runs other steps instead of calling its provider directly
+#[derive(Debug, Clone, PartialEq)]
+enum Composite {
+    Map(MapStep),
+    BestOf(BestOfStep),
 }
 
 impl PipelineStep {
@@ -29,9 +108,54 @@ impl PipelineStep {
             action: action.into(),
             context: None,
             transform: None,
+            env: HashMap::new(),
+            options: ProviderOptions::default(),
+            fallbacks: Vec::new(),
+            tools: None,
+            images: Vec::new(),
+            timeout: None,
+            retries: None,
+            context_policy: None,
+            composite: None,
         }
     }
     
+    /// Create a step that runs `map`'s sub-pipeline once per element of the previous output
+    pub fn map(map: MapStep) -> Self {
+        let mut step = Self::new(map::MAP_PROVIDER, PipelineParser::format(&map.steps));
+        step.composite = Some(Composite::Map(map));
+        step
+    }
+    
+    /// Create a step that samples several candidates and lets a judge pick or merge them
+    pub fn best_of(best_of: BestOfStep) -> Self {
+        let candidates: Vec<String> = best_of.candidates.iter().map(|c| c.to_string()).collect();
+        let mut step = Self::new(best_of::BEST_OF_PROVIDER, candidates.join(" ; "));
+        step.composite = Some(Composite::BestOf(best_of));
+        step
+    }
+    
+    /// Get the sub-pipeline if this is a map step
+    pub fn map_step(&self) -> Option<&MapStep> {
+        match &self.composite {
+            Some(Composite::Map(map)) => Some(map),
+            _ => None,
+        }
+    }
+    
+    /// Get the candidates and judge if this is a best-of step
+    pub fn best_of_step(&self) -> Option<&BestOfStep> {
+        match &self.composite {
+            Some(Composite::BestOf(best_of)) => Some(best_of),
+            _ => None,
+        }
+    }
+    
+    /// Check if this step runs other steps (map, best-of) rather than one provider call
+    pub fn is_composite(&self) -> bool {
+        self.composite.is_some()
+    }
+    
     /// Set context for this step
     pub fn set_context(&mut self, context: impl Into<String>) {
         self.context = Some(context.into());
@@ -68,6 +192,150 @@ impl PipelineStep {
     pub fn get_transform(&self) -> Option<Arc<dyn Transform>> {
         self.transform.clone()
     }
+    
+    /// Set a step-scoped environment variable, overriding the context's value
+    pub fn set_env(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.env.insert(key.into(), value.into());
+    }
+    
+    /// Create a step with a step-scoped environment variable
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_env(key, value);
+        self
+    }
+    
+    /// Get the step-scoped environment variables
+    pub fn env(&self) -> &HashMap<String, String> {
+        &self.env
+    }
+    
+    /// Set generation parameters for this step, overriding run and provider defaults
+    pub fn set_options(&mut self, options: ProviderOptions) {
+        self.options = options;
+    }
+    
+    /// Create a step with generation parameters
+    pub fn with_options(mut self, options: ProviderOptions) -> Self {
+        self.set_options(options);
+        self
+    }
+    
+    /// Get the step's generation parameters
+    pub fn options(&self) -> &ProviderOptions {
+        &self.options
+    }
+    
+    /// Try these providers in order if the step still fails after its retries
+    pub fn set_fallbacks(&mut self, fallbacks: Vec<String>) {
+        self.fallbacks = fallbacks;
+    }
+    
+    /// Create a step with fallback providers
+    pub fn with_fallbacks(mut self, fallbacks: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.set_fallbacks(fallbacks.into_iter().map(Into::into).collect());
+        self
+    }
+    
+    /// Get the fallback providers, in the order they are tried
+    pub fn fallbacks(&self) -> &[String] {
+        &self.fallbacks
+    }
+    
+    /// Let the model call these tools while it works on the step
+    pub fn set_tools(&mut self, tools: Toolset) {
+        self.tools = Some(tools);
+    }
+    
+    /// Create a step whose model may call tools
+    pub fn with_tools(mut self, tools: Toolset) -> Self {
+        self.set_tools(tools);
+        self
+    }
+    
+    /// Get the tools offered to the model
+    pub fn tools(&self) -> Option<&Toolset> {
+        self.tools.as_ref()
+    }
+    
+    /// Send these image files with the step's prompt
+    pub fn set_images(&mut self, images: Vec<PathBuf>) {
+        self.images = images;
+    }
+    
+    /// Create a step with attached images
+    pub fn with_images(mut self, images: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.set_images(images.into_iter().map(Into::into).collect());
+        self
+    }
+    
+    /// Get the image files sent with the prompt
+    pub fn images(&self) -> &[PathBuf] {
+        &self.images
+    }
+    
+    /// Call this step's model instead of the provider's configured one
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.options.model = Some(model.into());
+    }
+    
+    /// Create a step that calls a specific model
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.set_model(model);
+        self
+    }
+    
+    /// Get the model this step calls, if it overrides the provider's
+    pub fn model(&self) -> Option<&str> {
+        self.options.model.as_deref()
+    }
+    
+    /// Fail an attempt that takes longer than `timeout`, overriding the executor's `timeout_seconds`
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+    
+    /// Create a step with a per-attempt timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+    
+    /// Get the per-attempt timeout, if the step sets one
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    
+    /// Retry a failed attempt up to `retries` times, overriding the executor's `max_retries`
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = Some(retries);
+    }
+    
+    /// Create a step with its own retry count
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.set_retries(retries);
+        self
+    }
+    
+    /// Get the retry count, if the step sets one
+    pub fn retries(&self) -> Option<usize> {
+        self.retries
+    }
+    
+    /// Send this step `policy`'s share of the conversation, overriding the provider's and executor's
+    pub fn set_context_policy(&mut self, policy: ContextPolicy) {
+        self.context_policy = Some(policy);
+    }
+    
+    /// Create a step with its own context policy
+    pub fn with_context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.set_context_policy(policy);
+        self
+    }
+    
+    /// Get the context policy, if the step sets one
+    pub fn context_policy(&self) -> Option<ContextPolicy> {
+        self.context_policy
+    }
 }
 
 impl fmt::Debug for PipelineStep {
@@ -76,7 +344,16 @@ impl fmt::Debug for PipelineStep {
             .field("provider", &self.provider)
             .field("action", &self.action)
             .field("context", &self.context)
+            .field("env", &self.env)
+            .field("options", &self.options)
+            .field("fallbacks", &self.fallbacks)
+            .field("tools", &self.tools)
+            .field("images", &self.images)
+            .field("timeout", &self.timeout)
+            .field("retries", &self.retries)
+            .field("context_policy", &self.context_policy)
             .field("has_transform", &self.has_transform())
+            .field("composite", &self.composite)
             .finish()
     }
 }
@@ -86,13 +363,46 @@ impl PartialEq for PipelineStep {
         self.provider == other.provider 
             && self.action == other.action 
             && self.context == other.context
+            && self.env == other.env
+            && self.options == other.options
+            && self.fallbacks == other.fallbacks
+            && self.tools == other.tools
+            && self.images == other.images
+            && self.timeout == other.timeout
+            && self.retries == other.retries
+            && self.context_policy == other.context_policy
             && self.has_transform() == other.has_transform()
+            && self.composite == other.composite
     }
 }
 
 impl fmt::Display for PipelineStep {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{}", self.provider, self.action)
+        match &self.composite {
+            Some(Composite::Map(map)) => {
+                return match map.options() {
+                    options if options.is_empty() => write!(f, "{}({})", map::MAP_PROVIDER, self.action),
+                    options => write!(f, "{}[{}]({})", map::MAP_PROVIDER, options, self.action),
+                };
+            }
+            Some(Composite::BestOf(best_of)) => {
+                write!(f, "{}({}, judge={}", best_of::BEST_OF_PROVIDER, best_of.n, best_of.judge)?;
+                if best_of.mode != JudgeMode::Pick {
+                    write!(f, ", mode={}", best_of.mode)?;
+                }
+                return write!(f, "){{ {} }}", self.action);
+            }
+            None => {}
+        }
+        write!(f, "{}", self.provider)?;
+        for fallback in &self.fallbacks {
+            write!(f, "|{}", fallback)?;
+        }
+        if self.options.is_empty() {
+            write!(f, ":{}", PipelineParser::quote_action(&self.action))
+        } else {
+            write!(f, "[{}]:{}", self.options.to_assignments(), PipelineParser::quote_action(&self.action))
+        }
     }
 }
 
@@ -105,12 +415,119 @@ impl PipelineParser {
     /// # Format
     /// The pipeline format is: `provider:action -> provider:action -> ...`
     /// 
+    /// A provider may carry generation options: `claude[temperature=0.2,max_tokens=4000]:design`.
+    /// 
+    /// `claude|gemini:summarize` falls back to gemini if claude still fails after retries;
+    /// options follow the last provider (`claude|gemini[temperature=0.2]:summarize`).
+    /// 
+    /// `map[jobs=4](provider:action -> ...)` runs the parenthesized chain once per element
+    /// of the previous step's JSON array output, or with `over=chunks` once per chunk of
+    /// the context's files (see [`map`]).
+    /// 
+    /// `bestof(3, judge=claude){ gemini:implement ; codex:implement }` samples the candidate
+    /// steps three times in turn and lets the judge pick the best answer (see [`best_of`]).
+    /// 
+    /// An action in double quotes may contain `->`, `;` and unbalanced brackets: `claude:"compare A -> B"`.
+    /// Inside the quotes `\"` is a quote and `\\` a backslash.
+    /// 
     /// # Examples
     /// ```ignore
     /// let input = "claude:design -> gemini:implement -> codex:review";
     /// let steps = PipelineParser::parse(input).unwrap();
     /// ```
     pub fn parse(input: &str) -> Result<Vec<PipelineStep>> {
+        Self::parse_steps(input).map_err(|e| match e.downcast::<ChainError>() {
+            Ok(error) => error.locate(input).into(),
+            Err(e) => e,
+        })
+    }
+    
+    /// Parse a chain written one step per line; blank lines and lines starting with `#` are skipped
+    ///
+    /// A line may hold several `->`-separated steps and may start or end with `->`.
+    pub fn parse_lines(text: &str) -> Result<Vec<PipelineStep>> {
+        let mut steps = Vec::new();
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let step = line.trim();
+            let step = step.strip_prefix("->").unwrap_or(step);
+            let step = step.strip_suffix("->").unwrap_or(step).trim();
+            if step.is_empty() || step.starts_with('#') {
+                continue;
+            }
+            match Self::parse(step) {
+                Ok(parsed) => steps.extend(parsed),
+                // Point into the whole text so the diagnostic shows the line number
+                Err(e) => return Err(match e.downcast::<Diagnostic>() {
+                    Ok(diagnostic) => {
+                        let at = start + (step.as_ptr() as usize - line.as_ptr() as usize);
+                        let span = at + diagnostic.span.start..at + diagnostic.span.end;
+                        let mut located = Diagnostic::new(diagnostic.message, text, span);
+                        located.label = diagnostic.label;
+                        located.help = diagnostic.help;
+                        located.into()
+                    }
+                    Err(e) => e,
+                }),
+            }
+        }
+        if steps.is_empty() {
+            return Err(anyhow!("Pipeline file has no steps"));
+        }
+        Ok(steps)
+    }
+    
+    /// Read and parse a `--chain-file`
+    pub fn parse_file(path: &std::path::Path) -> Result<Vec<PipelineStep>> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse_lines(&text).map_err(|e| match e.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic.with_origin(path.display().to_string()).into(),
+            Err(e) => anyhow!("{}: {}", path.display(), e),
+        })
+    }
+    
+    /// Parse `input` and check that it names only `valid_providers`,
+    /// pointing at the offending name in the chain if not
+    pub fn parse_known(input: &str, valid_providers: &[&str]) -> Result<Vec<PipelineStep>> {
+        let steps = Self::parse(input)?;
+        if let Err(error) = Self::validate_providers(&steps, valid_providers) {
+            let unknown = Self::providers(&steps).into_iter().find(|name| !valid_providers.contains(&name.as_str()));
+            let span = unknown.as_deref().and_then(|name| find_word(input, name));
+            return Err(match (unknown, span) {
+                (Some(name), Some(span)) => {
+                    let mut diagnostic = Diagnostic::new(format!("Unknown provider '{}'", name), input, span)
+                        .with_label("unknown provider");
+                    diagnostic = match id::closest(&name, valid_providers.iter().copied()) {
+                        Some(suggestion) => diagnostic.with_help(format!("did you mean '{}'?", suggestion)),
+                        None => diagnostic.with_help(format!("use one of: {}", valid_providers.join(", "))),
+                    };
+                    diagnostic.into()
+                }
+                _ => error,
+            });
+        }
+        Ok(steps)
+    }
+    
+    /// Every provider a chain calls, including fallbacks, map steps and bestof candidates and judges
+    fn providers(steps: &[PipelineStep]) -> Vec<String> {
+        steps
+            .iter()
+            .flat_map(|step| match (step.map_step(), step.best_of_step()) {
+                (Some(map), _) => Self::providers(&map.steps),
+                (_, Some(best_of)) => {
+                    let mut names = Self::providers(&best_of.candidates);
+                    names.push(best_of.judge.clone());
+                    names
+                }
+                _ => std::iter::once(&step.provider).chain(&step.fallbacks).cloned().collect(),
+            })
+            .collect()
+    }
+    
+    fn parse_steps(input: &str) -> Result<Vec<PipelineStep>> {
         let trimmed = input.trim();
         
         if trimmed.is_empty() {
@@ -118,46 +535,283 @@ impl PipelineParser {
         }
         
         // Split by arrow separator and parse each step
-        trimmed
-            .split("->")
+        Self::split_steps(trimmed)?
+            .into_iter()
             .map(|part| Self::parse_step(part.trim()))
             .collect()
     }
     
+    /// Split a chain on top-level `->`, keeping each map step's parenthesized chain whole
+    fn split_steps(input: &str) -> Result<Vec<&str>> {
+        let mut parts = Vec::new();
+        let mut rest = input;
+        loop {
+            let step = rest.trim_start();
+            let close = match Self::map_body(step)? {
+                Some((_, close)) => Some(close),
+                None => Self::best_of_parts(step)?.map(|(_, body)| body.end),
+            };
+            let search_from = close.map_or(0, |close| rest.len() - step.len() + close);
+            match find_unquoted(&rest[search_from..], "->") {
+                Some(pos) => {
+                    parts.push(&rest[..search_from + pos]);
+                    rest = &rest[search_from + pos + 2..];
+                }
+                None => {
+                    parts.push(rest);
+                    return Ok(parts);
+                }
+            }
+        }
+    }
+    
+    /// Byte range of the parenthesized chain of a step starting `map(` or `map[...](`
+    fn map_body(step: &str) -> Result<Option<(usize, usize)>> {
+        let Some(after) = step.strip_prefix(map::MAP_PROVIDER) else { return Ok(None) };
+        let open = match after.chars().next() {
+            Some('(') => map::MAP_PROVIDER.len(),
+            Some('[') => match after.find("](") {
+                Some(pos) => map::MAP_PROVIDER.len() + pos + 1,
+                None => return Err(anyhow!("Invalid map step: '{}' (expected 'map[jobs=N](...)')", step)),
+            },
+            _ => return Ok(None),
+        };
+        let mut depth = 0;
+        for (pos, c) in unquoted(&step[open..]) {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(Some((open + 1, open + pos)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow!("Unclosed '(' in map step: '{}'", step))
+    }
+    
+    /// Byte ranges of the arguments and candidates of `bestof(...){ ... }`
+    fn best_of_parts(step: &str) -> Result<Option<(std::ops::Range<usize>, std::ops::Range<usize>)>> {
+        let prefix = best_of::BEST_OF_PROVIDER;
+        if !step.strip_prefix(prefix).is_some_and(|after| after.starts_with('(')) {
+            return Ok(None);
+        }
+        let args_end = step[prefix.len()..].find(')').map(|pos| prefix.len() + pos)
+            .ok_or_else(|| anyhow!("Unclosed '(' in bestof step: '{}'", step))?;
+        let open = step[args_end + 1..].find(|c: char| !c.is_whitespace()).map(|pos| args_end + 1 + pos)
+            .filter(|&open| step[open..].starts_with('{'))
+            .ok_or_else(|| anyhow!("Invalid bestof step: '{}' (expected 'bestof(N, judge=P){{ ... }}')", step))?;
+        let mut depth = 0;
+        for (pos, c) in unquoted(&step[open..]) {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(Some((prefix.len() + 1..args_end, open + 1..open + pos)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow!("Unclosed '{{' in bestof step: '{}'", step))
+    }
+    
+    /// Parse `bestof(N, judge=P[, mode=merge]){ candidate ; ... }`
+    fn parse_best_of_step(step_str: &str, args: std::ops::Range<usize>, body: std::ops::Range<usize>) -> Result<PipelineStep> {
+        if !step_str[body.end + 1..].trim().is_empty() {
+            return Err(anyhow!("Unexpected text after bestof step: '{}'", step_str));
+        }
+        let candidates = split_unquoted(&step_str[body], ';')
+            .into_iter()
+            .map(|part| {
+                let step = Self::parse_step(part.trim())?;
+                if step.is_composite() {
+                    return Err(anyhow!("bestof candidates must be provider:action steps: '{}'", part.trim()));
+                }
+                Ok(step)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        
+        let mut n = None;
+        let mut judge = None;
+        let mut mode = JudgeMode::Pick;
+        for arg in step_str[args].split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            match arg.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("judge", provider)) if !provider.is_empty() => judge = Some(ProviderId::canonical(provider).to_string()),
+                Some(("mode", value)) => mode = value.parse()?,
+                None => {
+                    n = Some(arg.parse::<usize>().ok().filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow!("Invalid bestof count '{}' (expected N >= 1)", arg))?);
+                }
+                _ => return Err(anyhow!("Invalid bestof option '{}' (expected N, judge=P or mode=pick|merge)", arg)),
+            }
+        }
+        let judge = judge.ok_or_else(|| anyhow!("bestof step needs a judge: '{}'", step_str))?;
+        let mut best_of = BestOfStep::new(candidates, judge).with_mode(mode);
+        if let Some(n) = n {
+            best_of = best_of.with_samples(n);
+        }
+        Ok(PipelineStep::best_of(best_of))
+    }
+    
+    /// Parse `map(...)` or `map[jobs=N](...)`
+    fn parse_map_step(step_str: &str, body_start: usize, body_end: usize) -> Result<PipelineStep> {
+        if !step_str[body_end + 1..].trim().is_empty() {
+            return Err(anyhow!("Unexpected text after map step: '{}'", step_str));
+        }
+        let mut map = MapStep::new(Self::parse_steps(&step_str[body_start..body_end])?);
+        if let Some(options) = step_str[map::MAP_PROVIDER.len()..body_start - 1].strip_prefix('[') {
+            let options = options.strip_suffix(']').unwrap_or(options);
+            for assignment in options.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                if let Some(over) = assignment.strip_prefix("over=") {
+                    map = map.with_source(over.trim().parse()?);
+                    continue;
+                }
+                let jobs = assignment
+                    .strip_prefix("jobs=")
+                    .and_then(|n| n.trim().parse::<usize>().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow!("Invalid map option '{}' (expected jobs=N with N >= 1 or over=chunks)", assignment))?;
+                map = map.with_jobs(jobs);
+            }
+        }
+        Ok(PipelineStep::map(map))
+    }
+    
     /// Parse a single pipeline step
     fn parse_step(step_str: &str) -> Result<PipelineStep> {
         if step_str.is_empty() {
-            return Err(anyhow!("Pipeline step cannot be empty"));
+            return Err(ChainError::at(step_str, "Pipeline step cannot be empty", "step expected here")
+                .with_help("remove the extra '->' or add a step such as 'claude:review'")
+                .into());
+        }
+        if let Some((body_start, body_end)) = Self::map_body(step_str)? {
+            return Self::parse_map_step(step_str, body_start, body_end);
+        }
+        if let Some((args, body)) = Self::best_of_parts(step_str)? {
+            return Self::parse_best_of_step(step_str, args, body);
         }
         
-        // Find the colon separator
-        let colon_pos = step_str.find(':')
-            .ok_or_else(|| anyhow!("Invalid pipeline step format: '{}' (missing ':')", step_str))?;
+        // Find the colon separator, skipping any `[options]` block (option values may contain ':')
+        let search_from = match (step_str.find('['), find_unquoted(step_str, ":")) {
+            (Some(open), Some(colon)) if open < colon => step_str[open..].find(']').map_or(0, |close| open + close),
+            _ => 0,
+        };
+        let colon_pos = find_unquoted(&step_str[search_from..], ":").map(|pos| search_from + pos)
+            .ok_or_else(|| {
+                let help = match step_str.parse::<ProviderId>() {
+                    Ok(_) => format!("add an action: '{}:<action>'", step_str),
+                    Err(_) => format!("name the provider first, e.g. 'claude:{}'", step_str),
+                };
+                ChainError::at(step_str, format!("Invalid pipeline step format: '{}' (missing ':')", step_str), "expected 'provider:action'")
+                    .with_help(help)
+            })?;
         
-        let provider = step_str[..colon_pos].trim();
-        let action = step_str[colon_pos + 1..].trim();
+        let (providers, options) = Self::parse_provider(step_str[..colon_pos].trim())?;
+        let action = Self::unquote_action(step_str[colon_pos + 1..].trim())?;
+        let action = action.as_ref();
+        let mut providers = providers.split('|').map(|name| ProviderId::canonical(name.trim()));
+        let provider = providers.next().unwrap_or_default();
+        let fallbacks: Vec<&str> = providers.collect();
         
         // Validate provider and action
-        if provider.is_empty() {
-            return Err(anyhow!("Provider cannot be empty in step: '{}'", step_str));
+        if provider.is_empty() || fallbacks.iter().any(|f| f.is_empty()) {
+            let help = if provider.is_empty() {
+                format!("put a provider before ':', e.g. 'claude:{}'", action)
+            } else {
+                "name a fallback provider after each '|', or remove the extra '|'".to_string()
+            };
+            return Err(ChainError::at(&step_str[..colon_pos], format!("Provider cannot be empty in step: '{}'", step_str), "provider expected here")
+                .with_help(help)
+                .into());
         }
         
         if action.is_empty() {
-            return Err(anyhow!("Action cannot be empty in step: '{}'", step_str));
+            return Err(ChainError::at(&step_str[colon_pos + 1..], format!("Action cannot be empty in step: '{}'", step_str), "action expected here")
+                .with_help(format!("add what {} should do after ':', e.g. '{}:review'", provider, provider))
+                .into());
         }
         
-        Ok(PipelineStep::new(provider, action))
+        Ok(PipelineStep::new(provider, action).with_options(options).with_fallbacks(fallbacks))
+    }
+    
+    /// The action text of `"..."`, unescaped; any other action unchanged
+    fn unquote_action(action: &str) -> Result<std::borrow::Cow<'_, str>> {
+        let Some(body) = action.strip_prefix('"') else {
+            return Ok(action.into());
+        };
+        let mut text = String::new();
+        let mut chars = body.char_indices();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                '"' => {
+                    let rest = &body[pos + 1..];
+                    if !rest.trim().is_empty() {
+                        return Err(ChainError::at(rest.trim(), "Unexpected text after quoted action", "after the closing quote")
+                            .with_help("put the whole action inside the quotes")
+                            .into());
+                    }
+                    return Ok(text.into());
+                }
+                _ => text.push(c),
+            }
+        }
+        Err(ChainError::at(action, "Unclosed quote in action", "quote opened here")
+            .with_help("end the action with '\"', writing quotes inside it as '\\\"'")
+            .into())
+    }
+    
+    /// `action` as written in a chain, quoted when it would not parse back unchanged
+    pub fn quote_action(action: &str) -> std::borrow::Cow<'_, str> {
+        let plain = !action.contains("->") && !action.contains(';') && !action.starts_with('"') && action.trim() == action;
+        if plain {
+            return action.into();
+        }
+        format!("\"{}\"", action.replace('\\', "\\\\").replace('"', "\\\"")).into()
+    }
+    
+    /// Split `provider[key=value,...]` into the provider name and its options
+    fn parse_provider(spec: &str) -> Result<(&str, ProviderOptions)> {
+        let Some(open) = spec.find('[') else {
+            return Ok((spec, ProviderOptions::default()));
+        };
+        let inner = spec[open + 1..].strip_suffix(']')
+            .ok_or_else(|| ChainError::at(&spec[open..], format!("Unclosed options in step provider: '{}'", spec), "unclosed '['")
+                .with_help("close the options with ']' before ':'"))?;
+        let options = ProviderOptions::parse_assignments(inner)
+            .map_err(|e| anyhow!("Invalid options for '{}': {}", spec, e))?;
+        Ok((spec[..open].trim(), options))
     }
     
     /// Validate that all providers in the pipeline are known
     pub fn validate_providers(steps: &[PipelineStep], valid_providers: &[&str]) -> Result<()> {
         for step in steps {
-            if !valid_providers.contains(&step.provider.as_str()) {
-                return Err(anyhow!(
-                    "Unknown provider: '{}'. Valid providers are: {:?}",
-                    step.provider,
-                    valid_providers
-                ));
+            if let Some(map) = step.map_step() {
+                Self::validate_providers(&map.steps, valid_providers)?;
+                continue;
+            }
+            if let Some(best_of) = step.best_of_step() {
+                let judge = PipelineStep::new(best_of.judge.clone(), "judge");
+                Self::validate_providers(&best_of.candidates, valid_providers)?;
+                Self::validate_providers(std::slice::from_ref(&judge), valid_providers)?;
+                continue;
+            }
+            for provider in std::iter::once(&step.provider).chain(&step.fallbacks) {
+                if !valid_providers.contains(&provider.as_str()) {
+                    let hint = id::closest(provider, valid_providers.iter().copied())
+                        .map(|name| format!(" Did you mean '{}'?", name))
+                        .unwrap_or_default();
+                    return Err(anyhow!(
+                        "Unknown provider: '{}'. Valid providers are: {:?}.{}",
+                        provider,
+                        valid_providers,
+                        hint
+                    ));
+                }
             }
         }
         Ok(())
@@ -173,6 +827,192 @@ impl PipelineParser {
     }
 }
 
+/// A parse error at a slice of the chain, turned into a [`Diagnostic`] over the whole chain by [`PipelineParser::parse`]
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+struct ChainError {
+    message: String,
+    /// Address range of the slice, since nested parsers only see part of the chain
+    addresses: std::ops::Range<usize>,
+    label: &'static str,
+    help: Option<String>,
+}
+
+impl ChainError {
+    fn at(slice: &str, message: impl Into<String>, label: &'static str) -> Self {
+        let start = slice.as_ptr() as usize;
+        Self { message: message.into(), addresses: start..start + slice.len(), label, help: None }
+    }
+    
+    fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+    
+    /// Locate the slice in `input`, which it was taken from
+    fn locate(self, input: &str) -> Diagnostic {
+        let base = input.as_ptr() as usize;
+        let span = self.addresses.start.saturating_sub(base)..self.addresses.end.saturating_sub(base);
+        let diagnostic = Diagnostic::new(self.message, input, span).with_label(self.label);
+        match self.help {
+            Some(help) => diagnostic.with_help(help),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Characters of a chain (with their byte offsets) outside quoted actions
+///
+/// A quote opens a quoted action only right after a step's `:`, so quotes inside
+/// unquoted actions (`claude:explain "foo"`) stay plain text.
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut after_colon = false;
+    text.char_indices().filter(move |&(_, c)| {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+            return false;
+        }
+        if c == '"' && after_colon {
+            quoted = true;
+            return false;
+        }
+        if !c.is_whitespace() {
+            after_colon = c == ':';
+        }
+        true
+    })
+}
+
+/// Byte offset of the first `pattern` outside quoted actions
+fn find_unquoted(text: &str, pattern: &str) -> Option<usize> {
+    unquoted(text).map(|(pos, _)| pos).find(|&pos| text[pos..].starts_with(pattern))
+}
+
+/// Split `text` on `separator` outside quoted actions
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (pos, _) in unquoted(text).filter(|&(_, c)| c == separator) {
+        parts.push(&text[start..pos]);
+        start = pos + separator.len_utf8();
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Byte range of the first occurrence of `word` in `text` not inside a longer name
+fn find_word(text: &str, word: &str) -> Option<std::ops::Range<usize>> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    text.match_indices(word)
+        .map(|(start, _)| start..start + word.len())
+        .find(|span| !text[..span.start].ends_with(is_name) && !text[span.end..].starts_with(is_name))
+}
+
+/// A step's tool handler as the executor hands it to the provider
+///
+/// Calls arrive with the model's view of the data, so anonymized values are restored
+/// before the tool runs; its output (or error) is then prepared like a prompt and audited.
+struct GuardedTools<'a> {
+    executor: &'a PipelineExecutor,
+    handler: &'a dyn ToolHandler,
+    provider: &'a str,
+    model: Option<&'a str>,
+    step: usize,
+    attempt: usize,
+    idempotency_key: Option<&'a str>,
+}
+
+#[async_trait]
+impl ToolHandler for GuardedTools<'_> {
+    async fn call(&self, call: &ToolCall) -> Result<String> {
+        let mut call = call.clone();
+        if let Some(anonymizer) = &self.executor.anonymizer {
+            anonymizer.restore_json(&mut call.input);
+        }
+        // Files read keep their own name, so redaction can tell configs from source
+        let location = match call.input["path"].as_str() {
+            Some(path) if call.name == BuiltinTool::ReadFile.name() => path.to_string(),
+            _ => format!("{} result", call.name),
+        };
+        let (output, failed) = match self.handler.call(&call).await {
+            Ok(output) => (output, false),
+            Err(e) => (format!("{:#}", e), true),
+        };
+        let sent = self.executor.prepare_tool_result(output, &location).map_err(anyhow::Error::from);
+        if let Some(audit) = &self.executor.audit {
+            let text = match &sent {
+                Ok(text) => text.as_str(),
+                Err(_) => "",
+            };
+            let audited = AuditCall {
+                provider: self.provider,
+                model: self.model,
+                step: self.step,
+                attempt: self.attempt,
+                idempotency_key: self.idempotency_key,
+                system: None,
+                prompt: text,
+            };
+            let error = if failed { Some(anyhow!("{}", text)) } else { None };
+            audit.record_tool(&audited, &call.name, error.as_ref().or(sent.as_ref().err()));
+        }
+        match sent {
+            Ok(text) if failed => Err(anyhow!(text)),
+            sent => sent,
+        }
+    }
+}
+
+/// First successful reply of a timed-out attempt within `wait`, if any
+async fn first_late_reply(late: &mut FuturesUnordered<BoxFuture<'_, Result<Response>>>, wait: Duration) -> Option<Response> {
+    let deadline = tokio::time::sleep(wait);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return None,
+            Some(reply) = late.next(), if !late.is_empty() => if let Ok(response) = reply {
+                return Some(response);
+            },
+        }
+    }
+}
+
+/// Run `call` until it replies or `timeout` passes, taking a late reply of an earlier attempt if one lands first
+///
+/// `None` means the attempt timed out; it is kept in `late` so its reply can still be used.
+async fn race_late_replies<'a>(
+    mut call: BoxFuture<'a, Result<Response>>,
+    late: &mut FuturesUnordered<BoxFuture<'a, Result<Response>>>,
+    timeout: Option<Duration>,
+) -> Option<Result<Response>> {
+    let deadline = async {
+        match timeout {
+            Some(limit) => tokio::time::sleep(limit).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            reply = &mut call => return Some(reply),
+            _ = &mut deadline => {
+                late.push(call);
+                return None;
+            }
+            Some(reply) = late.next(), if !late.is_empty() => if let Ok(response) = reply {
+                return Some(Ok(response));
+            },
+        }
+    }
+}
+
 /// Builder for creating pipelines programmatically
 pub struct PipelineBuilder {
     steps: Vec<PipelineStep>,
@@ -201,6 +1041,44 @@ impl PipelineBuilder {
         self
     }
     
+    /// Transform the last step's output before the next step sees it
+    pub fn transform(self, transform: Arc<dyn Transform>) -> Self {
+        self.update_last(|step| step.set_transform(transform))
+    }
+    
+    /// Call `model` for the last step instead of the provider's configured one
+    pub fn model(self, model: impl Into<String>) -> Self {
+        self.update_last(|step| step.set_model(model))
+    }
+    
+    /// Fail each attempt of the last step that takes longer than `timeout`
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.update_last(|step| step.set_timeout(timeout))
+    }
+    
+    /// Retry the last step up to `retries` times
+    pub fn retries(self, retries: usize) -> Self {
+        self.update_last(|step| step.set_retries(retries))
+    }
+    
+    /// Send the last step only `policy`'s share of the conversation
+    pub fn context_policy(self, policy: ContextPolicy) -> Self {
+        self.update_last(|step| step.set_context_policy(policy))
+    }
+    
+    /// Try `provider` if the last step still fails after its retries, after any fallbacks added before
+    pub fn fallback(self, provider: impl Into<String>) -> Self {
+        self.update_last(|step| step.fallbacks.push(provider.into()))
+    }
+    
+    /// Apply `update` to the most recently added step; does nothing before the first step
+    fn update_last(mut self, update: impl FnOnce(&mut PipelineStep)) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            update(step);
+        }
+        self
+    }
+    
     /// Build the pipeline
     pub fn build(self) -> Vec<PipelineStep> {
         self.steps
@@ -219,6 +1097,8 @@ pub struct ExecutionConfig {
     pub continue_on_error: bool,
     pub max_retries: usize,
     pub retry_delay_ms: u64,
+    /// Longest `Retry-After` a throttled step waits out; a longer one fails the step
+    pub max_retry_after_ms: u64,
     pub timeout_seconds: Option<u64>,
 }
 
@@ -228,11 +1108,23 @@ impl Default for ExecutionConfig {
             continue_on_error: false,
             max_retries: 0,
             retry_delay_ms: 1000,
+            max_retry_after_ms: 60_000,
             timeout_seconds: None,
         }
     }
 }
 
+/// An attempt the provider throttled (429)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitHit {
+    /// Attempt that was throttled, from 1
+    pub attempt: usize,
+    /// Delay the provider asked for, if it sent `Retry-After`
+    pub retry_after_ms: Option<u64>,
+    /// Time waited before the next attempt; 0 when no retry followed
+    pub waited_ms: u64,
+}
+
 /// Result of a single pipeline step execution
 #[derive(Debug)]
 pub struct StepResult {
@@ -240,6 +1132,8 @@ pub struct StepResult {
     pub response: Result<Response>,
     pub execution_time_ms: u64,
     pub retries: usize,
+    /// Throttled attempts, oldest first
+    pub rate_limits: Vec<RateLimitHit>,
 }
 
 impl StepResult {
@@ -262,43 +1156,121 @@ impl StepResult {
     pub fn get_error(&self) -> Option<&anyhow::Error> {
         self.response.as_ref().err()
     }
+    
+    /// Check if the step failed because the provider kept throttling it, rather than on a real error
+    pub fn is_rate_limited(&self) -> bool {
+        self.get_error().and_then(|e| e.downcast_ref::<ProviderError>()).is_some_and(ProviderError::is_rate_limited)
+    }
+}
+
+/// A request as it leaves the machine: redacted, checked against `[safety]` rules and anonymized
+#[derive(Debug)]
+pub struct PreparedRequest<'a> {
+    pub prompt: String,
+    pub system: Option<String>,
+    /// Borrowed when nothing had to change
+    pub context: Cow<'a, Context>,
+    /// Matches that were redacted or only warned about
+    pub safety_findings: Vec<SafetyFinding>,
+}
+
+/// Text wrapped around every prompt sent to one provider
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptAffixes {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
 }
 
 /// Callback for step execution events
 pub type StepCallback = Box<dyn Fn(&StepResult) + Send + Sync>;
 
 /// Pipeline execution engine
+///
+/// The provider registry, capabilities and execution config sit behind locks,
+/// so providers can be registered and settings changed through a shared
+/// reference (e.g. an `Arc` held by the server) while pipelines run. A running
+/// step keeps the provider and config it started with.
 pub struct PipelineExecutor {
-    providers: HashMap<String, Arc<dyn AIProvider>>,
+    providers: RwLock<HashMap<String, Arc<dyn AIProvider>>>,
+    capabilities: RwLock<HashMap<String, Capabilities>>,
     auth_manager: Option<AuthManager>,
-    config: ExecutionConfig,
+    config: RwLock<ExecutionConfig>,
     step_callback: Option<StepCallback>,
+    observers: Vec<Arc<dyn PipelineObserver>>,
+    gate: Option<Arc<dyn StepGate>>,
+    artifacts: Option<ArtifactsDir>,
+    file_writer: FileWriter,
+    prompt_prefix: Option<String>,
+    provider_prompts: HashMap<String, PromptAffixes>,
+    context_policy: ContextPolicy,
+    provider_context_policies: HashMap<String, ContextPolicy>,
+    context_filters: ContextFilters,
+    compactor: Option<Arc<Compactor>>,
+    options: ProviderOptions,
+    redactor: Option<Arc<Redactor>>,
+    anonymizer: Option<Arc<Anonymizer>>,
+    safety: Option<Arc<SafetyFilter>>,
+    retriever: Option<Arc<Retriever>>,
+    pricing: PricingTable,
+    limiters: HashMap<String, Arc<ProviderLimiter>>,
+    idempotency: IdempotencyKeys,
+    audit: Option<Arc<AuditLog>>,
 }
 
 impl PipelineExecutor {
     /// Create a new pipeline executor
     pub fn new() -> Self {
         Self {
-            providers: HashMap::new(),
+            providers: RwLock::default(),
+            capabilities: RwLock::default(),
             auth_manager: None,
-            config: ExecutionConfig::default(),
+            config: RwLock::default(),
             step_callback: None,
+            observers: Vec::new(),
+            gate: None,
+            artifacts: None,
+            file_writer: FileWriter::default(),
+            prompt_prefix: None,
+            provider_prompts: HashMap::new(),
+            context_policy: ContextPolicy::default(),
+            provider_context_policies: HashMap::new(),
+            context_filters: ContextFilters::new(),
+            compactor: None,
+            options: ProviderOptions::default(),
+            redactor: None,
+            anonymizer: None,
+            safety: None,
+            retriever: None,
+            pricing: PricingTable::default(),
+            limiters: HashMap::new(),
+            idempotency: IdempotencyKeys::new(),
+            audit: None,
         }
     }
     
     /// Create a new executor with configuration
     pub fn with_config(config: ExecutionConfig) -> Self {
-        Self {
-            providers: HashMap::new(),
-            auth_manager: None,
-            config,
-            step_callback: None,
-        }
+        Self { config: RwLock::new(config), ..Self::new() }
+    }
+    
+    /// Start building an executor with all its providers and settings at once
+    pub fn builder() -> PipelineExecutorBuilder {
+        PipelineExecutorBuilder::new()
+    }
+    
+    /// Register a provider, replacing any registered under `name`; steps already running keep the old one
+    pub fn register_provider(&self, name: impl Into<String>, provider: Arc<dyn AIProvider>) {
+        self.providers.write().unwrap_or_else(PoisonError::into_inner).insert(name.into(), provider);
+    }
+    
+    /// Remove a provider; steps already running on it finish
+    pub fn unregister_provider(&self, name: &str) -> Option<Arc<dyn AIProvider>> {
+        self.capabilities.write().unwrap_or_else(PoisonError::into_inner).remove(name);
+        self.providers.write().unwrap_or_else(PoisonError::into_inner).remove(name)
     }
     
-    /// Register a provider
-    pub fn register_provider(&mut self, name: impl Into<String>, provider: Arc<dyn AIProvider>) {
-        self.providers.insert(name.into(), provider);
+    fn registry(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<dyn AIProvider>>> {
+        self.providers.read().unwrap_or_else(PoisonError::into_inner)
     }
     
     /// Set authentication manager
@@ -306,19 +1278,115 @@ impl PipelineExecutor {
         self.auth_manager = Some(auth_manager);
     }
     
-    /// Update execution configuration
-    pub fn set_config(&mut self, config: ExecutionConfig) {
-        self.config = config;
+    /// Prepend text (e.g. a project's conventions) to every step's prompt
+    pub fn set_prompt_prefix(&mut self, prefix: Option<String>) {
+        self.prompt_prefix = prefix;
+    }
+    
+    /// Wrap every prompt sent to `provider` (including as a fallback) in `affixes`
+    pub fn set_provider_prompt(&mut self, provider: impl Into<String>, affixes: PromptAffixes) {
+        self.provider_prompts.insert(provider.into(), affixes);
+    }
+    
+    /// Conversation history sent with every step, unless its provider or the step sets a policy
+    pub fn set_context_policy(&mut self, policy: ContextPolicy) {
+        self.context_policy = policy;
+    }
+    
+    /// Conversation history sent to `provider` (including as a fallback), unless the step sets a policy
+    pub fn set_provider_context_policy(&mut self, provider: impl Into<String>, policy: ContextPolicy) {
+        self.provider_context_policies.insert(provider.into(), policy);
+    }
+    
+    /// Run `filter` on the context of every step sent to `provider`, after its context policy
+    pub fn add_context_filter(&mut self, provider: impl Into<String>, filter: impl ContextFilter + 'static) {
+        self.context_filters.register(provider, filter);
+    }
+    
+    /// Replace all context filters, e.g. with `ContextFilters::builtin()`
+    pub fn set_context_filters(&mut self, filters: ContextFilters) {
+        self.context_filters = filters;
+    }
+    
+    /// Summarize old messages with `compactor` before a step whenever the context is over its budget
+    pub fn set_compactor(&mut self, compactor: Arc<Compactor>) {
+        self.compactor = Some(compactor);
+    }
+    
+    /// Policy a step runs with: its own, else its provider's, else the executor's
+    pub fn context_policy_for(&self, step: &PipelineStep) -> ContextPolicy {
+        step.context_policy
+            .or_else(|| self.provider_context_policies.get(&step.provider).copied())
+            .unwrap_or(self.context_policy)
+    }
+    
+    /// Set generation parameters for every step (e.g. from CLI flags); step options still win
+    pub fn set_options(&mut self, options: ProviderOptions) {
+        self.options = options;
+    }
+    
+    /// Redact secrets from prompts and context before every provider call
+    pub fn set_redactor(&mut self, redactor: Arc<Redactor>) {
+        self.redactor = Some(redactor);
+    }
+    
+    /// Anonymize prompts and context before every provider call and restore replies
+    pub fn set_anonymizer(&mut self, anonymizer: Arc<Anonymizer>) {
+        self.anonymizer = Some(anonymizer);
+    }
+    
+    /// Check every prompt and reply against `safety`'s rules
+    pub fn set_safety_filter(&mut self, safety: Arc<SafetyFilter>) {
+        self.safety = Some(safety);
+    }
+    
+    /// Add index chunks relevant to each step's prompt to that step's context
+    pub fn set_retriever(&mut self, retriever: Arc<Retriever>) {
+        self.retriever = Some(retriever);
+    }
+    
+    /// Prices used to turn reported token usage into `cost_usd`
+    pub fn set_pricing(&mut self, pricing: PricingTable) {
+        self.pricing = pricing;
+    }
+    
+    /// Get the redactor applied before provider calls, if any
+    pub fn redactor(&self) -> Option<&Arc<Redactor>> {
+        self.redactor.as_ref()
+    }
+    
+    /// Get the anonymizer applied before provider calls, if any
+    pub fn anonymizer(&self) -> Option<&Arc<Anonymizer>> {
+        self.anonymizer.as_ref()
+    }
+    
+    /// Get the safety filter applied around provider calls, if any
+    pub fn safety_filter(&self) -> Option<&Arc<SafetyFilter>> {
+        self.safety.as_ref()
+    }
+    
+    /// Append every provider call to `audit`
+    pub fn set_audit_log(&mut self, audit: Arc<AuditLog>) {
+        self.audit = Some(audit);
+    }
+    
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit.as_ref()
+    }
+    
+    /// Update execution configuration; steps already running keep the old one
+    pub fn set_config(&self, config: ExecutionConfig) {
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
     }
     
     /// Set continue on error flag
-    pub fn set_continue_on_error(&mut self, continue_on_error: bool) {
-        self.config.continue_on_error = continue_on_error;
+    pub fn set_continue_on_error(&self, continue_on_error: bool) {
+        self.config.write().unwrap_or_else(PoisonError::into_inner).continue_on_error = continue_on_error;
     }
     
     /// Set maximum retries
-    pub fn set_max_retries(&mut self, max_retries: usize) {
-        self.config.max_retries = max_retries;
+    pub fn set_max_retries(&self, max_retries: usize) {
+        self.config.write().unwrap_or_else(PoisonError::into_inner).max_retries = max_retries;
     }
     
     /// Set step callback
@@ -326,50 +1394,317 @@ impl PipelineExecutor {
         self.step_callback = Some(callback);
     }
     
+    /// Report progress events to `observer` as well as any already added
+    pub fn add_observer(&mut self, observer: Arc<dyn PipelineObserver>) {
+        self.observers.push(observer);
+    }
+    
+    /// Receive progress events through a channel
+    pub fn subscribe(&mut self) -> tokio::sync::mpsc::UnboundedReceiver<PipelineEvent> {
+        let (observer, receiver) = events::channel();
+        self.add_observer(observer);
+        receiver
+    }
+    
+    /// Review each step's output before the next step sees it
+    pub fn set_gate(&mut self, gate: Arc<dyn StepGate>) {
+        self.gate = Some(gate);
+    }
+    
+    /// Write each top-level step's output to numbered files in `artifacts` as it completes
+    pub fn set_artifacts_dir(&mut self, artifacts: ArtifactsDir) {
+        self.artifacts = Some(artifacts);
+    }
+    
+    /// Where steps that write project files put them
+    pub fn set_file_writer(&mut self, writer: FileWriter) {
+        self.file_writer = writer;
+    }
+    
+    pub fn file_writer(&self) -> &FileWriter {
+        &self.file_writer
+    }
+    
+    fn emit(&self, event: PipelineEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+    
     /// Execute the pipeline
-    pub async fn execute(&self, steps: &[PipelineStep], mut context: Context) -> Result<Vec<Response>> {
+    pub async fn execute(&self, steps: &[PipelineStep], context: Context) -> Result<Vec<Response>> {
+        self.execute_with_context(steps, context).await.map(|(results, _)| results)
+    }
+    
+    /// Execute the pipeline and return the final context alongside the responses
+    pub async fn execute_with_context(&self, steps: &[PipelineStep], context: Context) -> Result<(Vec<Response>, Context)> {
+        self.run_pipeline(steps, context, false).await
+    }
+    
+    /// Run the steps and report the pipeline's completion
+    async fn run_pipeline(&self, steps: &[PipelineStep], context: Context, streaming: bool) -> Result<(Vec<Response>, Context)> {
+        let start_time = std::time::Instant::now();
+        let mut context = context;
         let mut results = Vec::new();
-        
-        for (step_index, step) in steps.iter().enumerate() {
-            let step_result = self.execute_step(step, &context, step_index).await;
-            
-            match &step_result.response {
-                Ok(response) => {
-                    // Update context with successful response
-                    context.add_message(Message::new(MessageRole::Assistant, response.content.clone()));
-                    results.push(response.clone());
+        let mut outcome = Ok(());
+        let span = tracing::info_span!("pipeline", steps = steps.len(), status = tracing::field::Empty);
+        async {
+            for (step_index, step) in steps.iter().enumerate() {
+                match self.run_step_at(step, step_index, &mut context, streaming, true).await {
+                    Ok(response) => results.push(response),
+                    Err(mut e) => {
+                        if let Some(failure) = e.downcast_mut::<PipelineFailure>() {
+                            failure.completed = std::mem::take(&mut results);
+                        }
+                        outcome = Err(e);
+                        break;
+                    }
                 }
-                Err(error) => {
-                    if !self.config.continue_on_error {
-                        return Err(anyhow!("Pipeline execution failed at step {}: {}", step_index + 1, error));
+            }
+            // Uploaded files and the like are cleaned up whether or not the run succeeded
+            self.release_context(&mut context).await;
+        }
+        .instrument(span.clone())
+        .await;
+        span.record("status", if outcome.is_ok() { "ok" } else { "error" });
+        self.emit(PipelineEvent::PipelineCompleted {
+            steps: steps.len(),
+            elapsed_ms: start_time.elapsed().as_millis() as u64,
+            succeeded: outcome.is_ok(),
+        });
+        outcome.map(|()| (results, context))
+    }
+    
+    /// Let every provider delete what it stored for `context`, e.g. uploaded files
+    ///
+    /// `execute` does this itself; callers driving steps with `run_step` call it when done.
+    pub async fn release_context(&self, context: &mut Context) {
+        let providers: Vec<(String, Arc<dyn AIProvider>)> = self.registry()
+            .iter()
+            .map(|(name, provider)| (name.clone(), provider.clone()))
+            .collect();
+        for (name, provider) in providers {
+            if let Err(e) = provider.release_context(context).await {
+                tracing::warn!(provider = %name, error = %format!("{:#}", e), "releasing provider-side context failed");
+            }
+        }
+    }
+    
+    /// Run `steps` in order; `top_level` is unset for the sub-pipelines of map steps
+    async fn run_steps(&self, steps: &[PipelineStep], mut context: Context, streaming: bool, top_level: bool) -> Result<(Vec<Response>, Context)> {
+        let mut results = Vec::new();
+        for (step_index, step) in steps.iter().enumerate() {
+            results.push(self.run_step_at(step, step_index, &mut context, streaming, top_level).await?);
+        }
+        Ok((results, context))
+    }
+    
+    /// Run one step of a pipeline and add its output to `context`
+    ///
+    /// Under `continue_on_error` a failed step yields an error response instead of a `PipelineFailure`.
+    pub async fn run_step(&self, step: &PipelineStep, step_index: usize, context: &mut Context, streaming: bool) -> Result<Response> {
+        self.run_step_at(step, step_index, context, streaming, true).await
+    }
+    
+    async fn run_step_at(&self, step: &PipelineStep, step_index: usize, context: &mut Context, streaming: bool, top_level: bool) -> Result<Response> {
+        tracing::info!(step = step_index + 1, provider = %step.provider, "running step");
+        if let Some(compactor) = &self.compactor {
+            // A failed compaction leaves the context as it was; the step can still run
+            match compactor.compact(context).await {
+                Ok(0) => {}
+                Ok(folded) => tracing::info!(step = step_index + 1, messages = folded, "compacted context"),
+                Err(e) => tracing::warn!(step = step_index + 1, error = %format!("{:#}", e), "context compaction failed"),
+            }
+        }
+        // Map items share the run's uploads through cloned metadata rather than making their own
+        if top_level
+            && step.composite.is_none()
+            && let Some(provider) = self.get_provider(&step.provider)
+            && let Err(e) = provider.prepare_context(context).await
+        {
+            // Whatever could not be stored provider-side is sent inline instead
+            tracing::warn!(step = step_index + 1, error = %format!("{:#}", e), "preparing context failed");
+        }
+        self.emit(PipelineEvent::StepStarted { step_index, provider: step.provider.clone() });
+        let span = tracing::info_span!(
+            "step",
+            step = step_index + 1,
+            provider = %step.provider,
+            retries = tracing::field::Empty,
+            status = tracing::field::Empty
+        );
+        let step_result = async {
+            match &step.composite {
+                Some(Composite::Map(map)) => self.execute_map(step, map, context, step_index, streaming).await,
+                Some(Composite::BestOf(best_of)) => self.execute_best_of(step, best_of, context, step_index, streaming).await,
+                None => self.execute_step(step, context, step_index, streaming).await,
+            }
+        }
+        .instrument(span.clone())
+        .await;
+        span.record("retries", step_result.retries);
+        span.record("status", if step_result.response.is_ok() { "ok" } else { "error" });
+        self.emit(PipelineEvent::StepCompleted {
+            step_index,
+            provider: step_result.step.provider.clone(),
+            elapsed_ms: step_result.execution_time_ms,
+            retries: step_result.retries,
+            outcome: step_result.response.as_ref().map(Response::clone).map_err(|e| format!("{:#}", e)),
+        });
+        
+        let mut response = match &step_result.response {
+            Ok(response) => {
+                tracing::info!(
+                    step = step_index + 1,
+                    provider = %step.provider,
+                    elapsed_ms = step_result.execution_time_ms,
+                    retries = step_result.retries,
+                    "step finished"
+                );
+                response.clone()
+            }
+            Err(error) => {
+                tracing::error!(step = step_index + 1, provider = %step.provider, error = %error, "step failed");
+                if !self.get_config().continue_on_error {
+                    let mut logs = vec![
+                        format!("retries: {}", step_result.retries),
+                        format!("elapsed: {} ms", step_result.execution_time_ms),
+                        format!(
+                            "context: {} messages, {} files, ~{} tokens",
+                            context.conversation_history.len(),
+                            context.file_contents.len(),
+                            self.get_provider(&step.provider)
+                                .map(|p| context.count_tokens(p.tokenizer().as_ref()))
+                                .unwrap_or_else(|| context.estimate_tokens())
+                        ),
+                    ];
+                    if let Some(last) = step_result.rate_limits.last() {
+                        let retry_after = last.retry_after_ms.map_or("none".to_string(), |ms| format!("{} ms", ms));
+                        logs.push(format!("throttled attempts: {} (last Retry-After: {})", step_result.rate_limits.len(), retry_after));
                     }
-                    
-                    // Create error response for continued execution
-                    let error_response = Response::new(format!("Error in step {}: {}", step_index + 1, error))
-                        .with_metadata("error", "true")
-                        .with_metadata("step_index", step_index.to_string());
-                    
-                    results.push(error_response.clone());
-                    context.add_message(Message::new(MessageRole::Assistant, error_response.content.clone()));
+                    return Err(PipelineFailure {
+                        step_index,
+                        provider: step.provider.clone(),
+                        prompt: self.build_prompt(step, context),
+                        error: error.to_string(),
+                        kind: FailureKind::classify(error),
+                        logs,
+                        completed: Vec::new(),
+                    }
+                    .into());
                 }
+                
+                // Create error response for continued execution
+                Response::new(format!("Error in step {}: {}", step_index + 1, error))
+                    .with_metadata("error", "true")
+                    .with_metadata("step_index", step_index.to_string())
             }
-            
-            // Call callback if set
-            if let Some(callback) = &self.step_callback {
-                callback(&step_result);
+        };
+        if let Some(gate) = &self.gate
+            && step_result.is_success()
+        {
+            match gate.review(step_index, step, &response) {
+                Review::Accept => {}
+                Review::Edit(content) => {
+                    response.content = content;
+                    response.metadata.insert("edited".to_string(), "true".to_string());
+                }
+                Review::Retry(tweak) => {
+                    let mut tweaked = step.clone();
+                    tweaked.action = format!("{}\n\n{}", step.action, tweak);
+                    return Box::pin(self.run_step_at(&tweaked, step_index, context, streaming, top_level)).await;
+                }
+                Review::Abort => return Err(anyhow!("{}", Msg::PipelineAborted(step_index + 1))),
             }
         }
         
-        Ok(results)
+        if top_level && let Some(artifacts) = &self.artifacts {
+            artifacts.write_step(step_index, step, &response)?;
+        }
+        
+        // Update context with the step's output
+        context.add_message(
+            Message::new(MessageRole::Assistant, response.content.clone())
+                .with_provenance(Provenance::step_output(step_index, &step.provider)),
+        );
+        
+        // Call callback if set
+        if let Some(callback) = &self.step_callback {
+            callback(&step_result);
+        }
+        Ok(response)
+    }
+    
+    /// Execute a single step, moving on to each fallback provider if it still fails after retries
+    ///
+    /// A successful response records the provider that served it under `provider`, and
+    /// the step's own provider under `fallback_from` when a fallback answered.
+    async fn execute_step(&self, step: &PipelineStep, context: &Context, step_index: usize, streaming: bool) -> StepResult {
+        let mut result = self.execute_with_retries(step, context, step_index, streaming).await;
+        for fallback in &step.fallbacks {
+            let Err(error) = &result.response else { break };
+            tracing::warn!(
+                step = step_index + 1,
+                provider = %result.step.provider,
+                fallback = %fallback,
+                error = %error,
+                "step failed; trying fallback provider"
+            );
+            let mut on_fallback = step.clone();
+            on_fallback.provider = fallback.clone();
+            let (elapsed_ms, retries) = (result.execution_time_ms, result.retries);
+            result = self.execute_with_retries(&on_fallback, context, step_index, streaming).await;
+            result.execution_time_ms += elapsed_ms;
+            result.retries += retries;
+        }
+        if let Ok(response) = &mut result.response {
+            response.metadata.insert("provider".to_string(), result.step.provider.clone());
+            if result.step.provider != step.provider {
+                response.metadata.insert("fallback_from".to_string(), step.provider.clone());
+            }
+        }
+        result
     }
     
-    /// Execute a single step with retry logic
-    async fn execute_step(&self, step: &PipelineStep, context: &Context, step_index: usize) -> StepResult {
+    /// Count the request with the provider's tokenizer against its context window
+    fn check_context_window(
+        &self,
+        step: &PipelineStep,
+        provider: &Arc<dyn AIProvider>,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+    ) -> std::result::Result<(), ContextOverflowError> {
+        let Some(window) = self.capabilities(&step.provider).map(|c| c.max_tokens) else { return Ok(()) };
+        let tokenizer = provider.tokenizer();
+        let prompt_tokens = tokenizer.count(prompt)
+            + options.system.as_deref().map_or(0, |system| tokenizer.count(system))
+            + context.count_tokens(tokenizer.as_ref());
+        let reserved_tokens = options.max_tokens.unwrap_or(0) as usize;
+        if prompt_tokens + reserved_tokens <= window {
+            return Ok(());
+        }
+        Err(ContextOverflowError {
+            provider: step.provider.clone(),
+            model: options.model.clone().or_else(|| provider.model().map(str::to_string)),
+            prompt_tokens,
+            reserved_tokens,
+            context_window: window,
+        })
+    }
+
+    /// Execute a single step on its provider with retry logic
+    async fn execute_with_retries(&self, step: &PipelineStep, context: &Context, step_index: usize, streaming: bool) -> StepResult {
         let start_time = std::time::Instant::now();
         let mut retries = 0;
+        let mut rate_limits = Vec::new();
+        let mut reauthenticated = false;
+        let config = self.get_config();
+        let max_retries = step.retries.unwrap_or(config.max_retries);
+        let timeout = step.timeout.or(config.timeout_seconds.map(Duration::from_secs));
         
         // Check if provider exists
-        let provider = match self.providers.get(&step.provider) {
+        let provider = match self.get_provider(&step.provider) {
             Some(provider) => provider,
             None => {
                 return StepResult {
@@ -377,75 +1712,499 @@ impl PipelineExecutor {
                     response: Err(anyhow!("Unknown provider: {}", step.provider)),
                     execution_time_ms: start_time.elapsed().as_millis() as u64,
                     retries: 0,
+                    rate_limits: Vec::new(),
                 };
             }
         };
         
         // Build prompt from action and step context
-        let prompt = self.build_prompt(step);
+        let prompt = self.build_prompt(step, context);
+        let mut options = self.options.merged(step.options());
+        // Structured replies are validated whole, so they never stream
+        let streaming = streaming && options.json.is_none() && self.capabilities(&step.provider).is_some_and(|c| c.supports_streaming);
+        
+        let limited;
+        let policy = self.context_policy_for(step);
+        let context = if policy == ContextPolicy::Full && !self.context_filters.has_filters(&step.provider) {
+            context
+        } else {
+            let mut copy = context.clone();
+            policy.apply(&mut copy);
+            self.context_filters.apply(&step.provider, &mut copy);
+            limited = copy;
+            &limited
+        };
+        
+        let retrieved;
+        let context = match &self.retriever {
+            Some(retriever) => {
+                let mut with_chunks = context.clone();
+                let injected = retriever.inject(&prompt, &mut with_chunks).await;
+                if let Ok(count) = &injected {
+                    tracing::debug!(step = step_index + 1, chunks = count, "retrieved index chunks");
+                }
+                if let Err(e) = injected {
+                    return StepResult {
+                        step: step.clone(),
+                        response: Err(anyhow!("Retrieval failed: {}", e)),
+                        execution_time_ms: start_time.elapsed().as_millis() as u64,
+                        retries: 0,
+                        rate_limits: Vec::new(),
+                    };
+                }
+                retrieved = with_chunks;
+                &retrieved
+            }
+            None => context,
+        };
+        
+        let with_images;
+        let context = if step.images.is_empty() {
+            context
+        } else {
+            match self.load_images(step) {
+                Ok(images) => {
+                    with_images = Context { images, ..context.clone() };
+                    &with_images
+                }
+                Err(e) => {
+                    return StepResult {
+                        step: step.clone(),
+                        response: Err(e),
+                        execution_time_ms: start_time.elapsed().as_millis() as u64,
+                        retries: 0,
+                        rate_limits: Vec::new(),
+                    };
+                }
+            }
+        };
+        
+        let prepared = match self.prepare_request(prompt, options.system.take(), context) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                tracing::warn!(step = step_index + 1, rule = %e.rule, location = %e.location, "prompt blocked by content filter");
+                return StepResult {
+                    step: step.clone(),
+                    response: Err(e.into()),
+                    execution_time_ms: start_time.elapsed().as_millis() as u64,
+                    retries: 0,
+                    rate_limits: Vec::new(),
+                };
+            }
+        };
+        let PreparedRequest { prompt, system, context, mut safety_findings } = prepared;
+        options.system = system;
+        let context = context.as_ref();
+        // An oversized prompt fails here rather than as an opaque 400 from the API
+        if let Err(e) = self.check_context_window(step, &provider, &prompt, context, &options) {
+            return StepResult {
+                step: step.clone(),
+                response: Err(e.into()),
+                execution_time_ms: start_time.elapsed().as_millis() as u64,
+                retries: 0,
+                rate_limits: Vec::new(),
+            };
+        }
+        let mut request = prompt.clone();
+        let mut json_retries = 0;
+        let mut attempts = 0;
+        // Retries of the same request share a key, so a late reply is not billed twice
+        options.idempotency_key = Some(self.idempotency.key(&step.provider, &request, context, &options));
+        
+        // Attempts that timed out keep running; the first late reply answers the retry
+        let mut late: FuturesUnordered<BoxFuture<'_, Result<Response>>> = FuturesUnordered::new();
+        let mut backoff_ms = 0;
         
         // Retry loop
         loop {
             
-            match provider.execute(&prompt, context).await {
+            // A late reply that lands during the backoff saves sending the request again
+            let reused = match std::mem::take(&mut backoff_ms) {
+                0 => None,
+                delay_ms => first_late_reply(&mut late, Duration::from_millis(delay_ms)).await,
+            };
+            let attempt = match reused {
+                Some(response) => {
+                    tracing::info!(provider = %step.provider, "reusing the late reply of a timed-out attempt");
+                    Ok(response)
+                }
+                None => {
+                    // Holds the provider's request slot until the attempt finishes
+                    let permit = match self.limiters.get(&step.provider) {
+                        Some(limiter) => limiter.acquire().await,
+                        None => None,
+                    };
+                    // Owns its request, so it can outlive the attempt if it times out
+                    let call: BoxFuture<'_, Result<Response>> = {
+                        let (provider, request, options) = (provider.clone(), request.clone(), options.clone());
+                        Box::pin(async move {
+                            // Tool calls need whole responses, so steps with tools never stream
+                            if let Some(tools) = &step.tools {
+                                let handler = GuardedTools {
+                                    executor: self,
+                                    handler: tools.handler.as_ref(),
+                                    provider: &step.provider,
+                                    model: options.model.as_deref().or(provider.model()),
+                                    step: step_index + 1,
+                                    attempt: attempts + 1,
+                                    idempotency_key: options.idempotency_key.as_deref(),
+                                };
+                                provider.execute_with_tools(&request, context, &options, &tools.specs, &handler).await
+                            } else if streaming {
+                                self.collect_stream(provider.as_ref(), &request, context, &options, step_index).await
+                            } else {
+                                provider.execute_with_options(&request, context, &options).await
+                            }
+                        })
+                    };
+                    let attempt = race_late_replies(call, &mut late, timeout).await.unwrap_or_else(|| {
+                        let limit = timeout.unwrap_or_default();
+                        Err(ProviderError::timeout(&step.provider, format!("no reply within {} s", limit.as_secs_f64())).into())
+                    });
+                    drop(permit);
+                    attempt
+                }
+            };
+            attempts += 1;
+            let model = options.model.as_deref().or(provider.model());
+            let attempt = attempt.map(|mut response| {
+                self.record_cost(&mut response, model);
+                response
+            });
+            if let Some(audit) = &self.audit {
+                let call = AuditCall {
+                    provider: &step.provider,
+                    model,
+                    step: step_index + 1,
+                    attempt: attempts,
+                    idempotency_key: options.idempotency_key.as_deref(),
+                    system: options.system.as_deref(),
+                    prompt: &request,
+                };
+                audit.record(&call, attempt.as_ref());
+            }
+            match attempt {
                 Ok(mut response) => {
+                    // Replies that break the JSON schema are sent back with the reason
+                    if let Some(json) = &options.json {
+                        match json.validate(&response.content) {
+                            Ok(mut value) => {
+                                if let Some(anonymizer) = &self.anonymizer {
+                                    anonymizer.restore_json(&mut value);
+                                }
+                                response.content = value.to_string();
+                                response.metadata.insert("json_retries".to_string(), json_retries.to_string());
+                            }
+                            Err(reason) if json_retries < json.retries => {
+                                json_retries += 1;
+                                tracing::warn!(step = step_index + 1, attempt = json_retries, reason = %reason, "reply does not match the JSON schema; asking again");
+                                request = json.feedback(&prompt, &response.content, &reason);
+                                options.idempotency_key = Some(self.idempotency.key(&step.provider, &request, context, &options));
+                                continue;
+                            }
+                            Err(reason) => {
+                                return StepResult {
+                                    step: step.clone(),
+                                    response: Err(anyhow!(
+                                        "Reply does not match the JSON schema after {} attempts: {}",
+                                        json_retries + 1,
+                                        reason
+                                    )),
+                                    execution_time_ms: start_time.elapsed().as_millis() as u64,
+                                    retries,
+                                    rate_limits,
+                                };
+                            }
+                        }
+                    } else if let Some(anonymizer) = &self.anonymizer {
+                        response.content = anonymizer.restore(&response.content);
+                    }
+                    if let Some(safety) = &self.safety {
+                        match safety.check_response(&response.content, &mut safety_findings) {
+                            Ok(content) => response.content = content,
+                            Err(e) => {
+                                tracing::warn!(step = step_index + 1, rule = %e.rule, "reply blocked by content filter");
+                                return StepResult {
+                                    step: step.clone(),
+                                    response: Err(e.into()),
+                                    execution_time_ms: start_time.elapsed().as_millis() as u64,
+                                    retries,
+                                    rate_limits,
+                                };
+                            }
+                        }
+                    }
+                    
                     // Enhance response with metadata
                     self.enhance_response(&mut response, context, step_index, retries);
+                    if let Some(key) = &options.idempotency_key {
+                        response.metadata.insert("idempotency_key".to_string(), key.clone());
+                    }
+                    if !safety_findings.is_empty() {
+                        for finding in safety_findings.iter().filter(|f| f.action == SafetyAction::Warn) {
+                            tracing::warn!(step = step_index + 1, rule = %finding.rule, location = %finding.location, line = finding.line, "content filter matched");
+                        }
+                        let findings = safety_findings.iter().map(|f| format!("{} {}", f.rule, f.location)).collect::<Vec<_>>();
+                        response.metadata.insert("safety_findings".to_string(), findings.join(", "));
+                    }
                     
                     // Apply transform if present
                     if let Some(transform) = step.get_transform() {
-                        match transform.transform(response).await {
+                        tracing::debug!(step = step_index + 1, transform = transform.name(), "applying transform");
+                        let raw = response.content.clone();
+                        match transform.transform_with_writer(response, &self.file_writer).await {
                             Ok(transformed) => {
                                 response = transformed;
+                                if response.content != raw {
+                                    response.raw = Some(raw);
+                                }
                             }
                             Err(e) => {
+                                tracing::warn!(step = step_index + 1, transform = transform.name(), error = %e, "transform failed");
                                 return StepResult {
                                     step: step.clone(),
-                                    response: Err(anyhow!("Transform failed: {}", e)),
+                                    response: Err(match e.downcast::<TransformError>() {
+                                        Ok(error) => error.into(),
+                                        Err(e) => TransformError::Operation(e.to_string()).into(),
+                                    }),
                                     execution_time_ms: start_time.elapsed().as_millis() as u64,
                                     retries,
+                                    rate_limits,
                                 };
                             }
                         }
                     }
                     
-                    // Add provider name to response content for compatibility with existing tests
-                    response.content = format!("{} response: {}", step.provider, response.content);
+                    response.metadata.insert("latency_ms".to_string(), start_time.elapsed().as_millis().to_string());
                     
                     return StepResult {
                         step: step.clone(),
                         response: Ok(response),
                         execution_time_ms: start_time.elapsed().as_millis() as u64,
                         retries,
+                        rate_limits,
                     };
                 }
                 Err(error) => {
-                    if retries >= self.config.max_retries {
+                    // Expired or revoked credentials: renew once and retry without spending a retry
+                    if error.downcast_ref::<AuthError>().is_some() {
+                        if !reauthenticated && matches!(provider.reauthenticate().await, Ok(true)) {
+                            tracing::info!(provider = %step.provider, "credentials renewed after 401; retrying");
+                            reauthenticated = true;
+                            continue;
+                        }
+                        // Retrying with the same rejected credentials cannot succeed
+                        return StepResult {
+                            step: step.clone(),
+                            response: Err(error),
+                            execution_time_ms: start_time.elapsed().as_millis() as u64,
+                            retries,
+                            rate_limits,
+                        };
+                    }
+                    
+                    // Errors the provider marks permanent (bad request, unknown model) fail at once
+                    let provider_error = error.downcast_ref::<ProviderError>();
+                    let permanent = provider_error.is_some_and(|e| !e.retryable);
+                    let retry_after_ms = provider_error.and_then(|e| e.retry_after).map(|d| d.as_millis() as u64);
+                    // A provider's `Retry-After` replaces the fixed delay, unless it is too long to wait out
+                    let delay_ms = retry_after_ms.unwrap_or(config.retry_delay_ms);
+                    let rate_limited = provider_error.is_some_and(ProviderError::is_rate_limited);
+                    let give_up = permanent || retries >= max_retries || delay_ms > config.max_retry_after_ms;
+                    if rate_limited {
+                        rate_limits.push(RateLimitHit { attempt: retries + 1, retry_after_ms, waited_ms: if give_up { 0 } else { delay_ms } });
+                    }
+                    if give_up {
                         return StepResult {
                             step: step.clone(),
                             response: Err(error),
                             execution_time_ms: start_time.elapsed().as_millis() as u64,
                             retries,
+                            rate_limits,
                         };
                     }
                     
                     retries += 1;
+                    tracing::warn!(provider = %step.provider, attempt = retries, delay_ms, rate_limited, error = %error, "step attempt failed; retrying");
+                    self.emit(PipelineEvent::RetryScheduled {
+                        step_index,
+                        provider: step.provider.clone(),
+                        attempt: retries,
+                        delay_ms,
+                        rate_limited,
+                        error: format!("{:#}", error),
+                    });
                     
                     // Wait before retry
-                    if self.config.retry_delay_ms > 0 {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(self.config.retry_delay_ms)).await;
+                    if late.is_empty() {
+                        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                    } else {
+                        backoff_ms = delay_ms;
                     }
                 }
             }
         }
     }
     
+    /// Redact, check and anonymize a prompt, its system prompt and context before they are sent
+    ///
+    /// Every provider call goes through this, from pipeline steps and `batch submit` alike.
+    pub fn prepare_request<'a>(&self, prompt: String, system: Option<String>, context: &'a Context) -> Result<PreparedRequest<'a>, SafetyError> {
+        let mut prepared = PreparedRequest { prompt, system, context: Cow::Borrowed(context), safety_findings: Vec::new() };
+        // Nothing reaches the provider before the redactor has seen it
+        if let Some(redactor) = &self.redactor {
+            prepared.context = Cow::Owned(redactor.redact_context(&prepared.context));
+            prepared.system = prepared.system.map(|system| redactor.redact_text(&system, "system prompt"));
+            prepared.prompt = redactor.redact_text(&prepared.prompt, "prompt");
+        }
+        // Safety rules see real values, so they run before anonymization
+        if let Some(safety) = &self.safety {
+            let (prompt, system, context, findings) = safety.check_request(&prepared.prompt, prepared.system.as_deref(), &prepared.context)?;
+            prepared = PreparedRequest { prompt, system, context: Cow::Owned(context), safety_findings: findings };
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            prepared.context = Cow::Owned(anonymizer.anonymize_context(&prepared.context));
+            prepared.system = prepared.system.map(|system| anonymizer.anonymize(&system));
+            prepared.prompt = anonymizer.anonymize(&prepared.prompt);
+        }
+        Ok(prepared)
+    }
+    
+    /// Redact, check and anonymize a tool's output before it goes back to the model, as [`prepare_request`](Self::prepare_request) does a prompt
+    pub fn prepare_tool_result(&self, output: String, location: &str) -> Result<String, SafetyError> {
+        let mut output = output;
+        if let Some(redactor) = &self.redactor {
+            output = redactor.redact_text(&output, location);
+        }
+        if let Some(safety) = &self.safety {
+            let mut findings = Vec::new();
+            output = safety.check_text(&output, SafetyScope::Prompt, location, &mut findings)?;
+            for finding in findings.iter().filter(|f| f.action == SafetyAction::Warn) {
+                tracing::warn!(rule = %finding.rule, location = %finding.location, line = finding.line, "content filter matched");
+            }
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            output = anonymizer.anonymize(&output);
+        }
+        Ok(output)
+    }
+    
+    /// Send one request outside any pipeline, such as a post-mortem, the way steps send theirs:
+    /// prepared by [`prepare_request`](Self::prepare_request), audited, restored and checked
+    pub async fn ask(&self, provider_name: &str, prompt: String, context: &Context) -> Result<Response> {
+        let provider = self
+            .get_provider(provider_name)
+            .ok_or_else(|| anyhow!("Provider '{}' is not registered", provider_name))?;
+        let prepared = self.prepare_request(prompt, self.options.system.clone(), context)?;
+        let options = ProviderOptions { system: prepared.system.clone(), ..self.options.clone() };
+        let result = provider.execute_with_options(&prepared.prompt, &prepared.context, &options).await;
+        if let Some(audit) = &self.audit {
+            let call = AuditCall {
+                provider: provider_name,
+                model: options.model.as_deref().or(provider.model()),
+                step: 0,
+                attempt: 1,
+                idempotency_key: None,
+                system: options.system.as_deref(),
+                prompt: &prepared.prompt,
+            };
+            audit.record(&call, result.as_ref());
+        }
+        let mut response = result?;
+        if let Some(anonymizer) = &self.anonymizer {
+            response.content = anonymizer.restore(&response.content);
+        }
+        if let Some(safety) = &self.safety {
+            response.content = safety.check_response(&response.content, &mut Vec::new())?;
+        }
+        Ok(response)
+    }
+    
+    /// Read a step's image files, refusing providers that cannot see them
+    fn load_images(&self, step: &PipelineStep) -> Result<Vec<Image>> {
+        if !self.capabilities(&step.provider).is_some_and(|c| c.supports_vision) {
+            return Err(anyhow!("Provider '{}' does not accept images", step.provider));
+        }
+        step.images.iter().map(|path| Image::from_file(path)).collect()
+    }
+    
+    /// Run a step through the provider's stream; an interrupted stream fails the attempt
+    ///
+    /// Observers see each chunk as it arrives.
+    async fn collect_stream(
+        &self,
+        provider: &dyn AIProvider,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        step_index: usize,
+    ) -> Result<Response> {
+        let stream = provider.stream_with_options(prompt, context, options).await?;
+        // A reply that safety rules may block or rewrite is only shown once it has been checked
+        let live = !self.safety.as_ref().is_some_and(|safety| safety.filters_responses());
+        // Chunks are shown restored; the collected reply stays anonymized and is restored whole
+        let mut restorer = self.anonymizer.as_deref().map(Anonymizer::chunk_restorer);
+        let stream = stream.inspect(|chunk| {
+            if let Ok(text) = chunk
+                && live
+            {
+                let text = match restorer.as_mut() {
+                    Some(restorer) => restorer.push(text),
+                    None => text.clone(),
+                };
+                if !text.is_empty() {
+                    self.emit(PipelineEvent::Chunk { step_index, text });
+                }
+            }
+        });
+        let content = streaming::collect(Box::pin(stream)).await?;
+        if let Some(restorer) = restorer
+            && live
+        {
+            let text = restorer.finish();
+            if !text.is_empty() {
+                self.emit(PipelineEvent::Chunk { step_index, text });
+            }
+        }
+        Ok(Response::new(content).with_metadata("streamed", "true"))
+    }
+    
     /// Build prompt from step
-    fn build_prompt(&self, step: &PipelineStep) -> String {
-        if let Some(step_context) = &step.get_context() {
+    fn build_prompt(&self, step: &PipelineStep, context: &Context) -> String {
+        let mut prompt = if let Some(step_context) = &step.get_context() {
             format!("{}: {}", step.action, step_context)
         } else {
             step.action.clone()
+        };
+        if let Some(affixes) = self.provider_prompts.get(&step.provider) {
+            if let Some(prefix) = &affixes.prefix {
+                prompt = format!("{}\n\n{}", prefix, prompt);
+            }
+            if let Some(suffix) = &affixes.suffix {
+                prompt = format!("{}\n\n{}", prompt, suffix);
+            }
+        }
+        if let Some(prefix) = &self.prompt_prefix {
+            prompt = format!("{}\n\n{}", prefix, prompt);
+        }
+        
+        // Step-scoped variables take precedence over the context environment
+        let mut vars = context.environment.clone();
+        vars.extend(step.env().iter().map(|(k, v)| (k.clone(), v.clone())));
+        template::render_env(&prompt, &vars)
+    }
+    
+    /// Price the usage a provider reported, naming the model when the provider did not
+    fn record_cost(&self, response: &mut Response, model: Option<&str>) {
+        if !response.metadata.contains_key("model")
+            && let Some(model) = model
+        {
+            response.metadata.insert("model".to_string(), model.to_string());
+        }
+        if let Some(usage) = Usage::from_response(response)
+            && let Some(cost) = response.metadata.get("model").and_then(|model| self.pricing.cost(model, usage))
+        {
+            response.metadata.insert("cost_usd".to_string(), format!("{:.6}", cost));
         }
     }
     
@@ -484,22 +2243,51 @@ impl PipelineExecutor {
     
     /// Get list of registered provider names
     pub fn get_provider_names(&self) -> Vec<String> {
-        self.providers.keys().cloned().collect()
+        self.registry().keys().cloned().collect()
+    }
+    
+    /// Get a registered provider by name
+    pub fn get_provider(&self, name: &str) -> Option<Arc<dyn AIProvider>> {
+        self.registry().get(name).cloned()
     }
     
     /// Check if a provider is registered
     pub fn has_provider(&self, name: &str) -> bool {
-        self.providers.contains_key(name)
+        self.registry().contains_key(name)
     }
     
     /// Get execution configuration
-    pub fn get_config(&self) -> &ExecutionConfig {
-        &self.config
+    pub fn get_config(&self) -> ExecutionConfig {
+        self.config.read().unwrap_or_else(PoisonError::into_inner).clone()
     }
     
-    /// Execute with streaming (simplified for now)
+    /// Override the capabilities reported for a provider (e.g. with probed values)
+    pub fn set_capabilities(&self, name: impl Into<String>, capabilities: Capabilities) {
+        self.capabilities.write().unwrap_or_else(PoisonError::into_inner).insert(name.into(), capabilities);
+    }
+    
+    /// Get effective capabilities for a provider, preferring probed values
+    pub fn capabilities(&self, name: &str) -> Option<Capabilities> {
+        let probed = self.capabilities.read().unwrap_or_else(PoisonError::into_inner).get(name).cloned();
+        probed.or_else(|| self.get_provider(name).map(|p| p.capabilities()))
+    }
+    
+    /// Probe capabilities of the named providers through the cache
+    pub async fn probe_capabilities(&self, cache: &mut CapabilityCache, names: &[&str], reprobe: bool) {
+        for name in names {
+            if let Some(provider) = self.get_provider(name) {
+                let capabilities = cache.resolve(provider.as_ref(), reprobe).await;
+                self.set_capabilities(*name, capabilities);
+            }
+        }
+    }
+    
+    /// Execute using provider streams where supported
+    ///
+    /// A stream that breaks off mid-response is treated like a failed request and
+    /// retried, so partial output never reaches the next step.
     pub async fn execute_streaming(&self, steps: &[PipelineStep], context: Context) -> Result<Vec<Response>> {
-        self.execute(steps, context).await
+        self.run_pipeline(steps, context, true).await.map(|(results, _)| results)
     }
 }
 
@@ -527,6 +2315,30 @@ mod tests {
         assert_eq!(pipeline[2].get_context(), Some("Please check for security issues".to_string()));
     }
     
+    #[test]
+    fn test_pipeline_builder_step_settings() {
+        let pipeline = PipelineBuilder::new()
+            .model("ignored before the first step")
+            .step("claude", "design")
+            .model("claude-3-5-haiku-20241022")
+            .timeout(Duration::from_secs(30))
+            .retries(2)
+            .fallback("gemini")
+            .fallback("codex")
+            .step("gemini", "extract")
+            .transform(Arc::new(IdentityTransform))
+            .build();
+        
+        assert_eq!(pipeline[0].model(), Some("claude-3-5-haiku-20241022"));
+        assert_eq!(pipeline[0].timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(pipeline[0].retries(), Some(2));
+        assert_eq!(pipeline[0].fallbacks(), ["gemini", "codex"]);
+        assert!(!pipeline[0].has_transform());
+        assert!(pipeline[1].has_transform());
+        assert_eq!((pipeline[1].model(), pipeline[1].retries()), (None, None));
+        assert_eq!(pipeline[0].to_string(), "claude|gemini|codex[model=claude-3-5-haiku-20241022]:design");
+    }
+    
     #[test]
     fn test_pipeline_format() {
         let steps = vec![
@@ -591,7 +2403,7 @@ mod tests {
     
     #[tokio::test]
     async fn test_pipeline_with_transform() {
-        let mut executor = PipelineExecutor::new();
+        let executor = PipelineExecutor::new();
         
         // Register mock providers
         executor.register_provider("provider1", Arc::new(MockProvider {
@@ -616,7 +2428,7 @@ mod tests {
         
         // The first step's response should be transformed to uppercase
         // This test will fail initially as transform is not yet implemented
-        assert_eq!(results[0].content, "provider1 response: HELLO WORLD");
-        assert_eq!(results[1].content, "provider2 response: goodbye");
+        assert_eq!(results[0].content, "HELLO WORLD");
+        assert_eq!(results[1].content, "goodbye");
     }
 }
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use std::fmt;
+
+use super::PipelineExecutor;
+use crate::error::Error;
+use crate::providers::{Context, Response};
+
+/// Most likely reason a step failed, judged from its error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Auth,
+    RateLimit,
+    ContextTooLarge,
+    BadPrompt,
+    Network,
+    Timeout,
+    Unknown,
+}
+
+impl FailureKind {
+    /// Classify an error by its typed status, falling back to well-known phrases
+    pub fn classify(error: &anyhow::Error) -> Self {
+        match Error::classify(error) {
+            Some(Error::Auth(_)) => return FailureKind::Auth,
+            Some(Error::ContextOverflow(_)) => return FailureKind::ContextTooLarge,
+            Some(Error::Safety(_)) => return FailureKind::BadPrompt,
+            Some(Error::Provider(e)) => match e.status {
+                Some(401 | 403) => return FailureKind::Auth,
+                Some(429) => return FailureKind::RateLimit,
+                Some(413) => return FailureKind::ContextTooLarge,
+                _ if e.timed_out => return FailureKind::Timeout,
+                None => return FailureKind::Network,
+                // A 400 may still be an oversized prompt; let the message decide
+                Some(_) => {}
+            },
+            _ => {}
+        }
+
+        let message = error.to_string().to_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| message.contains(n));
+        if has(&["401", "403", "unauthorized", "forbidden", "authenticat", "api key", "credentials"]) {
+            FailureKind::Auth
+        } else if has(&["429", "rate limit", "rate_limit", "quota", "resource_exhausted", "overloaded"]) {
+            FailureKind::RateLimit
+        } else if has(&["413", "too long", "too large", "context length", "context window", "maximum context", "token limit"]) {
+            FailureKind::ContextTooLarge
+        } else if has(&["400", "invalid request", "invalid_request", "invalid_argument"]) {
+            FailureKind::BadPrompt
+        } else if has(&["timed out", "timeout", "deadline exceeded"]) {
+            FailureKind::Timeout
+        } else if has(&["failed to send", "connection", "dns"]) {
+            FailureKind::Network
+        } else {
+            FailureKind::Unknown
+        }
+    }
+
+    /// Generic advice for this kind of failure
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            FailureKind::Auth => "Check credentials with `ai-cli auth status` and `ai-cli check-auth <provider> --validate`.",
+            FailureKind::RateLimit => "Wait and retry, lower concurrency, or move the step to another provider.",
+            FailureKind::ContextTooLarge => "Trim the context files or split the step so the prompt fits the model's window.",
+            FailureKind::BadPrompt => "Review the step's action and context for malformed or unsupported content.",
+            FailureKind::Network => "Check connectivity, proxies and the provider base URL.",
+            FailureKind::Timeout => "Retry later, or split the step so the provider can answer sooner.",
+            FailureKind::Unknown => "Re-run with --verbose and inspect the run's artifacts.",
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FailureKind::Auth => "authentication",
+            FailureKind::RateLimit => "rate limit",
+            FailureKind::ContextTooLarge => "context too large",
+            FailureKind::BadPrompt => "bad prompt",
+            FailureKind::Network => "network",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Details of the step that stopped a pipeline
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Pipeline execution failed at step {}: {}", .step_index + 1, .error)]
+pub struct PipelineFailure {
+    /// 0-based index of the failed step
+    pub step_index: usize,
+    pub provider: String,
+    /// Prompt sent to the provider
+    pub prompt: String,
+    pub error: String,
+    pub kind: FailureKind,
+    /// Execution notes (retries, timing, context size)
+    pub logs: Vec<String>,
+    /// Responses of the steps that finished before this one
+    pub completed: Vec<Response>,
+}
+
+impl PipelineFailure {
+    /// Heuristic diagnosis that needs no provider call
+    pub fn summary(&self) -> String {
+        format!("Likely cause: {}\nSuggested fix: {}", self.kind, self.kind.suggestion())
+    }
+
+    /// Prompt asking a provider to diagnose this failure
+    pub fn postmortem_prompt(&self) -> String {
+        format!(
+            "A step of an AI pipeline failed. Diagnose the most likely cause \
+             (bad prompt, context too large, authentication, rate limit, network, timeout or other) \
+             and suggest concrete fixes. Be brief.\n\n\
+             Step: {} ({})\nError: {}\nHeuristic classification: {}\n\nLogs:\n{}\n\nPrompt:\n{}",
+            self.step_index + 1,
+            self.provider,
+            self.error,
+            self.kind,
+            self.logs.join("\n"),
+            self.prompt,
+        )
+    }
+}
+
+/// Ask `provider` to diagnose a failure; the heuristic summary always comes first
+///
+/// The failed prompt is in the request, so it goes through `executor`'s
+/// redaction, `[safety]` rules, anonymization and audit log like the step did.
+pub async fn run_postmortem(executor: &PipelineExecutor, provider: &str, failure: &PipelineFailure) -> Result<String> {
+    let response = executor.ask(provider, failure.postmortem_prompt(), &Context::new()).await?;
+    Ok(format!("{}\n\nDiagnosis ({}):\n{}", failure.summary(), provider, response.content))
+}
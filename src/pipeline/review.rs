@@ -0,0 +1,251 @@
+//! Pull request review: a pipeline run over each file or chunk of a diff
+//!
+//! Every chunk is reviewed separately with its patch as context and a system
+//! message asking for a JSON array of `{"line", "body"}` comments on new-side
+//! line numbers. Replies that are not JSON become file-level comments, and
+//! comments on lines outside the diff are moved to the review body, since
+//! GitHub rejects them. The [`ReviewReport`] renders as markdown or as the body of
+//! GitHub's "create a review" endpoint.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use super::{PipelineExecutor, PipelineStep};
+use crate::context::Provenance;
+use crate::context::git::FileDiff;
+use crate::providers::{Context, Message, MessageRole};
+
+/// Chain used when neither `--chain` nor `[review]` sets one
+pub const DEFAULT_CHAIN: &str = "claude:Review this change for bugs, security problems and unclear code. \
+Only comment on things worth fixing.";
+
+/// Patch bytes sent per chunk when `[review] max_chunk_bytes` is not set
+pub const DEFAULT_CHUNK_BYTES: usize = 12_000;
+
+const FORMAT_INSTRUCTIONS: &str = "Reply with a JSON array of review comments and nothing else, e.g. \
+[{\"line\": 42, \"body\": \"This can panic when the list is empty.\"}]. `line` is the line number in the \
+new version of the file, taken from the hunk headers; omit it for a comment on the whole file. \
+Reply with [] when there is nothing to fix.";
+
+/// How `ai-cli review` writes the review
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ReviewFormat {
+    #[default]
+    Markdown,
+    /// Body for GitHub's "create a review" endpoint
+    Github,
+}
+
+/// `[review]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReviewSettings {
+    /// Pipeline chain run over each chunk
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Largest patch sent in one run; bigger files are split at hunk boundaries
+    #[serde(default)]
+    pub max_chunk_bytes: Option<usize>,
+}
+
+/// Part of one file's patch, reviewed in one pipeline run
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewChunk {
+    pub path: PathBuf,
+    pub patch: String,
+    /// New-side lines present in the patch, the only ones GitHub accepts comments on
+    pub lines: BTreeSet<u64>,
+}
+
+/// Split a file's patch at hunk boundaries into chunks of at most `max_bytes`
+///
+/// A single hunk larger than `max_bytes` stays whole. Files without a patch
+/// (binary, too large for the API) produce no chunks.
+pub fn chunk_diff(diff: &FileDiff, max_bytes: usize) -> Vec<ReviewChunk> {
+    let Some(first_hunk) = diff.patch.find("\n@@").map(|i| i + 1) else {
+        return Vec::new();
+    };
+    let header = &diff.patch[..first_hunk];
+    let mut hunks: Vec<&str> = Vec::new();
+    let mut rest = &diff.patch[first_hunk..];
+    while let Some(next) = rest[2..].find("\n@@").map(|i| i + 3) {
+        hunks.push(&rest[..next]);
+        rest = &rest[next..];
+    }
+    hunks.push(rest);
+
+    let mut chunks = Vec::new();
+    let mut patch = header.to_string();
+    for hunk in hunks {
+        if patch.len() > header.len() && patch.len() + hunk.len() > max_bytes {
+            chunks.push(std::mem::replace(&mut patch, header.to_string()));
+        }
+        patch.push_str(hunk);
+    }
+    chunks.push(patch);
+    chunks
+        .into_iter()
+        .map(|patch| ReviewChunk { path: diff.path.clone(), lines: commentable_lines(&patch), patch })
+        .collect()
+}
+
+/// New-side line numbers of the added and context lines in a patch
+pub fn commentable_lines(patch: &str) -> BTreeSet<u64> {
+    let mut lines = BTreeSet::new();
+    let mut next: Option<u64> = None;
+    for line in patch.lines() {
+        if let Some(header) = line.strip_prefix("@@ ") {
+            // @@ -a,b +c,d @@
+            next = header
+                .split_whitespace()
+                .find_map(|part| part.strip_prefix('+'))
+                .and_then(|range| range.split(',').next())
+                .and_then(|start| start.parse().ok());
+            continue;
+        }
+        let Some(current) = next.as_mut() else { continue };
+        if line.starts_with('+') || line.starts_with(' ') || line.is_empty() {
+            lines.insert(*current);
+            *current += 1;
+        }
+    }
+    lines
+}
+
+/// One review comment; `line` is `None` for a comment on the whole file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+struct ReplyComment {
+    #[serde(default)]
+    line: Option<u64>,
+    body: String,
+}
+
+/// Comments from a pipeline reply for `chunk`
+///
+/// Lines outside the chunk become file-level comments; a reply that is not a
+/// JSON array becomes one file-level comment unless it is empty.
+pub fn parse_comments(reply: &str, chunk: &ReviewChunk) -> Vec<ReviewComment> {
+    let json = reply.find('[').zip(reply.rfind(']')).and_then(|(start, end)| reply.get(start..=end));
+    match json.and_then(|json| serde_json::from_str::<Vec<ReplyComment>>(json).ok()) {
+        Some(comments) => comments
+            .into_iter()
+            .filter(|c| !c.body.trim().is_empty())
+            .map(|c| ReviewComment {
+                path: chunk.path.clone(),
+                line: c.line.filter(|line| chunk.lines.contains(line)),
+                body: c.body.trim().to_string(),
+            })
+            .collect(),
+        None if reply.trim().is_empty() => Vec::new(),
+        None => vec![ReviewComment { path: chunk.path.clone(), line: None, body: reply.trim().to_string() }],
+    }
+}
+
+/// Comments on a whole pull request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReviewReport {
+    pub comments: Vec<ReviewComment>,
+    /// Chunks whose run failed, with the error
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl ReviewReport {
+    /// Markdown grouped by file, line comments first
+    pub fn to_markdown(&self, title: &str) -> String {
+        let mut out = format!("# Review: {}\n", title);
+        if self.comments.is_empty() {
+            out.push_str("\nNo comments.\n");
+        }
+        let mut path: Option<&PathBuf> = None;
+        for comment in &self.comments {
+            if path != Some(&comment.path) {
+                out.push_str(&format!("\n## {}\n\n", comment.path.display()));
+                path = Some(&comment.path);
+            }
+            match comment.line {
+                Some(line) => out.push_str(&format!("- **L{}**: {}\n", line, comment.body)),
+                None => out.push_str(&format!("- {}\n", comment.body)),
+            }
+        }
+        for (path, error) in &self.failed {
+            out.push_str(&format!("\n> Not reviewed: {}: {}\n", path.display(), error));
+        }
+        out
+    }
+
+    /// Body for `POST /repos/{owner}/{repo}/pulls/{number}/reviews`
+    ///
+    /// Line comments go to `comments`; file-level ones are listed in `body`.
+    pub fn to_github(&self, commit_id: &str) -> serde_json::Value {
+        let comments: Vec<serde_json::Value> = self
+            .comments
+            .iter()
+            .filter_map(|c| {
+                c.line.map(|line| serde_json::json!({ "path": c.path, "line": line, "side": "RIGHT", "body": c.body }))
+            })
+            .collect();
+        let general: Vec<String> = self
+            .comments
+            .iter()
+            .filter(|c| c.line.is_none())
+            .map(|c| format!("**{}**: {}", c.path.display(), c.body))
+            .collect();
+        let body = if general.is_empty() {
+            format!("{} comment(s) from ai-cli review.", comments.len())
+        } else {
+            general.join("\n\n")
+        };
+        serde_json::json!({ "commit_id": commit_id, "event": "COMMENT", "body": body, "comments": comments })
+    }
+}
+
+/// Run `steps` over every chunk of `files`, one chunk at a time
+///
+/// `title` and `description` describe the change to every run. A failed run
+/// is recorded in [`ReviewReport::failed`] and the review goes on.
+pub async fn review_files(
+    executor: &PipelineExecutor,
+    steps: &[PipelineStep],
+    title: &str,
+    description: &str,
+    files: &[FileDiff],
+    max_chunk_bytes: usize,
+) -> Result<ReviewReport> {
+    let mut review = ReviewReport::default();
+    for chunk in files.iter().flat_map(|diff| chunk_diff(diff, max_chunk_bytes)) {
+        let mut context = Context::new();
+        let mut about = format!("Change under review: {}", title);
+        if !description.trim().is_empty() {
+            about.push_str(&format!("\n\n{}", description.trim()));
+        }
+        context.add_message(Message::new(MessageRole::System, about).with_provenance(Provenance::Api));
+        context.add_message(Message::new(MessageRole::System, FORMAT_INSTRUCTIONS).with_provenance(Provenance::Api));
+        context.add_file_with_provenance(
+            PathBuf::from(format!("{}.diff", chunk.path.display())),
+            chunk.patch.clone(),
+            Provenance::GitDiff { source: "pull request".to_string() },
+        );
+        context.environment.insert("FILE".to_string(), chunk.path.display().to_string());
+
+        match executor.execute_with_context(steps, context).await {
+            Ok((responses, _)) => {
+                let reply = responses.last().map(|r| r.content.as_str()).unwrap_or_default();
+                review.comments.extend(parse_comments(reply, &chunk));
+            }
+            Err(e) => review.failed.push((chunk.path.clone(), e.to_string())),
+        }
+    }
+    review.comments.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.is_none().cmp(&b.line.is_none())).then(a.line.cmp(&b.line)));
+    Ok(review)
+}
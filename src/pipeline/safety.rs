@@ -0,0 +1,239 @@
+//! Content safety filters around every provider call
+//!
+//! Rules from the `[safety]` config section scan each prompt (with its system
+//! prompt and context) before it is sent and each reply before a step uses it.
+//! A match blocks the call, is replaced with `[FILTERED:<rule>]`, or is only
+//! logged, depending on the rule's action. Built-in rules cover credentials
+//! and common PII and only warn until a rule of the same name overrides them.
+
+use anyhow::{Result, anyhow};
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::providers::{Context, MessageRole};
+
+/// What happens to text a rule matches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SafetyAction {
+    /// Fail the step without sending the prompt or using the reply
+    #[default]
+    Block,
+    /// Replace the match with `[FILTERED:<rule>]`
+    Redact,
+    /// Log the match and carry on
+    Warn,
+}
+
+/// Which side of a provider call a rule checks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SafetyScope {
+    Prompt,
+    Response,
+    #[default]
+    Both,
+}
+
+impl SafetyScope {
+    fn covers(self, side: SafetyScope) -> bool {
+        self == SafetyScope::Both || self == side
+    }
+}
+
+/// A rule from `[[safety.rules]]`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SafetyRule {
+    /// Shown in warnings, errors and placeholders; a built-in rule's name overrides it
+    pub name: String,
+    /// Regular expression to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Words or phrases to match case-insensitively as whole words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub terms: Vec<String>,
+    #[serde(default)]
+    pub action: SafetyAction,
+    #[serde(default)]
+    pub scope: SafetyScope,
+}
+
+/// `[safety]` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafetySettings {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub rules: Vec<SafetyRule>,
+}
+
+fn default_enabled() -> bool { true }
+
+impl Default for SafetySettings {
+    fn default() -> Self {
+        Self { enabled: default_enabled(), rules: Vec::new() }
+    }
+}
+
+/// Built-in rules: name and pattern; they warn and check both sides
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    (
+        "credentials",
+        r"\bsk-ant-[A-Za-z0-9_\-]{20,}|\bsk-(?:proj-)?[A-Za-z0-9_\-]{20,}|\bAIza[0-9A-Za-z_\-]{35}|\bgh[pousr]_[A-Za-z0-9]{36,}|\bxox[abpors]-[A-Za-z0-9\-]{10,}|\b(?:AKIA|ASIA)[0-9A-Z]{16}\b|-----BEGIN [A-Z ]*PRIVATE KEY-----",
+    ),
+    ("email", r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9\-]+(?:\.[A-Za-z0-9\-]+)*\.[A-Za-z]{2,}\b"),
+    ("us_ssn", r"\b\d{3}-\d{2}-\d{4}\b"),
+    ("credit_card", r"\b(?:4\d{3}|5[1-5]\d{2}|3[47]\d{2}|6011)(?:[ \-]?\d{4}){2}[ \-]?\d{1,4}\b"),
+];
+
+/// A rule match that was redacted or warned about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafetyFinding {
+    pub rule: String,
+    pub action: SafetyAction,
+    /// File path, `prompt`, `system prompt`, `message <n>`, `env <name>` or `response`
+    pub location: String,
+    /// 1-based line of the first match within its text
+    pub line: usize,
+}
+
+/// A rule with the `block` action matched
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub struct SafetyError {
+    pub rule: String,
+    /// [`SafetyScope::Prompt`] or [`SafetyScope::Response`]
+    pub scope: SafetyScope,
+    pub location: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SafetyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = if self.scope == SafetyScope::Response { "Reply" } else { "Prompt" };
+        write!(f, "{} blocked by content filter '{}' ({}, line {})", subject, self.rule, self.location, self.line)
+    }
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    name: String,
+    regex: Regex,
+    action: SafetyAction,
+    scope: SafetyScope,
+}
+
+/// Applies safety rules to the text going to and coming from providers
+#[derive(Debug)]
+pub struct SafetyFilter {
+    rules: Vec<CompiledRule>,
+}
+
+impl SafetyFilter {
+    /// Filter with the built-in rules, all warning
+    pub fn new() -> Self {
+        let rules = BUILTIN_RULES
+            .iter()
+            .map(|(name, pattern)| CompiledRule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).expect("valid built-in rule"),
+                action: SafetyAction::Warn,
+                scope: SafetyScope::Both,
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Built-in rules plus the configured ones
+    pub fn from_settings(settings: &SafetySettings) -> Result<Self> {
+        let mut filter = Self::new();
+        for rule in &settings.rules {
+            filter = filter.with_rule(rule)?;
+        }
+        Ok(filter)
+    }
+
+    /// Add a rule, or change the rule of the same name; a rule without a pattern or terms keeps the old one's
+    pub fn with_rule(mut self, rule: &SafetyRule) -> Result<Self> {
+        let existing = self.rules.iter().position(|r| r.name == rule.name);
+        let regex = match (&rule.pattern, rule.terms.is_empty(), existing) {
+            (None, true, Some(index)) => self.rules[index].regex.clone(),
+            (None, true, None) => return Err(anyhow!("Safety rule '{}' needs a pattern or terms", rule.name)),
+            (pattern, _, _) => {
+                let terms = rule.terms.iter().map(|term| regex::escape(term.trim())).collect::<Vec<_>>();
+                let alternatives = pattern
+                    .iter()
+                    .map(|p| format!("(?:{})", p))
+                    .chain((!terms.is_empty()).then(|| format!(r"(?i:\b(?:{})\b)", terms.join("|"))))
+                    .collect::<Vec<_>>();
+                Regex::new(&alternatives.join("|")).map_err(|e| anyhow!("Invalid safety rule '{}': {}", rule.name, e))?
+            }
+        };
+        let compiled = CompiledRule { name: rule.name.clone(), regex, action: rule.action, scope: rule.scope };
+        match existing {
+            Some(index) => self.rules[index] = compiled,
+            None => self.rules.push(compiled),
+        }
+        Ok(self)
+    }
+
+    /// Check one piece of text on the `side` of a call, returning it with redactions applied
+    pub fn check_text(&self, text: &str, side: SafetyScope, location: &str, findings: &mut Vec<SafetyFinding>) -> Result<String, SafetyError> {
+        let mut text = text.to_string();
+        for rule in self.rules.iter().filter(|r| r.scope.covers(side)) {
+            let Some(found) = rule.regex.find(&text) else { continue };
+            let line = text[..found.start()].matches('\n').count() + 1;
+            match rule.action {
+                SafetyAction::Block => {
+                    return Err(SafetyError { rule: rule.name.clone(), scope: side, location: location.to_string(), line });
+                }
+                SafetyAction::Redact => {
+                    text = rule.regex.replace_all(&text, NoExpand(&format!("[FILTERED:{}]", rule.name))).into_owned();
+                }
+                SafetyAction::Warn => {}
+            }
+            findings.push(SafetyFinding { rule: rule.name.clone(), action: rule.action, location: location.to_string(), line });
+        }
+        Ok(text)
+    }
+
+    /// Check everything sent with a prompt: the prompt, the system prompt, files, messages and environment
+    pub fn check_request(
+        &self,
+        prompt: &str,
+        system: Option<&str>,
+        context: &Context,
+    ) -> Result<(String, Option<String>, Context, Vec<SafetyFinding>), SafetyError> {
+        let mut findings = Vec::new();
+        let prompt = self.check_text(prompt, SafetyScope::Prompt, "prompt", &mut findings)?;
+        let system = system.map(|s| self.check_text(s, SafetyScope::Prompt, "system prompt", &mut findings)).transpose()?;
+        let mut checked = context.clone();
+        for (path, content) in checked.file_contents.iter_mut() {
+            *content = self.check_text(content, SafetyScope::Prompt, &path.display().to_string(), &mut findings)?;
+        }
+        for (index, message) in checked.conversation_history.iter_mut().enumerate() {
+            // Replies were checked when they came back
+            if message.role != MessageRole::Assistant {
+                message.content = self.check_text(&message.content, SafetyScope::Prompt, &format!("message {}", index + 1), &mut findings)?;
+            }
+        }
+        for (key, value) in checked.environment.iter_mut() {
+            *value = self.check_text(value, SafetyScope::Prompt, &format!("env {}", key), &mut findings)?;
+        }
+        Ok((prompt, system, checked, findings))
+    }
+
+    /// Whether any rule may block or rewrite replies, so they must not be shown before they are checked
+    pub fn filters_responses(&self) -> bool {
+        self.rules.iter().any(|r| r.scope.covers(SafetyScope::Response) && r.action != SafetyAction::Warn)
+    }
+
+    /// Check a provider's reply
+    pub fn check_response(&self, content: &str, findings: &mut Vec<SafetyFinding>) -> Result<String, SafetyError> {
+        self.check_text(content, SafetyScope::Response, "response", findings)
+    }
+}
+
+impl Default for SafetyFilter {
+    fn default() -> Self { Self::new() }
+}
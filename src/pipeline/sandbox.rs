@@ -0,0 +1,251 @@
+//! Where steps that write project files put them
+//!
+//! Every write goes through a [`FileWriter`], which takes paths relative to the
+//! project root and refuses ones that leave it. Normally files land in the
+//! project; with `--sandbox` they land in the run's `sandbox/` directory
+//! instead, mirroring the project layout:
+//!
+//! ```text
+//! <run dir>/sandbox/src/lib.rs   file as the run wrote it
+//! <run dir>/sandbox.json         hash of each project file when the run first wrote it
+//! ```
+//!
+//! After the run the changes are summarized against the project, and
+//! `ai-cli apply <run-id>` copies them over. Files edited in the project since
+//! the run wrote them are conflicts, and are only overwritten with `--force`.
+//! With `[encryption]` on, the sandbox copies and record are encrypted like
+//! the rest of the run, and only decrypted when applied.
+
+use anyhow::{Context as _, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::context::git::{ChangeKind, FileDiff};
+use crate::context::incremental::unified_diff;
+use crate::history::encryption::{self, Encryption};
+
+/// Directory of a run holding the files a sandboxed run wrote
+pub const SANDBOX_DIR: &str = "sandbox";
+
+/// Record of the project files a sandboxed run wrote over
+pub const SANDBOX_FILE: &str = "sandbox.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SandboxRecord {
+    /// Project-relative path to the FNV-1a hash of the project file when the
+    /// run first wrote it; `None` when the file did not exist
+    bases: BTreeMap<PathBuf, Option<String>>,
+}
+
+/// A run's sandbox directory
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    run_dir: PathBuf,
+    record: Arc<Mutex<SandboxRecord>>,
+    encryption: Option<Arc<Encryption>>,
+}
+
+impl Sandbox {
+    /// Start an empty sandbox in `run_dir`
+    pub fn create(run_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::create_with(run_dir, None)
+    }
+
+    /// Start an empty sandbox in `run_dir` whose files are encrypted with `encryption`
+    pub fn create_with(run_dir: impl Into<PathBuf>, encryption: Option<Arc<Encryption>>) -> Result<Self> {
+        let sandbox = Self { run_dir: run_dir.into(), record: Arc::default(), encryption };
+        std::fs::create_dir_all(sandbox.dir())
+            .with_context(|| format!("Failed to create {}", sandbox.dir().display()))?;
+        sandbox.save(&SandboxRecord::default())?;
+        Ok(sandbox)
+    }
+
+    /// Open the sandbox of a finished run
+    pub fn open(run_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with(run_dir, None)
+    }
+
+    /// Open the sandbox of a finished run, decrypting it with `encryption`
+    pub fn open_with(run_dir: impl Into<PathBuf>, encryption: Option<Arc<Encryption>>) -> Result<Self> {
+        let run_dir = run_dir.into();
+        let path = run_dir.join(SANDBOX_FILE);
+        if !path.exists() {
+            return Err(anyhow!("Run {} has no sandbox; only runs started with --sandbox can be applied", run_dir.display()));
+        }
+        let text = encryption::read_to_string(&path, encryption.as_deref())?;
+        let record = serde_json::from_str(&text).with_context(|| format!("Invalid sandbox record {}", path.display()))?;
+        Ok(Self { run_dir, record: Arc::new(Mutex::new(record)), encryption })
+    }
+
+    /// Directory the run's files are written to
+    pub fn dir(&self) -> PathBuf {
+        self.run_dir.join(SANDBOX_DIR)
+    }
+
+    /// Remember what `relative` looked like in the project before the first write to it
+    fn record_base(&self, root: &Path, relative: &Path) -> Result<()> {
+        let mut record = self.record.lock().unwrap_or_else(|e| e.into_inner());
+        if record.bases.contains_key(relative) {
+            return Ok(());
+        }
+        record.bases.insert(relative.to_path_buf(), file_hash(&root.join(relative)));
+        self.save(&record)
+    }
+
+    fn save(&self, record: &SandboxRecord) -> Result<()> {
+        encryption::write(&self.run_dir.join(SANDBOX_FILE), serde_json::to_string_pretty(record)?, self.encryption.as_deref())
+    }
+
+    /// The run's copy of the project file `relative`
+    fn read(&self, relative: &Path) -> Result<String> {
+        encryption::read_to_string(&self.dir().join(relative), self.encryption.as_deref())
+    }
+
+    /// Sandboxed files that differ from the project at `root`, sorted by path
+    pub fn changes(&self, root: &Path) -> Result<Vec<FileDiff>> {
+        let record = self.record.lock().unwrap_or_else(|e| e.into_inner());
+        let mut changes = Vec::new();
+        for relative in record.bases.keys() {
+            let new = self.read(relative)?;
+            let (kind, old) = match std::fs::read_to_string(root.join(relative)) {
+                Ok(old) if old == new => continue,
+                Ok(old) => (ChangeKind::Modified, old),
+                Err(_) => (ChangeKind::Added, String::new()),
+            };
+            let patch = format!("diff --git a/{0} b/{0}\n{1}", relative.display(), unified_diff(relative, &old, &new));
+            changes.push(FileDiff { path: relative.clone(), old_path: None, kind, patch });
+        }
+        Ok(changes)
+    }
+
+    /// Changed files whose project copy was edited after the run first wrote them
+    pub fn conflicts(&self, root: &Path, changes: &[FileDiff]) -> Vec<PathBuf> {
+        let record = self.record.lock().unwrap_or_else(|e| e.into_inner());
+        changes
+            .iter()
+            .filter(|change| record.bases.get(&change.path).is_some_and(|base| *base != file_hash(&root.join(&change.path))))
+            .map(|change| change.path.clone())
+            .collect()
+    }
+
+    /// Write the changed files into the project at `root`, returning them
+    ///
+    /// Fails without writing anything when there are conflicts, unless `force`.
+    pub fn apply(&self, root: &Path, force: bool) -> Result<Vec<FileDiff>> {
+        let changes = self.changes(root)?;
+        let conflicts = self.conflicts(root, &changes);
+        if !conflicts.is_empty() && !force {
+            let listed: Vec<String> = conflicts.iter().map(|p| p.display().to_string()).collect();
+            return Err(anyhow!(
+                "Changed in the project since the run wrote them: {}; re-run with --force to overwrite",
+                listed.join(", ")
+            ));
+        }
+        for change in &changes {
+            let target = root.join(&change.path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(&target, self.read(&change.path)?)
+                .with_context(|| format!("Failed to write {}", target.display()))?;
+        }
+        Ok(changes)
+    }
+}
+
+/// Writes files for a run, into the project or its sandbox
+#[derive(Debug, Clone)]
+pub struct FileWriter {
+    root: PathBuf,
+    sandbox: Option<Sandbox>,
+}
+
+impl FileWriter {
+    /// Write straight into the project at `root`
+    pub fn direct(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), sandbox: None }
+    }
+
+    /// Write into `sandbox` instead of the project at `root`
+    pub fn sandboxed(root: impl Into<PathBuf>, sandbox: Sandbox) -> Self {
+        Self { root: root.into(), sandbox: Some(sandbox) }
+    }
+
+    /// Project root paths are resolved against
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    /// `path` relative to the project root; an error if it points outside the project
+    pub fn relative(&self, path: &Path) -> Result<PathBuf> {
+        let inside = if path.is_absolute() {
+            path.strip_prefix(&self.root)
+                .map_err(|_| anyhow!("Refusing to write {}: outside the project", path.display()))?
+        } else {
+            path
+        };
+        let mut relative = PathBuf::new();
+        for component in inside.components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                _ => return Err(anyhow!("Refusing to write {}: outside the project", path.display())),
+            }
+        }
+        if relative.as_os_str().is_empty() {
+            return Err(anyhow!("Refusing to write '{}': not a file path", path.display()));
+        }
+        Ok(relative)
+    }
+
+    /// Write `text` to the project path `path`, returning where it went
+    pub fn write(&self, path: &Path, text: &str) -> Result<PathBuf> {
+        let relative = self.relative(path)?;
+        let (target, encryption) = match &self.sandbox {
+            Some(sandbox) => {
+                sandbox.record_base(&self.root, &relative)?;
+                (sandbox.dir().join(&relative), sandbox.encryption.as_deref())
+            }
+            None => (self.root.join(&relative), None),
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        encryption::write(&target, text, encryption)?;
+        Ok(target)
+    }
+}
+
+impl Default for FileWriter {
+    /// Write into the current directory
+    fn default() -> Self {
+        Self::direct(".")
+    }
+}
+
+/// One `A path (+added -removed)` line per change
+pub fn diffstat(changes: &[FileDiff]) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let count = |sign: char, header: &str| {
+                change.patch.lines().filter(|l| l.starts_with(sign) && !l.starts_with(header)).count()
+            };
+            let letter = if change.kind == ChangeKind::Added { 'A' } else { 'M' };
+            format!("{} {} (+{} -{})\n", letter, change.path.display(), count('+', "+++"), count('-', "---"))
+        })
+        .collect()
+}
+
+/// FNV-1a of a file's bytes, or `None` when it cannot be read
+fn file_hash(path: &Path) -> Option<String> {
+    let bytes = std::fs::read(path).ok()?;
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3));
+    Some(format!("{:016x}", hash))
+}
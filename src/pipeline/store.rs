@@ -0,0 +1,80 @@
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use std::path::{Path, PathBuf};
+
+use super::definition::{PipelineDefinition, validate_name};
+use crate::config;
+
+/// File-backed storage for named pipelines (`<dir>/<name>.yaml`)
+pub struct PipelineStore {
+    dir: PathBuf,
+}
+
+impl PipelineStore {
+    /// Create a store rooted at a directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Open the store in the user config directory
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(config::config_dir()?.join("pipelines")))
+    }
+
+    /// Get the store directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file holding a named pipeline
+    pub fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.yaml", name))
+    }
+
+    /// Check if a pipeline is saved under the name
+    pub fn exists(&self, name: &str) -> bool {
+        self.path_for(name).exists()
+    }
+
+    /// Save a pipeline, replacing any existing one with the same name
+    pub fn save(&self, definition: &PipelineDefinition) -> Result<PathBuf> {
+        validate_name(&definition.name)?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let path = self.path_for(&definition.name);
+        std::fs::write(&path, definition.to_yaml()?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Load a saved pipeline by name
+    pub fn load(&self, name: &str) -> Result<PipelineDefinition> {
+        validate_name(name)?;
+        let path = self.path_for(name);
+        if !path.exists() {
+            return Err(anyhow!("No saved pipeline named '{}'", name));
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        PipelineDefinition::from_yaml(&text)
+            .with_context(|| format!("Invalid pipeline file {}", path.display()))
+    }
+
+    /// List saved pipeline names in sorted order
+    pub fn list(&self) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("yaml")
+                && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+            {
+                names.push(stem.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
@@ -0,0 +1,108 @@
+use glob::Pattern;
+use std::collections::HashMap;
+
+/// Render `{{env.NAME}}` placeholders from `vars`; unknown names are left untouched
+pub fn render_env(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+
+        let placeholder = &rest[start..start + 2 + end + 2];
+        let value = after[..end]
+            .trim()
+            .strip_prefix("env.")
+            .and_then(|name| vars.get(name));
+        match value {
+            Some(value) => output.push_str(value),
+            None => output.push_str(placeholder),
+        }
+        rest = &after[end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Parse a `KEY=VALUE` pair as given to `--env`
+pub fn parse_env_pair(pair: &str) -> Result<(String, String), String> {
+    let (key, value) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("invalid KEY=VALUE pair: '{}' (missing '=')", pair))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("invalid KEY=VALUE pair: '{}' (empty key)", pair));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parts of names that mark a variable as a credential
+const SECRET_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL", "AUTH"];
+
+/// Variables from `vars` (usually the process environment) selected by `--env-passthrough` globs
+///
+/// Nothing passes unless a pattern names it. Wildcards never select names that look like
+/// credentials (`GITHUB_TOKEN`, `AWS_SECRET_ACCESS_KEY`); such a variable passes only when
+/// a pattern spells out its exact name.
+pub fn passthrough_env(patterns: &[Pattern], vars: impl IntoIterator<Item = (String, String)>) -> Vec<(String, String)> {
+    let mut selected: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(name, _)| {
+            patterns.iter().any(|pattern| {
+                pattern.as_str() == name || (pattern.matches(name) && !looks_secret(name))
+            })
+        })
+        .collect();
+    selected.sort();
+    selected
+}
+
+fn looks_secret(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    SECRET_MARKERS.iter().any(|marker| upper.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_env() {
+        let vars = HashMap::from([("LANG".to_string(), "Rust".to_string())]);
+        assert_eq!(render_env("Write {{env.LANG}} code", &vars), "Write Rust code");
+        assert_eq!(render_env("{{ env.LANG }}!", &vars), "Rust!");
+        assert_eq!(render_env("keep {{env.MISSING}} and {{other}}", &vars), "keep {{env.MISSING}} and {{other}}");
+        assert_eq!(render_env("unterminated {{env.LANG", &vars), "unterminated {{env.LANG");
+    }
+
+    #[test]
+    fn test_parse_env_pair() {
+        assert_eq!(parse_env_pair("A=b=c").unwrap(), ("A".to_string(), "b=c".to_string()));
+        assert_eq!(parse_env_pair("EMPTY=").unwrap(), ("EMPTY".to_string(), String::new()));
+        assert!(parse_env_pair("novalue").is_err());
+        assert!(parse_env_pair("=x").is_err());
+    }
+
+    #[test]
+    fn test_passthrough_env() {
+        let vars = || {
+            [("CI_JOB", "42"), ("CI_JOB_TOKEN", "t0k"), ("GITHUB_TOKEN", "ghp"), ("HOME", "/root"), ("LANG", "C")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        };
+        let patterns = |globs: &[&str]| globs.iter().map(|g| Pattern::new(g).unwrap()).collect::<Vec<_>>();
+
+        assert!(passthrough_env(&[], vars()).is_empty());
+        assert_eq!(passthrough_env(&patterns(&["CI_*", "LANG"]), vars()), vec![
+            ("CI_JOB".to_string(), "42".to_string()),
+            ("LANG".to_string(), "C".to_string()),
+        ]);
+        assert!(passthrough_env(&patterns(&["*"]), vars()).iter().all(|(name, _)| !name.contains("TOKEN")));
+        assert_eq!(passthrough_env(&patterns(&["GITHUB_TOKEN"]), vars()), vec![("GITHUB_TOKEN".to_string(), "ghp".to_string())]);
+    }
+}
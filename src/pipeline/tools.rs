@@ -0,0 +1,382 @@
+//! Built-in local tools a step's model may call
+//!
+//! `read_file` and `list_dir` only see paths inside the allowed directories
+//! (the step's working directory by default, which is the project root, or the
+//! package root with `--package`, unless the `tools` section sets `working_dir`). `run_command` runs a program without a shell,
+//! only if it is on the command allowlist (when one is set), and only after an
+//! [`Approver`] says yes; the default approver asks on the terminal and refuses
+//! when there is none.
+
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::providers::{ToolCall, ToolHandler, ToolSpec, Toolset};
+
+/// Largest file `read_file` returns
+const MAX_READ_BYTES: u64 = 256 * 1024;
+/// Most entries `list_dir` returns
+const MAX_LIST_ENTRIES: usize = 1000;
+/// Most output `run_command` returns per stream
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+/// How long `run_command` waits before killing the program
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A tool ai-cli ships
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinTool {
+    ReadFile,
+    ListDir,
+    RunCommand,
+}
+
+impl BuiltinTool {
+    pub const ALL: [BuiltinTool; 3] = [BuiltinTool::ReadFile, BuiltinTool::ListDir, BuiltinTool::RunCommand];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinTool::ReadFile => "read_file",
+            BuiltinTool::ListDir => "list_dir",
+            BuiltinTool::RunCommand => "run_command",
+        }
+    }
+
+    /// Schema and description offered to the model
+    pub fn spec(self) -> ToolSpec {
+        match self {
+            BuiltinTool::ReadFile => ToolSpec::new(
+                self.name(),
+                "Read a UTF-8 text file of the project.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": { "path": { "type": "string", "description": "Path relative to the project root" } },
+                    "required": ["path"],
+                }),
+            ),
+            BuiltinTool::ListDir => ToolSpec::new(
+                self.name(),
+                "List the entries of a project directory; directories end with '/'.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": { "path": { "type": "string", "description": "Path relative to the project root; defaults to the root" } },
+                }),
+            ),
+            BuiltinTool::RunCommand => ToolSpec::new(
+                self.name(),
+                "Run a program (no shell) in the project root after the user approves it; returns its exit status and output.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "program": { "type": "string" },
+                        "args": { "type": "array", "items": { "type": "string" } },
+                    },
+                    "required": ["program"],
+                }),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for BuiltinTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BuiltinTool {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        BuiltinTool::ALL
+            .into_iter()
+            .find(|tool| tool.name() == s)
+            .ok_or_else(|| anyhow!("Unknown tool '{}' (expected one of read_file, list_dir, run_command)", s))
+    }
+}
+
+/// Decides whether a command may run
+pub trait Approver: Send + Sync {
+    fn approve(&self, command: &str) -> bool;
+}
+
+impl<F> Approver for F
+where
+    F: Fn(&str) -> bool + Send + Sync,
+{
+    fn approve(&self, command: &str) -> bool {
+        self(command)
+    }
+}
+
+/// Asks `Run <command>? [y/N]` on stderr; refuses when stdin is not a terminal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalApprover;
+
+impl Approver for TerminalApprover {
+    fn approve(&self, command: &str) -> bool {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            tracing::warn!(command, "refusing command: no terminal to confirm it");
+            return false;
+        }
+        eprint!("Model wants to run `{}`. Allow? [y/N] ", command);
+        let _ = std::io::stderr().flush();
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer).is_ok() && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    }
+}
+
+/// Which built-in tools a step may use, and where
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolsDefinition {
+    /// Tool names: `read_file`, `list_dir`, `run_command`
+    pub enable: Vec<String>,
+    /// Directories file tools may see, relative to the project root (default: the root)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_paths: Vec<PathBuf>,
+    /// Programs `run_command` may start; any program when empty (each still needs approval)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_commands: Vec<String>,
+    /// Directory the tools treat as their root, relative to the project or package root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+}
+
+impl ToolsDefinition {
+    /// Check every tool name is known
+    pub fn validate(&self) -> Result<()> {
+        if self.enable.is_empty() {
+            return Err(anyhow!("tools.enable lists no tools"));
+        }
+        self.enable.iter().try_for_each(|name| name.parse::<BuiltinTool>().map(|_| ()))
+    }
+}
+
+/// Sandboxed built-in tools rooted at a project directory
+#[derive(Clone)]
+pub struct ToolRegistry {
+    root: PathBuf,
+    enabled: Vec<BuiltinTool>,
+    allowed_paths: Vec<PathBuf>,
+    allowed_commands: Vec<String>,
+    approver: Arc<dyn Approver>,
+}
+
+impl ToolRegistry {
+    /// No tools enabled; file tools limited to `root`; commands confirmed on the terminal
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            enabled: Vec::new(),
+            allowed_paths: Vec::new(),
+            allowed_commands: Vec::new(),
+            approver: Arc::new(TerminalApprover),
+        }
+    }
+
+    /// Registry configured by a step's `tools` section, rooted at its `working_dir` inside `root`
+    pub fn from_definition(root: impl Into<PathBuf>, definition: &ToolsDefinition) -> Result<Self> {
+        let mut root = root.into();
+        if let Some(dir) = &definition.working_dir {
+            check_relative("working_dir", dir)?;
+            root = root.join(dir);
+        }
+        let mut registry = Self::new(root);
+        for name in &definition.enable {
+            registry = registry.enable(name.parse()?);
+        }
+        for path in &definition.allow_paths {
+            check_relative("allow_paths entry", path)?;
+            registry = registry.allow_path(path);
+        }
+        for program in &definition.allow_commands {
+            registry = registry.allow_command(program);
+        }
+        Ok(registry)
+    }
+
+    pub fn enable(mut self, tool: BuiltinTool) -> Self {
+        if !self.enabled.contains(&tool) {
+            self.enabled.push(tool);
+        }
+        self
+    }
+
+    /// Let file tools see `path` (relative to the root) instead of the whole root;
+    /// directories that resolve outside the root are ignored
+    pub fn allow_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.allowed_paths.push(path.into());
+        self
+    }
+
+    /// Let `run_command` start `program`; once any is allowed, others are refused
+    pub fn allow_command(mut self, program: impl Into<String>) -> Self {
+        self.allowed_commands.push(program.into());
+        self
+    }
+
+    /// Decide with `approver` whether commands may run
+    pub fn with_approver(mut self, approver: Arc<dyn Approver>) -> Self {
+        self.approver = approver;
+        self
+    }
+
+    /// The enabled tools, handled by this registry
+    pub fn toolset(self) -> Toolset {
+        let specs = self.enabled.iter().map(|tool| tool.spec()).collect();
+        Toolset::new(specs, Arc::new(self))
+    }
+
+    /// Resolve `path` inside the root, refusing anything outside the allowed directories
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let root = self.root.canonicalize().with_context(|| format!("Tool root {} is not accessible", self.root.display()))?;
+        let resolved = root.join(path).canonicalize().with_context(|| format!("{}: no such file or directory", path))?;
+        let allowed = if self.allowed_paths.is_empty() {
+            resolved.starts_with(&root)
+        } else {
+            self.allowed_paths
+                .iter()
+                .filter_map(|dir| root.join(dir).canonicalize().ok())
+                .filter(|dir| dir.starts_with(&root))
+                .any(|dir| resolved.starts_with(dir))
+        };
+        if !allowed {
+            return Err(anyhow!("{}: outside the directories this step may access", path));
+        }
+        Ok(resolved)
+    }
+
+    fn read_file(&self, input: &serde_json::Value) -> Result<String> {
+        let path = input["path"].as_str().ok_or_else(|| anyhow!("read_file needs a 'path'"))?;
+        let resolved = self.resolve(path)?;
+        let size = std::fs::metadata(&resolved)?.len();
+        if size > MAX_READ_BYTES {
+            return Err(anyhow!("{} is {} bytes; read_file returns at most {}", path, size, MAX_READ_BYTES));
+        }
+        std::fs::read_to_string(&resolved).with_context(|| format!("{} is not a UTF-8 text file", path))
+    }
+
+    fn list_dir(&self, input: &serde_json::Value) -> Result<String> {
+        let path = input["path"].as_str().unwrap_or(".");
+        let resolved = self.resolve(path)?;
+        let mut entries: Vec<String> = std::fs::read_dir(&resolved)
+            .with_context(|| format!("{} is not a directory", path))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_dir() { format!("{}/", name) } else { name }
+            })
+            .collect();
+        entries.sort();
+        let total = entries.len();
+        entries.truncate(MAX_LIST_ENTRIES);
+        let mut listing = entries.join("\n");
+        if total > MAX_LIST_ENTRIES {
+            listing.push_str(&format!("\n... {} more", total - MAX_LIST_ENTRIES));
+        }
+        Ok(listing)
+    }
+
+    async fn run_command(&self, input: &serde_json::Value) -> Result<String> {
+        let program = input["program"].as_str().ok_or_else(|| anyhow!("run_command needs a 'program'"))?;
+        let args: Vec<String> = input["args"]
+            .as_array()
+            .map(|args| args.iter().map(|a| a.as_str().map_or_else(|| a.to_string(), str::to_string)).collect())
+            .unwrap_or_default();
+        if !self.allowed_commands.is_empty() && !self.allowed_commands.iter().any(|p| p == program) {
+            return Err(anyhow!("'{}' is not an allowed command (allowed: {})", program, self.allowed_commands.join(", ")));
+        }
+
+        let command_line = std::iter::once(program).chain(args.iter().map(String::as_str)).map(shell_quote).collect::<Vec<_>>().join(" ");
+        let approver = self.approver.clone();
+        let line = command_line.clone();
+        let approved = tokio::task::spawn_blocking(move || approver.approve(&line)).await.unwrap_or(false);
+        if !approved {
+            return Err(anyhow!("The user declined to run `{}`", command_line));
+        }
+
+        tracing::info!(command = %command_line, "running tool command");
+        let child = tokio::process::Command::new(program)
+            .args(&args)
+            .current_dir(&self.root)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(COMMAND_TIMEOUT, child)
+            .await
+            .map_err(|_| anyhow!("`{}` did not finish within {} s", command_line, COMMAND_TIMEOUT.as_secs()))?
+            .with_context(|| format!("Failed to start `{}`", command_line))?;
+        Ok(format!(
+            "exit status: {}\n--- stdout ---\n{}\n--- stderr ---\n{}",
+            output.status.code().map_or_else(|| "killed".to_string(), |c| c.to_string()),
+            truncated(&output.stdout),
+            truncated(&output.stderr),
+        ))
+    }
+}
+
+/// Refuse absolute paths and paths that climb out with `..`
+fn check_relative(what: &str, path: &Path) -> Result<()> {
+    if path.is_absolute() || path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+        return Err(anyhow!("{} {} must be a relative path inside the project", what, path.display()));
+    }
+    Ok(())
+}
+
+/// `word` quoted for a POSIX shell, so the confirmation shows exactly which arguments run
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c)) {
+        return word.to_string();
+    }
+    if !word.chars().any(char::is_control) {
+        return format!("'{}'", word.replace('\'', r"'\''"));
+    }
+    // ANSI-C quoting keeps newlines and escape sequences visible instead of letting them rewrite the prompt
+    let escaped: String = word
+        .chars()
+        .map(|c| match c {
+            '\'' => r"\'".to_string(),
+            '\\' => r"\\".to_string(),
+            '\n' => r"\n".to_string(),
+            '\t' => r"\t".to_string(),
+            '\r' => r"\r".to_string(),
+            c if c.is_control() => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect();
+    format!("$'{}'", escaped)
+}
+
+/// Lossy UTF-8 text of `bytes`, keeping the last `MAX_OUTPUT_BYTES`
+fn truncated(bytes: &[u8]) -> String {
+    let start = bytes.len().saturating_sub(MAX_OUTPUT_BYTES);
+    let text = String::from_utf8_lossy(&bytes[start..]);
+    if start > 0 { format!("[... {} bytes cut]\n{}", start, text) } else { text.into_owned() }
+}
+
+#[async_trait]
+impl ToolHandler for ToolRegistry {
+    async fn call(&self, call: &ToolCall) -> Result<String> {
+        let tool: BuiltinTool = call.name.parse()?;
+        if !self.enabled.contains(&tool) {
+            return Err(anyhow!("Tool '{}' is not enabled for this step", tool));
+        }
+        tracing::debug!(tool = %tool, input = %call.input, "tool call");
+        match tool {
+            BuiltinTool::ReadFile => self.read_file(&call.input),
+            BuiltinTool::ListDir => self.list_dir(&call.input),
+            BuiltinTool::RunCommand => self.run_command(&call.input).await,
+        }
+    }
+}
+
+/// Root directory tools of a stored pipeline see when the caller names none: the current directory
+pub fn default_root() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| Path::new(".").to_path_buf())
+}
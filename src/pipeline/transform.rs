@@ -1,13 +1,17 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use super::sandbox::FileWriter;
 use crate::providers::Response;
 use thiserror::Error;
 
 /// Errors that can occur during transform operations
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum TransformError {
     #[error("JSON parsing failed: {0}")]
-    JsonParse(#[from] serde_json::Error),
+    JsonParse(String),
     
     #[error("Field '{field}' not found in JSON")]
     FieldNotFound { field: String },
@@ -17,9 +21,10 @@ pub enum TransformError {
 }
 
 /// Behavior when JSON field extraction fails
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum FallbackBehavior {
     /// Keep the original content unchanged
+    #[default]
     KeepOriginal,
     /// Return empty content
     ReturnEmpty,
@@ -27,12 +32,6 @@ pub enum FallbackBehavior {
     ReturnError,
 }
 
-impl Default for FallbackBehavior {
-    fn default() -> Self {
-        Self::KeepOriginal
-    }
-}
-
 /// Configuration for JSON extractor transform
 #[derive(Debug, Clone)]
 pub struct JsonExtractorConfig {
@@ -59,9 +58,20 @@ impl JsonExtractorConfig {
 pub trait Transform: Send + Sync {
     /// Transform a response from one step to be used as input for the next step
     async fn transform(&self, response: Response) -> Result<Response>;
+
+    /// Transform with the run's [`FileWriter`], for transforms that write project
+    /// files; the executor calls this one. Defaults to [`Transform::transform`].
+    async fn transform_with_writer(&self, response: Response, _writer: &FileWriter) -> Result<Response> {
+        self.transform(response).await
+    }
     
     /// Get the name of this transform
     fn name(&self) -> &str;
+
+    /// Spec that rebuilds this transform through the [`TransformRegistry`], e.g. `summarizer:200`
+    fn spec(&self) -> String {
+        self.name().to_string()
+    }
 }
 
 /// Identity transform that passes through responses unchanged
@@ -106,7 +116,7 @@ impl Transform for JsonExtractorTransform {
     async fn transform(&self, mut response: Response) -> Result<Response> {
         // Parse JSON and extract field
         let json: serde_json::Value = serde_json::from_str(&response.content)
-            .map_err(TransformError::JsonParse)?;
+            .map_err(|e| TransformError::JsonParse(e.to_string()))?;
         
         match json.get(&self.config.field) {
             Some(value) => {
@@ -138,6 +148,10 @@ impl Transform for JsonExtractorTransform {
     fn name(&self) -> &str {
         "json_extractor"
     }
+
+    fn spec(&self) -> String {
+        format!("json_extractor:{}", self.config.field)
+    }
 }
 
 /// Summarizer transform that summarizes the response content
@@ -166,6 +180,233 @@ impl Transform for SummarizerTransform {
     fn name(&self) -> &str {
         "summarizer"
     }
+
+    fn spec(&self) -> String {
+        format!("summarizer:{}", self.max_length)
+    }
+}
+
+/// One `FILE: path` section of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSection {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// `FILE: path` headers, each followed by a fenced block with the file's contents
+///
+/// The header may be decorated as markdown (`### FILE: path`, `**FILE: path**`)
+/// and the path may be in backticks. A header without a fence on the next
+/// non-blank line is ignored. Later sections for the same path win.
+pub fn parse_file_sections(text: &str) -> Vec<FileSection> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut sections: Vec<FileSection> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let Some(path) = file_header(lines[index]) else {
+            index += 1;
+            continue;
+        };
+        index += 1;
+        while index < lines.len() && lines[index].trim().is_empty() {
+            index += 1;
+        }
+        let Some(fence) = lines.get(index).and_then(|line| fence_of(line)) else { continue };
+        let marker = if fence.starts_with('`') { '`' } else { '~' };
+        let start = index + 1;
+        let end = (start..lines.len())
+            .find(|&i| {
+                let line = lines[i].trim();
+                line.len() >= fence.len() && line.chars().all(|c| c == marker)
+            })
+            .unwrap_or(lines.len());
+        let mut content = lines[start..end].join("\n");
+        content.push('\n');
+        sections.retain(|section| section.path != path);
+        sections.push(FileSection { path, content });
+        index = end + 1;
+    }
+    sections
+}
+
+/// Path named by a `FILE: path` line
+fn file_header(line: &str) -> Option<PathBuf> {
+    let line = line.trim().trim_start_matches(['#', '*', '>']).trim_start();
+    let rest = line.get(..5).filter(|prefix| prefix.eq_ignore_ascii_case("file:")).map(|_| &line[5..])?;
+    let path = rest.trim().trim_end_matches('*').trim().trim_matches(['`', '"', '\'']);
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Opening fence (three or more backticks or tildes) of a code block line
+fn fence_of(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence = &line[..line.len() - line.trim_start_matches(marker).len()];
+    (fence.len() >= 3).then_some(fence)
+}
+
+/// Writes every `FILE: path` section of a response to its path
+///
+/// Files go through the run's [`FileWriter`], so they land in the sandbox with
+/// `--sandbox` and can never leave the project. The response passes through
+/// unchanged, with the written paths in the `files_written` metadata.
+pub struct MultiFileWriterTransform {
+    /// Directory under the project root the paths are relative to
+    base: Option<PathBuf>,
+}
+
+impl MultiFileWriterTransform {
+    pub fn new() -> Self {
+        Self { base: None }
+    }
+
+    /// Write under `base` instead of the project root
+    pub fn under(base: impl Into<PathBuf>) -> Self {
+        Self { base: Some(base.into()) }
+    }
+}
+
+impl Default for MultiFileWriterTransform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Transform for MultiFileWriterTransform {
+    /// Writes relative to the current directory; the executor passes the run's writer instead
+    async fn transform(&self, response: Response) -> Result<Response> {
+        self.transform_with_writer(response, &FileWriter::default()).await
+    }
+
+    async fn transform_with_writer(&self, mut response: Response, writer: &FileWriter) -> Result<Response> {
+        let sections = parse_file_sections(&response.content);
+        if sections.is_empty() {
+            return Err(TransformError::Operation("multi_file_writer found no `FILE: path` sections with a fenced block".to_string()).into());
+        }
+        // Check every path before writing any, so a bad one leaves nothing half-written
+        let base = self.base.as_deref().unwrap_or(Path::new(""));
+        let files = sections
+            .into_iter()
+            .map(|section| Ok((writer.relative(&base.join(&section.path))?, section.content)))
+            .collect::<Result<Vec<_>>>()?;
+        for (path, content) in &files {
+            writer.write(path, content)?;
+        }
+        let written: Vec<String> = files.iter().map(|(path, _)| path.display().to_string()).collect();
+        response.metadata.insert("files_written".to_string(), written.join(", "));
+        Ok(response)
+    }
+
+    fn name(&self) -> &str {
+        "multi_file_writer"
+    }
+
+    fn spec(&self) -> String {
+        match &self.base {
+            Some(base) => format!("multi_file_writer:{}", base.display()),
+            None => "multi_file_writer".to_string(),
+        }
+    }
+}
+
+/// Builds a transform from the argument after `name:` in its spec, if any
+pub type TransformConstructor = Arc<dyn Fn(Option<&str>) -> Result<Arc<dyn Transform>> + Send + Sync>;
+
+/// Named transform constructors that specs are resolved against
+#[derive(Clone)]
+pub struct TransformRegistry {
+    constructors: BTreeMap<String, TransformConstructor>,
+}
+
+impl TransformRegistry {
+    /// Registry without any transforms
+    pub fn new() -> Self {
+        Self { constructors: BTreeMap::new() }
+    }
+
+    /// Registry with `identity`, `summarizer`, `json_extractor` and `multi_file_writer`
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("identity", |arg| match arg {
+            None => Ok(Arc::new(IdentityTransform)),
+            Some(_) => Err(TransformError::Operation("identity takes no argument".to_string()).into()),
+        });
+        registry.register("summarizer", |arg| {
+            let max = arg.ok_or_else(|| TransformError::Operation("summarizer needs a length: summarizer:<n>".to_string()))?;
+            let max_length = max.parse::<usize>()
+                .map_err(|_| TransformError::Operation(format!("Invalid summarizer length: '{}'", max)))?;
+            Ok(Arc::new(SummarizerTransform::new(max_length)))
+        });
+        registry.register("json_extractor", |arg| match arg {
+            Some(field) if !field.is_empty() => Ok(Arc::new(JsonExtractorTransform::new(field))),
+            _ => Err(TransformError::Operation("json_extractor needs a field: json_extractor:<field>".to_string()).into()),
+        });
+        registry.register("multi_file_writer", |arg| match arg {
+            Some(base) if !base.is_empty() => Ok(Arc::new(MultiFileWriterTransform::under(base))),
+            _ => Ok(Arc::new(MultiFileWriterTransform::new())),
+        });
+        registry
+    }
+
+    /// Resolve specs named `name` with `constructor`, replacing any earlier one
+    pub fn register<F>(&mut self, name: impl Into<String>, constructor: F)
+    where
+        F: Fn(Option<&str>) -> Result<Arc<dyn Transform>> + Send + Sync + 'static,
+    {
+        self.constructors.insert(name.into(), Arc::new(constructor));
+    }
+
+    /// Registered transform names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.constructors.keys().map(String::as_str).collect()
+    }
+
+    /// Build the transform a spec like `summarizer:200` names
+    pub fn build(&self, spec: &str) -> Result<Arc<dyn Transform>> {
+        let spec = spec.trim();
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (spec, None),
+        };
+        match self.constructors.get(name) {
+            Some(constructor) => constructor(arg),
+            None => Err(TransformError::Operation(format!(
+                "Unknown transform spec: '{}' (expected one of: {})",
+                spec,
+                self.names().join(", ")
+            )).into()),
+        }
+    }
+}
+
+impl Default for TransformRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Registry used by [`from_spec`], and so by stored and deserialized pipelines
+fn registry() -> &'static RwLock<TransformRegistry> {
+    static REGISTRY: OnceLock<RwLock<TransformRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(TransformRegistry::builtin()))
+}
+
+/// Make `name` available to [`from_spec`] alongside the built-in transforms
+pub fn register<F>(name: impl Into<String>, constructor: F)
+where
+    F: Fn(Option<&str>) -> Result<Arc<dyn Transform>> + Send + Sync + 'static,
+{
+    registry().write().unwrap_or_else(PoisonError::into_inner).register(name, constructor);
+}
+
+/// Build a transform from its textual spec
+///
+/// Built-in specs: `identity`, `summarizer:<max_length>`, `json_extractor:<field>`,
+/// `multi_file_writer[:<dir>]`;
+/// transforms added with [`register`] resolve the same way.
+pub fn from_spec(spec: &str) -> Result<Arc<dyn Transform>> {
+    registry().read().unwrap_or_else(PoisonError::into_inner).build(spec)
 }
 
 #[cfg(test)]
@@ -280,6 +521,76 @@ mod tests {
         assert_eq!(result.content, "Short");
     }
 
+    #[test]
+    fn test_from_spec() {
+        assert_eq!(from_spec("identity").unwrap().name(), "identity");
+        assert_eq!(from_spec("summarizer:100").unwrap().name(), "summarizer");
+        assert_eq!(from_spec("json_extractor: data").unwrap().name(), "json_extractor");
+        assert!(from_spec("summarizer:abc").is_err());
+        assert!(from_spec("json_extractor:").is_err());
+        assert!(from_spec("unknown").is_err());
+    }
+
+    #[test]
+    fn test_spec_round_trips() {
+        for spec in ["identity", "summarizer:100", "json_extractor:data", "multi_file_writer", "multi_file_writer:app"] {
+            assert_eq!(from_spec(spec).unwrap().spec(), spec);
+        }
+    }
+
+    #[test]
+    fn test_registry_custom_transform() {
+        struct Upper;
+        #[async_trait]
+        impl Transform for Upper {
+            async fn transform(&self, mut response: Response) -> Result<Response> {
+                response.content = response.content.to_uppercase();
+                Ok(response)
+            }
+            fn name(&self) -> &str {
+                "upper"
+            }
+        }
+
+        let mut registry = TransformRegistry::builtin();
+        assert!(registry.build("upper").is_err());
+        registry.register("upper", |_| Ok(Arc::new(Upper)));
+        assert_eq!(registry.build("upper").unwrap().spec(), "upper");
+        assert_eq!(registry.names(), ["identity", "json_extractor", "multi_file_writer", "summarizer", "upper"]);
+        let err = TransformRegistry::new().build("identity").err().unwrap().to_string();
+        assert!(err.contains("Unknown transform spec: 'identity'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_file_sections() {
+        let text = "Here is the project.\n\n### FILE: `src/main.rs`\n```rust\nfn main() {}\n```\n\n**FILE: README.md**\n\n````markdown\n# Demo\n\n```sh\ncargo run\n```\n````\nFILE: notes.txt\nno fence here\nfile: src/main.rs\n~~~\nfn main() { run() }\n~~~\n";
+        let sections = parse_file_sections(text);
+        assert_eq!(sections, vec![
+            FileSection { path: PathBuf::from("README.md"), content: "# Demo\n\n```sh\ncargo run\n```\n".to_string() },
+            FileSection { path: PathBuf::from("src/main.rs"), content: "fn main() { run() }\n".to_string() },
+        ]);
+        assert!(parse_file_sections("FILE:\n```\nx\n```").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_multi_file_writer_checks_every_path_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = FileWriter::direct(dir.path());
+        let transform = MultiFileWriterTransform::under("app");
+        let response = Response::new("FILE: a.txt\n```\na\n```\nFILE: ../../b.txt\n```\nb\n```\n");
+        assert!(transform.transform_with_writer(response, &writer).await.is_err());
+        assert!(!dir.path().join("app/a.txt").exists());
+
+        let response = Response::new("FILE: a.txt\n```\na\n```\nFILE: src/b.txt\n```\nb\n```\n");
+        let result = transform.transform_with_writer(response.clone(), &writer).await.unwrap();
+        assert_eq!(result.content, response.content);
+        assert_eq!(result.metadata["files_written"], "app/a.txt, app/src/b.txt");
+        assert_eq!(std::fs::read_to_string(dir.path().join("app/src/b.txt")).unwrap(), "b\n");
+
+        let err = transform.transform_with_writer(Response::new("no files"), &writer).await.unwrap_err();
+        assert!(err.to_string().contains("FILE: path"), "{}", err);
+    }
+
     #[tokio::test]
     async fn test_json_extractor_config() {
         let config = JsonExtractorConfig::new("field")
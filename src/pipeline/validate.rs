@@ -0,0 +1,189 @@
+//! Checking a pipeline against an executor before running it
+//!
+//! Unlike [`PipelineParser::validate_providers`](super::PipelineParser::validate_providers),
+//! which stops at the first unknown provider, [`PipelineExecutor::validate`] reports
+//! every problem it finds:
+//!
+//! ```text
+//! Pipeline has 2 problems:
+//!   step 1: no credentials for gemini
+//!   step 2.1: claude: Unknown model 'claude-9'. Did you mean: claude-3-5-haiku-latest
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{PipelineExecutor, PipelineStep, transform};
+use crate::providers::{ModelInfo, check_model};
+
+/// What a validation problem is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// No provider is registered under the name
+    UnknownProvider,
+    /// The executor's auth manager finds no credentials
+    MissingAuth,
+    /// The provider does not list the requested model
+    UnknownModel,
+    /// The prompt and requested output cannot fit in the context window
+    TokenBudget,
+    /// The transform's spec does not resolve through the transform registry
+    UnknownTransform,
+}
+
+/// One problem with one step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Step number, `2.1` for the first step inside step 2
+    pub step: String,
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+/// Every problem found in a pipeline
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub problems: Vec<Problem>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Problems of one kind
+    pub fn of_kind(&self, kind: ProblemKind) -> impl Iterator<Item = &Problem> {
+        self.problems.iter().filter(move |problem| problem.kind == kind)
+    }
+
+    /// `Ok` when nothing was found, otherwise the report as the error
+    pub fn into_result(self) -> anyhow::Result<()> {
+        if self.is_ok() { Ok(()) } else { Err(self.into()) }
+    }
+
+    fn push(&mut self, step: &str, kind: ProblemKind, message: impl Into<String>) {
+        self.problems.push(Problem { step: step.to_string(), kind, message: message.into() });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problems.len() {
+            0 => return write!(f, "Pipeline has no problems"),
+            1 => write!(f, "Pipeline has 1 problem:")?,
+            n => write!(f, "Pipeline has {} problems:", n)?,
+        }
+        for problem in &self.problems {
+            write!(f, "\n  step {}: {}", problem.step, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+impl PipelineExecutor {
+    /// Check `steps` can run on this executor, collecting every problem
+    ///
+    /// Checks provider registration, credentials (when an auth manager is set),
+    /// that requested models are listed by providers that can list them, that the
+    /// step's own prompt plus `max_tokens` fits the context window, and that
+    /// transforms resolve by name so the pipeline can be stored.
+    pub async fn validate(&self, steps: &[PipelineStep]) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let mut models = HashMap::new();
+        self.validate_steps(steps, "", &mut models, &mut report).await;
+        report
+    }
+
+    async fn validate_steps(
+        &self,
+        steps: &[PipelineStep],
+        prefix: &str,
+        models: &mut HashMap<String, Option<Vec<ModelInfo>>>,
+        report: &mut ValidationReport,
+    ) {
+        for (index, step) in steps.iter().enumerate() {
+            let label = format!("{}{}", prefix, index + 1);
+            if let Some(map) = step.map_step() {
+                Box::pin(self.validate_steps(&map.steps, &format!("{}.", label), models, report)).await;
+                continue;
+            }
+            if let Some(best_of) = step.best_of_step() {
+                Box::pin(self.validate_steps(&best_of.candidates, &format!("{}.", label), models, report)).await;
+                self.validate_provider(&best_of.judge, &format!("{} (judge)", label), report).await;
+                continue;
+            }
+            for provider in std::iter::once(&step.provider).chain(step.fallbacks()) {
+                self.validate_provider(provider, &label, report).await;
+            }
+            let Some(provider) = self.get_provider(&step.provider) else { continue };
+
+            let listed = match models.get(&step.provider) {
+                Some(listed) => listed.clone(),
+                None if step.model().is_some() => {
+                    let listed = provider.list_models().await.ok();
+                    models.insert(step.provider.clone(), listed.clone());
+                    listed
+                }
+                None => None,
+            };
+            let model = step.model().or(provider.model());
+            let info = listed.as_deref().zip(model).and_then(|(listed, model)| listed.iter().find(|m| m.id == model));
+            if let (Some(listed), Some(requested)) = (&listed, step.model())
+                && let Err(e) = check_model(listed, requested)
+            {
+                report.push(&label, ProblemKind::UnknownModel, format!("{}: {}", step.provider, e));
+            }
+
+            let options = self.options.merged(step.options());
+            let tokenizer = provider.tokenizer();
+            let prompt_tokens = [Some(step.action.as_str()), step.get_context().as_deref(), options.system.as_deref()]
+                .into_iter()
+                .flatten()
+                .map(|text| tokenizer.count(text))
+                .sum::<usize>();
+            let output_tokens = options.max_tokens.unwrap_or(0) as usize;
+            if let Some(limit) = info.and_then(|m| m.max_output_tokens)
+                && output_tokens > limit
+            {
+                report.push(&label, ProblemKind::TokenBudget, format!(
+                    "max_tokens {} exceeds the {}-token output limit of {}",
+                    output_tokens, limit, info.map_or(step.provider.as_str(), |m| m.id.as_str())
+                ));
+            }
+            let window = info.and_then(|m| m.context_window).or_else(|| self.capabilities(&step.provider).map(|c| c.max_tokens));
+            if let Some(window) = window
+                && prompt_tokens + output_tokens > window
+            {
+                report.push(&label, ProblemKind::TokenBudget, format!(
+                    "prompt (~{} tokens) plus max_tokens {} exceeds the {}-token context window of {}",
+                    prompt_tokens, output_tokens, window, step.provider
+                ));
+            }
+
+            if let Some(transform) = step.get_transform()
+                && let Err(e) = transform::from_spec(&transform.spec())
+            {
+                report.push(&label, ProblemKind::UnknownTransform, e.to_string());
+            }
+        }
+    }
+
+    async fn validate_provider(&self, provider: &str, label: &str, report: &mut ValidationReport) {
+        if self.get_provider(provider).is_none() {
+            let mut names = self.get_provider_names();
+            names.sort();
+            let hint = crate::providers::id::closest(provider, names.iter().map(String::as_str))
+                .map(|name| format!(" Did you mean '{}'?", name))
+                .unwrap_or_default();
+            report.push(label, ProblemKind::UnknownProvider, format!("unknown provider '{}'.{}", provider, hint));
+            return;
+        }
+        if let Some(auth) = &self.auth_manager
+            && auth.detect_auth(provider).await.is_err()
+        {
+            report.push(label, ProblemKind::MissingAuth, format!("no credentials for {}", provider));
+        }
+    }
+}
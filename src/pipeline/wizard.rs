@@ -0,0 +1,161 @@
+use anyhow::{Result, anyhow};
+use std::io::{BufRead, Write};
+
+use super::definition::{PipelineDefinition, StepDefinition, validate_name};
+use super::transform;
+use crate::providers::{KNOWN_PROVIDERS, ProviderId};
+
+/// Interactive pipeline composer that builds a definition step by step
+pub struct PipelineWizard<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> PipelineWizard<R, W> {
+    /// Create a wizard reading answers from `input` and prompting on `output`
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Walk the user through building a pipeline
+    ///
+    /// Returns `Ok(None)` when the user declines to save the result.
+    pub fn run(&mut self) -> Result<Option<PipelineDefinition>> {
+        writeln!(self.output, "Compose a new pipeline. Press Enter to accept [defaults].")?;
+
+        let name = self.ask_until("Pipeline name", None, validate_name)?;
+        let mut definition = PipelineDefinition::new(name);
+
+        let description = self.ask("Description (optional)", None)?;
+        if !description.is_empty() {
+            definition.description = Some(description);
+        }
+
+        loop {
+            let index = definition.steps.len() + 1;
+            writeln!(self.output, "\nStep {}", index)?;
+            definition.steps.push(self.ask_step()?);
+
+            if !self.confirm("Add another step?", false)? {
+                break;
+            }
+        }
+
+        writeln!(self.output, "\nChain:\n  {}", definition.to_chain())?;
+        writeln!(self.output, "\nYAML:\n{}", definition.to_yaml()?)?;
+
+        if let Err(e) = definition.validate() {
+            writeln!(self.output, "Pipeline is invalid: {}", e)?;
+            return Err(e);
+        }
+
+        if self.confirm("Save this pipeline?", true)? {
+            Ok(Some(definition))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn ask_step(&mut self) -> Result<StepDefinition> {
+        let provider_prompt = format!("Provider ({})", KNOWN_PROVIDERS.join("/"));
+        let provider = self.ask_until(&provider_prompt, Some(KNOWN_PROVIDERS[0]), |answer| {
+            answer.parse::<ProviderId>().map(|_| ()).map_err(Into::into)
+        })?;
+        let provider = ProviderId::canonical(&provider).to_string();
+
+        let action = self.ask_until("Action", None, |answer| {
+            if answer.contains("->") {
+                Err(anyhow!("action cannot contain '->'"))
+            } else {
+                Ok(())
+            }
+        })?;
+
+        let mut step = StepDefinition::new(provider, action);
+
+        let context = self.ask("Extra context for this step (optional)", None)?;
+        if !context.is_empty() {
+            step.context = Some(context);
+        }
+
+        let transform = self.ask_optional_until(
+            "Transform (identity, summarizer:<n>, json_extractor:<field>; optional)",
+            |answer| transform::from_spec(answer).map(|_| ()),
+        )?;
+        step.transform = transform;
+
+        Ok(step)
+    }
+
+    /// Ask a question, returning the trimmed answer or the default
+    fn ask(&mut self, question: &str, default: Option<&str>) -> Result<String> {
+        match default {
+            Some(default) => write!(self.output, "{} [{}]: ", question, default)?,
+            None => write!(self.output, "{}: ", question)?,
+        }
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Input ended before the pipeline was complete"));
+        }
+
+        let answer = line.trim();
+        if answer.is_empty() {
+            Ok(default.unwrap_or_default().to_string())
+        } else {
+            Ok(answer.to_string())
+        }
+    }
+
+    /// Ask until the answer is non-empty and passes validation
+    fn ask_until(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        validate: impl Fn(&str) -> Result<()>,
+    ) -> Result<String> {
+        loop {
+            let answer = self.ask(question, default)?;
+            if answer.is_empty() {
+                writeln!(self.output, "  A value is required.")?;
+                continue;
+            }
+            match validate(&answer) {
+                Ok(()) => return Ok(answer),
+                Err(e) => writeln!(self.output, "  {}", e)?,
+            }
+        }
+    }
+
+    /// Ask until the answer is empty or passes validation
+    fn ask_optional_until(
+        &mut self,
+        question: &str,
+        validate: impl Fn(&str) -> Result<()>,
+    ) -> Result<Option<String>> {
+        loop {
+            let answer = self.ask(question, None)?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match validate(&answer) {
+                Ok(()) => return Ok(Some(answer)),
+                Err(e) => writeln!(self.output, "  {}", e)?,
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{} ({})", question, hint), None)?;
+            match answer.to_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(self.output, "  Please answer y or n.")?,
+            }
+        }
+    }
+}
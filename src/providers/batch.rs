@@ -0,0 +1,92 @@
+//! Provider batch APIs for large offline jobs
+//!
+//! Anthropic's Message Batches and OpenAI's Batch API take many requests at
+//! once, run them within 24 hours and bill them at half price. A
+//! [`BatchApi`] submits requests, reports progress and fetches the results,
+//! keyed by the `custom_id` each request was submitted with.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{Context, ProviderOptions};
+
+/// One prompt of a batch
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    /// Identifier the result is reported under; unique within the batch
+    pub custom_id: String,
+    pub prompt: String,
+    pub context: Context,
+    pub options: ProviderOptions,
+}
+
+/// Where a batch is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    /// Queued, validating or running
+    InProgress,
+    /// Finished; results can be fetched
+    Ended,
+    /// Rejected as a whole, e.g. a malformed input file
+    Failed,
+    /// Not finished within the completion window
+    Expired,
+    Canceled,
+}
+
+impl BatchState {
+    /// Whether the batch will make no more progress
+    pub fn is_done(self) -> bool {
+        self != BatchState::InProgress
+    }
+}
+
+impl fmt::Display for BatchState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BatchState::InProgress => "in progress",
+            BatchState::Ended => "ended",
+            BatchState::Failed => "failed",
+            BatchState::Expired => "expired",
+            BatchState::Canceled => "canceled",
+        })
+    }
+}
+
+/// Progress of a submitted batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchStatus {
+    pub id: String,
+    pub state: BatchState,
+    /// Requests in the batch
+    pub total: usize,
+    pub succeeded: usize,
+    /// Requests that errored, expired or were canceled
+    pub failed: usize,
+}
+
+/// Result of one request of an ended batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchOutput {
+    pub custom_id: String,
+    /// The reply's text when the request succeeded, otherwise the error
+    pub result: std::result::Result<String, String>,
+}
+
+/// A provider's batch endpoint
+#[async_trait]
+pub trait BatchApi: Send + Sync {
+    /// Provider name, e.g. `claude` or `openai`
+    fn name(&self) -> &str;
+
+    /// Submit `requests` as one batch, returning the provider's batch id
+    async fn submit(&self, requests: &[BatchRequest]) -> Result<String>;
+
+    async fn status(&self, id: &str) -> Result<BatchStatus>;
+
+    /// Results of an ended batch, in no particular order
+    async fn results(&self, id: &str) -> Result<Vec<BatchOutput>>;
+}
@@ -1,23 +1,53 @@
-use super::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use super::{AIProvider, AuthValidation, Capabilities, ContentPart, Context, Message, ModelInfo, ProviderOptions, Response, compose_request, ResponseStream};
+use super::batch::{BatchApi, BatchOutput, BatchRequest, BatchState, BatchStatus};
+use super::http;
+use super::streaming::{JsonAccumulator, ReconnectPolicy, response_bytes, sse_events};
+use super::pricing::Usage;
+use super::tools::{MAX_TOOL_ROUNDS, ToolCall, ToolHandler, ToolSpec};
+use crate::auth::{AuthMethod, ManagedCredentials, TokenRefresher};
 use async_trait::async_trait;
 use anyhow::{Result, anyhow, Context as AnyhowContext};
-use futures::stream;
+use futures::StreamExt;
 use std::path::PathBuf;
-use serde::{Deserialize, Serialize};
-use reqwest::Client;
+use std::sync::Arc;
+use serde::Deserialize;
 
 /// Claude AI provider implementation
 pub struct ClaudeProvider {
-    api_key: Option<String>,
+    credentials: Option<ManagedCredentials>,
     is_cli_session: bool,
+    model: String,
+    base_url: String,
+    options: ProviderOptions,
+    prompt_caching: bool,
+    transport: Arc<dyn http::Transport>,
 }
 
+/// Used when neither config nor the caller sets `max_tokens`
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Tool forced for structured output; its input is the reply
+const JSON_TOOL: &str = "respond";
+
+/// Anthropic API base URL used unless a profile overrides it
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
 impl ClaudeProvider {
     /// Create a new Claude provider with an API key
     pub fn new(api_key: String) -> Self {
-        Self { 
-            api_key: Some(api_key),
+        Self::from_credentials(ManagedCredentials::new("claude", AuthMethod::ApiKey { key: api_key }, None))
+    }
+
+    /// Create a Claude provider from an API key or session token that may be refreshed
+    pub fn from_credentials(credentials: ManagedCredentials) -> Self {
+        Self {
+            credentials: Some(credentials),
             is_cli_session: false,
+            model: Self::default_model(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            options: ProviderOptions::default(),
+            prompt_caching: false,
+            transport: http::default_transport(),
         }
     }
 
@@ -29,8 +59,13 @@ impl ClaudeProvider {
         if config_path.exists() {
             // TODO: Parse actual Claude CLI config when format is known
             Ok(Self {
-                api_key: None,
+                credentials: None,
                 is_cli_session: true,
+                model: Self::default_model(),
+                base_url: DEFAULT_BASE_URL.to_string(),
+                options: ProviderOptions::default(),
+                prompt_caching: false,
+                transport: http::default_transport(),
             })
         } else {
             Err(anyhow!("No Claude CLI session found"))
@@ -39,7 +74,105 @@ impl ClaudeProvider {
 
     /// Create a provider assuming a detected CLI/session exists
     pub fn from_detected_cli_session() -> Self {
-        Self { api_key: None, is_cli_session: true }
+        Self {
+            credentials: None,
+            is_cli_session: true,
+            model: Self::default_model(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            options: ProviderOptions::default(),
+            prompt_caching: false,
+            transport: http::default_transport(),
+        }
+    }
+
+    /// Renew the API key or session token through a hook when it expires or is rejected
+    pub fn with_token_refresher(mut self, refresher: Arc<dyn TokenRefresher>) -> Self {
+        self.credentials = self.credentials.take().map(|c| c.with_refresher(refresher));
+        self
+    }
+
+    /// Use a specific model instead of the default
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Send requests to a different API base URL (e.g. a gateway)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Execute HTTP requests through `transport`, e.g. a fake in tests
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Default generation parameters, overridden per call by `execute_with_options`
+    pub fn with_options(mut self, options: ProviderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Mark the system prompt and attached files as cacheable prefixes (`cache_control`)
+    ///
+    /// Steps that share them are then billed and served from Anthropic's prompt cache.
+    pub fn with_prompt_caching(mut self, enabled: bool) -> Self {
+        self.prompt_caching = enabled;
+        self
+    }
+
+    /// Messages API request body with the effective generation parameters
+    ///
+    /// The system prompt goes in the top-level `system` field; context files join the user turn.
+    fn request_body(&self, prompt: &str, context: &Context, options: &ProviderOptions, stream: bool) -> serde_json::Value {
+        let options = self.options.merged(options);
+        let (system, user) = compose_request(prompt, context, &options);
+        let mut turns: Vec<serde_json::Value> = context.request_turns(user)
+            .iter()
+            .map(|m| serde_json::json!({ "role": m.role, "content": message_content(m) }))
+            .collect();
+        if self.prompt_caching
+            && let Some(files) = context.render_files()
+            && let Some(last) = turns.last_mut()
+        {
+            last["content"] = cache_files(last["content"].take(), &files);
+        }
+        let mut body = serde_json::json!({
+            "model": options.model.as_deref().unwrap_or(&self.model),
+            "max_tokens": options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": turns,
+        });
+        if stream { body["stream"] = true.into(); }
+        if let Some(t) = options.temperature { body["temperature"] = t.into(); }
+        if let Some(p) = options.top_p { body["top_p"] = p.into(); }
+        match system {
+            Some(system) if self.prompt_caching => {
+                body["system"] = serde_json::json!([{ "type": "text", "text": system, "cache_control": cache_control() }]);
+            }
+            Some(system) => body["system"] = system.into(),
+            None => {}
+        }
+        if !options.stop.is_empty() { body["stop_sequences"] = options.stop.into(); }
+        // Structured output: force one tool whose input is the reply; input schemas must be objects
+        if !stream
+            && let Some(json) = &options.json
+            && json.schema["type"] == "object"
+        {
+            body["tools"] = serde_json::json!([{
+                "name": JSON_TOOL,
+                "description": "Reply with the result as this tool's input.",
+                "input_schema": json.schema,
+            }]);
+            body["tool_choice"] = serde_json::json!({ "type": "tool", "name": JSON_TOOL });
+        }
+        body
+    }
+
+    /// Model used when ANTHROPIC_MODEL is not set
+    fn default_model() -> String {
+        std::env::var("ANTHROPIC_MODEL").unwrap_or_else(|_| "claude-3-5-sonnet-20240620".to_string())
     }
 
     /// Get the path to Claude CLI configuration
@@ -51,71 +184,303 @@ impl ClaudeProvider {
 
     /// Check if provider is authenticated
     pub fn is_authenticated(&self) -> bool {
-        self.api_key.is_some() || self.is_cli_session
+        self.credentials.is_some() || self.is_cli_session
     }
 
-    async fn execute_via_api(&self, prompt: &str) -> Result<String> {
-        let key = self.api_key.clone().ok_or_else(|| anyhow!("No API key set"))?;
-
-        // Short-circuit for test/dummy keys to avoid network in tests
-        let lower = key.to_lowercase();
-        if key == "test_key" || lower.starts_with("test_") || lower.starts_with("dummy_") || lower.contains("example") {
-            return Ok(format!("Claude response to: {}", prompt));
+    /// Current API key or session token, refreshed first if it is about to expire
+    async fn credential(&self) -> Result<Option<AuthMethod>> {
+        match &self.credentials {
+            Some(credentials) => Ok(Some(credentials.current().await?).filter(|method| method.secret().is_some())),
+            None => Ok(None),
         }
+    }
 
-        let client = Client::new();
-        let url = "https://api.anthropic.com/v1/messages";
-        let model = std::env::var("ANTHROPIC_MODEL").unwrap_or_else(|_| "claude-3-5-sonnet-20240620".to_string());
+    /// POST a Messages API request body, failing on a non-success status
+    async fn post_messages(&self, auth: &AuthMethod, body: &serde_json::Value) -> Result<reqwest::Response> {
+        self.send(auth, http::shared_client().post(format!("{}/v1/messages", self.base_url)).json(body)).await
+    }
+
+    /// Send an authenticated Anthropic API request, failing on error statuses
+    async fn send(&self, auth: &AuthMethod, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = auth
+            .authorize(request, "x-api-key")
+            .header("anthropic-version", "2023-06-01");
+        let resp = http::send_via(self.transport.as_ref(), "claude", request)
+            .await
+            .with_context(|| "Failed to send request to Anthropic API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("claude", "Anthropic API error", resp).await);
+        }
+        Ok(resp)
+    }
 
-        #[derive(Serialize)]
-        struct Msg { role: String, content: String }
+    async fn execute_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("No API key set"))?;
+        let body = self.request_body(prompt, context, options, false);
 
-        #[derive(Serialize)]
-        struct ReqBody { model: String, max_tokens: u32, messages: Vec<Msg> }
+        #[derive(Deserialize)]
+        struct ContentPart {
+            #[serde(rename = "type", default)]
+            kind: String,
+            #[serde(default)]
+            text: Option<String>,
+            #[serde(default)]
+            name: Option<String>,
+            #[serde(default)]
+            input: Option<serde_json::Value>,
+        }
+        #[derive(Deserialize)]
+        struct RespUsage {
+            input_tokens: u64,
+            output_tokens: u64,
+            #[serde(default)]
+            cache_creation_input_tokens: u64,
+            #[serde(default)]
+            cache_read_input_tokens: u64,
+        }
+        #[derive(Deserialize)]
+        struct RespBody {
+            #[serde(default)]
+            content: Vec<ContentPart>,
+            #[serde(default)]
+            model: Option<String>,
+            #[serde(default)]
+            usage: Option<RespUsage>,
+        }
 
-        let body = ReqBody {
-            model,
-            max_tokens: 1024,
-            messages: vec![Msg { role: "user".to_string(), content: prompt.to_string() }],
+        let resp = self.post_messages(&auth, &body).await?;
+        let parsed: RespBody = resp.json().await.with_context(|| "Failed to parse Anthropic response")?;
+        let forced = parsed.content.iter().find(|p| p.kind == "tool_use" && p.name.as_deref() == Some(JSON_TOOL));
+        let text = match forced.and_then(|p| p.input.as_ref()) {
+            Some(input) => input.to_string(),
+            None => parsed
+                .content
+                .into_iter()
+                .filter_map(|p| p.text)
+                .collect::<Vec<_>>()
+                .join(""),
         };
+        let mut response = Response::new(if text.is_empty() { "(empty response)".to_string() } else { text });
+        if let Some(model) = parsed.model {
+            response = response.with_metadata("model", model);
+        }
+        if let Some(usage) = parsed.usage {
+            // Prompt caching reports cached tokens apart from `input_tokens`
+            if usage.cache_creation_input_tokens > 0 {
+                response = response.with_metadata("cache_write_tokens", usage.cache_creation_input_tokens.to_string());
+            }
+            if usage.cache_read_input_tokens > 0 {
+                response = response.with_metadata("cache_read_tokens", usage.cache_read_input_tokens.to_string());
+            }
+            response = response.with_usage(Usage::new(usage.input_tokens, usage.output_tokens));
+        }
+        Ok(response)
+    }
+
+    /// Messages API loop: run each `tool_use` block through `handler` and send the
+    /// results back until the model stops asking for tools
+    async fn execute_tools_via_api(
+        &self,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("No API key set"))?;
+        let mut body = self.request_body(prompt, context, options, false);
+        body["tools"] = tools
+            .iter()
+            .map(|t| serde_json::json!({ "name": t.name, "description": t.description, "input_schema": t.input_schema }))
+            .collect::<Vec<_>>()
+            .into();
+        // The model must stay free to pick tools; JSON replies are only validated afterwards
+        if let Some(body) = body.as_object_mut() {
+            body.remove("tool_choice");
+        }
 
         #[derive(Deserialize)]
-        struct ContentPart { #[serde(default)] r#type: Option<String>, #[serde(default)] text: Option<String> }
+        struct Block {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            text: Option<String>,
+            #[serde(default)]
+            id: Option<String>,
+            #[serde(default)]
+            name: Option<String>,
+            #[serde(default)]
+            input: Option<serde_json::Value>,
+        }
+        #[derive(Deserialize)]
+        struct RespUsage { input_tokens: u64, output_tokens: u64 }
         #[derive(Deserialize)]
-        struct RespBody { #[serde(default)] content: Vec<ContentPart> }
+        struct RespBody {
+            #[serde(default)]
+            content: Vec<serde_json::Value>,
+            #[serde(default)]
+            stop_reason: Option<String>,
+            #[serde(default)]
+            model: Option<String>,
+            #[serde(default)]
+            usage: Option<RespUsage>,
+        }
 
-        let resp = client
-            .post(url)
-            .header("x-api-key", key)
-            .header("anthropic-version", "2023-06-01")
-            .json(&body)
-            .send()
-            .await
-            .with_context(|| "Failed to send request to Anthropic API")?;
+        let mut usage = Usage::new(0, 0);
+        let mut tool_calls = 0;
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let resp = self.post_messages(&auth, &body).await?;
+            let parsed: RespBody = resp.json().await.with_context(|| "Failed to parse Anthropic response")?;
+            if let Some(u) = &parsed.usage {
+                usage = Usage::new(usage.prompt_tokens + u.input_tokens, usage.completion_tokens + u.output_tokens);
+            }
+            let blocks: Vec<Block> = parsed.content.iter().filter_map(|b| serde_json::from_value(b.clone()).ok()).collect();
+            let calls: Vec<ToolCall> = blocks
+                .iter()
+                .filter(|b| b.kind == "tool_use")
+                .map(|b| ToolCall {
+                    id: b.id.clone().unwrap_or_default(),
+                    name: b.name.clone().unwrap_or_default(),
+                    input: b.input.clone().unwrap_or_default(),
+                })
+                .collect();
 
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(anyhow!("Anthropic API error: {} - {}", status, text));
+            if parsed.stop_reason.as_deref() != Some("tool_use") || calls.is_empty() {
+                let text: String = blocks.into_iter().filter_map(|b| b.text).collect();
+                let mut response = Response::new(if text.is_empty() { "(empty response)".to_string() } else { text })
+                    .with_usage(usage)
+                    .with_metadata("tool_calls", tool_calls.to_string());
+                if let Some(model) = parsed.model {
+                    response = response.with_metadata("model", model);
+                }
+                return Ok(response);
+            }
+
+            let mut results = Vec::with_capacity(calls.len());
+            for call in &calls {
+                tool_calls += 1;
+                tracing::debug!(tool = %call.name, id = %call.id, "running tool call");
+                let (content, is_error) = match handler.call(call).await {
+                    Ok(output) => (output, false),
+                    Err(e) => (format!("{:#}", e), true),
+                };
+                results.push(serde_json::json!({
+                    "type": "tool_result",
+                    "tool_use_id": call.id,
+                    "content": content,
+                    "is_error": is_error,
+                }));
+            }
+            if let Some(messages) = body["messages"].as_array_mut() {
+                messages.push(serde_json::json!({ "role": "assistant", "content": parsed.content }));
+                messages.push(serde_json::json!({ "role": "user", "content": results }));
+            }
         }
+        Err(anyhow!("Claude was still calling tools after {} rounds", MAX_TOOL_ROUNDS))
+    }
 
-        let parsed: RespBody = resp.json().await.with_context(|| "Failed to parse Anthropic response")?;
-        let text = parsed
-            .content
-            .into_iter()
-            .filter_map(|p| p.text)
-            .collect::<Vec<_>>()
-            .join("");
-        Ok(if text.is_empty() { "(empty response)".to_string() } else { text })
+    /// Stream text deltas from the Messages API over SSE
+    ///
+    /// Tool-call arguments arrive as partial JSON and are only validated once their
+    /// block closes; a garbled block or an `error` event fails the stream so the
+    /// executor can retry instead of emitting corrupt output.
+    async fn stream_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream<'static>> {
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("No API key set"))?;
+        let client = http::shared_client();
+        let url = format!("{}/v1/messages", self.base_url);
+        let body = self.request_body(prompt, context, options, true);
+
+        // The Messages API cannot resume a stream, so only failures before the first
+        // event are reconnected; later ones surface as errors for the retry loop
+        let transport = self.transport.clone();
+        let connect = move |_last_event_id: Option<String>| {
+            let transport = transport.clone();
+            let request = auth
+                .authorize(client.post(&url), "x-api-key")
+                .header("anthropic-version", "2023-06-01")
+                .json(&body);
+            async move {
+                let resp = http::send_via(transport.as_ref(), "claude", request).await.with_context(|| "Failed to send request to Anthropic API")?;
+                if !resp.status().is_success() {
+                    return Err(http::error_for_status("claude", "Anthropic API error", resp).await);
+                }
+                Ok(response_bytes(resp))
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct Delta {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            text: Option<String>,
+            #[serde(default)]
+            partial_json: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Event {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            index: usize,
+            #[serde(default)]
+            delta: Option<Delta>,
+            #[serde(default)]
+            error: Option<serde_json::Value>,
+        }
+
+        let events = sse_events(connect, ReconnectPolicy::default());
+        let chunks = events
+            .scan((JsonAccumulator::new(), false), |(tool_args, stopped), event| {
+                if *stopped {
+                    return futures::future::ready(None);
+                }
+                let item = event.and_then(|event| {
+                    let parsed: Event = serde_json::from_str(&event.data)
+                        .with_context(|| format!("Garbled Anthropic stream event: {}", event.data))?;
+                    match (parsed.kind.as_str(), parsed.delta) {
+                        ("content_block_delta", Some(delta)) if delta.kind == "text_delta" => {
+                            Ok(delta.text)
+                        }
+                        ("content_block_delta", Some(delta)) if delta.kind == "input_json_delta" => {
+                            tool_args.push(parsed.index, delta.partial_json.as_deref().unwrap_or_default());
+                            Ok(None)
+                        }
+                        ("content_block_stop", _) => {
+                            tool_args.finish(parsed.index)?;
+                            Ok(None)
+                        }
+                        ("message_stop", _) => {
+                            *stopped = true;
+                            Ok(None)
+                        }
+                        ("error", _) => Err(anyhow!(
+                            "Anthropic stream error: {}",
+                            parsed.error.map(|e| e.to_string()).unwrap_or_default()
+                        )),
+                        _ => Ok(None),
+                    }
+                });
+                if item.is_err() {
+                    *stopped = true;
+                }
+                futures::future::ready(Some(item))
+            })
+            .filter_map(|item| futures::future::ready(item.transpose()));
+        Ok(Box::pin(chunks))
     }
 }
 
 #[async_trait]
 impl AIProvider for ClaudeProvider {
     async fn execute(&self, prompt: &str, context: &Context) -> Result<Response> {
-        if let Some(_) = &self.api_key {
-            let response_text = self.execute_via_api(prompt).await?;
-            let mut response = Response::new(response_text);
+        self.execute_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn execute_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
+        if self.credentials.is_some() {
+            let mut response = self.execute_via_api(prompt, context, options).await?;
             if !context.conversation_history.is_empty() {
                 response = response.with_metadata(
                     "conversation_length",
@@ -133,25 +498,30 @@ impl AIProvider for ClaudeProvider {
             ));
         }
 
-        // Fallback (should not reach here normally)
-        let response_text = format!("Claude response to: {}", prompt);
-        
-        let mut response = Response::new(response_text);
-        if !context.conversation_history.is_empty() {
-            response = response.with_metadata(
-                "conversation_length", 
-                context.conversation_history.len().to_string()
-            );
+        Err(anyhow!("Claude provider not authenticated"))
+    }
+
+    async fn execute_with_tools(
+        &self,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        if self.credentials.is_some() {
+            return self.execute_tools_via_api(prompt, context, options, tools, handler).await;
         }
-        
-        Ok(response)
+        Err(anyhow!("Claude tool calling needs API access. Set ANTHROPIC_API_KEY to call the API."))
     }
 
-    async fn stream(&self, prompt: &str, _context: &Context) -> Result<ResponseStream> {
-        // For now, use non-streaming call to produce a single chunk when API key present
-        if self.api_key.is_some() {
-            let text = self.execute_via_api(prompt).await?;
-            return Ok(Box::pin(stream::once(async move { Ok(text) })));
+    async fn stream(&self, prompt: &str, context: &Context) -> Result<ResponseStream> {
+        self.stream_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn stream_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream> {
+        if self.credentials.is_some() {
+            return self.stream_via_api(prompt, context, options).await;
         }
         return Err(anyhow!("Claude provider not authenticated for streaming"));
     }
@@ -161,10 +531,364 @@ impl AIProvider for ClaudeProvider {
             supports_streaming: true,
             supports_context: true,
             max_tokens: 200000, // Claude 3's context window
+            supports_json_mode: true, // via tool forcing
+            supports_vision: true,
         }
     }
 
     fn name(&self) -> &str {
         "claude"
     }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.base_url)
+    }
+
+    async fn probe(&self) -> Result<Capabilities> {
+        let Some(auth) = self.credential().await? else {
+            return Ok(self.capabilities());
+        };
+
+        #[derive(Deserialize)]
+        struct ModelInfo {
+            #[serde(default)]
+            max_input_tokens: Option<usize>,
+            #[serde(default)]
+            context_window: Option<usize>,
+        }
+
+        let url = format!("{}/v1/models/{}", self.base_url, self.model);
+        let request = auth
+            .authorize(http::shared_client().get(&url), "x-api-key")
+            .header("anthropic-version", "2023-06-01");
+        let resp = http::send_via(self.transport.as_ref(), "claude", request)
+            .await
+            .with_context(|| "Failed to probe Anthropic model")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("claude", "Anthropic model probe failed", resp).await);
+        }
+
+        let info: ModelInfo = resp.json().await.with_context(|| "Failed to parse Anthropic model info")?;
+        let mut capabilities = self.capabilities();
+        if let Some(limit) = info.max_input_tokens.or(info.context_window) {
+            capabilities.max_tokens = limit;
+        }
+        Ok(capabilities)
+    }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        match &self.credentials {
+            Some(credentials) if credentials.can_refresh() => {
+                credentials.refresh().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn validate_auth(&self) -> Result<AuthValidation> {
+        if self.credentials.is_none() {
+            return Err(anyhow!("Claude CLI/Desktop session cannot be validated; set ANTHROPIC_API_KEY"));
+        }
+        let models: Vec<String> = self.list_models().await?.into_iter().map(|m| m.id).collect();
+        let model_accessible = Some(models.iter().any(|m| m == &self.model));
+        Ok(AuthValidation { account: None, models, model_accessible })
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let auth = self.credential().await?.ok_or_else(|| {
+            anyhow!("Claude CLI/Desktop session cannot list models; set ANTHROPIC_API_KEY")
+        })?;
+
+        #[derive(Deserialize)]
+        struct ModelEntry {
+            id: String,
+            #[serde(default)]
+            display_name: Option<String>,
+            #[serde(default)]
+            max_input_tokens: Option<usize>,
+            #[serde(default)]
+            max_tokens: Option<usize>,
+        }
+        #[derive(Deserialize)]
+        struct ModelList { #[serde(default)] data: Vec<ModelEntry> }
+
+        let url = format!("{}/v1/models?limit=1000", self.base_url);
+        let request = auth
+            .authorize(http::shared_client().get(&url), "x-api-key")
+            .header("anthropic-version", "2023-06-01");
+        let resp = http::send_via(self.transport.as_ref(), "claude", request)
+            .await
+            .with_context(|| "Failed to reach Anthropic API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("claude", "Anthropic model listing failed", resp).await);
+        }
+
+        let list: ModelList = resp.json().await.with_context(|| "Failed to parse Anthropic model list")?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                display_name: m.display_name,
+                context_window: m.max_input_tokens,
+                max_output_tokens: m.max_tokens,
+            })
+            .collect())
+    }
+}
+
+/// Message Batches API: `params` are the Messages API bodies `execute` would send
+#[async_trait]
+impl BatchApi for ClaudeProvider {
+    fn name(&self) -> &str {
+        "claude"
+    }
+
+    async fn submit(&self, requests: &[BatchRequest]) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Created { id: String }
+
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("Anthropic batches need an API key"))?;
+        let requests: Vec<serde_json::Value> = requests
+            .iter()
+            .map(|r| serde_json::json!({
+                "custom_id": r.custom_id,
+                "params": self.request_body(&r.prompt, &r.context, &r.options, false),
+            }))
+            .collect();
+        let url = format!("{}/v1/messages/batches", self.base_url);
+        let resp = self.send(&auth, http::shared_client().post(url).json(&serde_json::json!({ "requests": requests }))).await?;
+        let created: Created = resp.json().await.with_context(|| "Failed to parse Anthropic batch")?;
+        Ok(created.id)
+    }
+
+    async fn status(&self, id: &str) -> Result<BatchStatus> {
+        let batch = self.batch(id).await?;
+        let counts = batch.request_counts;
+        let state = match batch.processing_status.as_str() {
+            "ended" => BatchState::Ended,
+            // Canceling batches end with whatever finished before the cancel
+            _ => BatchState::InProgress,
+        };
+        Ok(BatchStatus {
+            id: batch.id,
+            state,
+            total: counts.processing + counts.succeeded + counts.errored + counts.canceled + counts.expired,
+            succeeded: counts.succeeded,
+            failed: counts.errored + counts.canceled + counts.expired,
+        })
+    }
+
+    async fn results(&self, id: &str) -> Result<Vec<BatchOutput>> {
+        #[derive(Deserialize)]
+        struct Block { #[serde(default)] text: Option<String> }
+        #[derive(Deserialize)]
+        struct Message { #[serde(default)] content: Vec<Block> }
+        #[derive(Deserialize)]
+        struct Outcome {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            message: Option<Message>,
+            #[serde(default)]
+            error: Option<serde_json::Value>,
+        }
+        #[derive(Deserialize)]
+        struct Line { custom_id: String, result: Outcome }
+
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("Anthropic batches need an API key"))?;
+        let url = self.batch(id).await?.results_url.ok_or_else(|| anyhow!("Batch {} has not ended yet", id))?;
+        let text = self.send(&auth, http::shared_client().get(url)).await?.text().await?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let line: Line = serde_json::from_str(line).with_context(|| "Failed to parse Anthropic batch result")?;
+                let result = match (line.result.kind.as_str(), line.result.message) {
+                    ("succeeded", Some(message)) => Ok(message.content.into_iter().filter_map(|b| b.text).collect()),
+                    (kind, _) => Err(match line.result.error {
+                        Some(error) => error["error"]["message"].as_str().map_or_else(|| error.to_string(), str::to_string),
+                        None => kind.to_string(),
+                    }),
+                };
+                Ok(BatchOutput { custom_id: line.custom_id, result })
+            })
+            .collect()
+    }
+}
+
+/// A Message Batches API batch
+#[derive(Deserialize)]
+struct MessageBatch {
+    id: String,
+    processing_status: String,
+    #[serde(default)]
+    request_counts: RequestCounts,
+    #[serde(default)]
+    results_url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct RequestCounts {
+    #[serde(default)]
+    processing: usize,
+    #[serde(default)]
+    succeeded: usize,
+    #[serde(default)]
+    errored: usize,
+    #[serde(default)]
+    canceled: usize,
+    #[serde(default)]
+    expired: usize,
+}
+
+impl ClaudeProvider {
+    async fn batch(&self, id: &str) -> Result<MessageBatch> {
+        let auth = self.credential().await?.ok_or_else(|| anyhow!("Anthropic batches need an API key"))?;
+        let url = format!("{}/v1/messages/batches/{}", self.base_url, id);
+        let resp = self.send(&auth, http::shared_client().get(url)).await?;
+        resp.json().await.with_context(|| "Failed to parse Anthropic batch")
+    }
+}
+
+/// Messages API content: a plain string, or image blocks followed by the text
+fn message_content(message: &Message) -> serde_json::Value {
+    if message.images.is_empty() {
+        return message.content.clone().into();
+    }
+    message
+        .parts()
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+            ContentPart::Image { image } => serde_json::json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": image.media_type, "data": image.data },
+            }),
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Cache breakpoint; Anthropic keeps the prefix up to it for five minutes after last use
+fn cache_control() -> serde_json::Value {
+    serde_json::json!({ "type": "ephemeral" })
+}
+
+/// Split the user turn's text after the attached files, marking everything up to them cacheable
+fn cache_files(content: serde_json::Value, files: &str) -> serde_json::Value {
+    let blocks = match content {
+        serde_json::Value::String(text) => vec![serde_json::json!({ "type": "text", "text": text })],
+        serde_json::Value::Array(blocks) => blocks,
+        other => return other,
+    };
+    blocks
+        .into_iter()
+        .flat_map(|block| {
+            let split = block["text"].as_str().and_then(|text| {
+                let end = text.find(files)? + files.len();
+                Some((text[..end].to_string(), text[end..].trim_start().to_string()))
+            });
+            match split {
+                Some((cached, rest)) => {
+                    let mut blocks = vec![serde_json::json!({ "type": "text", "text": cached, "cache_control": cache_control() })];
+                    if !rest.is_empty() {
+                        blocks.push(serde_json::json!({ "type": "text", "text": rest }));
+                    }
+                    blocks
+                }
+                None => vec![block],
+            }
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::MessageRole;
+
+    #[test]
+    fn test_request_body_layers_options() {
+        let provider = ClaudeProvider::new("test_key".to_string())
+            .with_model("claude-test")
+            .with_options(ProviderOptions { temperature: Some(0.5), max_tokens: Some(4096), ..ProviderOptions::default() });
+
+        let body = provider.request_body("hi", &Context::new(), &ProviderOptions::default(), false);
+        assert_eq!(body["max_tokens"], 4096);
+        assert_eq!(body["temperature"], 0.5);
+        assert!(body.get("system").is_none());
+        assert!(body.get("stream").is_none());
+
+        let call = ProviderOptions { max_tokens: Some(10), system: Some("Be terse.".into()), stop: vec!["END".into()], ..ProviderOptions::default() };
+        let body = provider.request_body("hi", &Context::new(), &call, true);
+        assert_eq!(body["max_tokens"], 10);
+        assert_eq!(body["system"], "Be terse.");
+        assert_eq!(body["stop_sequences"][0], "END");
+        assert_eq!(body["stream"], true);
+
+        let body = ClaudeProvider::new("test_key".to_string()).request_body("hi", &Context::new(), &ProviderOptions::default(), false);
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_request_body_separates_system_prompt() {
+        let provider = ClaudeProvider::new("test_key".to_string());
+        let mut context = Context::new();
+        context.add_file_with_content("notes.md".into(), "remember".to_string());
+        let call = ProviderOptions { system: Some("Be terse.".into()), ..ProviderOptions::default() };
+
+        let body = provider.request_body("summarize", &context, &call, false);
+        assert_eq!(body["system"], "Be terse.");
+        let user = body["messages"][0]["content"].as_str().unwrap();
+        assert!(user.starts_with("<file path=\"notes.md\">\nremember\n</file>"));
+        assert!(user.ends_with("summarize"));
+        assert!(!user.contains("Be terse."));
+    }
+
+    #[test]
+    fn test_request_body_replays_conversation() {
+        let provider = ClaudeProvider::new("test_key".to_string());
+        let mut context = Context::new();
+        context.add_message(Message::new(MessageRole::User, "What is 2 + 2?"));
+        context.add_message(Message::new(MessageRole::Assistant, "4"));
+
+        let body = provider.request_body("And doubled?", &context, &ProviderOptions::default(), false);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"], "4");
+        assert_eq!(messages[2]["content"], "And doubled?");
+    }
+
+    #[test]
+    fn test_request_body_marks_cacheable_prefix() {
+        let mut context = Context::new();
+        context.add_file_with_content("notes.md".into(), "remember".to_string());
+        let call = ProviderOptions { system: Some("Be terse.".into()), ..ProviderOptions::default() };
+
+        let provider = ClaudeProvider::new("test_key".to_string()).with_prompt_caching(true);
+        let body = provider.request_body("summarize", &context, &call, false);
+        assert_eq!(body["system"][0]["text"], "Be terse.");
+        assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+        let blocks = body["messages"][0]["content"].as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0]["text"], "<file path=\"notes.md\">\nremember\n</file>");
+        assert_eq!(blocks[0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(blocks[1]["text"], "summarize");
+        assert!(blocks[1].get("cache_control").is_none());
+
+        // Without files only the system prompt is cached
+        let body = provider.request_body("summarize", &Context::new(), &call, false);
+        assert_eq!(body["messages"][0]["content"], "summarize");
+        assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+    }
 }
@@ -1,23 +1,54 @@
-use super::{AIProvider, Capabilities, Context, Response, ResponseStream};
+//! OpenAI-compatible chat completions (`codex`, alias `openai`)
+//!
+//! Talks to `/chat/completions` under the configured base URL, so any
+//! OpenAI-compatible gateway works. Tool calls run through the same
+//! request/response loop as Claude's: the model's `tool_calls` are answered
+//! with `tool` messages until it replies without one.
+
+use super::{AIProvider, AuthValidation, Capabilities, ContentPart, Context, Message, MessageRole, ModelInfo, ProviderOptions, Response, ResponseStream, compose_request};
+use super::http;
+use super::idempotency::IDEMPOTENCY_HEADER;
+use super::openai::DEFAULT_CHAT_MODEL;
+use super::pricing::Usage;
+use super::streaming::{ReconnectPolicy, response_bytes, sse_events};
+use super::tokenizer::{Tokenizer, tokenizer_for};
+use super::tools::{MAX_TOOL_ROUNDS, ToolCall, ToolHandler, ToolSpec};
+use crate::auth::{AuthMethod, ManagedCredentials, TokenRefresher};
 use async_trait::async_trait;
-use anyhow::{Result, anyhow};
-use futures::stream;
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use futures::StreamExt;
+use serde::Deserialize;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+/// OpenAI API base URL used unless a profile overrides it
+pub const API_BASE: &str = "https://api.openai.com/v1";
 
 pub struct CodexProvider {
-    api_key: Option<String>,
+    credentials: Option<ManagedCredentials>,
     is_cli_session: bool,
+    model: String,
+    base_url: String,
+    options: ProviderOptions,
+    transport: Arc<dyn http::Transport>,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
 }
 
 impl CodexProvider {
+    /// Create a new provider with an API key
     pub fn new(api_key: String) -> Self {
-        Self { api_key: Some(api_key), is_cli_session: false }
+        Self::from_credentials(ManagedCredentials::new("codex", AuthMethod::ApiKey { key: api_key }, None))
+    }
+
+    /// Create a provider from an API key or session token that may be refreshed
+    pub fn from_credentials(credentials: ManagedCredentials) -> Self {
+        Self { credentials: Some(credentials), is_cli_session: false, ..Self::from_detected_cli_session() }
     }
 
     pub async fn from_cli_session() -> Result<Self> {
         let config_path = Self::get_config_path()?;
         if config_path.exists() {
-            Ok(Self { api_key: None, is_cli_session: true })
+            Ok(Self::from_detected_cli_session())
         } else {
             Err(anyhow!("No Codex CLI session found"))
         }
@@ -25,7 +56,56 @@ impl CodexProvider {
 
     /// Create a provider assuming a detected CLI/session exists
     pub fn from_detected_cli_session() -> Self {
-        Self { api_key: None, is_cli_session: true }
+        Self {
+            credentials: None,
+            is_cli_session: true,
+            model: Self::default_model(),
+            base_url: API_BASE.to_string(),
+            options: ProviderOptions::default(),
+            transport: http::default_transport(),
+            tokenizer: None,
+        }
+    }
+
+    /// Renew the API key through a hook when it expires or is rejected
+    pub fn with_token_refresher(mut self, refresher: Arc<dyn TokenRefresher>) -> Self {
+        self.credentials = self.credentials.take().map(|c| c.with_refresher(refresher));
+        self
+    }
+
+    /// Use a specific model instead of the default
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Send requests to a different API base URL (e.g. a gateway or local server)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Execute HTTP requests through `transport`, e.g. a fake in tests
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Default generation parameters, overridden per call by `execute_with_options`
+    pub fn with_options(mut self, options: ProviderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Count tokens with a specific encoding, e.g. a loaded `cl100k_base` rank file
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Model used when OPENAI_MODEL is not set
+    fn default_model() -> String {
+        std::env::var("OPENAI_MODEL").unwrap_or_else(|_| DEFAULT_CHAT_MODEL.to_string())
     }
 
     fn get_config_path() -> Result<PathBuf> {
@@ -33,30 +113,379 @@ impl CodexProvider {
         Ok(home.join(".codex").join("config.json"))
     }
 
-    fn is_authenticated(&self) -> bool { self.api_key.is_some() || self.is_cli_session }
+    fn is_authenticated(&self) -> bool { self.credentials.is_some() || self.is_cli_session }
+
+    /// Model a call with `options` goes to: the call's, the provider options', then the configured one
+    fn model_for(&self, options: &ProviderOptions) -> String {
+        self.options.merged(options).model.unwrap_or_else(|| self.model.clone())
+    }
+
+    /// Fail unless requests can actually reach the API
+    fn require_api(&self) -> Result<()> {
+        if self.credentials.is_some() {
+            return Ok(());
+        }
+        if self.is_cli_session {
+            return Err(anyhow!("Codex CLI session detected, but API access is not bridged. Set CODEX_API_KEY to call the API."));
+        }
+        Err(anyhow!("Codex provider not authenticated"))
+    }
+
+    /// Attach the API key or session token as a bearer token
+    async fn authorize(&self, request: reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder> {
+        let auth = match &self.credentials {
+            Some(credentials) => Some(credentials.current().await?).filter(|method| method.secret().is_some()),
+            None => None,
+        };
+        match auth {
+            Some(AuthMethod::ApiKey { key }) => Ok(request.bearer_auth(key)),
+            Some(other) => Ok(other.authorize(request, "authorization")),
+            None => Err(anyhow!("No Codex API key set")),
+        }
+    }
+
+    /// Chat completions request body with the effective generation parameters
+    ///
+    /// The system prompt is the first message; images go in `image_url` parts as data URLs.
+    fn request_body(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> serde_json::Value {
+        let options = self.options.merged(options);
+        let (system, user) = compose_request(prompt, context, &options);
+        let messages: Vec<serde_json::Value> = system
+            .map(|system| serde_json::json!({ "role": "system", "content": system }))
+            .into_iter()
+            .chain(context.request_turns(user).iter().map(|m| {
+                let role = if m.role == MessageRole::Assistant { "assistant" } else { "user" };
+                serde_json::json!({ "role": role, "content": message_content(m) })
+            }))
+            .collect();
+        let mut body = serde_json::json!({ "model": self.model_for(&options), "messages": messages });
+        if let Some(t) = options.temperature { body["temperature"] = t.into(); }
+        if let Some(p) = options.top_p { body["top_p"] = p.into(); }
+        if let Some(m) = options.max_tokens { body["max_tokens"] = m.into(); }
+        if !options.stop.is_empty() { body["stop"] = options.stop.into(); }
+        if let Some(seed) = options.seed { body["seed"] = seed.into(); }
+        if let Some(json) = options.json {
+            body["response_format"] = serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": json.schema },
+            });
+        }
+        body
+    }
+
+    /// POST a chat completions body, failing on error statuses
+    async fn post_completions(&self, body: &serde_json::Value, options: &ProviderOptions) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.base_url);
+        let mut request = http::shared_client().post(&url).json(body);
+        if let Some(key) = self.options.merged(options).idempotency_key {
+            request = request.header(IDEMPOTENCY_HEADER, key);
+        }
+        let request = self.authorize(request).await?;
+        let resp = http::send_via(self.transport.as_ref(), "codex", request)
+            .await
+            .with_context(|| "Failed to send request to OpenAI API")?;
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("codex", "OpenAI API error", resp).await);
+        }
+        Ok(resp)
+    }
+
+    async fn execute_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
+        let body = self.request_body(prompt, context, options);
+        let resp = self.post_completions(&body, options).await?;
+        let parsed: Completion = resp.json().await.with_context(|| "Failed to parse OpenAI response")?;
+        let text = parsed.choices.into_iter().next().and_then(|c| c.message.content).unwrap_or_default();
+        let response = Response::new(if text.is_empty() { "(empty response)".to_string() } else { text })
+            .with_metadata("model", parsed.model.unwrap_or_else(|| self.model_for(options)));
+        Ok(match parsed.usage {
+            Some(usage) => response.with_usage(Usage::new(usage.prompt_tokens, usage.completion_tokens)),
+            None => response,
+        })
+    }
+
+    async fn execute_tools_via_api(
+        &self,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        let mut body = self.request_body(prompt, context, options);
+        body["tools"] = tools
+            .iter()
+            .map(|t| serde_json::json!({
+                "type": "function",
+                "function": { "name": t.name, "description": t.description, "parameters": t.input_schema },
+            }))
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut usage = Usage::new(0, 0);
+        let mut tool_calls = 0;
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let resp = self.post_completions(&body, options).await?;
+            let parsed: Completion = resp.json().await.with_context(|| "Failed to parse OpenAI response")?;
+            if let Some(u) = &parsed.usage {
+                usage = Usage::new(usage.prompt_tokens + u.prompt_tokens, usage.completion_tokens + u.completion_tokens);
+            }
+            let Some(choice) = parsed.choices.into_iter().next() else {
+                return Err(anyhow!("OpenAI response has no choices"));
+            };
+
+            if choice.finish_reason.as_deref() != Some("tool_calls") || choice.message.tool_calls.is_empty() {
+                let text = choice.message.content.unwrap_or_default();
+                let mut response = Response::new(if text.is_empty() { "(empty response)".to_string() } else { text })
+                    .with_usage(usage)
+                    .with_metadata("tool_calls", tool_calls.to_string());
+                if let Some(model) = parsed.model {
+                    response = response.with_metadata("model", model);
+                }
+                return Ok(response);
+            }
+
+            let mut results = Vec::with_capacity(choice.message.tool_calls.len());
+            for requested in &choice.message.tool_calls {
+                tool_calls += 1;
+                // Arguments arrive as a JSON string; unparseable ones are passed through as text
+                let arguments = &requested.function.arguments;
+                let call = ToolCall {
+                    id: requested.id.clone(),
+                    name: requested.function.name.clone(),
+                    input: serde_json::from_str(arguments).unwrap_or_else(|_| arguments.clone().into()),
+                };
+                tracing::debug!(tool = %call.name, id = %call.id, "running tool call");
+                let content = match handler.call(&call).await {
+                    Ok(output) => output,
+                    Err(e) => format!("Error: {:#}", e),
+                };
+                results.push(serde_json::json!({ "role": "tool", "tool_call_id": call.id, "content": content }));
+            }
+            if let Some(messages) = body["messages"].as_array_mut() {
+                let calls: Vec<serde_json::Value> = choice.message.tool_calls
+                    .iter()
+                    .map(|c| serde_json::json!({
+                        "id": c.id,
+                        "type": "function",
+                        "function": { "name": c.function.name, "arguments": c.function.arguments },
+                    }))
+                    .collect();
+                messages.push(serde_json::json!({ "role": "assistant", "content": choice.message.content, "tool_calls": calls }));
+                messages.extend(results);
+            }
+        }
+        Err(anyhow!("OpenAI model was still calling tools after {} rounds", MAX_TOOL_ROUNDS))
+    }
+
+    /// Stream content deltas from chat completions over SSE
+    async fn stream_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream<'static>> {
+        let url = format!("{}/chat/completions", self.base_url);
+        let mut body = self.request_body(prompt, context, options);
+        body["stream"] = true.into();
+        // Authorize once up front; the stream outlives `self`
+        let request = self.authorize(http::shared_client().post(&url).json(&body)).await?;
+
+        // Completions streams cannot be resumed, so only reconnect before the first event
+        let transport = self.transport.clone();
+        let connect = move |_last_event_id: Option<String>| {
+            let request = request.try_clone();
+            let transport = transport.clone();
+            async move {
+                let request = request.ok_or_else(|| anyhow!("OpenAI stream request cannot be retried"))?;
+                let resp = http::send_via(transport.as_ref(), "codex", request).await.with_context(|| "Failed to send request to OpenAI API")?;
+                if !resp.status().is_success() {
+                    return Err(http::error_for_status("codex", "OpenAI API error", resp).await);
+                }
+                Ok(response_bytes(resp))
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct Delta { #[serde(default)] content: Option<String> }
+        #[derive(Deserialize)]
+        struct ChunkChoice { #[serde(default)] delta: Option<Delta> }
+        #[derive(Deserialize)]
+        struct Chunk { #[serde(default)] choices: Vec<ChunkChoice> }
+
+        let chunks = sse_events(connect, ReconnectPolicy::default())
+            .take_while(|event| futures::future::ready(!matches!(event, Ok(event) if event.data.trim() == "[DONE]")))
+            .filter_map(|event| {
+                let item = event.and_then(|event| {
+                    let chunk: Chunk = serde_json::from_str(&event.data)
+                        .with_context(|| format!("Garbled OpenAI stream chunk: {}", event.data))?;
+                    let text: String = chunk
+                        .choices
+                        .into_iter()
+                        .filter_map(|c| c.delta.and_then(|d| d.content))
+                        .collect();
+                    Ok((!text.is_empty()).then_some(text))
+                });
+                futures::future::ready(item.transpose())
+            });
+        Ok(Box::pin(chunks))
+    }
+}
+
+/// A chat completions response
+#[derive(Deserialize)]
+struct Completion {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<RequestedCall>,
+}
+
+#[derive(Deserialize)]
+struct RequestedCall {
+    id: String,
+    function: RequestedFunction,
+}
+
+#[derive(Deserialize)]
+struct RequestedFunction {
+    name: String,
+    /// JSON-encoded arguments
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct CompletionUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+/// Message content: plain text, or text and `image_url` parts when images are attached
+fn message_content(message: &Message) -> serde_json::Value {
+    if message.images.is_empty() {
+        return message.content.clone().into();
+    }
+    message
+        .parts()
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+            ContentPart::Image { image } => serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:{};base64,{}", image.media_type, image.data) },
+            }),
+        })
+        .collect::<Vec<_>>()
+        .into()
 }
 
 #[async_trait]
 impl AIProvider for CodexProvider {
     async fn execute(&self, prompt: &str, context: &Context) -> Result<Response> {
+        self.execute_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn execute_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
         if !self.is_authenticated() { return Err(anyhow!("Codex provider not authenticated")); }
-        let response_text = format!("Codex response to: {}", prompt);
-        let mut response = Response::new(response_text);
+        self.require_api()?;
+        let mut response = self.execute_via_api(prompt, context, options).await?;
         if !context.conversation_history.is_empty() {
             response = response.with_metadata("conversation_length", context.conversation_history.len().to_string());
         }
         Ok(response)
     }
 
-    async fn stream(&self, prompt: &str, _context: &Context) -> Result<ResponseStream> {
-        if !self.is_authenticated() { return Err(anyhow!("Codex provider not authenticated")); }
-        let response = format!("Codex streaming response to: {}", prompt);
-        Ok(Box::pin(stream::once(async move { Ok(response) })))
+    async fn execute_with_tools(
+        &self,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        self.require_api()?;
+        self.execute_tools_via_api(prompt, context, options, tools, handler).await
+    }
+
+    async fn stream(&self, prompt: &str, context: &Context) -> Result<ResponseStream> {
+        self.stream_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn stream_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream> {
+        self.require_api()?;
+        self.stream_via_api(prompt, context, options).await
     }
 
     fn capabilities(&self) -> Capabilities {
-        Capabilities { supports_streaming: true, supports_context: true, max_tokens: 32000 }
+        Capabilities { supports_streaming: true, supports_context: true, max_tokens: 128000, supports_json_mode: true, supports_vision: true }
     }
 
     fn name(&self) -> &str { "codex" }
+
+    fn model(&self) -> Option<&str> { Some(&self.model) }
+
+    fn supports_seed(&self) -> bool { true }
+
+    fn endpoint(&self) -> Option<&str> { Some(&self.base_url) }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        if let Some(credentials) = &self.credentials
+            && credentials.can_refresh()
+        {
+            credentials.refresh().await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn validate_auth(&self) -> Result<AuthValidation> {
+        self.require_api()?;
+        let models: Vec<String> = self.list_models().await?.into_iter().map(|m| m.id).collect();
+        let model_accessible = Some(models.iter().any(|m| m == &self.model));
+        Ok(AuthValidation { account: None, models, model_accessible })
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.require_api()?;
+
+        #[derive(Deserialize)]
+        struct ModelEntry { id: String }
+        #[derive(Deserialize)]
+        struct ModelList { #[serde(default)] data: Vec<ModelEntry> }
+
+        let url = format!("{}/models", self.base_url);
+        let request = self.authorize(http::shared_client().get(&url)).await?;
+        let resp = http::send_via(self.transport.as_ref(), "codex", request).await.with_context(|| "Failed to reach OpenAI API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("codex", "OpenAI model listing failed", resp).await);
+        }
+
+        let list: ModelList = resp.json().await.with_context(|| "Failed to parse OpenAI model list")?;
+        let mut models: Vec<ModelInfo> = list
+            .data
+            .into_iter()
+            .map(|m| ModelInfo { id: m.id, display_name: None, context_window: None, max_output_tokens: None })
+            .collect();
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(models)
+    }
+
+    fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        self.tokenizer.clone().unwrap_or_else(|| tokenizer_for(self.name()))
+    }
 }
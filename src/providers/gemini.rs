@@ -1,23 +1,64 @@
-use super::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use super::{AIProvider, AuthValidation, Capabilities, ContentPart, Context, Message, MessageRole, ModelInfo, ProviderOptions, Response, compose_request, ResponseStream};
+use super::http;
+use super::streaming::{ReconnectPolicy, response_bytes, sse_events};
+use super::pricing::Usage;
+use crate::auth::google::GoogleAdc;
+use crate::context::Embedder;
+use crate::auth::{AuthMethod, ManagedCredentials, TokenRefresher};
 use async_trait::async_trait;
-use anyhow::{Result, anyhow};
-use futures::stream;
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use futures::StreamExt;
 use std::path::PathBuf;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 
+/// Gemini API base URL used unless a profile overrides it
+pub const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+/// Model used for `ai-cli index` embeddings
+const EMBEDDING_MODEL: &str = "text-embedding-004";
+/// Files at least this large are uploaded instead of inlined unless configured otherwise
+pub const DEFAULT_UPLOAD_THRESHOLD: usize = 1024 * 1024;
+/// Context metadata key of the uploaded files: path -> `{ name, uri, mime_type }`
+pub const UPLOADED_FILES_KEY: &str = "gemini_files";
+
+/// Gemini AI provider implementation
 pub struct GeminiProvider {
-    api_key: Option<String>,
+    credentials: Option<ManagedCredentials>,
+    adc: Option<Arc<GoogleAdc>>,
     is_cli_session: bool,
+    model: String,
+    base_url: String,
+    options: ProviderOptions,
+    /// Attached files at least this many bytes go through the Files API; `None` always inlines
+    upload_threshold: Option<usize>,
+    transport: Arc<dyn http::Transport>,
 }
 
 impl GeminiProvider {
+    /// Create a new Gemini provider with an API key
     pub fn new(api_key: String) -> Self {
-        Self { api_key: Some(api_key), is_cli_session: false }
+        Self::from_credentials(ManagedCredentials::new("gemini", AuthMethod::ApiKey { key: api_key }, None))
+    }
+
+    /// Create a Gemini provider from an API key that may be refreshed
+    pub fn from_credentials(credentials: ManagedCredentials) -> Self {
+        Self { credentials: Some(credentials), adc: None, is_cli_session: false, model: Self::default_model(), base_url: API_BASE.to_string(), options: ProviderOptions::default(), upload_threshold: Some(DEFAULT_UPLOAD_THRESHOLD), transport: http::default_transport() }
+    }
+
+    /// Create a Gemini provider authenticated via Google OAuth (ADC)
+    ///
+    /// Access tokens are account session tokens minted and renewed through the ADC's refresher.
+    pub fn from_adc(adc: GoogleAdc) -> Self {
+        let adc = Arc::new(adc);
+        let session = AuthMethod::AccountBased { provider: "gemini".to_string(), session_token: None, expires_at: None };
+        let credentials = ManagedCredentials::new("gemini", session, Some(adc.clone()));
+        Self { credentials: Some(credentials), adc: Some(adc), is_cli_session: true, model: Self::default_model(), base_url: API_BASE.to_string(), options: ProviderOptions::default(), upload_threshold: Some(DEFAULT_UPLOAD_THRESHOLD), transport: http::default_transport() }
     }
 
     pub async fn from_cli_session() -> Result<Self> {
         let config_path = Self::get_config_path()?;
         if config_path.exists() {
-            Ok(Self { api_key: None, is_cli_session: true })
+            Ok(Self { credentials: None, adc: None, is_cli_session: true, model: Self::default_model(), base_url: API_BASE.to_string(), options: ProviderOptions::default(), upload_threshold: Some(DEFAULT_UPLOAD_THRESHOLD), transport: http::default_transport() })
         } else {
             Err(anyhow!("No Gemini CLI session found"))
         }
@@ -25,7 +66,105 @@ impl GeminiProvider {
 
     /// Create a provider assuming a detected CLI/session exists
     pub fn from_detected_cli_session() -> Self {
-        Self { api_key: None, is_cli_session: true }
+        Self { credentials: None, adc: None, is_cli_session: true, model: Self::default_model(), base_url: API_BASE.to_string(), options: ProviderOptions::default(), upload_threshold: Some(DEFAULT_UPLOAD_THRESHOLD), transport: http::default_transport() }
+    }
+
+    /// Renew the API key through a hook when it expires or is rejected
+    pub fn with_token_refresher(mut self, refresher: Arc<dyn TokenRefresher>) -> Self {
+        self.credentials = self.credentials.take().map(|c| c.with_refresher(refresher));
+        self
+    }
+
+    /// Use a specific model instead of the default
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Send requests to a different API base URL (e.g. a gateway)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Execute HTTP requests through `transport`, e.g. a fake in tests
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Default generation parameters, overridden per call by `execute_with_options`
+    pub fn with_options(mut self, options: ProviderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Upload attached files of at least `threshold` bytes through the Files API; `None` inlines every file
+    pub fn with_upload_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.upload_threshold = threshold;
+        self
+    }
+
+    /// Model a call with `options` goes to: the call's, the provider options', then the configured one
+    fn model_for(&self, options: &ProviderOptions) -> String {
+        self.options.merged(options).model.unwrap_or_else(|| self.model.clone())
+    }
+
+    /// generateContent request body with the effective generation parameters
+    ///
+    /// The system prompt goes in `systemInstruction`; context files join the user turn.
+    fn request_body(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> serde_json::Value {
+        let options = self.options.merged(options);
+        // Uploaded files are referenced by URI instead of inlined in the user turn
+        let uploaded = uploaded_files(context);
+        let inline;
+        let context = if uploaded.is_empty() {
+            context
+        } else {
+            let mut copy = context.clone();
+            copy.file_contents.retain(|path, _| !uploaded.iter().any(|(uploaded, _)| uploaded == path));
+            inline = copy;
+            &inline
+        };
+        let (system, user) = compose_request(prompt, context, &options);
+        let mut contents: Vec<serde_json::Value> = context.request_turns(user)
+            .iter()
+            .map(|m| {
+                // Gemini calls the assistant role "model"
+                let role = if m.role == MessageRole::Assistant { "model" } else { "user" };
+                serde_json::json!({ "role": role, "parts": message_parts(m) })
+            })
+            .collect();
+        if let Some(parts) = contents.last_mut().and_then(|turn| turn["parts"].as_array_mut()) {
+            let files = uploaded.iter().flat_map(|(path, file)| [
+                serde_json::json!({ "text": format!("<file path=\"{}\">", path.display()) }),
+                serde_json::json!({ "file_data": { "mime_type": file.mime_type, "file_uri": file.uri } }),
+            ]);
+            parts.splice(0..0, files);
+        }
+        let mut body = serde_json::json!({ "contents": contents });
+        if let Some(system) = system {
+            body["systemInstruction"] = serde_json::json!({ "parts": [{ "text": system }] });
+        }
+        let mut generation = serde_json::Map::new();
+        if let Some(t) = options.temperature { generation.insert("temperature".into(), t.into()); }
+        if let Some(p) = options.top_p { generation.insert("topP".into(), p.into()); }
+        if let Some(m) = options.max_tokens { generation.insert("maxOutputTokens".into(), m.into()); }
+        if !options.stop.is_empty() { generation.insert("stopSequences".into(), options.stop.into()); }
+        if let Some(seed) = options.seed { generation.insert("seed".into(), seed.into()); }
+        if let Some(json) = options.json {
+            generation.insert("responseMimeType".into(), "application/json".into());
+            generation.insert("responseJsonSchema".into(), json.schema);
+        }
+        if !generation.is_empty() {
+            body["generationConfig"] = generation.into();
+        }
+        body
+    }
+
+    /// Model used when GEMINI_MODEL is not set
+    fn default_model() -> String {
+        std::env::var("GEMINI_MODEL").unwrap_or_else(|_| "gemini-1.5-pro".to_string())
     }
 
     fn get_config_path() -> Result<PathBuf> {
@@ -33,30 +172,439 @@ impl GeminiProvider {
         Ok(home.join(".gemini").join("config.json"))
     }
 
-    fn is_authenticated(&self) -> bool { self.api_key.is_some() || self.is_cli_session }
+    fn is_authenticated(&self) -> bool { self.credentials.is_some() || self.adc.is_some() || self.is_cli_session }
+
+    /// Check whether requests can actually reach the API
+    fn has_api_credentials(&self) -> bool { self.credentials.is_some() || self.adc.is_some() }
+
+    /// Current API key or session token, refreshed first if it is about to expire
+    async fn credential(&self) -> Result<Option<AuthMethod>> {
+        match &self.credentials {
+            Some(credentials) => Ok(Some(credentials.current().await?).filter(|method| method.secret().is_some())),
+            None => Ok(None),
+        }
+    }
+
+    /// Attach API key or OAuth credentials to a request
+    async fn authorize(&self, request: reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder> {
+        let Some(auth) = self.credential().await? else {
+            return Err(anyhow!("No Gemini credentials set"));
+        };
+        let mut request = auth.authorize(request, "x-goog-api-key");
+        if let Some(project) = self.adc.as_ref().and_then(|adc| adc.quota_project()) {
+            request = request.header("x-goog-user-project", project);
+        }
+        Ok(request)
+    }
+
+    /// Files API upload endpoint, e.g. `.../upload/v1beta/files` for `.../v1beta`
+    fn upload_url(&self) -> String {
+        match self.base_url.rsplit_once('/') {
+            Some((root, version)) => format!("{}/upload/{}/files?uploadType=media", root, version),
+            None => format!("{}/files?uploadType=media", self.base_url),
+        }
+    }
+
+    /// Upload one file's contents through the Files API
+    async fn upload_file(&self, content: &str) -> Result<UploadedFile> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RespFile { name: String, uri: String, #[serde(default)] mime_type: Option<String> }
+        #[derive(Deserialize)]
+        struct RespBody { file: RespFile }
+
+        let request = http::shared_client()
+            .post(self.upload_url())
+            .header(reqwest::header::CONTENT_TYPE, TEXT_MIME_TYPE)
+            .body(content.to_string());
+        let request = self.authorize(request).await?;
+        let resp = http::send_via(self.transport.as_ref(), "gemini", request).await.with_context(|| "Failed to upload file to Gemini")?;
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("gemini", "Gemini file upload failed", resp).await);
+        }
+        let parsed: RespBody = resp.json().await.with_context(|| "Failed to parse Gemini file upload response")?;
+        Ok(UploadedFile {
+            name: parsed.file.name,
+            uri: parsed.file.uri,
+            mime_type: parsed.file.mime_type.unwrap_or_else(|| TEXT_MIME_TYPE.to_string()),
+        })
+    }
+
+    async fn execute_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
+        let client = http::shared_client();
+        let url = format!("{}/models/{}:generateContent", self.base_url, self.model_for(options));
+
+        let body = self.request_body(prompt, context, options);
+
+        #[derive(Deserialize)]
+        struct RespPart { #[serde(default)] text: Option<String> }
+        #[derive(Deserialize)]
+        struct RespContent { #[serde(default)] parts: Vec<RespPart> }
+        #[derive(Deserialize)]
+        struct Candidate { #[serde(default)] content: Option<RespContent> }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct UsageMetadata {
+            #[serde(default)]
+            prompt_token_count: u64,
+            #[serde(default)]
+            candidates_token_count: u64,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RespBody {
+            #[serde(default)]
+            candidates: Vec<Candidate>,
+            #[serde(default)]
+            usage_metadata: Option<UsageMetadata>,
+            #[serde(default)]
+            model_version: Option<String>,
+        }
+
+        let request = self.authorize(client.post(&url).json(&body)).await?;
+        let resp = http::send_via(self.transport.as_ref(), "gemini", request)
+            .await
+            .with_context(|| "Failed to send request to Gemini API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("gemini", "Gemini API error", resp).await);
+        }
+
+        let parsed: RespBody = resp.json().await.with_context(|| "Failed to parse Gemini response")?;
+        let text = parsed
+            .candidates
+            .into_iter()
+            .next()
+            .and_then(|c| c.content)
+            .map(|c| c.parts.into_iter().filter_map(|p| p.text).collect::<Vec<_>>().join(""))
+            .unwrap_or_default();
+        let response = Response::new(if text.is_empty() { "(empty response)".to_string() } else { text })
+            .with_metadata("model", parsed.model_version.unwrap_or_else(|| self.model_for(options)));
+        Ok(match parsed.usage_metadata {
+            Some(usage) => response.with_usage(Usage::new(usage.prompt_token_count, usage.candidates_token_count)),
+            None => response,
+        })
+    }
+
+    /// Stream text from `streamGenerateContent` over SSE
+    async fn stream_via_api(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream<'static>> {
+        let url = format!("{}/models/{}:streamGenerateContent?alt=sse", self.base_url, self.model_for(options));
+        let body = self.request_body(prompt, context, options);
+        // Authorize once up front; the stream outlives `self`
+        let request = self.authorize(http::shared_client().post(&url).json(&body)).await?;
+
+        // Gemini streams cannot be resumed, so only reconnect before the first event
+        let transport = self.transport.clone();
+        let connect = move |_last_event_id: Option<String>| {
+            let request = request.try_clone();
+            let transport = transport.clone();
+            async move {
+                let request = request.ok_or_else(|| anyhow!("Gemini stream request cannot be retried"))?;
+                let resp = http::send_via(transport.as_ref(), "gemini", request).await.with_context(|| "Failed to send request to Gemini API")?;
+                if !resp.status().is_success() {
+                    return Err(http::error_for_status("gemini", "Gemini API error", resp).await);
+                }
+                Ok(response_bytes(resp))
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct RespPart { #[serde(default)] text: Option<String> }
+        #[derive(Deserialize)]
+        struct RespContent { #[serde(default)] parts: Vec<RespPart> }
+        #[derive(Deserialize)]
+        struct Candidate { #[serde(default)] content: Option<RespContent> }
+        #[derive(Deserialize)]
+        struct Chunk { #[serde(default)] candidates: Vec<Candidate> }
+
+        let chunks = sse_events(connect, ReconnectPolicy::default()).filter_map(|event| {
+            let item = event.and_then(|event| {
+                let chunk: Chunk = serde_json::from_str(&event.data)
+                    .with_context(|| format!("Garbled Gemini stream chunk: {}", event.data))?;
+                let text: String = chunk
+                    .candidates
+                    .into_iter()
+                    .filter_map(|c| c.content)
+                    .flat_map(|c| c.parts)
+                    .filter_map(|p| p.text)
+                    .collect();
+                Ok((!text.is_empty()).then_some(text))
+            });
+            futures::future::ready(item.transpose())
+        });
+        Ok(Box::pin(chunks))
+    }
+}
+
+/// Mime type files are uploaded as; context files are always text
+const TEXT_MIME_TYPE: &str = "text/plain";
+
+/// Handle of a file stored through the Files API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadedFile {
+    /// Resource name used to delete the file, e.g. `files/abc123`
+    pub name: String,
+    /// URI generateContent requests reference the file by
+    pub uri: String,
+    pub mime_type: String,
+}
+
+/// Uploaded files recorded in `context` that are still attached, sorted by path
+pub fn uploaded_files(context: &Context) -> Vec<(PathBuf, UploadedFile)> {
+    let Some(serde_json::Value::Object(files)) = context.metadata.get(UPLOADED_FILES_KEY) else { return Vec::new() };
+    let mut uploaded: Vec<(PathBuf, UploadedFile)> = files
+        .iter()
+        .filter_map(|(path, file)| Some((PathBuf::from(path), serde_json::from_value(file.clone()).ok()?)))
+        .filter(|(path, _)| context.file_contents.contains_key(path))
+        .collect();
+    uploaded.sort_by(|a, b| a.0.cmp(&b.0));
+    uploaded
+}
+
+/// generateContent parts: inline images followed by the text
+fn message_parts(message: &Message) -> Vec<serde_json::Value> {
+    message
+        .parts()
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => serde_json::json!({ "text": text }),
+            ContentPart::Image { image } => {
+                serde_json::json!({ "inline_data": { "mime_type": image.media_type, "data": image.data } })
+            }
+        })
+        .collect()
 }
 
 #[async_trait]
 impl AIProvider for GeminiProvider {
     async fn execute(&self, prompt: &str, context: &Context) -> Result<Response> {
+        self.execute_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn execute_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
         if !self.is_authenticated() { return Err(anyhow!("Gemini provider not authenticated")); }
-        let response_text = format!("Gemini response to: {}", prompt);
-        let mut response = Response::new(response_text);
+        if !self.has_api_credentials() {
+            return Err(anyhow!(
+                "Gemini CLI session detected, but no OAuth credentials could be loaded. Run `gcloud auth application-default login` or set GEMINI_API_KEY."
+            ));
+        }
+
+        let mut response = self.execute_via_api(prompt, context, options).await?;
         if !context.conversation_history.is_empty() {
             response = response.with_metadata("conversation_length", context.conversation_history.len().to_string());
         }
         Ok(response)
     }
 
-    async fn stream(&self, prompt: &str, _context: &Context) -> Result<ResponseStream> {
-        if !self.is_authenticated() { return Err(anyhow!("Gemini provider not authenticated")); }
-        let response = format!("Gemini streaming response to: {}", prompt);
-        Ok(Box::pin(stream::once(async move { Ok(response) })))
+    async fn stream(&self, prompt: &str, context: &Context) -> Result<ResponseStream> {
+        self.stream_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn stream_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream> {
+        if !self.has_api_credentials() { return Err(anyhow!("Gemini provider not authenticated for streaming")); }
+        self.stream_via_api(prompt, context, options).await
     }
 
     fn capabilities(&self) -> Capabilities {
-        Capabilities { supports_streaming: true, supports_context: true, max_tokens: 100000 }
+        Capabilities { supports_streaming: true, supports_context: true, max_tokens: 100000, supports_json_mode: true, supports_vision: true }
     }
 
     fn name(&self) -> &str { "gemini" }
+
+    fn model(&self) -> Option<&str> { Some(&self.model) }
+
+    fn supports_seed(&self) -> bool { true }
+
+    fn endpoint(&self) -> Option<&str> { Some(&self.base_url) }
+
+    async fn probe(&self) -> Result<Capabilities> {
+        if !self.has_api_credentials() {
+            return Ok(self.capabilities());
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ModelInfo {
+            #[serde(default)]
+            input_token_limit: Option<usize>,
+            #[serde(default)]
+            supported_generation_methods: Vec<String>,
+        }
+
+        let url = format!("{}/models/{}", self.base_url, self.model);
+        let request = self.authorize(http::shared_client().get(&url)).await?;
+        let resp = http::send_via(self.transport.as_ref(), "gemini", request).await.with_context(|| "Failed to probe Gemini model")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("gemini", "Gemini model probe failed", resp).await);
+        }
+
+        let info: ModelInfo = resp.json().await.with_context(|| "Failed to parse Gemini model info")?;
+        let mut capabilities = self.capabilities();
+        if let Some(limit) = info.input_token_limit {
+            capabilities.max_tokens = limit;
+        }
+        capabilities.supports_streaming = info
+            .supported_generation_methods
+            .iter()
+            .any(|m| m == "streamGenerateContent");
+        Ok(capabilities)
+    }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        if let Some(credentials) = &self.credentials
+            && credentials.can_refresh()
+        {
+            // OAuth access tokens can be revoked before their recorded expiry; the ADC mints a new one
+            credentials.refresh().await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn validate_auth(&self) -> Result<AuthValidation> {
+        if !self.has_api_credentials() {
+            return Err(anyhow!("Gemini CLI session has no loadable credentials; run `gcloud auth application-default login`"));
+        }
+        let models: Vec<String> = self.list_models().await?.into_iter().map(|m| m.id).collect();
+        let model_accessible = Some(models.iter().any(|m| m == &self.model));
+        let account = self.adc.as_ref()
+            .and_then(|adc| adc.quota_project())
+            .map(|project| format!("project {}", project));
+        Ok(AuthValidation { account, models, model_accessible })
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        if !self.has_api_credentials() {
+            return Err(anyhow!("Gemini CLI session has no loadable credentials; run `gcloud auth application-default login`"));
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ModelEntry {
+            name: String,
+            #[serde(default)]
+            display_name: Option<String>,
+            #[serde(default)]
+            input_token_limit: Option<usize>,
+            #[serde(default)]
+            output_token_limit: Option<usize>,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ModelList { #[serde(default)] models: Vec<ModelEntry> }
+
+        let url = format!("{}/models?pageSize=1000", self.base_url);
+        let request = self.authorize(http::shared_client().get(&url)).await?;
+        let resp = http::send_via(self.transport.as_ref(), "gemini", request).await.with_context(|| "Failed to reach Gemini API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("gemini", "Gemini model listing failed", resp).await);
+        }
+
+        let list: ModelList = resp.json().await.with_context(|| "Failed to parse Gemini model list")?;
+        Ok(list
+            .models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.name.trim_start_matches("models/").to_string(),
+                display_name: m.display_name,
+                context_window: m.input_token_limit,
+                max_output_tokens: m.output_token_limit,
+            })
+            .collect())
+    }
+
+    /// Upload attached files over the threshold, recording their handles under [`UPLOADED_FILES_KEY`]
+    async fn prepare_context(&self, context: &mut Context) -> Result<()> {
+        let Some(threshold) = self.upload_threshold else { return Ok(()) };
+        if !self.has_api_credentials() {
+            return Ok(());
+        }
+        let mut files = match context.metadata.remove(UPLOADED_FILES_KEY) {
+            Some(serde_json::Value::Object(files)) => files,
+            _ => serde_json::Map::new(),
+        };
+        let mut pending: Vec<(&PathBuf, &String)> = context.file_contents
+            .iter()
+            .filter(|(path, content)| content.len() >= threshold && !files.contains_key(&path.to_string_lossy().into_owned()))
+            .collect();
+        pending.sort();
+        let mut result = Ok(());
+        for (path, content) in pending {
+            match self.upload_file(content).await {
+                Ok(file) => {
+                    tracing::debug!(path = %path.display(), name = %file.name, "uploaded file to Gemini");
+                    files.insert(path.to_string_lossy().into_owned(), serde_json::to_value(file)?);
+                }
+                Err(e) => {
+                    result = Err(e.context(format!("Uploading {} failed", path.display())));
+                    break;
+                }
+            }
+        }
+        // Files uploaded before a failure are still recorded so they get deleted
+        if !files.is_empty() {
+            context.metadata.insert(UPLOADED_FILES_KEY.to_string(), files.into());
+        }
+        result
+    }
+
+    /// Delete every file recorded under [`UPLOADED_FILES_KEY`], forgetting the handles
+    async fn release_context(&self, context: &mut Context) -> Result<()> {
+        let Some(serde_json::Value::Object(files)) = context.metadata.remove(UPLOADED_FILES_KEY) else { return Ok(()) };
+        let mut result = Ok(());
+        for file in files.into_values().filter_map(|file| serde_json::from_value::<UploadedFile>(file).ok()) {
+            let url = format!("{}/{}", self.base_url, file.name);
+            let deleted = match self.authorize(http::shared_client().delete(&url)).await {
+                Ok(request) => match http::send_via(self.transport.as_ref(), "gemini", request).await {
+                    Ok(resp) if resp.status().is_success() => Ok(()),
+                    Ok(resp) => Err(http::error_for_status("gemini", "Gemini file deletion failed", resp).await),
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e),
+            };
+            // Keep deleting the rest; report the first failure
+            if let Err(e) = deleted
+                && result.is_ok()
+            {
+                result = Err(e.context(format!("Deleting {} failed", file.name)));
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl Embedder for GeminiProvider {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if !self.has_api_credentials() {
+            return Err(anyhow!("Gemini embeddings need an API key or Application Default Credentials"));
+        }
+
+        #[derive(Deserialize)]
+        struct Embedding { #[serde(default)] values: Vec<f32> }
+        #[derive(Deserialize)]
+        struct EmbedResponse { #[serde(default)] embeddings: Vec<Embedding> }
+
+        let model = format!("models/{}", EMBEDDING_MODEL);
+        let requests: Vec<serde_json::Value> = texts
+            .iter()
+            .map(|text| serde_json::json!({ "model": model, "content": { "parts": [{ "text": text }] } }))
+            .collect();
+        let url = format!("{}/{}:batchEmbedContents", self.base_url, model);
+        let request = self.authorize(http::shared_client().post(&url).json(&serde_json::json!({ "requests": requests }))).await?;
+        let resp = http::send_via(self.transport.as_ref(), "gemini", request).await.with_context(|| "Failed to reach Gemini embeddings API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("gemini", "Gemini embeddings error", resp).await);
+        }
+
+        let parsed: EmbedResponse = resp.json().await.with_context(|| "Failed to parse Gemini embeddings")?;
+        Ok(parsed.embeddings.into_iter().map(|e| e.values).collect())
+    }
 }
@@ -0,0 +1,527 @@
+//! Logged HTTP sends for provider calls
+//!
+//! Requests are logged at debug level with credential headers and `key=`
+//! query parameters replaced by `[REDACTED]`; responses with status and timing.
+//! Providers execute requests through a [`Transport`] so tests can swap the
+//! network for canned responses. Every provider shares one pooled client,
+//! configured by the `[http]` config section through [`init_shared_client`].
+//! The client honors `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`
+//! (SOCKS URLs included) unless `[http] proxy` names a proxy explicitly.
+//! With `--offline`, only loopback hosts are reachable: requests anywhere else
+//! fail at once without retries, while local model servers and injected
+//! transports keep answering. With `--debug-http`, every exchange is also dumped in full — credential
+//! headers and token fields redacted, bodies truncated — to stderr or a file.
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Certificate, Client, NoProxy, Request, RequestBuilder, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+
+use crate::error::{AuthError, ProviderError};
+
+/// Headers whose values are never logged
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "cookie",
+    "set-cookie",
+];
+
+/// JSON and form fields whose values are never dumped
+const SENSITIVE_FIELDS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "client_secret",
+    "api_key",
+    "assertion",
+    "password",
+];
+
+/// Characters of a body shown by `--debug-http`
+pub const DEBUG_BODY_LIMIT: usize = 4096;
+
+/// Header names and values safe to log
+pub fn redacted_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "[REDACTED]".to_string()
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// URL with credential query parameters redacted
+pub fn redacted_url(url: &Url) -> String {
+    if url.query().is_none() {
+        return url.to_string();
+    }
+    let mut redacted = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let secret = matches!(k.as_ref(), "key" | "api_key" | "access_token");
+            (k.into_owned(), if secret { "[REDACTED]".to_string() } else { v.into_owned() })
+        })
+        .collect();
+    redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    redacted.to_string()
+}
+
+/// `[http]` section: how the shared client connects
+///
+/// Read from the user config and environment only; a project `.ai-cli.toml`
+/// cannot route requests through a proxy or trust extra certificates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpSettings {
+    /// Seconds a whole request may take, streaming included; no limit when unset
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Seconds to wait for a connection
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Seconds an idle pooled connection stays open for reuse
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+    /// Idle connections kept open per host
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// Seconds between TCP keep-alive probes
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64,
+    /// Speak HTTP/1.1 only instead of negotiating HTTP/2
+    #[serde(default)]
+    pub http1_only: bool,
+    /// Proxy for every request: `http://`, `https://`, `socks5://` or `socks5h://`, credentials allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Comma-separated hosts, domains and CIDR ranges that bypass `proxy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    /// PEM file of extra root certificates to trust, e.g. a corporate TLS-inspecting proxy's CA
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+fn default_connect_timeout_secs() -> u64 { 10 }
+fn default_pool_idle_timeout_secs() -> u64 { 90 }
+fn default_pool_max_idle_per_host() -> usize { 8 }
+fn default_tcp_keepalive_secs() -> u64 { 60 }
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: None,
+            connect_timeout_secs: default_connect_timeout_secs(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            http1_only: false,
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// These settings with `AI_CLI_PROXY`, `AI_CLI_NO_PROXY` and `AI_CLI_CA_BUNDLE` from `lookup` taking precedence
+    pub fn with_env(mut self, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let lookup = |var: &str| lookup(var).filter(|value| !value.trim().is_empty());
+        if let Some(proxy) = lookup("AI_CLI_PROXY") {
+            self.proxy = Some(proxy);
+        }
+        if let Some(no_proxy) = lookup("AI_CLI_NO_PROXY") {
+            self.no_proxy = Some(no_proxy);
+        }
+        if let Some(path) = lookup("AI_CLI_CA_BUNDLE") {
+            self.ca_bundle = Some(PathBuf::from(path));
+        }
+        self
+    }
+
+    /// A client with these settings
+    pub fn client(&self) -> anyhow::Result<Client> {
+        let mut builder = Client::builder()
+            .user_agent(concat!("ai-cli/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(self.pool_idle_timeout_secs))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(Duration::from_secs(self.tcp_keepalive_secs));
+        if let Some(timeout) = self.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if self.http1_only {
+            builder = builder.http1_only();
+        }
+        if let Some(url) = &self.proxy {
+            let proxy = reqwest::Proxy::all(url)
+                .with_context(|| format!("Invalid proxy URL '{}'", redacted_proxy(url)))?
+                .no_proxy(self.no_proxy.as_deref().and_then(NoProxy::from_string));
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_bundle {
+            let pem = std::fs::read(path).with_context(|| format!("Cannot read CA bundle {}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .ok()
+                .filter(|certs| !certs.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("No PEM certificates in CA bundle {}", path.display()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Proxy URL with any password replaced by `[REDACTED]`
+pub fn redacted_proxy(url: &str) -> String {
+    match Url::parse(url).ok().as_ref().and_then(Url::password) {
+        Some(password) => url.replacen(&format!(":{}@", password), ":[REDACTED]@", 1),
+        None => url.to_string(),
+    }
+}
+
+static SHARED_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Build the client every provider shares from `settings`
+///
+/// Call before any provider is created; fails if the shared client already exists.
+pub fn init_shared_client(settings: &HttpSettings) -> anyhow::Result<()> {
+    let client = settings.client().context("Invalid [http] settings")?;
+    SHARED_CLIENT.set(client).map_err(|_| anyhow::anyhow!("The shared HTTP client is already in use"))
+}
+
+/// The pooled client shared by every provider, with default settings unless [`init_shared_client`] ran first
+pub fn shared_client() -> Client {
+    SHARED_CLIENT
+        .get_or_init(|| HttpSettings::default().client().unwrap_or_default())
+        .clone()
+}
+
+/// Executes built requests on behalf of a provider
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send `request`, failing only when no response arrived
+    async fn execute(&self, provider: &str, request: Request) -> Result<Response, ProviderError>;
+}
+
+/// Transport that sends requests over the network with `reqwest`
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl Default for ReqwestTransport {
+    /// Transport over the [`shared_client`]
+    fn default() -> Self {
+        Self::new(shared_client())
+    }
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn execute(&self, provider: &str, request: Request) -> Result<Response, ProviderError> {
+        if is_offline() && !is_loopback(request.url()) {
+            return Err(ProviderError::network(
+                provider,
+                false,
+                format!(
+                    "Offline mode: {} needs network access (unset --offline / AI_CLI_OFFLINE)",
+                    request.url().host_str().unwrap_or("the endpoint")
+                ),
+            ));
+        }
+        self.client.execute(request).await.map_err(|e| {
+            if e.is_timeout() {
+                ProviderError::timeout(provider, e.to_string())
+            } else {
+                ProviderError::network(provider, !e.is_builder(), e.to_string())
+            }
+        })
+    }
+}
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbid network access to anything but loopback hosts (`--offline`)
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Whether [`set_offline`] forbade network access
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Whether `url` points at this machine, e.g. a local Ollama server
+pub fn is_loopback(url: &Url) -> bool {
+    url.host_str().is_some_and(|host| {
+        host.eq_ignore_ascii_case("localhost")
+            || host.trim_start_matches('[').trim_end_matches(']').parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    })
+}
+
+/// Transport providers use unless one is injected
+pub fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(ReqwestTransport::default())
+}
+
+/// Send a request with the client it was built from, logging it and its outcome under `provider`
+pub async fn send(provider: &str, request: RequestBuilder) -> Result<Response, ProviderError> {
+    let (client, request) = request.build_split();
+    send_request(&ReqwestTransport::new(client), provider, request).await
+}
+
+/// Send a request through `transport`, logging it and its outcome under `provider`
+pub async fn send_via(transport: &dyn Transport, provider: &str, request: RequestBuilder) -> Result<Response, ProviderError> {
+    send_request(transport, provider, request.build_split().1).await
+}
+
+async fn send_request(
+    transport: &dyn Transport,
+    provider: &str,
+    request: reqwest::Result<Request>,
+) -> Result<Response, ProviderError> {
+    let request = request.map_err(|e| ProviderError::network(provider, false, e.to_string()))?;
+    if DEBUG_HTTP.get().is_some() {
+        debug_write(&dump_request(provider, &request));
+    }
+    tracing::debug!(
+        provider,
+        method = %request.method(),
+        url = %redacted_url(request.url()),
+        headers = ?redacted_headers(request.headers()),
+        "http request"
+    );
+    let span = tracing::info_span!(
+        "http",
+        provider,
+        method = %request.method(),
+        url = %redacted_url(request.url()),
+        status = tracing::field::Empty
+    );
+    let started = Instant::now();
+    let result = transport.execute(provider, request).instrument(span.clone()).await;
+    match &result {
+        Ok(response) => span.record("status", response.status().as_u16()),
+        Err(_) => span.record("status", "error"),
+    };
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match &result {
+        Ok(response) => tracing::debug!(provider, status = response.status().as_u16(), elapsed_ms, "http response"),
+        Err(error) => tracing::warn!(provider, %error, elapsed_ms, "http request failed"),
+    }
+    match result {
+        Ok(response) if DEBUG_HTTP.get().is_some() => debug_response(provider, response, elapsed_ms).await,
+        Err(error) if DEBUG_HTTP.get().is_some() => {
+            debug_write(&format!("<<< {} failed after {} ms: {}\n\n", provider, elapsed_ms, error));
+            Err(error)
+        }
+        result => result,
+    }
+}
+
+static DEBUG_HTTP: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Dump every request and response to `path`, or to stderr when `None` (`--debug-http`)
+///
+/// Fails if dumping was already enabled or the file cannot be opened.
+pub fn enable_debug_http(path: Option<&Path>) -> anyhow::Result<()> {
+    let sink: Box<dyn Write + Send> = match path {
+        Some(path) => Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open HTTP debug file {}", path.display()))?,
+        ),
+        None => Box::new(std::io::stderr()),
+    };
+    DEBUG_HTTP.set(Mutex::new(sink)).map_err(|_| anyhow::anyhow!("HTTP debugging is already enabled"))
+}
+
+fn debug_write(text: &str) {
+    if let Some(sink) = DEBUG_HTTP.get() {
+        let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = sink.write_all(text.as_bytes()).and_then(|_| sink.flush());
+    }
+}
+
+/// Dump the response, buffering its body unless it streams, and hand back an equivalent response
+async fn debug_response(provider: &str, response: Response, elapsed_ms: u64) -> Result<Response, ProviderError> {
+    let (status, headers) = (response.status(), response.headers().clone());
+    let streamed = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if streamed {
+        debug_write(&dump_response(provider, status, &headers, None, elapsed_ms));
+        return Ok(response);
+    }
+    let version = response.version();
+    let body = response.bytes().await.map_err(|e| ProviderError::network(provider, true, e.to_string()))?;
+    debug_write(&dump_response(provider, status, &headers, Some(&body), elapsed_ms));
+    let mut rebuilt = http::Response::new(body);
+    *rebuilt.status_mut() = status;
+    *rebuilt.version_mut() = version;
+    *rebuilt.headers_mut() = headers;
+    Ok(Response::from(rebuilt))
+}
+
+/// Request as `--debug-http` shows it: `>>>` line, redacted headers, sanitized body
+pub fn dump_request(provider: &str, request: &Request) -> String {
+    let mut out = format!(">>> {} {} {}\n", provider, request.method(), redacted_url(request.url()));
+    dump_headers(&mut out, '>', request.headers());
+    match request.body().map(|body| body.as_bytes()) {
+        Some(Some(bytes)) => dump_body(&mut out, '>', &sanitized_body(bytes, DEBUG_BODY_LIMIT)),
+        Some(None) => dump_body(&mut out, '>', "(streamed body not captured)"),
+        None => {}
+    }
+    out.push('\n');
+    out
+}
+
+/// Response as `--debug-http` shows it; `body` is `None` for streamed responses
+pub fn dump_response(provider: &str, status: StatusCode, headers: &HeaderMap, body: Option<&[u8]>, elapsed_ms: u64) -> String {
+    let mut out = format!("<<< {} {} ({} ms)\n", provider, status, elapsed_ms);
+    dump_headers(&mut out, '<', headers);
+    match body {
+        Some(bytes) if !bytes.is_empty() => dump_body(&mut out, '<', &sanitized_body(bytes, DEBUG_BODY_LIMIT)),
+        Some(_) => {}
+        None => dump_body(&mut out, '<', "(streamed body not captured)"),
+    }
+    out.push('\n');
+    out
+}
+
+fn dump_headers(out: &mut String, marker: char, headers: &HeaderMap) {
+    for (name, value) in redacted_headers(headers) {
+        out.push_str(&format!("{} {}: {}\n", marker, name, value));
+    }
+}
+
+fn dump_body(out: &mut String, marker: char, body: &str) {
+    out.push_str(&format!("{}\n", marker));
+    for line in body.lines() {
+        out.push_str(&format!("{} {}\n", marker, line));
+    }
+}
+
+/// Body text safe to dump: token fields of JSON or form bodies redacted, cut to `limit` characters
+pub fn sanitized_body(bytes: &[u8], limit: usize) -> String {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return format!("({} bytes of binary data)", bytes.len());
+    };
+    let text = if let Ok(mut json) = serde_json::from_str::<serde_json::Value>(text) {
+        redact_fields(&mut json);
+        json.to_string()
+    } else if text.contains('=') && !text.contains(char::is_whitespace) {
+        text.split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if SENSITIVE_FIELDS.contains(&key) => format!("{}=[REDACTED]", key),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    } else {
+        text.to_string()
+    };
+    match text.char_indices().nth(limit) {
+        Some((cut, _)) => format!("{}… ({} bytes total)", &text[..cut], bytes.len()),
+        None => text,
+    }
+}
+
+fn redact_fields(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_FIELDS.contains(&key.as_str()) {
+                    *value = serde_json::Value::String("[REDACTED]".to_string());
+                } else {
+                    redact_fields(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_fields),
+        _ => {}
+    }
+}
+
+/// Typed error for a non-success response: 401 is an [`AuthError`], anything
+/// else a [`ProviderError`] described as `"{what}: {status} - {body}"`, carrying
+/// the response's [`retry_after`] delay
+pub async fn error_for_status(provider: &str, what: &str, response: Response) -> anyhow::Error {
+    let status = response.status();
+    let wait = retry_after(response.headers(), SystemTime::now());
+    let text = response.text().await.unwrap_or_default();
+    if status == StatusCode::UNAUTHORIZED {
+        return AuthError { provider: provider.to_string(), detail: text }.into();
+    }
+    ProviderError::from_status(provider, status.as_u16(), format!("{}: {} - {}", what, status, text))
+        .with_retry_after(wait)
+        .into()
+}
+
+/// Delay a response asks for before the next request
+///
+/// Reads OpenAI's `retry-after-ms`, then `Retry-After` as seconds or as an
+/// HTTP date (relative to `now`).
+pub fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()).filter(|ms| ms.is_finite() && *ms >= 0.0) {
+        return Some(Duration::from_secs_f64(ms / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(parse_http_date(value)?);
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Seconds since the epoch of an IMF-fixdate such as `Wed, 21 Oct 2015 07:28:00 GMT`
+fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| m == month)? as u64 + 1;
+    let year: u64 = year.parse().ok()?;
+    let mut clock = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if !(1..=31).contains(&day) || year < 1970 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    // Days from the civil date (Howard Hinnant's algorithm), with March as the first month
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let year_of_era = y % 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
@@ -0,0 +1,139 @@
+//! Provider names: the canonical name of each built-in provider and its aliases
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// A built-in provider
+///
+/// Parses from the canonical name or an alias (`anthropic`, `google`, `openai`),
+/// ignoring case, and dereferences to the canonical name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ProviderId {
+    Claude,
+    Gemini,
+    Codex,
+}
+
+impl ProviderId {
+    pub const ALL: [ProviderId; 3] = [ProviderId::Claude, ProviderId::Gemini, ProviderId::Codex];
+
+    /// Canonical name
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ProviderId::Claude => "claude",
+            ProviderId::Gemini => "gemini",
+            ProviderId::Codex => "codex",
+        }
+    }
+
+    /// Other names accepted for this provider
+    pub const fn aliases(self) -> &'static [&'static str] {
+        match self {
+            ProviderId::Claude => &["anthropic"],
+            ProviderId::Gemini => &["google"],
+            ProviderId::Codex => &["openai"],
+        }
+    }
+
+    /// Canonical name for `name` if it names a built-in provider, else `name` unchanged
+    pub fn canonical(name: &str) -> &str {
+        match name.parse::<ProviderId>() {
+            Ok(id) => id.as_str(),
+            Err(_) => name,
+        }
+    }
+}
+
+/// A name that is not a known provider, with the closest known name if one is near
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown provider '{name}'{}", suggestion_hint(.suggestion))]
+pub struct UnknownProvider {
+    pub name: String,
+    pub suggestion: Option<&'static str>,
+}
+
+fn suggestion_hint(suggestion: &Option<&'static str>) -> String {
+    match suggestion {
+        Some(name) => format!(" (did you mean '{}'?)", name),
+        None => format!(" (expected one of: {})", ProviderId::ALL.map(ProviderId::as_str).join(", ")),
+    }
+}
+
+impl FromStr for ProviderId {
+    type Err = UnknownProvider;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let lowered = name.trim().to_lowercase();
+        ProviderId::ALL
+            .into_iter()
+            .find(|id| id.as_str() == lowered || id.aliases().contains(&lowered.as_str()))
+            .ok_or_else(|| {
+                let names = ProviderId::ALL.iter().flat_map(|id| std::iter::once(id.as_str()).chain(id.aliases().iter().copied()));
+                let suggestion = closest(&lowered, names).map(ProviderId::canonical);
+                UnknownProvider { name: name.to_string(), suggestion }
+            })
+    }
+}
+
+impl fmt::Display for ProviderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Deref for ProviderId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<&str> for ProviderId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl From<ProviderId> for String {
+    fn from(id: ProviderId) -> Self {
+        id.as_str().to_string()
+    }
+}
+
+impl TryFrom<String> for ProviderId {
+    type Error = UnknownProvider;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+/// The candidate within a small edit distance of `name`, if any
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
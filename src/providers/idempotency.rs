@@ -0,0 +1,65 @@
+//! Idempotency keys for provider requests
+//!
+//! A slow response can arrive after its request timed out and was retried,
+//! billing the same generation twice. The executor keeps a timed-out attempt
+//! running and answers the retry with its reply if that lands first, during
+//! the backoff or while the retry is in flight. Each logical request also gets
+//! one key, derived from what is sent and reused by every retry of it; it is
+//! written to the audit log and the response's `idempotency_key` metadata,
+//! and sent as [`IDEMPOTENCY_HEADER`] by providers whose API accepts it.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Context, ProviderOptions};
+
+/// Header carrying the key, for providers that support it
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// Issues idempotency keys for one run
+///
+/// Keys hash the request content plus a per-run salt and sequence number, so
+/// identical steps run twice on purpose still get distinct keys.
+#[derive(Debug)]
+pub struct IdempotencyKeys {
+    salt: String,
+    next: AtomicU64,
+}
+
+impl IdempotencyKeys {
+    /// Keys salted from the clock and process id
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let seed = format!("{}:{}", nanos, std::process::id());
+        Self::with_salt(&content_hash(&[seed.as_bytes()])[..8])
+    }
+
+    /// Keys with a fixed salt
+    pub fn with_salt(salt: impl Into<String>) -> Self {
+        Self { salt: salt.into(), next: AtomicU64::new(0) }
+    }
+
+    /// A fresh key for sending `request` with `context` and `options` to `provider`
+    pub fn key(&self, provider: &str, request: &str, context: &Context, options: &ProviderOptions) -> String {
+        let sequence = self.next.fetch_add(1, Ordering::Relaxed);
+        let context = serde_json::to_vec(context).unwrap_or_default();
+        let options = serde_json::to_vec(options).unwrap_or_default();
+        let hash = content_hash(&[provider.as_bytes(), request.as_bytes(), &context, &options]);
+        format!("ai-cli-{}-{}-{}", self.salt, sequence, hash)
+    }
+}
+
+impl Default for IdempotencyKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FNV-1a over `parts`, each followed by a separator byte
+pub fn content_hash(parts: &[&[u8]]) -> String {
+    let hash = parts.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, part| {
+        part.iter()
+            .chain(std::iter::once(&0xff))
+            .fold(hash, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3))
+    });
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,57 @@
+//! Images attached to prompts for vision-capable providers
+
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Largest image file accepted; providers reject bigger inline images anyway
+pub const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// A base64-encoded image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    /// MIME type, e.g. `image/png`
+    pub media_type: String,
+    /// Base64 (standard alphabet) of the image bytes
+    pub data: String,
+}
+
+impl Image {
+    pub fn from_bytes(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        Self { media_type: media_type.into(), data: base64::engine::general_purpose::STANDARD.encode(bytes) }
+    }
+
+    /// Read and encode an image file, taking its type from the extension
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let media_type = media_type_for(path).ok_or_else(|| {
+            anyhow!("{}: unsupported image type (expected png, jpeg, gif or webp)", path.display())
+        })?;
+        let size = std::fs::metadata(path).with_context(|| format!("Failed to read image {}", path.display()))?.len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(anyhow!("{}: image is {} bytes; the limit is {}", path.display(), size, MAX_IMAGE_BYTES));
+        }
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read image {}", path.display()))?;
+        Ok(Self::from_bytes(media_type, &bytes))
+    }
+}
+
+/// MIME type of an image path by extension
+pub fn media_type_for(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// One part of a multi-part message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ContentPart {
+    Text { text: String },
+    Image { image: Image },
+}
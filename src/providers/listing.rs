@@ -0,0 +1,86 @@
+//! `ai-cli list-providers`: every known provider, whether it can be used and what it offers
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+use super::claude::ClaudeProvider;
+use super::codex::CodexProvider;
+use super::gemini::GeminiProvider;
+use super::{AIProvider, Capabilities, ProviderId};
+use crate::auth::AuthManager;
+use crate::pipeline::PipelineExecutor;
+
+/// One known provider
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// Registered with detected credentials and ready to use
+    pub available: bool,
+    /// How credentials were detected: `api-key`, `account`, `browser`, `cli-session`
+    pub auth_method: Option<String>,
+    /// Where they were found, e.g. `env ANTHROPIC_API_KEY`
+    pub auth_source: Option<String>,
+    /// Model requests go to (the default when the provider is unavailable)
+    pub model: Option<String>,
+    pub capabilities: Capabilities,
+}
+
+/// All known providers, in `ProviderId::ALL` order
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ProviderListing {
+    pub providers: Vec<ProviderInfo>,
+}
+
+impl ProviderListing {
+    /// Describe every known provider using detected auth and what `executor` has registered
+    pub async fn collect(auth: &AuthManager, executor: &PipelineExecutor) -> Self {
+        let mut providers = Vec::new();
+        for id in ProviderId::ALL {
+            let detected = auth.detect_auth_source(id.as_str()).await.ok();
+            let registered = executor.get_provider(id.as_str());
+            let reference = registered.clone().unwrap_or_else(|| unauthenticated(id));
+            providers.push(ProviderInfo {
+                name: id.to_string(),
+                aliases: id.aliases().iter().map(|alias| alias.to_string()).collect(),
+                available: registered.is_some(),
+                auth_method: detected.as_ref().map(|(method, _)| method.kind().to_string()),
+                auth_source: detected.as_ref().map(|(_, source)| source.to_string()),
+                model: reference.model().map(str::to_string),
+                capabilities: executor.capabilities(id.as_str()).unwrap_or_else(|| reference.capabilities()),
+            });
+        }
+        Self { providers }
+    }
+}
+
+/// A provider without credentials, only asked for its defaults
+fn unauthenticated(id: ProviderId) -> Arc<dyn AIProvider> {
+    match id {
+        ProviderId::Claude => Arc::new(ClaudeProvider::new(String::new())),
+        ProviderId::Gemini => Arc::new(GeminiProvider::new(String::new())),
+        ProviderId::Codex => Arc::new(CodexProvider::new(String::new())),
+    }
+}
+
+impl fmt::Display for ProviderListing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<8} {:<9} {:<12} {:<28} {:<6} {:<8} SOURCE", "PROVIDER", "AVAILABLE", "AUTH", "MODEL", "STREAM", "CONTEXT")?;
+        for info in &self.providers {
+            writeln!(
+                f,
+                "{:<8} {:<9} {:<12} {:<28} {:<6} {:<8} {}",
+                info.name,
+                if info.available { "yes" } else { "no" },
+                info.auth_method.as_deref().unwrap_or("none"),
+                info.model.as_deref().unwrap_or("-"),
+                if info.capabilities.supports_streaming { "yes" } else { "no" },
+                info.capabilities.max_tokens,
+                info.auth_source.as_deref().unwrap_or("-"),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! Scripted provider for tests (`testing` feature)
+//!
+//! Replies are queued with [`MockProvider::with_reply`] and
+//! [`MockProvider::with_error`]; once the queue is empty the prompt is echoed
+//! back. Tool calls queued with [`MockProvider::with_tool_call`] are made by
+//! `execute_with_tools` before it replies. Every prompt received and every
+//! tool result is recorded for assertions.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::stream;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{AIProvider, Capabilities, Context, ProviderOptions, Response, ResponseStream, ToolCall, ToolHandler, ToolSpec};
+
+/// Provider that answers from a script instead of an API
+pub struct MockProvider {
+    name: String,
+    capabilities: Capabilities,
+    replies: Mutex<VecDeque<Result<String, String>>>,
+    prompts: Mutex<Vec<String>>,
+    tool_calls: Mutex<VecDeque<ToolCall>>,
+    tool_results: Mutex<Vec<Result<String, String>>>,
+}
+
+impl MockProvider {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            capabilities: Capabilities { supports_streaming: true, supports_context: true, supports_vision: true, ..Capabilities::default() },
+            replies: Mutex::new(VecDeque::new()),
+            prompts: Mutex::new(Vec::new()),
+            tool_calls: Mutex::new(VecDeque::new()),
+            tool_results: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queue a successful reply
+    pub fn with_reply(self, content: impl Into<String>) -> Self {
+        self.replies.lock().unwrap().push_back(Ok(content.into()));
+        self
+    }
+
+    /// Queue a failed call
+    pub fn with_error(self, message: impl Into<String>) -> Self {
+        self.replies.lock().unwrap().push_back(Err(message.into()));
+        self
+    }
+
+    /// Queue a tool call made by the next `execute_with_tools`
+    pub fn with_tool_call(self, name: impl Into<String>, input: serde_json::Value) -> Self {
+        let id = format!("call_{}", self.tool_calls.lock().unwrap().len() + 1);
+        self.tool_calls.lock().unwrap().push_back(ToolCall { id, name: name.into(), input });
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Prompts received so far, oldest first
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    /// Results of the tool calls made so far, failures as `Err`
+    pub fn tool_results(&self) -> Vec<Result<String, String>> {
+        self.tool_results.lock().unwrap().clone()
+    }
+
+    fn next_reply(&self, prompt: &str) -> Result<String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        match self.replies.lock().unwrap().pop_front() {
+            Some(Ok(content)) => Ok(content),
+            Some(Err(message)) => Err(anyhow!(message)),
+            None => Ok(prompt.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for MockProvider {
+    async fn execute(&self, prompt: &str, _context: &Context) -> Result<Response> {
+        Ok(Response::new(self.next_reply(prompt)?))
+    }
+
+    async fn execute_with_tools(
+        &self,
+        prompt: &str,
+        _context: &Context,
+        _options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        let calls: Vec<ToolCall> = self.tool_calls.lock().unwrap().drain(..).collect();
+        for call in &calls {
+            if !tools.iter().any(|t| t.name == call.name) {
+                return Err(anyhow!("tool '{}' was not offered", call.name));
+            }
+            let result = handler.call(call).await.map_err(|e| format!("{:#}", e));
+            self.tool_results.lock().unwrap().push(result);
+        }
+        Ok(Response::new(self.next_reply(prompt)?).with_metadata("tool_calls", calls.len().to_string()))
+    }
+
+    async fn stream(&self, prompt: &str, _context: &Context) -> Result<ResponseStream> {
+        let content = self.next_reply(prompt)?;
+        Ok(Box::pin(stream::once(async move { Ok(content) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
@@ -1,6 +1,23 @@
+pub mod batch;
 pub mod claude;
 pub mod gemini;
 pub mod codex;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock;
+pub mod http;
+pub mod id;
+pub mod idempotency;
+pub mod image;
+pub mod listing;
+pub mod openai;
+pub mod pricing;
+pub mod probe;
+pub mod streaming;
+pub mod structured;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tokenizer;
+pub mod tools;
 
 use async_trait::async_trait;
 use std::collections::HashMap;
@@ -9,11 +26,29 @@ use futures::stream::BoxStream;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::context::Provenance;
+
+pub use id::{ProviderId, UnknownProvider};
+pub use image::{ContentPart, Image};
+pub use structured::JsonMode;
+pub use tools::{ToolCall, ToolHandler, ToolSpec, Toolset};
+
+/// Names of the providers ai-cli knows how to construct
+pub const KNOWN_PROVIDERS: &[&str] = &[ProviderId::Claude.as_str(), ProviderId::Gemini.as_str(), ProviderId::Codex.as_str()];
+
+/// Tokens spent on role markers around each message
+const MESSAGE_TOKEN_OVERHEAD: usize = 4;
+/// Tokens spent on the closing tag and separators around each file
+const FILE_TOKEN_OVERHEAD: usize = 4;
+
 /// Response from an AI provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
     pub content: String,
     pub metadata: HashMap<String, String>,
+    /// Provider output before the step's transform, when the transform changed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
 }
 
 impl Response {
@@ -22,6 +57,7 @@ impl Response {
         Self {
             content: content.into(),
             metadata: HashMap::new(),
+            raw: None,
         }
     }
 
@@ -30,6 +66,12 @@ impl Response {
         self.metadata.insert(key.into(), value.into());
         self
     }
+    
+    /// Record the token usage reported by the provider
+    pub fn with_usage(self, usage: pricing::Usage) -> Self {
+        self.with_metadata("prompt_tokens", usage.prompt_tokens.to_string())
+            .with_metadata("completion_tokens", usage.completion_tokens.to_string())
+    }
 }
 
 /// Context for AI provider requests with enhanced capabilities
@@ -40,6 +82,12 @@ pub struct Context {
     pub environment: HashMap<String, String>,
     pub metadata: HashMap<String, serde_json::Value>,
     pub file_contents: HashMap<PathBuf, String>,
+    /// Where each file in `file_contents` came from
+    #[serde(default)]
+    pub file_provenance: HashMap<PathBuf, Provenance>,
+    /// Images sent with the next user turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Image>,
     #[serde(skip)]
     pub scopes: Vec<String>,
     #[serde(skip, default = "current_time")]
@@ -58,6 +106,8 @@ impl Context {
             environment: HashMap::new(),
             metadata: HashMap::new(),
             file_contents: HashMap::new(),
+            file_provenance: HashMap::new(),
+            images: Vec::new(),
             scopes: Vec::new(),
             created_at: now,
             last_updated: now,
@@ -65,7 +115,12 @@ impl Context {
     }
 
     /// Add a message to the conversation history with timestamp update
-    pub fn add_message(&mut self, message: Message) {
+    ///
+    /// Inside a scoped context, untagged messages are tagged with the current scope.
+    pub fn add_message(&mut self, mut message: Message) {
+        if message.scope.is_none() {
+            message.scope = self.scopes.last().cloned();
+        }
         self.conversation_history.push(message);
         self.update_timestamp();
     }
@@ -96,18 +151,18 @@ impl Context {
         }
         
         // Add response to step results with enhanced metadata
-        if let Some(step_results) = self.metadata.get_mut("step_results") {
-            if let Some(results_array) = step_results.as_array_mut() {
-                let enhanced_result = json!({
-                    "content": response.content,
-                    "metadata": response.metadata,
-                    "timestamp": std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs()
-                });
-                results_array.push(enhanced_result);
-            }
+        if let Some(step_results) = self.metadata.get_mut("step_results")
+            && let Some(results_array) = step_results.as_array_mut()
+        {
+            let enhanced_result = json!({
+                "content": response.content,
+                "metadata": response.metadata,
+                "timestamp": std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            });
+            results_array.push(enhanced_result);
         }
         
         // Copy response metadata to context metadata with prefix
@@ -119,7 +174,14 @@ impl Context {
     }
     
     /// Filter context for a specific provider with security and optimization considerations
+    ///
+    /// Applies the built-in filters; see [`filter_with`](Self::filter_with) for others.
     pub fn filter_for_provider(&self, provider: &str, excluded_keys: &[&str]) -> Context {
+        self.filter_with(provider, excluded_keys, &crate::context::ContextFilters::builtin())
+    }
+    
+    /// Copy of this context with `excluded_keys` removed from its metadata and `filters` for `provider` applied
+    pub fn filter_with(&self, provider: &str, excluded_keys: &[&str], filters: &crate::context::ContextFilters) -> Context {
         let mut filtered = self.clone();
         
         // Remove excluded metadata keys for security/privacy
@@ -127,27 +189,7 @@ impl Context {
             filtered.metadata.remove(*key);
         }
         
-        // Provider-specific filtering logic
-        match provider {
-            "claude" => {
-                // Claude might need more conversation history
-                // Already includes full history
-            }
-            "gemini" => {
-                // Gemini might need truncated history for performance
-                if filtered.conversation_history.len() > 10 {
-                    let start_idx = filtered.conversation_history.len() - 10;
-                    filtered.conversation_history = filtered.conversation_history[start_idx..].to_vec();
-                }
-            }
-            "codex" => {
-                // Codex might focus more on file contents
-                filtered.metadata.insert("focus_mode".to_string(), serde_json::json!("code"));
-            }
-            _ => {
-                // Default behavior for unknown providers
-            }
-        }
+        filters.apply(provider, &mut filtered);
         
         // Add provider-specific metadata
         filtered.metadata.insert("filtered_for_provider".to_string(), serde_json::json!(provider));
@@ -178,10 +220,10 @@ impl Context {
             }
             
             // Check for overly large metadata values
-            if let Some(string_value) = value.as_str() {
-                if string_value.len() > 10_000 {
-                    return Err(anyhow::anyhow!("Metadata value too large for key: {}", key));
-                }
+            if let Some(string_value) = value.as_str()
+                && string_value.len() > 10_000
+            {
+                return Err(anyhow::anyhow!("Metadata value too large for key: {}", key));
             }
         }
         
@@ -210,48 +252,50 @@ impl Context {
         Ok(())
     }
     
-    /// Sophisticated token estimation using multiple heuristics
+    /// Rough token count for when the provider is not known; see `count_tokens`
     pub fn estimate_tokens(&self) -> usize {
-        let mut count = 0;
-        
-        // Estimate tokens from conversation history
-        for message in &self.conversation_history {
-            // More accurate token estimation: ~1.3 tokens per word on average
-            let word_count = message.content.split_whitespace().count();
-            count += (word_count as f64 * 1.3) as usize;
-            
-            // Add overhead for role and formatting
-            count += 5; // Role overhead
-        }
-        
-        // Estimate tokens from file contents
-        for (_, content) in &self.file_contents {
-            let word_count = content.split_whitespace().count();
-            count += (word_count as f64 * 1.3) as usize;
-            count += 10; // File metadata overhead
-        }
-        
-        // Estimate tokens from metadata
-        for (key, value) in &self.metadata {
-            count += key.len() / 4; // Key tokens
-            if let Some(str_val) = value.as_str() {
-                let word_count = str_val.split_whitespace().count();
-                count += (word_count as f64 * 1.3) as usize;
-            } else {
-                // JSON structure overhead
-                count += 5;
-            }
+        self.count_tokens(&tokenizer::ApproxTokenizer::default())
+    }
+
+    /// Tokens this context adds to a request, counted with a provider's tokenizer
+    ///
+    /// Includes the per-message and per-file framing that providers wrap around content.
+    pub fn count_tokens(&self, tokenizer: &dyn tokenizer::Tokenizer) -> usize {
+        let messages: usize = self.conversation_history
+            .iter()
+            .map(|message| tokenizer.count(&message.content) + MESSAGE_TOKEN_OVERHEAD)
+            .sum();
+        let files: usize = self.file_contents
+            .iter()
+            .map(|(path, content)| {
+                tokenizer.count(&format!("<file path=\"{}\">\n", path.display())) + tokenizer.count(content) + FILE_TOKEN_OVERHEAD
+            })
+            .sum();
+        let metadata: usize = self.metadata
+            .iter()
+            .map(|(key, value)| tokenizer.count(key) + tokenizer.count(&value.to_string()))
+            .sum();
+        let environment: usize = self.environment
+            .iter()
+            .map(|(key, value)| tokenizer.count(key) + tokenizer.count(value))
+            .sum();
+        messages + files + metadata + environment
+    }
+
+    /// Drop the oldest non-system messages until the context fits in `budget` tokens
+    ///
+    /// Files are left alone; returns how many messages were dropped.
+    pub fn truncate_to_token_budget(&mut self, tokenizer: &dyn tokenizer::Tokenizer, budget: usize) -> usize {
+        let mut dropped = 0;
+        while self.count_tokens(tokenizer) > budget {
+            let Some(index) = self.conversation_history.iter().position(|m| m.role != MessageRole::System) else { break };
+            self.conversation_history.remove(index);
+            dropped += 1;
         }
-        
-        // Environment variables
-        for (key, value) in &self.environment {
-            count += (key.len() + value.len()) / 4; // Rough character to token ratio
+        if dropped > 0 {
+            self.update_timestamp();
         }
-        
-        // Base context overhead
-        count += 50;
-        
-        count
+        dropped
     }
     
     /// Truncate conversation history to limit
@@ -266,18 +310,89 @@ impl Context {
         // Minimal implementation - in real use would check timestamps
     }
     
-    /// Create a scoped context
-    pub fn create_scoped(&self, _scope_name: &str) -> Context {
-        self.clone()
+    /// Innermost active scope, if this is a scoped context
+    pub fn current_scope(&self) -> Option<&str> {
+        self.scopes.last().map(String::as_str)
     }
     
-    /// Merge scoped context back
+    /// Fork an isolated copy for one branch of work
+    ///
+    /// Changes to the copy do not affect this context until merged back. Nested
+    /// scopes are named `outer/inner`, and messages added to the copy are tagged
+    /// with its scope name.
+    pub fn create_scoped(&self, scope_name: &str) -> Context {
+        let mut scoped = self.clone();
+        let name = match self.current_scope() {
+            Some(outer) => format!("{}/{}", outer, scope_name),
+            None => scope_name.to_string(),
+        };
+        scoped.scopes.push(name);
+        scoped
+    }
+    
+    /// Merge everything a scoped context added: its messages plus file and metadata changes
     pub fn merge_scope(&mut self, scoped_context: Context) {
-        for message in scoped_context.conversation_history {
-            if !self.conversation_history.contains(&message) {
+        self.merge_scope_where(scoped_context, |_| true);
+    }
+    
+    /// Merge a scoped context, keeping only messages whose scope passes `keep`
+    ///
+    /// Messages from the scoped context's own scope and any nested scope are
+    /// candidates; the rest of its history came from this context and is not
+    /// repeated. Merged messages are re-tagged with this context's scope. Files,
+    /// metadata and environment set in the scope overwrite this context's values.
+    pub fn merge_scope_where(&mut self, scoped_context: Context, keep: impl Fn(&str) -> bool) {
+        let Some(scope) = scoped_context.current_scope().map(str::to_string) else {
+            // Not a scoped context: nothing is known to be new, so only add unseen messages
+            for message in scoped_context.conversation_history {
+                if !self.conversation_history.contains(&message) {
+                    self.add_message(message);
+                }
+            }
+            return;
+        };
+        for mut message in scoped_context.conversation_history {
+            let in_scope = message.scope.as_deref().is_some_and(|s| is_within_scope(s, &scope));
+            if in_scope && message.scope.as_deref().is_some_and(&keep) {
+                message.scope = self.scopes.last().cloned();
                 self.conversation_history.push(message);
             }
         }
+        for (path, content) in scoped_context.file_contents {
+            if self.file_contents.get(&path) != Some(&content) {
+                match scoped_context.file_provenance.get(&path) {
+                    Some(provenance) => self.add_file_with_provenance(path, content, provenance.clone()),
+                    None => self.add_file_with_content(path, content),
+                }
+            }
+        }
+        for (key, value) in scoped_context.metadata {
+            self.metadata.insert(key, value);
+        }
+        for (key, value) in scoped_context.environment {
+            self.environment.insert(key, value);
+        }
+        self.update_timestamp();
+    }
+    
+    /// Messages tagged with a scope or one nested inside it
+    pub fn messages_in_scope(&self, scope_name: &str) -> Vec<&Message> {
+        self.conversation_history
+            .iter()
+            .filter(|m| m.scope.as_deref().is_some_and(|s| is_within_scope(s, scope_name)))
+            .collect()
+    }
+    
+    /// Drop the messages of a scope and its nested scopes, returning how many were removed
+    pub fn discard_scope(&mut self, scope_name: &str) -> usize {
+        let before = self.conversation_history.len();
+        self.conversation_history
+            .retain(|m| !m.scope.as_deref().is_some_and(|s| is_within_scope(s, scope_name)));
+        let removed = before - self.conversation_history.len();
+        if removed > 0 {
+            self.update_timestamp();
+        }
+        removed
     }
     
     /// Add file with content
@@ -291,10 +406,95 @@ impl Context {
         self.file_contents.get(path)
     }
     
+    /// Add file with content, recording where it came from
+    pub fn add_file_with_provenance(&mut self, path: PathBuf, content: String, provenance: Provenance) {
+        self.file_provenance.insert(path.clone(), provenance);
+        self.add_file_with_content(path, content);
+    }
+    
     /// Remove file
     pub fn remove_file(&mut self, path: &PathBuf) {
         self.current_files.retain(|p| p != path);
         self.file_contents.remove(path);
+        self.file_provenance.remove(path);
+    }
+    
+    /// System instructions carried by the context: its System messages, in order
+    pub fn system_instructions(&self) -> Option<String> {
+        let parts: Vec<&str> = self.conversation_history
+            .iter()
+            .filter(|m| m.role == MessageRole::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+    
+    /// Prior user/assistant messages followed by a final user turn, as providers expect them
+    ///
+    /// Consecutive messages from the same role are merged so turns alternate, and
+    /// assistant messages before the first user message are left out because
+    /// providers require the conversation to open with the user.
+    /// The context's images go with the final user turn.
+    pub fn request_turns(&self, user: String) -> Vec<Message> {
+        let mut turns: Vec<Message> = Vec::new();
+        let history = self.conversation_history
+            .iter()
+            .filter(|m| m.role != MessageRole::System)
+            .skip_while(|m| m.role != MessageRole::User)
+            .map(|m| Message::new(m.role.clone(), m.content.clone()).with_images(m.images.clone()))
+            .chain(std::iter::once(Message::new(MessageRole::User, user).with_images(self.images.clone())));
+        for message in history {
+            match turns.last_mut() {
+                Some(last) if last.role == message.role => {
+                    last.content = format!("{}\n\n{}", last.content, message.content);
+                    last.images.extend(message.images);
+                }
+                _ => turns.push(message),
+            }
+        }
+        turns
+    }
+    
+    /// Attached file contents as one block for the user turn, sorted by path
+    pub fn render_files(&self) -> Option<String> {
+        let mut files: Vec<(&PathBuf, &String)> = self.file_contents.iter().collect();
+        if files.is_empty() {
+            return None;
+        }
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let blocks: Vec<String> = files
+            .into_iter()
+            .map(|(path, content)| format!("<file path=\"{}\">\n{}\n</file>", path.display(), content))
+            .collect();
+        Some(blocks.join("\n"))
+    }
+    
+    /// Describe every message and file in the context along with its provenance
+    pub fn explain(&self) -> String {
+        let mut lines = Vec::new();
+        lines.push(format!("Messages ({}):", self.conversation_history.len()));
+        for (index, message) in self.conversation_history.iter().enumerate() {
+            let source = message.provenance.as_ref()
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            lines.push(format!(
+                "  [{}] {:?} ({} chars) <- {}",
+                index + 1, message.role, message.content.chars().count(), source
+            ));
+        }
+        
+        let mut files: Vec<&PathBuf> = self.file_contents.keys().collect();
+        files.sort();
+        lines.push(format!("Files ({}):", files.len()));
+        for path in files {
+            let source = self.file_provenance.get(path)
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            let size = self.file_contents.get(path).map(|c| c.len()).unwrap_or(0);
+            lines.push(format!("  {} ({} bytes) <- {}", path.display(), size, source));
+        }
+        
+        lines.join("\n")
     }
     
     /// Inherit environment from another context
@@ -306,31 +506,108 @@ impl Context {
         }
     }
     
-    /// Compute diff with another context
+    /// Compute the changes that turn this context into `other`
+    ///
+    /// Messages are compared by position: the common leading and trailing
+    /// messages are kept and the differing run in between is reported as
+    /// removed and added, so an edited message shows up as both.
     pub fn diff(&self, other: &Context) -> ContextDiff {
-        let mut added_messages = Vec::new();
+        let old = &self.conversation_history;
+        let new = &other.conversation_history;
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
         
-        if other.conversation_history.len() > self.conversation_history.len() {
-            let start_index = self.conversation_history.len();
-            added_messages = other.conversation_history[start_index..].to_vec();
+        let mut metadata_changes = HashMap::new();
+        for (key, value) in &other.metadata {
+            if self.metadata.get(key) != Some(value) {
+                metadata_changes.insert(key.clone(), value.clone());
+            }
         }
+        let mut removed_metadata: Vec<String> = self.metadata
+            .keys()
+            .filter(|key| !other.metadata.contains_key(*key))
+            .cloned()
+            .collect();
+        removed_metadata.sort();
+        
+        let mut changed_files = HashMap::new();
+        let mut file_provenance = HashMap::new();
+        for (path, content) in &other.file_contents {
+            let provenance = other.file_provenance.get(path);
+            if self.file_contents.get(path) != Some(content) || self.file_provenance.get(path) != provenance {
+                changed_files.insert(path.clone(), content.clone());
+                if let Some(provenance) = provenance {
+                    file_provenance.insert(path.clone(), provenance.clone());
+                }
+            }
+        }
+        let mut removed_files: Vec<PathBuf> = self.file_contents
+            .keys()
+            .filter(|path| !other.file_contents.contains_key(*path))
+            .cloned()
+            .collect();
+        removed_files.sort();
         
         ContextDiff {
-            added_messages,
-            removed_messages: Vec::new(),
-            metadata_changes: HashMap::new(),
+            message_offset: prefix,
+            added_messages: new[prefix..new.len() - suffix].to_vec(),
+            removed_messages: old[prefix..old.len() - suffix].to_vec(),
+            metadata_changes,
+            removed_metadata,
+            changed_files,
+            file_provenance,
+            removed_files,
         }
     }
     
     /// Apply diff to context
+    ///
+    /// When the removed messages are still at `message_offset` they are replaced
+    /// in place; otherwise each is removed where it is found and the added
+    /// messages take the place of the first one (or go at the offset, clamped
+    /// to the end of the history, when nothing was removed).
     pub fn apply_diff(&mut self, diff: ContextDiff) {
-        for message in diff.added_messages {
-            self.add_message(message);
+        let offset = diff.message_offset;
+        let end = offset + diff.removed_messages.len();
+        if self.conversation_history.get(offset..end) == Some(diff.removed_messages.as_slice()) {
+            self.conversation_history.splice(offset..end, diff.added_messages);
+        } else {
+            let mut at = None;
+            for removed in &diff.removed_messages {
+                if let Some(index) = self.conversation_history.iter().position(|m| m == removed) {
+                    self.conversation_history.remove(index);
+                    at = Some(at.map_or(index, |at: usize| at.min(index)));
+                }
+            }
+            let at = at.unwrap_or(offset).min(self.conversation_history.len());
+            self.conversation_history.splice(at..at, diff.added_messages);
         }
         
         for (key, value) in diff.metadata_changes {
             self.metadata.insert(key, value);
         }
+        for key in &diff.removed_metadata {
+            self.metadata.remove(key);
+        }
+        
+        for (path, content) in diff.changed_files {
+            match diff.file_provenance.get(&path) {
+                Some(provenance) => self.add_file_with_provenance(path, content, provenance.clone()),
+                None => {
+                    self.file_provenance.remove(&path);
+                    self.add_file_with_content(path, content);
+                }
+            }
+        }
+        for path in &diff.removed_files {
+            self.remove_file(path);
+        }
+        self.update_timestamp();
     }
 }
 
@@ -338,16 +615,30 @@ impl Default for Context {
     fn default() -> Self { Self::new() }
 }
 
+/// Whether `scope` is `outer` or nested inside it (`outer/...`)
+fn is_within_scope(scope: &str, outer: &str) -> bool {
+    scope == outer || scope.strip_prefix(outer).is_some_and(|rest| rest.starts_with('/'))
+}
+
 fn current_time() -> std::time::SystemTime {
     std::time::SystemTime::now()
 }
 
 /// Context diff for tracking changes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ContextDiff {
+    /// Index in the history where `removed_messages` start and `added_messages` go
+    pub message_offset: usize,
     pub added_messages: Vec<Message>,
     pub removed_messages: Vec<Message>,
+    /// Metadata keys that were added or changed, with their new values
     pub metadata_changes: HashMap<String, serde_json::Value>,
+    pub removed_metadata: Vec<String>,
+    /// Files that were added or whose content changed, with the new content
+    pub changed_files: HashMap<PathBuf, String>,
+    /// Provenance of the changed files, where known
+    pub file_provenance: HashMap<PathBuf, Provenance>,
+    pub removed_files: Vec<PathBuf>,
 }
 
 impl ContextDiff {
@@ -356,6 +647,9 @@ impl ContextDiff {
         self.added_messages.is_empty() 
             && self.removed_messages.is_empty() 
             && self.metadata_changes.is_empty()
+            && self.removed_metadata.is_empty()
+            && self.changed_files.is_empty()
+            && self.removed_files.is_empty()
     }
 }
 
@@ -364,6 +658,14 @@ impl ContextDiff {
 pub struct Message {
     pub role: MessageRole,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Scope the message was added in, for scoped contexts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Images sent along with the text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Image>,
 }
 
 impl Message {
@@ -372,8 +674,38 @@ impl Message {
         Self {
             role,
             content: content.into(),
+            provenance: None,
+            scope: None,
+            images: Vec::new(),
         }
     }
+    
+    /// Record where this message came from
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+    
+    /// Tag this message with a scope instead of the context's current one
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Attach images to the message
+    pub fn with_images(mut self, images: Vec<Image>) -> Self {
+        self.images.extend(images);
+        self
+    }
+
+    /// Images first, then the text, as vision models prefer
+    pub fn parts(&self) -> Vec<ContentPart> {
+        self.images
+            .iter()
+            .map(|image| ContentPart::Image { image: image.clone() })
+            .chain(std::iter::once(ContentPart::Text { text: self.content.clone() }))
+            .collect()
+    }
 }
 
 /// Role of a message sender
@@ -386,11 +718,16 @@ pub enum MessageRole {
 }
 
 /// Capabilities of an AI provider
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Capabilities {
     pub supports_streaming: bool,
     pub supports_context: bool,
     pub max_tokens: usize,
+    #[serde(default)]
+    pub supports_json_mode: bool,
+    /// Accepts images in messages
+    #[serde(default)]
+    pub supports_vision: bool,
 }
 
 impl Default for Capabilities {
@@ -399,10 +736,26 @@ impl Default for Capabilities {
             supports_streaming: false,
             supports_context: false,
             max_tokens: 4096,
+            supports_json_mode: false,
+            supports_vision: false,
         }
     }
 }
 
+/// Error returned when a provider rejects the credentials (HTTP 401)
+pub use crate::error::AuthError as UnauthorizedError;
+
+/// Result of a live credential check against a provider API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuthValidation {
+    /// Account, project or organization the credentials belong to, when reported
+    pub account: Option<String>,
+    /// Models the credentials can access
+    pub models: Vec<String>,
+    /// Whether the provider's configured model is among the accessible ones
+    pub model_accessible: Option<bool>,
+}
+
 /// Stream of response chunks
 pub type ResponseStream<'a> = BoxStream<'a, Result<String>>;
 
@@ -415,9 +768,267 @@ pub trait AIProvider: Send + Sync {
     /// Stream a response for the given prompt
     async fn stream(&self, prompt: &str, context: &Context) -> Result<ResponseStream>;
     
+    /// Execute with generation parameters layered over the provider's defaults
+    ///
+    /// Providers without tunable parameters ignore `options`.
+    async fn execute_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<Response> {
+        let _ = options;
+        self.execute(prompt, context).await
+    }
+    
+    /// Stream with generation parameters layered over the provider's defaults
+    async fn stream_with_options(&self, prompt: &str, context: &Context, options: &ProviderOptions) -> Result<ResponseStream> {
+        let _ = options;
+        self.stream(prompt, context).await
+    }
+    
+    /// Execute letting the model call `tools`, each call run by `handler`
+    ///
+    /// The model sees every result and continues until it answers without a tool
+    /// call. Providers without tool calling refuse.
+    async fn execute_with_tools(
+        &self,
+        prompt: &str,
+        context: &Context,
+        options: &ProviderOptions,
+        tools: &[ToolSpec],
+        handler: &dyn ToolHandler,
+    ) -> Result<Response> {
+        let _ = (prompt, context, options, tools, handler);
+        Err(anyhow::anyhow!("{} does not support tool calling", self.name()))
+    }
+    
     /// Get the capabilities of this provider
     fn capabilities(&self) -> Capabilities;
     
     /// Get the name of this provider
     fn name(&self) -> &str;
+
+    /// Get the model this provider sends requests to, if it has one
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether requests honor `ProviderOptions::seed`; others ignore it
+    fn supports_seed(&self) -> bool {
+        false
+    }
+
+    /// Get the base URL of the HTTP API this provider calls, if it calls one
+    fn endpoint(&self) -> Option<&str> {
+        None
+    }
+
+    /// Query the provider API for its real capabilities
+    ///
+    /// Defaults to the static `capabilities()` for providers without a probe.
+    async fn probe(&self) -> Result<Capabilities> {
+        Ok(self.capabilities())
+    }
+
+    /// Renew credentials after an `UnauthorizedError`; returns whether a retry is worthwhile
+    async fn reauthenticate(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Perform a minimal authenticated API round-trip to prove the credentials work
+    async fn validate_auth(&self) -> Result<AuthValidation> {
+        Err(anyhow::anyhow!("{} does not support live auth validation", self.name()))
+    }
+
+    /// Tokenizer used to count this provider's prompt tokens
+    fn tokenizer(&self) -> std::sync::Arc<dyn tokenizer::Tokenizer> {
+        tokenizer::tokenizer_for(self.name())
+    }
+
+    /// Query the provider's model listing API
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Err(anyhow::anyhow!("{} does not support listing models", self.name()))
+    }
+
+    /// Store parts of a run's context provider-side (e.g. upload large files), noting handles in its metadata
+    async fn prepare_context(&self, _context: &mut Context) -> Result<()> {
+        Ok(())
+    }
+
+    /// Delete whatever `prepare_context` stored, once the run is over
+    async fn release_context(&self, _context: &mut Context) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Generation parameters; unset fields fall back to the next layer down
+///
+/// Layers, lowest first: provider config, CLI flags, pipeline step options.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderOptions {
+    /// Model to call instead of the provider's configured one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// System prompt, sent through the provider's native system mechanism
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    /// Stop sequences; a non-empty list replaces the lower layer's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Require a JSON reply matching a schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<JsonMode>,
+    /// Sampling seed, for providers that can repeat a generation given the same one; run-wide only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Key shared by every retry of one request, sent as `Idempotency-Key` where
+    /// supported; set per request by the executor, never configured
+    #[serde(skip)]
+    pub idempotency_key: Option<String>,
+}
+
+/// Seed used by `--deterministic` when `--seed` is not given
+pub const DEFAULT_SEED: u64 = 42;
+
+impl ProviderOptions {
+    /// Check if no parameter is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Layer `overrides` on top of these options
+    pub fn merged(&self, overrides: &ProviderOptions) -> ProviderOptions {
+        ProviderOptions {
+            model: overrides.model.clone().or_else(|| self.model.clone()),
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            system: overrides.system.clone().or_else(|| self.system.clone()),
+            stop: if overrides.stop.is_empty() { self.stop.clone() } else { overrides.stop.clone() },
+            json: overrides.json.clone().or_else(|| self.json.clone()),
+            seed: overrides.seed.or(self.seed),
+            idempotency_key: overrides.idempotency_key.clone().or_else(|| self.idempotency_key.clone()),
+        }
+    }
+
+    /// Set a parameter from its name and textual value (`stop` takes `|`-separated sequences)
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = |e: &dyn std::fmt::Display| anyhow::anyhow!("Invalid value '{}' for {}: {}", value, key, e);
+        match key {
+            "model" => self.model = Some(value.to_string()),
+            "temperature" => self.temperature = Some(value.parse().map_err(|e| invalid(&e))?),
+            "top_p" => self.top_p = Some(value.parse().map_err(|e| invalid(&e))?),
+            "max_tokens" => self.max_tokens = Some(value.parse().map_err(|e| invalid(&e))?),
+            "system" => self.system = Some(value.to_string()),
+            "stop" => self.stop = value.split('|').map(str::to_string).collect(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown option '{}'. Valid options are: model, temperature, top_p, max_tokens, system, stop",
+                    key
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check parameters are within the ranges providers accept
+    pub fn validate(&self) -> Result<()> {
+        if let Some(t) = self.temperature
+            && !(0.0..=2.0).contains(&t)
+        {
+            return Err(anyhow::anyhow!("temperature must be between 0 and 2, got {}", t));
+        }
+        if let Some(p) = self.top_p
+            && !(0.0..=1.0).contains(&p)
+        {
+            return Err(anyhow::anyhow!("top_p must be between 0 and 1, got {}", p));
+        }
+        if self.max_tokens == Some(0) {
+            return Err(anyhow::anyhow!("max_tokens must be greater than 0"));
+        }
+        if let Some(json) = &self.json {
+            json.check_schema()?;
+        }
+        Ok(())
+    }
+
+    /// Parse comma-separated `key=value` assignments, as used in chain step options
+    pub fn parse_assignments(input: &str) -> Result<Self> {
+        let mut options = Self::default();
+        for assignment in input.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            let (key, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid option '{}' (expected key=value)", assignment))?;
+            options.set(key.trim(), value.trim())?;
+        }
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Render as comma-separated `key=value` assignments
+    pub fn to_assignments(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(m) = &self.model { parts.push(format!("model={}", m)); }
+        if let Some(t) = self.temperature { parts.push(format!("temperature={}", t)); }
+        if let Some(p) = self.top_p { parts.push(format!("top_p={}", p)); }
+        if let Some(m) = self.max_tokens { parts.push(format!("max_tokens={}", m)); }
+        if let Some(s) = &self.system { parts.push(format!("system={}", s)); }
+        if !self.stop.is_empty() { parts.push(format!("stop={}", self.stop.join("|"))); }
+        parts.join(",")
+    }
+}
+
+/// Split a request into the native system prompt and the user turn
+///
+/// The system prompt is the `system` option followed by the context's System
+/// messages; attached files precede the prompt in the user turn.
+pub fn compose_request(prompt: &str, context: &Context, options: &ProviderOptions) -> (Option<String>, String) {
+    let system = match (options.system.as_deref(), context.system_instructions()) {
+        (Some(system), Some(extra)) => Some(format!("{}\n\n{}", system, extra)),
+        (Some(system), None) => Some(system.to_string()),
+        (None, extra) => extra,
+    };
+    let user = match context.render_files() {
+        Some(files) => format!("{}\n\n{}", files, prompt),
+        None => prompt.to_string(),
+    };
+    (system, user)
+}
+
+/// A model reported by a provider's listing API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Input context window in tokens, when reported
+    #[serde(default)]
+    pub context_window: Option<usize>,
+    #[serde(default)]
+    pub max_output_tokens: Option<usize>,
+}
+
+/// Check that a requested model is among the listed ones, suggesting close matches otherwise
+pub fn check_model(models: &[ModelInfo], requested: &str) -> Result<()> {
+    if models.iter().any(|m| m.id == requested) {
+        return Ok(());
+    }
+    let needle = requested.to_lowercase();
+    let family = needle.split(['-', '.']).next().unwrap_or_default();
+    let suggestions: Vec<&str> = models
+        .iter()
+        .map(|m| m.id.as_str())
+        .filter(|id| {
+            let id = id.to_lowercase();
+            id.contains(&needle) || needle.contains(&id) || (!family.is_empty() && id.starts_with(family))
+        })
+        .take(5)
+        .collect();
+    if suggestions.is_empty() {
+        Err(anyhow::anyhow!("Unknown model '{}'", requested))
+    } else {
+        Err(anyhow::anyhow!("Unknown model '{}'. Did you mean: {}", requested, suggestions.join(", ")))
+    }
 }
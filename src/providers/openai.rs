@@ -0,0 +1,298 @@
+//! OpenAI embeddings and batch endpoints
+//!
+//! Chat goes through the `codex` provider ([`super::codex`]); this covers
+//! `/v1/embeddings` for `ai-cli embed` and `ai-cli index --embedder openai`,
+//! and `/v1/batches` (chat completions) for `ai-cli batch`.
+
+use super::batch::{BatchApi, BatchOutput, BatchRequest, BatchState, BatchStatus};
+use super::idempotency::{IDEMPOTENCY_HEADER, content_hash};
+use super::{MessageRole, ProviderOptions, compose_request, http};
+use crate::context::Embedder;
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// OpenAI API root
+pub const API_BASE: &str = "https://api.openai.com";
+/// Model used when OPENAI_EMBEDDING_MODEL is not set
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+/// Chat model batches use when OPENAI_MODEL is not set
+pub const DEFAULT_CHAT_MODEL: &str = "gpt-4o-mini";
+/// Endpoint every request of a batch goes to
+const BATCH_ENDPOINT: &str = "/v1/chat/completions";
+
+/// Embeds text with OpenAI's embeddings API
+pub struct OpenAIEmbedder {
+    api_key: String,
+    model: String,
+    base_url: String,
+    transport: Arc<dyn http::Transport>,
+}
+
+impl OpenAIEmbedder {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: std::env::var("OPENAI_EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string()),
+            base_url: API_BASE.to_string(),
+            transport: http::default_transport(),
+        }
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Use a different API root, e.g. an OpenAI-compatible gateway
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the network
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        #[derive(Deserialize)]
+        struct Embedding { index: usize, embedding: Vec<f32> }
+        #[derive(Deserialize)]
+        struct EmbedResponse { #[serde(default)] data: Vec<Embedding> }
+
+        let request = http::shared_client()
+            .post(format!("{}/v1/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({ "model": self.model, "input": texts }));
+        let resp = http::send_via(self.transport.as_ref(), "openai", request)
+            .await
+            .with_context(|| "Failed to reach OpenAI embeddings API")?;
+
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("openai", "OpenAI embeddings error", resp).await);
+        }
+
+        let mut parsed: EmbedResponse = resp.json().await.with_context(|| "Failed to parse OpenAI embeddings")?;
+        parsed.data.sort_by_key(|e| e.index);
+        Ok(parsed.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+/// Runs chat completions through OpenAI's Batch API
+pub struct OpenAIBatch {
+    api_key: String,
+    model: String,
+    base_url: String,
+    transport: Arc<dyn http::Transport>,
+}
+
+impl OpenAIBatch {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: std::env::var("OPENAI_MODEL").unwrap_or_else(|_| DEFAULT_CHAT_MODEL.to_string()),
+            base_url: API_BASE.to_string(),
+            transport: http::default_transport(),
+        }
+    }
+
+    /// Model for requests whose options name none
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Use a different API root, e.g. an OpenAI-compatible gateway
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Send requests through `transport` instead of the network
+    pub fn with_transport(mut self, transport: Arc<dyn http::Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Chat completions body for one request of a batch
+    fn chat_body(&self, request: &BatchRequest) -> serde_json::Value {
+        let (system, user) = compose_request(&request.prompt, &request.context, &request.options);
+        let messages: Vec<serde_json::Value> = system
+            .map(|system| serde_json::json!({ "role": "system", "content": system }))
+            .into_iter()
+            .chain(request.context.request_turns(user).iter().map(|m| {
+                let role = if m.role == MessageRole::Assistant { "assistant" } else { "user" };
+                serde_json::json!({ "role": role, "content": m.content })
+            }))
+            .collect();
+        let ProviderOptions { model, temperature, top_p, max_tokens, stop, seed, .. } = &request.options;
+        let mut body = serde_json::json!({ "model": model.as_deref().unwrap_or(&self.model), "messages": messages });
+        if let Some(t) = temperature { body["temperature"] = (*t).into(); }
+        if let Some(p) = top_p { body["top_p"] = (*p).into(); }
+        if let Some(m) = max_tokens { body["max_tokens"] = (*m).into(); }
+        if !stop.is_empty() { body["stop"] = stop.clone().into(); }
+        if let Some(seed) = seed { body["seed"] = (*seed).into(); }
+        body
+    }
+
+    /// Send an authenticated request, failing on error statuses
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let resp = http::send_via(self.transport.as_ref(), "openai", request.bearer_auth(&self.api_key))
+            .await
+            .with_context(|| "Failed to reach OpenAI API")?;
+        if !resp.status().is_success() {
+            return Err(http::error_for_status("openai", "OpenAI batch error", resp).await);
+        }
+        Ok(resp)
+    }
+
+    async fn batch(&self, id: &str) -> Result<Batch> {
+        let resp = self.send(http::shared_client().get(format!("{}/v1/batches/{}", self.base_url, id))).await?;
+        resp.json().await.with_context(|| "Failed to parse OpenAI batch")
+    }
+}
+
+/// A Batch API batch
+#[derive(Deserialize)]
+struct Batch {
+    id: String,
+    status: String,
+    #[serde(default)]
+    request_counts: Option<RequestCounts>,
+    #[serde(default)]
+    output_file_id: Option<String>,
+    #[serde(default)]
+    error_file_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RequestCounts {
+    #[serde(default)]
+    total: usize,
+    #[serde(default)]
+    completed: usize,
+    #[serde(default)]
+    failed: usize,
+}
+
+#[async_trait]
+impl BatchApi for OpenAIBatch {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn submit(&self, requests: &[BatchRequest]) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Created { id: String }
+
+        let mut lines = String::new();
+        for request in requests {
+            let line = serde_json::json!({
+                "custom_id": request.custom_id,
+                "method": "POST",
+                "url": BATCH_ENDPOINT,
+                "body": self.chat_body(request),
+            });
+            lines.push_str(&line.to_string());
+            lines.push('\n');
+        }
+
+        // Resubmitting the same requests, e.g. after a dropped response, returns the first batch
+        let key = format!("ai-cli-batch-{}", content_hash(&[lines.as_bytes()]));
+
+        // The input file goes up as multipart form data with `purpose=batch`
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let boundary = format!("ai-cli-{:x}", nanos);
+        let form = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"batch.jsonl\"\r\n\
+             Content-Type: application/jsonl\r\n\r\n{lines}\r\n--{b}--\r\n",
+            b = boundary,
+            lines = lines,
+        );
+        let upload = http::shared_client()
+            .post(format!("{}/v1/files", self.base_url))
+            .header(reqwest::header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .header(IDEMPOTENCY_HEADER, format!("{}-file", key))
+            .body(form);
+        let file: Created = self.send(upload).await?.json().await.with_context(|| "Failed to parse OpenAI file upload")?;
+
+        let create = http::shared_client().post(format!("{}/v1/batches", self.base_url)).header(IDEMPOTENCY_HEADER, &key).json(&serde_json::json!({
+            "input_file_id": file.id,
+            "endpoint": BATCH_ENDPOINT,
+            "completion_window": "24h",
+        }));
+        let batch: Created = self.send(create).await?.json().await.with_context(|| "Failed to parse OpenAI batch")?;
+        Ok(batch.id)
+    }
+
+    async fn status(&self, id: &str) -> Result<BatchStatus> {
+        let batch = self.batch(id).await?;
+        let state = match batch.status.as_str() {
+            "completed" => BatchState::Ended,
+            "failed" => BatchState::Failed,
+            "expired" => BatchState::Expired,
+            "cancelled" => BatchState::Canceled,
+            _ => BatchState::InProgress,
+        };
+        let counts = batch.request_counts.unwrap_or(RequestCounts { total: 0, completed: 0, failed: 0 });
+        Ok(BatchStatus { id: batch.id, state, total: counts.total, succeeded: counts.completed, failed: counts.failed })
+    }
+
+    async fn results(&self, id: &str) -> Result<Vec<BatchOutput>> {
+        #[derive(Deserialize)]
+        struct Message { #[serde(default)] content: Option<String> }
+        #[derive(Deserialize)]
+        struct Choice { message: Message }
+        #[derive(Deserialize)]
+        struct Body { #[serde(default)] choices: Vec<Choice> }
+        #[derive(Deserialize)]
+        struct Reply { status_code: u16, body: serde_json::Value }
+        #[derive(Deserialize)]
+        struct Line {
+            custom_id: String,
+            #[serde(default)]
+            response: Option<Reply>,
+            #[serde(default)]
+            error: Option<serde_json::Value>,
+        }
+
+        let batch = self.batch(id).await?;
+        if batch.output_file_id.is_none() && batch.error_file_id.is_none() {
+            return Err(anyhow!("Batch {} has no results yet (status: {})", id, batch.status));
+        }
+        let mut outputs = Vec::new();
+        // Failed requests are reported in a separate error file
+        for file in [batch.output_file_id, batch.error_file_id].into_iter().flatten() {
+            let url = format!("{}/v1/files/{}/content", self.base_url, file);
+            let text = self.send(http::shared_client().get(url)).await?.text().await?;
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                let line: Line = serde_json::from_str(line).with_context(|| "Failed to parse OpenAI batch result")?;
+                let result = match (line.response, line.error) {
+                    (Some(reply), None) if reply.status_code < 300 => serde_json::from_value::<Body>(reply.body)
+                        .ok()
+                        .and_then(|body| body.choices.into_iter().next())
+                        .and_then(|choice| choice.message.content)
+                        .ok_or_else(|| "reply has no content".to_string()),
+                    (Some(reply), None) => Err(reply.body["error"]["message"]
+                        .as_str()
+                        .map_or_else(|| format!("status {}", reply.status_code), str::to_string)),
+                    (_, Some(error)) => Err(error["message"].as_str().map_or_else(|| error.to_string(), str::to_string)),
+                    (None, None) => Err("no response".to_string()),
+                };
+                outputs.push(BatchOutput { custom_id: line.custom_id, result });
+            }
+        }
+        Ok(outputs)
+    }
+}
@@ -0,0 +1,192 @@
+//! Token usage and cost accounting
+//!
+//! Providers report token usage in response metadata (`prompt_tokens`,
+//! `completion_tokens`, `model`). The executor prices it with a [`PricingTable`]
+//! and stores the result as `cost_usd`. Built-in prices are public list prices
+//! in USD per million tokens; `[pricing."<model prefix>"]` in config overrides them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Response;
+
+/// Tokens consumed by one or more requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn new(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self { prompt_tokens, completion_tokens }
+    }
+
+    /// Usage recorded in a response's metadata, if the provider reported any
+    pub fn from_response(response: &Response) -> Option<Self> {
+        let get = |key: &str| response.metadata.get(key).and_then(|v| v.parse::<u64>().ok());
+        Some(Self { prompt_tokens: get("prompt_tokens")?, completion_tokens: get("completion_tokens")? })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.input + usage.completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// Built-in prices keyed by model id prefix
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-haiku-4", 1.0, 5.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("gemini-2.5-pro", 1.25, 10.0),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-flash-lite", 0.10, 0.40),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-pro", 1.25, 5.0),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.60),
+];
+
+/// Model prices looked up by the longest matching model id prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PricingTable {
+    /// Table with no prices
+    pub fn empty() -> Self {
+        Self { prices: HashMap::new() }
+    }
+
+    /// Built-in list prices
+    pub fn builtin() -> Self {
+        let prices = BUILTIN_PRICES
+            .iter()
+            .map(|(model, input, output)| (model.to_string(), ModelPrice { input: *input, output: *output }))
+            .collect();
+        Self { prices }
+    }
+
+    /// Add or replace prices, e.g. from the `[pricing]` config section
+    pub fn with_overrides(mut self, overrides: &HashMap<String, ModelPrice>) -> Self {
+        self.prices.extend(overrides.iter().map(|(model, price)| (model.clone(), *price)));
+        self
+    }
+
+    /// Price of a model
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost of usage on a model, if its price is known
+    pub fn cost(&self, model: &str, usage: Usage) -> Option<f64> {
+        self.price(model).map(|price| price.cost(usage))
+    }
+}
+
+impl Default for PricingTable {
+    fn default() -> Self { Self::builtin() }
+}
+
+/// Usage and cost of one pipeline step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepCost {
+    pub provider: String,
+    pub model: Option<String>,
+    pub usage: Option<Usage>,
+    pub cost_usd: Option<f64>,
+}
+
+/// Usage and cost of a run, step by step
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostSummary {
+    pub steps: Vec<StepCost>,
+}
+
+impl CostSummary {
+    /// Collect what each step's response recorded
+    pub fn from_responses<'a>(steps: impl IntoIterator<Item = (&'a str, &'a Response)>) -> Self {
+        let steps = steps
+            .into_iter()
+            .map(|(provider, response)| StepCost {
+                provider: provider.to_string(),
+                model: response.metadata.get("model").cloned(),
+                usage: Usage::from_response(response),
+                cost_usd: response.metadata.get("cost_usd").and_then(|c| c.parse().ok()),
+            })
+            .collect();
+        Self { steps }
+    }
+
+    /// Tokens used by the steps that reported usage
+    pub fn usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for usage in self.steps.iter().filter_map(|s| s.usage) {
+            total += usage;
+        }
+        total
+    }
+
+    /// Cost of the steps that could be priced
+    pub fn cost_usd(&self) -> f64 {
+        self.steps.iter().filter_map(|s| s.cost_usd).sum()
+    }
+
+    /// Whether every step was priced
+    pub fn is_complete(&self) -> bool {
+        self.steps.iter().all(|s| s.cost_usd.is_some())
+    }
+}
+
+impl fmt::Display for CostSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Cost:")?;
+        for (index, step) in self.steps.iter().enumerate() {
+            let model = step.model.as_deref().map(|m| format!(" ({})", m)).unwrap_or_default();
+            let detail = match (step.usage, step.cost_usd) {
+                (Some(usage), Some(cost)) => format!("{} in + {} out tokens, ${:.4}", usage.prompt_tokens, usage.completion_tokens, cost),
+                (Some(usage), None) => format!("{} in + {} out tokens, no price for this model", usage.prompt_tokens, usage.completion_tokens),
+                (None, _) => "usage not reported".to_string(),
+            };
+            writeln!(f, "  [{}] {}{}: {}", index + 1, step.provider, model, detail)?;
+        }
+        let usage = self.usage();
+        write!(f, "  Total: {} in + {} out tokens, ${:.4}", usage.prompt_tokens, usage.completion_tokens, self.cost_usd())?;
+        if !self.is_complete() {
+            write!(f, " (excludes unpriced steps)")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{Result, Context as AnyhowContext};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{AIProvider, Capabilities};
+use crate::config;
+
+/// Capabilities discovered by probing a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedCapabilities {
+    pub capabilities: Capabilities,
+    /// Seconds since the Unix epoch when the probe ran
+    pub probed_at: u64,
+}
+
+/// On-disk cache of probed capabilities keyed by `provider/model`
+pub struct CapabilityCache {
+    path: PathBuf,
+    entries: HashMap<String, CachedCapabilities>,
+}
+
+impl CapabilityCache {
+    /// Load the cache from a file, starting empty if it does not exist or is corrupt
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    /// Open the cache in the user cache directory
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::load(config::cache_dir()?.join("capabilities.json")))
+    }
+
+    /// Get the cache file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Cache key for a provider and its model
+    pub fn key_for(provider: &dyn AIProvider) -> String {
+        format!("{}/{}", provider.name(), provider.model().unwrap_or("default"))
+    }
+
+    /// Get a cached entry
+    pub fn get(&self, key: &str) -> Option<&CachedCapabilities> {
+        self.entries.get(key)
+    }
+
+    /// Insert an entry, replacing any previous probe
+    pub fn insert(&mut self, key: impl Into<String>, capabilities: Capabilities) {
+        let probed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.entries.insert(key.into(), CachedCapabilities { capabilities, probed_at });
+    }
+
+    /// Drop all cached entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Persist the cache to disk
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Get capabilities for a provider, probing on first use or when `reprobe` is set
+    ///
+    /// A failed probe falls back to the provider's static capabilities without caching them.
+    pub async fn resolve(&mut self, provider: &dyn AIProvider, reprobe: bool) -> Capabilities {
+        let key = Self::key_for(provider);
+        if !reprobe
+            && let Some(cached) = self.entries.get(&key)
+        {
+            return cached.capabilities.clone();
+        }
+
+        match provider.probe().await {
+            Ok(capabilities) => {
+                self.insert(key, capabilities.clone());
+                capabilities
+            }
+            Err(_) => provider.capabilities(),
+        }
+    }
+}
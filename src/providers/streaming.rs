@@ -0,0 +1,282 @@
+//! Server-sent events parsing and resilient streaming
+//!
+//! `SseParser` turns arbitrary network chunks into whole events (chunks may split
+//! lines, CRLF pairs or UTF-8 characters). `JsonAccumulator` reassembles tool-call
+//! arguments streamed as partial JSON. `sse_events` reconnects after network errors:
+//! resumable providers continue from `Last-Event-ID`; others are retried only while
+//! nothing has been emitted, so consumers never see duplicated or spliced output.
+
+use anyhow::{Result, anyhow};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::time::Duration;
+
+/// Raw response body chunks
+pub type ByteStream = BoxStream<'static, Result<Vec<u8>>>;
+
+/// A dispatched server-sent event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    /// `event:` field; `None` means the default `message` type
+    pub event: Option<String>,
+    /// `data:` lines joined with `\n`
+    pub data: String,
+    /// Last event id seen when this event was dispatched
+    pub id: Option<String>,
+}
+
+/// Incremental SSE parser (WHATWG event stream format)
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+    /// `id:` of the event being parsed; it only becomes the last id once dispatched
+    id: Option<String>,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk and return the events it completes
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n' || b == b'\r') {
+            // A CR at the very end may be the first half of a CRLF; wait for more input
+            if self.buf[pos] == b'\r' && pos + 1 == self.buf.len() {
+                break;
+            }
+            let line: Vec<u8> = self.buf.drain(..pos).collect();
+            let terminator = if self.buf.starts_with(b"\r\n") { 2 } else { 1 };
+            self.buf.drain(..terminator);
+
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Check if an event was cut off mid-way (unterminated line or undispatched data)
+    pub fn has_partial(&self) -> bool {
+        !self.buf.is_empty() || !self.data.is_empty()
+    }
+
+    /// Discard a cut-off event, e.g. before resuming on a new connection
+    pub fn discard_partial(&mut self) {
+        self.buf.clear();
+        self.data.clear();
+        self.event = None;
+        self.id = None;
+    }
+
+    /// Id of the last event, sent as `Last-Event-ID` when resuming
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Reconnection delay requested by the server
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None; // comment / keep-alive
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => self.data.push(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(ms) = value.parse::<u64>() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if let Some(id) = self.id.take() {
+            self.last_event_id = Some(id);
+        }
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+/// Reassembles JSON values streamed as string fragments, keyed by content block index
+#[derive(Debug, Default)]
+pub struct JsonAccumulator {
+    parts: HashMap<usize, String>,
+}
+
+impl JsonAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a fragment for a block
+    pub fn push(&mut self, index: usize, fragment: &str) {
+        self.parts.entry(index).or_default().push_str(fragment);
+    }
+
+    /// Parse a block's accumulated JSON; an empty block is `{}`
+    pub fn finish(&mut self, index: usize) -> Result<serde_json::Value> {
+        let text = self.parts.remove(&index).unwrap_or_default();
+        if text.trim().is_empty() {
+            return Ok(serde_json::json!({}));
+        }
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Incomplete or garbled streamed JSON for block {}: {}", index, e))
+    }
+
+    /// Check if any block is still being accumulated
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+/// How `sse_events` recovers from interrupted connections
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub max_reconnects: usize,
+    /// Provider honors `Last-Event-ID`, so a stream can continue where it stopped
+    pub resumable: bool,
+    /// Delay before reconnecting unless the server sent `retry:`
+    pub delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self { max_reconnects: 2, resumable: false, delay: Duration::from_millis(500) }
+    }
+}
+
+struct SseState<C> {
+    connect: C,
+    policy: ReconnectPolicy,
+    parser: SseParser,
+    body: Option<ByteStream>,
+    pending: VecDeque<SseEvent>,
+    reconnects: usize,
+    emitted: bool,
+    done: bool,
+}
+
+/// Stream SSE events from `connect`, reconnecting per `policy` after network errors
+///
+/// `connect` receives the last event id when resuming.
+pub fn sse_events<C, Fut>(connect: C, policy: ReconnectPolicy) -> BoxStream<'static, Result<SseEvent>>
+where
+    C: Fn(Option<String>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<ByteStream>> + Send + 'static,
+{
+    let state = SseState {
+        connect,
+        policy,
+        parser: SseParser::new(),
+        body: None,
+        pending: VecDeque::new(),
+        reconnects: 0,
+        emitted: false,
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                state.emitted = true;
+                return Some((Ok(event), state));
+            }
+            if state.done {
+                return None;
+            }
+
+            let body = match state.body.as_mut() {
+                Some(body) => body,
+                None => {
+                    let last_id = state.parser.last_event_id().map(str::to_string);
+                    match (state.connect)(last_id).await {
+                        Ok(body) => state.body.insert(body),
+                        Err(e) => {
+                            state.done = true;
+                            return Some((Err(e), state));
+                        }
+                    }
+                }
+            };
+
+            let failure = match body.next().await {
+                Some(Ok(chunk)) => {
+                    let events = state.parser.feed(&chunk);
+                    state.pending.extend(events);
+                    continue;
+                }
+                Some(Err(e)) => e,
+                None if state.parser.has_partial() => anyhow!("stream ended in the middle of an event"),
+                None => {
+                    state.done = true;
+                    continue;
+                }
+            };
+
+            // Resume where supported; otherwise only restart if nothing was emitted yet
+            let can_resume = state.policy.resumable && state.parser.last_event_id().is_some();
+            if state.reconnects < state.policy.max_reconnects && (can_resume || !state.emitted) {
+                state.reconnects += 1;
+                state.parser.discard_partial();
+                state.body = None;
+                tokio::time::sleep(state.parser.retry().unwrap_or(state.policy.delay)).await;
+                continue;
+            }
+
+            state.done = true;
+            return Some((Err(anyhow!("Stream interrupted: {}", failure)), state));
+        }
+    })
+    .boxed()
+}
+
+/// Concatenate a response stream, failing if any chunk fails
+///
+/// Partial output is dropped on error so a retry starts from a clean slate.
+pub async fn collect(mut stream: super::ResponseStream<'_>) -> Result<String> {
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        text.push_str(&chunk?);
+    }
+    Ok(text)
+}
+
+/// Adapt a reqwest response body into a `ByteStream`
+pub fn response_bytes(response: reqwest::Response) -> ByteStream {
+    response
+        .bytes_stream()
+        .map(|chunk| chunk.map(|b| b.to_vec()).map_err(anyhow::Error::from))
+        .boxed()
+}
@@ -0,0 +1,81 @@
+//! Structured output: replies constrained to JSON matching a schema
+//!
+//! Providers enforce the schema natively where they can (Anthropic through a
+//! forced tool call, Gemini through `responseJsonSchema`). The executor checks
+//! every reply against the schema anyway and, when it does not conform, asks
+//! again with the validation error up to [`JsonMode::retries`] times.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Extra attempts after an invalid reply unless configured otherwise
+pub const DEFAULT_JSON_RETRIES: u32 = 2;
+
+fn default_retries() -> u32 {
+    DEFAULT_JSON_RETRIES
+}
+
+/// JSON Schema a step's reply must satisfy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonMode {
+    pub schema: serde_json::Value,
+    /// Attempts, with the validation error fed back, after an invalid reply
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+impl JsonMode {
+    pub fn new(schema: serde_json::Value) -> Self {
+        Self { schema, retries: DEFAULT_JSON_RETRIES }
+    }
+
+    /// Load the schema from a JSON file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let schema = serde_json::from_str(&text).map_err(|e| anyhow!("{} is not valid JSON: {}", path.display(), e))?;
+        let mode = Self::new(schema);
+        mode.check_schema()?;
+        Ok(mode)
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Check the schema itself is a valid JSON Schema
+    pub fn check_schema(&self) -> Result<()> {
+        jsonschema::validator_for(&self.schema).map(|_| ()).map_err(|e| anyhow!("Invalid JSON schema: {}", e))
+    }
+
+    /// Parse a reply and check it against the schema, describing every violation on failure
+    pub fn validate(&self, reply: &str) -> Result<serde_json::Value, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(strip_fences(reply)).map_err(|e| format!("the reply is not valid JSON: {}", e))?;
+        let validator = jsonschema::validator_for(&self.schema).map_err(|e| format!("invalid JSON schema: {}", e))?;
+        let errors: Vec<String> = validator
+            .iter_errors(&value)
+            .map(|e| format!("{} at '{}'", e, e.instance_path()))
+            .collect();
+        if errors.is_empty() { Ok(value) } else { Err(errors.join("; ")) }
+    }
+
+    /// Prompt asking for a corrected reply
+    pub fn feedback(&self, prompt: &str, reply: &str, error: &str) -> String {
+        format!(
+            "{}\n\nYour previous reply was rejected because {}.\nPrevious reply:\n{}\n\nReply again with only JSON that matches this schema:\n{}",
+            prompt, error, reply, self.schema
+        )
+    }
+}
+
+/// Drop a surrounding Markdown code fence, which models add despite instructions
+fn strip_fences(reply: &str) -> &str {
+    let trimmed = reply.trim();
+    trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map_or(trimmed, str::trim)
+}
@@ -0,0 +1,150 @@
+//! Offline HTTP for provider tests (`testing` feature)
+//!
+//! [`FakeTransport`] answers provider requests from a queue of canned
+//! responses, so tests can exercise real request building and response
+//! parsing, and simulate rate limits, server errors and timeouts, without a
+//! network. Inject it with `with_transport` on a provider.
+
+use async_trait::async_trait;
+use reqwest::{Request, Response};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::http::Transport;
+use crate::error::ProviderError;
+pub use super::mock::MockProvider;
+
+/// Canned outcome for one request
+#[derive(Debug, Clone)]
+pub enum FakeReply {
+    /// A response with this status, headers and body
+    Response { status: u16, headers: Vec<(String, String)>, body: String },
+    /// The request timed out before a response arrived
+    Timeout,
+    /// The connection failed before a response arrived
+    ConnectionError(String),
+}
+
+/// Request as seen by the transport
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub provider: String,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl RecordedRequest {
+    /// Value of a header, if it was sent
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Body parsed as JSON
+    pub fn json(&self) -> Option<serde_json::Value> {
+        self.body.as_deref().and_then(|b| serde_json::from_str(b).ok())
+    }
+}
+
+/// Transport that replies from a queue and records every request
+///
+/// Replies are used in order; a request with nothing queued fails with a
+/// non-retryable [`ProviderError`].
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    replies: Mutex<VecDeque<FakeReply>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl FakeTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue any reply
+    pub fn with_reply(self, reply: FakeReply) -> Self {
+        self.replies.lock().unwrap().push_back(reply);
+        self
+    }
+
+    /// Queue a `200 OK` JSON response
+    pub fn with_json(self, body: serde_json::Value) -> Self {
+        self.with_response(200, "application/json", body.to_string())
+    }
+
+    /// Queue a `200 OK` server-sent events response with `body` as the raw stream
+    pub fn with_sse(self, body: impl Into<String>) -> Self {
+        self.with_response(200, "text/event-stream", body.into())
+    }
+
+    /// Queue an error status such as 429 or 500
+    pub fn with_status(self, status: u16, body: impl Into<String>) -> Self {
+        self.with_response(status, "application/json", body.into())
+    }
+
+    /// Queue a request that times out
+    pub fn with_timeout(self) -> Self {
+        self.with_reply(FakeReply::Timeout)
+    }
+
+    /// Queue a request that fails to connect
+    pub fn with_connection_error(self, message: impl Into<String>) -> Self {
+        self.with_reply(FakeReply::ConnectionError(message.into()))
+    }
+
+    fn with_response(self, status: u16, content_type: &str, body: String) -> Self {
+        self.with_reply(FakeReply::Response {
+            status,
+            headers: vec![("content-type".to_string(), content_type.to_string())],
+            body,
+        })
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Replies not yet used
+    pub fn remaining(&self) -> usize {
+        self.replies.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl Transport for FakeTransport {
+    async fn execute(&self, provider: &str, request: Request) -> Result<Response, ProviderError> {
+        self.requests.lock().unwrap().push(RecordedRequest {
+            provider: provider.to_string(),
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_str().unwrap_or_default().to_string()))
+                .collect(),
+            body: request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(|b| String::from_utf8_lossy(b).into_owned()),
+        });
+
+        let reply = self.replies.lock().unwrap().pop_front();
+        match reply {
+            Some(FakeReply::Response { status, headers, body }) => {
+                let mut builder = http::Response::builder().status(status);
+                for (name, value) in headers {
+                    builder = builder.header(name, value);
+                }
+                let response = builder
+                    .body(body)
+                    .map_err(|e| ProviderError::network(provider, false, e.to_string()))?;
+                Ok(Response::from(response))
+            }
+            Some(FakeReply::Timeout) => Err(ProviderError::timeout(provider, "operation timed out")),
+            Some(FakeReply::ConnectionError(message)) => Err(ProviderError::network(provider, true, message)),
+            None => Err(ProviderError::network(provider, false, format!("FakeTransport has no reply queued for {}", request.url()))),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Token counting for context budgets and truncation
+//!
+//! OpenAI-family models use byte-pair encoding with published `.tiktoken`
+//! rank files, which `BpeTokenizer` loads for exact counts. Anthropic and
+//! Google do not publish their vocabularies, so their counts come from
+//! `ApproxTokenizer`, calibrated per provider on the same pre-tokenization.
+
+use anyhow::{Result, anyhow, Context as AnyhowContext};
+use base64::Engine;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Counts the tokens a model sees for a piece of text
+pub trait Tokenizer: Send + Sync {
+    /// Name shown in diagnostics, e.g. `cl100k_base` or `claude-approx`
+    fn name(&self) -> &str;
+
+    /// Number of tokens in `text`
+    fn count(&self, text: &str) -> usize;
+
+    /// Longest prefix of `text` that fits in `max_tokens`, cut on a character boundary
+    ///
+    /// Counts add up over pre-tokenized pieces, so whole pieces are taken while
+    /// they fit and only the piece that overflows is searched.
+    fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        let mut start = 0;
+        let mut left = max_tokens;
+        for piece in pre_tokenize(text) {
+            let tokens = self.count(piece);
+            if tokens > left {
+                return &text[..start + longest_prefix(piece, |prefix| self.count(prefix) <= left)];
+            }
+            left -= tokens;
+            start += piece.len();
+        }
+        text
+    }
+}
+
+/// Byte length of the longest prefix of `piece`, in characters, that `fits`
+fn longest_prefix(piece: &str, fits: impl Fn(&str) -> bool) -> usize {
+    let end_of = |chars: usize| piece.char_indices().nth(chars).map(|(i, _)| i).unwrap_or(piece.len());
+    // Counts grow with the prefix, so search for the most characters that fit
+    let (mut low, mut high) = (0, piece.chars().count());
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if fits(&piece[..end_of(mid)]) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    end_of(low)
+}
+
+/// Tokenizer used for a provider when no rank file is configured
+pub fn tokenizer_for(provider: &str) -> Arc<dyn Tokenizer> {
+    match provider {
+        "claude" => Arc::new(ApproxTokenizer::claude()),
+        "gemini" => Arc::new(ApproxTokenizer::gemini()),
+        _ => Arc::new(ApproxTokenizer::default()),
+    }
+}
+
+/// Split text the way the cl100k pre-tokenizer does before BPE runs
+///
+/// Mirrors the pattern `'(?i:[sdmt]|ll|ve|re)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}|
+/// ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+`, tried in that order.
+pub fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map(|&(o, _)| o).unwrap_or(text.len());
+    let is_word = |c: char| c.is_alphabetic();
+    let is_punct = |c: char| !c.is_whitespace() && !c.is_alphanumeric();
+    let is_newline = |c: char| c == '\r' || c == '\n';
+
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let run = |from: usize, pred: &dyn Fn(char) -> bool| {
+            let mut end = from;
+            while end < chars.len() && pred(chars[end].1) {
+                end += 1;
+            }
+            end
+        };
+
+        let end = if let Some(len) = contraction(&text[chars[i].0..]) {
+            i + len
+        } else if is_word(c) {
+            run(i, &is_word)
+        } else if !is_newline(c) && !c.is_numeric() && next.is_some_and(is_word) {
+            run(i + 1, &is_word)
+        } else if c.is_numeric() {
+            (i..chars.len().min(i + 3)).take_while(|&j| chars[j].1.is_numeric()).last().unwrap_or(i) + 1
+        } else if is_punct(c) || (c == ' ' && next.is_some_and(is_punct)) {
+            let start = if c == ' ' { i + 1 } else { i };
+            run(run(start, &is_punct), &is_newline)
+        } else {
+            let spaces = run(i, &|c: char| c.is_whitespace());
+            match (i..spaces).rev().find(|&j| is_newline(chars[j].1)) {
+                Some(last_newline) => last_newline + 1,
+                // Leave the last space to prefix the following word
+                None if spaces < chars.len() && spaces - i > 1 => spaces - 1,
+                None => spaces,
+            }
+        };
+        pieces.push(&text[offset(i)..offset(end)]);
+        i = end;
+    }
+    pieces
+}
+
+/// Length in characters of an English contraction suffix at the start of `text`
+fn contraction(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('\'')?;
+    let lower: String = rest.chars().take(2).collect::<String>().to_lowercase();
+    if lower.starts_with("ll") || lower.starts_with("ve") || lower.starts_with("re") {
+        Some(3)
+    } else if lower.starts_with(['s', 'd', 'm', 't']) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Longest piece merged in one go; longer ones are rare (e.g. base64 blobs) and are
+/// counted in slices so memory and time stay bounded
+pub const MAX_PIECE_BYTES: usize = 4096;
+
+/// Byte-pair encoder loaded from a tiktoken rank file
+#[derive(Debug, Clone)]
+pub struct BpeTokenizer {
+    name: String,
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl BpeTokenizer {
+    /// Load a `.tiktoken` file (one `<base64 token> <rank>` pair per line)
+    ///
+    /// The encoding is named after the file stem, e.g. `cl100k_base`.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tokenizer ranks {}", path.display()))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        Self::parse(name, &text).with_context(|| format!("Invalid tokenizer ranks {}", path.display()))
+    }
+
+    /// Parse rank-file text
+    pub fn parse(name: impl Into<String>, text: &str) -> Result<Self> {
+        let mut ranks = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("line {}: expected '<base64 token> <rank>'", number + 1))?;
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|e| anyhow!("line {}: invalid base64: {}", number + 1, e))?;
+            let rank = rank.parse().map_err(|_| anyhow!("line {}: invalid rank '{}'", number + 1, rank))?;
+            ranks.insert(bytes, rank);
+        }
+        if ranks.is_empty() {
+            return Err(anyhow!("no ranks found"));
+        }
+        Ok(Self { name: name.into(), ranks })
+    }
+
+    /// Number of BPE tokens for one pre-tokenized piece
+    ///
+    /// Pieces longer than [`MAX_PIECE_BYTES`] are counted in slices of that size.
+    fn count_piece(&self, piece: &[u8]) -> usize {
+        if piece.len() > MAX_PIECE_BYTES {
+            return piece.chunks(MAX_PIECE_BYTES).map(|slice| self.count_piece(slice)).sum();
+        }
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return piece.len().min(1);
+        }
+        // Start from single bytes and repeatedly merge the lowest-ranked adjacent pair,
+        // leftmost first. Parts are named by their start; queued pairs that a merge
+        // has since changed are skipped when they come up.
+        let len = piece.len();
+        let mut end: Vec<usize> = (1..=len).collect();
+        let mut prev: Vec<Option<usize>> = (0..len).map(|i| i.checked_sub(1)).collect();
+        let mut alive = vec![true; len];
+        let mut queue: BinaryHeap<Reverse<(u32, usize, usize, usize)>> = BinaryHeap::new();
+        let push = |queue: &mut BinaryHeap<_>, start: usize, mid: usize, stop: usize| {
+            if let Some(&rank) = self.ranks.get(&piece[start..stop]) {
+                queue.push(Reverse((rank, start, mid, stop)));
+            }
+        };
+        for start in 0..len - 1 {
+            push(&mut queue, start, start + 1, start + 2);
+        }
+        let mut parts = len;
+        while let Some(Reverse((_, start, mid, stop))) = queue.pop() {
+            if !alive[start] || end[start] != mid || !alive[mid] || end[mid] != stop {
+                continue;
+            }
+            end[start] = stop;
+            alive[mid] = false;
+            parts -= 1;
+            if stop < len {
+                prev[stop] = Some(start);
+                push(&mut queue, start, stop, end[stop]);
+            }
+            if let Some(before) = prev[start] {
+                push(&mut queue, before, start, stop);
+            }
+        }
+        parts
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        pre_tokenize(text).iter().map(|piece| self.count_piece(piece.as_bytes())).sum()
+    }
+}
+
+/// Vocabulary-free estimate: ASCII runs cost one token per `chars_per_token`
+/// characters and every other character costs one token
+#[derive(Debug, Clone)]
+pub struct ApproxTokenizer {
+    name: String,
+    chars_per_token: f64,
+}
+
+impl ApproxTokenizer {
+    /// Estimator with a specific ratio of ASCII characters to tokens
+    pub fn new(name: impl Into<String>, chars_per_token: f64) -> Self {
+        Self { name: name.into(), chars_per_token }
+    }
+
+    /// Claude's vocabulary splits English and code slightly finer than cl100k
+    pub fn claude() -> Self {
+        Self::new("claude-approx", 3.5)
+    }
+
+    /// Gemini's SentencePiece vocabulary averages about four characters per token
+    pub fn gemini() -> Self {
+        Self::new("gemini-approx", 4.0)
+    }
+}
+
+impl Default for ApproxTokenizer {
+    fn default() -> Self {
+        Self::new("approx", 4.0)
+    }
+}
+
+impl Tokenizer for ApproxTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        pre_tokenize(text)
+            .iter()
+            .map(|piece| {
+                let ascii = piece.bytes().filter(u8::is_ascii).count();
+                let other = piece.chars().filter(|c| !c.is_ascii()).count();
+                let ascii_tokens = if ascii == 0 { 0 } else { ((ascii as f64 / self.chars_per_token).round() as usize).max(1) };
+                ascii_tokens + other
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,87 @@
+//! Tool (function) calling
+//!
+//! A step may offer the model a set of [`ToolSpec`]s. When the model asks to
+//! call one, the provider hands the [`ToolCall`] to a [`ToolHandler`], sends
+//! the result back and lets the model continue, until it answers without
+//! calling a tool or [`MAX_TOOL_ROUNDS`] is reached.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Most model turns one `execute_with_tools` call may spend calling tools
+pub const MAX_TOOL_ROUNDS: usize = 16;
+
+/// A local function the model may call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the call's arguments
+    pub input_schema: serde_json::Value,
+}
+
+impl ToolSpec {
+    pub fn new(name: impl Into<String>, description: impl Into<String>, input_schema: serde_json::Value) -> Self {
+        Self { name: name.into(), description: description.into(), input_schema }
+    }
+}
+
+/// A call the model asked for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Provider-assigned id that ties the result to the call
+    pub id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+}
+
+/// Runs tool calls for a provider
+///
+/// An `Err` is not fatal: its message goes back to the model as a failed call.
+#[async_trait]
+pub trait ToolHandler: Send + Sync {
+    async fn call(&self, call: &ToolCall) -> Result<String>;
+}
+
+#[async_trait]
+impl<F> ToolHandler for F
+where
+    F: Fn(&ToolCall) -> Result<String> + Send + Sync,
+{
+    async fn call(&self, call: &ToolCall) -> Result<String> {
+        self(call)
+    }
+}
+
+/// Tools offered to the model together with the handler that runs them
+#[derive(Clone)]
+pub struct Toolset {
+    pub specs: Vec<ToolSpec>,
+    pub handler: Arc<dyn ToolHandler>,
+}
+
+impl Toolset {
+    pub fn new(specs: Vec<ToolSpec>, handler: Arc<dyn ToolHandler>) -> Self {
+        Self { specs, handler }
+    }
+
+    /// Names of the offered tools
+    pub fn names(&self) -> Vec<&str> {
+        self.specs.iter().map(|s| s.name.as_str()).collect()
+    }
+}
+
+impl fmt::Debug for Toolset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Toolset").field("tools", &self.names()).finish()
+    }
+}
+
+impl PartialEq for Toolset {
+    fn eq(&self, other: &Self) -> bool {
+        self.specs == other.specs
+    }
+}
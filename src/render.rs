@@ -0,0 +1,180 @@
+//! Terminal renderers for model output
+//!
+//! Markdown is formatted with termimad and fenced code blocks are highlighted
+//! with syntect. Output is left untouched when stdout is not a terminal unless
+//! `--render` asks otherwise.
+
+use regex::Regex;
+use std::sync::{LazyLock, OnceLock};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
+use termimad::MadSkin;
+
+/// How `--render` prints model output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderMode {
+    /// Markdown syntax removed
+    Plain,
+    /// Formatted markdown with highlighted code blocks
+    Markdown,
+    /// Exactly as the model wrote it
+    Raw,
+}
+
+impl RenderMode {
+    /// The requested mode, else markdown on a terminal and raw when piped
+    pub fn resolve(requested: Option<Self>, is_terminal: bool) -> Self {
+        requested.unwrap_or(if is_terminal { RenderMode::Markdown } else { RenderMode::Raw })
+    }
+
+    pub fn render(self, text: &str) -> String {
+        static MARKDOWN: OnceLock<MarkdownRenderer> = OnceLock::new();
+        match self {
+            RenderMode::Plain => PlainRenderer.render(text),
+            RenderMode::Markdown => MARKDOWN.get_or_init(MarkdownRenderer::new).render(text),
+            RenderMode::Raw => RawRenderer.render(text),
+        }
+    }
+}
+
+/// Turns model output into what gets printed
+pub trait Renderer: Send + Sync {
+    fn render(&self, text: &str) -> String;
+}
+
+/// Leaves output untouched
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawRenderer;
+
+impl Renderer for RawRenderer {
+    fn render(&self, text: &str) -> String {
+        text.to_string()
+    }
+}
+
+/// Strips headings, emphasis, inline code and fences, keeping link targets
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainRenderer;
+
+static MARKUP: LazyLock<[(Regex, &'static str); 6]> = LazyLock::new(|| {
+    [
+        (Regex::new(r"(?m)^ {0,3}#{1,6}\s+").unwrap(), ""),
+        (Regex::new(r"\*\*(.+?)\*\*|__(.+?)__").unwrap(), "$1$2"),
+        (Regex::new(r"(^|[^\w*])\*([^*\s][^*]*?)\*").unwrap(), "$1$2"),
+        (Regex::new(r"`([^`]+)`").unwrap(), "$1"),
+        (Regex::new(r"!?\[([^\]]*)\]\(([^)\s]+)\)").unwrap(), "$1 ($2)"),
+        (Regex::new(r"(?m)^(\s*)[*+] ").unwrap(), "$1- "),
+    ]
+});
+
+impl Renderer for PlainRenderer {
+    fn render(&self, text: &str) -> String {
+        blocks(text)
+            .into_iter()
+            .map(|block| match block {
+                Block::Prose(prose) => MARKUP.iter().fold(prose, |text, (pattern, with)| pattern.replace_all(&text, *with).into_owned()),
+                Block::Code { code, .. } => code,
+            })
+            .collect()
+    }
+}
+
+/// Formats markdown for the terminal and highlights fenced code by language
+pub struct MarkdownRenderer {
+    skin: MadSkin,
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    width: usize,
+}
+
+impl MarkdownRenderer {
+    /// Renderer sized to the terminal
+    pub fn new() -> Self {
+        let (columns, _) = termimad::terminal_size();
+        let mut themes = ThemeSet::load_defaults();
+        Self {
+            skin: MadSkin::default(),
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.themes.remove("base16-ocean.dark").unwrap_or_default(),
+            width: usize::from(columns).max(20),
+        }
+    }
+
+    /// Wrap prose at `width` columns instead of the terminal's width
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width.max(20);
+        self
+    }
+
+    fn highlight(&self, language: &str, code: &str) -> String {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut out = String::new();
+        for line in LinesWithEndings::from(code) {
+            match highlighter.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                Err(_) => out.push_str(line),
+            }
+        }
+        out.push_str("\x1b[0m");
+        out
+    }
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, text: &str) -> String {
+        blocks(text)
+            .into_iter()
+            .map(|block| match block {
+                Block::Prose(prose) => self.skin.text(&prose, Some(self.width)).to_string(),
+                Block::Code { language, code } => self.highlight(language, &code),
+            })
+            .collect()
+    }
+}
+
+/// A run of prose or a fenced code block (without its fences)
+enum Block<'a> {
+    Prose(String),
+    Code { language: &'a str, code: String },
+}
+
+/// Split text at ``` fences; an unclosed fence runs to the end
+fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    let mut fence: Option<&str> = None;
+    for line in LinesWithEndings::from(text) {
+        let trimmed = line.trim();
+        match (fence, trimmed.strip_prefix("```")) {
+            (None, Some(language)) => {
+                if !current.is_empty() {
+                    blocks.push(Block::Prose(std::mem::take(&mut current)));
+                }
+                fence = Some(language.trim());
+            }
+            (Some(language), Some("")) => {
+                blocks.push(Block::Code { language, code: std::mem::take(&mut current) });
+                fence = None;
+            }
+            _ => current.push_str(line),
+        }
+    }
+    match fence {
+        Some(language) => blocks.push(Block::Code { language, code: current }),
+        None if !current.is_empty() => blocks.push(Block::Prose(current)),
+        None => {}
+    }
+    blocks
+}
@@ -0,0 +1,306 @@
+//! Per-provider request limits and quota-aware scheduling of queued jobs
+//!
+//! Limits are enforced by the executor for every call to a provider, so they
+//! hold across pipeline steps, batch inputs and parallel runs alike.
+//!
+//! Queued jobs (such as batch jobs submitted to `ai-cli serve`) are lists of
+//! provider tasks. The scheduler hands out the next task of the highest-priority
+//! job whose provider has quota left, rotating between jobs of equal priority,
+//! so a job stuck behind one provider's rate limit (or a huge job) does not
+//! block work for other providers or other jobs.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::sync::Notify;
+
+/// Per-provider request limits (`[rate_limits.<provider>]` in config)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Sustained request rate, with starts spaced out evenly (0 = unlimited)
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// Requests allowed in flight at once (0 counts as 1)
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+/// One unit of work bound to a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledTask<T> {
+    pub provider: String,
+    pub payload: T,
+}
+
+impl<T> ScheduledTask<T> {
+    pub fn new(provider: impl Into<String>, payload: T) -> Self {
+        Self { provider: provider.into(), payload }
+    }
+}
+
+/// A queued job: an ordered list of tasks with a priority (higher runs first)
+#[derive(Debug, Clone)]
+pub struct Job<T> {
+    pub id: String,
+    pub priority: i32,
+    tasks: VecDeque<ScheduledTask<T>>,
+    last_served: u64,
+}
+
+impl<T> Job<T> {
+    /// Create an empty job
+    pub fn new(id: impl Into<String>, priority: i32) -> Self {
+        Self { id: id.into(), priority, tasks: VecDeque::new(), last_served: 0 }
+    }
+
+    /// Append a task to the job
+    pub fn with_task(mut self, provider: impl Into<String>, payload: T) -> Self {
+        self.tasks.push_back(ScheduledTask::new(provider, payload));
+        self
+    }
+
+    /// Number of tasks not yet handed out
+    pub fn remaining(&self) -> usize {
+        self.tasks.len()
+    }
+}
+
+/// Start spacing plus in-flight counter for one provider, mirroring the executor's limiter
+#[derive(Debug)]
+struct ProviderQuota {
+    limit: RateLimit,
+    /// Starts available now; at most one, since the executor spaces starts evenly
+    tokens: f64,
+    last_refill: Instant,
+    in_flight: usize,
+}
+
+impl ProviderQuota {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self { limit, tokens: 1.0, last_refill: now, in_flight: 0 }
+    }
+
+    fn rpm(&self) -> Option<u32> {
+        self.limit.requests_per_minute.filter(|rpm| *rpm > 0)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(rpm) = self.rpm() {
+            let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * f64::from(rpm) / 60.0).min(1.0);
+        }
+        self.last_refill = now;
+    }
+
+    fn has_slot(&self) -> bool {
+        self.limit.max_concurrent.is_none_or(|max| self.in_flight < max.max(1))
+    }
+
+    fn has_token(&self) -> bool {
+        self.rpm().is_none() || self.tokens >= 1.0
+    }
+
+    /// Time until the next start is allowed, if the rate limit is what blocks
+    fn wait_for_token(&self) -> Option<Duration> {
+        let rpm = self.rpm()?;
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64((1.0 - self.tokens) * 60.0 / f64::from(rpm)))
+    }
+}
+
+/// Interleaves tasks from queued jobs while respecting per-provider limits
+pub struct QuotaScheduler<T> {
+    jobs: Vec<Job<T>>,
+    limits: HashMap<String, RateLimit>,
+    quotas: HashMap<String, ProviderQuota>,
+    served: u64,
+}
+
+impl<T> QuotaScheduler<T> {
+    /// Create a scheduler; providers without a limit are unthrottled
+    pub fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self { jobs: Vec::new(), limits, quotas: HashMap::new(), served: 0 }
+    }
+
+    /// Queue a job
+    pub fn submit(&mut self, job: Job<T>) {
+        if job.remaining() > 0 {
+            self.jobs.push(job);
+        }
+    }
+
+    /// Check if every queued task has been handed out
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Number of tasks still queued across all jobs
+    pub fn pending(&self) -> usize {
+        self.jobs.iter().map(Job::remaining).sum()
+    }
+
+    fn quota(&mut self, provider: &str, now: Instant) -> &mut ProviderQuota {
+        let limit = self.limits.get(provider).cloned().unwrap_or_default();
+        let quota = self.quotas
+            .entry(provider.to_string())
+            .or_insert_with(|| ProviderQuota::new(limit, now));
+        quota.refill(now);
+        quota
+    }
+
+    /// Take the next task that may start at `now`, with its job id
+    pub fn next_ready(&mut self, now: Instant) -> Option<(String, ScheduledTask<T>)> {
+        // Highest priority first; among equals, the job served longest ago
+        let mut order: Vec<usize> = (0..self.jobs.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&self.jobs[a], &self.jobs[b]);
+            b.priority.cmp(&a.priority).then(a.last_served.cmp(&b.last_served))
+        });
+
+        for index in order {
+            let Some(provider) = self.jobs[index].tasks.front().map(|t| t.provider.clone()) else {
+                continue;
+            };
+            let quota = self.quota(&provider, now);
+            if !(quota.has_slot() && quota.has_token()) {
+                continue;
+            }
+            if quota.rpm().is_some() {
+                quota.tokens -= 1.0;
+            }
+            quota.in_flight += 1;
+
+            self.served += 1;
+            let job = &mut self.jobs[index];
+            job.last_served = self.served;
+            let task = job.tasks.pop_front()?;
+            let id = job.id.clone();
+            if job.tasks.is_empty() {
+                self.jobs.remove(index);
+            }
+            return Some((id, task));
+        }
+        None
+    }
+
+    /// Release the concurrency slot taken by a finished task
+    pub fn complete(&mut self, provider: &str) {
+        if let Some(quota) = self.quotas.get_mut(provider) {
+            quota.in_flight = quota.in_flight.saturating_sub(1);
+        }
+    }
+
+    /// How long until a rate-limited task could start, if any is waiting on its provider's rate
+    pub fn next_wakeup(&mut self, now: Instant) -> Option<Duration> {
+        let providers: Vec<String> = self.jobs
+            .iter()
+            .filter_map(|job| job.tasks.front().map(|t| t.provider.clone()))
+            .collect();
+        providers
+            .iter()
+            .filter_map(|provider| {
+                let quota = self.quota(provider, now);
+                if quota.has_slot() { quota.wait_for_token() } else { None }
+            })
+            .min()
+    }
+
+    /// Run every queued task with `worker`, returning `(job id, result)` in completion order
+    pub async fn run<F, Fut, R>(mut self, worker: F) -> Vec<(String, R)>
+    where
+        F: Fn(String, T) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let mut running = FuturesUnordered::new();
+        let mut results = Vec::new();
+
+        loop {
+            while let Some((job_id, task)) = self.next_ready(Instant::now()) {
+                let provider = task.provider;
+                let work = worker(job_id.clone(), task.payload);
+                running.push(async move { (job_id, provider, work.await) });
+            }
+
+            if running.is_empty() {
+                match self.next_wakeup(Instant::now()) {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => break,
+                }
+                continue;
+            }
+
+            let wakeup = self.next_wakeup(Instant::now());
+            tokio::select! {
+                Some((job_id, provider, result)) = running.next() => {
+                    self.complete(&provider);
+                    results.push((job_id, result));
+                }
+                _ = tokio::time::sleep(wakeup.unwrap_or_default()), if wakeup.is_some() => {}
+            }
+        }
+
+        results
+    }
+}
+
+/// A [`QuotaScheduler`] that keeps taking jobs while it runs, for long-lived servers
+pub struct JobQueue<T> {
+    scheduler: Mutex<QuotaScheduler<T>>,
+    submitted: Notify,
+}
+
+impl<T> JobQueue<T> {
+    /// Create an empty queue; providers without a limit are unthrottled
+    pub fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self { scheduler: Mutex::new(QuotaScheduler::new(limits)), submitted: Notify::new() }
+    }
+
+    /// Queue a job, waking the runner
+    pub fn submit(&self, job: Job<T>) {
+        self.scheduler.lock().unwrap_or_else(PoisonError::into_inner).submit(job);
+        self.submitted.notify_one();
+    }
+
+    /// Number of tasks still queued across all jobs
+    pub fn pending(&self) -> usize {
+        self.scheduler.lock().unwrap_or_else(PoisonError::into_inner).pending()
+    }
+
+    /// Run queued tasks with `worker`, at most `max_running` at once; never returns
+    pub async fn run<F, Fut>(&self, max_running: usize, worker: F)
+    where
+        F: Fn(String, T) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let max_running = max_running.max(1);
+        let mut running = FuturesUnordered::new();
+        loop {
+            let wakeup = {
+                let mut scheduler = self.scheduler.lock().unwrap_or_else(PoisonError::into_inner);
+                while running.len() < max_running {
+                    let Some((job_id, task)) = scheduler.next_ready(Instant::now()) else { break };
+                    let provider = task.provider;
+                    let work = worker(job_id, task.payload);
+                    running.push(async move {
+                        work.await;
+                        provider
+                    });
+                }
+                if running.len() < max_running { scheduler.next_wakeup(Instant::now()) } else { None }
+            };
+            tokio::select! {
+                Some(provider) = running.next(), if !running.is_empty() => {
+                    self.scheduler.lock().unwrap_or_else(PoisonError::into_inner).complete(&provider);
+                }
+                _ = self.submitted.notified() => {}
+                _ = tokio::time::sleep(wakeup.unwrap_or_default()), if wakeup.is_some() => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,527 @@
+//! `ai-cli serve`: the configured providers and saved pipelines over HTTP
+//!
+//! Endpoints:
+//!
+//! - `GET /health`
+//! - `GET /metrics`: request counts, latency, tokens and errors per provider, for Prometheus
+//! - `GET /v1/providers`: registered provider names
+//! - `GET /v1/pipelines`: saved pipelines with their chains
+//! - `POST /v1/execute`: `{"provider", "prompt", "options"?, "env"?, "stream"?}`
+//! - `POST /v1/pipelines/{name}/run`: `{"input"?, "env"?, "stream"?}`; the input
+//!   becomes a user message and `{{env.INPUT}}`, as in batch mode
+//! - `POST /v1/jobs`: `{"pipeline", "inputs", "env"?, "priority"?}` queues a batch
+//!   job and answers `202` with its id; inputs are batch input lines (a string or
+//!   `{"id"?, "input", "env"?}`)
+//! - `GET /v1/jobs/{id}`: a queued job's state and the results finished so far
+//!
+//! Queued jobs share one [`JobQueue`]: each input is a task for the provider of the
+//! pipeline's first step, handed out by priority and per-provider rate limits, so
+//! one huge job does not hold up the others.
+//!
+//! Results are JSON. With `"stream": true` they arrive as server-sent events
+//! instead: one `step` event per finished step, then `done`, or `error`.
+//!
+//! With a token every endpoint but `/health` needs `Authorization: Bearer <token>`;
+//! one is required to listen on a non-loopback address. On loopback, requests
+//! whose `Host` is not a loopback name are refused, so web pages cannot reach
+//! the API through DNS rebinding.
+
+use anyhow::{Context as AnyhowContext, Result, anyhow};
+use axum::extract::{Path, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response as HttpResponse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::error::ExitCode;
+use crate::pipeline::batch::run_input;
+use crate::pipeline::{BatchInput, BatchResult, Metrics, PipelineExecutor, PipelineParser, PipelineStep, PipelineStore};
+use crate::providers::{Context, Message, MessageRole, ProviderOptions, Response};
+use crate::scheduler::{Job, JobQueue, RateLimit};
+
+/// Queued batch inputs run at once unless [`ServerState::with_job_limits`] says otherwise
+pub const DEFAULT_RUNNING_JOBS: usize = 4;
+
+/// What the handlers share
+#[derive(Clone)]
+pub struct ServerState {
+    executor: Arc<PipelineExecutor>,
+    store: Option<Arc<PipelineStore>>,
+    metrics: Arc<Metrics>,
+    token: Option<Arc<str>>,
+    /// Refuse requests addressed to anything but a loopback name
+    loopback: bool,
+    jobs: Arc<JobBoard>,
+}
+
+impl ServerState {
+    /// Serve the providers registered on `executor`
+    pub fn new(mut executor: PipelineExecutor) -> Self {
+        let metrics = Arc::new(Metrics::new());
+        executor.add_observer(metrics.clone());
+        Self {
+            executor: Arc::new(executor),
+            store: None,
+            metrics,
+            token: None,
+            loopback: true,
+            jobs: Arc::new(JobBoard::new(HashMap::new(), DEFAULT_RUNNING_JOBS)),
+        }
+    }
+
+    /// Serve the pipelines saved in `store`
+    pub fn with_store(mut self, store: PipelineStore) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Require `Authorization: Bearer <token>` on every endpoint but `/health`
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into().into());
+        self
+    }
+
+    /// Schedule queued jobs by `limits`, running at most `max_running` inputs at once
+    pub fn with_job_limits(mut self, limits: HashMap<String, RateLimit>, max_running: usize) -> Self {
+        self.jobs = Arc::new(JobBoard::new(limits, max_running));
+        self
+    }
+}
+
+/// One input of a queued job
+struct JobTask {
+    steps: Arc<Vec<PipelineStep>>,
+    context: Arc<Context>,
+    index: usize,
+    input: BatchInput,
+}
+
+/// A queued job's progress
+struct JobRecord {
+    pipeline: String,
+    priority: i32,
+    total: usize,
+    started: usize,
+    results: Vec<BatchResult>,
+}
+
+/// Jobs queued through `POST /v1/jobs` and the queue that runs them
+struct JobBoard {
+    queue: JobQueue<JobTask>,
+    records: Mutex<HashMap<String, JobRecord>>,
+    next_id: AtomicU64,
+    max_running: usize,
+    /// The runner is spawned with the first job, inside the server's runtime
+    running: AtomicBool,
+}
+
+impl JobBoard {
+    fn new(limits: HashMap<String, RateLimit>, max_running: usize) -> Self {
+        Self {
+            queue: JobQueue::new(limits),
+            records: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            max_running,
+            running: AtomicBool::new(false),
+        }
+    }
+
+    fn records(&self) -> std::sync::MutexGuard<'_, HashMap<String, JobRecord>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queue `inputs` as one job, starting the runner if this is the first
+    fn submit(self: &Arc<Self>, executor: &Arc<PipelineExecutor>, request: JobRequest, steps: Vec<PipelineStep>, inputs: Vec<BatchInput>) -> String {
+        let id = format!("job-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let provider = steps.first().map(|step| step.provider.clone()).unwrap_or_default();
+        let steps = Arc::new(steps);
+        let mut context = Context::new();
+        context.environment.extend(request.env);
+        let context = Arc::new(context);
+        let record = JobRecord { pipeline: request.pipeline, priority: request.priority, total: inputs.len(), started: 0, results: Vec::new() };
+        self.records().insert(id.clone(), record);
+
+        let mut job = Job::new(id.clone(), request.priority);
+        for (index, input) in inputs.into_iter().enumerate() {
+            job = job.with_task(provider.clone(), JobTask { steps: steps.clone(), context: context.clone(), index, input });
+        }
+        self.queue.submit(job);
+        if !self.running.swap(true, Ordering::SeqCst) {
+            tokio::spawn(Self::run(self.clone(), executor.clone()));
+        }
+        id
+    }
+
+    async fn run(self: Arc<Self>, executor: Arc<PipelineExecutor>) {
+        let worker = |job_id: String, task: JobTask| {
+            let (board, executor) = (self.clone(), executor.clone());
+            async move {
+                if let Some(record) = board.records().get_mut(&job_id) {
+                    record.started += 1;
+                }
+                let result = run_input(&executor, &task.steps, &task.context, task.index, task.input).await;
+                if let Some(record) = board.records().get_mut(&job_id) {
+                    record.results.push(result);
+                }
+            }
+        };
+        self.queue.run(self.max_running, worker).await;
+    }
+
+    fn status(&self, id: &str) -> Option<JobStatus> {
+        let records = self.records();
+        let record = records.get(id)?;
+        let state = if record.results.len() == record.total {
+            "done"
+        } else if record.started > 0 {
+            "running"
+        } else {
+            "queued"
+        };
+        let mut results = record.results.clone();
+        results.sort_by_key(|result| result.index);
+        Some(JobStatus {
+            id: id.to_string(),
+            pipeline: record.pipeline.clone(),
+            priority: record.priority,
+            state,
+            total: record.total,
+            finished: results.len(),
+            results,
+        })
+    }
+}
+
+/// Body of `POST /v1/execute`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecuteRequest {
+    pub provider: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub options: ProviderOptions,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Body of `POST /v1/pipelines/{name}/run`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunRequest {
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Body of `POST /v1/jobs`
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRequest {
+    /// Saved pipeline to run for every input
+    pub pipeline: String,
+    /// Batch input lines: a string, or `{"id"?, "input", "env"?}`
+    pub inputs: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Higher runs first; jobs of equal priority take turns
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Answer of `GET /v1/jobs/{id}`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: String,
+    pub pipeline: String,
+    pub priority: i32,
+    /// `queued`, `running` or `done`
+    pub state: &'static str,
+    pub total: usize,
+    pub finished: usize,
+    /// Finished inputs in input order
+    pub results: Vec<BatchResult>,
+}
+
+/// One step's output
+#[derive(Debug, Clone, Serialize)]
+pub struct StepOutput {
+    pub index: usize,
+    pub provider: String,
+    pub content: String,
+    pub metadata: HashMap<String, String>,
+}
+
+impl StepOutput {
+    fn new(index: usize, step: &PipelineStep, response: Response) -> Self {
+        Self { index, provider: step.provider.clone(), content: response.content, metadata: response.metadata }
+    }
+}
+
+/// Result of `POST /v1/pipelines/{name}/run`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResponse {
+    pub pipeline: String,
+    pub responses: Vec<StepOutput>,
+}
+
+/// A saved pipeline as listed by `GET /v1/pipelines`
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineSummary {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub chain: String,
+}
+
+/// Error answered as `{"error": "..."}`
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    /// A failed provider call: 502, or 504 for timeouts
+    fn upstream(error: &anyhow::Error) -> Self {
+        let status = match ExitCode::for_error(error) {
+            ExitCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        Self::new(status, format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> HttpResponse {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Routes of the API
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .route("/v1/providers", get(providers))
+        .route("/v1/pipelines", get(pipelines))
+        .route("/v1/pipelines/{name}/run", post(run_pipeline))
+        .route("/v1/execute", post(execute))
+        .route("/v1/jobs", post(submit_job))
+        .route("/v1/jobs/{id}", get(job_status))
+        .layer(middleware::from_fn_with_state(state.clone(), guard))
+        .with_state(state)
+}
+
+/// Listen on `addr` until the process is interrupted; other machines can only be served with a token
+pub async fn serve(addr: SocketAddr, mut state: ServerState) -> Result<()> {
+    state.loopback = addr.ip().is_loopback();
+    if !state.loopback && state.token.is_none() {
+        return Err(anyhow!("Serving on {} exposes the API to other machines: set --token or AI_CLI_SERVER_TOKEN", addr.ip()));
+    }
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    tracing::info!(%addr, "serving");
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("Server failed")
+}
+
+/// Serve only `GET /metrics` on `addr`, e.g. while a batch runs
+pub async fn serve_metrics(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    tracing::info!(%addr, "serving metrics");
+    let router = Router::new().route("/metrics", get(move || async move { exposition(&metrics) }));
+    axum::serve(listener, router).await.context("Metrics server failed")
+}
+
+/// Check the `Host` header on loopback and the bearer token when one is set
+async fn guard(State(state): State<ServerState>, request: Request, next: Next) -> Result<HttpResponse, ApiError> {
+    if state.loopback
+        && let Some(host) = request.headers().get(header::HOST)
+        && !host.to_str().is_ok_and(is_loopback_host)
+    {
+        return Err(ApiError::new(StatusCode::MISDIRECTED_REQUEST, "This server only answers requests for localhost"));
+    }
+    if let Some(token) = &state.token
+        && request.uri().path() != "/health"
+    {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| same_secret(given.as_bytes(), token.as_bytes())) {
+            return Err(ApiError::new(StatusCode::UNAUTHORIZED, "Missing or wrong bearer token"));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// Whether a `Host` header names this machine: `localhost` or a loopback address, with any port
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Compare secrets without stopping at the first difference
+fn same_secret(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn metrics(State(state): State<ServerState>) -> HttpResponse {
+    exposition(&state.metrics)
+}
+
+/// Metrics with the Prometheus text format's content type
+fn exposition(metrics: &Metrics) -> HttpResponse {
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render()).into_response()
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+async fn providers(State(state): State<ServerState>) -> Json<Vec<String>> {
+    let mut names = state.executor.get_provider_names();
+    names.sort();
+    Json(names)
+}
+
+async fn pipelines(State(state): State<ServerState>) -> Result<Json<Vec<PipelineSummary>>, ApiError> {
+    let Some(store) = &state.store else {
+        return Ok(Json(Vec::new()));
+    };
+    let names = store.list().map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+    let summaries = names
+        .into_iter()
+        .filter_map(|name| store.load(&name).ok())
+        .map(|def| PipelineSummary { chain: def.to_chain(), name: def.name, description: def.description })
+        .collect();
+    Ok(Json(summaries))
+}
+
+async fn execute(State(state): State<ServerState>, Json(request): Json<ExecuteRequest>) -> Result<HttpResponse, ApiError> {
+    let steps = vec![PipelineStep::new(request.provider, request.prompt).with_options(request.options)];
+    check_providers(&state.executor, &steps)?;
+    let mut context = Context::new();
+    context.environment.extend(request.env);
+
+    if request.stream {
+        return Ok(sse(state.executor, steps, context).into_response());
+    }
+    let response = state.executor.execute(&steps, context).await.map_err(|e| ApiError::upstream(&e))?;
+    let output = response.into_iter().next().map(|r| StepOutput::new(0, &steps[0], r));
+    Ok(Json(output).into_response())
+}
+
+async fn run_pipeline(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+    request: Option<Json<RunRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let store = state.store.as_ref().ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No pipeline store configured"))?;
+    let steps = store
+        .load(&name)
+        .and_then(|def| def.to_steps())
+        .map_err(|e| ApiError::new(StatusCode::NOT_FOUND, format!("{:#}", e)))?;
+    check_providers(&state.executor, &steps)?;
+
+    let mut context = Context::new();
+    context.environment.extend(request.env);
+    if let Some(input) = request.input {
+        context.environment.insert("INPUT".to_string(), input.clone());
+        context.add_message(Message::new(MessageRole::User, input));
+    }
+
+    if request.stream {
+        return Ok(sse(state.executor, steps, context).into_response());
+    }
+    let responses = state.executor.execute(&steps, context).await.map_err(|e| ApiError::upstream(&e))?;
+    let responses = steps.iter().zip(responses).enumerate().map(|(i, (step, r))| StepOutput::new(i, step, r)).collect();
+    Ok(Json(RunResponse { pipeline: name, responses }).into_response())
+}
+
+async fn submit_job(State(state): State<ServerState>, Json(request): Json<JobRequest>) -> Result<HttpResponse, ApiError> {
+    let store = state.store.as_ref().ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No pipeline store configured"))?;
+    let steps = store
+        .load(&request.pipeline)
+        .and_then(|def| def.to_steps())
+        .map_err(|e| ApiError::new(StatusCode::NOT_FOUND, format!("{:#}", e)))?;
+    check_providers(&state.executor, &steps)?;
+    let inputs = request
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, value)| BatchInput::parse(&value.to_string()).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Input {}: {}", i + 1, e))))
+        .collect::<Result<Vec<_>, _>>()?;
+    if inputs.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "A job needs at least one input"));
+    }
+    let total = inputs.len();
+    let id = state.jobs.submit(&state.executor, request, steps, inputs);
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id, "inputs": total }))).into_response())
+}
+
+async fn job_status(State(state): State<ServerState>, Path(id): Path<String>) -> Result<Json<JobStatus>, ApiError> {
+    state.jobs.status(&id).map(Json).ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("No job '{}'", id)))
+}
+
+/// Reject steps naming providers that are not registered
+fn check_providers(executor: &PipelineExecutor, steps: &[PipelineStep]) -> Result<(), ApiError> {
+    let names = executor.get_provider_names();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    PipelineParser::validate_providers(steps, &names).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// Run `steps` one at a time, sending an event as each finishes
+fn sse(executor: Arc<PipelineExecutor>, steps: Vec<PipelineStep>, context: Context) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = futures::stream::unfold(Some((0, context)), move |state| {
+        let executor = executor.clone();
+        let steps = steps.clone();
+        async move {
+            let (index, mut context) = state?;
+            let Some(step) = steps.get(index) else {
+                executor.release_context(&mut context).await;
+                let done = Event::default().event("done").data(serde_json::json!({ "steps": steps.len() }).to_string());
+                return Some((Ok(done), None));
+            };
+            match executor.run_step(step, index, &mut context, true).await {
+                Ok(response) => {
+                    let output = serde_json::to_string(&StepOutput::new(index, step, response)).unwrap_or_default();
+                    Some((Ok(Event::default().event("step").data(output)), Some((index + 1, context))))
+                }
+                Err(e) => {
+                    executor.release_context(&mut context).await;
+                    let error = serde_json::json!({ "error": format!("{:#}", e) }).to_string();
+                    Some((Ok(Event::default().event("error").data(error)), None))
+                }
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
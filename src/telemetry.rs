@@ -0,0 +1,280 @@
+//! OpenTelemetry traces over OTLP/HTTP
+//!
+//! With `--otlp-endpoint` (or `OTEL_EXPORTER_OTLP_ENDPOINT`) the executor's
+//! `pipeline`, `step` and `http` spans are sent as OTLP JSON to
+//! `<endpoint>/v1/traces`, so one trace shows a pipeline, its steps and every
+//! provider call they made. Spans are batched and posted every few seconds;
+//! [`flush`] sends what is left before the process exits.
+
+use anyhow::{Result, anyhow};
+use reqwest::header::CONTENT_TYPE;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::providers::http::{self, Transport};
+
+/// Path under the endpoint traces are posted to
+pub const TRACES_PATH: &str = "/v1/traces";
+
+/// How often batched spans are posted
+pub const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `service.name` of exported spans
+const SERVICE_NAME: &str = "ai-cli";
+
+static EXPORTER: OnceLock<Arc<OtlpExporter>> = OnceLock::new();
+
+/// A finished span
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRecord {
+    /// 32 hex digits, shared by every span of a pipeline run
+    pub trace_id: String,
+    /// 16 hex digits
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_unix_nanos: u128,
+    pub end_unix_nanos: u128,
+    /// Span fields as OTLP `AnyValue`s
+    pub attributes: Vec<(String, serde_json::Value)>,
+}
+
+impl SpanRecord {
+    /// The span's string attribute `key`
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == key).and_then(|(_, v)| v["stringValue"].as_str())
+    }
+
+    fn set(&mut self, key: &str, value: serde_json::Value) {
+        match self.attributes.iter_mut().find(|(k, _)| k == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((key.to_string(), value)),
+        }
+    }
+
+    /// The span in OTLP JSON form
+    fn to_json(&self) -> serde_json::Value {
+        let mut span = serde_json::json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            // Provider calls are outgoing requests; the rest is work inside ai-cli
+            "kind": if self.name == "http" { 3 } else { 1 },
+            "startTimeUnixNano": self.start_unix_nanos.to_string(),
+            "endTimeUnixNano": self.end_unix_nanos.to_string(),
+            "attributes": self.attributes.iter().map(|(key, value)| serde_json::json!({ "key": key, "value": value })).collect::<Vec<_>>(),
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = parent.clone().into();
+        }
+        match self.attribute("status") {
+            Some("ok") => span["status"] = serde_json::json!({ "code": 1 }),
+            Some("error") => span["status"] = serde_json::json!({ "code": 2 }),
+            _ => {}
+        }
+        span
+    }
+}
+
+/// Collects finished spans and posts them to an OTLP/HTTP collector
+pub struct OtlpExporter {
+    url: String,
+    transport: Arc<dyn Transport>,
+    pending: Mutex<Vec<SpanRecord>>,
+}
+
+impl OtlpExporter {
+    /// Export to the collector at `endpoint`, e.g. `http://localhost:4318`
+    pub fn new(endpoint: &str) -> Self {
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = if endpoint.ends_with(TRACES_PATH) { endpoint.to_string() } else { format!("{}{}", endpoint, TRACES_PATH) };
+        Self { url, transport: http::default_transport(), pending: Mutex::default() }
+    }
+
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// URL spans are posted to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A layer recording the crate's spans into this exporter
+    pub fn layer(self: &Arc<Self>) -> OtlpLayer {
+        OtlpLayer { exporter: self.clone() }
+    }
+
+    /// Spans finished but not yet exported
+    pub fn pending(&self) -> Vec<SpanRecord> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn push(&self, span: SpanRecord) {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).push(span);
+    }
+
+    /// OTLP `ExportTraceServiceRequest` JSON for `spans`
+    pub fn payload(spans: &[SpanRecord]) -> serde_json::Value {
+        serde_json::json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": SERVICE_NAME } },
+                        { "key": "service.version", "value": { "stringValue": env!("CARGO_PKG_VERSION") } },
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "spans": spans.iter().map(SpanRecord::to_json).collect::<Vec<_>>(),
+                }],
+            }],
+        })
+    }
+
+    /// Post the pending spans, returning how many were sent
+    ///
+    /// The request goes straight to the transport so exporting makes no spans of its own.
+    pub async fn flush(&self) -> Result<usize> {
+        let spans = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if spans.is_empty() {
+            return Ok(0);
+        }
+        let request = http::shared_client()
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Self::payload(&spans).to_string())
+            .build()?;
+        let response = self.transport.execute("otlp", request).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("OTLP collector at {} answered {}", self.url, response.status()));
+        }
+        Ok(spans.len())
+    }
+
+    /// Flush every [`EXPORT_INTERVAL`] in the background
+    fn spawn(self: &Arc<Self>) {
+        let exporter = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = exporter.flush().await {
+                    eprintln!("Failed to export traces: {:#}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Install the process-wide exporter for `endpoint` and return its layer
+///
+/// Must be called from inside the Tokio runtime, which runs the periodic export.
+pub fn install(endpoint: &str) -> Result<OtlpLayer> {
+    let exporter = Arc::new(OtlpExporter::new(endpoint));
+    EXPORTER.set(exporter.clone()).map_err(|_| anyhow!("Trace export is already set up"))?;
+    exporter.spawn();
+    Ok(exporter.layer())
+}
+
+/// Send the spans the installed exporter has not posted yet
+pub async fn flush() {
+    if let Some(exporter) = EXPORTER.get()
+        && let Err(e) = exporter.flush().await
+    {
+        eprintln!("Failed to export traces: {:#}", e);
+    }
+}
+
+/// Records spans into an [`OtlpExporter`] as they close
+pub struct OtlpLayer {
+    exporter: Arc<OtlpExporter>,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanRecord>().map(|r| (r.trace_id.clone(), r.span_id.clone())));
+        let (trace_id, parent_span_id) = match parent {
+            Some((trace_id, parent)) => (trace_id, Some(parent)),
+            None => (format!("{}{}", new_id(), new_id()), None),
+        };
+        let mut record = SpanRecord {
+            trace_id,
+            span_id: new_id(),
+            parent_span_id,
+            name: attrs.metadata().name().to_string(),
+            start_unix_nanos: unix_nanos(),
+            end_unix_nanos: 0,
+            attributes: Vec::new(),
+        };
+        attrs.record(&mut Fields(&mut record));
+        span.extensions_mut().insert(record);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(record) = span.extensions_mut().get_mut::<SpanRecord>()
+        {
+            values.record(&mut Fields(record));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id)
+            && let Some(mut record) = span.extensions_mut().remove::<SpanRecord>()
+        {
+            record.end_unix_nanos = unix_nanos();
+            self.exporter.push(record);
+        }
+    }
+}
+
+/// Copies span fields into a record's attributes
+struct Fields<'a>(&'a mut SpanRecord);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.set(field.name(), serde_json::json!({ "stringValue": value }));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.set(field.name(), serde_json::json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.set(field.name(), serde_json::json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.set(field.name(), serde_json::json!({ "boolValue": value }));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+/// 16 hex digits that differ between calls
+fn new_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let seed = format!("{}:{}:{}", unix_nanos(), std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+    let hash = seed.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3));
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,340 @@
+//! `ai-cli pipeline --tui`: a terminal UI for a running pipeline
+//!
+//! Shows the step list, the selected step's output as it streams in and
+//! token/cost totals, all driven by [`PipelineEvent`]s. When a step fails the
+//! run pauses so it can be retried (`r`), skipped (`s`) or abandoned (`q`).
+
+use anyhow::{Result, anyhow};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::pipeline::{PipelineEvent, PipelineExecutor, PipelineStep};
+use crate::providers::pricing::Usage;
+use crate::providers::{Context, Response};
+
+/// How often the screen is redrawn and the keyboard polled while a step runs
+const TICK: Duration = Duration::from_millis(50);
+
+/// Where a step is in its run
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Pending,
+    Running,
+    /// Waiting to make this retry attempt
+    Retrying(usize),
+    Done,
+    Failed(String),
+    Skipped,
+}
+
+impl StepStatus {
+    fn symbol(&self) -> (&'static str, Color) {
+        match self {
+            StepStatus::Pending => ("·", Color::DarkGray),
+            StepStatus::Running => ("▶", Color::Cyan),
+            StepStatus::Retrying(_) => ("↻", Color::Yellow),
+            StepStatus::Done => ("✓", Color::Green),
+            StepStatus::Failed(_) => ("✗", Color::Red),
+            StepStatus::Skipped => ("↷", Color::DarkGray),
+        }
+    }
+}
+
+/// One row of the step list
+#[derive(Debug, Clone)]
+pub struct StepView {
+    pub label: String,
+    pub status: StepStatus,
+    pub output: String,
+    pub usage: Usage,
+    pub cost_usd: f64,
+}
+
+/// What a key press asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Retry,
+    Skip,
+    Quit,
+}
+
+impl Action {
+    pub fn for_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => Some(Action::Up),
+            KeyCode::Down | KeyCode::Char('j') => Some(Action::Down),
+            KeyCode::Char('r') => Some(Action::Retry),
+            KeyCode::Char('s') => Some(Action::Skip),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Everything on screen, updated from pipeline events
+#[derive(Debug, Clone)]
+pub struct TuiState {
+    pub steps: Vec<StepView>,
+    /// Step whose output is shown
+    pub selected: usize,
+    /// Move the selection to whichever step starts next
+    follow: bool,
+    pub finished: bool,
+}
+
+impl TuiState {
+    pub fn new(steps: &[PipelineStep]) -> Self {
+        let steps = steps
+            .iter()
+            .map(|step| StepView {
+                label: format!("{}:{}", step.provider, step.action),
+                status: StepStatus::Pending,
+                output: String::new(),
+                usage: Usage::default(),
+                cost_usd: 0.0,
+            })
+            .collect();
+        Self { steps, selected: 0, follow: true, finished: false }
+    }
+
+    pub fn apply(&mut self, event: &PipelineEvent) {
+        match event {
+            PipelineEvent::StepStarted { step_index, .. } => {
+                if let Some(view) = self.steps.get_mut(*step_index) {
+                    view.status = StepStatus::Running;
+                    view.output.clear();
+                }
+                if self.follow {
+                    self.selected = (*step_index).min(self.steps.len().saturating_sub(1));
+                }
+            }
+            PipelineEvent::Chunk { step_index, text } => {
+                if let Some(view) = self.steps.get_mut(*step_index) {
+                    view.output.push_str(text);
+                }
+            }
+            PipelineEvent::RetryScheduled { step_index, attempt, error, .. } => {
+                if let Some(view) = self.steps.get_mut(*step_index) {
+                    view.status = StepStatus::Retrying(*attempt);
+                    view.output = format!("Attempt failed: {}\nRetrying (attempt {})...\n", error, attempt);
+                }
+            }
+            PipelineEvent::StepCompleted { step_index, outcome, .. } => {
+                let Some(view) = self.steps.get_mut(*step_index) else { return };
+                match outcome {
+                    Ok(response) => {
+                        view.status = StepStatus::Done;
+                        view.output = response.content.clone();
+                        if let Some(usage) = Usage::from_response(response) {
+                            view.usage += usage;
+                        }
+                        view.cost_usd += response.metadata.get("cost_usd").and_then(|c| c.parse().ok()).unwrap_or(0.0);
+                    }
+                    Err(error) => view.status = StepStatus::Failed(error.clone()),
+                }
+            }
+            PipelineEvent::PipelineCompleted { .. } => self.finished = true,
+        }
+    }
+
+    pub fn skip(&mut self, step_index: usize) {
+        if let Some(view) = self.steps.get_mut(step_index) {
+            view.status = StepStatus::Skipped;
+        }
+    }
+
+    /// Move the selection; manual moves stop it following the running step
+    pub fn select(&mut self, action: Action) {
+        match action {
+            Action::Up => self.selected = self.selected.saturating_sub(1),
+            Action::Down => self.selected = (self.selected + 1).min(self.steps.len().saturating_sub(1)),
+            _ => return,
+        }
+        self.follow = false;
+    }
+
+    /// Tokens in, tokens out and cost over all steps
+    pub fn totals(&self) -> (u64, u64, f64) {
+        self.steps.iter().fold((0, 0, 0.0), |(input, output, cost), view| {
+            (input + view.usage.prompt_tokens, output + view.usage.completion_tokens, cost + view.cost_usd)
+        })
+    }
+
+    fn failed_step(&self) -> Option<usize> {
+        self.steps.iter().position(|view| matches!(view.status, StepStatus::Failed(_)))
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [header, body, footer] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, output_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(body);
+
+        let done = self.steps.iter().filter(|v| matches!(v.status, StepStatus::Done | StepStatus::Skipped)).count();
+        let (input, output, cost) = self.totals();
+        let title = format!(
+            " ai-cli pipeline  {}/{} steps  tokens {} in / {} out  ${:.4}",
+            done,
+            self.steps.len(),
+            input,
+            output,
+            cost
+        );
+        frame.render_widget(Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)), header);
+
+        let items: Vec<ListItem> = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(index, view)| {
+                let (symbol, color) = view.status.symbol();
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{} ", symbol), Style::default().fg(color)),
+                    Span::raw(format!("{}. {}", index + 1, view.label)),
+                ]))
+            })
+            .collect();
+        let mut list_state = ListState::default().with_selected(Some(self.selected));
+        let list = List::new(items)
+            .block(Block::bordered().title(" Steps "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut list_state);
+
+        let view = self.steps.get(self.selected);
+        let text = match view.map(|v| &v.status) {
+            Some(StepStatus::Failed(error)) => format!("{}\n\nError: {}", view.map_or("", |v| v.output.as_str()), error),
+            _ => view.map(|v| v.output.clone()).unwrap_or_default(),
+        };
+        // Keep the end of the output in view
+        let width = output_area.width.saturating_sub(2).max(1) as usize;
+        let lines: usize = text.lines().map(|line| line.chars().count().max(1).div_ceil(width)).sum();
+        let scroll = lines.saturating_sub(output_area.height.saturating_sub(2) as usize);
+        let title = view.map(|v| format!(" Output: {} ", v.label)).unwrap_or_default();
+        let output = Paragraph::new(text)
+            .block(Block::bordered().title(title))
+            .wrap(Wrap { trim: false })
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+        frame.render_widget(output, output_area);
+
+        let help = if self.finished {
+            " ↑/↓ select  q quit"
+        } else if self.failed_step().is_some() {
+            " Step failed: r retry  s skip  q quit"
+        } else {
+            " ↑/↓ select  q abort"
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), footer);
+    }
+}
+
+/// Run `steps` under the TUI, returning their responses (empty ones for skipped steps) and the final context
+pub async fn run(executor: &mut PipelineExecutor, steps: &[PipelineStep], context: Context) -> Result<(Vec<Response>, Context)> {
+    let events = executor.subscribe();
+    let mut terminal = ratatui::init();
+    let mut context = context;
+    let result = drive(executor, &mut terminal, steps, &mut context, events).await;
+    ratatui::restore();
+    executor.release_context(&mut context).await;
+    result.map(|responses| (responses, context))
+}
+
+async fn drive(
+    executor: &PipelineExecutor,
+    terminal: &mut DefaultTerminal,
+    steps: &[PipelineStep],
+    context: &mut Context,
+    mut events: UnboundedReceiver<PipelineEvent>,
+) -> Result<Vec<Response>> {
+    let mut state = TuiState::new(steps);
+    let mut responses = Vec::with_capacity(steps.len());
+    let mut ticker = tokio::time::interval(TICK);
+    let mut index = 0;
+
+    while index < steps.len() {
+        let outcome = {
+            let step = executor.run_step(&steps[index], index, context, true);
+            tokio::pin!(step);
+            loop {
+                tokio::select! {
+                    outcome = &mut step => break outcome,
+                    _ = ticker.tick() => {
+                        drain(&mut events, &mut state);
+                        terminal.draw(|frame| state.render(frame))?;
+                        if let Some(Action::Quit) = handle_keys(&mut state)? {
+                            return Err(anyhow!("Pipeline aborted at step {}", index + 1));
+                        }
+                    }
+                }
+            }
+        };
+        drain(&mut events, &mut state);
+        terminal.draw(|frame| state.render(frame))?;
+
+        match outcome {
+            Ok(response) => {
+                responses.push(response);
+                index += 1;
+            }
+            Err(error) => match wait_for(&mut state, terminal, &[Action::Retry, Action::Skip, Action::Quit])? {
+                Action::Retry => {}
+                Action::Skip => {
+                    state.skip(index);
+                    responses.push(Response::new("").with_metadata("skipped", "true"));
+                    index += 1;
+                }
+                _ => return Err(error),
+            },
+        }
+    }
+
+    state.finished = true;
+    wait_for(&mut state, terminal, &[Action::Quit])?;
+    Ok(responses)
+}
+
+fn drain(events: &mut UnboundedReceiver<PipelineEvent>, state: &mut TuiState) {
+    while let Ok(event) = events.try_recv() {
+        state.apply(&event);
+    }
+}
+
+/// Apply pending key presses, returning the last non-navigation action
+fn handle_keys(state: &mut TuiState) -> Result<Option<Action>> {
+    let mut action = None;
+    while event::poll(Duration::ZERO)? {
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && let Some(pressed) = Action::for_key(key.code)
+        {
+            state.select(pressed);
+            if !matches!(pressed, Action::Up | Action::Down) {
+                action = Some(pressed);
+            }
+        }
+    }
+    Ok(action)
+}
+
+/// Block until one of `wanted` is pressed, redrawing after every key
+fn wait_for(state: &mut TuiState, terminal: &mut DefaultTerminal, wanted: &[Action]) -> Result<Action> {
+    loop {
+        terminal.draw(|frame| state.render(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && let Some(action) = Action::for_key(key.code)
+        {
+            state.select(action);
+            if wanted.contains(&action) {
+                return Ok(action);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{ArtifactsDir, JsonExtractorTransform, PipelineExecutor, PipelineStep};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::Context;
+use clap::Parser;
+use std::sync::Arc;
+
+#[test]
+fn test_file_stems() {
+    assert_eq!(ArtifactsDir::file_stem(0, &PipelineStep::new("claude", "design")), "01-claude-design");
+    assert_eq!(ArtifactsDir::file_stem(9, &PipelineStep::new("gemini", "Review the API: errors & docs!\nmore")), "10-gemini-review-the-api-errors-docs");
+    assert_eq!(ArtifactsDir::file_stem(1, &PipelineStep::new("codex", "設計")), "02-codex-設計");
+    assert_eq!(ArtifactsDir::file_stem(2, &PipelineStep::new("codex", "?!")), "03-codex");
+}
+
+#[tokio::test]
+async fn test_steps_write_numbered_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply(r#"{"plan": "three modules"}"#)));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_reply("looks good")));
+    executor.set_artifacts_dir(ArtifactsDir::create(dir.path().join("out")).unwrap());
+
+    let steps = vec![
+        PipelineStep::new("claude", "design").with_transform(Arc::new(JsonExtractorTransform::new("plan"))),
+        PipelineStep::new("gemini", "review"),
+    ];
+    let responses = executor.execute(&steps, Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "three modules");
+
+    let out = dir.path().join("out");
+    let read = |name: &str| std::fs::read_to_string(out.join(name)).unwrap();
+    assert_eq!(read("01-claude-design.md"), r#"{"plan": "three modules"}"#);
+    assert_eq!(read("01-claude-design.transformed.md"), "three modules");
+    let meta: serde_json::Value = serde_json::from_str(&read("01-claude-design.meta.json")).unwrap();
+    assert_eq!(meta["step"], 1);
+    assert_eq!(meta["action"], "design");
+    assert_eq!(meta["transformed"], true);
+    assert_eq!(meta["metadata"]["provider"], "claude");
+    assert_eq!(read("02-gemini-review.md"), "looks good");
+    assert!(!out.join("02-gemini-review.transformed.md").exists());
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 5);
+}
+
+#[test]
+fn test_artifacts_dir_flag() {
+    let args = CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--artifacts-dir", "out"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Pipeline { artifacts_dir: Some(ref dir), .. }) if dir.as_os_str() == "out"));
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--artifacts-dir", "out", "--input-file", "in.jsonl"]).is_err());
+}
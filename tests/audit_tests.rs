@@ -0,0 +1,152 @@
+use ai_cli::cli::{AuditAction, CliArgs, Command};
+use ai_cli::config::Config;
+use ai_cli::history::audit::{self, AuditEntry, AuditLog, AuditSettings};
+use ai_cli::history::stats::TimeRange;
+use ai_cli::pipeline::{ExecutionConfig, PipelineExecutor, PipelineStep};
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::idempotency::content_hash;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{Context, ProviderOptions};
+use clap::Parser;
+use std::sync::Arc;
+
+const HELLO: &str = r#"{"content":[{"type":"text","text":"Hello!"}],"model":"claude-3-5-sonnet-20240620","usage":{"input_tokens":9,"output_tokens":2}}"#;
+
+fn entry(timestamp: u64, provider: &str) -> AuditEntry {
+    AuditEntry {
+        timestamp,
+        user: "dev".to_string(),
+        host: "box".to_string(),
+        provider: provider.to_string(),
+        model: None,
+        step: 1,
+        attempt: 1,
+        idempotency_key: None,
+        prompt_hash: "0123456789abcdef".to_string(),
+        prompt: None,
+        response_hash: None,
+        response: None,
+        prompt_tokens: None,
+        completion_tokens: None,
+        cost_usd: None,
+        status: "ok".to_string(),
+        error: None,
+    }
+}
+
+#[tokio::test]
+async fn test_every_provider_call_is_audited() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let mut executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 1, retry_delay_ms: 1, ..ExecutionConfig::default() });
+    executor.set_audit_log(log.clone());
+    executor.set_options(ProviderOptions { system: Some("Be terse.".into()), ..ProviderOptions::default() });
+    let transport = Arc::new(FakeTransport::new().with_status(200, HELLO));
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("key".to_string()).with_transport(transport)));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_error("overloaded").with_reply("fine")));
+
+    let steps = vec![PipelineStep::new("claude", "Say hello"), PipelineStep::new("gemini", "Review")];
+    let responses = executor.execute(&steps, Context::new()).await.unwrap();
+
+    let entries = log.read().unwrap();
+    assert_eq!(entries.len(), 3);
+    let hello = &entries[0];
+    assert_eq!((hello.provider.as_str(), hello.step, hello.attempt), ("claude", 1, 1));
+    assert_eq!(hello.model.as_deref(), Some("claude-3-5-sonnet-20240620"));
+    assert_eq!((hello.prompt_tokens, hello.completion_tokens), (Some(9), Some(2)));
+    assert!(hello.cost_usd.is_some());
+    assert_eq!(hello.prompt.as_deref(), Some("Be terse.\n\nSay hello"));
+    assert_eq!(hello.prompt_hash, content_hash(&["Be terse.\n\nSay hello".as_bytes()]));
+    assert_eq!(hello.response.as_deref(), Some("Hello!"));
+    assert_eq!(hello.idempotency_key.as_ref(), responses[0].metadata.get("idempotency_key"));
+    assert!(!hello.user.is_empty() && hello.timestamp > 0);
+
+    let (failed, retried) = (&entries[1], &entries[2]);
+    assert_eq!((failed.status.as_str(), failed.attempt), ("error", 1));
+    assert!(failed.error.as_deref().unwrap().contains("overloaded"));
+    assert_eq!((retried.status.as_str(), retried.attempt, retried.step), ("ok", 2, 2));
+    assert_eq!(failed.idempotency_key, retried.idempotency_key);
+}
+
+#[test]
+fn test_hashed_content_keeps_no_prompt_or_reply_text() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = AuditLog::new(dir.path().join("audit.jsonl")).hash_content(true);
+    let call = audit::AuditCall {
+        provider: "claude",
+        model: Some("claude-test"),
+        step: 1,
+        attempt: 1,
+        idempotency_key: None,
+        system: None,
+        prompt: "our secret roadmap",
+    };
+    log.record(&call, Ok(&ai_cli::providers::Response::new("noted")));
+
+    let text = std::fs::read_to_string(log.path()).unwrap();
+    assert!(!text.contains("secret roadmap") && !text.contains("noted"), "{}", text);
+    let entry = &log.read().unwrap()[0];
+    assert_eq!(entry.prompt_hash, content_hash(&["our secret roadmap".as_bytes()]));
+    assert_eq!(entry.response_hash, Some(content_hash(&["noted".as_bytes()])));
+    assert_eq!(entry.model.as_deref(), Some("claude-test"));
+}
+
+#[test]
+fn test_log_rotates_and_is_read_across_rotated_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = AuditLog::new(dir.path().join("audit.jsonl")).rotate_at(1, 2);
+    for timestamp in 1..=4 {
+        log.append(&entry(timestamp, "claude")).unwrap();
+    }
+    // Each append finds the log full, so only the newest files survive
+    assert!(dir.path().join("audit.1.jsonl").exists());
+    assert!(dir.path().join("audit.2.jsonl").exists());
+    assert!(!dir.path().join("audit.3.jsonl").exists());
+    let timestamps: Vec<u64> = log.read().unwrap().iter().map(|e| e.timestamp).collect();
+    assert_eq!(timestamps, vec![2, 3, 4]);
+}
+
+#[test]
+fn test_query_filters_by_time_provider_and_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = AuditLog::new(dir.path().join("audit.jsonl"));
+    for (timestamp, provider) in [(100, "claude"), (200, "gemini"), (300, "claude"), (400, "claude")] {
+        log.append(&entry(timestamp, provider)).unwrap();
+    }
+    let since = TimeRange { since: Some(150), until: None };
+    let claude: Vec<u64> = log.query(since, Some("claude"), None).unwrap().iter().map(|e| e.timestamp).collect();
+    assert_eq!(claude, vec![300, 400]);
+    let last: Vec<u64> = log.query(TimeRange::default(), None, Some(2)).unwrap().iter().map(|e| e.timestamp).collect();
+    assert_eq!(last, vec![300, 400]);
+
+    let mut failed = entry(1_760_520_612, "gemini");
+    failed.status = "error".to_string();
+    failed.error = Some("quota exceeded\nretry later".to_string());
+    failed.prompt_tokens = Some(5);
+    failed.completion_tokens = Some(0);
+    assert_eq!(
+        audit::format_table(&[failed]),
+        "2025-10-15 09:30:12  dev@box  gemini  step 1 #1  5+0  -  0123456789abcdef  error: quota exceeded\n"
+    );
+}
+
+#[test]
+fn test_audit_settings_and_arguments_parse() {
+    let config: Config = toml::from_str("[audit]\nenabled = true\nhash_content = true\nmax_size_kb = 64\n").unwrap();
+    assert!(config.audit.enabled && config.audit.hash_content);
+    assert_eq!((config.audit.max_size_kb, config.audit.keep), (64, AuditSettings::default().keep));
+    assert!(!Config::default().audit.enabled);
+
+    let args = CliArgs::try_parse_from(["ai-cli", "audit", "show", "--since", "7d", "-p", "claude", "-n", "20", "--json"]).unwrap();
+    match args.command {
+        Some(Command::Audit { action: AuditAction::Show { since, until, provider, limit, json } }) => {
+            assert_eq!(since.as_deref(), Some("7d"));
+            assert_eq!(until, None);
+            assert_eq!(provider.as_deref(), Some("claude"));
+            assert_eq!(limit, Some(20));
+            assert!(json);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+}
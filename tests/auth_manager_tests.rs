@@ -56,3 +56,198 @@ async fn test_auth_manager_unknown_provider() {
     let auth = manager.detect_auth("unknown_provider").await;
     assert!(auth.is_err());
 }
+
+#[tokio::test]
+async fn test_detect_auth_source_programmatic() {
+    use ai_cli::auth::AuthSource;
+
+    let mut manager = AuthManager::new();
+    manager.set_api_key("codex", "codex_key");
+
+    // A CLI session marker on the machine would take precedence
+    if let Ok((AuthMethod::ApiKey { key }, source)) = manager.detect_auth_source("codex").await {
+        assert_eq!(key, "codex_key");
+        assert_eq!(source, AuthSource::Programmatic);
+    }
+}
+
+#[tokio::test]
+async fn test_credential_store_round_trip() {
+    use ai_cli::auth::CredentialStore;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials.toml");
+
+    let mut store = CredentialStore::load(&path).unwrap();
+    assert!(store.providers().is_empty());
+    store.set("claude", "sk-ant-1234567890abcdef");
+    store.set("gemini", "gemini-key");
+    store.save().unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let mut reloaded = CredentialStore::load(&path).unwrap();
+    assert_eq!(reloaded.get("claude"), Some("sk-ant-1234567890abcdef"));
+    assert_eq!(reloaded.providers(), vec!["claude", "gemini"]);
+
+    assert!(reloaded.remove("claude").unwrap());
+    assert!(!reloaded.remove("claude").unwrap());
+    reloaded.save().unwrap();
+    assert_eq!(CredentialStore::load(&path).unwrap().get("claude"), None);
+
+    // Rewriting a file someone loosened leaves it private again, without a temp file behind
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        reloaded.save().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
+
+#[test]
+fn test_keyring_entries_keep_the_key_out_of_the_file() {
+    use ai_cli::auth::CredentialStore;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials.toml");
+    std::fs::write(&path, "[claude]\nkeyring = true\n\n[gemini]\napi_key = \"gemini-key\"\n").unwrap();
+    let store = CredentialStore::load(&path).unwrap();
+    assert!(store.in_keyring("claude"));
+    assert_eq!(store.get("claude"), None);
+    assert!(!store.in_keyring("gemini"));
+    assert_eq!(store.get("gemini"), Some("gemini-key"));
+
+    store.save().unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("keyring = true") && !text.contains("api_key = \"\""), "{}", text);
+}
+
+#[tokio::test]
+async fn test_credential_store_is_last_resort() {
+    use ai_cli::auth::{AuthSource, CredentialStore};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = CredentialStore::load(dir.path().join("credentials.toml")).unwrap();
+    store.set("codex", "stored_key");
+
+    let mut manager = AuthManager::new();
+    manager.set_credential_store(store);
+
+    // Only assert when no ambient credentials exist on this machine
+    let ambient = std::env::var("CODEX_API_KEY").is_ok()
+        || dirs::home_dir().is_some_and(|h| h.join(".codex/config.json").exists());
+    if !ambient {
+        let (method, source) = manager.detect_auth_source("codex").await.unwrap();
+        assert_eq!(source, AuthSource::CredentialStore);
+        assert!(matches!(method, AuthMethod::ApiKey { key } if key == "stored_key"));
+    }
+}
+
+#[test]
+fn test_mask_key() {
+    use ai_cli::auth::mask_key;
+
+    assert_eq!(mask_key("sk-ant-1234567890abcdef"), "sk-a…cdef");
+    assert_eq!(mask_key("short"), "****");
+    assert!(!mask_key("sk-ant-1234567890abcdef").contains("1234567890"));
+}
+
+#[test]
+fn test_remove_profile_api_key_preserves_rest_of_file() {
+    use ai_cli::config::{Config, remove_profile_api_key};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "# team settings\n\
+        [profiles.work.claude]\n\
+        api_key = \"secret\"\n\
+        model = \"claude-3-opus-20240229\"\n").unwrap();
+
+    assert!(remove_profile_api_key(&path, "work", "claude").unwrap());
+    assert!(!remove_profile_api_key(&path, "work", "claude").unwrap());
+    assert!(!remove_profile_api_key(&path, "personal", "claude").unwrap());
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("# team settings"));
+    assert!(!text.contains("secret"));
+
+    let config = Config::load(&path).unwrap();
+    let claude = config.profile("work").unwrap().provider("claude").unwrap();
+    assert_eq!(claude.model.as_deref(), Some("claude-3-opus-20240229"));
+    assert_eq!(claude.api_key, None);
+}
+
+struct RotatingRefresher;
+
+#[async_trait::async_trait]
+impl ai_cli::auth::TokenRefresher for RotatingRefresher {
+    async fn refresh(&self, provider: &str, _current: &AuthMethod) -> anyhow::Result<AuthMethod> {
+        Ok(AuthMethod::AccountBased {
+            provider: provider.to_string(),
+            session_token: Some("fresh-token".to_string()),
+            expires_at: Some(std::time::SystemTime::now() + std::time::Duration::from_secs(3600)),
+        })
+    }
+}
+
+#[test]
+fn test_auth_method_expiry() {
+    use std::time::{Duration, SystemTime};
+
+    let expired = AuthMethod::AccountBased {
+        provider: "claude".to_string(),
+        session_token: Some("old".to_string()),
+        expires_at: Some(SystemTime::now() - Duration::from_secs(1)),
+    };
+    assert!(expired.is_expired());
+    assert_eq!(expired.secret(), Some("old"));
+
+    let soon = AuthMethod::AccountBased {
+        provider: "claude".to_string(),
+        session_token: None,
+        expires_at: Some(SystemTime::now() + Duration::from_secs(30)),
+    };
+    assert!(!soon.is_expired());
+    assert!(soon.expires_within(Duration::from_secs(60)));
+
+    let key = AuthMethod::ApiKey { key: "k".to_string() };
+    assert!(key.expires_at().is_none());
+    assert!(!key.expires_within(Duration::from_secs(3600)));
+}
+
+#[tokio::test]
+async fn test_managed_credentials_refresh_when_expiring() {
+    use ai_cli::auth::ManagedCredentials;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    let mut manager = AuthManager::new();
+    assert!(manager.refresher("claude").is_none());
+    manager.set_refresher("claude", Arc::new(RotatingRefresher));
+
+    let expiring = AuthMethod::AccountBased {
+        provider: "claude".to_string(),
+        session_token: Some("old".to_string()),
+        expires_at: Some(SystemTime::now() + Duration::from_secs(10)),
+    };
+    let credentials = ManagedCredentials::new("claude", expiring, manager.refresher("claude"));
+    assert!(credentials.can_refresh());
+    assert_eq!(credentials.current().await.unwrap().secret(), Some("fresh-token"));
+
+    // A session without a token yet is minted on first use
+    let session = AuthMethod::AccountBased { provider: "gemini".to_string(), session_token: None, expires_at: None };
+    let credentials = ManagedCredentials::new("gemini", session, Some(Arc::new(RotatingRefresher)));
+    assert_eq!(credentials.current().await.unwrap().secret(), Some("fresh-token"));
+
+    // Without a hook, expiring credentials are used as-is and refresh fails
+    let static_key = ManagedCredentials::new("gemini", AuthMethod::ApiKey { key: "k".to_string() }, None);
+    assert_eq!(static_key.current().await.unwrap().secret(), Some("k"));
+    assert!(static_key.refresh().await.is_err());
+}
@@ -0,0 +1,270 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{BatchInput, BatchRunner, PipelineExecutor, PipelineStep};
+use ai_cli::pipeline::batch::RateLimiter;
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use clap::Parser;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Echoes the pending user turn after a delay, tracking how many calls overlap
+#[derive(Default)]
+struct SlowEcho {
+    running: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+#[async_trait]
+impl AIProvider for SlowEcho {
+    async fn execute(&self, prompt: &str, context: &Context) -> anyhow::Result<Response> {
+        let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        let input = context.conversation_history.last().map(|m| m.content.clone()).unwrap_or_default();
+        // Later inputs finish first so ordering is observable
+        let delay = if input.contains("first") { 60 } else { 10 };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        if input.contains("fail") {
+            return Err(anyhow!("cannot handle {}", input));
+        }
+        Ok(Response::new(format!("{}: {}", prompt, input)))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "echo"
+    }
+}
+
+fn inputs(lines: &[&str]) -> Vec<BatchInput> {
+    lines.iter().map(|l| BatchInput::parse(l).unwrap()).collect()
+}
+
+#[test]
+fn test_parse_batch_input_lines() {
+    let plain = BatchInput::parse(r#""summarize this""#).unwrap();
+    assert_eq!(plain.input, "summarize this");
+    assert!(plain.id.is_none());
+
+    let object = BatchInput::parse(r#"{"id": 7, "input": "hi", "env": {"LANG": "Rust"}}"#).unwrap();
+    assert_eq!(object.id.as_deref(), Some("7"));
+    assert_eq!(object.env.get("LANG").map(String::as_str), Some("Rust"));
+
+    assert!(BatchInput::parse(r#"{"id": "x"}"#).is_err());
+}
+
+#[test]
+fn test_read_file_reports_line_number() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("prompts.jsonl");
+    std::fs::write(&path, "\"one\"\n\n{\"input\": \"two\"}\nnot json\n").unwrap();
+    let err = BatchInput::read_file(&path).unwrap_err();
+    assert!(format!("{:#}", err).contains("prompts.jsonl:4"));
+
+    std::fs::write(&path, "\"one\"\n\n{\"input\": \"two\"}\n").unwrap();
+    assert_eq!(BatchInput::read_file(&path).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_runs_concurrently_and_keeps_input_order() {
+    let provider = Arc::new(SlowEcho::default());
+    let executor = PipelineExecutor::new();
+    executor.register_provider("echo", provider.clone());
+    let steps = vec![PipelineStep::new("echo", "Answer")];
+
+    let runner = BatchRunner::new(&executor, 2);
+    let context = Context::new();
+    let results: Vec<_> = runner
+        .run(&steps, &context, inputs(&[r#""first""#, r#"{"id": "b", "input": "fail me"}"#, r#""third""#]))
+        .collect()
+        .await;
+
+    assert_eq!(results.iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(results[0].output.as_deref(), Some("Answer: first"));
+    assert!(!results[1].ok);
+    assert_eq!(results[1].id.as_deref(), Some("b"));
+    assert!(results[1].error.as_deref().unwrap().contains("cannot handle fail me"));
+    assert!(results[2].ok);
+    assert_eq!(provider.peak.load(Ordering::SeqCst), 2);
+
+    let line = serde_json::to_value(&results[0]).unwrap();
+    assert_eq!(line["ok"], true);
+    assert!(line.get("error").is_none());
+}
+
+#[tokio::test]
+async fn test_batch_input_is_available_as_env_placeholder() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("echo", Arc::new(SlowEcho::default()));
+    let steps = vec![PipelineStep::new("echo", "Translate {{env.INPUT}} to {{env.LANG}}")];
+
+    let runner = BatchRunner::new(&executor, 1);
+    let context = Context::new();
+    let results: Vec<_> = runner
+        .run(&steps, &context, inputs(&[r#"{"input": "hello", "env": {"LANG": "French"}}"#]))
+        .collect()
+        .await;
+    assert_eq!(results[0].output.as_deref(), Some("Translate hello to French: hello"));
+}
+
+#[tokio::test]
+async fn test_rate_limiter_spaces_starts() {
+    let limiter = RateLimiter::per_minute(600);
+    let started = Instant::now();
+    for _ in 0..3 {
+        limiter.acquire().await;
+    }
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_jobs_requires_input_file() {
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--jobs", "4"]).is_err());
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--input-file", "in.jsonl", "--jobs", "0"]).is_err());
+
+    let args = <CliArgs as Parser>::try_parse_from([
+        "ai-cli", "pipeline", "--chain", "claude:x", "--input-file", "in.jsonl", "--jobs", "4", "--rate-limit", "30",
+    ])
+    .unwrap();
+    match args.command {
+        Some(Command::Pipeline { input_file, jobs, rate_limit, .. }) => {
+            assert_eq!(input_file.unwrap().to_str(), Some("in.jsonl"));
+            assert_eq!(jobs, 4);
+            assert_eq!(rate_limit, Some(30));
+        }
+        _ => panic!("expected pipeline command"),
+    }
+}
+
+#[tokio::test]
+async fn test_claude_batch_round_trip() {
+    use ai_cli::pipeline::{BatchJob, BatchJobStore};
+    use ai_cli::providers::ProviderOptions;
+    use ai_cli::providers::batch::{BatchApi, BatchState};
+    use ai_cli::providers::claude::ClaudeProvider;
+    use ai_cli::providers::testing::FakeTransport;
+
+    let results = [
+        r#"{"custom_id":"input-1","result":{"type":"errored","error":{"type":"error","error":{"type":"invalid_request_error","message":"too long"}}}}"#,
+        r#"{"custom_id":"input-0","result":{"type":"succeeded","message":{"content":[{"type":"text","text":"positive"}]}}}"#,
+    ].join("\n");
+    let transport = Arc::new(FakeTransport::new()
+        .with_json(serde_json::json!({ "id": "msgbatch_01", "processing_status": "in_progress" }))
+        .with_json(serde_json::json!({
+            "id": "msgbatch_01",
+            "processing_status": "ended",
+            "request_counts": { "processing": 0, "succeeded": 1, "errored": 1, "canceled": 0, "expired": 0 },
+            "results_url": "https://api.anthropic.com/v1/messages/batches/msgbatch_01/results",
+        }))
+        .with_json(serde_json::json!({
+            "id": "msgbatch_01",
+            "processing_status": "ended",
+            "results_url": "https://api.anthropic.com/v1/messages/batches/msgbatch_01/results",
+        }))
+        .with_status(200, results));
+    let api = ClaudeProvider::new("key".to_string()).with_model("claude-test").with_transport(transport.clone());
+
+    let inputs = vec![
+        BatchInput::parse(r#"{"id": "a", "input": "great"}"#).unwrap(),
+        BatchInput::parse(r#""awful""#).unwrap(),
+    ];
+    let job = BatchJob::submit(&api, "Sentiment of {{env.INPUT}}?", &Context::new(), inputs, &ProviderOptions::default()).await.unwrap();
+    assert_eq!(job.id, "msgbatch_01");
+    assert_eq!(job.provider, "claude");
+    let body = transport.requests()[0].json().unwrap();
+    assert_eq!(body["requests"][0]["custom_id"], "input-0");
+    assert_eq!(body["requests"][0]["params"]["model"], "claude-test");
+    assert_eq!(body["requests"][1]["params"]["messages"][0]["content"], "awful\n\nSentiment of awful?");
+
+    let status = api.status(&job.id).await.unwrap();
+    assert_eq!(status.state, BatchState::Ended);
+    assert_eq!((status.total, status.succeeded, status.failed), (2, 1, 1));
+
+    let results = job.results(api.results(&job.id).await.unwrap());
+    assert_eq!(results[0].id.as_deref(), Some("a"));
+    assert_eq!(results[0].output.as_deref(), Some("positive"));
+    assert!(!results[1].ok);
+    assert_eq!(results[1].error.as_deref(), Some("too long"));
+    assert_eq!(transport.requests()[3].url, "https://api.anthropic.com/v1/messages/batches/msgbatch_01/results");
+
+    let dir = std::env::temp_dir().join(format!("ai-cli-batches-{}", std::process::id()));
+    let store = BatchJobStore::new(&dir);
+    store.save(&job).unwrap();
+    assert_eq!(store.load("msgbatch_01").unwrap(), job);
+    assert_eq!(store.list().unwrap().len(), 1);
+    assert!(store.load("../secrets").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_subcommands_parse() {
+    use ai_cli::cli::BatchAction;
+
+    let args = CliArgs::try_parse_from(["ai-cli", "batch", "submit", "openai", "Summarize {{env.INPUT}}", "--input-file", "in.jsonl", "-m", "gpt-4o"]).unwrap();
+    match args.command {
+        Some(Command::Batch { action: BatchAction::Submit { provider, model, .. } }) => {
+            assert_eq!(provider, "openai");
+            assert_eq!(model.as_deref(), Some("gpt-4o"));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "batch", "submit", "gemini", "x", "--input-file", "in.jsonl"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "batch", "fetch", "msgbatch_01", "--poll-interval", "5"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "batch", "fetch", "msgbatch_01", "--wait", "--poll-interval", "5"]).is_ok());
+}
+
+#[tokio::test]
+async fn test_batch_requests_are_redacted_filtered_anonymized_and_audited() {
+    use ai_cli::context::{Anonymizer, Redactor};
+    use ai_cli::history::audit::AuditLog;
+    use ai_cli::pipeline::{BatchJob, SafetyAction, SafetyFilter, SafetyRule, SafetyScope};
+    use ai_cli::providers::ProviderOptions;
+    use ai_cli::providers::batch::BatchOutput;
+    use ai_cli::providers::claude::ClaudeProvider;
+    use ai_cli::providers::testing::FakeTransport;
+
+    let dir = tempfile::tempdir().unwrap();
+    let ticket = SafetyRule { name: "ticket".to_string(), pattern: Some(r"INC-\d+".to_string()), action: SafetyAction::Redact, scope: SafetyScope::Both, ..SafetyRule::default() };
+    let mut executor = PipelineExecutor::new();
+    executor.set_redactor(Arc::new(Redactor::new()));
+    executor.set_safety_filter(Arc::new(SafetyFilter::new().with_rule(&ticket).unwrap()));
+    executor.set_anonymizer(Arc::new(Anonymizer::new()));
+    executor.set_audit_log(Arc::new(AuditLog::new(dir.path().join("audit.jsonl"))));
+
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({ "id": "msgbatch_02", "processing_status": "in_progress" })));
+    let api = ClaudeProvider::new("key".to_string()).with_model("claude-test").with_transport(transport.clone());
+    let mut context = Context::new();
+    context.add_file_with_content("/home/alice/app/.env".into(), "ANTHROPIC_API_KEY=sk-ant-REDACTED".to_string());
+    let inputs = vec![BatchInput::parse(r#""See INC-7 in /home/alice/app/src/main.rs""#).unwrap()];
+    let job = BatchJob::submit_with(&executor, &api, "Triage {{env.INPUT}}", &context, inputs, &ProviderOptions::default()).await.unwrap();
+
+    let body = transport.requests()[0].json().unwrap().to_string();
+    for original in ["sk-ant-api03", "INC-7", "/home/alice"] {
+        assert!(!body.contains(original), "{} sent in {}", original, body);
+    }
+    assert!(body.contains("[FILTERED:ticket]") && body.contains("[PATH_2]/main.rs"), "{}", body);
+    assert_eq!(executor.redactor().unwrap().log().len(), 1);
+
+    let entries = executor.audit_log().unwrap().read().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!((entries[0].provider.as_str(), entries[0].status.as_str()), ("claude", "submitted"));
+
+    // Placeholders are kept with the job and restored when the results are fetched
+    assert!(job.placeholders.iter().any(|(_, original)| original == "/home/alice/app/src"));
+    let outputs = vec![BatchOutput { custom_id: "input-0".to_string(), result: Ok("Look at [PATH_2]/main.rs".to_string()) }];
+    let results = job.results_with(&PipelineExecutor::new(), outputs);
+    assert_eq!(results[0].output.as_deref(), Some("Look at /home/alice/app/src/main.rs"));
+
+    let outputs = vec![BatchOutput { custom_id: "input-0".to_string(), result: Ok("Closed INC-9".to_string()) }];
+    assert_eq!(job.results_with(&executor, outputs)[0].output.as_deref(), Some("Closed [FILTERED:ticket]"));
+}
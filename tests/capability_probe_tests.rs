@@ -0,0 +1,134 @@
+use ai_cli::pipeline::PipelineExecutor;
+use ai_cli::providers::probe::CapabilityCache;
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::stream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Provider whose probe reports a larger context window than its static capabilities
+struct ProbingProvider {
+    probes: AtomicUsize,
+    fail: bool,
+}
+
+impl ProbingProvider {
+    fn new(fail: bool) -> Self {
+        Self { probes: AtomicUsize::new(0), fail }
+    }
+}
+
+#[async_trait]
+impl AIProvider for ProbingProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> Result<Response> {
+        Ok(Response::new("ok"))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> Result<ResponseStream> {
+        Ok(Box::pin(stream::once(async { Ok("ok".to_string()) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "probing"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some("model-x")
+    }
+
+    async fn probe(&self) -> Result<Capabilities> {
+        self.probes.fetch_add(1, Ordering::SeqCst);
+        if self.fail {
+            return Err(anyhow!("probe failed"));
+        }
+        Ok(Capabilities {
+            supports_streaming: true,
+            supports_context: true,
+            max_tokens: 1_000_000,
+            supports_json_mode: true,
+            supports_vision: true,
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_resolve_probes_once_and_caches() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = CapabilityCache::load(dir.path().join("caps.json"));
+    let provider = ProbingProvider::new(false);
+
+    let first = cache.resolve(&provider, false).await;
+    let second = cache.resolve(&provider, false).await;
+
+    assert_eq!(first.max_tokens, 1_000_000);
+    assert_eq!(first, second);
+    assert_eq!(provider.probes.load(Ordering::SeqCst), 1);
+    assert!(cache.get("probing/model-x").is_some());
+}
+
+#[tokio::test]
+async fn test_reprobe_bypasses_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = CapabilityCache::load(dir.path().join("caps.json"));
+    let provider = ProbingProvider::new(false);
+
+    cache.resolve(&provider, false).await;
+    cache.resolve(&provider, true).await;
+
+    assert_eq!(provider.probes.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_failed_probe_falls_back_without_caching() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = CapabilityCache::load(dir.path().join("caps.json"));
+    let provider = ProbingProvider::new(true);
+
+    let capabilities = cache.resolve(&provider, false).await;
+
+    assert_eq!(capabilities, Capabilities::default());
+    assert!(cache.is_empty());
+}
+
+#[tokio::test]
+async fn test_cache_persists_across_loads() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested").join("caps.json");
+
+    let mut cache = CapabilityCache::load(&path);
+    cache.resolve(&ProbingProvider::new(false), false).await;
+    cache.save().unwrap();
+
+    let reloaded = CapabilityCache::load(&path);
+    assert_eq!(reloaded.len(), 1);
+    assert_eq!(reloaded.get("probing/model-x").unwrap().capabilities.max_tokens, 1_000_000);
+}
+
+#[tokio::test]
+async fn test_corrupt_cache_starts_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("caps.json");
+    std::fs::write(&path, "{not json").unwrap();
+
+    assert!(CapabilityCache::load(&path).is_empty());
+}
+
+#[tokio::test]
+async fn test_executor_uses_probed_capabilities() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = CapabilityCache::load(dir.path().join("caps.json"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("probing", Arc::new(ProbingProvider::new(false)));
+
+    assert_eq!(executor.capabilities("probing").unwrap().max_tokens, 4096);
+
+    executor.probe_capabilities(&mut cache, &["probing", "missing"], false).await;
+
+    assert_eq!(executor.capabilities("probing").unwrap().max_tokens, 1_000_000);
+    assert!(executor.capabilities("missing").is_none());
+}
@@ -0,0 +1,180 @@
+use ai_cli::context::chunking::chunk_source;
+use ai_cli::context::{ChunkingSettings, ContextLimits, ContextLoader, Provenance, SkipReason};
+use ai_cli::pipeline::{MapSource, PipelineDefinition, PipelineExecutor, PipelineParser};
+use ai_cli::providers::{Capabilities, Context};
+use ai_cli::providers::mock::MockProvider;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const RUST: &str = "\
+use std::fmt;
+
+/// First function
+#[inline]
+pub fn first() -> u32 {
+    let s = \"}\";
+    1
+}
+
+// A note about second
+fn second() {
+    if true {
+        println!(\"{{\");
+    }
+}
+
+impl fmt::Display for Thing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, \"thing\")
+    }
+}
+";
+
+fn starts(path: &str, text: &str, max_bytes: usize) -> Vec<(usize, usize)> {
+    chunk_source(Path::new(path), text, max_bytes).iter().map(|c| (c.start_line, c.end_line)).collect()
+}
+
+#[test]
+fn test_rust_chunks_break_between_items_with_their_docs() {
+    // The imports and first() fit together; no other pair of items does
+    assert_eq!(starts("lib.rs", RUST, 130), vec![(1, 9), (10, 16), (17, 21)]);
+
+    let chunks = chunk_source(Path::new("src/lib.rs"), RUST, 130);
+    assert!(chunks[1].text.starts_with("// A note about second\nfn second()"));
+    assert_eq!(chunks[2].key(), PathBuf::from("src/lib.rs#L17-21"));
+
+    // Chunks cover every line exactly once
+    let joined: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(joined.join("\n"), RUST.trim_end());
+
+    // Everything fits in one chunk when the limit allows
+    assert_eq!(starts("lib.rs", RUST, 10_000), vec![(1, 21)]);
+}
+
+#[test]
+fn test_python_chunks_follow_indentation() {
+    let python = "import os\n\n@cache\ndef load():\n    return 1\n\n    # still load\n\nclass Store:\n    def get(self):\n        pass\n";
+    assert_eq!(starts("store.py", python, 60), vec![(1, 2), (3, 8), (9, 11)]);
+}
+
+#[test]
+fn test_oversized_items_split_at_blank_lines() {
+    let body: String = (0..6).map(|i| format!("    let v{} = {};\n\n", i, i)).collect();
+    let text = format!("fn big() {{\n{}}}\n", body);
+    let chunks = chunk_source(Path::new("big.rs"), &text, 60);
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|c| c.text.len() <= 60));
+    assert_eq!(chunks.first().unwrap().start_line, 1);
+    assert_eq!(chunks.last().unwrap().end_line, text.lines().count());
+    assert!(chunk_source(Path::new("empty.rs"), "", 60).is_empty());
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn test_parsed_chunks_ignore_code_inside_strings() {
+    // The line scan would take the string's `fn fake()` line for an item
+    let text = "const HELP: &str = \"\nfn fake() {\n}\n\";\n\nfn real() {\n    1;\n}\n";
+    assert_eq!(starts("help.rs", text, 40), vec![(1, 5), (6, 8)]);
+    // Unparseable source falls back to the line scan
+    let broken = "fn broken( {\n\nfn next() {}\n";
+    assert_eq!(starts("broken.rs", broken, 15), vec![(1, 2), (3, 3)]);
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn test_oversized_items_split_between_members() {
+    let text = "\
+class Store:
+    def get(self):
+        value = 1
+        return value
+
+    # Writes through
+    def put(self, value):
+        self.value = value
+";
+    assert_eq!(starts("store.py", text, 75), vec![(1, 5), (6, 8)]);
+
+    let ts = "export class Store {\n  get() {\n    return 1;\n  }\n  put(v: number) {\n    this.v = v;\n  }\n}\n";
+    assert_eq!(starts("store.ts", ts, 50), vec![(1, 4), (5, 8)]);
+}
+
+#[test]
+fn test_loader_chunks_large_files_when_enabled() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("lib.rs"), RUST).unwrap();
+    let limits = ContextLimits { max_file_bytes: 130, max_total_bytes: 10_000 };
+
+    let mut skipping = ContextLoader::new(root).unwrap().with_limits(limits);
+    let mut context = Context::new();
+    let skipped = skipping.load_into(&mut context, vec![root.join("lib.rs")], Provenance::Api).unwrap();
+    assert!(matches!(skipped[0].1, SkipReason::TooLarge { .. }));
+
+    let mut chunking = ContextLoader::new(root).unwrap()
+        .with_limits(limits)
+        .with_chunking(ChunkingSettings { large_files: true });
+    let mut context = Context::new();
+    let skipped = chunking.load_into(&mut context, vec![root.join("lib.rs")], Provenance::Api).unwrap();
+    assert!(skipped.is_empty());
+    let mut keys: Vec<_> = context.file_contents.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, vec![
+        PathBuf::from("lib.rs#L1-9"),
+        PathBuf::from("lib.rs#L10-16"),
+        PathBuf::from("lib.rs#L17-21"),
+    ]);
+
+    // A file cut short by the budget is reported
+    let mut tight = ContextLoader::new(root).unwrap()
+        .with_limits(ContextLimits { max_file_bytes: 130, max_total_bytes: 150 })
+        .with_chunking(ChunkingSettings { large_files: true });
+    let mut context = Context::new();
+    let skipped = tight.load_into(&mut context, vec![root.join("lib.rs")], Provenance::Api).unwrap();
+    assert_eq!(skipped[0].1, SkipReason::BudgetExhausted { limit: 150 });
+    assert_eq!(context.file_contents.len(), 1);
+}
+
+#[test]
+fn test_map_over_chunks_round_trips() {
+    let steps = PipelineParser::parse("map[jobs=2,over=chunks](claude:review {{env.ITEM_PATH}}) -> gemini:summarize").unwrap();
+    let map = steps[0].map_step().unwrap();
+    assert_eq!(map.over, MapSource::Chunks);
+    assert_eq!(map.jobs, 2);
+    assert_eq!(steps[0].to_string(), "map[jobs=2,over=chunks](claude:review {{env.ITEM_PATH}})");
+
+    let chain = "map[jobs=2,over=chunks](claude:review {{env.ITEM_PATH}}) -> gemini:summarize";
+    let definition = PipelineDefinition::from_chain("chunked", chain).unwrap();
+    assert_eq!(definition.to_chain(), chain);
+    let yaml = definition.to_yaml().unwrap();
+    assert!(yaml.contains("over: chunks"));
+    let reloaded = PipelineDefinition::from_yaml(&yaml).unwrap();
+    assert_eq!(reloaded.to_steps().unwrap(), steps);
+
+    assert!(PipelineParser::parse("map[over=lines](claude:review)").is_err());
+}
+
+#[tokio::test]
+async fn test_map_runs_once_per_chunk() {
+    // The first chunk of b.rs is one 20000-byte line, over the mock's default window
+    let capabilities = Capabilities { max_tokens: 100_000, ..Capabilities::default() };
+    let mock = Arc::new(MockProvider::new("claude").with_capabilities(capabilities));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", mock.clone());
+    let steps = PipelineParser::parse("map[over=chunks](claude:review {{env.ITEM_PATH}} {{env.ITEM_LINES}})").unwrap();
+
+    let mut context = Context::new();
+    context.add_file_with_content(PathBuf::from("a.md"), "short".to_string());
+    context.add_file_with_content(PathBuf::from("b.rs"), "x".repeat(20_000) + "\n\nfn b() {}\n");
+    let responses = executor.execute(&steps, context).await.unwrap();
+
+    let mut prompts = mock.prompts();
+    prompts.sort();
+    assert_eq!(prompts.len(), 3);
+    assert!(prompts[0].contains("review a.md 1-1"));
+    assert!(prompts[1].contains("review b.rs 1-1"));
+    assert!(prompts[2].contains("review b.rs 2-3"));
+    let outputs: Vec<String> = serde_json::from_str(&responses[0].content).unwrap();
+    assert_eq!(outputs.len(), 3);
+    assert_eq!(responses[0].metadata.get("map_items").map(String::as_str), Some("3"));
+}
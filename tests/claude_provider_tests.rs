@@ -1,5 +1,16 @@
 use ai_cli::providers::{AIProvider, Context, MessageRole, Message};
 use ai_cli::providers::claude::ClaudeProvider;
+use futures::StreamExt;
+use ai_cli::error::{Error, ExitCode};
+use ai_cli::pipeline::{ExecutionConfig, PipelineExecutor, PipelineStep};
+use ai_cli::providers::testing::FakeTransport;
+use std::sync::Arc;
+
+const HELLO: &str = r#"{"content":[{"type":"text","text":"Hello!"}],"model":"claude-3-5-sonnet-20240620","usage":{"input_tokens":9,"output_tokens":2}}"#;
+
+fn provider_with(transport: &Arc<FakeTransport>) -> ClaudeProvider {
+    ClaudeProvider::new("test_key".to_string()).with_transport(transport.clone())
+}
 
 #[tokio::test]
 async fn test_claude_provider_name() {
@@ -19,15 +30,37 @@ async fn test_claude_provider_capabilities() {
 
 #[tokio::test]
 async fn test_claude_provider_execute() {
-    let provider = ClaudeProvider::new("test_key".to_string());
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::from_str(HELLO).unwrap()));
+    let provider = provider_with(&transport);
     let mut context = Context::new();
     context.add_message(Message::new(MessageRole::User, "Hello"));
     
-    let response = provider.execute("Say hello", &context).await;
-    assert!(response.is_ok());
+    let response = provider.execute("Say hello", &context).await.unwrap();
+    assert_eq!(response.content, "Hello!");
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url, "https://api.anthropic.com/v1/messages");
+    assert_eq!(requests[0].header("x-api-key"), Some("test_key"));
+    assert_eq!(requests[0].json().unwrap()["messages"][0]["content"], "Hello\n\nSay hello");
 }
 
 #[tokio::test]
+async fn test_claude_session_tokens_are_sent_as_bearer() {
+    use ai_cli::auth::{AuthMethod, ManagedCredentials};
+
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::from_str(HELLO).unwrap()));
+    let session = AuthMethod::AccountBased { provider: "claude".to_string(), session_token: Some("session".to_string()), expires_at: None };
+    let provider = ClaudeProvider::from_credentials(ManagedCredentials::new("claude", session, None)).with_transport(transport.clone());
+    provider.execute("Say hello", &Context::new()).await.unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].header("authorization"), Some("Bearer session"));
+    assert_eq!(requests[0].header("x-api-key"), None);
+}
+
+#[tokio::test]
+#[allow(clippy::unnecessary_unwrap)]
 async fn test_claude_provider_with_cli_auth() {
     // This test will fail if no CLI session exists, which is expected
     let provider = ClaudeProvider::from_cli_session().await;
@@ -45,9 +78,205 @@ async fn test_claude_provider_with_cli_auth() {
 
 #[tokio::test]
 async fn test_claude_provider_stream() {
-    let provider = ClaudeProvider::new("test_key".to_string());
+    let transport = Arc::new(FakeTransport::new().with_sse(
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n\
+         event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+    ));
+    let provider = provider_with(&transport);
     let context = Context::new();
     
-    let stream = provider.stream("Hello", &context).await;
-    assert!(stream.is_ok());
-}
\ No newline at end of file
+    let stream = provider.stream("Hello", &context).await.unwrap();
+    let chunks: Vec<String> = stream.map(|chunk| chunk.unwrap()).collect().await;
+    assert_eq!(chunks.concat(), "Hi");
+}
+
+#[tokio::test]
+async fn test_claude_provider_without_credentials_fails() {
+    let provider = ClaudeProvider::from_detected_cli_session();
+    assert!(provider.execute("Say hello", &Context::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_claude_rate_limit_is_retryable_provider_error() {
+    let transport = Arc::new(FakeTransport::new().with_status(429, r#"{"error":{"type":"rate_limit_error"}}"#));
+    let err = provider_with(&transport).execute("Say hello", &Context::new()).await.unwrap_err();
+    match Error::classify(&err) {
+        Some(Error::Provider(e)) => {
+            assert_eq!(e.status, Some(429));
+            assert!(e.retryable);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_claude_server_error_and_timeout_exit_codes() {
+    let transport = Arc::new(FakeTransport::new().with_status(500, "overloaded").with_timeout());
+    let provider = provider_with(&transport);
+
+    let server = provider.execute("Say hello", &Context::new()).await.unwrap_err();
+    assert_eq!(ExitCode::for_error(&server), ExitCode::Provider);
+    let timeout = provider.execute("Say hello", &Context::new()).await.unwrap_err();
+    assert_eq!(ExitCode::for_error(&timeout), ExitCode::Timeout);
+}
+
+#[tokio::test]
+async fn test_claude_unauthorized_is_auth_error() {
+    let transport = Arc::new(FakeTransport::new().with_status(401, "invalid x-api-key"));
+    let err = provider_with(&transport).execute("Say hello", &Context::new()).await.unwrap_err();
+    assert!(matches!(Error::classify(&err), Some(Error::Auth(e)) if e.provider == "claude"));
+}
+
+#[tokio::test]
+async fn test_executor_retries_transient_claude_failures() {
+    let transport = Arc::new(
+        FakeTransport::new()
+            .with_status(503, "unavailable")
+            .with_timeout()
+            .with_json(serde_json::from_str(HELLO).unwrap()),
+    );
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(provider_with(&transport)));
+    executor.set_config(ExecutionConfig { max_retries: 2, retry_delay_ms: 0, ..ExecutionConfig::default() });
+
+    let responses = executor.execute(&[PipelineStep::new("claude", "Say hello")], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "Hello!");
+    assert_eq!(transport.requests().len(), 3);
+    assert_eq!(transport.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_claude_tool_calls_round_trip() {
+    use ai_cli::providers::{ProviderOptions, ToolCall, ToolSpec};
+
+    let transport = Arc::new(FakeTransport::new()
+        .with_json(serde_json::json!({
+            "content": [
+                {"type": "text", "text": "Let me read it."},
+                {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {"path": "Cargo.toml"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }))
+        .with_json(serde_json::json!({
+            "content": [{"type": "text", "text": "The crate is ai-cli."}],
+            "stop_reason": "end_turn",
+            "model": "claude-3-5-sonnet-20240620",
+            "usage": {"input_tokens": 30, "output_tokens": 7}
+        })));
+    let provider = provider_with(&transport);
+    let tools = vec![ToolSpec::new(
+        "read_file",
+        "Read a file",
+        serde_json::json!({"type": "object", "properties": {"path": {"type": "string"}}}),
+    )];
+    let handler = |call: &ToolCall| -> anyhow::Result<String> {
+        Ok(format!("[package] name = \"ai-cli\" ({})", call.input["path"].as_str().unwrap_or_default()))
+    };
+
+    let response = provider
+        .execute_with_tools("What is the crate called?", &Context::new(), &ProviderOptions::default(), &tools, &handler)
+        .await
+        .unwrap();
+    assert_eq!(response.content, "The crate is ai-cli.");
+    assert_eq!(response.metadata.get("tool_calls").map(String::as_str), Some("1"));
+    assert_eq!(response.metadata.get("prompt_tokens").map(String::as_str), Some("40"));
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].json().unwrap()["tools"][0]["name"], "read_file");
+    let followup = requests[1].json().unwrap();
+    let messages = followup["messages"].as_array().unwrap();
+    assert_eq!(messages[1]["role"], "assistant");
+    assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+    assert_eq!(messages[2]["content"][0]["content"], "[package] name = \"ai-cli\" (Cargo.toml)");
+    assert_eq!(messages[2]["content"][0]["is_error"], false);
+}
+
+fn throttled(headers: &[(&str, &str)]) -> ai_cli::providers::testing::FakeReply {
+    ai_cli::providers::testing::FakeReply::Response {
+        status: 429,
+        headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+        body: r#"{"type":"error","error":{"type":"rate_limit_error","message":"slow down"}}"#.to_string(),
+    }
+}
+
+/// Whether each finished step was rate limited, and its throttled attempts
+type SeenResults = Arc<std::sync::Mutex<Vec<(bool, Vec<ai_cli::pipeline::RateLimitHit>)>>>;
+
+fn capture_results(executor: &mut PipelineExecutor) -> SeenResults {
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    executor.set_step_callback(Box::new(move |result| sink.lock().unwrap().push((result.is_rate_limited(), result.rate_limits.clone()))));
+    seen
+}
+
+#[tokio::test]
+async fn test_executor_honors_retry_after_on_429() {
+    let transport = Arc::new(
+        FakeTransport::new()
+            .with_reply(throttled(&[("retry-after", "0")]))
+            .with_reply(throttled(&[("retry-after-ms", "20")]))
+            .with_json(serde_json::from_str(HELLO).unwrap()),
+    );
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(provider_with(&transport)));
+    // The fixed delay would make this test take a minute; Retry-After replaces it
+    executor.set_config(ExecutionConfig { max_retries: 2, retry_delay_ms: 60_000, ..ExecutionConfig::default() });
+    let seen = capture_results(&mut executor);
+
+    let started = std::time::Instant::now();
+    let responses = executor.execute(&[PipelineStep::new("claude", "Say hello")], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "Hello!");
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+    let seen = seen.lock().unwrap();
+    let (rate_limited, hits) = &seen[0];
+    assert!(!rate_limited);
+    assert_eq!(hits.len(), 2);
+    assert_eq!((hits[0].attempt, hits[0].retry_after_ms, hits[0].waited_ms), (1, Some(0), 0));
+    assert_eq!((hits[1].attempt, hits[1].retry_after_ms, hits[1].waited_ms), (2, Some(20), 20));
+}
+
+#[tokio::test]
+async fn test_executor_gives_up_when_retry_after_is_too_long() {
+    let transport = Arc::new(FakeTransport::new().with_reply(throttled(&[("retry-after", "3600")])));
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(provider_with(&transport)));
+    let config = ExecutionConfig { max_retries: 3, retry_delay_ms: 0, max_retry_after_ms: 1000, ..ExecutionConfig::default() };
+    executor.set_config(config.clone());
+
+    let failure = executor.execute(&[PipelineStep::new("claude", "Say hello")], Context::new()).await.unwrap_err();
+    let failure = failure.downcast_ref::<ai_cli::pipeline::PipelineFailure>().unwrap();
+    assert_eq!(failure.kind, ai_cli::pipeline::FailureKind::RateLimit);
+    assert!(failure.logs.contains(&"throttled attempts: 1 (last Retry-After: 3600000 ms)".to_string()), "{:?}", failure.logs);
+    assert_eq!(transport.requests().len(), 1);
+
+    // Under continue_on_error the callback sees the throttled result
+    let transport = Arc::new(FakeTransport::new().with_reply(throttled(&[("retry-after", "3600")])));
+    executor.register_provider("claude", Arc::new(provider_with(&transport)));
+    executor.set_config(ExecutionConfig { continue_on_error: true, ..config });
+    let seen = capture_results(&mut executor);
+    executor.execute(&[PipelineStep::new("claude", "Say hello")], Context::new()).await.unwrap();
+    let seen = seen.lock().unwrap();
+    let (rate_limited, hits) = &seen[0];
+    assert!(rate_limited);
+    assert_eq!(hits[0].retry_after_ms, Some(3_600_000));
+    assert_eq!(hits[0].waited_ms, 0);
+}
+
+#[tokio::test]
+async fn test_claude_prompt_caching_reports_cached_tokens() {
+    let reply = r#"{"content":[{"type":"text","text":"Done"}],"usage":{"input_tokens":5,"output_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":2048}}"#;
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::from_str(reply).unwrap()));
+    let provider = provider_with(&transport).with_prompt_caching(true);
+    let mut context = Context::new();
+    context.add_file_with_content("src/lib.rs".into(), "pub fn answer() -> u32 { 42 }".to_string());
+
+    let response = provider.execute("Review", &context).await.unwrap();
+    assert_eq!(response.metadata.get("cache_read_tokens").map(String::as_str), Some("2048"));
+    assert!(!response.metadata.contains_key("cache_write_tokens"));
+
+    let body = transport.requests()[0].json().unwrap();
+    assert_eq!(body["messages"][0]["content"][0]["cache_control"]["type"], "ephemeral");
+    assert_eq!(body["messages"][0]["content"][1]["text"], "Review");
+}
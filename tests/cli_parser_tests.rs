@@ -1,12 +1,16 @@
 use ai_cli::cli::{CliArgs, Command};
+use clap::Parser;
+
+fn parse(args: &[&str]) -> CliArgs {
+    CliArgs::try_parse_from(args).unwrap()
+}
 
 #[test]
 fn test_parse_basic_execute_command() {
-    let args = vec!["ai-cli", "--provider", "claude", "--prompt", "Hello, world!"];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "execute", "--provider", "claude", "--prompt", "Hello, world!"]);
     
     match cli_args.command {
-        Some(Command::Execute { provider, prompt, api_key: _, context: _, no_stream }) => {
+        Some(Command::Execute { provider, prompt, api_key: _, context: _, no_stream, .. }) => {
             assert_eq!(provider, "claude");
             assert_eq!(prompt, "Hello, world!");
             assert!(!no_stream); // stream is true by default
@@ -17,16 +21,15 @@ fn test_parse_basic_execute_command() {
 
 #[test]
 fn test_parse_execute_with_api_key() {
-    let args = vec![
-        "ai-cli",
+    let cli_args = parse(&[
+        "ai-cli", "execute",
         "--provider", "gemini",
         "--prompt", "Test prompt",
         "--api-key", "test-key-123"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    ]);
     
     match cli_args.command {
-        Some(Command::Execute { provider, prompt, api_key, context: _, no_stream: _ }) => {
+        Some(Command::Execute { provider, prompt, api_key, context: _, no_stream: _, .. }) => {
             assert_eq!(provider, "gemini");
             assert_eq!(prompt, "Test prompt");
             assert_eq!(api_key, Some("test-key-123".to_string()));
@@ -37,16 +40,15 @@ fn test_parse_execute_with_api_key() {
 
 #[test]
 fn test_parse_pipeline_command() {
-    let args = vec![
-        "ai-cli",
+    let cli_args = parse(&[
+        "ai-cli", "pipeline",
         "--chain",
         "claude:設計 -> gemini:実装 -> codex:レビュー"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    ]);
     
     match cli_args.command {
-        Some(Command::Pipeline { chain, context: _, no_stream }) => {
-            assert_eq!(chain, "claude:設計 -> gemini:実装 -> codex:レビュー");
+        Some(Command::Pipeline { chain, context: _, no_stream, .. }) => {
+            assert_eq!(chain.as_deref(), Some("claude:設計 -> gemini:実装 -> codex:レビュー"));
             assert!(!no_stream); // stream is true by default
         }
         _ => panic!("Expected Pipeline command"),
@@ -55,17 +57,16 @@ fn test_parse_pipeline_command() {
 
 #[test]
 fn test_parse_execute_with_context_file() {
-    let args = vec![
-        "ai-cli",
+    let cli_args = parse(&[
+        "ai-cli", "execute",
         "--provider", "claude",
         "--prompt", "Analyze this",
         "--context", "file.txt"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    ]);
     
     match cli_args.command {
-        Some(Command::Execute { provider: _, prompt: _, api_key: _, context, no_stream: _ }) => {
-            assert_eq!(context, Some("file.txt".to_string()));
+        Some(Command::Execute { provider: _, prompt: _, api_key: _, context, no_stream: _, .. }) => {
+            assert_eq!(context, vec!["file.txt".to_string()]);
         }
         _ => panic!("Expected Execute command"),
     }
@@ -73,16 +74,15 @@ fn test_parse_execute_with_context_file() {
 
 #[test]
 fn test_parse_execute_no_stream() {
-    let args = vec![
-        "ai-cli",
+    let cli_args = parse(&[
+        "ai-cli", "execute",
         "--provider", "claude",
         "--prompt", "Hello",
         "--no-stream"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    ]);
     
     match cli_args.command {
-        Some(Command::Execute { provider: _, prompt: _, api_key: _, context: _, no_stream }) => {
+        Some(Command::Execute { provider: _, prompt: _, api_key: _, context: _, no_stream, .. }) => {
             assert!(no_stream);
         }
         _ => panic!("Expected Execute command"),
@@ -91,16 +91,15 @@ fn test_parse_execute_no_stream() {
 
 #[test]
 fn test_parse_pipeline_with_context() {
-    let args = vec![
-        "ai-cli",
+    let cli_args = parse(&[
+        "ai-cli", "pipeline",
         "--chain", "claude:analyze -> gemini:summarize",
         "--context", "data.json"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    ]);
     
     match cli_args.command {
-        Some(Command::Pipeline { chain: _, context, no_stream: _ }) => {
-            assert_eq!(context, Some("data.json".to_string()));
+        Some(Command::Pipeline { chain: _, context, no_stream: _, .. }) => {
+            assert_eq!(context, vec!["data.json".to_string()]);
         }
         _ => panic!("Expected Pipeline command"),
     }
@@ -108,48 +107,35 @@ fn test_parse_pipeline_with_context() {
 
 #[test]
 fn test_parse_verbose_flag() {
-    let args = vec![
-        "ai-cli",
-        "--provider", "claude",
-        "--prompt", "test",
-        "--verbose"
-    ];
-    let cli_args = CliArgs::parse_from(args);
-    
-    assert!(cli_args.verbose);
+    let cli_args = parse(&["ai-cli", "execute", "--provider", "claude", "--prompt", "test", "--verbose"]);
+    assert_eq!(cli_args.verbose, 1);
 }
 
 #[test]
 fn test_parse_quiet_flag() {
-    let args = vec![
-        "ai-cli",
-        "--provider", "claude",
-        "--prompt", "test",
-        "--quiet"
-    ];
-    let cli_args = CliArgs::parse_from(args);
-    
+    let cli_args = parse(&["ai-cli", "--quiet", "execute", "--provider", "claude", "--prompt", "test"]);
     assert!(cli_args.quiet);
 }
 
 #[test]
 fn test_parse_list_providers_command() {
-    let args = vec!["ai-cli", "--list-providers"];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "list-providers"]);
     
     match cli_args.command {
-        Some(Command::ListProviders) => (),
+        Some(Command::ListProviders { json: false }) => (),
         _ => panic!("Expected ListProviders command"),
     }
+    // Subcommands are not flags
+    assert!(CliArgs::try_parse_from(["ai-cli", "--list-providers"]).is_err());
 }
 
 #[test]
 fn test_parse_check_auth_command() {
-    let args = vec!["ai-cli", "--check-auth", "claude"];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "check-auth", "claude"]);
     
     match cli_args.command {
-        Some(Command::CheckAuth { provider }) => {
+        Some(Command::CheckAuth { provider, validate }) => {
+            assert!(!validate);
             assert_eq!(provider, "claude");
         }
         _ => panic!("Expected CheckAuth command"),
@@ -158,53 +144,307 @@ fn test_parse_check_auth_command() {
 
 #[test]
 fn test_parse_version_command() {
-    let args = vec!["ai-cli", "--version"];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "version"]);
     
     match cli_args.command {
         Some(Command::Version) => (),
         _ => panic!("Expected Version command"),
     }
+    let err = CliArgs::try_parse_from(["ai-cli", "--version"]).unwrap_err();
+    assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+}
+
+#[test]
+fn test_provider_and_chain_value_parsers() {
+    let err = CliArgs::try_parse_from(["ai-cli", "execute", "-p", "gpt", "-P", "hi"]).unwrap_err();
+    assert!(err.to_string().contains("unknown provider 'gpt' (expected one of: claude, gemini, codex)"), "{}", err);
+    assert!(CliArgs::try_parse_from(["ai-cli", "consensus", "-P", "hi", "--providers", "claude,llama"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "check-auth", "nope"]).is_err());
+
+    let err = CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:a -> llama:b"]).unwrap_err();
+    assert!(err.to_string().contains("llama"), "{}", err);
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "save", "x", "claude:a ->"]).is_err());
 }
 
 #[test]
 fn test_execute_command_helper() {
-    let args = vec![
-        "ai-cli",
-        "--provider", "claude",
-        "--prompt", "Hello",
-        "--no-stream"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "execute", "--provider", "claude", "--prompt", "Hello", "--no-stream"]);
     
-    if let Some(cmd) = &cli_args.command {
-        if let Some(exec) = cmd.as_execute() {
-            assert_eq!(exec.provider, "claude");
-            assert_eq!(exec.prompt, "Hello");
-            assert!(!exec.stream);
-            assert!(exec.no_stream);
-        } else {
-            panic!("Expected Execute command");
-        }
-    }
+    let exec = cli_args.command.as_ref().and_then(Command::as_execute).expect("Expected Execute command");
+    assert_eq!(exec.provider, "claude");
+    assert_eq!(exec.prompt, "Hello");
+    assert!(!exec.stream);
+    assert!(exec.no_stream);
 }
 
 #[test]
 fn test_pipeline_command_helper() {
-    let args = vec![
-        "ai-cli",
-        "--chain", "claude:test",
-        "--context", "file.txt"
-    ];
-    let cli_args = CliArgs::parse_from(args);
+    let cli_args = parse(&["ai-cli", "pipeline", "--chain", "claude:test", "--context", "file.txt"]);
     
-    if let Some(cmd) = &cli_args.command {
-        if let Some(pipe) = cmd.as_pipeline() {
-            assert_eq!(pipe.chain, "claude:test");
-            assert_eq!(pipe.context_file(), Some("file.txt".to_string()));
-            assert!(pipe.stream);
-        } else {
-            panic!("Expected Pipeline command");
+    let pipe = cli_args.command.as_ref().and_then(Command::as_pipeline).expect("Expected Pipeline command");
+    assert_eq!(pipe.chain, "claude:test");
+    assert_eq!(pipe.context_file(), Some("file.txt".to_string()));
+    assert!(pipe.stream);
+}
+
+#[test]
+fn test_parse_explain_context_flag() {
+    let cli_args = parse(&["ai-cli", "pipeline", "--chain", "claude:test", "--explain-context"]);
+
+    match cli_args.command {
+        Some(Command::Pipeline { explain_context, .. }) => assert!(explain_context),
+        _ => panic!("Expected Pipeline command"),
+    }
+}
+
+#[test]
+fn test_parse_env_flags() {
+    let cli_args = parse(&[
+        "ai-cli", "pipeline",
+        "--chain", "claude:write {{env.LANG}}",
+        "--env", "LANG=Rust",
+        "--env", "STYLE=terse",
+    ]);
+
+    match cli_args.command {
+        Some(Command::Pipeline { env, .. }) => {
+            assert_eq!(env, vec![
+                ("LANG".to_string(), "Rust".to_string()),
+                ("STYLE".to_string(), "terse".to_string()),
+            ]);
+        }
+        _ => panic!("Expected Pipeline command"),
+    }
+}
+
+#[test]
+fn test_parse_env_passthrough() {
+    let cli_args = parse(&["ai-cli", "run", "deploy", "--env-passthrough", "CI_*", "--env-passthrough", "GITHUB_SHA"]);
+    match cli_args.command {
+        Some(Command::Run { env_passthrough, .. }) => {
+            assert_eq!(env_passthrough.iter().map(|p| p.as_str()).collect::<Vec<_>>(), vec!["CI_*", "GITHUB_SHA"]);
+        }
+        _ => panic!("Expected Run command"),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--env-passthrough", "[bad"]).is_err());
+}
+
+#[test]
+fn test_parse_pipeline_save_and_run() {
+    use ai_cli::cli::PipelineAction;
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "pipeline", "save", "review", "claude:analyze -> codex:review", "--force",
+    ]).unwrap();
+    match cli_args.command {
+        Some(Command::Pipeline { action: Some(PipelineAction::Save { name, chain, description, force }), .. }) => {
+            assert_eq!(name, "review");
+            assert_eq!(chain, "claude:analyze -> codex:review");
+            assert!(description.is_none());
+            assert!(force);
         }
+        _ => panic!("Expected pipeline save command"),
     }
-}
\ No newline at end of file
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "run", "review", "--context", "src/"]).unwrap();
+    match cli_args.command {
+        Some(Command::Run { name, context, .. }) => {
+            assert_eq!(name, "review");
+            assert_eq!(context, vec!["src/".to_string()]);
+        }
+        _ => panic!("Expected run command"),
+    }
+}
+
+#[test]
+fn test_parse_models_command() {
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "models", "-p", "claude"]).unwrap();
+    match cli_args.command {
+        Some(Command::Models { provider }) => assert_eq!(provider.as_deref(), Some("claude")),
+        _ => panic!("Expected models command"),
+    }
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "models"]).unwrap();
+    assert!(matches!(cli_args.command, Some(Command::Models { provider: None })));
+}
+
+#[test]
+fn test_parse_package_flag() {
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "run", "review", "--package", "app-core"]).unwrap();
+    assert_eq!(cli_args.package.as_deref(), Some("app-core"));
+
+    let cli_args = parse(&["ai-cli", "--package", "web", "execute", "--provider", "claude", "--prompt", "hi"]);
+    assert_eq!(cli_args.package.as_deref(), Some("web"));
+}
+
+#[test]
+fn test_parse_generation_flags() {
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "execute", "-p", "claude", "-P", "hi",
+        "--temperature", "0.1", "--max-tokens", "500", "--stop", "END", "--stop", "DONE",
+    ]).unwrap();
+    match cli_args.command {
+        Some(Command::Execute { generation, .. }) => {
+            let options = generation.to_options();
+            assert_eq!(options.temperature, Some(0.1));
+            assert_eq!(options.max_tokens, Some(500));
+            assert_eq!(options.top_p, None);
+            assert_eq!(options.stop, vec!["END", "DONE"]);
+        }
+        _ => panic!("Expected execute command"),
+    }
+}
+
+#[test]
+fn test_parse_system_flag() {
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "execute", "-p", "claude", "-P", "hi", "--system", "You are terse.",
+    ]).unwrap();
+    match cli_args.command {
+        Some(Command::Execute { generation, .. }) => {
+            assert_eq!(generation.to_options().system.as_deref(), Some("You are terse."));
+        }
+        _ => panic!("Expected execute command"),
+    }
+}
+
+#[test]
+fn test_parse_multiple_context_specs() {
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "execute", "-p", "claude", "-P", "review", "--context", "src/**/*.rs", "-c", "README.md",
+    ]).unwrap();
+    match cli_args.command {
+        Some(Command::Execute { context, .. }) => assert_eq!(context, vec!["src/**/*.rs", "README.md"]),
+        _ => panic!("Expected execute command"),
+    }
+}
+
+#[test]
+fn test_parse_git_context_flags() {
+    use ai_cli::context::DiffSource;
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "pipeline", "--chain", "claude:review", "--context-git-staged", "--context-git-range", "main..HEAD",
+    ]).unwrap();
+    match cli_args.command {
+        Some(Command::Pipeline { git, .. }) => {
+            assert_eq!(git.sources(), vec![DiffSource::Staged, DiffSource::Range("main..HEAD".to_string())]);
+        }
+        _ => panic!("Expected pipeline command"),
+    }
+}
+
+#[test]
+fn test_parse_session_commands() {
+    use ai_cli::cli::SessionAction;
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "hi", "--session", "work"]).unwrap();
+    match cli_args.command {
+        Some(Command::Execute { session, .. }) => assert_eq!(session.as_deref(), Some("work")),
+        _ => panic!("Expected execute command"),
+    }
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "session", "show", "work"]).unwrap();
+    assert!(matches!(cli_args.command, Some(Command::Session { action: SessionAction::Show { name } }) if name == "work"));
+}
+
+#[test]
+fn test_parse_index_and_retrieve() {
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "index", "src", "--embedder", "gemini", "--rebuild"]).unwrap();
+    match cli_args.command {
+        Some(Command::Index { paths, embedder, chunk_lines, rebuild }) => {
+            assert_eq!(paths, vec!["src".to_string()]);
+            assert_eq!(embedder, "gemini");
+            assert_eq!(chunk_lines, ai_cli::context::index::DEFAULT_CHUNK_LINES);
+            assert!(rebuild);
+        }
+        _ => panic!("Expected index command"),
+    }
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:hi", "--retrieve", "5"]).unwrap();
+    assert!(matches!(cli_args.command, Some(Command::Pipeline { retrieve: Some(5), .. })));
+}
+
+#[test]
+fn test_parse_show_cost() {
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "hi", "--show-cost"]).unwrap();
+    assert!(cli_args.show_cost);
+    assert!(parse(&["ai-cli", "--show-cost", "execute", "--provider", "claude", "--prompt", "hi"]).show_cost);
+}
+
+#[test]
+fn test_parse_stats() {
+    use ai_cli::history::stats::GroupBy;
+
+    let cli_args = CliArgs::try_parse_from(["ai-cli", "stats", "--since", "7d", "--by", "provider", "--json"]).unwrap();
+    match cli_args.command {
+        Some(Command::Stats { since, until, by, json }) => {
+            assert_eq!(since.as_deref(), Some("7d"));
+            assert_eq!(until, None);
+            assert_eq!(by, GroupBy::Provider);
+            assert!(json);
+        }
+        _ => panic!("Expected stats command"),
+    }
+}
+
+#[test]
+fn test_parse_log_flags() {
+    use ai_cli::logging::LogFormat;
+
+    let cli_args = CliArgs::try_parse_from([
+        "ai-cli", "-vv", "--log-format", "json", "--log-file", "ai.log", "list-providers",
+    ]).unwrap();
+    assert_eq!(cli_args.verbose, 2);
+    assert_eq!(cli_args.log_format, LogFormat::Json);
+    assert_eq!(cli_args.log_file, Some(std::path::PathBuf::from("ai.log")));
+}
+
+#[test]
+fn test_chain_file_flag() {
+    match parse(&["ai-cli", "pipeline", "--chain-file", "pipeline.txt"]).command {
+        Some(Command::Pipeline { chain_file, chain, .. }) => {
+            assert_eq!(chain_file, Some(std::path::PathBuf::from("pipeline.txt")));
+            assert_eq!(chain, None);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain-file", "p.txt", "--chain", "claude:x"]).is_err());
+}
+
+#[test]
+fn test_rerun_command() {
+    match parse(&["ai-cli", "rerun", "last"]).command {
+        Some(Command::Rerun { id }) => assert_eq!(id, "last"),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_deterministic_flag() {
+    use ai_cli::providers::DEFAULT_SEED;
+
+    let options = |args: &[&str]| match parse(args).command {
+        Some(Command::Execute { generation, .. }) => generation.to_options(),
+        other => panic!("unexpected {:?}", other),
+    };
+    let deterministic = options(&["ai-cli", "execute", "-p", "gemini", "-P", "hi", "--deterministic"]);
+    assert_eq!(deterministic.temperature, Some(0.0));
+    assert_eq!(deterministic.seed, Some(DEFAULT_SEED));
+
+    let explicit = options(&["ai-cli", "execute", "-p", "gemini", "-P", "hi", "--deterministic", "--seed", "7", "--temperature", "0.5"]);
+    assert_eq!(explicit.seed, Some(7));
+    assert_eq!(explicit.temperature, Some(0.5));
+
+    let seeded = options(&["ai-cli", "execute", "-p", "gemini", "-P", "hi", "--seed", "7"]);
+    assert_eq!(seeded.temperature, None);
+    assert_eq!(seeded.seed, Some(7));
+}
@@ -0,0 +1,24 @@
+use ai_cli::cli::CliArgs;
+use ai_cli::clipboard::pick;
+use ai_cli::providers::Response;
+use clap::Parser;
+
+#[test]
+fn test_pick_step_output() {
+    let responses = vec![Response::new("design"), Response::new("code")];
+    assert_eq!(pick(&responses, None).unwrap(), "code");
+    assert_eq!(pick(&responses, Some(1)).unwrap(), "design");
+    assert!(pick(&responses, Some(3)).unwrap_err().to_string().contains("the run has 2 steps"));
+    assert!(pick(&responses, Some(0)).is_err());
+    assert!(pick(&[], None).is_err());
+}
+
+#[test]
+fn test_copy_flag() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "--copy", "pipeline", "--chain", "claude:x"]).unwrap();
+    assert_eq!(args.copy, Some(None));
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--copy=1"]).unwrap();
+    assert_eq!(args.copy, Some(Some(1)));
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "hi"]).unwrap();
+    assert_eq!(args.copy, None);
+}
@@ -0,0 +1,134 @@
+use ai_cli::providers::codex::CodexProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{AIProvider, Context, Image, JsonMode, Message, MessageRole, ProviderOptions, ToolCall, ToolSpec};
+use futures::StreamExt;
+use serde_json::json;
+use std::sync::Arc;
+
+fn completion(content: &str) -> serde_json::Value {
+    json!({
+        "model": "gpt-4o-mini-2024-07-18",
+        "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }],
+        "usage": { "prompt_tokens": 9, "completion_tokens": 2 },
+    })
+}
+
+fn provider_with(transport: &Arc<FakeTransport>) -> CodexProvider {
+    CodexProvider::new("sk-test".to_string()).with_model("gpt-4o-mini").with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn test_codex_chat_completion() {
+    let transport = Arc::new(FakeTransport::new().with_json(completion("Hello!")));
+    let provider = provider_with(&transport);
+    let response = provider.execute("Say hello", &Context::new()).await.unwrap();
+
+    assert_eq!(response.content, "Hello!");
+    assert_eq!(response.metadata["model"], "gpt-4o-mini-2024-07-18");
+    assert_eq!(response.metadata["prompt_tokens"], "9");
+    assert_eq!(response.metadata["completion_tokens"], "2");
+    let requests = transport.requests();
+    assert_eq!(requests[0].url, "https://api.openai.com/v1/chat/completions");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+    let body = requests[0].json().unwrap();
+    assert_eq!(body["model"], "gpt-4o-mini");
+    assert_eq!(body["messages"], json!([{ "role": "user", "content": "Say hello" }]));
+}
+
+#[tokio::test]
+async fn test_codex_request_carries_images_json_seed_and_idempotency_key() {
+    let transport = Arc::new(FakeTransport::new().with_json(completion("{\"ok\":true}")));
+    let provider = provider_with(&transport).with_base_url("http://localhost:8080/v1/");
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::User, "What is this?").with_images(vec![Image::from_bytes("image/png", b"png")]));
+    let options = ProviderOptions {
+        system: Some("Be brief".to_string()),
+        seed: Some(7),
+        json: Some(JsonMode::new(json!({ "type": "object" }))),
+        idempotency_key: Some("key-1".to_string()),
+        ..ProviderOptions::default()
+    };
+    provider.execute_with_options("Answer", &context, &options).await.unwrap();
+
+    let request = &transport.requests()[0];
+    assert_eq!(request.url, "http://localhost:8080/v1/chat/completions");
+    assert_eq!(request.header("Idempotency-Key"), Some("key-1"));
+    let body = request.json().unwrap();
+    assert_eq!(body["seed"], 7);
+    assert_eq!(body["response_format"]["type"], "json_schema");
+    assert_eq!(body["response_format"]["json_schema"]["schema"], json!({ "type": "object" }));
+    assert_eq!(body["messages"][0], json!({ "role": "system", "content": "Be brief" }));
+    let parts = body["messages"][1]["content"].as_array().unwrap();
+    assert!(parts.iter().any(|p| p["image_url"]["url"] == "data:image/png;base64,cG5n"), "{:?}", parts);
+}
+
+#[tokio::test]
+async fn test_codex_runs_tool_calls_until_the_model_answers() {
+    let call = json!({
+        "choices": [{
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{ "id": "call_1", "type": "function", "function": { "name": "add", "arguments": "{\"a\":2,\"b\":3}" } }],
+            },
+            "finish_reason": "tool_calls",
+        }],
+        "usage": { "prompt_tokens": 10, "completion_tokens": 5 },
+    });
+    let transport = Arc::new(FakeTransport::new().with_json(call).with_json(completion("5")));
+    let provider = provider_with(&transport);
+    let tools = [ToolSpec::new("add", "Add two numbers", json!({ "type": "object" }))];
+    let handler = |call: &ToolCall| -> anyhow::Result<String> {
+        Ok((call.input["a"].as_i64().unwrap() + call.input["b"].as_i64().unwrap()).to_string())
+    };
+
+    let response = provider
+        .execute_with_tools("2 + 3?", &Context::new(), &ProviderOptions::default(), &tools, &handler)
+        .await
+        .unwrap();
+
+    assert_eq!(response.content, "5");
+    assert_eq!(response.metadata["tool_calls"], "1");
+    assert_eq!(response.metadata["prompt_tokens"], "19");
+    assert_eq!(response.metadata["completion_tokens"], "7");
+    let requests = transport.requests();
+    assert_eq!(requests[0].json().unwrap()["tools"][0]["function"]["name"], "add");
+    let messages = requests[1].json().unwrap()["messages"].clone();
+    assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+    assert_eq!(messages[2], json!({ "role": "tool", "tool_call_id": "call_1", "content": "5" }));
+}
+
+#[tokio::test]
+async fn test_codex_streams_content_deltas() {
+    let transport = Arc::new(FakeTransport::new().with_sse(concat!(
+        "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+        "data: [DONE]\n\n",
+    )));
+    let provider = provider_with(&transport);
+    let chunks: Vec<String> = provider.stream("Say hello", &Context::new()).await.unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(chunks.concat(), "Hello");
+    assert_eq!(transport.requests()[0].json().unwrap()["stream"], true);
+}
+
+#[tokio::test]
+async fn test_codex_lists_models() {
+    let transport = Arc::new(FakeTransport::new().with_json(json!({ "data": [{ "id": "gpt-4o" }, { "id": "gpt-4o-mini" }] })));
+    let provider = provider_with(&transport);
+    let models: Vec<String> = provider.list_models().await.unwrap().into_iter().map(|m| m.id).collect();
+
+    assert_eq!(models, vec!["gpt-4o", "gpt-4o-mini"]);
+    assert_eq!(transport.requests()[0].url, "https://api.openai.com/v1/models");
+}
+
+#[tokio::test]
+async fn test_codex_cli_session_without_key_is_refused() {
+    let provider = CodexProvider::from_detected_cli_session();
+    let err = provider.execute("Say hello", &Context::new()).await.unwrap_err();
+    assert!(err.to_string().contains("CODEX_API_KEY"), "{}", err);
+}
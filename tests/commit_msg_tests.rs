@@ -0,0 +1,96 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::context::git::git_path;
+use ai_cli::pipeline::commit_msg::{HOOK_MARKER, clean_message, install_hook, is_conventional, write_message};
+use clap::Parser;
+use std::path::PathBuf;
+
+#[test]
+fn test_clean_message_strips_fences_and_quotes() {
+    assert_eq!(clean_message("```text\nfeat(cli): add watch\n\nBody.\n```\n"), "feat(cli): add watch\n\nBody.");
+    assert_eq!(clean_message("\"fix: handle empty diff\""), "fix: handle empty diff");
+    assert_eq!(clean_message("  docs: update README  \n"), "docs: update README");
+}
+
+#[test]
+fn test_conventional_subjects() {
+    assert!(is_conventional("feat(cli): add commit-msg\n\nBody"));
+    assert!(is_conventional("fix!: drop the old flag"));
+    assert!(is_conventional("chore: bump deps"));
+    assert!(!is_conventional("Add commit-msg"));
+    assert!(!is_conventional("feature: add commit-msg"));
+    assert!(!is_conventional("feat:missing space"));
+    assert!(!is_conventional(""));
+}
+
+#[test]
+fn test_write_message_keeps_git_comments() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("COMMIT_EDITMSG");
+    std::fs::write(&path, "# Please enter the commit message for your changes.\n").unwrap();
+    write_message(&path, "feat: add hooks").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "feat: add hooks\n\n# Please enter the commit message for your changes.\n"
+    );
+
+    let fresh = dir.path().join("MSG");
+    write_message(&fresh, "fix: typo").unwrap();
+    assert_eq!(std::fs::read_to_string(&fresh).unwrap(), "fix: typo\n");
+}
+
+#[test]
+fn test_install_hook_keeps_foreign_hooks_unless_forced() {
+    let dir = tempfile::tempdir().unwrap();
+    let hooks = dir.path().join("hooks");
+
+    let path = install_hook(&hooks, false).unwrap();
+    assert_eq!(path, hooks.join("prepare-commit-msg"));
+    let script = std::fs::read_to_string(&path).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains(HOOK_MARKER));
+    assert!(script.contains("ai-cli commit-msg --write \"$1\""));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o111, 0o111);
+    }
+    // Reinstalling over our own hook is fine
+    install_hook(&hooks, false).unwrap();
+
+    std::fs::write(&path, "#!/bin/sh\necho mine\n").unwrap();
+    assert!(install_hook(&hooks, false).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "#!/bin/sh\necho mine\n");
+    install_hook(&hooks, true).unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().contains(HOOK_MARKER));
+}
+
+#[test]
+fn test_git_path_points_into_the_git_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = std::process::Command::new("git").arg("-C").arg(dir.path()).arg("init").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(git_path(dir.path(), "hooks").unwrap(), dir.path().join(".git/hooks"));
+    assert_eq!(git_path(dir.path(), "COMMIT_EDITMSG").unwrap(), dir.path().join(".git/COMMIT_EDITMSG"));
+}
+
+#[test]
+fn test_commit_msg_subcommand_parses() {
+    let args = CliArgs::try_parse_from(["ai-cli", "commit-msg", "--write"]).unwrap();
+    match args.command {
+        Some(Command::CommitMsg { write, install_hook, .. }) => {
+            assert_eq!(write, Some(None));
+            assert!(!install_hook);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+
+    let args = CliArgs::try_parse_from(["ai-cli", "commit-msg", "--write", ".git/COMMIT_EDITMSG"]).unwrap();
+    match args.command {
+        Some(Command::CommitMsg { write, .. }) => assert_eq!(write, Some(Some(PathBuf::from(".git/COMMIT_EDITMSG")))),
+        other => panic!("unexpected command: {:?}", other),
+    }
+
+    assert!(CliArgs::try_parse_from(["ai-cli", "commit-msg", "--install-hook", "--force"]).is_ok());
+    assert!(CliArgs::try_parse_from(["ai-cli", "commit-msg", "--force"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "commit-msg", "--install-hook", "--write"]).is_err());
+}
@@ -0,0 +1,72 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{CompareReport, CompareView, PipelineExecutor, PipelineStep};
+use ai_cli::providers::Context;
+use ai_cli::providers::mock::MockProvider;
+use clap::Parser;
+use std::sync::Arc;
+
+fn executor() -> PipelineExecutor {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply("one\ntwo\nthree")));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_reply("one\n2\nthree")));
+    executor
+}
+
+#[tokio::test]
+async fn test_compare_providers() {
+    let sides = vec![PipelineStep::new("claude", "count"), PipelineStep::new("gemini", "count")];
+    let report = executor().compare(&sides, Context::new()).await.unwrap();
+    assert_eq!(report.sides.len(), 2);
+    assert_eq!((report.sides[0].label.as_str(), report.sides[0].provider.as_str()), ("A", "claude"));
+    assert_eq!(report.sides[1].content.as_deref(), Some("one\n2\nthree"));
+
+    let diff = report.diff();
+    assert!(diff.contains("-two\n+2\n"), "{}", diff);
+    assert!(diff.contains("--- A · claude"), "{}", diff);
+
+    let json: CompareReport = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+    assert_eq!(json, report);
+}
+
+#[tokio::test]
+async fn test_compare_columns_and_prompts() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply("short").with_error("quota")));
+    let sides = vec![PipelineStep::new("claude", "be brief"), PipelineStep::new("claude", "be verbose")];
+    let mut report = executor.compare(&sides, Context::new()).await.unwrap();
+    report.sides.iter_mut().for_each(|side| side.elapsed_ms = 5);
+
+    let columns = report.columns(40);
+    let lines: Vec<&str> = columns.lines().collect();
+    assert_eq!(lines[0], "A: be brief");
+    assert_eq!(lines[1], "B: be verbose");
+    assert_eq!(lines[3], "A · claude (5 ms)  │ B · claude (5 ms)");
+    assert!(lines[5].starts_with("short"));
+    assert!(lines[5].contains("│ (failed: quota)"), "{}", columns);
+    assert!(lines.iter().all(|line| line.chars().count() <= 40), "{}", columns);
+}
+
+#[tokio::test]
+async fn test_compare_fails_when_no_side_answers() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_error("down").with_error("down")));
+    let sides = vec![PipelineStep::new("claude", "a"), PipelineStep::new("claude", "b")];
+    assert!(executor.compare(&sides, Context::new()).await.is_err());
+}
+
+#[test]
+fn test_compare_command() {
+    let args = CliArgs::try_parse_from(["ai-cli", "compare", "--providers", "claude,gemini", "-P", "hi", "--view", "diff"]).unwrap();
+    match args.command {
+        Some(Command::Compare { prompt, providers, view, .. }) => {
+            assert_eq!(prompt.as_deref(), Some("hi"));
+            assert_eq!(providers.len(), 2);
+            assert_eq!(view, CompareView::Diff);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    let args = CliArgs::try_parse_from(["ai-cli", "compare", "-p", "claude", "--prompts", "a", "--prompts", "b"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Compare { prompts, .. }) if prompts == ["a", "b"]));
+    assert!(CliArgs::try_parse_from(["ai-cli", "compare", "--providers", "claude,gemini"]).is_err());
+    assert!(CliArgs::try_parse_from(["ai-cli", "compare", "-P", "hi", "-p", "claude", "--providers", "gemini"]).is_err());
+}
@@ -0,0 +1,50 @@
+use ai_cli::cli::CliArgs;
+use ai_cli::cli::completion::write_script;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_complete::engine::complete;
+
+fn candidates(args: &[&str]) -> Vec<String> {
+    let mut cmd = CliArgs::command();
+    let args: Vec<std::ffi::OsString> = args.iter().map(Into::into).collect();
+    let index = args.len() - 1;
+    complete(&mut cmd, args, index, None)
+        .unwrap()
+        .into_iter()
+        .map(|c| c.get_value().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn test_provider_names_complete() {
+    let names = candidates(&["ai-cli", "execute", "--provider", ""]);
+    assert_eq!(names, vec!["claude", "gemini", "codex"]);
+
+    let names = candidates(&["ai-cli", "check-auth", "ge"]);
+    assert_eq!(names, vec!["gemini"]);
+}
+
+#[test]
+fn test_subcommands_complete() {
+    let names = candidates(&["ai-cli", "comp"]);
+    assert_eq!(names, vec!["compare", "completions"]);
+}
+
+#[test]
+fn test_dynamic_script_calls_back_into_ai_cli() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+        let mut out = Vec::new();
+        write_script(shell, true, &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("COMPLETE"), "{}: {}", shell, script);
+    }
+}
+
+#[test]
+fn test_static_script_lists_subcommands() {
+    let mut out = Vec::new();
+    write_script(Shell::Bash, false, &mut out).unwrap();
+    let script = String::from_utf8(out).unwrap();
+    assert!(script.contains("completions"));
+    assert!(!script.contains("COMPLETE="));
+}
@@ -0,0 +1,255 @@
+use ai_cli::auth::{AuthManager, AuthMethod};
+use ai_cli::config::{Config, expand_env};
+use ai_cli::providers::AIProvider;
+use ai_cli::providers::claude::ClaudeProvider;
+
+const CONFIG: &str = r#"
+default_profile = "work"
+
+[profiles.work.claude]
+api_key = "work-claude-key"
+model = "claude-3-opus-20240229"
+base_url = "https://gateway.example.com/"
+
+[profiles.personal.claude]
+api_key_env = "AI_CLI_TEST_PERSONAL_CLAUDE_KEY"
+
+[profiles.personal.gemini]
+api_key = "${AI_CLI_TEST_UNSET_VARIABLE}"
+"#;
+
+#[test]
+fn test_parse_profiles() {
+    let config = Config::from_toml(CONFIG).unwrap();
+
+    assert_eq!(config.default_profile.as_deref(), Some("work"));
+    assert_eq!(config.profiles.len(), 2);
+
+    let work = config.profile("work").unwrap();
+    let claude = work.provider("claude").unwrap();
+    assert_eq!(claude.model.as_deref(), Some("claude-3-opus-20240229"));
+    assert_eq!(claude.resolve_api_key().as_deref(), Some("work-claude-key"));
+    assert!(work.provider("gemini").is_none());
+}
+
+#[test]
+fn test_unknown_profile_lists_known() {
+    let config = Config::from_toml(CONFIG).unwrap();
+
+    let err = config.profile("missing").unwrap_err().to_string();
+    assert!(err.contains("missing"));
+    assert!(err.contains("personal"));
+    assert!(err.contains("work"));
+}
+
+#[test]
+fn test_active_profile_name() {
+    let config = Config::from_toml(CONFIG).unwrap();
+    assert_eq!(config.active_profile_name(None).as_deref(), Some("work"));
+    assert_eq!(config.active_profile_name(Some("personal")).as_deref(), Some("personal"));
+    assert_eq!(Config::default().active_profile_name(None), None);
+}
+
+#[test]
+fn test_empty_config_is_valid() {
+    let config = Config::from_toml("").unwrap();
+    assert!(config.profiles.is_empty());
+    assert!(Config::from_toml("profiles = 3").is_err());
+}
+
+#[test]
+fn test_expand_env() {
+    // SAFETY: test-local variable name not read elsewhere
+    unsafe { std::env::set_var("AI_CLI_TEST_EXPAND", "value") };
+    assert_eq!(expand_env("${AI_CLI_TEST_EXPAND}"), "value");
+    assert_eq!(expand_env("a-${AI_CLI_TEST_EXPAND}-b"), "a-value-b");
+    assert_eq!(expand_env("${AI_CLI_TEST_UNSET_VARIABLE}"), "");
+    assert_eq!(expand_env("plain"), "plain");
+    assert_eq!(expand_env("${unterminated"), "${unterminated");
+}
+
+#[test]
+fn test_api_key_env_and_empty_expansion() {
+    let config = Config::from_toml(CONFIG).unwrap();
+    let personal = config.profile("personal").unwrap();
+
+    // An api_key that expands to nothing does not count as a credential
+    assert_eq!(personal.provider("gemini").unwrap().resolve_api_key(), None);
+
+    // SAFETY: test-local variable name not read elsewhere
+    unsafe { std::env::set_var("AI_CLI_TEST_PERSONAL_CLAUDE_KEY", "personal-key") };
+    assert_eq!(
+        personal.provider("claude").unwrap().resolve_api_key().as_deref(),
+        Some("personal-key")
+    );
+}
+
+#[tokio::test]
+async fn test_detect_auth_resolves_within_profile() {
+    let config = Config::from_toml(CONFIG).unwrap();
+    let manager = AuthManager::with_profile("work", config.profile("work").unwrap().clone());
+
+    assert_eq!(manager.profile_name(), Some("work"));
+    match manager.detect_auth("claude").await.unwrap() {
+        AuthMethod::ApiKey { key } => assert_eq!(key, "work-claude-key"),
+        other => panic!("Expected profile API key, got {:?}", other),
+    }
+    assert!(manager.provider_settings("claude").is_some());
+    assert!(manager.provider_settings("gemini").is_none());
+}
+
+#[test]
+fn test_provider_model_and_base_url_overrides() {
+    let provider = ClaudeProvider::new("test_key".to_string())
+        .with_model("claude-3-opus-20240229")
+        .with_base_url("https://gateway.example.com/");
+
+    assert_eq!(provider.model(), Some("claude-3-opus-20240229"));
+}
+
+#[test]
+fn test_find_project_config_walks_up() {
+    use ai_cli::config::find_project_config;
+
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("crates/core/src");
+    std::fs::create_dir_all(&nested).unwrap();
+    assert!(find_project_config(&nested).is_none_or(|p| !p.starts_with(dir.path())));
+
+    std::fs::write(dir.path().join(".ai-cli.toml"), "").unwrap();
+    assert_eq!(find_project_config(&nested).unwrap(), dir.path().join(".ai-cli.toml"));
+
+    // The nearest file wins
+    std::fs::write(dir.path().join("crates/.ai-cli.toml"), "").unwrap();
+    assert_eq!(find_project_config(&nested).unwrap(), dir.path().join("crates/.ai-cli.toml"));
+}
+
+#[test]
+fn test_project_config_merges_over_user() {
+    let project = r#"
+default_chain = "claude:review -> gemini:summarize"
+prompt_prefix = "Follow the team style guide."
+context = ["docs/*.md"]
+
+[profiles.work.claude]
+model = "claude-3-haiku-20240307"
+
+[providers.gemini]
+model = "gemini-1.5-flash"
+"#;
+    let path = std::path::Path::new("/repo/.ai-cli.toml");
+    let config = Config::layered(Some(CONFIG), project, path).unwrap();
+
+    assert_eq!(config.default_profile.as_deref(), Some("work"));
+    assert_eq!(config.default_chain.as_deref(), Some("claude:review -> gemini:summarize"));
+    assert_eq!(config.prompt_prefix.as_deref(), Some("Follow the team style guide."));
+    assert_eq!(config.project_root(), Some(std::path::Path::new("/repo")));
+
+    // Nested tables merge key by key: the user's key survives, the project's model wins
+    let claude = config.profile("work").unwrap().provider("claude").unwrap();
+    assert_eq!(claude.api_key.as_deref(), Some("work-claude-key"));
+    assert_eq!(claude.model.as_deref(), Some("claude-3-haiku-20240307"));
+    assert_eq!(
+        config.provider_preferences("gemini").and_then(|p| p.model.as_deref()),
+        Some("gemini-1.5-flash")
+    );
+}
+
+#[test]
+fn test_project_config_rejects_credentials_and_endpoints() {
+    let path = std::path::Path::new("/repo/.ai-cli.toml");
+    for project in [
+        "[profiles.work.claude]\napi_key = \"stolen\"\n",
+        "[providers.claude]\nbase_url = \"https://evil.example.com\"\n",
+        "[profiles.work.gemini]\napi_key_env = \"HOME\"\n",
+        "[providers.openai]\ntokenizer = \"/etc/passwd\"\n",
+    ] {
+        let err = Config::layered(Some(CONFIG), project, path).unwrap_err();
+        assert!(err.to_string().contains("project config may not set"), "{}", err);
+    }
+}
+
+#[test]
+fn test_project_config_only_sets_allowed_sections() {
+    let path = std::path::Path::new("/repo/.ai-cli.toml");
+    for (project, key) in [
+        ("[redaction]\nenabled = false\n", "redaction"),
+        ("[safety]\nenabled = false\n", "safety"),
+        ("[audit]\nenabled = false\n", "audit"),
+        ("[encryption]\nenabled = false\n", "encryption"),
+        ("[post_mortem]\nenabled = true\n", "post_mortem"),
+        ("default_profile = \"personal\"\n", "default_profile"),
+    ] {
+        let err = Config::layered(Some(CONFIG), project, path).unwrap_err();
+        assert!(err.to_string().contains(&format!("project config may not set '{}'", key)), "{}", err);
+    }
+    let config = Config::layered(Some(CONFIG), "[review]\nchain = \"claude:review\"\n[commit_msg]\nchain = \"claude:write\"\n", path).unwrap();
+    assert_eq!(config.review.chain.as_deref(), Some("claude:review"));
+}
+
+#[test]
+fn test_context_files_from_globs() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+    std::fs::write(dir.path().join("docs/a.md"), "a").unwrap();
+    std::fs::write(dir.path().join("docs/b.md"), "b").unwrap();
+    std::fs::write(dir.path().join("docs/c.txt"), "c").unwrap();
+
+    let config = Config::layered(None, "context = [\"docs/*.md\", \"docs/a.md\"]\n", &dir.path().join(".ai-cli.toml")).unwrap();
+    let files = config.context_files(std::path::Path::new("/elsewhere")).unwrap();
+    assert_eq!(files, vec![dir.path().join("docs/a.md"), dir.path().join("docs/b.md")]);
+}
+
+#[test]
+fn test_context_globs_stay_inside_the_project() {
+    let outside = tempfile::tempdir().unwrap();
+    std::fs::write(outside.path().join("secret.env"), "TOKEN=x").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let project = dir.path().join(".ai-cli.toml");
+
+    for glob in [format!("{}/*.env", outside.path().display()), "../*/*.env".to_string(), "docs/../../*.env".to_string()] {
+        let config = Config::layered(None, &format!("context = [{:?}]\n", glob), &project).unwrap();
+        let err = config.context_files(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("must stay inside"), "{}: {}", glob, err);
+    }
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("linked")).unwrap();
+        std::fs::write(dir.path().join("own.env"), "A=1").unwrap();
+        let config = Config::layered(None, "context = [\"*.env\", \"linked/*.env\"]\n", &project).unwrap();
+        assert_eq!(config.context_files(dir.path()).unwrap(), vec![dir.path().join("own.env")]);
+    }
+}
+
+#[test]
+fn test_provider_generation_defaults() {
+    let config = Config::from_toml(
+        "[providers.claude]\ntemperature = 0.3\nmax_tokens = 4096\nsystem = \"Be terse.\"\nstop = [\"END\"]\n",
+    ).unwrap();
+    let options = &config.provider_preferences("claude").unwrap().options;
+    assert_eq!(options.temperature, Some(0.3));
+    assert_eq!(options.max_tokens, Some(4096));
+    assert_eq!(options.system.as_deref(), Some("Be terse."));
+    assert_eq!(options.stop, vec!["END"]);
+}
+
+#[test]
+fn test_provider_prompt_affixes() {
+    let config = Config::from_toml("[providers.gemini]\nprompt_prefix = \"Answer in Japanese.\"\nprompt_suffix = \"Cite sources.\"\n").unwrap();
+    let gemini = config.provider_preferences("gemini").unwrap();
+    assert_eq!(gemini.prompt_prefix.as_deref(), Some("Answer in Japanese."));
+    assert_eq!(gemini.prompt_suffix.as_deref(), Some("Cite sources."));
+    assert!(gemini.options.system.is_none());
+}
+
+#[test]
+fn test_context_policy_config() {
+    use ai_cli::context::ContextPolicy;
+
+    let config = Config::from_toml("context_policy = \"last:20\"\n\n[providers.gemini]\ncontext_policy = \"summary\"\n").unwrap();
+    assert_eq!(config.context_policy, Some(ContextPolicy::Last(20)));
+    assert_eq!(config.providers["gemini"].context_policy, Some(ContextPolicy::SummaryOnly));
+    let err = Config::from_toml("context_policy = \"recent\"\n").unwrap_err();
+    assert!(format!("{:#}", err).contains("Invalid context policy 'recent'"), "{:#}", err);
+}
@@ -0,0 +1,88 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineExecutor, Synthesis};
+use ai_cli::providers::{AIProvider, Context};
+use ai_cli::providers::mock::MockProvider;
+use clap::Parser;
+use std::sync::Arc;
+
+fn executor(providers: Vec<MockProvider>) -> PipelineExecutor {
+    let executor = PipelineExecutor::new();
+    for provider in providers {
+        executor.register_provider(provider.name().to_string(), Arc::new(provider));
+    }
+    executor
+}
+
+#[tokio::test]
+async fn test_consensus_side_by_side() {
+    let executor = executor(vec![
+        MockProvider::new("claude").with_reply("Use a mutex."),
+        MockProvider::new("gemini").with_error("quota exceeded"),
+    ]);
+    let providers = vec!["claude".to_string(), "gemini".to_string()];
+
+    let report = executor.consensus("How do I share state?", &providers, None, Context::new()).await.unwrap();
+    assert_eq!(report.answers.len(), 2);
+    assert_eq!(report.answers[0].content.as_deref(), Some("Use a mutex."));
+    assert!(report.answers[1].error.as_deref().unwrap().contains("quota exceeded"));
+    assert!(report.synthesis.is_none());
+
+    let text = report.to_string();
+    assert!(text.contains("=== claude"));
+    assert!(text.contains("(failed: quota exceeded)"));
+}
+
+#[tokio::test]
+async fn test_consensus_with_arbiter() {
+    let arbiter_reply = r#"Here you go:
+{"consensus": "Use Arc<Mutex<T>>", "agreements": ["shared ownership needs Arc"],
+ "disagreements": [{"topic": "lock type", "positions": {"claude": "Mutex", "gemini": "RwLock"}}]}"#;
+    let executor = executor(vec![
+        MockProvider::new("claude").with_reply("Mutex").with_reply(arbiter_reply),
+        MockProvider::new("gemini").with_reply("RwLock"),
+    ]);
+    let providers = vec!["claude".to_string(), "gemini".to_string()];
+
+    let report = executor.consensus("Which lock?", &providers, Some("claude"), Context::new()).await.unwrap();
+    let synthesis = report.synthesis.as_ref().unwrap();
+    assert_eq!(synthesis.consensus, "Use Arc<Mutex<T>>");
+    assert_eq!(synthesis.agreements, vec!["shared ownership needs Arc"]);
+    assert_eq!(synthesis.disagreements[0].positions.get("gemini").map(String::as_str), Some("RwLock"));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["arbiter"], "claude");
+    assert_eq!(json["synthesis"]["disagreements"][0]["topic"], "lock type");
+    assert!(report.to_string().contains("Disagreements:\n  - lock type"));
+}
+
+#[tokio::test]
+async fn test_consensus_fails_when_nobody_answers() {
+    let executor = executor(vec![MockProvider::new("claude").with_error("offline")]);
+    let err = executor.consensus("Hi", &["claude".to_string()], None, Context::new()).await.unwrap_err();
+    assert!(err.to_string().contains("offline"));
+}
+
+#[test]
+fn test_synthesis_keeps_unstructured_reply() {
+    let synthesis = Synthesis::parse("They mostly agree: use a mutex.");
+    assert_eq!(synthesis.consensus, "They mostly agree: use a mutex.");
+    assert!(synthesis.agreements.is_empty());
+}
+
+#[test]
+fn test_consensus_command_parses() {
+    let args = <CliArgs as Parser>::try_parse_from([
+        "ai-cli", "consensus", "-P", "question", "--providers", "claude,gemini,codex", "--arbiter", "claude", "--json",
+    ])
+    .unwrap();
+    match args.command {
+        Some(Command::Consensus { prompt, providers, arbiter, json, .. }) => {
+            assert_eq!(prompt, "question");
+            assert_eq!(providers, vec!["claude", "gemini", "codex"]);
+            assert_eq!(arbiter.as_deref(), Some("claude"));
+            assert!(json);
+        }
+        _ => panic!("expected consensus command"),
+    }
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "consensus", "-P", "question"]).is_err());
+}
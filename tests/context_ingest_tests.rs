@@ -0,0 +1,171 @@
+use ai_cli::context::{ContextLimits, ContextLoader, IgnoreRules, IgnoreSettings, Provenance, SkipReason};
+use ai_cli::providers::Context;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn write(root: &Path, rel: &str, contents: &[u8]) {
+    let path = root.join(rel);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn relative(root: &Path, files: &[PathBuf]) -> Vec<String> {
+    files.iter().map(|f| f.strip_prefix(root).unwrap().to_string_lossy().into_owned()).collect()
+}
+
+fn project() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "src/main.rs", b"fn main() {}");
+    write(root, "src/lib.rs", b"pub fn lib() {}");
+    write(root, "src/gen/schema.rs", b"// generated");
+    write(root, "target/debug/out.rs", b"// build output");
+    write(root, ".git/config", b"[core]");
+    write(root, "notes.log", b"log");
+    write(root, "README.md", b"# readme");
+    write(root, ".aiignore", b"# build output\ntarget/\n*.log\nsrc/gen/\n");
+    dir
+}
+
+#[test]
+fn test_ignore_rules() {
+    let rules = IgnoreRules::parse("target/\n*.log\n!keep.log\n/docs/draft.md\n").unwrap();
+    assert!(rules.is_ignored(Path::new("target"), true));
+    assert!(rules.is_ignored(Path::new("target/debug/a.rs"), false));
+    assert!(!rules.is_ignored(Path::new("target"), false));
+    assert!(rules.is_ignored(Path::new("logs/app.log"), false));
+    assert!(!rules.is_ignored(Path::new("keep.log"), false));
+    assert!(rules.is_ignored(Path::new("docs/draft.md"), false));
+    assert!(!rules.is_ignored(Path::new("src/docs/draft.md"), false));
+}
+
+#[test]
+fn test_expand_directory_honors_aiignore() {
+    let dir = project();
+    let root = dir.path();
+    let loader = ContextLoader::new(root).unwrap();
+
+    let files = loader.expand(".", root).unwrap();
+    assert_eq!(relative(root, &files), vec![".aiignore", "README.md", "src/lib.rs", "src/main.rs"]);
+}
+
+#[test]
+fn test_expand_glob_and_explicit_file() {
+    let dir = project();
+    let root = dir.path();
+    let loader = ContextLoader::new(root).unwrap();
+
+    let files = loader.expand("src/**/*.rs", root).unwrap();
+    assert_eq!(relative(root, &files), vec!["src/lib.rs", "src/main.rs"]);
+
+    // Naming an ignored file explicitly still includes it
+    let files = loader.expand("notes.log", root).unwrap();
+    assert_eq!(relative(root, &files), vec!["notes.log"]);
+
+    assert!(loader.expand("missing.rs", root).unwrap_err().to_string().contains("does not exist"));
+    assert!(loader.expand("src/**/*.py", root).unwrap_err().to_string().contains("matched no files"));
+}
+
+#[test]
+fn test_load_skips_binary_and_oversized_files() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "small.txt", b"hello");
+    write(root, "image.png", &[0x89, b'P', b'N', b'G', 0, 0, 1]);
+    write(root, "latin1.txt", &[b'c', b'a', b'f', 0xe9]);
+    write(root, "big.txt", &[b'x'; 64]);
+    write(root, "more.txt", &[b'y'; 30]);
+
+    let mut loader = ContextLoader::new(root).unwrap()
+        .with_limits(ContextLimits { max_file_bytes: 40, max_total_bytes: 32 });
+    let mut context = Context::new();
+    let files = loader.expand(".", root).unwrap();
+    let skipped = loader.load_into(&mut context, files, Provenance::cli_flag("--context", ".")).unwrap();
+
+    let reasons: Vec<(String, SkipReason)> = skipped
+        .into_iter()
+        .map(|(path, reason)| (path.strip_prefix(root).unwrap().to_string_lossy().into_owned(), reason))
+        .collect();
+    assert_eq!(reasons, vec![
+        ("big.txt".to_string(), SkipReason::TooLarge { size: 64, limit: 40 }),
+        ("image.png".to_string(), SkipReason::Binary),
+        ("latin1.txt".to_string(), SkipReason::Binary),
+        ("small.txt".to_string(), SkipReason::BudgetExhausted { limit: 32 }),
+    ]);
+
+    // Files are keyed relative to the root
+    assert_eq!(context.get_file_content(&PathBuf::from("more.txt")).map(String::len), Some(30));
+    assert_eq!(context.file_contents.len(), 1);
+}
+
+#[test]
+fn test_default_ignores_skip_build_output_lockfiles_and_binaries() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "Cargo.toml", b"[package]");
+    write(root, "Cargo.lock", b"# lock");
+    write(root, "src/main.rs", b"fn main() {}");
+    write(root, "target/debug/build.rs", b"// output");
+    write(root, "web/node_modules/react/index.js", b"module");
+    write(root, "web/logo.png", &[0x89, b'P', b'N', b'G']);
+    write(root, "web/app.min.js", b"x");
+    write(root, "web/app.js", b"app");
+    // No pyproject.toml, so build/ is kept
+    write(root, "build/notes.md", b"# notes");
+
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&IgnoreSettings::default()).unwrap();
+    let files = loader.expand(".", root).unwrap();
+    assert_eq!(relative(root, &files), vec!["Cargo.toml", "build/notes.md", "src/main.rs", "web/app.js"]);
+    let files = loader.expand("web/**/*.js", root).unwrap();
+    assert_eq!(relative(root, &files), vec!["web/app.js"]);
+
+    // Turned off, and overridden from .aiignore
+    let off = IgnoreSettings { defaults: false, ..IgnoreSettings::default() };
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&off).unwrap();
+    assert!(loader.expand(".", root).unwrap().contains(&root.join("Cargo.lock")));
+    write(root, ".aiignore", b"!Cargo.lock\n");
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&IgnoreSettings::default()).unwrap();
+    let files = loader.expand(".", root).unwrap();
+    assert!(files.contains(&root.join("Cargo.lock")));
+    assert!(!files.contains(&root.join("target/debug/build.rs")));
+}
+
+#[test]
+fn test_gitignore_files_and_configured_patterns() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, ".gitignore", b"*.log\n/out/\n");
+    write(root, "app.log", b"log");
+    write(root, "out/report.txt", b"report");
+    write(root, "docs/out/guide.md", b"# guide");
+    write(root, "docs/.gitignore", b"drafts/\n!keep.log\n");
+    write(root, "docs/drafts/wip.md", b"wip");
+    write(root, "docs/keep.log", b"kept");
+    write(root, "docs/secret.env", b"KEY=1");
+
+    let settings = IgnoreSettings { patterns: vec!["*.env".to_string()], ..IgnoreSettings::default() };
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&settings).unwrap();
+    let files = loader.expand(".", root).unwrap();
+    assert_eq!(relative(root, &files), vec![".gitignore", "docs/.gitignore", "docs/keep.log", "docs/out/guide.md"]);
+
+    let no_git = IgnoreSettings { gitignore: false, ..IgnoreSettings::default() };
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&no_git).unwrap();
+    assert_eq!(loader.expand(".", root).unwrap().len(), 8);
+
+    let bad = IgnoreSettings { patterns: vec!["[z-a]".to_string()], ..IgnoreSettings::default() };
+    assert!(ContextLoader::new(root).unwrap().with_ignore_settings(&bad).is_err());
+}
+
+#[test]
+fn test_unparsable_gitignore_lines_do_not_drop_the_rest() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, ".gitignore", b"secrets.json\n[z-a]\nsrc/{a\nbuild**\n");
+    write(root, "secrets.json", b"{}");
+    write(root, "main.rs", b"fn main() {}");
+
+    let loader = ContextLoader::new(root).unwrap().with_ignore_settings(&IgnoreSettings::default()).unwrap();
+    assert_eq!(relative(root, &loader.expand(".", root).unwrap()), vec![".gitignore", "main.rs"]);
+    assert!(IgnoreRules::parse_lenient("secrets.json\n[z-a]\n").is_ignored(Path::new("secrets.json"), false));
+}
@@ -121,6 +121,67 @@ async fn test_context_scope_management() {
     assert_eq!(context.conversation_history.len(), 2);
 }
 
+#[test]
+fn test_scoped_contexts_isolate_branches() {
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::User, "Shared question"));
+
+    let mut branch_a = context.create_scoped("branch_a");
+    let mut branch_b = context.create_scoped("branch_b");
+    assert_eq!(branch_a.current_scope(), Some("branch_a"));
+    branch_a.add_message(Message::new(MessageRole::Assistant, "Answer A"));
+    branch_a.metadata.insert("winner".to_string(), json!("a"));
+    branch_b.add_message(Message::new(MessageRole::Assistant, "Answer B"));
+    branch_b.add_file_with_content(PathBuf::from("b.rs"), "fn b() {}".to_string());
+
+    // Branches do not see each other or leak into the parent
+    assert_eq!(branch_a.conversation_history.len(), 2);
+    assert_eq!(context.conversation_history.len(), 1);
+    assert_eq!(branch_a.conversation_history[1].scope.as_deref(), Some("branch_a"));
+    assert_eq!(branch_a.messages_in_scope("branch_a").len(), 1);
+
+    // Keep branch A, discard branch B by simply not merging it
+    context.merge_scope(branch_a);
+    drop(branch_b);
+    let contents: Vec<&str> = context.conversation_history.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["Shared question", "Answer A"]);
+    assert_eq!(context.conversation_history[1].scope, None);
+    assert_eq!(context.metadata.get("winner"), Some(&json!("a")));
+    assert!(context.file_contents.is_empty());
+
+    // Reusing a scope name later does not re-merge earlier messages
+    let mut again = context.create_scoped("branch_a");
+    again.add_message(Message::new(MessageRole::User, "Next"));
+    context.merge_scope(again);
+    assert_eq!(context.conversation_history.len(), 3);
+}
+
+#[test]
+fn test_nested_scopes_merge_selectively() {
+    let context = Context::new();
+    let mut step = context.create_scoped("step");
+    step.add_message(Message::new(MessageRole::User, "Plan"));
+
+    let mut draft = step.create_scoped("draft");
+    assert_eq!(draft.current_scope(), Some("step/draft"));
+    draft.add_message(Message::new(MessageRole::Assistant, "Rough draft"));
+    step.merge_scope(draft);
+    assert_eq!(step.conversation_history[1].scope.as_deref(), Some("step"));
+
+    let mut review = step.create_scoped("review");
+    review.add_message(Message::new(MessageRole::Assistant, "Review notes"));
+    review.add_message(Message::new(MessageRole::Assistant, "Scratch").with_scope("step/review/scratch"));
+    assert_eq!(review.messages_in_scope("step/review").len(), 2);
+
+    let mut merged = step.clone();
+    merged.merge_scope_where(review.clone(), |scope| scope != "step/review/scratch");
+    assert_eq!(merged.conversation_history.len(), 3);
+    assert_eq!(merged.conversation_history[2].content, "Review notes");
+
+    assert_eq!(review.discard_scope("step/review"), 2);
+    assert_eq!(review.conversation_history.len(), 2);
+}
+
 #[tokio::test]
 async fn test_context_file_tracking() {
     // Test enhanced file tracking capabilities
@@ -174,3 +235,267 @@ async fn test_context_diff_and_merge() {
     assert_eq!(context1.conversation_history.len(), 2);
     assert_eq!(context1.conversation_history.len(), context2.conversation_history.len());
 }
+
+#[test]
+fn test_context_diff_removals_edits_and_metadata() {
+    use ai_cli::context::Provenance;
+
+    let mut before = Context::new();
+    before.add_message(Message::new(MessageRole::System, "Be brief"));
+    before.add_message(Message::new(MessageRole::User, "First question"));
+    before.add_message(Message::new(MessageRole::Assistant, "First answer"));
+    before.add_message(Message::new(MessageRole::User, "Follow-up"));
+    before.metadata.insert("kept".to_string(), json!(1));
+    before.metadata.insert("changed".to_string(), json!("old"));
+    before.metadata.insert("dropped".to_string(), json!(true));
+    before.add_file_with_content(PathBuf::from("a.rs"), "fn a() {}".to_string());
+    before.add_file_with_content(PathBuf::from("b.rs"), "fn b() {}".to_string());
+
+    let mut after = before.clone();
+    after.conversation_history[2].content = "Edited answer".to_string();
+    after.conversation_history.remove(3);
+    after.metadata.insert("changed".to_string(), json!("new"));
+    after.metadata.remove("dropped");
+    after.remove_file(&PathBuf::from("b.rs"));
+    after.add_file_with_provenance(
+        PathBuf::from("c.rs"),
+        "fn c() {}".to_string(),
+        Provenance::cli_flag("--context", "c.rs"),
+    );
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.message_offset, 2);
+    assert_eq!(diff.removed_messages.len(), 2);
+    assert_eq!(diff.added_messages, vec![Message::new(MessageRole::Assistant, "Edited answer")]);
+    assert_eq!(diff.metadata_changes.len(), 1);
+    assert_eq!(diff.metadata_changes["changed"], json!("new"));
+    assert_eq!(diff.removed_metadata, vec!["dropped".to_string()]);
+    assert_eq!(diff.changed_files.keys().collect::<Vec<_>>(), vec![&PathBuf::from("c.rs")]);
+    assert_eq!(diff.removed_files, vec![PathBuf::from("b.rs")]);
+    assert!(before.diff(&before).is_empty());
+
+    let mut synced = before.clone();
+    synced.apply_diff(diff);
+    assert_eq!(synced.conversation_history, after.conversation_history);
+    assert_eq!(synced.metadata, after.metadata);
+    assert_eq!(synced.file_contents, after.file_contents);
+    assert_eq!(synced.file_provenance, after.file_provenance);
+    assert!(synced.diff(&after).is_empty());
+}
+
+#[test]
+fn test_apply_diff_to_a_context_that_has_moved_on() {
+    let mut base = Context::new();
+    base.add_message(Message::new(MessageRole::User, "Question"));
+    base.add_message(Message::new(MessageRole::Assistant, "Draft"));
+
+    let mut edited = base.clone();
+    edited.conversation_history[1].content = "Final".to_string();
+    let diff = base.diff(&edited);
+
+    // The target gained a message at the front; the edit still lands on the right message
+    let mut target = base.clone();
+    target.conversation_history.insert(0, Message::new(MessageRole::System, "Scope"));
+    target.apply_diff(diff);
+    let contents: Vec<&str> = target.conversation_history.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["Scope", "Question", "Final"]);
+}
+
+#[tokio::test]
+async fn test_context_provenance_tracking() {
+    use ai_cli::context::Provenance;
+
+    let mut context = Context::new();
+    context.add_message(
+        Message::new(MessageRole::System, "From flag")
+            .with_provenance(Provenance::cli_flag("--context", "notes.txt")),
+    );
+    context.add_message(Message::new(MessageRole::User, "No provenance"));
+    context.add_file_with_provenance(
+        PathBuf::from("/src/lib.rs"),
+        "pub mod x;".to_string(),
+        Provenance::Retrieval { source: "index".to_string() },
+    );
+
+    // Provenance survives serialization
+    let serialized = serde_json::to_string(&context).unwrap();
+    let restored: Context = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(
+        restored.conversation_history[0].provenance,
+        Some(Provenance::cli_flag("--context", "notes.txt"))
+    );
+    assert!(restored.file_provenance.contains_key(&PathBuf::from("/src/lib.rs")));
+
+    let explanation = context.explain();
+    assert!(explanation.contains("Messages (2):"));
+    assert!(explanation.contains("cli --context notes.txt"));
+    assert!(explanation.contains("unknown"));
+    assert!(explanation.contains("/src/lib.rs (10 bytes) <- retrieval from index"));
+
+    context.remove_file(&PathBuf::from("/src/lib.rs"));
+    assert!(context.file_provenance.is_empty());
+}
+
+#[test]
+fn test_incremental_context_sends_only_changes() {
+    use ai_cli::context::IncrementalContext;
+    use std::path::PathBuf;
+
+    let long: String = (1..=40).map(|i| format!("line {}\n", i)).collect();
+    let mut ctx = Context::new();
+    ctx.add_file_with_content(PathBuf::from("src/lib.rs"), long.clone());
+    ctx.add_file_with_content(PathBuf::from("src/old.rs"), "old".to_string());
+    ctx.add_file_with_content(PathBuf::from("README.md"), "readme".to_string());
+
+    let mut incremental = IncrementalContext::new();
+    let first = incremental.prepare(&ctx);
+    assert_eq!(first.file_contents.len(), 3);
+    assert!(incremental.has_baseline());
+
+    ctx.add_file_with_content(PathBuf::from("src/lib.rs"), long.replace("line 20\n", "line twenty\n"));
+    ctx.remove_file(&PathBuf::from("src/old.rs"));
+    ctx.add_file_with_content(PathBuf::from("src/new.rs"), "fn new() {}".to_string());
+
+    let second = incremental.prepare(&ctx);
+    assert!(second.file_contents.is_empty());
+    let update = &second.conversation_history.last().unwrap().content;
+    assert!(update.starts_with("Incremental update: 1 modified, 1 added, 1 removed; 1 unchanged"));
+    assert!(update.contains("-line 20\n+line twenty"));
+    assert!(!update.contains("line 1\n"));
+    assert!(update.contains("=== added src/new.rs ===\nfn new() {}"));
+    assert!(update.contains("=== removed src/old.rs ==="));
+
+    let third = incremental.prepare(&ctx);
+    let update = &third.conversation_history.last().unwrap().content;
+    assert!(update.contains("no files changed"));
+
+    // A run that failed to send its update is rolled back, so the change is sent again
+    ctx.add_file_with_content(PathBuf::from("README.md"), "readme v2".to_string());
+    incremental.prepare(&ctx);
+    incremental.rollback();
+    let retried = incremental.prepare(&ctx);
+    assert!(retried.conversation_history.last().unwrap().content.contains("=== rewritten README.md ===\nreadme v2"));
+
+    incremental.reset();
+    assert_eq!(incremental.prepare(&ctx).file_contents.len(), 3);
+}
+
+#[test]
+fn test_diff_files_rewrites_small_files_in_full() {
+    use ai_cli::context::incremental::diff_files;
+    use ai_cli::context::FileChange;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    let previous = HashMap::from([(PathBuf::from("a.txt"), "one".to_string())]);
+    let current = HashMap::from([(PathBuf::from("a.txt"), "two".to_string())]);
+    assert_eq!(
+        diff_files(&previous, &current),
+        vec![FileChange::Rewritten { path: PathBuf::from("a.txt"), content: "two".to_string() }]
+    );
+}
+
+#[test]
+fn test_context_policy_parse_and_apply() {
+    use ai_cli::context::ContextPolicy;
+
+    assert_eq!("last:3".parse::<ContextPolicy>().unwrap(), ContextPolicy::Last(3));
+    assert_eq!("summary".parse::<ContextPolicy>().unwrap(), ContextPolicy::SummaryOnly);
+    assert!("last:x".parse::<ContextPolicy>().is_err());
+    for policy in [ContextPolicy::Full, ContextPolicy::Last(2), ContextPolicy::SummaryOnly, ContextPolicy::None] {
+        assert_eq!(policy.to_string().parse::<ContextPolicy>().unwrap(), policy);
+    }
+
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "Be terse"));
+    for i in 0..5 {
+        context.add_message(Message::new(MessageRole::User, format!("Message {}", i)));
+    }
+    let kept = |policy: ContextPolicy| {
+        let mut limited = context.clone();
+        policy.apply(&mut limited);
+        limited.conversation_history.into_iter().map(|m| m.content).collect::<Vec<_>>()
+    };
+    assert_eq!(kept(ContextPolicy::Full).len(), 6);
+    assert_eq!(kept(ContextPolicy::Last(2)), ["Be terse", "Message 3", "Message 4"]);
+    assert_eq!(kept(ContextPolicy::SummaryOnly), ["Be terse"]);
+    assert!(kept(ContextPolicy::None).is_empty());
+
+    // Gemini keeps its truncated history by default
+    for i in 5..20 {
+        context.add_message(Message::new(MessageRole::User, format!("Message {}", i)));
+    }
+    assert_eq!(context.filter_for_provider("gemini", &[]).conversation_history.len(), 11);
+    assert_eq!(context.filter_for_provider("claude", &[]).conversation_history.len(), 21);
+}
+
+#[test]
+fn test_context_filters_per_provider() {
+    use ai_cli::context::{ContextFilters, ContextPolicy, PolicyFilter};
+
+    let mut context = Context::new();
+    for i in 0..12 {
+        context.add_message(Message::new(MessageRole::User, format!("Message {}", i)));
+    }
+    context.add_file_with_content(PathBuf::from("src/lib.rs"), "pub fn lib() {}".to_string());
+
+    let codex = context.filter_for_provider("codex", &[]);
+    assert_eq!(codex.metadata["focus_mode"], json!("code"));
+    assert_eq!(codex.conversation_history.len(), 12);
+
+    let mut filters = ContextFilters::builtin();
+    filters.register("local", PolicyFilter(ContextPolicy::Last(2)));
+    filters.register("local", |context: &mut Context| context.file_contents.clear());
+    let local = context.filter_with("local", &[], &filters);
+    assert_eq!(local.conversation_history.len(), 2);
+    assert!(local.file_contents.is_empty());
+    assert_eq!(local.metadata["filtered_for_provider"], json!("local"));
+
+    filters.clear("gemini");
+    assert!(!filters.has_filters("gemini"));
+    assert_eq!(context.filter_with("gemini", &[], &filters).conversation_history.len(), 12);
+}
+
+#[tokio::test]
+async fn test_compactor_summarizes_old_turns() {
+    use ai_cli::context::{Compactor, Provenance};
+    use ai_cli::providers::mock::MockProvider;
+    use std::sync::Arc;
+
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "rules"));
+    for i in 0..7 {
+        context.add_message(Message::new(MessageRole::User, format!("question {}", i)));
+        context.add_message(Message::new(MessageRole::Assistant, format!("answer {}", i)));
+    }
+    let provider = Arc::new(MockProvider::new("claude").with_reply("They discussed 5 questions.").with_error("overloaded").with_reply("Ten questions so far."));
+    let compactor = Compactor::new(provider.clone()).with_max_tokens(10).with_keep_recent(3);
+
+    // A reply stays with its prompt, so 4 messages are kept instead of 3
+    assert_eq!(compactor.compact(&mut context).await.unwrap(), 10);
+    let history: Vec<&str> = context.conversation_history.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(history, [
+        "rules",
+        "Earlier in this conversation (summarized):\nThey discussed 5 questions.",
+        "question 5", "answer 5", "question 6", "answer 6",
+    ]);
+    assert_eq!(context.conversation_history[1].provenance, Some(Provenance::Compaction { messages: 10 }));
+    assert!(provider.prompts()[0].contains("user: question 0\n\nassistant: answer 0"));
+    assert!(!provider.prompts()[0].contains("rules"));
+
+    // A failed compaction leaves the context alone
+    context.add_message(Message::new(MessageRole::User, "question 7"));
+    context.add_message(Message::new(MessageRole::Assistant, "answer 7"));
+    let before = context.conversation_history.clone();
+    assert!(compactor.compact(&mut context).await.is_err());
+    assert_eq!(context.conversation_history.len(), before.len());
+
+    // The earlier summary is folded into the new one
+    assert_eq!(compactor.compact(&mut context).await.unwrap(), 2);
+    assert!(provider.prompts()[2].contains("summary: They discussed 5 questions."));
+    assert_eq!(context.conversation_history.iter().filter(|m| m.role == MessageRole::System).count(), 2);
+
+    // Under budget nothing is sent
+    assert_eq!(Compactor::new(provider.clone()).compact(&mut context).await.unwrap(), 0);
+    assert_eq!(provider.prompts().len(), 3);
+}
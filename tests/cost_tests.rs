@@ -0,0 +1,108 @@
+use ai_cli::config::Config;
+use ai_cli::history::RunStore;
+use ai_cli::pipeline::{PipelineExecutor, PipelineStep};
+use ai_cli::providers::pricing::{CostSummary, ModelPrice, PricingTable, Usage};
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use async_trait::async_trait;
+use futures::stream;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[test]
+fn test_usage_from_response_metadata() {
+    let response = Response::new("hi").with_usage(Usage::new(1200, 300));
+    assert_eq!(Usage::from_response(&response), Some(Usage::new(1200, 300)));
+    assert_eq!(Usage::from_response(&Response::new("hi")), None);
+}
+
+#[test]
+fn test_pricing_uses_longest_prefix_and_overrides() {
+    let table = PricingTable::builtin();
+    let flash = table.price("gemini-2.5-flash-001").unwrap();
+    let lite = table.price("gemini-2.5-flash-lite").unwrap();
+    assert!(lite.input < flash.input);
+    assert!(table.price("some-local-model").is_none());
+
+    let cost = table.cost("claude-sonnet-4-20250514", Usage::new(1_000_000, 100_000)).unwrap();
+    assert!((cost - 4.5).abs() < 1e-9);
+
+    let overrides = HashMap::from([("claude-sonnet-4".to_string(), ModelPrice { input: 1.0, output: 2.0 })]);
+    let table = table.with_overrides(&overrides);
+    let cost = table.cost("claude-sonnet-4-20250514", Usage::new(1_000_000, 1_000_000)).unwrap();
+    assert!((cost - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_cost_summary_totals_and_display() {
+    let priced = Response::new("a")
+        .with_usage(Usage::new(1000, 200))
+        .with_metadata("model", "claude-sonnet-4")
+        .with_metadata("cost_usd", "0.006000");
+    let unpriced = Response::new("b").with_usage(Usage::new(10, 5)).with_metadata("model", "custom");
+    let unreported = Response::new("c");
+    let summary = CostSummary::from_responses([("claude", &priced), ("gemini", &unpriced), ("codex", &unreported)]);
+
+    assert_eq!(summary.usage(), Usage::new(1010, 205));
+    assert!((summary.cost_usd() - 0.006).abs() < 1e-9);
+    assert!(!summary.is_complete());
+    let text = summary.to_string();
+    assert!(text.contains("[1] claude (claude-sonnet-4): 1000 in + 200 out tokens, $0.0060"));
+    assert!(text.contains("[2] gemini (custom): 10 in + 5 out tokens, no price for this model"));
+    assert!(text.contains("[3] codex: usage not reported"));
+    assert!(text.ends_with("Total: 1010 in + 205 out tokens, $0.0060 (excludes unpriced steps)"));
+}
+
+#[test]
+fn test_pricing_config_section() {
+    let config: Config = toml::from_str("[pricing.\"my-model\"]\ninput = 0.5\noutput = 1.5\n").unwrap();
+    assert_eq!(config.pricing["my-model"], ModelPrice { input: 0.5, output: 1.5 });
+}
+
+/// Reports fixed token usage like a real API would
+struct MeteredProvider;
+
+#[async_trait]
+impl AIProvider for MeteredProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Ok(Response::new("ok").with_usage(Usage::new(2000, 1000)))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Ok(Box::pin(stream::once(async { Ok("ok".to_string()) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "metered"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some("claude-3-5-haiku-latest")
+    }
+}
+
+#[tokio::test]
+async fn test_executor_prices_reported_usage() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("metered", Arc::new(MeteredProvider));
+    let steps = vec![PipelineStep::new("metered", "a"), PipelineStep::new("metered", "b")];
+    let responses = executor.execute(&steps, Context::new()).await.unwrap();
+
+    assert_eq!(responses[0].metadata["model"], "claude-3-5-haiku-latest");
+    assert_eq!(responses[0].metadata["cost_usd"], "0.005600");
+
+    let summary = CostSummary::from_responses(steps.iter().map(|s| s.provider.as_str()).zip(&responses));
+    assert!((summary.cost_usd() - 0.0112).abs() < 1e-9);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let store = RunStore::new(dir.path());
+    let mut run = store.create("pipeline", "metered:a -> metered:b").unwrap();
+    run.record_cost(&summary);
+    run.finish(None).unwrap();
+    let record = store.list().unwrap().remove(0);
+    assert_eq!(record.usage, Some(Usage::new(4000, 2000)));
+    assert!((record.cost_usd.unwrap() - 0.0112).abs() < 1e-9);
+}
@@ -0,0 +1,43 @@
+use ai_cli::diagnostics::{Diagnostic, render_error};
+use ai_cli::pipeline::PipelineParser;
+
+#[test]
+fn test_empty_action_points_after_colon() {
+    let err = PipelineParser::parse("claude: -> gemini:test").unwrap_err();
+    let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!(diagnostic.span, 7..7);
+    assert_eq!(
+        diagnostic.to_string(),
+        "Action cannot be empty in step: 'claude:'\n  |\n1 | claude: -> gemini:test\n  |        ^ action expected here\n  |\n  = help: add what claude should do after ':', e.g. 'claude:review'"
+    );
+}
+
+#[test]
+fn test_spans_in_nested_and_wide_chains() {
+    let err = PipelineParser::parse("claude:list -> map[jobs=2](gemini:x -> codex)").unwrap_err();
+    let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!(&diagnostic.input[diagnostic.span.clone()], "codex");
+    assert_eq!(diagnostic.help.as_deref(), Some("add an action: 'codex:<action>'"));
+
+    let err = PipelineParser::parse("claude:設計 -> :実装").unwrap_err();
+    assert!(err.to_string().contains("1 | claude:設計 -> :実装\n  |                ^ provider expected here"), "{}", err);
+}
+
+#[test]
+fn test_unknown_provider_suggestion() {
+    let err = PipelineParser::parse_known("claude:design -> gemnii:review", &["claude", "gemini"]).unwrap_err();
+    let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!(diagnostic.span, 17..23);
+    assert_eq!(diagnostic.help.as_deref(), Some("did you mean 'gemini'?"));
+    assert!(PipelineParser::parse_known("claude:x", &["claude"]).is_ok());
+}
+
+#[test]
+fn test_render_error_colors_and_fallback() {
+    let diagnostic = Diagnostic::new("Bad step", "a:b", 2..3).with_label("here");
+    let plain = render_error(&diagnostic.clone().into(), false);
+    assert_eq!(plain, "Bad step\n  |\n1 | a:b\n  |   ^ here");
+    colored::control::set_override(true);
+    assert!(render_error(&diagnostic.into(), true).contains("\x1b["));
+    assert_eq!(render_error(&anyhow::anyhow!("plain failure"), true), "plain failure");
+}
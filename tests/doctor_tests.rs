@@ -0,0 +1,122 @@
+use ai_cli::auth::AuthManager;
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::doctor::{Check, Doctor, DoctorReport, Status, check_config_file};
+use ai_cli::pipeline::PipelineExecutor;
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use clap::Parser;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_endpoint_reachable_on_any_status() {
+    let auth = AuthManager::new();
+    let executor = PipelineExecutor::new();
+    let transport = Arc::new(FakeTransport::new().with_status(404, "not found").with_connection_error("dns failure"));
+    let doctor = Doctor::new(&auth, &executor).with_transport(transport.clone());
+
+    let check = doctor.check_endpoint("claude").await;
+    assert_eq!(check.status, Status::Ok);
+    assert!(check.detail.contains("https://api.anthropic.com (HTTP 404)"));
+    assert!(check.latency_ms.is_some());
+
+    let check = doctor.check_endpoint("gemini").await;
+    assert_eq!(check.status, Status::Fail);
+    assert!(check.detail.contains("dns failure"));
+    assert_eq!(transport.requests()[1].url, "https://generativelanguage.googleapis.com/v1beta");
+
+    assert_eq!(doctor.check_endpoint("codex").await.status, Status::Skip);
+}
+
+#[tokio::test]
+async fn test_round_trip_uses_model_listing() {
+    let auth = AuthManager::new();
+    let models = Arc::new(FakeTransport::new()
+        .with_json(serde_json::json!({"data": [{"id": "claude-a"}, {"id": "claude-b"}]}))
+        .with_status(401, "invalid x-api-key"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("key".to_string()).with_model("claude-b").with_transport(models.clone())));
+    executor.register_provider("codex", Arc::new(MockProvider::new("codex")));
+    let doctor = Doctor::new(&auth, &executor);
+
+    let check = doctor.check_round_trip("claude").await;
+    assert_eq!(check.status, Status::Ok, "{}", check.detail);
+    assert!(check.detail.ends_with("2 models"));
+    assert!(models.requests()[0].url.contains("/v1/models"));
+
+    let check = doctor.check_round_trip("claude").await;
+    assert_eq!(check.status, Status::Fail);
+    assert!(check.detail.contains("invalid x-api-key"));
+
+    assert_eq!(doctor.check_round_trip("codex").await.status, Status::Skip);
+    assert_eq!(doctor.check_round_trip("gemini").await.status, Status::Skip);
+}
+
+#[tokio::test]
+async fn test_auth_check_reports_source_or_remedy() {
+    let mut auth = AuthManager::new();
+    auth.set_api_key("codex", "sk-test");
+    let executor = PipelineExecutor::new();
+    let doctor = Doctor::new(&auth, &executor);
+
+    let check = doctor.check_auth("codex").await;
+    assert_eq!((check.status, check.detail.as_str()), (Status::Ok, "programmatic"));
+
+    let check = doctor.check_auth("unknown").await;
+    assert_eq!(check.status, Status::Fail);
+    assert!(check.detail.contains("UNKNOWN_API_KEY"));
+
+    let session = doctor.check_session("codex");
+    if session.status != Status::Ok {
+        assert_eq!(session.status, Status::Skip);
+        assert!(session.paths[0].ends_with(".codex/config.json"));
+    }
+}
+
+#[test]
+fn test_config_file_check() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    assert_eq!(check_config_file("user", &path).status, Status::Skip);
+
+    std::fs::write(&path, "default_profile = \"work\"\n").unwrap();
+    assert_eq!(check_config_file("user", &path).status, Status::Ok);
+
+    std::fs::write(&path, "default_profile = [\n").unwrap();
+    let check = check_config_file("user", &path);
+    assert_eq!(check.status, Status::Fail);
+    assert!(check.detail.contains("Invalid config file"));
+}
+
+#[test]
+fn test_report_table_and_health() {
+    let mut report = DoctorReport {
+        checks: vec![
+            Check::new("claude", "auth", Status::Ok, "env ANTHROPIC_API_KEY"),
+            Check::new("gemini", "endpoint", Status::Warn, "slow"),
+        ],
+    };
+    assert!(report.healthy());
+    let table = report.to_string();
+    assert!(table.starts_with("SUBJECT  CHECK      STATUS DETAIL\n"));
+    assert!(table.contains("claude   auth       ok     env ANTHROPIC_API_KEY\n"));
+
+    report.checks.push(Check::new("codex", "auth", Status::Fail, "no credentials"));
+    assert!(!report.healthy());
+    assert_eq!(report.failures().count(), 1);
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["checks"][2]["status"], "fail");
+}
+
+#[test]
+fn test_doctor_command_parses() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "doctor", "-p", "claude", "--timeout", "3", "--json"]).unwrap();
+    match args.command {
+        Some(Command::Doctor { provider, timeout, json }) => {
+            assert_eq!(provider.as_deref(), Some("claude"));
+            assert_eq!(timeout, 3);
+            assert!(json);
+        }
+        _ => panic!("expected doctor command"),
+    }
+}
@@ -0,0 +1,63 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::context::embed::split_inputs;
+use ai_cli::context::{EmbedFormat, Embedder, Embeddings, HashEmbedder};
+use ai_cli::providers::openai::OpenAIEmbedder;
+use ai_cli::providers::testing::FakeTransport;
+use clap::Parser;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_openai_embeddings_request() {
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({
+        "data": [
+            {"index": 1, "embedding": [0.0, 1.0]},
+            {"index": 0, "embedding": [1.0, 0.0]}
+        ]
+    })));
+    let embedder = OpenAIEmbedder::new("sk-test").with_model("text-embedding-3-large").with_transport(transport.clone());
+
+    let vectors = embedder.embed(&["first".to_string(), "second".to_string()]).await.unwrap();
+    assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+    let request = &transport.requests()[0];
+    assert_eq!(request.url, "https://api.openai.com/v1/embeddings");
+    assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+    assert_eq!(request.json().unwrap(), serde_json::json!({"model": "text-embedding-3-large", "input": ["first", "second"]}));
+}
+
+#[tokio::test]
+async fn test_embeddings_json_and_binary_output() {
+    let inputs = split_inputs("parse config\n\nload files\n", true);
+    assert_eq!(inputs, vec!["parse config", "load files"]);
+    let embeddings = Embeddings::compute(&HashEmbedder::new(8), &inputs).await.unwrap();
+    assert_eq!((embeddings.embedder.as_str(), embeddings.dimensions, embeddings.embeddings.len()), ("local", 8, 2));
+
+    let mut json = Vec::new();
+    embeddings.write(EmbedFormat::Json, &mut json).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["dimensions"], 8);
+    assert_eq!(value["embeddings"][1].as_array().unwrap().len(), 8);
+
+    let mut binary = Vec::new();
+    embeddings.write(EmbedFormat::Binary, &mut binary).unwrap();
+    assert_eq!(binary.len(), 8 + 2 * 8 * 4);
+    assert_eq!(&binary[..8], &[2, 0, 0, 0, 8, 0, 0, 0]);
+    let first = f32::from_le_bytes(binary[8..12].try_into().unwrap());
+    assert_eq!(first, embeddings.embeddings[0][0]);
+}
+
+#[test]
+fn test_embed_command_parses() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "embed", "notes.txt", "--embedder", "openai", "--lines", "--format", "binary"]).unwrap();
+    match args.command {
+        Some(Command::Embed { file, embedder, lines, format }) => {
+            assert_eq!(file, Some("notes.txt".into()));
+            assert_eq!(embedder, "openai");
+            assert!(lines);
+            assert_eq!(format, EmbedFormat::Binary);
+        }
+        _ => panic!("expected embed command"),
+    }
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "embed"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Embed { file: None, lines: false, format: EmbedFormat::Json, .. })));
+}
@@ -0,0 +1,171 @@
+use ai_cli::config::Config;
+use ai_cli::history::RunStore;
+use ai_cli::history::encryption::{self, Encryption, KeySource};
+use ai_cli::history::manifest::RunManifest;
+use ai_cli::history::session::{Session, SessionStore};
+use ai_cli::pipeline::{BatchJob, BatchJobStore, PipelineStep};
+use ai_cli::pipeline::sandbox::{self, FileWriter, Sandbox};
+use ai_cli::providers::{Context, ProviderOptions, Response};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Few PBKDF2 rounds keep the tests fast; real files use the default
+fn encryption(passphrase: &str) -> Arc<Encryption> {
+    Arc::new(Encryption::from_passphrase(passphrase).unwrap().with_iterations(1_000))
+}
+
+#[test]
+fn test_round_trip_and_wrong_passphrase() {
+    let key = encryption("correct horse");
+    let sealed = key.encrypt(b"fn main() {}").unwrap();
+    assert!(encryption::is_encrypted(&sealed));
+    assert!(!sealed.windows(4).any(|w| w == b"main"));
+    assert_eq!(key.decrypt(&sealed).unwrap(), b"fn main() {}");
+    // Every file gets a fresh nonce
+    assert_ne!(key.encrypt(b"fn main() {}").unwrap(), sealed);
+
+    // Another key from the same passphrase has its own salt and reads files by their header
+    let other = encryption("correct horse");
+    assert_eq!(other.decrypt(&sealed).unwrap(), b"fn main() {}");
+    let wrong = encryption("battery staple");
+    assert!(wrong.decrypt(&sealed).unwrap_err().to_string().contains("wrong passphrase"));
+
+    let mut tampered = sealed.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(key.decrypt(&tampered).is_err());
+    assert!(key.decrypt(b"plain text").is_err());
+    assert!(Encryption::from_passphrase("").is_err());
+}
+
+#[test]
+fn test_sessions_are_encrypted_on_disk_and_read_transparently() {
+    let dir = TempDir::new().unwrap();
+    // Written before encryption was turned on
+    SessionStore::new(dir.path()).save(&Session::new("old")).unwrap();
+
+    let store = SessionStore::new(dir.path()).with_encryption(encryption("s3cret"));
+    let mut session = store.load_or_new("work").unwrap();
+    session.record("Where is the API key loaded?", "In src/auth/credentials.rs");
+    let path = store.save(&session).unwrap();
+
+    let raw = std::fs::read(&path).unwrap();
+    assert!(encryption::is_encrypted(&raw));
+    assert!(!String::from_utf8_lossy(&raw).contains("credentials.rs"));
+    assert_eq!(store.load("work").unwrap(), session);
+    assert_eq!(store.list().unwrap().len(), 2);
+    assert!(store.load("old").unwrap().messages.is_empty());
+
+    // Without the key the session cannot be read, and says why
+    let error = SessionStore::new(dir.path()).load("work").unwrap_err();
+    assert!(format!("{:#}", error).contains("is encrypted"), "{:#}", error);
+    let error = SessionStore::new(dir.path()).with_encryption(encryption("guess")).load("work").unwrap_err();
+    assert!(format!("{:#}", error).contains("wrong passphrase"), "{:#}", error);
+    assert!(SessionStore::new(dir.path()).list().is_err());
+}
+
+#[test]
+fn test_run_history_is_encrypted_on_disk_and_read_transparently() {
+    let dir = TempDir::new().unwrap();
+    let store = RunStore::new(dir.path()).with_encryption(encryption("s3cret"));
+    let steps = vec![PipelineStep::new("claude", "review the payment code")];
+    let manifest = RunManifest::new("pipeline", &steps, ProviderOptions::default(), &Config::default());
+
+    let mut run = store.create(&manifest.command, &manifest.chain).unwrap();
+    run.set_manifest(manifest);
+    let step_dir = run.record_step(0, &steps[0], &Response::new("card numbers are logged")).unwrap();
+    run.record_context(&Context::new()).unwrap();
+    let report = run.record_report("postmortem.md", "Step 1 failed: card numbers are logged").unwrap();
+    run.finish(None).unwrap();
+
+    for path in [
+        report,
+        run.dir().join("run.json"),
+        run.dir().join("manifest.json"),
+        run.dir().join("context.txt"),
+        step_dir.join("action.txt"),
+        step_dir.join("response.md"),
+        step_dir.join("metadata.json"),
+    ] {
+        let raw = std::fs::read(&path).unwrap();
+        assert!(encryption::is_encrypted(&raw), "{} is not encrypted", path.display());
+    }
+    assert!(!String::from_utf8_lossy(&std::fs::read(step_dir.join("response.md")).unwrap()).contains("card numbers"));
+
+    let records = store.list().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].chain, "claude:review the payment code");
+    let dir = store.find("last").unwrap();
+    assert_eq!(RunManifest::load_with(&dir, store.encryption()).unwrap().chain, "claude:review the payment code");
+    assert!(RunManifest::load(&dir).is_err());
+    // Without the key, listing says why instead of hiding the run
+    let error = RunStore::new(store.dir()).list().unwrap_err();
+    assert!(format!("{:#}", error).contains("is encrypted"), "{:#}", error);
+    let error = RunStore::new(store.dir()).with_encryption(encryption("guess")).list().unwrap_err();
+    assert!(format!("{:#}", error).contains("wrong passphrase"), "{:#}", error);
+}
+
+#[test]
+fn test_key_check_file_shares_one_salt_and_catches_wrong_passphrases() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("data").join(encryption::KEY_CHECK_FILE);
+    let key = |passphrase: &str| Encryption::from_passphrase(passphrase).unwrap().with_iterations(1_000);
+    let first = key("s3cret").with_key_check(&path).unwrap();
+    assert!(encryption::is_encrypted(&std::fs::read(&path).unwrap()));
+
+    // Later processes adopt the stored salt, so their files share one derived key
+    let second = key("s3cret").with_key_check(&path).unwrap();
+    let header = |data: &[u8]| data[..29].to_vec();
+    assert_eq!(header(&first.encrypt(b"a").unwrap()), header(&second.encrypt(b"b").unwrap()));
+    assert_eq!(second.decrypt(&first.encrypt(b"a").unwrap()).unwrap(), b"a");
+
+    let error = key("guess").with_key_check(&path).unwrap_err();
+    assert!(format!("{:#}", error).contains("wrong passphrase"), "{:#}", error);
+}
+
+#[test]
+fn test_sandboxed_files_are_encrypted_until_applied() {
+    let project = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let key = encryption("s3cret");
+    let sandbox = Sandbox::create_with(run.path(), Some(key.clone())).unwrap();
+    let written = FileWriter::sandboxed(project.path(), sandbox).write(Path::new("src/secret.rs"), "const PIN: u32 = 1234;\n").unwrap();
+
+    for path in [written, run.path().join(sandbox::SANDBOX_FILE)] {
+        assert!(encryption::is_encrypted(&std::fs::read(&path).unwrap()), "{} is not encrypted", path.display());
+    }
+    assert!(Sandbox::open(run.path()).is_err());
+
+    let changes = Sandbox::open_with(run.path(), Some(key)).unwrap().apply(project.path(), false).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(std::fs::read_to_string(project.path().join("src/secret.rs")).unwrap(), "const PIN: u32 = 1234;\n");
+}
+
+#[test]
+fn test_batch_jobs_keep_their_placeholder_mapping_encrypted() {
+    let dir = TempDir::new().unwrap();
+    let store = BatchJobStore::new(dir.path()).with_encryption(encryption("s3cret"));
+    let job = BatchJob {
+        id: "msgbatch_01".to_string(),
+        provider: "claude".to_string(),
+        submitted_at: 1,
+        inputs: vec![None],
+        placeholders: vec![("[EMAIL_1]".to_string(), "alice@example.com".to_string())],
+    };
+    let path = store.save(&job).unwrap();
+
+    let raw = std::fs::read(&path).unwrap();
+    assert!(encryption::is_encrypted(&raw));
+    assert!(!String::from_utf8_lossy(&raw).contains("alice@example.com"));
+    assert_eq!(store.load("msgbatch_01").unwrap(), job);
+    assert!(BatchJobStore::new(dir.path()).load("msgbatch_01").is_err());
+}
+
+#[test]
+fn test_encryption_settings_parse() {
+    let config: Config = toml::from_str("[encryption]\nenabled = true\nkey = \"keyring\"\n").unwrap();
+    assert!(config.encryption.enabled);
+    assert_eq!(config.encryption.key, KeySource::Keyring);
+    assert!(!Config::default().encryption.enabled);
+    assert_eq!(Config::default().encryption.key, KeySource::Passphrase);
+}
@@ -0,0 +1,231 @@
+use ai_cli::config::Config;
+use ai_cli::error::{AuthError, ConfigError, Error, ExitCode, ProviderError, TransformError};
+use ai_cli::pipeline::{FailureKind, FallbackBehavior, JsonExtractorTransform, PipelineExecutor, PipelineStep};
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use anyhow::{Context as AnyhowContext, anyhow};
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Provider that fails every call with a fixed HTTP status
+struct StatusProvider {
+    status: u16,
+    calls: AtomicUsize,
+}
+
+impl StatusProvider {
+    fn new(status: u16) -> Self {
+        Self { status, calls: AtomicUsize::new(0) }
+    }
+}
+
+#[async_trait]
+impl AIProvider for StatusProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(ProviderError::from_status("status", self.status, format!("API error: {}", self.status)).into())
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "status"
+    }
+}
+
+struct TextProvider;
+
+#[async_trait]
+impl AIProvider for TextProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Ok(Response::new("{\"other\": 1}"))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "text"
+    }
+}
+
+#[test]
+fn test_provider_error_retryable_by_status() {
+    assert!(ProviderError::from_status("claude", 429, "").retryable);
+    assert!(ProviderError::from_status("claude", 503, "").retryable);
+    assert!(ProviderError::from_status("claude", 408, "").retryable);
+    assert!(!ProviderError::from_status("claude", 400, "").retryable);
+    assert!(!ProviderError::from_status("claude", 404, "").retryable);
+    assert!(ProviderError::network("claude", true, "timed out").status.is_none());
+}
+
+#[test]
+fn test_classify_finds_typed_error_through_context() {
+    let err = anyhow::Error::from(ProviderError::from_status("gemini", 500, "Gemini API error: 500"))
+        .context("Failed to summarize");
+    match Error::classify(&err) {
+        Some(Error::Provider(e)) => {
+            assert_eq!(e.provider, "gemini");
+            assert_eq!(e.status, Some(500));
+            assert!(e.retryable);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let auth: anyhow::Error = AuthError { provider: "claude".into(), detail: "expired".into() }.into();
+    assert!(matches!(Error::classify(&auth), Some(Error::Auth(e)) if e.provider == "claude"));
+    assert!(Error::classify(&anyhow!("plain failure")).is_none());
+}
+
+#[test]
+fn test_failure_kind_uses_status() {
+    let rate: anyhow::Error = ProviderError::from_status("claude", 429, "slow down").into();
+    assert_eq!(FailureKind::classify(&rate), FailureKind::RateLimit);
+    let forbidden: anyhow::Error = ProviderError::from_status("claude", 403, "no access").into();
+    assert_eq!(FailureKind::classify(&forbidden), FailureKind::Auth);
+    let offline: anyhow::Error = ProviderError::network("claude", true, "dns error").into();
+    assert_eq!(FailureKind::classify(&offline), FailureKind::Network);
+}
+
+#[test]
+fn test_config_errors_are_typed() {
+    let err = Config::from_toml("profiles = 3").unwrap_err();
+    assert!(matches!(Error::classify(&err), Some(Error::Config(_))));
+
+    let err = Config::layered(None, "[providers.claude]\napi_key = \"sk\"", Path::new("/repo/.ai-cli.toml")).unwrap_err();
+    match Error::classify(&err) {
+        Some(Error::Config(ConfigError { path: Some(path), message })) => {
+            assert_eq!(path, Path::new("/repo/.ai-cli.toml"));
+            assert!(message.contains("project config may not set"));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let err = Config::default().profile("missing").with_context(|| "Failed to activate profile").unwrap_err();
+    assert!(matches!(Error::classify(&err), Some(Error::Config(_))));
+}
+
+#[tokio::test]
+async fn test_pipeline_error_reports_step_index() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("text", Arc::new(TextProvider));
+    executor.register_provider("status", Arc::new(StatusProvider::new(503)));
+    executor.set_max_retries(0);
+
+    let steps = vec![PipelineStep::new("text", "draft"), PipelineStep::new("status", "review")];
+    let err = executor.execute(&steps, Context::new()).await.unwrap_err();
+    match Error::classify(&err) {
+        Some(Error::Pipeline(failure)) => {
+            assert_eq!(failure.step_index, 1);
+            assert_eq!(failure.provider, "status");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_permanent_provider_errors_skip_retries() {
+    let executor = PipelineExecutor::new();
+    let bad_request = Arc::new(StatusProvider::new(400));
+    let unavailable = Arc::new(StatusProvider::new(503));
+    executor.register_provider("bad", bad_request.clone());
+    executor.register_provider("busy", unavailable.clone());
+    executor.set_max_retries(2);
+
+    assert!(executor.execute(&[PipelineStep::new("bad", "go")], Context::new()).await.is_err());
+    assert_eq!(bad_request.calls.load(Ordering::SeqCst), 1);
+
+    assert!(executor.execute(&[PipelineStep::new("busy", "go")], Context::new()).await.is_err());
+    assert_eq!(unavailable.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_transform_failure_is_typed() {
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("text", Arc::new(TextProvider));
+    executor.set_continue_on_error(true);
+    let seen = Arc::new(Mutex::new(None));
+    let sink = seen.clone();
+    executor.set_step_callback(Box::new(move |result| {
+        *sink.lock().unwrap() = result.get_error().and_then(Error::classify);
+    }));
+
+    let transform = Arc::new(JsonExtractorTransform::with_fallback("answer", FallbackBehavior::ReturnError));
+    let steps = vec![PipelineStep::new("text", "extract").with_transform(transform)];
+    executor.execute(&steps, Context::new()).await.unwrap();
+    assert!(matches!(
+        seen.lock().unwrap().take(),
+        Some(Error::Transform(TransformError::FieldNotFound { field })) if field == "answer"
+    ));
+}
+
+#[test]
+fn test_exit_codes_by_error_class() {
+    let auth: anyhow::Error = AuthError { provider: "claude".into(), detail: "expired".into() }.into();
+    assert_eq!(ExitCode::for_error(&auth).code(), 3);
+    let api: anyhow::Error = ProviderError::from_status("claude", 500, "server error").into();
+    assert_eq!(ExitCode::for_error(&api).code(), 4);
+    let slow: anyhow::Error = ProviderError::timeout("claude", "operation timed out").into();
+    assert_eq!(ExitCode::for_error(&slow).code(), 6);
+    let config = Config::from_toml("profiles = 3").unwrap_err();
+    assert_eq!(ExitCode::for_error(&config).code(), 2);
+    assert_eq!(ExitCode::for_error(&anyhow!("disk full")).code(), 1);
+    assert_eq!(ExitCode::Pipeline.code(), 5);
+}
+
+#[tokio::test]
+async fn test_pipeline_exit_code_follows_failure_kind() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("status", Arc::new(StatusProvider::new(429)));
+    executor.set_max_retries(0);
+
+    let err = executor.execute(&[PipelineStep::new("status", "go")], Context::new()).await.unwrap_err();
+    assert_eq!(ExitCode::for_error(&err), ExitCode::Provider);
+}
+
+#[test]
+fn test_retry_after_header_forms() {
+    use ai_cli::providers::http::retry_after;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let headers = |pairs: &[(&'static str, &'static str)]| {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        map
+    };
+    // Wed, 21 Oct 2015 07:28:00 GMT
+    let now = UNIX_EPOCH + Duration::from_secs(1_445_412_470);
+
+    assert_eq!(retry_after(&headers(&[("retry-after", "7")]), now), Some(Duration::from_secs(7)));
+    assert_eq!(retry_after(&headers(&[("retry-after", "1.5")]), now), Some(Duration::from_millis(1500)));
+    assert_eq!(retry_after(&headers(&[("retry-after-ms", "250"), ("retry-after", "1")]), now), Some(Duration::from_millis(250)));
+    assert_eq!(retry_after(&headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]), now), Some(Duration::from_secs(10)));
+    assert_eq!(retry_after(&headers(&[("retry-after", "Wed, 21 Oct 2015 07:27:00 GMT")]), now), Some(Duration::ZERO));
+    assert_eq!(retry_after(&headers(&[("retry-after", "soon")]), now), None);
+    assert_eq!(retry_after(&headers(&[("retry-after", "-3")]), now), None);
+    assert_eq!(retry_after(&HeaderMap::new(), SystemTime::now()), None);
+}
+
+#[test]
+fn test_rate_limited_provider_error() {
+    let error = ProviderError::from_status("claude", 429, "slow down").with_retry_after(Some(std::time::Duration::from_secs(2)));
+    assert!(error.is_rate_limited());
+    assert!(error.retryable);
+    assert_eq!(error.retry_after, Some(std::time::Duration::from_secs(2)));
+    assert!(!ProviderError::from_status("claude", 503, "unavailable").is_rate_limited());
+}
@@ -0,0 +1,126 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::eval::{EvalCase, EvalReport, Evaluator, Metric, Variant, parse_verdict};
+use ai_cli::pipeline::{PipelineExecutor, PipelineParser};
+use ai_cli::providers::Context;
+use ai_cli::providers::mock::MockProvider;
+use clap::Parser;
+use std::sync::Arc;
+
+fn cases(lines: &[&str]) -> Vec<EvalCase> {
+    lines.iter().map(|line| EvalCase::parse(line).unwrap()).collect()
+}
+
+#[test]
+fn test_parse_cases() {
+    let case = EvalCase::parse(r#"{"id": 3, "input": "2+2", "expected": "4", "contains": ["four"], "criteria": "is correct"}"#).unwrap();
+    assert_eq!(case.id.as_deref(), Some("3"));
+    assert_eq!(case.expected.as_deref(), Some("4"));
+    assert_eq!(case.contains, vec!["four"]);
+    assert!(EvalCase::parse(r#"{"expected": "4"}"#).is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cases.jsonl");
+    std::fs::write(&path, "{\"input\": \"a\"}\n\n{\"input\": 1}\n").unwrap();
+    let err = format!("{:#}", EvalCase::read_file(&path).unwrap_err());
+    assert!(err.contains("cases.jsonl:3"), "{}", err);
+}
+
+#[test]
+fn test_metrics() {
+    assert_eq!(Metric::Exact.score(" Paris\n", "Paris"), 1.0);
+    assert_eq!(Metric::Exact.score("Paris, France", "Paris"), 0.0);
+    assert_eq!(Metric::Contains.score("It is Paris.", "Paris"), 1.0);
+    assert_eq!(Metric::Similarity.score("the cat sat", "The cat sat"), 1.0);
+    let partial = Metric::Similarity.score("the cat sat down", "the cat sat");
+    assert!(partial > 0.8 && partial < 1.0, "{}", partial);
+    assert_eq!(Metric::Similarity.score("dog", "cat"), 0.0);
+}
+
+#[test]
+fn test_parse_verdict() {
+    assert_eq!(parse_verdict("PASS: covers every point"), (true, "covers every point".to_string()));
+    assert_eq!(parse_verdict("\nfail - misses the edge case"), (false, "misses the edge case".to_string()));
+    assert!(!parse_verdict("Passing grade").0);
+}
+
+#[tokio::test]
+async fn test_eval_compares_providers() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude")));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_reply("no idea").with_error("quota")));
+
+    let steps = PipelineParser::parse("claude:answer {{env.INPUT}}").unwrap();
+    let variants = vec![Variant::with_provider(&steps, "claude"), Variant::with_provider(&steps, "gemini")];
+    let cases = cases(&[
+        r#"{"id": "capital", "input": "Paris", "expected": "Paris"}"#,
+        r#"{"input": "Rome", "contains": ["answer", "Rome"]}"#,
+    ]);
+
+    let report = Evaluator::new(&executor, 1).run(&variants, &Context::new(), &cases).await.unwrap();
+    assert!(!report.all_passed());
+    assert_eq!(report.summary.len(), 2);
+    assert_eq!((report.summary[0].variant.as_str(), report.summary[0].passed), ("claude", 2));
+    assert_eq!(report.summary[0].mean_score, Some(1.0));
+    assert_eq!((report.summary[1].passed, report.summary[1].errors), (0, 1));
+
+    let gemini: Vec<_> = report.results.iter().filter(|r| r.variant == "gemini").collect();
+    assert_eq!(gemini[0].checks[0].detail.as_deref(), Some("score 0.00 below 0.80"));
+    assert!(gemini[1].error.as_deref().unwrap().contains("quota"));
+
+    let text = report.to_string();
+    assert!(text.contains("claude"));
+    assert!(text.contains("[gemini] capital: expected: score 0.00 below 0.80"), "{}", text);
+    assert!(text.contains("[gemini] #2: error:"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_eval_judge_grades_criteria() {
+    let judge = Arc::new(MockProvider::new("claude").with_reply("FAIL: too vague").with_reply("PASS"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini")));
+    executor.register_provider("claude", judge.clone());
+
+    let variants = vec![Variant::chain(PipelineParser::parse("gemini:summarize {{env.INPUT}}").unwrap())];
+    let cases = cases(&[
+        r#"{"input": "a long text", "criteria": "is one sentence"}"#,
+        r#"{"input": "another text", "criteria": "is one sentence"}"#,
+    ]);
+
+    let err = Evaluator::new(&executor, 1).run(&variants, &Context::new(), &cases).await.unwrap_err();
+    assert!(err.to_string().contains("no judge"));
+
+    let report = Evaluator::new(&executor, 1).with_judge("claude").run(&variants, &Context::new(), &cases).await.unwrap();
+    assert_eq!(report.summary[0].variant, "gemini:summarize {{env.INPUT}}");
+    assert!(!report.results[0].passed);
+    assert_eq!(report.results[0].checks[0].detail.as_deref(), Some("too vague"));
+    assert!(report.results[1].passed);
+    let prompts = judge.prompts();
+    assert!(prompts[0].contains("## Criteria\nis one sentence"));
+    assert!(prompts[0].contains("## Output\nsummarize a long text"));
+}
+
+#[test]
+fn test_empty_report_passes() {
+    let report = EvalReport::new(Vec::new());
+    assert!(report.all_passed());
+    assert!(report.summary.is_empty());
+}
+
+#[test]
+fn test_eval_command() {
+    let args = CliArgs::try_parse_from([
+        "ai-cli", "eval", "cases.jsonl", "--chain", "claude:answer", "--providers", "claude,gemini",
+        "--metric", "similarity", "--threshold", "0.6", "--judge", "claude", "--jobs", "4",
+    ]).unwrap();
+    match args.command {
+        Some(Command::Eval { cases, providers, metric, threshold, judge, jobs, .. }) => {
+            assert_eq!(cases, std::path::PathBuf::from("cases.jsonl"));
+            assert_eq!(providers.len(), 2);
+            assert_eq!(metric, Metric::Similarity);
+            assert_eq!(threshold, 0.6);
+            assert_eq!(judge.as_deref(), Some("claude"));
+            assert_eq!(jobs, 4);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
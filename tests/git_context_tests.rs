@@ -0,0 +1,107 @@
+use ai_cli::context::git::{ChangeKind, add_diffs_to_context, collect_diff, parse_diff};
+use ai_cli::context::{DiffSource, Provenance};
+use ai_cli::providers::Context;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-fn old() {}
++fn new() {}
+diff --git a/NOTES.md b/NOTES.md
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/NOTES.md
+@@ -0,0 +1 @@
++notes
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 4444444..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/old/name.rs b/new/name.rs
+similarity index 100%
+rename from old/name.rs
+rename to new/name.rs
+";
+
+#[test]
+fn test_parse_diff_splits_per_file() {
+    let diffs = parse_diff(DIFF);
+    let summary: Vec<(&Path, ChangeKind)> = diffs.iter().map(|d| (d.path.as_path(), d.kind)).collect();
+    assert_eq!(summary, vec![
+        (Path::new("src/lib.rs"), ChangeKind::Modified),
+        (Path::new("NOTES.md"), ChangeKind::Added),
+        (Path::new("gone.txt"), ChangeKind::Deleted),
+        (Path::new("new/name.rs"), ChangeKind::Renamed),
+    ]);
+    assert_eq!(diffs[3].old_path.as_deref(), Some(Path::new("old/name.rs")));
+    assert!(diffs[0].patch.starts_with("diff --git a/src/lib.rs"));
+    assert!(diffs[0].patch.ends_with("+fn new() {}\n"));
+    assert!(parse_diff("").is_empty());
+}
+
+#[test]
+fn test_add_diffs_to_context() {
+    let mut context = Context::new();
+    assert_eq!(add_diffs_to_context(&mut context, parse_diff(DIFF), &DiffSource::Staged), 4);
+    assert_eq!(add_diffs_to_context(&mut context, parse_diff(DIFF), &DiffSource::WorkingTree), 4);
+
+    let staged = context.get_file_content(&PathBuf::from("src/lib.rs.diff")).unwrap();
+    assert!(staged.starts_with("# modified (staged changes)\n"));
+    assert!(context.get_file_content(&PathBuf::from("src/lib.rs (working tree).diff")).is_some());
+    let renamed = context.get_file_content(&PathBuf::from("new/name.rs.diff")).unwrap();
+    assert!(renamed.starts_with("# renamed from old/name.rs"));
+    assert_eq!(
+        context.file_provenance.get(&PathBuf::from("NOTES.md.diff")),
+        Some(&Provenance::GitDiff { source: "staged".to_string() })
+    );
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com", "-c", "commit.gpgsign=false"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(status.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&status.stderr));
+}
+
+#[test]
+fn test_collect_diff_from_repository() {
+    let dir = TempDir::new().unwrap();
+    let repo = dir.path();
+    git(repo, &["init", "-q"]);
+    std::fs::write(repo.join("a.txt"), "one\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "init"]);
+
+    std::fs::write(repo.join("a.txt"), "two\n").unwrap();
+    std::fs::write(repo.join("b.txt"), "new\n").unwrap();
+    git(repo, &["add", "b.txt"]);
+
+    let unstaged = collect_diff(repo, &DiffSource::WorkingTree).unwrap();
+    assert_eq!(unstaged.len(), 1);
+    assert_eq!(unstaged[0].path, PathBuf::from("a.txt"));
+    assert!(unstaged[0].patch.contains("+two"));
+
+    let staged = collect_diff(repo, &DiffSource::Staged).unwrap();
+    assert_eq!(staged.len(), 1);
+    assert_eq!(staged[0].kind, ChangeKind::Added);
+
+    git(repo, &["commit", "-q", "-am", "second"]);
+    let range = collect_diff(repo, &DiffSource::Range("HEAD~1..HEAD".to_string())).unwrap();
+    assert_eq!(range.len(), 2);
+
+    let err = collect_diff(repo, &DiffSource::Range("no-such-ref..HEAD".to_string())).unwrap_err();
+    assert!(err.to_string().contains("git diff (no-such-ref..HEAD) failed"));
+}
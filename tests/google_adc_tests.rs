@@ -0,0 +1,62 @@
+use ai_cli::auth::google::{AccessToken, AdcKind, GoogleAdc};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_parse_authorized_user_adc() {
+    let json = r#"{
+        "type": "authorized_user",
+        "client_id": "id.apps.googleusercontent.com",
+        "client_secret": "secret",
+        "refresh_token": "refresh",
+        "quota_project_id": "my-project"
+    }"#;
+
+    let adc = GoogleAdc::from_json(json).unwrap();
+    assert_eq!(adc.kind(), AdcKind::AuthorizedUser);
+    assert_eq!(adc.quota_project(), Some("my-project"));
+}
+
+#[test]
+fn test_parse_authorized_user_missing_refresh_token() {
+    let json = r#"{"type": "authorized_user", "client_id": "id", "client_secret": "secret"}"#;
+
+    let result = GoogleAdc::from_json(json);
+    assert!(result.is_err());
+    assert!(result.err().unwrap().to_string().contains("refresh_token"));
+}
+
+#[test]
+fn test_service_account_delegates_to_gcloud() {
+    let json = r#"{"type": "service_account", "client_email": "sa@example.iam.gserviceaccount.com"}"#;
+
+    let adc = GoogleAdc::from_json(json).unwrap();
+    assert_eq!(adc.kind(), AdcKind::Gcloud);
+    assert_eq!(adc.quota_project(), None);
+}
+
+#[test]
+fn test_unsupported_adc_type() {
+    let result = GoogleAdc::from_json(r#"{"type": "mystery"}"#);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_invalid_adc_json() {
+    assert!(GoogleAdc::from_json("not json").is_err());
+}
+
+#[test]
+fn test_access_token_expiry() {
+    let fresh = AccessToken {
+        token: "t".to_string(),
+        expires_at: SystemTime::now() + Duration::from_secs(3600),
+    };
+    assert!(!fresh.is_expired());
+
+    // Tokens inside the refresh skew window count as expired
+    let expiring = AccessToken {
+        token: "t".to_string(),
+        expires_at: SystemTime::now() + Duration::from_secs(10),
+    };
+    assert!(expiring.is_expired());
+}
@@ -0,0 +1,131 @@
+use ai_cli::config::Config;
+use ai_cli::history::manifest::{RunManifest, config_hash};
+use ai_cli::history::{RunStatus, RunStore};
+use ai_cli::pipeline::PipelineStep;
+use ai_cli::providers::{Context, Response};
+use std::sync::Arc;
+use ai_cli::pipeline::IdentityTransform;
+
+#[test]
+fn test_run_layout_and_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+
+    let mut run = store.create("pipeline", "claude:design -> gemini:implement").unwrap();
+    assert!(run.dir().join("run.json").exists());
+    assert!(run.reports_dir().is_dir());
+    assert!(run.recordings_dir().is_dir());
+    assert_eq!(run.record().status, RunStatus::Running);
+
+    let plain = PipelineStep::new("claude", "design");
+    let transformed = PipelineStep::new("gemini", "implement").with_transform(Arc::new(IdentityTransform));
+    let step_dir = run.record_step(0, &plain, &Response::new("the design")).unwrap();
+    assert!(step_dir.ends_with("steps/01-claude"));
+    assert_eq!(std::fs::read_to_string(step_dir.join("response.md")).unwrap(), "the design");
+    assert_eq!(std::fs::read_to_string(step_dir.join("action.txt")).unwrap(), "design");
+
+    let mut response = Response::new("code");
+    response.raw = Some("```rust\ncode\n```".to_string());
+    let step_dir = run.record_step(1, &transformed, &response).unwrap();
+    assert_eq!(std::fs::read_to_string(step_dir.join("transformed.md")).unwrap(), "code");
+    assert_eq!(std::fs::read_to_string(step_dir.join("response.md")).unwrap(), "```rust\ncode\n```");
+
+    run.record_context(&Context::new()).unwrap();
+    run.finish(None).unwrap();
+
+    let records = store.list().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].status, RunStatus::Succeeded);
+    assert_eq!(records[0].chain, "claude:design -> gemini:implement");
+    assert!(records[0].finished_at.is_some());
+}
+
+#[test]
+fn test_failed_run_records_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+
+    let mut run = store.create("execute", "claude:hello").unwrap();
+    run.finish(Some("boom".to_string())).unwrap();
+
+    let records = store.list().unwrap();
+    assert_eq!(records[0].status, RunStatus::Failed);
+    assert_eq!(records[0].error.as_deref(), Some("boom"));
+}
+
+#[test]
+fn test_find_run_by_prefix_and_last() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+
+    assert!(store.find("last").is_err());
+
+    let first = store.create("execute", "claude:a").unwrap();
+    let second = store.create("execute", "claude:b").unwrap();
+
+    assert_eq!(store.find(first.id()).unwrap(), first.dir());
+    assert_eq!(store.find("last").unwrap(), store.find(second.id()).unwrap());
+    assert!(store.find("nope").is_err());
+    // Both ids share the date prefix
+    assert!(store.find(&first.id()[..4]).is_err());
+}
+
+#[test]
+fn test_manifest_records_steps_and_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+    let steps = vec![PipelineStep::new("claude", "design"), PipelineStep::new("gemini", "implement")];
+    let options = ai_cli::providers::ProviderOptions { temperature: Some(0.2), ..Default::default() };
+    let manifest = RunManifest::new("pipeline", &steps, options, &Config::default())
+        .with_inputs(&["src/".to_string()], &[("STAGE".to_string(), "prod".to_string())], &[glob::Pattern::new("CI_*").unwrap()]);
+    assert_eq!(manifest.chain, "claude:design -> gemini:implement");
+    assert_eq!(manifest.steps[1].options.temperature, Some(0.2));
+
+    let mut run = store.create(&manifest.command, &manifest.chain).unwrap();
+    run.set_manifest(manifest);
+    let response = Response::new("the design")
+        .with_metadata("model", "claude-sonnet-4")
+        .with_metadata("latency_ms", "1200")
+        .with_metadata("prompt_tokens", "10")
+        .with_metadata("completion_tokens", "20");
+    run.record_step(0, &steps[0], &response).unwrap();
+    run.finish(None).unwrap();
+
+    let loaded = RunManifest::load(&store.find("last").unwrap()).unwrap();
+    assert_eq!(loaded.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(loaded.context, vec!["src/"]);
+    assert_eq!(loaded.env.get("STAGE").map(String::as_str), Some("prod"));
+    assert_eq!(loaded.env_passthrough, vec!["CI_*"]);
+    assert_eq!(loaded.steps[0].model.as_deref(), Some("claude-sonnet-4"));
+    assert_eq!(loaded.steps[0].duration_ms, Some(1200));
+    assert_eq!(loaded.steps[0].usage.map(|u| u.total()), Some(30));
+    assert_eq!(loaded.steps[1].model, None);
+}
+
+#[test]
+fn test_manifest_missing_for_old_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+    let mut run = store.create("execute", "claude:hello").unwrap();
+    run.finish(None).unwrap();
+    let err = RunManifest::load(&store.find("last").unwrap()).unwrap_err();
+    assert!(err.to_string().contains("cannot be rerun"));
+}
+
+#[test]
+fn test_manifest_drift() {
+    let config = Config::default();
+    let steps = vec![PipelineStep::new("claude", "design")];
+    let mut manifest = RunManifest::new("pipeline", &steps, Default::default(), &config);
+    manifest.record_step(0, &Response::new("x").with_metadata("model", "claude-old"));
+
+    assert!(manifest.drift(&config, |_| Some("claude-old".to_string())).is_empty());
+    let notes = manifest.drift(&config, |_| Some("claude-new".to_string()));
+    assert_eq!(notes, vec!["step 1: claude now uses claude-new instead of claude-old"]);
+
+    let mut changed = config.clone();
+    changed.prompt_prefix = Some("Be brief.".to_string());
+    assert_eq!(config_hash(&config), config_hash(&Config::default()));
+    assert_ne!(config_hash(&config), config_hash(&changed));
+    assert!(manifest.drift(&changed, |_| None).iter().any(|n| n.contains("configuration changed")));
+}
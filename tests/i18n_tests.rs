@@ -0,0 +1,37 @@
+use ai_cli::cli::{CliArgs, localized_command};
+use ai_cli::i18n::{Lang, Msg};
+use clap::Parser;
+
+#[test]
+fn test_locale_detection() {
+    assert_eq!(Lang::from_locale("ja_JP.UTF-8"), Some(Lang::Ja));
+    assert_eq!(Lang::from_locale("en_US"), Some(Lang::En));
+    assert_eq!(Lang::from_locale("C"), None);
+    assert_eq!(Lang::from_locale("fr_FR.UTF-8"), None);
+    assert_eq!(Lang::detect(Some(Lang::Ja)), Lang::Ja);
+}
+
+#[test]
+fn test_messages_follow_current_language() {
+    assert_eq!(Msg::PipelineAborted(2).to_string(), "Pipeline aborted after step 2");
+    Lang::Ja.set_current();
+    assert_eq!(Msg::PipelineAborted(2).to_string(), "ステップ 2 の後でパイプラインを中止しました");
+    assert_eq!(Msg::ProviderUnavailable("codex").to_string(), "プロバイダ 'codex' は利用できません。--api-key を指定するか認証を設定してください。");
+    Lang::En.set_current();
+    assert_eq!(Msg::Warning(&"disk full").to_string(), "Warning: disk full");
+}
+
+#[test]
+fn test_localized_help() {
+    let mut command = localized_command(Lang::Ja);
+    let help = command.render_help().to_string();
+    assert!(help.contains("単一の AI プロンプトを実行する"), "{}", help);
+    assert!(help.contains("補助的な出力を抑制する"), "{}", help);
+    let help = localized_command(Lang::En).render_help().to_string();
+    assert!(help.contains("Execute a single AI prompt"), "{}", help);
+
+    let args = CliArgs::try_parse_from(["ai-cli", "--lang", "ja", "version"]).unwrap();
+    assert_eq!(args.lang, Some(Lang::Ja));
+    let args = CliArgs::parse_localized(["ai-cli", "version", "--lang=ja"]);
+    assert_eq!(args.lang, Some(Lang::Ja));
+}
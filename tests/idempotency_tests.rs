@@ -0,0 +1,178 @@
+use ai_cli::pipeline::{BatchInput, BatchJob, ExecutionConfig, PipelineExecutor, PipelineStep};
+use ai_cli::providers::idempotency::{IdempotencyKeys, content_hash};
+use ai_cli::providers::openai::OpenAIBatch;
+use ai_cli::providers::structured::JsonMode;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{AIProvider, Capabilities, Context, ProviderOptions, Response, ResponseStream};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Replies from a queue, recording the idempotency key of every call
+#[derive(Default)]
+struct KeyRecorder {
+    replies: Mutex<VecDeque<Result<String, String>>>,
+    keys: Mutex<Vec<Option<String>>>,
+    delays_ms: Mutex<VecDeque<u64>>,
+}
+
+impl KeyRecorder {
+    fn new(replies: Vec<Result<&str, &str>>) -> Self {
+        let replies = replies.into_iter().map(|r| r.map(str::to_string).map_err(str::to_string)).collect();
+        Self { replies: Mutex::new(replies), ..Self::default() }
+    }
+
+    /// Hold back the replies to the first calls by these delays
+    fn with_delays(self, delays_ms: &[u64]) -> Self {
+        *self.delays_ms.lock().unwrap() = delays_ms.iter().copied().collect();
+        self
+    }
+
+    fn keys(&self) -> Vec<Option<String>> {
+        self.keys.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AIProvider for KeyRecorder {
+    async fn execute(&self, prompt: &str, context: &Context) -> anyhow::Result<Response> {
+        self.execute_with_options(prompt, context, &ProviderOptions::default()).await
+    }
+
+    async fn execute_with_options(&self, _prompt: &str, _context: &Context, options: &ProviderOptions) -> anyhow::Result<Response> {
+        self.keys.lock().unwrap().push(options.idempotency_key.clone());
+        let reply = self.replies.lock().unwrap().pop_front();
+        let delay_ms = self.delays_ms.lock().unwrap().pop_front().unwrap_or(0);
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        match reply {
+            Some(Ok(reply)) => Ok(Response::new(reply)),
+            Some(Err(error)) => Err(anyhow!(error)),
+            None => Ok(Response::new("done")),
+        }
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "recorder"
+    }
+}
+
+fn retrying_executor() -> PipelineExecutor {
+    PipelineExecutor::with_config(ExecutionConfig { max_retries: 2, retry_delay_ms: 1, ..ExecutionConfig::default() })
+}
+
+#[test]
+fn test_keys_hash_the_request_and_never_repeat() {
+    let keys = IdempotencyKeys::with_salt("run1");
+    let options = ProviderOptions::default();
+    let first = keys.key("claude", "summarize", &Context::new(), &options);
+    let second = keys.key("claude", "summarize", &Context::new(), &options);
+    assert!(first.starts_with("ai-cli-run1-0-"), "{}", first);
+    assert!(second.starts_with("ai-cli-run1-1-"), "{}", second);
+    assert_eq!(first["ai-cli-run1-0-".len()..], second["ai-cli-run1-1-".len()..]);
+
+    let other = keys.key("claude", "translate", &Context::new(), &options);
+    assert_ne!(other["ai-cli-run1-2-".len()..], first["ai-cli-run1-0-".len()..]);
+
+    assert_eq!(content_hash(&[b"ab", b"c"]).len(), 16);
+    assert_ne!(content_hash(&[b"ab", b"c"]), content_hash(&[b"a", b"bc"]));
+}
+
+#[tokio::test]
+async fn test_retries_reuse_the_key_and_record_it() {
+    let provider = Arc::new(KeyRecorder::new(vec![Err("timed out"), Ok("answer")]));
+    let executor = retrying_executor();
+    executor.register_provider("recorder", provider.clone());
+
+    let responses = executor.execute(&[PipelineStep::new("recorder", "summarize")], Context::new()).await.unwrap();
+    let keys = provider.keys();
+    assert_eq!(keys.len(), 2);
+    assert!(keys[0].is_some());
+    assert_eq!(keys[0], keys[1]);
+    assert_eq!(responses[0].metadata.get("idempotency_key"), keys[1].as_ref());
+}
+
+#[tokio::test]
+async fn test_late_reply_during_backoff_answers_the_retry() {
+    let provider = Arc::new(KeyRecorder::new(vec![Ok("late answer")]).with_delays(&[150]));
+    let executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 2, retry_delay_ms: 2000, ..ExecutionConfig::default() });
+    executor.register_provider("recorder", provider.clone());
+
+    let step = PipelineStep::new("recorder", "summarize").with_timeout(Duration::from_millis(50));
+    let started = std::time::Instant::now();
+    let responses = executor.execute(&[step], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "late answer");
+    // Answered without sending the request again or waiting out the backoff
+    assert_eq!(provider.keys().len(), 1);
+    assert!(started.elapsed() < Duration::from_millis(1500));
+}
+
+#[tokio::test]
+async fn test_late_reply_beats_the_retry_in_flight() {
+    let provider = Arc::new(KeyRecorder::new(vec![Ok("first"), Ok("second")]).with_delays(&[150, 5000]));
+    let executor = retrying_executor();
+    executor.register_provider("recorder", provider.clone());
+
+    let step = PipelineStep::new("recorder", "summarize").with_timeout(Duration::from_millis(100));
+    let responses = executor.execute(&[step], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "first");
+    let keys = provider.keys();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[0], keys[1]);
+}
+
+#[tokio::test]
+async fn test_separate_requests_get_separate_keys() {
+    let provider = Arc::new(KeyRecorder::new(vec![]));
+    let executor = retrying_executor();
+    executor.register_provider("recorder", provider.clone());
+
+    let steps = vec![PipelineStep::new("recorder", "same"), PipelineStep::new("recorder", "same")];
+    executor.execute(&steps, Context::new()).await.unwrap();
+    let keys = provider.keys();
+    assert_ne!(keys[0], keys[1]);
+
+    // Asking again with schema feedback is a new request, not a retry
+    let provider = Arc::new(KeyRecorder::new(vec![Ok("not json"), Ok(r#"{"ok": true}"#)]));
+    let mut executor = retrying_executor();
+    executor.register_provider("recorder", provider.clone());
+    executor.set_options(ProviderOptions { json: Some(JsonMode::new(serde_json::json!({"type": "object"})).with_retries(1)), ..ProviderOptions::default() });
+    let responses = executor.execute(&[PipelineStep::new("recorder", "answer")], Context::new()).await.unwrap();
+    let keys = provider.keys();
+    assert_ne!(keys[0], keys[1]);
+    assert_eq!(responses[0].metadata.get("idempotency_key"), keys[1].as_ref());
+}
+
+#[tokio::test]
+async fn test_openai_batch_submission_sends_idempotency_keys() {
+    let submit = |transport: Arc<FakeTransport>| async move {
+        let api = OpenAIBatch::new("key").with_model("gpt-test").with_transport(transport);
+        let inputs = vec![BatchInput::parse(r#""hello""#).unwrap()];
+        BatchJob::submit(&api, "Echo {{env.INPUT}}", &Context::new(), inputs, &ProviderOptions::default()).await.unwrap()
+    };
+    let transport = Arc::new(
+        FakeTransport::new()
+            .with_json(serde_json::json!({"id": "file-1"}))
+            .with_json(serde_json::json!({"id": "batch_1"}))
+            .with_json(serde_json::json!({"id": "file-1"}))
+            .with_json(serde_json::json!({"id": "batch_1"})),
+    );
+    submit(transport.clone()).await;
+    submit(transport.clone()).await;
+
+    let requests = transport.requests();
+    let key = requests[1].header("idempotency-key").unwrap().to_string();
+    assert!(key.starts_with("ai-cli-batch-"), "{}", key);
+    assert_eq!(requests[0].header("idempotency-key"), Some(format!("{}-file", key).as_str()));
+    // The same requests submitted again carry the same keys, so OpenAI returns the first batch
+    assert_eq!(requests[3].header("idempotency-key"), Some(key.as_str()));
+}
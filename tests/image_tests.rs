@@ -0,0 +1,101 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineDefinition, PipelineExecutor, PipelineStep};
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::gemini::GeminiProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{AIProvider, Capabilities, Context, Image, Message, MessageRole};
+use clap::Parser;
+use std::sync::Arc;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\nfake";
+
+fn png_file(dir: &tempfile::TempDir) -> std::path::PathBuf {
+    let path = dir.path().join("diagram.png");
+    std::fs::write(&path, PNG).unwrap();
+    path
+}
+
+#[test]
+fn test_image_from_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = Image::from_file(&png_file(&dir)).unwrap();
+    assert_eq!(image, Image::from_bytes("image/png", PNG));
+    assert_eq!(image.data, "iVBORw0KGgpmYWtl");
+
+    let text = dir.path().join("notes.txt");
+    std::fs::write(&text, "hi").unwrap();
+    assert!(Image::from_file(&text).unwrap_err().to_string().contains("unsupported image type"));
+}
+
+#[tokio::test]
+async fn test_step_images_reach_claude() {
+    let dir = tempfile::tempdir().unwrap();
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({
+        "content": [{"type": "text", "text": "A box diagram."}],
+        "usage": {"input_tokens": 10, "output_tokens": 4}
+    })));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("key".to_string()).with_transport(transport.clone())));
+
+    let step = PipelineStep::new("claude", "Describe this").with_images([png_file(&dir)]);
+    let responses = executor.execute(&[step], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "A box diagram.");
+
+    let body = transport.requests()[0].json().unwrap();
+    let content = &body["messages"][0]["content"];
+    assert_eq!(content[0]["type"], "image");
+    assert_eq!(content[0]["source"]["media_type"], "image/png");
+    assert_eq!(content[0]["source"]["data"], "iVBORw0KGgpmYWtl");
+    assert_eq!(content[1], serde_json::json!({"type": "text", "text": "Describe this"}));
+}
+
+#[tokio::test]
+async fn test_message_images_reach_gemini() {
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({
+        "candidates": [{"content": {"parts": [{"text": "Two cats."}]}}]
+    })));
+    let provider = GeminiProvider::new("key".to_string()).with_transport(transport.clone());
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::User, "What is in here?").with_images(vec![Image::from_bytes("image/jpeg", b"jpg")]));
+
+    provider.execute("Answer briefly", &context).await.unwrap();
+    let body = transport.requests()[0].json().unwrap();
+    let parts = &body["contents"][0]["parts"];
+    assert_eq!(parts[0]["inline_data"]["mime_type"], "image/jpeg");
+    assert_eq!(parts[1]["text"], "What is in here?\n\nAnswer briefly");
+}
+
+#[tokio::test]
+async fn test_images_refused_without_vision() {
+    let dir = tempfile::tempdir().unwrap();
+    let executor = PipelineExecutor::new();
+    let blind = MockProvider::new("codex").with_capabilities(Capabilities::default());
+    executor.register_provider("codex", Arc::new(blind));
+
+    let step = PipelineStep::new("codex", "Describe this").with_images([png_file(&dir)]);
+    let err = executor.execute(&[step], Context::new()).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("does not accept images"));
+}
+
+#[test]
+fn test_image_flags_and_definition() {
+    let args = <CliArgs as Parser>::try_parse_from([
+        "ai-cli", "execute", "-p", "claude", "-P", "Compare", "--image", "a.png", "--image", "b.jpg",
+    ])
+    .unwrap();
+    match args.command {
+        Some(Command::Execute { images, .. }) => assert_eq!(images, vec![std::path::PathBuf::from("a.png"), "b.jpg".into()]),
+        _ => panic!("expected execute command"),
+    }
+
+    let yaml = "name: look\nsteps:\n  - provider: gemini\n    action: Describe\n    images: [shots/home.png]\n";
+    let def: PipelineDefinition = serde_yaml::from_str(yaml).unwrap();
+    def.validate().unwrap();
+    assert_eq!(def.to_steps().unwrap()[0].images(), [std::path::PathBuf::from("shots/home.png")]);
+    assert_eq!(PipelineDefinition::from_yaml(&def.to_yaml().unwrap()).unwrap(), def);
+
+    let mut bad = def.clone();
+    bad.steps[0].images = vec!["report.pdf".into()];
+    assert!(bad.validate().unwrap_err().to_string().contains("Step 1: report.pdf is not a png"));
+}
@@ -0,0 +1,186 @@
+use ai_cli::context::index::{DEFAULT_CHUNK_LINES, chunk_text};
+use ai_cli::context::{Embedder, HashEmbedder, Provenance, Redactor, Retriever, VectorIndex};
+use ai_cli::pipeline::{PipelineExecutor, PipelineStep};
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use async_trait::async_trait;
+use futures::stream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+fn project() -> Vec<(PathBuf, String)> {
+    vec![
+        (
+            PathBuf::from("src/config.rs"),
+            "/// Parse the TOML config file\nfn parse_config(path: &Path) -> Config {\n    toml::from_str(&read(path))\n}\n".to_string(),
+        ),
+        (
+            PathBuf::from("src/http.rs"),
+            "/// Send an HTTP request with retries\nfn send_request(client: &Client) -> Response {\n    client.retry(3)\n}\n".to_string(),
+        ),
+    ]
+}
+
+#[test]
+fn test_chunk_text_prefers_blank_lines() {
+    let text: String = (1..=10).map(|i| if i == 4 { "\n".to_string() } else { format!("line {}\n", i) }).collect();
+    let chunks = chunk_text(&text, 6);
+    assert_eq!(chunks[0].0, 1);
+    assert_eq!(chunks[0].1, 4);
+    assert_eq!(chunks[1].0, 5);
+    assert_eq!(chunks.last().unwrap().1, 10);
+    assert!(chunk_text("", DEFAULT_CHUNK_LINES).is_empty());
+}
+
+#[tokio::test]
+async fn test_hash_embedder_scores_related_text_higher() {
+    let embedder = HashEmbedder::default();
+    let texts = vec![
+        "how is the config file parsed".to_string(),
+        "fn parseConfig(path) -> Config".to_string(),
+        "fn send_request(client) -> Response".to_string(),
+    ];
+    let vectors = embedder.embed(&texts).await.unwrap();
+    let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    assert!(dot(&vectors[0], &vectors[1]) > dot(&vectors[0], &vectors[2]));
+}
+
+#[tokio::test]
+async fn test_update_reuses_unchanged_files() {
+    let embedder = HashEmbedder::default();
+    let mut index = VectorIndex::new("/project", embedder.name());
+    let stats = index.update(project(), &embedder).await.unwrap();
+    assert_eq!((stats.embedded, stats.reused, stats.removed), (2, 0, 0));
+    assert_eq!(index.chunk_count(), 2);
+
+    let mut files = project();
+    files[1].1.push_str("// changed\n");
+    files.remove(0);
+    let stats = index.update(files, &embedder).await.unwrap();
+    assert_eq!((stats.embedded, stats.reused, stats.removed), (1, 0, 1));
+    assert_eq!(index.files.len(), 1);
+
+    let mut other = VectorIndex::new("/project", "gemini");
+    let err = other.update(project(), &embedder).await.unwrap_err();
+    assert!(err.to_string().contains("built with the 'gemini' embedder"));
+}
+
+/// Records what would be sent to an embeddings API
+#[derive(Default)]
+struct RecordingEmbedder {
+    texts: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl Embedder for RecordingEmbedder {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        self.texts.lock().unwrap().extend(texts.iter().cloned());
+        Ok(texts.iter().map(|_| vec![1.0]).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_secrets_are_redacted_before_embedding() {
+    let embedder = RecordingEmbedder::default();
+    let redactor = Redactor::new();
+    let mut files = project();
+    files.push((PathBuf::from(".env"), "DEBUG=1\nANTHROPIC_API_KEY=sk-ant-REDACTED\n".to_string()));
+    let mut index = VectorIndex::new("/project", embedder.name());
+    index.update_redacted(files.clone(), &embedder, Some(&redactor)).await.unwrap();
+
+    let sent = embedder.texts.lock().unwrap().join("\n");
+    assert!(!sent.contains("sk-ant-api03"), "{}", sent);
+    assert!(sent.contains("[REDACTED:anthropic_api_key]"), "{}", sent);
+    assert!(!serde_json::to_string(&index).unwrap().contains("sk-ant-api03"));
+    let log = redactor.log();
+    assert!(log.iter().all(|r| r.location == ".env"), "{:?}", log);
+    assert!(log.iter().any(|r| r.rule == "anthropic_api_key" && r.line == 2), "{:?}", log);
+
+    // Files are still recognized as unchanged by their original content
+    let stats = index.update_redacted(files, &embedder, Some(&redactor)).await.unwrap();
+    assert_eq!(stats.reused, 3);
+}
+
+#[tokio::test]
+async fn test_index_round_trip_and_search() {
+    let embedder = HashEmbedder::default();
+    let mut index = VectorIndex::new("/project", embedder.name());
+    index.update(project(), &embedder).await.unwrap();
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("index").join("project.json");
+    index.save(&path).unwrap();
+    let loaded = VectorIndex::load(&path).unwrap();
+    assert_eq!(loaded, index);
+    assert!(VectorIndex::load(&dir.path().join("missing.json")).unwrap_err().to_string().contains("ai-cli index"));
+
+    let query = embedder.embed(&["retry the http request".to_string()]).await.unwrap().remove(0);
+    let results = loaded.search(&query, 1);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, PathBuf::from("src/http.rs"));
+}
+
+#[tokio::test]
+async fn test_retriever_injects_chunks_with_provenance() {
+    let embedder = Arc::new(HashEmbedder::default());
+    let mut index = VectorIndex::new("/project", embedder.name());
+    index.update(project(), embedder.as_ref()).await.unwrap();
+    assert!(Retriever::new(index.clone(), embedder.clone(), 1).is_ok());
+    assert!(Retriever::new(VectorIndex::new("/project", "gemini"), embedder.clone(), 1).is_err());
+    let retriever = Retriever::new(index, embedder, 1).unwrap();
+
+    let mut context = Context::new();
+    assert_eq!(retriever.inject("where is the config parsed?", &mut context).await.unwrap(), 1);
+    let key = PathBuf::from("src/config.rs#L1-L4");
+    assert!(context.file_contents[&key].contains("parse_config"));
+    assert!(matches!(&context.file_provenance[&key], Provenance::Retrieval { source } if source.starts_with("index (local")));
+
+    // A file already in the context in full is not repeated
+    let mut context = Context::new();
+    context.add_file_with_content(PathBuf::from("src/config.rs"), "full file".to_string());
+    assert_eq!(retriever.inject("where is the config parsed?", &mut context).await.unwrap(), 0);
+}
+
+/// Records the files each call was given
+struct CapturingProvider {
+    seen: Mutex<Vec<PathBuf>>,
+}
+
+#[async_trait]
+impl AIProvider for CapturingProvider {
+    async fn execute(&self, _prompt: &str, context: &Context) -> anyhow::Result<Response> {
+        self.seen.lock().unwrap().extend(context.file_contents.keys().cloned());
+        Ok(Response::new("ok"))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Ok(Box::pin(stream::once(async { Ok("ok".to_string()) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "capture"
+    }
+}
+
+#[tokio::test]
+async fn test_executor_retrieves_per_step() {
+    let embedder = Arc::new(HashEmbedder::default());
+    let mut index = VectorIndex::new("/project", embedder.name());
+    index.update(project(), embedder.as_ref()).await.unwrap();
+
+    let provider = Arc::new(CapturingProvider { seen: Mutex::new(Vec::new()) });
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("capture", provider.clone());
+    executor.set_retriever(Arc::new(Retriever::new(index, embedder, 1).unwrap()));
+
+    let steps = vec![PipelineStep::new("capture", "retry the http request")];
+    executor.execute(&steps, Context::new()).await.unwrap();
+    assert_eq!(*provider.seen.lock().unwrap(), vec![PathBuf::from("src/http.rs#L1-L4")]);
+}
@@ -0,0 +1,33 @@
+use ai_cli::logging::level_for;
+use ai_cli::providers::http::{redacted_headers, redacted_url};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Url;
+use tracing::level_filters::LevelFilter;
+
+#[test]
+fn test_verbosity_levels() {
+    assert_eq!(level_for(0, false), LevelFilter::WARN);
+    assert_eq!(level_for(1, false), LevelFilter::INFO);
+    assert_eq!(level_for(2, false), LevelFilter::DEBUG);
+    assert_eq!(level_for(5, false), LevelFilter::TRACE);
+    assert_eq!(level_for(2, true), LevelFilter::ERROR);
+}
+
+#[test]
+fn test_credentials_are_redacted_from_logged_requests() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", HeaderValue::from_static("sk-ant-secret"));
+    headers.insert("authorization", HeaderValue::from_static("Bearer ya29.token"));
+    headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+    let logged = redacted_headers(&headers);
+    assert!(logged.contains(&("x-api-key".to_string(), "[REDACTED]".to_string())));
+    assert!(logged.contains(&("authorization".to_string(), "[REDACTED]".to_string())));
+    assert!(logged.contains(&("anthropic-version".to_string(), "2023-06-01".to_string())));
+
+    let url = Url::parse("https://example.com/v1beta/models?key=AIzaSecret&pageSize=10").unwrap();
+    let logged = redacted_url(&url);
+    assert!(!logged.contains("AIzaSecret"));
+    assert!(logged.contains("pageSize=10"));
+    let plain = Url::parse("https://example.com/v1/messages").unwrap();
+    assert_eq!(redacted_url(&plain), "https://example.com/v1/messages");
+}
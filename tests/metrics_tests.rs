@@ -0,0 +1,137 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{ExecutionConfig, Metrics, PipelineEvent, PipelineExecutor, PipelineObserver, PipelineStep};
+use ai_cli::providers::Context;
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::server::{ServerState, router};
+use ai_cli::telemetry::OtlpExporter;
+use clap::Parser;
+use std::sync::Arc;
+use tracing_subscriber::prelude::*;
+
+const HELLO: &str = r#"{"content":[{"type":"text","text":"Hello!"}],"model":"claude-3-5-sonnet-20240620","usage":{"input_tokens":9,"output_tokens":2}}"#;
+
+fn claude(transport: &Arc<FakeTransport>) -> Arc<ClaudeProvider> {
+    Arc::new(ClaudeProvider::new("test_key".to_string()).with_transport(transport.clone()))
+}
+
+#[tokio::test]
+async fn test_metrics_count_requests_tokens_retries_and_errors_per_provider() {
+    let metrics = Arc::new(Metrics::new());
+    let mut executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 1, retry_delay_ms: 1, ..ExecutionConfig::default() });
+    executor.add_observer(metrics.clone());
+    let transport = Arc::new(FakeTransport::new().with_status(200, HELLO).with_status(200, HELLO));
+    executor.register_provider("claude", claude(&transport));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_error("overloaded").with_error("overloaded")));
+
+    executor.execute(&[PipelineStep::new("claude", "a"), PipelineStep::new("claude", "b")], Context::new()).await.unwrap();
+    assert!(executor.execute(&[PipelineStep::new("gemini", "c")], Context::new()).await.is_err());
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE ai_cli_requests_total counter\n"), "{}", text);
+    assert!(text.contains("ai_cli_requests_total{provider=\"claude\",status=\"ok\"} 2\n"), "{}", text);
+    assert!(text.contains("ai_cli_requests_total{provider=\"gemini\",status=\"error\"} 1\n"), "{}", text);
+    assert!(text.contains("ai_cli_retries_total{provider=\"gemini\",reason=\"error\"} 1\n"), "{}", text);
+    assert!(text.contains("ai_cli_tokens_total{provider=\"claude\",kind=\"prompt\"} 18\n"), "{}", text);
+    assert!(text.contains("ai_cli_tokens_total{provider=\"claude\",kind=\"completion\"} 4\n"), "{}", text);
+    assert!(text.contains("ai_cli_request_duration_seconds_bucket{provider=\"claude\",le=\"+Inf\"} 2\n"), "{}", text);
+    assert!(text.contains("ai_cli_request_duration_seconds_count{provider=\"gemini\"} 1\n"), "{}", text);
+    assert!(text.contains("ai_cli_pipelines_total{status=\"ok\"} 1\n"), "{}", text);
+    assert!(text.contains("ai_cli_pipelines_total{status=\"error\"} 1\n"), "{}", text);
+}
+
+#[test]
+fn test_latency_histogram_buckets_are_cumulative() {
+    let metrics = Metrics::new();
+    for elapsed_ms in [100, 3_000, 500_000] {
+        metrics.on_event(&PipelineEvent::StepCompleted {
+            step_index: 0,
+            provider: "my \"local\" model".to_string(),
+            elapsed_ms,
+            retries: 0,
+            outcome: Err("failed".to_string()),
+        });
+    }
+    let text = metrics.render();
+    assert!(text.contains("ai_cli_request_duration_seconds_bucket{provider=\"my \\\"local\\\" model\",le=\"0.25\"} 1\n"), "{}", text);
+    assert!(text.contains("le=\"5\"} 2\n"), "{}", text);
+    assert!(text.contains("le=\"120\"} 2\n"), "{}", text);
+    assert!(text.contains("le=\"+Inf\"} 3\n"), "{}", text);
+    assert!(text.contains("ai_cli_request_duration_seconds_sum{provider=\"my \\\"local\\\" model\"} 503.1\n"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_server_exposes_metrics() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply("Hi")));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(ServerState::new(executor))).await.unwrap() });
+
+    let client = reqwest::Client::new();
+    client
+        .post(format!("http://{}/v1/execute", addr))
+        .json(&serde_json::json!({"provider": "claude", "prompt": "Say hi"}))
+        .send()
+        .await
+        .unwrap();
+    let response = client.get(format!("http://{}/metrics", addr)).send().await.unwrap();
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+    let text = response.text().await.unwrap();
+    assert!(text.contains("ai_cli_requests_total{provider=\"claude\",status=\"ok\"} 1\n"), "{}", text);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_spans_nest_pipeline_step_and_http_call_and_export_as_otlp() {
+    let exporter = Arc::new(OtlpExporter::new("http://collector:4318/"));
+    assert_eq!(exporter.url(), "http://collector:4318/v1/traces");
+    let _guard = tracing_subscriber::registry().with(exporter.layer()).set_default();
+
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", claude(&Arc::new(FakeTransport::new().with_status(200, HELLO))));
+    executor.execute(&[PipelineStep::new("claude", "hi")], Context::new()).await.unwrap();
+
+    let spans = exporter.pending();
+    let find = |name: &str| spans.iter().find(|s| s.name == name).unwrap_or_else(|| panic!("no {} span in {:?}", name, spans));
+    let (pipeline, step, http) = (find("pipeline"), find("step"), find("http"));
+    assert_eq!(pipeline.parent_span_id, None);
+    assert_eq!(step.parent_span_id.as_ref(), Some(&pipeline.span_id));
+    assert_eq!(http.parent_span_id.as_ref(), Some(&step.span_id));
+    assert!(spans.iter().all(|s| s.trace_id == pipeline.trace_id && s.trace_id.len() == 32 && s.span_id.len() == 16));
+    assert_eq!(step.attribute("provider"), Some("claude"));
+    assert_eq!(pipeline.attribute("status"), Some("ok"));
+    assert!(http.end_unix_nanos >= http.start_unix_nanos);
+
+    let collector = Arc::new(FakeTransport::new().with_status(200, "{}"));
+    let exporter = Arc::new(OtlpExporter::new("http://collector:4318").with_transport(collector.clone()));
+    let _guard = tracing_subscriber::registry().with(exporter.layer()).set_default();
+    executor.execute(&[PipelineStep::new("claude", "again")], Context::new()).await.ok();
+    assert_eq!(exporter.flush().await.unwrap(), 3);
+    let body = collector.requests()[0].json().unwrap();
+    assert_eq!(collector.requests()[0].url, "http://collector:4318/v1/traces");
+    assert_eq!(body["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"], "ai-cli");
+    let names: Vec<&str> = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["http", "step", "pipeline"]);
+    assert_eq!(exporter.flush().await.unwrap(), 0);
+}
+
+#[test]
+fn test_metrics_and_trace_arguments_parse() {
+    let args = CliArgs::try_parse_from([
+        "ai-cli", "--otlp-endpoint", "http://localhost:4318", "pipeline", "--chain", "claude:x", "--input-file", "in.jsonl",
+        "--metrics-addr", "127.0.0.1:9464",
+    ])
+    .unwrap();
+    assert_eq!(args.otlp_endpoint.as_deref(), Some("http://localhost:4318"));
+    match args.command {
+        Some(Command::Pipeline { metrics_addr, .. }) => assert_eq!(metrics_addr, Some("127.0.0.1:9464".parse().unwrap())),
+        other => panic!("unexpected command: {:?}", other),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--metrics-addr", "127.0.0.1:9464"]).is_err());
+}
@@ -0,0 +1,55 @@
+use ai_cli::cli::CliArgs;
+use ai_cli::error::ProviderError;
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::http::{is_loopback, set_offline};
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{AIProvider, Context};
+use axum::routing::post;
+use axum::{Json, Router};
+use clap::Parser;
+use reqwest::Url;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+fn reply() -> Value {
+    json!({"content": [{"type": "text", "text": "Hello!"}], "usage": {"input_tokens": 1, "output_tokens": 1}})
+}
+
+#[test]
+fn test_offline_flag() {
+    assert!(CliArgs::try_parse_from(["ai-cli", "--offline", "models"]).unwrap().offline);
+    assert!(CliArgs::try_parse_from(["ai-cli", "models", "--offline"]).unwrap().offline);
+}
+
+#[test]
+fn test_loopback_hosts() {
+    for url in ["http://localhost:11434/api", "http://127.0.0.1:8080", "http://[::1]:8080"] {
+        assert!(is_loopback(&Url::parse(url).unwrap()), "{}", url);
+    }
+    for url in ["https://api.anthropic.com/v1/messages", "http://10.0.0.1", "http://localhost.example.com"] {
+        assert!(!is_loopback(&Url::parse(url).unwrap()), "{}", url);
+    }
+}
+
+#[tokio::test]
+async fn test_offline_allows_only_local_endpoints() {
+    let app = Router::new().route("/v1/messages", post(|| async { Json(reply()) }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let local = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    set_offline(true);
+    let err = ClaudeProvider::new("key".to_string()).execute("hi", &Context::new()).await.unwrap_err();
+    let provider_error = err.chain().find_map(|e| e.downcast_ref::<ProviderError>()).expect("provider error");
+    assert!(!provider_error.retryable);
+    assert!(format!("{:#}", err).contains("Offline mode: api.anthropic.com needs network access"), "{:#}", err);
+
+    // A server on this machine, like a local model, still answers
+    let local = ClaudeProvider::new("key".to_string()).with_base_url(local);
+    assert_eq!(local.execute("hi", &Context::new()).await.unwrap().content, "Hello!");
+
+    // Recorded responses served by an injected transport need no network either
+    let recorded = ClaudeProvider::new("key".to_string()).with_transport(Arc::new(FakeTransport::new().with_json(reply())));
+    assert_eq!(recorded.execute("hi", &Context::new()).await.unwrap().content, "Hello!");
+    set_offline(false);
+}
@@ -0,0 +1,55 @@
+use ai_cli::pipeline::{ExecutionConfig, PipelineEvent, PipelineExecutor, PipelineStep};
+use ai_cli::providers::Context;
+use ai_cli::providers::mock::MockProvider;
+use std::sync::{Arc, Mutex};
+
+fn kind(event: &PipelineEvent) -> String {
+    match event {
+        PipelineEvent::StepStarted { step_index, .. } => format!("started {}", step_index),
+        PipelineEvent::Chunk { step_index, text } => format!("chunk {} {}", step_index, text),
+        PipelineEvent::RetryScheduled { step_index, attempt, .. } => format!("retry {} #{}", step_index, attempt),
+        PipelineEvent::StepCompleted { step_index, outcome, .. } => format!("completed {} {}", step_index, outcome.is_ok()),
+        PipelineEvent::PipelineCompleted { steps, succeeded, .. } => format!("done {} {}", steps, succeeded),
+    }
+}
+
+#[tokio::test]
+async fn test_streaming_run_emits_events_in_order() {
+    let mut executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 1, retry_delay_ms: 0, ..ExecutionConfig::default() });
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_error("overloaded").with_reply("draft")));
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_reply("review")));
+    let mut events = executor.subscribe();
+
+    let steps = vec![PipelineStep::new("claude", "write"), PipelineStep::new("gemini", "review")];
+    executor.execute_streaming(&steps, Context::new()).await.unwrap();
+
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        seen.push(kind(&event));
+    }
+    assert_eq!(seen, vec![
+        "started 0", "retry 0 #1", "chunk 0 draft", "completed 0 true",
+        "started 1", "chunk 1 review", "completed 1 true",
+        "done 2 true",
+    ]);
+}
+
+#[tokio::test]
+async fn test_observer_sees_failure() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_error("quota exceeded")));
+    executor.add_observer(Arc::new(move |event: &PipelineEvent| sink.lock().unwrap().push(event.clone())));
+
+    assert!(executor.execute(&[PipelineStep::new("claude", "write")], Context::new()).await.is_err());
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.iter().map(kind).collect::<Vec<_>>(), vec!["started 0", "completed 0 false", "done 1 false"]);
+    match &seen[1] {
+        PipelineEvent::StepCompleted { outcome: Err(error), provider, .. } => {
+            assert_eq!(provider, "claude");
+            assert!(error.contains("quota exceeded"));
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+}
@@ -1,5 +1,5 @@
-use ai_cli::pipeline::{PipelineStep, PipelineExecutor};
-use ai_cli::providers::{AIProvider, Context, Response, Message, MessageRole, Capabilities, ResponseStream};
+use ai_cli::pipeline::{ExecutionConfig, PipelineStep, PipelineExecutor, PromptAffixes};
+use ai_cli::providers::{AIProvider, Context, Response, Message, MessageRole, Capabilities, ResponseStream, ProviderOptions, UnauthorizedError};
 use ai_cli::auth::AuthManager;
 use std::sync::Arc;
 
@@ -73,7 +73,7 @@ fn create_error_provider() -> Arc<dyn AIProvider> {
 
 #[tokio::test]
 async fn test_execute_single_step_pipeline() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     
     let steps = vec![
@@ -89,7 +89,7 @@ async fn test_execute_single_step_pipeline() {
 
 #[tokio::test]
 async fn test_execute_multi_step_pipeline() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     executor.register_provider("gemini", create_mock_provider("gemini"));
     
@@ -108,7 +108,7 @@ async fn test_execute_multi_step_pipeline() {
 
 #[tokio::test]
 async fn test_context_propagation() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     executor.register_provider("gemini", create_mock_provider("gemini"));
     
@@ -128,7 +128,7 @@ async fn test_context_propagation() {
 
 #[tokio::test]
 async fn test_execute_with_initial_context() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     
     let steps = vec![
@@ -146,7 +146,7 @@ async fn test_execute_with_initial_context() {
 
 #[tokio::test]
 async fn test_execute_with_streaming() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     
     let steps = vec![
@@ -197,7 +197,7 @@ async fn test_execute_fails_unknown_provider() {
 
 #[tokio::test]
 async fn test_execute_with_step_error_handling() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     executor.register_provider("error", create_error_provider());
     
@@ -218,7 +218,7 @@ async fn test_execute_with_step_error_handling() {
 
 #[tokio::test]
 async fn test_execute_stops_on_error() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     executor.register_provider("error", create_error_provider());
     
@@ -262,7 +262,7 @@ async fn test_execute_with_callbacks() {
 
 #[tokio::test]
 async fn test_execute_with_retry() {
-    let mut executor = PipelineExecutor::new();
+    let executor = PipelineExecutor::new();
     executor.register_provider("claude", create_mock_provider("claude"));
     
     executor.set_max_retries(3);
@@ -275,4 +275,718 @@ async fn test_execute_with_retry() {
     let results = executor.execute(&steps, context).await.unwrap();
     
     assert_eq!(results.len(), 1);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_step_outputs_carry_provenance() {
+    use ai_cli::context::Provenance;
+
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", create_mock_provider("claude"));
+    executor.register_provider("gemini", create_mock_provider("gemini"));
+
+    let steps = vec![
+        PipelineStep::new("claude", "design"),
+        PipelineStep::new("gemini", "implement"),
+    ];
+
+    let (results, context) = executor.execute_with_context(&steps, Context::new()).await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(context.conversation_history.len(), 2);
+    assert_eq!(
+        context.conversation_history[1].provenance,
+        Some(Provenance::step_output(1, "gemini"))
+    );
+    assert!(context.explain().contains("step 2 output (gemini)"));
+}
+
+#[tokio::test]
+async fn test_get_provider_by_name() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", create_mock_provider("claude"));
+
+    assert_eq!(executor.get_provider("claude").unwrap().name(), "claude");
+    assert!(executor.get_provider("gemini").is_none());
+}
+
+#[tokio::test]
+async fn test_env_placeholders_rendered_with_step_override() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", create_mock_provider("claude"));
+
+    let mut context = Context::new();
+    context.environment.insert("LANG".to_string(), "Rust".to_string());
+    context.environment.insert("STYLE".to_string(), "terse".to_string());
+
+    let steps = vec![
+        PipelineStep::new("claude", "write {{env.LANG}} in a {{env.STYLE}} style"),
+        PipelineStep::new("claude", "port to {{env.LANG}}").with_env("LANG", "Go"),
+    ];
+
+    let (responses, _) = executor.execute_with_context(&steps, context).await.unwrap();
+    assert!(responses[0].content.ends_with("response to: write Rust in a terse style"));
+    assert!(responses[1].content.ends_with("response to: port to Go"));
+}
+
+// Provider whose credentials are rejected until it re-authenticates
+struct ExpiringProvider {
+    can_refresh: bool,
+    refreshed: std::sync::atomic::AtomicBool,
+    calls: std::sync::atomic::AtomicUsize,
+}
+
+impl ExpiringProvider {
+    fn new(can_refresh: bool) -> Self {
+        Self {
+            can_refresh,
+            refreshed: std::sync::atomic::AtomicBool::new(false),
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for ExpiringProvider {
+    async fn execute(&self, prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        if self.refreshed.load(std::sync::atomic::Ordering::SeqCst) {
+            Ok(Response::new(format!("fresh: {}", prompt)))
+        } else {
+            Err(UnauthorizedError { provider: "expiring".to_string(), detail: "token expired".to_string() }.into())
+        }
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "expiring"
+    }
+
+    async fn reauthenticate(&self) -> anyhow::Result<bool> {
+        self.refreshed.store(self.can_refresh, std::sync::atomic::Ordering::SeqCst);
+        Ok(self.can_refresh)
+    }
+}
+
+#[tokio::test]
+async fn test_reauthenticates_on_unauthorized_mid_pipeline() {
+    let provider = Arc::new(ExpiringProvider::new(true));
+    let executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 0, ..ExecutionConfig::default() });
+    executor.register_provider("expiring", provider.clone());
+
+    let responses = executor.execute(&[PipelineStep::new("expiring", "step")], Context::new()).await.unwrap();
+    assert!(responses[0].content.ends_with("fresh: step"));
+    assert_eq!(provider.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_unauthorized_without_refresh_fails_without_retrying() {
+    let provider = Arc::new(ExpiringProvider::new(false));
+    let executor = PipelineExecutor::with_config(ExecutionConfig { max_retries: 3, ..ExecutionConfig::default() });
+    executor.register_provider("expiring", provider.clone());
+
+    let err = executor.execute(&[PipelineStep::new("expiring", "step")], Context::new()).await.unwrap_err();
+    assert!(err.to_string().contains("401"));
+    assert_eq!(provider.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_prompt_prefix_prepended_to_each_step() {
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", create_mock_provider("claude"));
+    executor.set_prompt_prefix(Some("Use British spelling.".to_string()));
+
+    let responses = executor.execute(&[PipelineStep::new("claude", "write")], Context::new()).await.unwrap();
+    assert!(responses[0].content.ends_with("response to: Use British spelling.\n\nwrite"));
+}
+
+#[tokio::test]
+async fn test_provider_prompt_wraps_only_that_provider() {
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", create_mock_provider("claude"));
+    executor.register_provider("gemini", create_mock_provider("gemini"));
+    executor.set_prompt_prefix(Some("Team rules.".to_string()));
+    executor.set_provider_prompt("claude", PromptAffixes {
+        prefix: Some("Answer concisely, in Japanese.".to_string()),
+        suffix: Some("End with a summary.".to_string()),
+    });
+
+    let steps = [PipelineStep::new("claude", "design"), PipelineStep::new("gemini", "review")];
+    let responses = executor.execute(&steps, Context::new()).await.unwrap();
+    assert!(responses[0].content.ends_with("response to: Team rules.\n\nAnswer concisely, in Japanese.\n\ndesign\n\nEnd with a summary."));
+    assert!(responses[1].content.contains("response to: Team rules.\n\nreview"));
+    assert!(!responses[1].content.contains("Japanese"));
+}
+
+// Echoes the generation options it receives
+struct OptionsEchoProvider;
+
+#[async_trait]
+impl AIProvider for OptionsEchoProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Ok(Response::new("no options"))
+    }
+
+    async fn execute_with_options(&self, _prompt: &str, _context: &Context, options: &ProviderOptions) -> anyhow::Result<Response> {
+        Ok(Response::new(options.to_assignments()))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not streaming"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "claude"
+    }
+}
+
+#[tokio::test]
+async fn test_step_options_override_run_options() {
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(OptionsEchoProvider));
+    executor.set_options(ProviderOptions { temperature: Some(0.5), max_tokens: Some(100), ..ProviderOptions::default() });
+
+    let steps = vec![
+        PipelineStep::new("claude", "first"),
+        PipelineStep::new("claude", "second")
+            .with_options(ProviderOptions { max_tokens: Some(200), ..ProviderOptions::default() }),
+    ];
+    let results = executor.execute(&steps, Context::new()).await.unwrap();
+
+    assert!(results[0].content.ends_with("temperature=0.5,max_tokens=100"));
+    assert!(results[1].content.ends_with("temperature=0.5,max_tokens=200"));
+}
+
+#[tokio::test]
+async fn test_response_content_is_passed_through_unchanged() {
+    use ai_cli::providers::mock::MockProvider;
+
+    let provider = Arc::new(MockProvider::new("claude").with_reply("exact output"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", provider.clone());
+
+    let results = executor.execute(&[PipelineStep::new("claude", "analyze")], Context::new()).await.unwrap();
+    assert_eq!(results[0].content, "exact output");
+    assert_eq!(provider.prompts(), vec!["analyze"]);
+}
+
+#[tokio::test]
+async fn test_map_step_runs_sub_pipeline_per_element() {
+    use ai_cli::error::Error;
+    use ai_cli::pipeline::PipelineParser;
+    use ai_cli::providers::mock::MockProvider;
+
+    let lister = Arc::new(MockProvider::new("claude").with_reply("Files:\n```json\n[\"a.rs\", \"b.rs\", {\"path\": \"c.rs\"}]\n```"));
+    let reviewer = Arc::new(MockProvider::new("gemini"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", lister.clone());
+    executor.register_provider("gemini", reviewer.clone());
+
+    let steps = PipelineParser::parse(
+        "claude:list files -> map[jobs=2](gemini:review {{env.ITEM_INDEX}} {{env.ITEM}}) -> claude:combine",
+    ).unwrap();
+    let results = executor.execute(&steps, Context::new()).await.unwrap();
+
+    let mapped: serde_json::Value = serde_json::from_str(&results[1].content).unwrap();
+    assert_eq!(mapped, serde_json::json!(["review 0 a.rs", "review 1 b.rs", "review 2 {\"path\":\"c.rs\"}"]));
+    assert_eq!(results[1].metadata.get("map_items").map(String::as_str), Some("3"));
+    assert_eq!(reviewer.prompts().len(), 3);
+    assert_eq!(results[2].content, "combine");
+
+    // Output that is not a JSON array fails the map step with a typed error
+    let lister = Arc::new(MockProvider::new("claude").with_reply("no list here"));
+    executor.register_provider("claude", lister);
+    let err = executor.execute(&steps, Context::new()).await.unwrap_err();
+    match Error::classify(&err) {
+        Some(Error::Pipeline(failure)) => assert_eq!(failure.step_index, 1),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(err.to_string().contains("JSON array"));
+}
+
+#[tokio::test]
+async fn test_best_of_step_lets_judge_pick() {
+    use ai_cli::pipeline::{BestOfStep, JudgeMode};
+    use ai_cli::providers::mock::MockProvider;
+
+    let writer = Arc::new(MockProvider::new("gemini").with_reply("draft one").with_error("overloaded").with_reply("draft three"));
+    let judge = Arc::new(MockProvider::new("claude").with_reply("Answer 2 is clearly better."));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("gemini", writer.clone());
+    executor.register_provider("claude", judge.clone());
+
+    let step = PipelineStep::best_of(BestOfStep::new(vec![PipelineStep::new("gemini", "implement")], "claude").with_samples(3));
+    let results = executor.execute(&[step], Context::new()).await.unwrap();
+
+    // The failed sample is dropped, so the judge sees two answers and "2" is the third sample
+    assert_eq!(results[0].content, "draft three");
+    assert_eq!(results[0].metadata.get("best_of_winner").map(String::as_str), Some("3"));
+    let prompts = judge.prompts();
+    assert_eq!(prompts.len(), 1);
+    assert!(prompts[0].contains("### Answer 2\ndraft three"));
+    assert!(prompts[0].contains("## Task\nimplement"));
+
+    let merger = Arc::new(MockProvider::new("claude").with_reply("merged"));
+    executor.register_provider("claude", merger);
+    executor.register_provider("gemini", Arc::new(MockProvider::new("gemini").with_reply("a").with_reply("b")));
+    let step = PipelineStep::best_of(
+        BestOfStep::new(vec![PipelineStep::new("gemini", "implement")], "claude").with_samples(2).with_mode(JudgeMode::Merge),
+    );
+    let results = executor.execute(&[step], Context::new()).await.unwrap();
+    assert_eq!(results[0].content, "merged");
+}
+
+#[test]
+fn test_best_of_parse_choice() {
+    use ai_cli::pipeline::best_of::parse_choice;
+
+    assert_eq!(parse_choice("2", 3), Some(2));
+    assert_eq!(parse_choice("Answer 3.", 3), Some(3));
+    assert_eq!(parse_choice("Of the 5 answers, 1 wins", 3), Some(1));
+    assert_eq!(parse_choice("none", 3), None);
+}
+
+#[tokio::test]
+async fn test_step_falls_back_when_primary_fails() {
+    use ai_cli::pipeline::PipelineParser;
+    use ai_cli::providers::mock::MockProvider;
+
+    let primary = Arc::new(MockProvider::new("claude").with_error("overloaded"));
+    let fallback = Arc::new(MockProvider::new("gemini").with_reply("fallback summary"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", primary.clone());
+    executor.register_provider("gemini", fallback.clone());
+
+    let steps = PipelineParser::parse("claude|gemini:summarize -> claude:polish").unwrap();
+    let results = executor.execute(&steps, Context::new()).await.unwrap();
+
+    assert_eq!(results[0].content, "fallback summary");
+    assert_eq!(results[0].metadata.get("provider").map(String::as_str), Some("gemini"));
+    assert_eq!(results[0].metadata.get("fallback_from").map(String::as_str), Some("claude"));
+    assert_eq!(results[1].metadata.get("provider").map(String::as_str), Some("claude"));
+    assert!(!results[1].metadata.contains_key("fallback_from"));
+    assert_eq!(fallback.prompts(), vec!["summarize"]);
+
+    // The last provider's error is reported when every provider fails
+    let primary = Arc::new(MockProvider::new("claude").with_error("overloaded"));
+    let fallback = Arc::new(MockProvider::new("gemini").with_error("quota exceeded"));
+    executor.register_provider("claude", primary);
+    executor.register_provider("gemini", fallback);
+    let err = executor.execute(&steps[..1], Context::new()).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("quota exceeded"));
+}
+
+#[tokio::test]
+async fn test_step_with_tools_runs_tool_calls() {
+    use ai_cli::providers::mock::MockProvider;
+    use ai_cli::providers::{ToolCall, ToolSpec, Toolset};
+
+    let provider = Arc::new(MockProvider::new("claude")
+        .with_tool_call("run_tests", serde_json::json!({"filter": "parser"}))
+        .with_tool_call("run_tests", serde_json::json!({"filter": "missing"}))
+        .with_reply("Parser tests pass."));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", provider.clone());
+
+    let handler = |call: &ToolCall| -> anyhow::Result<String> {
+        match call.input["filter"].as_str() {
+            Some("parser") => Ok("12 passed".to_string()),
+            other => Err(anyhow!("no tests match {:?}", other)),
+        }
+    };
+    let tools = Toolset::new(
+        vec![ToolSpec::new("run_tests", "Run cargo test", serde_json::json!({"type": "object"}))],
+        Arc::new(handler),
+    );
+    let step = PipelineStep::new("claude", "check the parser").with_tools(tools);
+    assert_eq!(step.tools().unwrap().names(), vec!["run_tests"]);
+
+    let results = executor.execute_streaming(&[step], Context::new()).await.unwrap();
+    assert_eq!(results[0].content, "Parser tests pass.");
+    assert_eq!(results[0].metadata.get("tool_calls").map(String::as_str), Some("2"));
+    assert!(!results[0].metadata.contains_key("streamed"));
+    let tool_results = provider.tool_results();
+    assert_eq!(tool_results[0], Ok("12 passed".to_string()));
+    assert!(tool_results[1].as_ref().unwrap_err().contains("no tests match"));
+}
+
+/// Provider that records how many of its calls overlap
+struct SlowProvider {
+    in_flight: std::sync::atomic::AtomicUsize,
+    peak: std::sync::atomic::AtomicUsize,
+}
+
+#[async_trait]
+impl AIProvider for SlowProvider {
+    async fn execute(&self, prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        use std::sync::atomic::Ordering;
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(Response::new(prompt.to_string()))
+    }
+
+    async fn stream(&self, prompt: &str, context: &Context) -> anyhow::Result<ResponseStream> {
+        let response = self.execute(prompt, context).await?.content;
+        Ok(Box::pin(stream::once(async move { Ok(response) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "slow"
+    }
+}
+
+#[tokio::test]
+async fn test_builder_configures_executor() {
+    use ai_cli::pipeline::events;
+    use ai_cli::providers::probe::CapabilityCache;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = CapabilityCache::load(dir.path().join("capabilities.json"));
+    cache.insert("claude/default", Capabilities { max_tokens: 1234, ..Capabilities::default() });
+    let (sink, mut events) = events::channel();
+
+    let executor = PipelineExecutor::builder()
+        .provider("claude", create_mock_provider("claude"))
+        .provider("error", create_error_provider())
+        .config(ExecutionConfig { continue_on_error: true, ..ExecutionConfig::default() })
+        .auth(AuthManager::new())
+        .cache(cache)
+        .prompt_prefix(Some("Be brief.".to_string()))
+        .event_sink(sink)
+        .build();
+    assert_eq!(executor.capabilities("claude").unwrap().max_tokens, 1234);
+    assert_eq!(executor.capabilities("error"), Some(Capabilities::default()));
+
+    let steps = vec![PipelineStep::new("error", "fail"), PipelineStep::new("claude", "answer")];
+    let results = executor.execute(&steps, Context::new()).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results[1].content.starts_with("Mock claude response to: Be brief."), "{}", results[1].content);
+    assert!(events.try_recv().is_ok());
+}
+
+#[tokio::test]
+async fn test_builder_rate_limit_caps_concurrent_calls() {
+    use ai_cli::scheduler::RateLimit;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let provider = Arc::new(SlowProvider { in_flight: AtomicUsize::new(0), peak: AtomicUsize::new(0) });
+    let executor = Arc::new(
+        PipelineExecutor::builder()
+            .provider("slow", provider.clone())
+            .rate_limit("slow", RateLimit { requests_per_minute: None, max_concurrent: Some(1) })
+            .build(),
+    );
+    let runs = (0..3).map(|i| {
+        let executor = executor.clone();
+        tokio::spawn(async move { executor.execute(&[PipelineStep::new("slow", format!("run {}", i))], Context::new()).await })
+    });
+    for run in futures::future::join_all(runs).await {
+        run.unwrap().unwrap();
+    }
+    assert_eq!(provider.peak.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_shared_executor_registers_while_running() {
+    use std::sync::atomic::AtomicUsize;
+
+    let executor = Arc::new(PipelineExecutor::new());
+    executor.register_provider("slow", Arc::new(SlowProvider { in_flight: AtomicUsize::new(0), peak: AtomicUsize::new(0) }));
+    let running = {
+        let executor = executor.clone();
+        tokio::spawn(async move { executor.execute(&[PipelineStep::new("slow", "first")], Context::new()).await })
+    };
+    // Let the first pipeline reach its provider call
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+    // Registration and settings go through the shared reference while the first pipeline runs
+    executor.register_provider("claude", create_mock_provider("claude"));
+    executor.set_max_retries(2);
+    assert_eq!(executor.get_config().max_retries, 2);
+    let results = executor.execute(&[PipelineStep::new("claude", "second")], Context::new()).await.unwrap();
+    assert_eq!(results[0].content, "Mock claude response to: second");
+    // Removing a provider does not disturb the step already running on it
+    assert!(executor.unregister_provider("slow").is_some());
+
+    assert_eq!(running.await.unwrap().unwrap()[0].content, "first");
+    assert!(!executor.has_provider("slow"));
+    assert!(executor.execute(&[PipelineStep::new("slow", "third")], Context::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_step_timeout_and_retries() {
+    use ai_cli::pipeline::PipelineBuilder;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    let slow = Arc::new(SlowProvider { in_flight: AtomicUsize::new(0), peak: AtomicUsize::new(0) });
+    let executor = PipelineExecutor::with_config(ExecutionConfig { retry_delay_ms: 0, ..ExecutionConfig::default() });
+    executor.register_provider("slow", slow);
+    executor.register_provider("claude", create_mock_provider("claude"));
+
+    let steps = PipelineBuilder::new().step("slow", "think").timeout(Duration::from_millis(5)).retries(1).build();
+    let err = executor.execute(&steps, Context::new()).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("no reply within 0.005 s"), "{:#}", err);
+    let failure = err.downcast_ref::<ai_cli::pipeline::PipelineFailure>().unwrap();
+    assert!(failure.logs.contains(&"retries: 1".to_string()), "{:?}", failure.logs);
+
+    // The step's own fallback answers once its retries are spent
+    let steps = PipelineBuilder::new().step("slow", "think").timeout(Duration::from_millis(5)).fallback("claude").build();
+    let results = executor.execute(&steps, Context::new()).await.unwrap();
+    assert_eq!(results[0].content, "Mock claude response to: think");
+}
+
+#[tokio::test]
+async fn test_step_model_reaches_provider() {
+    use ai_cli::providers::claude::ClaudeProvider;
+    use ai_cli::providers::testing::FakeTransport;
+
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({
+        "content": [{"type": "text", "text": "ok"}],
+        "usage": {"input_tokens": 1, "output_tokens": 1}
+    })));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("key".to_string()).with_transport(transport.clone())));
+
+    let results = executor.execute(&[PipelineStep::new("claude", "hi").with_model("claude-3-5-haiku-20241022")], Context::new()).await.unwrap();
+    assert_eq!(transport.requests()[0].json().unwrap()["model"], "claude-3-5-haiku-20241022");
+    assert_eq!(results[0].metadata.get("model").map(String::as_str), Some("claude-3-5-haiku-20241022"));
+}
+
+/// Provider that lists one small model
+struct ListingProvider;
+
+#[async_trait]
+impl AIProvider for ListingProvider {
+    async fn execute(&self, prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Ok(Response::new(prompt.to_string()))
+    }
+
+    async fn stream(&self, prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        let response = prompt.to_string();
+        Ok(Box::pin(stream::once(async move { Ok(response) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "claude"
+    }
+
+    async fn list_models(&self) -> anyhow::Result<Vec<ai_cli::providers::ModelInfo>> {
+        Ok(vec![ai_cli::providers::ModelInfo {
+            id: "small-1".to_string(),
+            display_name: None,
+            context_window: Some(1000),
+            max_output_tokens: Some(100),
+        }])
+    }
+}
+
+#[tokio::test]
+async fn test_validate_reports_every_problem() {
+    use ai_cli::pipeline::{JsonExtractorTransform, MapStep, ProblemKind, Transform};
+
+    struct Unregistered;
+    #[async_trait]
+    impl Transform for Unregistered {
+        async fn transform(&self, response: Response) -> anyhow::Result<Response> {
+            Ok(response)
+        }
+        fn name(&self) -> &str {
+            "unregistered"
+        }
+    }
+
+    let mut auth = AuthManager::new();
+    auth.set_api_key("claude", "key");
+    let executor = PipelineExecutor::builder()
+        .provider("claude", Arc::new(ListingProvider))
+        .provider("local", create_mock_provider("local"))
+        .auth(auth)
+        .build();
+
+    let fine = vec![
+        PipelineStep::new("claude", "summarize").with_model("small-1").with_transform(Arc::new(JsonExtractorTransform::new("summary"))),
+    ];
+    assert!(executor.validate(&fine).await.is_ok());
+
+    let steps = vec![
+        PipelineStep::new("claude", "summarize").with_model("small-2"),
+        PipelineStep::new("cladue", "review"),
+        PipelineStep::new("local", "review"),
+        PipelineStep::new("claude", "expand")
+            .with_options(ProviderOptions { max_tokens: Some(500), ..ProviderOptions::default() })
+            .with_model("small-1"),
+        PipelineStep::map(MapStep::new(vec![PipelineStep::new("claude", "word ".repeat(5000))])),
+        PipelineStep::new("claude", "shape").with_transform(Arc::new(Unregistered)),
+    ];
+    let report = executor.validate(&steps).await;
+    let found: Vec<(&str, ProblemKind)> = report.problems.iter().map(|p| (p.step.as_str(), p.kind)).collect();
+    assert_eq!(found, [
+        ("1", ProblemKind::UnknownModel),
+        ("2", ProblemKind::UnknownProvider),
+        ("3", ProblemKind::MissingAuth),
+        ("4", ProblemKind::TokenBudget),
+        ("5.1", ProblemKind::TokenBudget),
+        ("6", ProblemKind::UnknownTransform),
+    ]);
+    let message = report.to_string();
+    assert!(message.starts_with("Pipeline has 6 problems:"), "{}", message);
+    assert!(message.contains("step 1: claude: Unknown model 'small-2'. Did you mean: small-1"), "{}", message);
+    assert!(message.contains("step 2: unknown provider 'cladue'. Did you mean 'claude'?"), "{}", message);
+    assert!(message.contains("max_tokens 500 exceeds the 100-token output limit of small-1"), "{}", message);
+    assert!(report.into_result().is_err());
+}
+
+/// Provider that replies with the conversation it was sent
+struct HistoryEchoProvider;
+
+#[async_trait]
+impl AIProvider for HistoryEchoProvider {
+    async fn execute(&self, _prompt: &str, context: &Context) -> anyhow::Result<Response> {
+        let history: Vec<&str> = context.conversation_history.iter().map(|m| m.content.as_str()).collect();
+        Ok(Response::new(history.join(",")))
+    }
+
+    async fn stream(&self, prompt: &str, context: &Context) -> anyhow::Result<ResponseStream> {
+        let response = self.execute(prompt, context).await?.content;
+        Ok(Box::pin(stream::once(async move { Ok(response) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "echo"
+    }
+}
+
+#[tokio::test]
+async fn test_context_policy_per_executor_provider_and_step() {
+    use ai_cli::context::ContextPolicy;
+
+    let executor = PipelineExecutor::builder()
+        .provider("claude", Arc::new(HistoryEchoProvider))
+        .provider("gemini", Arc::new(HistoryEchoProvider))
+        .context_policy(ContextPolicy::Last(1))
+        .provider_context_policy("gemini", ContextPolicy::SummaryOnly)
+        .build();
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "rules"));
+    context.add_message(Message::new(MessageRole::User, "first"));
+    context.add_message(Message::new(MessageRole::Assistant, "second"));
+
+    let steps = vec![
+        PipelineStep::new("claude", "a"),
+        PipelineStep::new("gemini", "b"),
+        PipelineStep::new("gemini", "c").with_context_policy(ContextPolicy::None),
+    ];
+    assert_eq!(executor.context_policy_for(&steps[2]), ContextPolicy::None);
+    let results = executor.execute(&steps, context).await.unwrap();
+    assert!(results[0].content.starts_with("rules,"), "{}", results[0].content);
+    assert_eq!(results[0].content.split(',').count(), 2);
+    assert_eq!(results[1].content, "rules");
+    assert_eq!(results[2].content, "");
+}
+
+#[tokio::test]
+async fn test_context_filter_runs_for_its_provider() {
+    use ai_cli::context::{ContextFilters, ContextPolicy, PolicyFilter};
+
+    let executor = PipelineExecutor::builder()
+        .provider("claude", Arc::new(HistoryEchoProvider))
+        .provider("gemini", Arc::new(HistoryEchoProvider))
+        .context_filters(ContextFilters::builtin())
+        .context_filter("claude", |context: &mut Context| {
+            context.conversation_history.retain(|m| m.role != MessageRole::System)
+        })
+        .context_filter("claude", PolicyFilter(ContextPolicy::Last(1)))
+        .build();
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "rules"));
+    for i in 0..12 {
+        context.add_message(Message::new(MessageRole::User, format!("m{}", i)));
+    }
+
+    let results = executor.execute(&[PipelineStep::new("claude", "a"), PipelineStep::new("gemini", "b")], context).await.unwrap();
+    assert_eq!(results[0].content, "m11");
+    // The built-in gemini filter keeps system messages and the last 10 others
+    assert_eq!(results[1].content.split(',').count(), 11);
+    assert!(results[1].content.starts_with("rules,"), "{}", results[1].content);
+}
+
+#[tokio::test]
+async fn test_compactor_runs_before_each_step() {
+    use ai_cli::context::Compactor;
+    use ai_cli::providers::mock::MockProvider;
+
+    let summarizer = Arc::new(MockProvider::new("claude").with_reply("short summary"));
+    let executor = PipelineExecutor::builder()
+        .provider("gemini", Arc::new(HistoryEchoProvider))
+        .compactor(Arc::new(Compactor::new(summarizer.clone()).with_max_tokens(20).with_keep_recent(2)))
+        .build();
+    let mut context = Context::new();
+    for i in 0..6 {
+        context.add_message(Message::new(MessageRole::User, format!("a fairly long question number {}", i)));
+        context.add_message(Message::new(MessageRole::Assistant, format!("a fairly long answer number {}", i)));
+    }
+
+    let results = executor.execute(&[PipelineStep::new("gemini", "next")], context).await.unwrap();
+    assert_eq!(summarizer.prompts().len(), 1);
+    assert_eq!(
+        results[0].content,
+        "Earlier in this conversation (summarized):\nshort summary,a fairly long question number 5,a fairly long answer number 5"
+    );
+}
+
+#[tokio::test]
+async fn test_oversized_prompt_fails_before_the_provider_call() {
+    use ai_cli::error::{Error, ExitCode};
+    use ai_cli::pipeline::FailureKind;
+    use ai_cli::providers::mock::MockProvider;
+
+    let capabilities = Capabilities { max_tokens: 50, ..Capabilities::default() };
+    let provider = Arc::new(MockProvider::new("claude").with_reply("unused").with_capabilities(capabilities));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", provider.clone());
+
+    let mut context = Context::new();
+    context.add_file_with_content("big.rs".into(), "fn main() {}\n".repeat(100));
+    let err = executor.execute(&[PipelineStep::new("claude", "review")], context.clone()).await.unwrap_err();
+
+    assert!(provider.prompts().is_empty());
+    let message = err.to_string();
+    assert!(message.contains("prompt exceeds claude context by"), "{}", message);
+    assert!(message.contains("bigger context window"), "{}", message);
+    match Error::classify(&err) {
+        Some(Error::Pipeline(failure)) => assert_eq!(failure.kind, FailureKind::ContextTooLarge),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(ExitCode::for_error(&err), ExitCode::Timeout);
+
+    // The same file fits once the window is large enough
+    let roomy = Arc::new(MockProvider::new("claude").with_reply("ok"));
+    executor.register_provider("claude", roomy.clone());
+    let results = executor.execute(&[PipelineStep::new("claude", "review")], context.clone()).await.unwrap();
+    assert_eq!(results[0].content, "ok");
+}
@@ -123,4 +123,193 @@ fn test_pipeline_validate_all_known_providers() {
     
     let validation = PipelineParser::validate_providers(&steps, &["claude", "gemini", "codex"]);
     assert!(validation.is_ok());
-}
\ No newline at end of file
+}
+#[test]
+fn test_parse_step_options() {
+    let steps = PipelineParser::parse(
+        "claude[temperature=0.2, max_tokens=4000, stop=END|DONE]:design -> gemini[system=Reply in JSON: {}]:implement",
+    ).unwrap();
+
+    assert_eq!(steps[0].provider, "claude");
+    assert_eq!(steps[0].action, "design");
+    assert_eq!(steps[0].options().temperature, Some(0.2));
+    assert_eq!(steps[0].options().max_tokens, Some(4000));
+    assert_eq!(steps[0].options().stop, vec!["END", "DONE"]);
+
+    // A ':' inside the options block does not split provider and action
+    assert_eq!(steps[1].provider, "gemini");
+    assert_eq!(steps[1].action, "implement");
+    assert_eq!(steps[1].options().system.as_deref(), Some("Reply in JSON: {}"));
+
+    let formatted = PipelineParser::format(&steps);
+    assert_eq!(PipelineParser::parse(&formatted).unwrap(), steps);
+}
+
+#[test]
+fn test_parse_invalid_step_options() {
+    assert!(PipelineParser::parse("claude[temperature=hot]:design").is_err());
+    assert!(PipelineParser::parse("claude[temperature=3]:design").is_err());
+    assert!(PipelineParser::parse("claude[seed=1]:design").is_err());
+    assert!(PipelineParser::parse("claude[temperature=0.2:design").is_err());
+}
+
+#[test]
+fn test_parse_map_step() {
+    let steps = PipelineParser::parse(
+        "claude:list files as JSON -> map[jobs=4](gemini:review {{env.ITEM}} -> claude:summarize) -> claude:combine",
+    ).unwrap();
+
+    assert_eq!(steps.len(), 3);
+    let map = steps[1].map_step().unwrap();
+    assert_eq!(map.jobs, 4);
+    assert_eq!(map.steps.len(), 2);
+    assert_eq!(map.steps[0].provider, "gemini");
+    assert_eq!(map.steps[0].action, "review {{env.ITEM}}");
+    assert_eq!(steps[2].action, "combine");
+
+    let formatted = PipelineParser::format(&steps);
+    assert_eq!(formatted, "claude:list files as JSON -> map[jobs=4](gemini:review {{env.ITEM}} -> claude:summarize) -> claude:combine");
+    assert_eq!(PipelineParser::parse(&formatted).unwrap(), steps);
+
+    // A plain step for a provider whose action mentions map is not a map step
+    let plain = PipelineParser::parse("claude:map the module (briefly)").unwrap();
+    assert!(plain[0].map_step().is_none());
+
+    assert!(PipelineParser::validate_providers(&steps, &["claude", "gemini"]).is_ok());
+    assert!(PipelineParser::validate_providers(&steps, &["claude"]).is_err());
+}
+
+#[test]
+fn test_parse_invalid_map_step() {
+    assert!(PipelineParser::parse("claude:list -> map(gemini:review").is_err());
+    assert!(PipelineParser::parse("claude:list -> map()").is_err());
+    assert!(PipelineParser::parse("claude:list -> map[jobs=0](gemini:review)").is_err());
+    assert!(PipelineParser::parse("claude:list -> map[retries=2](gemini:review)").is_err());
+    assert!(PipelineParser::parse("claude:list -> map(gemini:review) extra").is_err());
+}
+
+#[test]
+fn test_parse_best_of_step() {
+    use ai_cli::pipeline::JudgeMode;
+
+    let steps = PipelineParser::parse(
+        "claude:design -> bestof(3, judge=claude){ gemini:implement ; codex[temperature=0.9]:implement } -> codex:review",
+    ).unwrap();
+
+    assert_eq!(steps.len(), 3);
+    let best_of = steps[1].best_of_step().unwrap();
+    assert_eq!(best_of.n, 3);
+    assert_eq!(best_of.judge, "claude");
+    assert_eq!(best_of.mode, JudgeMode::Pick);
+    assert_eq!(best_of.candidates.len(), 2);
+    assert_eq!(best_of.candidates[1].options().temperature, Some(0.9));
+
+    let formatted = PipelineParser::format(&steps);
+    assert_eq!(PipelineParser::parse(&formatted).unwrap(), steps);
+
+    let merged = PipelineParser::parse("bestof(judge=gemini, mode=merge){ claude:summarize }").unwrap();
+    let best_of = merged[0].best_of_step().unwrap();
+    assert_eq!(best_of.n, 1);
+    assert_eq!(best_of.mode, JudgeMode::Merge);
+
+    assert!(PipelineParser::validate_providers(&steps, &["claude", "gemini", "codex"]).is_ok());
+    assert!(PipelineParser::validate_providers(&merged, &["claude"]).is_err());
+}
+
+#[test]
+fn test_parse_invalid_best_of_step() {
+    assert!(PipelineParser::parse("bestof(3){ gemini:implement }").is_err());
+    assert!(PipelineParser::parse("bestof(0, judge=claude){ gemini:implement }").is_err());
+    assert!(PipelineParser::parse("bestof(3, judge=claude, mode=vote){ gemini:implement }").is_err());
+    assert!(PipelineParser::parse("bestof(3, judge=claude){ gemini:implement").is_err());
+    assert!(PipelineParser::parse("bestof(3, judge=claude) gemini:implement").is_err());
+    assert!(PipelineParser::parse("bestof(3, judge=claude){ map(gemini:implement) }").is_err());
+}
+
+#[test]
+fn test_parse_fallback_providers() {
+    let steps = PipelineParser::parse("claude|gemini|codex[temperature=0.2]:summarize -> gemini:review").unwrap();
+    assert_eq!(steps[0].provider, "claude");
+    assert_eq!(steps[0].fallbacks(), ["gemini", "codex"]);
+    assert_eq!(steps[0].options().temperature, Some(0.2));
+    assert!(steps[1].fallbacks().is_empty());
+
+    let formatted = PipelineParser::format(&steps);
+    assert!(formatted.starts_with("claude|gemini|codex[temperature=0.2]:summarize"));
+    assert_eq!(PipelineParser::parse(&formatted).unwrap(), steps);
+
+    assert!(PipelineParser::parse("claude|:summarize").is_err());
+    assert!(PipelineParser::validate_providers(&steps, &["claude", "gemini", "codex"]).is_ok());
+    assert!(PipelineParser::validate_providers(&steps, &["claude", "gemini"]).is_err());
+}
+
+#[test]
+fn test_quoted_actions() {
+    let steps = PipelineParser::parse(r#"claude:"compare A -> B" -> gemini:summarize"#).unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0].action, "compare A -> B");
+    assert_eq!(steps[1].action, "summarize");
+
+    let steps = PipelineParser::parse(r#"claude: "say \"hi\" \\ bye" -> codex:x"#).unwrap();
+    assert_eq!(steps[0].action, r#"say "hi" \ bye"#);
+
+    let steps = PipelineParser::parse(r#"map(claude:"fix ) and ; here") -> bestof(2, judge=claude){ gemini:"a;b" ; codex:c }"#).unwrap();
+    assert_eq!(steps[0].map_step().unwrap().steps[0].action, "fix ) and ; here");
+    assert_eq!(steps[1].best_of_step().unwrap().candidates[0].action, "a;b");
+    assert_eq!(steps[1].best_of_step().unwrap().candidates.len(), 2);
+}
+
+#[test]
+fn test_colons_and_plain_quotes_in_actions() {
+    let steps = PipelineParser::parse("claude:read https://example.com/a:b -> gemini:explain \"foo\" twice").unwrap();
+    assert_eq!(steps[0].action, "read https://example.com/a:b");
+    assert_eq!(steps[1].action, "explain \"foo\" twice");
+    let steps = PipelineParser::parse("claude[stop=END]:note: keep it short").unwrap();
+    assert_eq!(steps[0].action, "note: keep it short");
+}
+
+#[test]
+fn test_quoted_action_errors_and_round_trip() {
+    let err = PipelineParser::parse(r#"claude:"compare A -> B"#).unwrap_err();
+    assert!(err.to_string().starts_with("Unclosed quote in action"), "{}", err);
+    assert!(PipelineParser::parse(r#"claude:"a" b"#).unwrap_err().to_string().starts_with("Unexpected text after quoted action"));
+    assert!(PipelineParser::parse(r#"claude:"""#).is_err());
+
+    let steps = vec![
+        PipelineStep::new("claude", "compare A -> B"),
+        PipelineStep::new("gemini", r#""quoted" \ text"#),
+        PipelineStep::new("codex", "plain: text"),
+    ];
+    let chain = PipelineParser::format(&steps);
+    assert_eq!(chain, r#"claude:"compare A -> B" -> gemini:"\"quoted\" \\ text" -> codex:plain: text"#);
+    let parsed = PipelineParser::parse(&chain).unwrap();
+    assert_eq!(parsed.iter().map(|s| s.action.as_str()).collect::<Vec<_>>(), ["compare A -> B", r#""quoted" \ text"#, "plain: text"]);
+}
+
+#[test]
+fn test_parse_lines() {
+    let text = "# Design, build and review\nclaude:設計\n\n  -> codex:実装 ->\n# gemini:skipped\ngemini:review -> claude:\"summarize A -> B\"\n";
+    let steps = PipelineParser::parse_lines(text).unwrap();
+    let actions: Vec<&str> = steps.iter().map(|s| s.action.as_str()).collect();
+    assert_eq!(actions, ["設計", "実装", "review", "summarize A -> B"]);
+    assert!(PipelineParser::parse_lines("# nothing\n\n").is_err());
+}
+
+#[test]
+fn test_chain_file_errors_point_at_the_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pipeline.txt");
+    std::fs::write(&path, "claude:design\ncodex:\n").unwrap();
+    let err = PipelineParser::parse_file(&path).unwrap_err();
+    let diagnostic = err.downcast_ref::<ai_cli::diagnostics::Diagnostic>().unwrap();
+    assert_eq!(diagnostic.span, 20..20);
+    assert!(err.to_string().contains(&format!("--> {}:2:7\n  |\n2 | codex:", path.display())), "{}", err);
+    assert!(PipelineParser::parse_file(&dir.path().join("missing.txt")).is_err());
+}
+
+#[test]
+fn test_parse_model_option() {
+    let steps = PipelineParser::parse("claude[model=claude-3-5-haiku-20241022,temperature=0.2]:summarize").unwrap();
+    assert_eq!(steps[0].model(), Some("claude-3-5-haiku-20241022"));
+    assert_eq!(PipelineParser::format(&steps), "claude[model=claude-3-5-haiku-20241022,temperature=0.2]:summarize");
+}
@@ -0,0 +1,272 @@
+use ai_cli::pipeline::{
+    BuiltinTool, JsonExtractorTransform, PipelineDefinition, PipelineParser, PipelineStep, PipelineStore, PipelineWizard,
+    StepDefinition, ToolRegistry, Transform, transform,
+};
+use ai_cli::providers::Response;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn wizard_output(answers: &str) -> (anyhow::Result<Option<PipelineDefinition>>, String) {
+    let mut output = Vec::new();
+    let result = PipelineWizard::new(Cursor::new(answers.to_string()), &mut output).run();
+    (result, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_definition_chain_round_trip() {
+    let definition = PipelineDefinition::from_chain("review", "claude:analyze -> codex:review").unwrap();
+
+    assert_eq!(definition.steps.len(), 2);
+    assert_eq!(definition.to_chain(), "claude:analyze -> codex:review");
+    assert!(definition.validate().is_ok());
+}
+
+#[test]
+fn test_definition_yaml_round_trip() {
+    let mut definition = PipelineDefinition::new("design");
+    let mut step = StepDefinition::new("claude", "design");
+    step.transform = Some("summarizer:200".to_string());
+    definition.steps.push(step);
+
+    let yaml = definition.to_yaml().unwrap();
+    let parsed = PipelineDefinition::from_yaml(&yaml).unwrap();
+    assert_eq!(parsed, definition);
+
+    let steps = parsed.to_steps().unwrap();
+    assert!(steps[0].has_transform());
+}
+
+#[test]
+fn test_definition_map_step_round_trip() {
+    let chain = "claude:list files -> map[jobs=3](gemini:review {{env.ITEM}} -> claude:summarize) -> claude:combine";
+    let definition = PipelineDefinition::from_chain("fanout", chain).unwrap();
+    assert_eq!(definition.to_chain(), chain);
+    assert!(definition.validate().is_ok());
+
+    let yaml = definition.to_yaml().unwrap();
+    assert!(yaml.contains("jobs: 3"));
+    let parsed = PipelineDefinition::from_yaml(&yaml).unwrap();
+    assert_eq!(parsed, definition);
+
+    let steps = parsed.to_steps().unwrap();
+    let map = steps[1].map_step().unwrap();
+    assert_eq!(map.jobs, 3);
+    assert_eq!(map.steps[1].action, "summarize");
+
+    let mut invalid = PipelineDefinition::new("fanout");
+    invalid.steps.push(StepDefinition::map(vec![StepDefinition::new("unknown", "review")], None));
+    assert!(invalid.validate().unwrap_err().to_string().contains("Step 1.1"));
+}
+
+#[test]
+fn test_definition_best_of_step_round_trip() {
+    let chain = "bestof(3, judge=claude, mode=merge){ gemini:implement ; codex:implement } -> claude:review";
+    let definition = PipelineDefinition::from_chain("sampled", chain).unwrap();
+    assert_eq!(definition.to_chain(), chain);
+    assert!(definition.validate().is_ok());
+
+    let parsed = PipelineDefinition::from_yaml(&definition.to_yaml().unwrap()).unwrap();
+    assert_eq!(parsed, definition);
+    let steps = parsed.to_steps().unwrap();
+    assert_eq!(steps[0].best_of_step().unwrap().n, 3);
+
+    let mut invalid = PipelineDefinition::new("sampled");
+    invalid.steps.push(StepDefinition::best_of(vec![StepDefinition::new("gemini", "implement")], "nobody", None, None));
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn test_definition_step_env_from_yaml() {
+    let yaml = "name: port\nsteps:\n  - provider: claude\n    action: port to {{env.LANG}}\n    env:\n      LANG: Go\n";
+    let definition = PipelineDefinition::from_yaml(yaml).unwrap();
+    assert!(definition.validate().is_ok());
+
+    let steps = definition.to_steps().unwrap();
+    assert_eq!(steps[0].env().get("LANG").map(String::as_str), Some("Go"));
+}
+
+#[test]
+fn test_definition_validation_errors() {
+    let mut definition = PipelineDefinition::new("bad name");
+    definition.steps.push(StepDefinition::new("claude", "design"));
+    assert!(definition.validate().is_err());
+
+    let mut definition = PipelineDefinition::new("empty");
+    assert!(definition.validate().is_err());
+
+    definition.steps.push(StepDefinition::new("unknown", "design"));
+    assert!(definition.validate().is_err());
+
+    let mut definition = PipelineDefinition::new("transform");
+    let mut step = StepDefinition::new("claude", "design");
+    step.transform = Some("bogus".to_string());
+    definition.steps.push(step);
+    assert!(definition.validate().is_err());
+}
+
+#[test]
+fn test_store_save_load_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = PipelineStore::new(dir.path());
+
+    assert!(store.list().unwrap().is_empty());
+
+    let definition = PipelineDefinition::from_chain("review", "claude:analyze -> codex:review").unwrap();
+    let path = store.save(&definition).unwrap();
+    assert!(path.ends_with("review.yaml"));
+    assert!(store.exists("review"));
+
+    let loaded = store.load("review").unwrap();
+    assert_eq!(loaded, definition);
+    assert_eq!(store.list().unwrap(), vec!["review".to_string()]);
+}
+
+#[test]
+fn test_store_load_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = PipelineStore::new(dir.path());
+
+    assert!(store.load("missing").is_err());
+    assert!(store.load("../escape").is_err());
+}
+
+#[test]
+fn test_wizard_builds_pipeline() {
+    let answers = "review\n\
+                   Code review flow\n\
+                   claude\n\
+                   analyze\n\
+                   \n\
+                   summarizer:500\n\
+                   y\n\
+                   codex\n\
+                   review\n\
+                   focus on security\n\
+                   \n\
+                   n\n\
+                   y\n";
+    let (result, output) = wizard_output(answers);
+
+    let definition = result.unwrap().expect("pipeline should be saved");
+    assert_eq!(definition.name, "review");
+    assert_eq!(definition.description.as_deref(), Some("Code review flow"));
+    assert_eq!(definition.to_chain(), "claude:analyze -> codex:review");
+    assert_eq!(definition.steps[0].transform.as_deref(), Some("summarizer:500"));
+    assert_eq!(definition.steps[1].context.as_deref(), Some("focus on security"));
+    assert!(output.contains("claude:analyze -> codex:review"));
+    assert!(output.contains("YAML:"));
+}
+
+#[test]
+fn test_wizard_reprompts_invalid_answers() {
+    // Invalid name, unknown provider and bad transform are asked again
+    let answers = "bad name\nok\n\ngpt\n\ndesign\n\nnope\n\nn\ny\n";
+    let (result, output) = wizard_output(answers);
+
+    let definition = result.unwrap().unwrap();
+    assert_eq!(definition.name, "ok");
+    // Empty provider answer falls back to the default provider
+    assert_eq!(definition.steps[0].provider, "claude");
+    assert!(output.contains("unknown provider 'gpt'"));
+    assert!(output.contains("Unknown transform spec"));
+}
+
+#[test]
+fn test_wizard_discard_and_eof() {
+    let (result, _) = wizard_output("tmp\n\ngemini\nsummarize\n\n\nn\nn\n");
+    assert!(result.unwrap().is_none());
+
+    let (result, _) = wizard_output("tmp\n");
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_definition_step_options_from_yaml() {
+    let yaml = "name: draft\nsteps:\n  - provider: claude\n    action: draft\n    options:\n      temperature: 0.9\n      max_tokens: 2000\n";
+    let def = PipelineDefinition::from_yaml(yaml).unwrap();
+    def.validate().unwrap();
+    let steps = def.to_steps().unwrap();
+    assert_eq!(steps[0].options().temperature, Some(0.9));
+    assert_eq!(steps[0].options().max_tokens, Some(2000));
+
+    let invalid = "name: draft\nsteps:\n  - provider: claude\n    action: draft\n    options:\n      top_p: 1.5\n";
+    let err = PipelineDefinition::from_yaml(invalid).unwrap().validate().unwrap_err();
+    assert!(err.to_string().contains("Step 1: top_p"));
+}
+
+#[test]
+fn test_definition_system_prompt_applies_to_steps() {
+    let yaml = "name: review\nsystem: You are a careful reviewer.\nsteps:\n  - provider: claude\n    action: analyze\n  - provider: gemini\n    action: summarize\n    options:\n      system: Summarize in one line.\n";
+    let steps = PipelineDefinition::from_yaml(yaml).unwrap().to_steps().unwrap();
+    assert_eq!(steps[0].options().system.as_deref(), Some("You are a careful reviewer."));
+    assert_eq!(steps[1].options().system.as_deref(), Some("Summarize in one line."));
+}
+
+#[test]
+fn test_definition_fallbacks_round_trip() {
+    let def = PipelineDefinition::from_chain("summary", "claude|gemini:summarize -> codex:review").unwrap();
+    assert_eq!(def.steps[0].fallbacks, vec!["gemini"]);
+    assert_eq!(def.to_chain(), "claude|gemini:summarize -> codex:review");
+    def.validate().unwrap();
+
+    let yaml = def.to_yaml().unwrap();
+    assert!(yaml.contains("fallbacks:"));
+    let steps = PipelineDefinition::from_yaml(&yaml).unwrap().to_steps().unwrap();
+    assert_eq!(steps[0].fallbacks(), ["gemini"]);
+
+    let mut invalid = def.clone();
+    invalid.steps[0].fallbacks.push("gpt".to_string());
+    assert!(invalid.validate().unwrap_err().to_string().contains("unknown provider 'gpt'"));
+}
+
+#[test]
+fn test_pipeline_step_serde_round_trip() {
+    let step = PipelineStep::new("claude", "summarize")
+        .with_context("notes")
+        .with_transform(Arc::new(JsonExtractorTransform::new("summary")))
+        .with_env("LANG", "ja")
+        .with_model("claude-3-5-haiku-latest")
+        .with_fallbacks(["gemini"])
+        .with_timeout(Duration::from_millis(1500))
+        .with_retries(2);
+    let steps = vec![step, PipelineParser::parse("map[jobs=2](codex:review)").unwrap().remove(0)];
+
+    let json = serde_json::to_string(&steps).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value[0]["transform"], "json_extractor:summary");
+    assert_eq!(value[0]["options"]["model"], "claude-3-5-haiku-latest");
+    assert_eq!(value[0]["timeout_ms"], 1500);
+
+    let parsed: Vec<PipelineStep> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, steps);
+    assert_eq!(parsed[0].get_transform().unwrap().spec(), "json_extractor:summary");
+    assert_eq!(parsed[1].map_step().unwrap().jobs, 2);
+}
+
+#[test]
+fn test_pipeline_step_serde_uses_transform_registry() {
+    struct Shout;
+    #[async_trait::async_trait]
+    impl Transform for Shout {
+        async fn transform(&self, mut response: Response) -> anyhow::Result<Response> {
+            response.content = response.content.to_uppercase();
+            Ok(response)
+        }
+        fn name(&self) -> &str {
+            "shout"
+        }
+    }
+
+    let json = r#"{"provider": "claude", "action": "greet", "transform": "shout"}"#;
+    let err = serde_json::from_str::<PipelineStep>(json).unwrap_err().to_string();
+    assert!(err.contains("Unknown transform spec: 'shout'"), "{}", err);
+
+    transform::register("shout", |_| Ok(Arc::new(Shout)));
+    let step: PipelineStep = serde_json::from_str(json).unwrap();
+    assert_eq!(step.get_transform().unwrap().name(), "shout");
+    assert_eq!(serde_json::to_value(&step).unwrap()["transform"], "shout");
+
+    let tools = ToolRegistry::new(".").enable(BuiltinTool::ReadFile).toolset();
+    assert!(serde_json::to_string(&PipelineStep::new("claude", "read").with_tools(tools)).is_err());
+}
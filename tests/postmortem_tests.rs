@@ -0,0 +1,160 @@
+use ai_cli::config::Config;
+use ai_cli::pipeline::postmortem::run_postmortem;
+use ai_cli::context::Redactor;
+use ai_cli::pipeline::{FailureKind, PipelineExecutor, PipelineFailure, PipelineStep, SafetyAction, SafetyFilter, SafetyRule, SafetyScope};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream, UnauthorizedError};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::stream;
+use std::sync::Arc;
+
+struct FailingProvider;
+
+#[async_trait]
+impl AIProvider for FailingProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Err(anyhow!("Anthropic API error: 429 Too Many Requests - rate limit exceeded"))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        Err(anyhow!("not supported"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "failing"
+    }
+}
+
+struct EchoProvider;
+
+#[async_trait]
+impl AIProvider for EchoProvider {
+    async fn execute(&self, prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Ok(Response::new(format!("diagnosed {} chars", prompt.len())))
+    }
+
+    async fn stream(&self, prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        let text = prompt.to_string();
+        Ok(Box::pin(stream::once(async move { Ok(text) })))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn name(&self) -> &str {
+        "echo"
+    }
+}
+
+#[test]
+fn test_classify_failures() {
+    let unauthorized: anyhow::Error = UnauthorizedError { provider: "claude".into(), detail: "expired".into() }.into();
+    assert_eq!(FailureKind::classify(&unauthorized), FailureKind::Auth);
+    assert_eq!(FailureKind::classify(&anyhow!("429 Too Many Requests")), FailureKind::RateLimit);
+    assert_eq!(FailureKind::classify(&anyhow!("prompt is too long: 250000 tokens")), FailureKind::ContextTooLarge);
+    assert_eq!(FailureKind::classify(&anyhow!("400 Bad Request - invalid_request_error")), FailureKind::BadPrompt);
+    assert_eq!(FailureKind::classify(&anyhow!("Failed to send request to Gemini API")), FailureKind::Network);
+    assert_eq!(FailureKind::classify(&anyhow!("operation timed out")), FailureKind::Timeout);
+    assert_eq!(FailureKind::classify(&anyhow!("something odd")), FailureKind::Unknown);
+}
+
+#[tokio::test]
+async fn test_pipeline_failure_carries_step_details() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("failing", Arc::new(FailingProvider));
+
+    let steps = vec![PipelineStep::new("failing", "summarize").with_context("notes")];
+    let err = executor.execute(&steps, Context::new()).await.unwrap_err();
+    assert!(err.to_string().starts_with("Pipeline execution failed at step 1:"));
+
+    let failure = err.downcast_ref::<PipelineFailure>().unwrap();
+    assert_eq!(failure.step_index, 0);
+    assert_eq!(failure.provider, "failing");
+    assert_eq!(failure.prompt, "summarize: notes");
+    assert_eq!(failure.kind, FailureKind::RateLimit);
+    assert!(failure.logs.iter().any(|l| l.starts_with("retries:")));
+    assert!(failure.postmortem_prompt().contains("Error: Anthropic API error: 429"));
+    assert!(failure.completed.is_empty());
+}
+
+#[tokio::test]
+async fn test_pipeline_failure_keeps_the_responses_of_earlier_steps() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply("the design")));
+    executor.register_provider("failing", Arc::new(FailingProvider));
+
+    let steps = vec![PipelineStep::new("claude", "design"), PipelineStep::new("failing", "implement")];
+    let err = executor.execute(&steps, Context::new()).await.unwrap_err();
+    let failure = err.downcast_ref::<PipelineFailure>().unwrap();
+    assert_eq!(failure.step_index, 1);
+    let completed: Vec<&str> = failure.completed.iter().map(|r| r.content.as_str()).collect();
+    assert_eq!(completed, vec!["the design"]);
+}
+
+#[tokio::test]
+async fn test_run_postmortem_prefixes_heuristic_summary() {
+    let failure = PipelineFailure {
+        step_index: 1,
+        provider: "claude".to_string(),
+        prompt: "implement".to_string(),
+        error: "prompt is too long".to_string(),
+        kind: FailureKind::ContextTooLarge,
+        logs: Vec::new(),
+        completed: Vec::new(),
+    };
+
+    let executor = PipelineExecutor::new();
+    executor.register_provider("echo", Arc::new(EchoProvider));
+    let report = run_postmortem(&executor, "echo", &failure).await.unwrap();
+    assert!(report.starts_with("Likely cause: context too large\nSuggested fix:"));
+    assert!(report.contains("Diagnosis (echo):\ndiagnosed"));
+}
+
+#[tokio::test]
+async fn test_postmortem_prompt_is_prepared_like_a_step() {
+    let key = "sk-ant-REDACTED";
+    let failure = PipelineFailure {
+        step_index: 0,
+        provider: "claude".to_string(),
+        prompt: format!("deploy with {} for Project Falcon", key),
+        error: "overloaded".to_string(),
+        kind: FailureKind::RateLimit,
+        logs: Vec::new(),
+        completed: Vec::new(),
+    };
+    let provider = Arc::new(MockProvider::new("gemini").with_reply("wait and retry"));
+    let mut executor = PipelineExecutor::new();
+    executor.set_redactor(Arc::new(Redactor::new()));
+    executor.register_provider("gemini", provider.clone());
+
+    run_postmortem(&executor, "gemini", &failure).await.unwrap();
+    assert!(!provider.prompts()[0].contains(key), "{}", provider.prompts()[0]);
+    assert!(provider.prompts()[0].contains("[REDACTED:anthropic_api_key]"));
+
+    // A prompt that a block rule stopped is not forwarded to another provider
+    let rule = SafetyRule {
+        name: "codename".to_string(),
+        pattern: None,
+        terms: vec!["Project Falcon".to_string()],
+        action: SafetyAction::Block,
+        scope: SafetyScope::Prompt,
+    };
+    executor.set_safety_filter(Arc::new(SafetyFilter::new().with_rule(&rule).unwrap()));
+    assert!(run_postmortem(&executor, "gemini", &failure).await.is_err());
+    assert_eq!(provider.prompts().len(), 1);
+}
+
+#[test]
+fn test_post_mortem_config() {
+    assert!(!Config::default().post_mortem.enabled);
+
+    let config = Config::from_toml("[post_mortem]\nenabled = true\nprovider = \"gemini\"\n").unwrap();
+    assert!(config.post_mortem.enabled);
+    assert_eq!(config.post_mortem.provider.as_deref(), Some("gemini"));
+}
@@ -0,0 +1,141 @@
+use ai_cli::cli::CliArgs;
+use ai_cli::context::{Anonymizer, Sensitive};
+use ai_cli::pipeline::{PipelineEvent, PipelineExecutor, PipelineStep};
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{Context, JsonMode, ProviderOptions};
+use clap::Parser;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn anonymizer() -> Anonymizer {
+    Anonymizer::new().with_term(Sensitive::User, "alice").with_term(Sensitive::Host, "build-box")
+}
+
+#[test]
+fn test_anonymize_replaces_emails_paths_users_and_hosts_consistently() {
+    let anonymizer = anonymizer();
+    let text = "alice@example.com on build-box saw a panic in /home/alice/acme/src/main.rs; alice says /home/alice/acme/src/lib.rs too";
+    let anonymized = anonymizer.anonymize(text);
+    assert_eq!(
+        anonymized,
+        "[EMAIL_1] on [HOST_1] saw a panic in [PATH_1]/main.rs; [USER_1] says [PATH_1]/lib.rs too"
+    );
+    assert_eq!(anonymizer.restore(&anonymized), text);
+    // The same original always gets the same placeholder
+    assert_eq!(anonymizer.anonymize("mail alice@example.com"), "mail [EMAIL_1]");
+    assert_eq!(
+        anonymizer.mapping(),
+        vec![
+            ("[EMAIL_1]".to_string(), "alice@example.com".to_string()),
+            ("[HOST_1]".to_string(), "build-box".to_string()),
+            ("[PATH_1]".to_string(), "/home/alice/acme/src".to_string()),
+            ("[USER_1]".to_string(), "alice".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_urls_relative_paths_and_unknown_placeholders_are_left_alone() {
+    let anonymizer = anonymizer();
+    let text = "See https://github.com/acme/app/issues/1, src/main.rs and ~/notes/todo.md; alicel is not alice's";
+    assert_eq!(
+        anonymizer.anonymize(text),
+        "See https://github.com/acme/app/issues/1, src/main.rs and ~/notes/todo.md; alicel is not [USER_1]'s"
+    );
+    assert_eq!(anonymizer.anonymize(r"Open C:\Users\bob\report.txt"), r"Open [PATH_1]\report.txt");
+    assert_eq!(anonymizer.restore("[PATH_1] and [PATH_9]"), r"C:\Users\bob and [PATH_9]");
+    // Generic names would mangle ordinary text
+    let generic = Anonymizer::new().with_term(Sensitive::User, "root").with_term(Sensitive::Host, "localhost");
+    assert_eq!(generic.anonymize("root on localhost"), "root on localhost");
+}
+
+#[test]
+fn test_anonymize_context_covers_file_names_contents_messages_and_environment() {
+    let anonymizer = anonymizer();
+    let mut context = Context::new();
+    context.add_file_with_content(PathBuf::from("/home/alice/acme/Cargo.toml"), "authors = [\"alice@example.com\"]".to_string());
+    context.add_message(ai_cli::providers::Message::new(ai_cli::providers::MessageRole::User, "Built on build-box"));
+    context.environment.insert("HOME".to_string(), "/home/alice/acme".to_string());
+
+    let anonymized = anonymizer.anonymize_context(&context);
+    let path = PathBuf::from("[PATH_1]/Cargo.toml");
+    assert_eq!(anonymized.file_contents.get(&path).map(String::as_str), Some("authors = [\"[EMAIL_1]\"]"));
+    assert!(anonymized.current_files.contains(&path));
+    assert_eq!(anonymized.conversation_history[0].content, "Built on [HOST_1]");
+    assert_eq!(anonymized.environment["HOME"], "[PATH_2]/acme");
+    // The original is untouched
+    assert!(context.file_contents.contains_key(&PathBuf::from("/home/alice/acme/Cargo.toml")));
+}
+
+#[tokio::test]
+async fn test_executor_sends_placeholders_and_restores_the_reply() {
+    let reply = r#"{"content":[{"type":"text","text":"Fixed [PATH_1]/main.rs, thanks [USER_1]"}],"model":"claude-3-5-sonnet-20240620","usage":{"input_tokens":9,"output_tokens":2}}"#;
+    let transport = Arc::new(FakeTransport::new().with_status(200, reply));
+    let mut executor = PipelineExecutor::new();
+    executor.set_anonymizer(Arc::new(anonymizer()));
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("key".to_string()).with_transport(transport.clone())));
+
+    let mut context = Context::new();
+    context.add_file_with_content(PathBuf::from("/home/alice/acme/src/main.rs"), "fn main() {} // alice@example.com".to_string());
+    let responses = executor.execute(&[PipelineStep::new("claude", "Fix /home/alice/acme/src/main.rs for alice")], context).await.unwrap();
+
+    let body = transport.requests()[0].json().unwrap().to_string();
+    for original in ["/home/alice", "alice@example.com", "for alice"] {
+        assert!(!body.contains(original), "{} sent in {}", original, body);
+    }
+    assert!(body.contains("[PATH_1]/main.rs") && body.contains("[EMAIL_1]"), "{}", body);
+    assert_eq!(responses[0].content, "Fixed /home/alice/acme/src/main.rs, thanks alice");
+}
+
+#[test]
+fn test_streamed_chunks_are_restored_even_when_a_placeholder_is_split() {
+    let anonymizer = anonymizer();
+    anonymizer.anonymize("alice on build-box");
+    let mut restorer = anonymizer.chunk_restorer();
+    let shown: Vec<String> = ["Thanks [US", "ER_1], see [", "HOST_1]", " and [notes] [EMAIL"].iter().map(|chunk| restorer.push(chunk)).collect();
+    assert_eq!(shown, vec!["Thanks ", "alice, see ", "build-box", " and [notes] "]);
+    // An unfinished bracket at the end is shown as it is
+    assert_eq!(restorer.finish(), "[EMAIL");
+}
+
+#[tokio::test]
+async fn test_streamed_replies_are_shown_restored() {
+    let anonymizer = Arc::new(anonymizer());
+    let mut executor = PipelineExecutor::new();
+    executor.set_anonymizer(anonymizer.clone());
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply("Hi [USER_1]")));
+    let mut events = executor.subscribe();
+
+    let responses = executor.execute_streaming(&[PipelineStep::new("claude", "I am alice")], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "Hi alice");
+    let chunks: String = std::iter::from_fn(|| events.try_recv().ok())
+        .filter_map(|event| match event {
+            PipelineEvent::Chunk { text, .. } => Some(text),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(chunks, "Hi alice");
+}
+
+#[tokio::test]
+async fn test_json_replies_are_restored_inside_strings() {
+    let provider = Arc::new(MockProvider::new("claude").with_reply(r#"{"file": "[PATH_1]\\report.txt"}"#));
+    let mut executor = PipelineExecutor::new();
+    executor.set_anonymizer(Arc::new(Anonymizer::new()));
+    executor.set_options(ProviderOptions { json: Some(JsonMode::new(serde_json::json!({"type": "object"}))), ..ProviderOptions::default() });
+    executor.register_provider("claude", provider.clone());
+
+    let responses = executor.execute(&[PipelineStep::new("claude", r"Summarize C:\Users\bob\report.txt")], Context::new()).await.unwrap();
+    assert_eq!(provider.prompts()[0], r"Summarize [PATH_1]\report.txt");
+    let value: serde_json::Value = serde_json::from_str(&responses[0].content).unwrap();
+    assert_eq!(value["file"], r"C:\Users\bob\report.txt");
+}
+
+#[test]
+fn test_privacy_flag_parses_anywhere() {
+    assert!(CliArgs::try_parse_from(["ai-cli", "--privacy", "execute", "-p", "claude", "-P", "hi"]).unwrap().privacy);
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--privacy"]).unwrap().privacy);
+    assert!(!CliArgs::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "hi"]).unwrap().privacy);
+}
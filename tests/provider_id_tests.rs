@@ -0,0 +1,47 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineDefinition, PipelineParser};
+use ai_cli::providers::{ProviderId, UnknownProvider};
+use clap::Parser;
+
+#[test]
+fn test_names_and_aliases() {
+    assert_eq!("claude".parse::<ProviderId>().unwrap(), ProviderId::Claude);
+    assert_eq!("Anthropic".parse::<ProviderId>().unwrap(), ProviderId::Claude);
+    assert_eq!("google".parse::<ProviderId>().unwrap(), ProviderId::Gemini);
+    assert_eq!("openai".parse::<ProviderId>().unwrap(), ProviderId::Codex);
+    assert_eq!(ProviderId::Gemini.to_string(), "gemini");
+    assert_eq!(ProviderId::canonical("anthropic"), "claude");
+    assert_eq!(ProviderId::canonical("local-llm"), "local-llm");
+    assert_eq!(serde_json::to_string(&ProviderId::Codex).unwrap(), "\"codex\"");
+    assert_eq!(serde_json::from_str::<ProviderId>("\"google\"").unwrap(), ProviderId::Gemini);
+}
+
+#[test]
+fn test_typo_suggestions() {
+    let err = "cluade".parse::<ProviderId>().unwrap_err();
+    assert_eq!(err, UnknownProvider { name: "cluade".into(), suggestion: Some("claude") });
+    assert_eq!(err.to_string(), "unknown provider 'cluade' (did you mean 'claude'?)");
+    assert_eq!("gogle".parse::<ProviderId>().unwrap_err().suggestion, Some("gemini"));
+    assert_eq!(
+        "llama".parse::<ProviderId>().unwrap_err().to_string(),
+        "unknown provider 'llama' (expected one of: claude, gemini, codex)"
+    );
+}
+
+#[test]
+fn test_aliases_in_cli_and_chains() {
+    let args = CliArgs::try_parse_from(["ai-cli", "execute", "-p", "anthropic", "-P", "hi"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Execute { provider: ProviderId::Claude, .. })));
+    let err = CliArgs::try_parse_from(["ai-cli", "execute", "-p", "gemnii", "-P", "hi"]).unwrap_err();
+    assert!(err.to_string().contains("did you mean 'gemini'?"), "{}", err);
+
+    let steps = PipelineParser::parse("anthropic:design -> google|openai:implement").unwrap();
+    assert_eq!(PipelineParser::format(&steps), "claude:design -> gemini|codex:implement");
+    let err = PipelineParser::validate_providers(&PipelineParser::parse("cladue:x").unwrap(), &["claude", "gemini"]).unwrap_err();
+    assert!(err.to_string().contains("Did you mean 'claude'?"), "{}", err);
+
+    let def: PipelineDefinition = serde_yaml::from_str("name: x\nsteps:\n  - provider: google\n    action: review\n").unwrap();
+    assert_eq!(def.to_steps().unwrap()[0].provider, "gemini");
+    let def: PipelineDefinition = serde_yaml::from_str("name: x\nsteps:\n  - provider: codx\n    action: review\n").unwrap();
+    assert!(def.validate().unwrap_err().to_string().contains("did you mean 'codex'?"));
+}
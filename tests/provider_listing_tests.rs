@@ -0,0 +1,45 @@
+use ai_cli::auth::AuthManager;
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::PipelineExecutor;
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::listing::ProviderListing;
+use clap::Parser;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_listing_covers_every_known_provider() {
+    let mut auth = AuthManager::new();
+    auth.set_api_key("claude", "sk-ant-test-key-0000");
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(ClaudeProvider::new("sk-ant-test-key-0000".into()).with_model("claude-test")));
+
+    let listing = ProviderListing::collect(&auth, &executor).await;
+    let names: Vec<&str> = listing.providers.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["claude", "gemini", "codex"]);
+
+    let claude = &listing.providers[0];
+    assert!(claude.available);
+    assert_eq!(claude.auth_method.as_deref(), Some("api-key"));
+    assert_eq!(claude.auth_source.as_deref(), Some("programmatic"));
+    assert_eq!(claude.model.as_deref(), Some("claude-test"));
+    assert!(claude.capabilities.supports_streaming);
+    assert_eq!(claude.aliases, vec!["anthropic"]);
+
+    let gemini = &listing.providers[1];
+    assert!(!gemini.available);
+    assert!(gemini.model.is_some(), "unavailable providers still report their default model");
+
+    let table = listing.to_string();
+    assert!(table.starts_with("PROVIDER AVAILABLE"));
+    assert!(table.contains("claude   yes       api-key      claude-test"), "{}", table);
+
+    let json = serde_json::to_value(&listing).unwrap();
+    assert_eq!(json[0]["name"], "claude");
+    assert_eq!(json[0]["capabilities"]["max_tokens"], 200000);
+}
+
+#[test]
+fn test_list_providers_json_flag() {
+    let args = CliArgs::try_parse_from(["ai-cli", "list-providers", "--json"]).unwrap();
+    assert!(matches!(args.command, Some(Command::ListProviders { json: true })));
+}
@@ -1,4 +1,4 @@
-use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use ai_cli::providers::{AIProvider, Capabilities, Context, ModelInfo, Response, ResponseStream, check_model};
 use async_trait::async_trait;
 use futures::stream;
 use anyhow::Result;
@@ -27,6 +27,7 @@ impl AIProvider for MockProvider {
             supports_streaming: true,
             supports_context: true,
             max_tokens: 4096,
+            ..Capabilities::default()
         }
     }
 
@@ -71,4 +72,147 @@ async fn test_provider_stream() {
     let stream = provider.stream("test prompt", &context).await;
     
     assert!(stream.is_ok());
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_validate_auth_unsupported_by_default() {
+    let provider = MockProvider::new();
+    let err = provider.validate_auth().await.unwrap_err();
+    assert!(err.to_string().contains("does not support live auth validation"));
+}
+
+#[tokio::test]
+async fn test_list_models_unsupported_by_default() {
+    let provider = MockProvider::new();
+    let err = provider.list_models().await.unwrap_err();
+    assert!(err.to_string().contains("does not support listing models"));
+}
+
+#[test]
+fn test_check_model_suggests_close_matches() {
+    let models: Vec<ModelInfo> = ["claude-3-5-sonnet-20240620", "claude-3-haiku-20240307", "gemini-1.5-pro"]
+        .iter()
+        .map(|id| ModelInfo { id: id.to_string(), ..ModelInfo::default() })
+        .collect();
+
+    assert!(check_model(&models, "gemini-1.5-pro").is_ok());
+
+    let err = check_model(&models, "claude-3-5-sonnet").unwrap_err().to_string();
+    assert!(err.contains("Did you mean"));
+    assert!(err.contains("claude-3-5-sonnet-20240620"));
+    assert!(!err.contains("gemini"));
+
+    let err = check_model(&models, "gpt-4o").unwrap_err().to_string();
+    assert_eq!(err, "Unknown model 'gpt-4o'");
+}
+
+#[test]
+fn test_provider_options_layering() {
+    use ai_cli::providers::ProviderOptions;
+
+    let config = ProviderOptions { temperature: Some(0.3), max_tokens: Some(4096), stop: vec!["END".into()], ..ProviderOptions::default() };
+    let step = ProviderOptions::parse_assignments("max_tokens=200, system=Be brief").unwrap();
+    let merged = config.merged(&step);
+
+    assert_eq!(merged.temperature, Some(0.3));
+    assert_eq!(merged.max_tokens, Some(200));
+    assert_eq!(merged.system.as_deref(), Some("Be brief"));
+    assert_eq!(merged.stop, vec!["END"]);
+    assert!(ProviderOptions::default().is_empty());
+    assert!(ProviderOptions::parse_assignments("max_tokens=0").is_err());
+}
+
+#[test]
+fn test_compose_request_separates_roles() {
+    use ai_cli::providers::{Message, MessageRole, ProviderOptions, compose_request};
+
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "Follow the style guide."));
+    context.add_message(Message::new(MessageRole::Assistant, "earlier output"));
+    context.add_file_with_content("b.rs".into(), "fn b() {}".to_string());
+    context.add_file_with_content("a.rs".into(), "fn a() {}".to_string());
+
+    let options = ProviderOptions { system: Some("You are a reviewer.".into()), ..ProviderOptions::default() };
+    let (system, user) = compose_request("review", &context, &options);
+
+    assert_eq!(system.as_deref(), Some("You are a reviewer.\n\nFollow the style guide."));
+    let a = user.find("a.rs").unwrap();
+    let b = user.find("b.rs").unwrap();
+    assert!(a < b);
+    assert!(user.ends_with("\n\nreview"));
+
+    let (system, user) = compose_request("hi", &Context::new(), &ProviderOptions::default());
+    assert_eq!(system, None);
+    assert_eq!(user, "hi");
+}
+
+#[test]
+fn test_seed_layering() {
+    use ai_cli::providers::ProviderOptions;
+
+    let run = ProviderOptions { seed: Some(7), ..ProviderOptions::default() };
+    let step = ProviderOptions::parse_assignments("temperature=0.4").unwrap();
+    assert_eq!(ProviderOptions { seed: Some(1), ..ProviderOptions::default() }.merged(&run).seed, Some(7));
+    assert_eq!(run.merged(&step).seed, Some(7));
+    assert!(ProviderOptions::parse_assignments("seed=7").is_err());
+}
+
+#[tokio::test]
+async fn test_gemini_sends_seed() {
+    use ai_cli::providers::ProviderOptions;
+    use ai_cli::providers::gemini::GeminiProvider;
+    use ai_cli::providers::testing::FakeTransport;
+    use std::sync::Arc;
+
+    let transport = Arc::new(FakeTransport::new().with_json(serde_json::json!({
+        "candidates": [{"content": {"parts": [{"text": "ok"}]}}]
+    })));
+    let provider = GeminiProvider::new("key".to_string()).with_transport(transport.clone());
+    assert!(provider.supports_seed());
+
+    let options = ProviderOptions { temperature: Some(0.0), seed: Some(42), ..ProviderOptions::default() };
+    provider.execute_with_options("hi", &Context::new(), &options).await.unwrap();
+    let body = transport.requests()[0].json().unwrap();
+    assert_eq!(body["generationConfig"]["seed"], 42);
+    assert_eq!(body["generationConfig"]["temperature"], 0.0);
+}
+
+#[tokio::test]
+async fn test_gemini_uploads_large_files_for_the_run() {
+    use ai_cli::pipeline::{PipelineExecutor, PipelineStep};
+    use ai_cli::providers::gemini::{GeminiProvider, UPLOADED_FILES_KEY};
+    use ai_cli::providers::testing::FakeTransport;
+    use std::sync::Arc;
+
+    let transport = Arc::new(FakeTransport::new()
+        .with_json(serde_json::json!({
+            "file": {"name": "files/abc", "uri": "https://files.example/abc", "mimeType": "text/plain"}
+        }))
+        .with_json(serde_json::json!({ "candidates": [{"content": {"parts": [{"text": "reviewed"}]}}] }))
+        .with_json(serde_json::json!({})));
+    let provider = GeminiProvider::new("key".to_string()).with_transport(transport.clone()).with_upload_threshold(Some(64));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("gemini", Arc::new(provider));
+
+    let mut context = Context::new();
+    context.add_file_with_content("big.rs".into(), "fn main() {}\n".repeat(10));
+    context.add_file_with_content("small.rs".into(), "fn f() {}".to_string());
+    let (results, context) = executor.execute_with_context(&[PipelineStep::new("gemini", "review")], context).await.unwrap();
+    assert_eq!(results[0].content, "reviewed");
+    assert!(!context.metadata.contains_key(UPLOADED_FILES_KEY));
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].method, "POST");
+    assert!(requests[0].url.starts_with("https://generativelanguage.googleapis.com/upload/v1beta/files"));
+    assert_eq!(requests[0].body.as_deref(), Some("fn main() {}\n".repeat(10).as_str()));
+
+    let body = requests[1].json().unwrap();
+    let parts = body["contents"][0]["parts"].as_array().unwrap();
+    assert_eq!(parts[0]["text"], "<file path=\"big.rs\">");
+    assert_eq!(parts[1]["file_data"]["file_uri"], "https://files.example/abc");
+    let text = parts[2]["text"].as_str().unwrap();
+    assert!(text.contains("<file path=\"small.rs\">") && !text.contains("big.rs"), "{}", text);
+
+    assert_eq!(requests[2].method, "DELETE");
+    assert_eq!(requests[2].url, "https://generativelanguage.googleapis.com/v1beta/files/abc");
+}
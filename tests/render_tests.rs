@@ -0,0 +1,36 @@
+use ai_cli::cli::CliArgs;
+use ai_cli::render::{MarkdownRenderer, PlainRenderer, RenderMode, Renderer};
+use clap::Parser;
+
+const REPLY: &str = "# Plan\n\nUse **serde** and see [docs](https://serde.rs).\n\n* parse\n* `validate`\n\n```rust\nfn main() {}\n```\nDone.\n";
+
+#[test]
+fn test_plain_strips_markup() {
+    assert_eq!(
+        PlainRenderer.render(REPLY),
+        "Plan\n\nUse serde and see docs (https://serde.rs).\n\n- parse\n- validate\n\nfn main() {}\nDone.\n"
+    );
+    assert_eq!(PlainRenderer.render("2 * 3 * 4 = 24, _snake_case_"), "2 * 3 * 4 = 24, _snake_case_");
+}
+
+#[test]
+fn test_markdown_highlights_code() {
+    let rendered = MarkdownRenderer::new().with_width(60).render(REPLY);
+    assert!(rendered.contains("\x1b["), "{:?}", rendered);
+    assert!(!rendered.contains("```"));
+    assert!(!rendered.contains("**serde**"));
+    assert!(rendered.contains("main"));
+    assert!(rendered.contains("Done."));
+}
+
+#[test]
+fn test_mode_resolution() {
+    assert_eq!(RenderMode::resolve(None, true), RenderMode::Markdown);
+    assert_eq!(RenderMode::resolve(None, false), RenderMode::Raw);
+    assert_eq!(RenderMode::resolve(Some(RenderMode::Plain), true), RenderMode::Plain);
+    assert_eq!(RenderMode::Raw.render(REPLY), REPLY);
+
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "hi", "--render", "plain"]).unwrap();
+    assert_eq!(args.render, Some(RenderMode::Plain));
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "--render", "html", "doctor"]).is_err());
+}
@@ -0,0 +1,158 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::context::git::{ChangeKind, FileDiff};
+use ai_cli::context::github::{GitHubClient, PullRef, parse_remote};
+use ai_cli::pipeline::review::{ReviewChunk, ReviewComment, ReviewFormat, chunk_diff, commentable_lines, parse_comments, review_files};
+use ai_cli::pipeline::{PipelineExecutor, PipelineStep};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use clap::Parser;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const PATCH: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@
+ fn a() {}
++fn b() {}
+ fn c() {}
+ fn d() {}
+@@ -20,2 +21,2 @@ impl Foo {
+-    old();
++    new();
+     done();
+";
+
+fn diff(patch: &str) -> FileDiff {
+    FileDiff { path: PathBuf::from("src/lib.rs"), old_path: None, kind: ChangeKind::Modified, patch: patch.to_string() }
+}
+
+#[test]
+fn test_pull_refs_parse_from_urls_slugs_and_numbers() {
+    let expected = PullRef { owner: "octo".to_string(), repo: "tools".to_string(), number: 42 };
+    assert_eq!(PullRef::parse("https://github.com/octo/tools/pull/42", None).unwrap(), expected);
+    assert_eq!(PullRef::parse("https://github.com/octo/tools/pull/42/files", None).unwrap(), expected);
+    assert_eq!(PullRef::parse("octo/tools#42", None).unwrap(), expected);
+    assert_eq!(PullRef::parse("42", Some("git@github.com:octo/tools.git")).unwrap(), expected);
+    assert_eq!(PullRef::parse("#42", Some("https://github.com/octo/tools")).unwrap(), expected);
+    assert!(PullRef::parse("42", None).is_err());
+    assert!(PullRef::parse("42", Some("https://gitlab.com/octo/tools.git")).is_err());
+    assert!(PullRef::parse("https://github.com/octo/tools/issues/42", None).is_err());
+    assert_eq!(expected.to_string(), "octo/tools#42");
+
+    assert_eq!(parse_remote("ssh://git@github.com/octo/tools.git"), Some(("octo".to_string(), "tools".to_string())));
+    assert_eq!(parse_remote("https://github.com/octo"), None);
+}
+
+#[test]
+fn test_commentable_lines_follow_the_hunk_headers() {
+    let lines: Vec<u64> = commentable_lines(PATCH).into_iter().collect();
+    assert_eq!(lines, vec![1, 2, 3, 4, 21, 22]);
+}
+
+#[test]
+fn test_large_patches_split_at_hunk_boundaries() {
+    let whole = chunk_diff(&diff(PATCH), 10_000);
+    assert_eq!(whole.len(), 1);
+    assert_eq!(whole[0].patch, PATCH);
+
+    let split = chunk_diff(&diff(PATCH), 120);
+    assert_eq!(split.len(), 2);
+    for chunk in &split {
+        assert!(chunk.patch.starts_with("diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ "));
+    }
+    assert!(split[0].patch.contains("+fn b() {}"));
+    assert!(split[1].patch.contains("+    new();"));
+    assert_eq!(split[1].lines.iter().copied().collect::<Vec<_>>(), vec![21, 22]);
+
+    assert!(chunk_diff(&diff(""), 100).is_empty());
+}
+
+#[test]
+fn test_replies_become_comments_on_lines_in_the_diff() {
+    let chunk = ReviewChunk { path: PathBuf::from("src/lib.rs"), patch: PATCH.to_string(), lines: commentable_lines(PATCH) };
+    let comments = parse_comments(
+        "Here you go:\n```json\n[{\"line\": 2, \"body\": \"b is unused\"}, {\"line\": 99, \"body\": \"off the diff\"}, {\"body\": \"overall fine\"}]\n```",
+        &chunk,
+    );
+    assert_eq!(comments, vec![
+        ReviewComment { path: PathBuf::from("src/lib.rs"), line: Some(2), body: "b is unused".to_string() },
+        ReviewComment { path: PathBuf::from("src/lib.rs"), line: None, body: "off the diff".to_string() },
+        ReviewComment { path: PathBuf::from("src/lib.rs"), line: None, body: "overall fine".to_string() },
+    ]);
+    assert!(parse_comments("[]", &chunk).is_empty());
+    assert_eq!(parse_comments("Looks risky.", &chunk)[0].body, "Looks risky.");
+}
+
+#[tokio::test]
+async fn test_review_runs_per_chunk_and_renders_github_json() {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude")
+        .with_reply(r#"[{"line": 2, "body": "b is unused"}]"#)
+        .with_reply("nothing")
+        .with_error("overloaded")));
+    let steps = vec![PipelineStep::new("claude", "review")];
+    let files = vec![
+        diff(PATCH),
+        FileDiff { path: PathBuf::from("README.md"), old_path: None, kind: ChangeKind::Modified, patch: "diff --git a/README.md b/README.md\n@@ -1 +1 @@\n-a\n+b\n".to_string() },
+        FileDiff { path: PathBuf::from("logo.png"), old_path: None, kind: ChangeKind::Added, patch: String::new() },
+        FileDiff { path: PathBuf::from("src/main.rs"), old_path: None, kind: ChangeKind::Modified, patch: "diff --git a/src/main.rs b/src/main.rs\n@@ -1 +1 @@\n-x\n+y\n".to_string() },
+    ];
+    let report = review_files(&executor, &steps, "Add b", "", &files, 10_000).await.unwrap();
+    assert_eq!(report.comments.len(), 2);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, PathBuf::from("src/main.rs"));
+
+    let github = report.to_github("abc123");
+    assert_eq!(github["commit_id"], "abc123");
+    assert_eq!(github["event"], "COMMENT");
+    assert_eq!(github["comments"], serde_json::json!([{ "path": "src/lib.rs", "line": 2, "side": "RIGHT", "body": "b is unused" }]));
+    assert_eq!(github["body"], "**README.md**: nothing");
+
+    let markdown = report.to_markdown("octo/tools#42 Add b");
+    assert!(markdown.starts_with("# Review: octo/tools#42 Add b\n"));
+    assert!(markdown.contains("## src/lib.rs\n\n- **L2**: b is unused\n"));
+    assert!(markdown.contains("> Not reviewed: src/main.rs"));
+}
+
+#[tokio::test]
+async fn test_github_client_fetches_the_pull_request_and_its_files() {
+    let transport = Arc::new(FakeTransport::new()
+        .with_json(serde_json::json!({ "number": 42, "title": "Add b", "body": null, "head": { "sha": "abc123" } }))
+        .with_json(serde_json::json!([
+            { "filename": "src/lib.rs", "status": "modified", "patch": "@@ -1 +1,2 @@\n fn a() {}\n+fn b() {}" },
+            { "filename": "new/name.rs", "status": "renamed", "previous_filename": "old/name.rs" },
+        ])));
+    let client = GitHubClient::from_env().with_token(Some("ghp_test".to_string())).with_transport(transport.clone());
+    let pull = PullRef::parse("octo/tools#42", None).unwrap();
+    let request = client.pull_request(&pull).await.unwrap();
+
+    assert_eq!(request.title, "Add b");
+    assert_eq!(request.head_sha, "abc123");
+    assert_eq!(request.files.len(), 2);
+    assert_eq!(request.files[0].patch, "diff --git a/src/lib.rs b/src/lib.rs\n@@ -1 +1,2 @@\n fn a() {}\n+fn b() {}\n");
+    assert_eq!(request.files[1].kind, ChangeKind::Renamed);
+    assert_eq!(request.files[1].old_path, Some(PathBuf::from("old/name.rs")));
+    assert!(request.files[1].patch.is_empty());
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].url, "https://api.github.com/repos/octo/tools/pulls/42");
+    assert_eq!(requests[1].url, "https://api.github.com/repos/octo/tools/pulls/42/files?per_page=100&page=1");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer ghp_test"));
+}
+
+#[test]
+fn test_review_subcommand_parses() {
+    let args = CliArgs::try_parse_from(["ai-cli", "review", "--pr", "42", "--format", "github", "-o", "review.json"]).unwrap();
+    match args.command {
+        Some(Command::Review { pr, format, output, chain, .. }) => {
+            assert_eq!(pr, "42");
+            assert_eq!(format, ReviewFormat::Github);
+            assert_eq!(output, Some(PathBuf::from("review.json")));
+            assert_eq!(chain, None);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "review"]).is_err());
+}
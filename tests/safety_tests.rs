@@ -0,0 +1,156 @@
+use ai_cli::config::Config;
+use ai_cli::error::{Error, ExitCode};
+use ai_cli::pipeline::{
+    FailureKind, PipelineEvent, PipelineExecutor, PipelineStep, SafetyAction, SafetyError, SafetyFilter, SafetyRule, SafetyScope, SafetySettings,
+};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::{Context, ProviderOptions};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn rule(name: &str, pattern: Option<&str>, terms: &[&str], action: SafetyAction, scope: SafetyScope) -> SafetyRule {
+    SafetyRule {
+        name: name.to_string(),
+        pattern: pattern.map(str::to_string),
+        terms: terms.iter().map(|t| t.to_string()).collect(),
+        action,
+        scope,
+    }
+}
+
+fn executor(filter: SafetyFilter, provider: &Arc<MockProvider>) -> PipelineExecutor {
+    let mut executor = PipelineExecutor::new();
+    executor.set_safety_filter(Arc::new(filter));
+    executor.register_provider("claude", provider.clone());
+    executor
+}
+
+#[test]
+fn test_rules_block_redact_or_warn() {
+    let filter = SafetyFilter::new()
+        .with_rule(&rule("codename", None, &["Project Falcon"], SafetyAction::Block, SafetyScope::Both))
+        .unwrap()
+        .with_rule(&rule("ticket", Some(r"INC-\d+"), &[], SafetyAction::Redact, SafetyScope::Prompt))
+        .unwrap();
+
+    let mut findings = Vec::new();
+    let text = filter.check_text("See INC-42 and INC-7\nmail ops@example.com", SafetyScope::Prompt, "prompt", &mut findings).unwrap();
+    assert_eq!(text, "See [FILTERED:ticket] and [FILTERED:ticket]\nmail ops@example.com");
+    let rules: Vec<(&str, SafetyAction, usize)> = findings.iter().map(|f| (f.rule.as_str(), f.action, f.line)).collect();
+    assert_eq!(rules, vec![("email", SafetyAction::Warn, 2), ("ticket", SafetyAction::Redact, 1)]);
+
+    // Prompt-only rules leave replies alone
+    let mut findings = Vec::new();
+    assert_eq!(filter.check_response("INC-42", &mut findings).unwrap(), "INC-42");
+    assert!(findings.is_empty());
+
+    let error = filter.check_text("status of project falcon?", SafetyScope::Prompt, "prompt", &mut Vec::new()).unwrap_err();
+    assert_eq!(error, SafetyError { rule: "codename".to_string(), scope: SafetyScope::Prompt, location: "prompt".to_string(), line: 1 });
+    assert_eq!(error.to_string(), "Prompt blocked by content filter 'codename' (prompt, line 1)");
+}
+
+#[test]
+fn test_builtin_rules_warn_until_overridden() {
+    let key = "sk-ant-REDACTED";
+    let mut findings = Vec::new();
+    let filter = SafetyFilter::new();
+    assert_eq!(filter.check_response(key, &mut findings).unwrap(), key);
+    assert_eq!(findings[0].rule, "credentials");
+
+    // A rule without a pattern keeps the built-in one and changes only its action
+    let strict = SafetyFilter::new().with_rule(&rule("credentials", None, &[], SafetyAction::Redact, SafetyScope::Both)).unwrap();
+    assert_eq!(strict.check_response(&format!("key: {}", key), &mut Vec::new()).unwrap(), "key: [FILTERED:credentials]");
+    assert!(SafetyFilter::new().with_rule(&rule("empty", None, &[], SafetyAction::Block, SafetyScope::Both)).is_err());
+    assert!(SafetyFilter::new().with_rule(&rule("broken", Some("(unclosed"), &[], SafetyAction::Block, SafetyScope::Both)).is_err());
+}
+
+#[tokio::test]
+async fn test_blocked_prompt_is_never_sent() {
+    let provider = Arc::new(MockProvider::new("claude").with_reply("ok"));
+    let filter = SafetyFilter::new().with_rule(&rule("ssn", Some(r"\b\d{3}-\d{2}-\d{4}\b"), &[], SafetyAction::Block, SafetyScope::Prompt)).unwrap();
+    let executor = executor(filter, &provider);
+    let mut context = Context::new();
+    context.add_file_with_content(PathBuf::from("customers.csv"), "name,ssn\nBob,123-45-6789".to_string());
+
+    let error = executor.execute(&[PipelineStep::new("claude", "Summarize")], context).await.unwrap_err();
+    assert!(provider.prompts().is_empty());
+    match Error::classify(&error) {
+        Some(Error::Pipeline(failure)) => {
+            assert_eq!(failure.kind, FailureKind::BadPrompt);
+            assert_eq!(failure.error, "Prompt blocked by content filter 'ssn' (customers.csv, line 2)");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    let blocked = SafetyError { rule: "ssn".to_string(), scope: SafetyScope::Prompt, location: "prompt".to_string(), line: 1 };
+    assert_eq!(ExitCode::for_error(&blocked.into()), ExitCode::Failure);
+}
+
+#[tokio::test]
+async fn test_every_call_is_filtered_both_ways() {
+    let provider = Arc::new(MockProvider::new("claude").with_reply("Ask INC-9 about Falcon").with_reply("Nothing to see"));
+    let filter = SafetyFilter::new()
+        .with_rule(&rule("ticket", Some(r"INC-\d+"), &[], SafetyAction::Redact, SafetyScope::Both))
+        .unwrap()
+        .with_rule(&rule("codename", None, &["falcon"], SafetyAction::Block, SafetyScope::Response))
+        .unwrap();
+    let mut executor = executor(filter, &provider);
+    executor.set_options(ProviderOptions { system: Some("Triage INC-1".into()), ..ProviderOptions::default() });
+
+    let error = executor.execute(&[PipelineStep::new("claude", "Check INC-3")], Context::new()).await.unwrap_err();
+    assert!(error.to_string().contains("Reply blocked by content filter 'codename'"), "{}", error);
+    assert_eq!(provider.prompts()[0], "Check [FILTERED:ticket]");
+
+    let responses = executor.execute(&[PipelineStep::new("claude", "Again")], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "Nothing to see");
+    assert_eq!(responses[0].metadata.get("safety_findings").map(String::as_str), Some("ticket system prompt"));
+}
+
+#[tokio::test]
+async fn test_filtered_replies_are_not_streamed_before_the_check() {
+    let provider = Arc::new(MockProvider::new("claude").with_reply("Falcon launches Friday").with_reply("INC-9 is fixed"));
+    let filter = SafetyFilter::new()
+        .with_rule(&rule("codename", None, &["falcon"], SafetyAction::Block, SafetyScope::Response))
+        .unwrap();
+    let mut blocking = executor(filter, &provider);
+    let mut events = blocking.subscribe();
+
+    assert!(blocking.execute_streaming(&[PipelineStep::new("claude", "Plan")], Context::new()).await.is_err());
+    let mut chunks = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let PipelineEvent::Chunk { text, .. } = event {
+            chunks.push(text);
+        }
+    }
+    assert!(chunks.is_empty(), "{:?}", chunks);
+
+    // Warnings never change a reply, so it still streams
+    assert!(!SafetyFilter::new().filters_responses());
+    let warn_only = SafetyFilter::new().with_rule(&rule("ticket", Some(r"INC-\d+"), &[], SafetyAction::Warn, SafetyScope::Both)).unwrap();
+    let mut executor = executor(warn_only, &provider);
+    let mut events = executor.subscribe();
+    executor.execute_streaming(&[PipelineStep::new("claude", "Status")], Context::new()).await.unwrap();
+    assert!(std::iter::from_fn(|| events.try_recv().ok()).any(|event| matches!(event, PipelineEvent::Chunk { .. })));
+}
+
+#[test]
+fn test_safety_settings_parse() {
+    let config: Config = toml::from_str(
+        r#"
+[[safety.rules]]
+name = "codename"
+terms = ["Project Falcon"]
+
+[[safety.rules]]
+name = "email"
+action = "redact"
+scope = "response"
+"#,
+    )
+    .unwrap();
+    assert!(config.safety.enabled);
+    assert_eq!(config.safety.rules[0].action, SafetyAction::Block);
+    assert_eq!(config.safety.rules[0].scope, SafetyScope::Both);
+    assert_eq!((config.safety.rules[1].action, config.safety.rules[1].scope), (SafetyAction::Redact, SafetyScope::Response));
+    assert!(SafetyFilter::from_settings(&config.safety).is_ok());
+    assert_eq!(Config::default().safety, SafetySettings::default());
+}
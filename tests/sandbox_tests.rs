@@ -0,0 +1,144 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::context::git::ChangeKind;
+use ai_cli::history::manifest::RunManifest;
+use ai_cli::pipeline::sandbox::diffstat;
+use ai_cli::pipeline::{FileWriter, MultiFileWriterTransform, PipelineDefinition, PipelineExecutor, PipelineStep, Sandbox};
+use ai_cli::providers::Context;
+use ai_cli::providers::mock::MockProvider;
+use clap::Parser;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[test]
+fn test_direct_writes_stay_inside_the_project() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let writer = FileWriter::direct(root);
+
+    let written = writer.write(Path::new("src/new.rs"), "fn new() {}\n").unwrap();
+    assert_eq!(written, root.join("src/new.rs"));
+    assert_eq!(fs::read_to_string(&written).unwrap(), "fn new() {}\n");
+    assert_eq!(writer.relative(&root.join("./docs/a.md")).unwrap(), PathBuf::from("docs/a.md"));
+
+    assert!(writer.write(Path::new("../escape.txt"), "x").is_err());
+    assert!(writer.write(Path::new("src/../../escape.txt"), "x").is_err());
+    assert!(writer.write(Path::new("/etc/passwd"), "x").is_err());
+    assert!(writer.write(Path::new("."), "x").is_err());
+    assert!(!root.parent().unwrap().join("escape.txt").exists());
+}
+
+#[test]
+fn test_sandboxed_writes_leave_the_project_untouched_until_applied() {
+    let project = tempfile::tempdir().unwrap();
+    let run = tempfile::tempdir().unwrap();
+    let root = project.path();
+    fs::write(root.join("lib.rs"), "fn a() {}\n").unwrap();
+    fs::write(root.join("same.rs"), "fn same() {}\n").unwrap();
+
+    let writer = FileWriter::sandboxed(root, Sandbox::create(run.path()).unwrap());
+    let written = writer.write(Path::new("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    writer.write(Path::new("docs/new.md"), "# New\n").unwrap();
+    writer.write(Path::new("same.rs"), "fn same() {}\n").unwrap();
+    assert_eq!(written, run.path().join("sandbox/lib.rs"));
+    assert_eq!(fs::read_to_string(root.join("lib.rs")).unwrap(), "fn a() {}\n");
+    assert!(!root.join("docs/new.md").exists());
+
+    // A finished run's sandbox is found again from its directory
+    let sandbox = Sandbox::open(run.path()).unwrap();
+    let changes = sandbox.changes(root).unwrap();
+    let kinds: Vec<(PathBuf, ChangeKind)> = changes.iter().map(|c| (c.path.clone(), c.kind)).collect();
+    assert_eq!(kinds, vec![(PathBuf::from("docs/new.md"), ChangeKind::Added), (PathBuf::from("lib.rs"), ChangeKind::Modified)]);
+    assert!(changes[1].patch.starts_with("diff --git a/lib.rs b/lib.rs\n--- a/lib.rs\n+++ b/lib.rs\n@@"));
+    assert!(changes[1].patch.contains("+fn b() {}"));
+    assert_eq!(diffstat(&changes), "A docs/new.md (+1 -0)\nM lib.rs (+1 -0)\n");
+
+    let applied = sandbox.apply(root, false).unwrap();
+    assert_eq!(applied.len(), 2);
+    assert_eq!(fs::read_to_string(root.join("lib.rs")).unwrap(), "fn a() {}\nfn b() {}\n");
+    assert_eq!(fs::read_to_string(root.join("docs/new.md")).unwrap(), "# New\n");
+    assert!(sandbox.apply(root, false).unwrap().is_empty());
+}
+
+#[test]
+fn test_apply_refuses_files_edited_since_the_run() {
+    let project = tempfile::tempdir().unwrap();
+    let run = tempfile::tempdir().unwrap();
+    let root = project.path();
+    fs::write(root.join("lib.rs"), "fn a() {}\n").unwrap();
+    let writer = FileWriter::sandboxed(root, Sandbox::create(run.path()).unwrap());
+    writer.write(Path::new("lib.rs"), "fn b() {}\n").unwrap();
+
+    fs::write(root.join("lib.rs"), "fn edited() {}\n").unwrap();
+    let sandbox = Sandbox::open(run.path()).unwrap();
+    let err = sandbox.apply(root, false).unwrap_err().to_string();
+    assert!(err.contains("lib.rs") && err.contains("--force"), "{}", err);
+    assert_eq!(fs::read_to_string(root.join("lib.rs")).unwrap(), "fn edited() {}\n");
+
+    sandbox.apply(root, true).unwrap();
+    assert_eq!(fs::read_to_string(root.join("lib.rs")).unwrap(), "fn b() {}\n");
+
+    let plain = tempfile::tempdir().unwrap();
+    assert!(Sandbox::open(plain.path()).unwrap_err().to_string().contains("--sandbox"));
+}
+
+#[test]
+fn test_sandbox_and_apply_arguments_parse() {
+    let args = CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:scaffold", "--sandbox", "--out", "notes.md"]).unwrap();
+    match args.command {
+        Some(Command::Pipeline { sandbox, out, .. }) => {
+            assert!(sandbox);
+            assert_eq!(out, Some(PathBuf::from("notes.md")));
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+    let args = CliArgs::try_parse_from(["ai-cli", "apply", "last", "--diff"]).unwrap();
+    match args.command {
+        Some(Command::Apply { id, diff, force }) => {
+            assert_eq!(id, "last");
+            assert!(diff);
+            assert!(!force);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+    assert!(CliArgs::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--sandbox", "--input-file", "in.jsonl"]).is_err());
+}
+
+#[test]
+fn test_manifest_remembers_where_the_run_wrote() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = ai_cli::config::Config::default();
+    let manifest = RunManifest::new("pipeline", &[], Default::default(), &config).with_outputs(Some(PathBuf::from("out.md")), true);
+    manifest.save(dir.path()).unwrap();
+    let loaded = RunManifest::load(dir.path()).unwrap();
+    assert_eq!(loaded.out, Some(PathBuf::from("out.md")));
+    assert!(loaded.sandbox);
+
+    let plain = RunManifest::new("pipeline", &[], Default::default(), &config);
+    assert!(!serde_json::to_string(&plain).unwrap().contains("sandbox"));
+}
+
+#[tokio::test]
+async fn test_scaffolding_step_writes_its_files_into_the_sandbox() {
+    let project = tempfile::tempdir().unwrap();
+    let run = tempfile::tempdir().unwrap();
+    let root = project.path();
+    let reply = "Scaffolded.\n\nFILE: Cargo.toml\n```toml\n[package]\nname = \"demo\"\n```\n\nFILE: src/main.rs\n```rust\nfn main() {}\n```\n";
+    let mut executor = PipelineExecutor::new();
+    executor.register_provider("claude", Arc::new(MockProvider::new("claude").with_reply(reply)));
+    executor.set_file_writer(FileWriter::sandboxed(root, Sandbox::create(run.path()).unwrap()));
+
+    let steps = vec![PipelineStep::new("claude", "scaffold a CLI").with_transform(Arc::new(MultiFileWriterTransform::new()))];
+    let responses = executor.execute(&steps, Context::new()).await.unwrap();
+    assert_eq!(responses[0].metadata["files_written"], "Cargo.toml, src/main.rs");
+    assert!(!root.join("src/main.rs").exists());
+    assert_eq!(fs::read_to_string(run.path().join("sandbox/src/main.rs")).unwrap(), "fn main() {}\n");
+
+    let sandbox = Sandbox::open(run.path()).unwrap();
+    assert_eq!(diffstat(&sandbox.changes(root).unwrap()), "A Cargo.toml (+2 -0)\nA src/main.rs (+1 -0)\n");
+
+    // Saved pipelines name the transform by its spec
+    let yaml = "name: scaffold\nsteps:\n  - provider: claude\n    action: scaffold a CLI\n    transform: multi_file_writer:demo\n";
+    let steps = PipelineDefinition::from_yaml(yaml).unwrap().to_steps().unwrap();
+    assert_eq!(steps[0].get_transform().unwrap().spec(), "multi_file_writer:demo");
+}
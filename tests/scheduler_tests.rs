@@ -0,0 +1,150 @@
+use ai_cli::config::Config;
+use ai_cli::pipeline::batch::ProviderLimiter;
+use ai_cli::scheduler::{Job, JobQueue, QuotaScheduler, RateLimit};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn limits(entries: &[(&str, Option<u32>, Option<usize>)]) -> HashMap<String, RateLimit> {
+    entries
+        .iter()
+        .map(|(name, rpm, max)| (name.to_string(), RateLimit { requests_per_minute: *rpm, max_concurrent: *max }))
+        .collect()
+}
+
+fn drain_ids(scheduler: &mut QuotaScheduler<u32>, now: Instant) -> Vec<String> {
+    let mut ids = Vec::new();
+    while let Some((id, task)) = scheduler.next_ready(now) {
+        scheduler.complete(&task.provider);
+        ids.push(id);
+    }
+    ids
+}
+
+#[test]
+fn test_equal_priority_jobs_are_interleaved() {
+    let mut scheduler = QuotaScheduler::new(HashMap::new());
+    let mut huge = Job::new("huge", 0);
+    for i in 0..5 {
+        huge = huge.with_task("claude", i);
+    }
+    scheduler.submit(huge);
+    scheduler.submit(Job::new("small", 0).with_task("claude", 100));
+
+    let ids = drain_ids(&mut scheduler, Instant::now());
+    // The small job does not wait behind all of the huge one
+    assert_eq!(&ids[..2], &["huge".to_string(), "small".to_string()]);
+    assert_eq!(ids.len(), 6);
+    assert!(scheduler.is_empty());
+}
+
+#[test]
+fn test_higher_priority_runs_first() {
+    let mut scheduler = QuotaScheduler::new(HashMap::new());
+    scheduler.submit(Job::new("low", 0).with_task("claude", 1).with_task("claude", 2));
+    scheduler.submit(Job::new("high", 10).with_task("claude", 3).with_task("claude", 4));
+
+    let ids = drain_ids(&mut scheduler, Instant::now());
+    assert_eq!(ids, vec!["high", "high", "low", "low"]);
+}
+
+#[test]
+fn test_rate_limited_provider_does_not_block_others() {
+    let now = Instant::now();
+    let mut scheduler = QuotaScheduler::new(limits(&[("claude", Some(1), None)]));
+    scheduler.submit(Job::new("claude-job", 10).with_task("claude", 1).with_task("claude", 2));
+    scheduler.submit(Job::new("gemini-job", 0).with_task("gemini", 3));
+
+    assert_eq!(scheduler.next_ready(now).unwrap().0, "claude-job");
+    // Claude's bucket is empty; the gemini job proceeds instead of waiting
+    assert_eq!(scheduler.next_ready(now).unwrap().0, "gemini-job");
+    assert!(scheduler.next_ready(now).is_none());
+
+    let wait = scheduler.next_wakeup(now).unwrap();
+    assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+    let (id, task) = scheduler.next_ready(now + Duration::from_secs(60)).unwrap();
+    assert_eq!((id.as_str(), task.payload), ("claude-job", 2));
+}
+
+#[test]
+fn test_concurrency_limit_released_on_complete() {
+    let now = Instant::now();
+    let mut scheduler = QuotaScheduler::new(limits(&[("codex", None, Some(1))]));
+    scheduler.submit(Job::new("a", 0).with_task("codex", 1).with_task("codex", 2));
+
+    let (_, first) = scheduler.next_ready(now).unwrap();
+    assert!(scheduler.next_ready(now).is_none());
+    assert!(scheduler.next_wakeup(now).is_none());
+
+    scheduler.complete(&first.provider);
+    assert_eq!(scheduler.next_ready(now).unwrap().1.payload, 2);
+}
+
+#[tokio::test]
+async fn test_run_executes_all_tasks() {
+    let mut scheduler = QuotaScheduler::new(limits(&[("claude", None, Some(2))]));
+    scheduler.submit(Job::new("a", 0).with_task("claude", 1).with_task("gemini", 2));
+    scheduler.submit(Job::new("b", 0).with_task("claude", 3));
+    assert_eq!(scheduler.pending(), 3);
+
+    let mut results = scheduler.run(|job, n| async move { format!("{}:{}", job, n * 10) }).await;
+    results.sort();
+    let values: Vec<String> = results.into_iter().map(|(_, r)| r).collect();
+    assert_eq!(values, vec!["a:10", "a:20", "b:30"]);
+}
+
+#[test]
+fn test_rate_limits_from_config() {
+    let config = Config::from_toml("[rate_limits.claude]\nrequests_per_minute = 50\nmax_concurrent = 4\n").unwrap();
+    assert_eq!(
+        config.rate_limits.get("claude"),
+        Some(&RateLimit { requests_per_minute: Some(50), max_concurrent: Some(4) })
+    );
+}
+
+#[tokio::test]
+async fn test_zero_concurrency_still_lets_one_call_through() {
+    let limiter = ProviderLimiter::new(&RateLimit { requests_per_minute: Some(0), max_concurrent: Some(0) });
+    let permit = tokio::time::timeout(std::time::Duration::from_secs(1), limiter.acquire()).await.unwrap();
+    assert!(permit.is_some());
+}
+
+#[tokio::test]
+async fn test_job_queue_takes_jobs_while_running() {
+    let queue = Arc::new(JobQueue::new(limits(&[("claude", None, Some(1))])));
+    let mut huge = Job::new("huge", 0);
+    for i in 0..4 {
+        huge = huge.with_task("claude", i);
+    }
+    queue.submit(huge);
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let seen = order.clone();
+    let runner = queue.clone();
+    tokio::spawn(async move {
+        runner
+            .run(4, move |job, n| {
+                let seen = seen.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    seen.lock().unwrap().push(format!("{}:{}", job, n));
+                }
+            })
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    queue.submit(Job::new("urgent", 10).with_task("claude", 100));
+    queue.submit(Job::new("other", 0).with_task("gemini", 200));
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while order.lock().unwrap().len() < 6 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let order = order.lock().unwrap().clone();
+    // Gemini is not held up by claude's single slot, and the urgent job jumps the huge one
+    assert_eq!(&order[..3], &["huge:0", "other:200", "urgent:100"]);
+    assert_eq!(queue.pending(), 0);
+}
@@ -0,0 +1,223 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineDefinition, PipelineExecutor, PipelineStore};
+use ai_cli::providers::AIProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::server::{self, ServerState, router};
+use clap::Parser;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Serve `state` on an ephemeral port and return its base URL
+async fn spawn(state: ServerState) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+fn executor(providers: Vec<MockProvider>) -> PipelineExecutor {
+    let executor = PipelineExecutor::new();
+    for provider in providers {
+        executor.register_provider(provider.name().to_string(), Arc::new(provider));
+    }
+    executor
+}
+
+#[tokio::test]
+async fn test_execute_returns_json() {
+    let base = spawn(ServerState::new(executor(vec![MockProvider::new("claude").with_reply("Hi there")]))).await;
+    let client = reqwest::Client::new();
+
+    let health: Value = client.get(format!("{}/health", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(health["status"], "ok");
+    let providers: Value = client.get(format!("{}/v1/providers", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(providers, json!(["claude"]));
+
+    let response = client
+        .post(format!("{}/v1/execute", base))
+        .json(&json!({"provider": "claude", "prompt": "Say hi", "options": {"temperature": 0.2}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["content"], "Hi there");
+    assert_eq!(body["metadata"]["provider"], "claude");
+
+    let response = client
+        .post(format!("{}/v1/execute", base))
+        .json(&json!({"provider": "gpt", "prompt": "Say hi"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("Unknown provider: 'gpt'"));
+}
+
+#[tokio::test]
+async fn test_execute_failure_is_bad_gateway() {
+    let base = spawn(ServerState::new(executor(vec![MockProvider::new("claude").with_error("overloaded")]))).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/execute", base))
+        .json(&json!({"provider": "claude", "prompt": "Say hi"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("overloaded"));
+}
+
+#[tokio::test]
+async fn test_run_saved_pipeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = PipelineStore::new(dir.path());
+    let mut def = PipelineDefinition::from_chain("translate", "claude:Translate {{env.INPUT}} to {{env.LANG}} -> gemini:polish").unwrap();
+    def.description = Some("Translate and polish".to_string());
+    store.save(&def).unwrap();
+
+    let state = ServerState::new(executor(vec![MockProvider::new("claude"), MockProvider::new("gemini")]))
+        .with_store(PipelineStore::new(dir.path()));
+    let base = spawn(state).await;
+    let client = reqwest::Client::new();
+
+    let listed: Value = client.get(format!("{}/v1/pipelines", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed, json!([{"name": "translate", "description": "Translate and polish", "chain": def.to_chain()}]));
+
+    let body: Value = client
+        .post(format!("{}/v1/pipelines/translate/run", base))
+        .json(&json!({"input": "hello", "env": {"LANG": "French"}}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["pipeline"], "translate");
+    assert_eq!(body["responses"][0]["content"], "Translate hello to French");
+    assert_eq!(body["responses"][1]["provider"], "gemini");
+
+    let missing = client.post(format!("{}/v1/pipelines/nope/run", base)).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn test_run_streams_step_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = PipelineStore::new(dir.path());
+    store.save(&PipelineDefinition::from_chain("review", "claude:draft -> gemini:review").unwrap()).unwrap();
+    let state = ServerState::new(executor(vec![
+        MockProvider::new("claude").with_reply("first draft"),
+        MockProvider::new("gemini").with_error("quota exceeded"),
+    ]))
+    .with_store(store);
+    let base = spawn(state).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/pipelines/review/run", base))
+        .json(&json!({"stream": true}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let text = response.text().await.unwrap();
+    let events: Vec<&str> = text.lines().filter_map(|l| l.strip_prefix("event: ")).collect();
+    assert_eq!(events, vec!["step", "error"]);
+    assert!(text.contains(r#""content":"first draft""#));
+    assert!(text.contains("quota exceeded"));
+}
+
+#[test]
+fn test_serve_command_parses() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "serve", "--port", "9000", "--host", "0.0.0.0"]).unwrap();
+    match args.command {
+        Some(Command::Serve { port, host, token, jobs }) => {
+            assert_eq!(jobs, 4);
+            assert_eq!(port, 9000);
+            assert_eq!(host.to_string(), "0.0.0.0");
+            assert_eq!(token, None);
+        }
+        _ => panic!("expected serve command"),
+    }
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "serve"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Serve { port: 8080, .. })));
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "serve", "--token", "s3cret"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Serve { token: Some(ref t), .. }) if t == "s3cret"));
+}
+
+#[tokio::test]
+async fn test_token_is_required_when_set() {
+    let state = ServerState::new(executor(vec![MockProvider::new("claude").with_reply("Hi")])).with_token("s3cret");
+    let base = spawn(state).await;
+    let client = reqwest::Client::new();
+
+    assert_eq!(client.get(format!("{}/health", base)).send().await.unwrap().status(), 200);
+    let providers = format!("{}/v1/providers", base);
+    assert_eq!(client.get(&providers).send().await.unwrap().status(), 401);
+    assert_eq!(client.get(&providers).bearer_auth("guess").send().await.unwrap().status(), 401);
+    let response = client.get(&providers).bearer_auth("s3cret").send().await.unwrap();
+    assert_eq!(response.json::<Value>().await.unwrap(), json!(["claude"]));
+}
+
+#[tokio::test]
+async fn test_loopback_server_refuses_other_host_names() {
+    let base = spawn(ServerState::new(executor(vec![]))).await;
+    let client = reqwest::Client::new();
+    let health = format!("{}/health", base);
+
+    let response = client.get(&health).header("Host", "attacker.example").send().await.unwrap();
+    assert_eq!(response.status(), 421);
+    for host in ["localhost:8080", "127.0.0.1", "[::1]:8080"] {
+        assert_eq!(client.get(&health).header("Host", host).send().await.unwrap().status(), 200, "{}", host);
+    }
+
+    // Other machines are only served with a token
+    let error = server::serve("0.0.0.0:0".parse().unwrap(), ServerState::new(executor(vec![]))).await.unwrap_err();
+    assert!(error.to_string().contains("--token"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_queued_jobs_run_in_the_background() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = PipelineStore::new(dir.path());
+    store.save(&PipelineDefinition::from_chain("echo", "claude:Echo {{env.INPUT}} in {{env.LANG}}").unwrap()).unwrap();
+    let state = ServerState::new(executor(vec![MockProvider::new("claude")]))
+        .with_store(PipelineStore::new(dir.path()))
+        .with_job_limits(std::collections::HashMap::new(), 2);
+    let base = spawn(state).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/v1/jobs", base))
+        .json(&json!({"pipeline": "echo", "inputs": ["one", {"id": "b", "input": "two"}], "env": {"LANG": "French"}, "priority": 5}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let submitted: Value = response.json().await.unwrap();
+    assert_eq!(submitted["inputs"], 2);
+    let id = submitted["id"].as_str().unwrap().to_string();
+
+    let status = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let status: Value = client.get(format!("{}/v1/jobs/{}", base, id)).send().await.unwrap().json().await.unwrap();
+            if status["state"] == "done" {
+                return status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(status["pipeline"], "echo");
+    assert_eq!(status["priority"], 5);
+    assert_eq!(status["finished"], 2);
+    assert_eq!(status["results"][0]["output"], "Echo one in French");
+    assert_eq!(status["results"][1]["id"], "b");
+
+    let missing = client.get(format!("{}/v1/jobs/job-99", base)).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+    let empty = client.post(format!("{}/v1/jobs", base)).json(&json!({"pipeline": "echo", "inputs": []})).send().await.unwrap();
+    assert_eq!(empty.status(), 400);
+}
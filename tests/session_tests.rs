@@ -0,0 +1,103 @@
+use ai_cli::context::Provenance;
+use ai_cli::history::session::{Session, SessionSettings, SessionStore};
+use ai_cli::providers::tokenizer::ApproxTokenizer;
+use ai_cli::providers::{Context, Message, MessageRole};
+use tempfile::TempDir;
+
+#[test]
+fn test_session_store_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    assert!(store.list().unwrap().is_empty());
+
+    let mut session = store.load_or_new("work").unwrap();
+    assert!(session.messages.is_empty());
+    session.record("What is 2 + 2?", "4");
+    store.save(&session).unwrap();
+
+    let loaded = store.load("work").unwrap();
+    assert_eq!(loaded, session);
+    assert_eq!(store.load_or_new("work").unwrap().messages.len(), 2);
+
+    store.save(&Session::new("other")).unwrap();
+    let names: Vec<String> = store.list().unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names.len(), 2);
+    assert!(names.contains(&"work".to_string()));
+
+    store.delete("work").unwrap();
+    assert!(store.load("work").unwrap_err().to_string().contains("No session named 'work'"));
+    assert!(store.delete("work").is_err());
+}
+
+#[test]
+fn test_session_names_are_validated() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    assert!(store.load_or_new("../escape").is_err());
+    assert!(store.save(&Session::new("")).is_err());
+}
+
+#[test]
+fn test_session_replays_into_context() {
+    let mut session = Session::new("work");
+    session.record("What is 2 + 2?", "4");
+
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "Scope: package core"));
+    session.apply_to(&mut context);
+    assert_eq!(context.conversation_history.len(), 3);
+    assert_eq!(
+        context.conversation_history[1].provenance,
+        Some(Provenance::Session { name: "work".to_string() })
+    );
+
+    let turns = context.request_turns("And doubled?".to_string());
+    let roles: Vec<MessageRole> = turns.iter().map(|m| m.role.clone()).collect();
+    assert_eq!(roles, vec![MessageRole::User, MessageRole::Assistant, MessageRole::User]);
+    assert_eq!(turns[2].content, "And doubled?");
+
+    session.clear();
+    assert!(session.messages.is_empty());
+}
+
+#[test]
+fn test_request_turns_alternate() {
+    let mut context = Context::new();
+    // Step outputs before any user message are not replayed
+    context.add_message(Message::new(MessageRole::Assistant, "step output"));
+    context.add_message(Message::new(MessageRole::User, "first"));
+    context.add_message(Message::new(MessageRole::User, "second"));
+    context.add_message(Message::new(MessageRole::Assistant, "reply"));
+
+    let turns = context.request_turns("next".to_string());
+    assert_eq!(turns.len(), 3);
+    assert_eq!(turns[0].content, "first\n\nsecond");
+    assert_eq!(turns[2].content, "next");
+    assert_eq!(Context::new().request_turns("only".to_string()).len(), 1);
+}
+
+#[test]
+fn test_session_compaction() {
+    let tokenizer = ApproxTokenizer::default();
+    let mut session = Session::new("long");
+    for i in 0..20 {
+        session.record(&format!("question {} about the parser and its error handling", i), &format!("answer {}\nwith details", i));
+    }
+    let settings = SessionSettings { max_tokens: 150, keep_recent: 4 };
+    let folded = session.compact(&tokenizer, &settings);
+
+    assert!(folded > 0);
+    assert_eq!(folded % 2, 0, "exchanges are folded whole");
+    assert!(session.messages.len() >= settings.keep_recent);
+    assert_eq!(session.messages[0].role, MessageRole::User);
+    assert!(session.count_tokens(&tokenizer) <= settings.max_tokens);
+    assert!(session.summary.last().unwrap().starts_with("assistant: answer"));
+    assert!(session.summary.last().unwrap().ends_with("..."));
+
+    let mut context = Context::new();
+    session.apply_to(&mut context);
+    assert!(context.system_instructions().unwrap().starts_with("Earlier in this conversation"));
+
+    // Nothing to do under budget
+    assert_eq!(session.compact(&tokenizer, &SessionSettings::default()), 0);
+}
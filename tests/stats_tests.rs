@@ -0,0 +1,120 @@
+use ai_cli::history::stats::{GroupBy, StatsReport, TimeRange, parse_time_bound};
+use ai_cli::history::{RunRecord, RunStatus, RunStore, StepRecord};
+use ai_cli::pipeline::PipelineStep;
+use ai_cli::providers::Response;
+use ai_cli::providers::pricing::Usage;
+
+const NOW: u64 = 1_792_000_000;
+
+fn step(provider: &str, model: &str, latency_ms: u64, tokens: (u64, u64), cost: f64) -> StepRecord {
+    StepRecord {
+        provider: provider.to_string(),
+        model: Some(model.to_string()),
+        usage: Some(Usage::new(tokens.0, tokens.1)),
+        cost_usd: Some(cost),
+        latency_ms: Some(latency_ms),
+        error: false,
+    }
+}
+
+fn run(id: &str, started_at: u64, steps: Vec<StepRecord>) -> RunRecord {
+    RunRecord {
+        id: id.to_string(),
+        command: "pipeline".to_string(),
+        chain: String::new(),
+        started_at,
+        finished_at: Some(started_at + 5),
+        status: RunStatus::Succeeded,
+        error: None,
+        usage: None,
+        cost_usd: None,
+        steps,
+    }
+}
+
+fn history() -> Vec<RunRecord> {
+    vec![
+        run("old", NOW - 40 * 86_400, vec![step("claude", "claude-sonnet-4", 9_000, (10, 10), 1.0)]),
+        run("a", NOW - 3_600, vec![
+            step("claude", "claude-sonnet-4", 100, (1000, 200), 0.006),
+            step("gemini", "gemini-2.5-flash", 300, (500, 100), 0.0004),
+        ]),
+        run("b", NOW - 60, vec![
+            step("claude", "claude-sonnet-4", 200, (2000, 400), 0.012),
+            step("claude", "claude-3-5-haiku", 50, (100, 10), 0.0001),
+            StepRecord::failed("gemini"),
+        ]),
+    ]
+}
+
+#[test]
+fn test_parse_time_bounds() {
+    assert_eq!(parse_time_bound("24h", NOW).unwrap(), NOW - 86_400);
+    assert_eq!(parse_time_bound("2w", NOW).unwrap(), NOW - 14 * 86_400);
+    assert_eq!(parse_time_bound("30m", NOW).unwrap(), NOW - 1_800);
+    assert_eq!(parse_time_bound("2026-10-15", NOW).unwrap(), 20_741 * 86_400);
+    assert_eq!(parse_time_bound("2024-03-01", NOW).unwrap(), 19_783 * 86_400);
+    assert!(parse_time_bound("yesterday", NOW).is_err());
+    assert!(parse_time_bound("2026-13-01", NOW).is_err());
+    assert!(TimeRange::parse(Some("1d"), Some("7d"), NOW).is_err());
+}
+
+#[test]
+fn test_aggregate_by_model_within_range() {
+    let range = TimeRange::parse(Some("7d"), None, NOW).unwrap();
+    let report = StatsReport::aggregate(&history(), range, GroupBy::Model);
+    assert_eq!(report.runs, 2);
+
+    let sonnet = report.groups.iter().find(|g| g.model.as_deref() == Some("claude-sonnet-4")).unwrap();
+    assert_eq!(sonnet.requests, 2);
+    assert_eq!((sonnet.prompt_tokens, sonnet.completion_tokens), (3000, 600));
+    assert!((sonnet.cost_usd - 0.018).abs() < 1e-9);
+    assert_eq!(sonnet.latency_p50_ms, Some(100));
+    assert_eq!(sonnet.latency_p99_ms, Some(200));
+
+    let failed = report.groups.iter().find(|g| g.provider == "gemini" && g.model.as_deref() == Some("unknown")).unwrap();
+    assert_eq!((failed.requests, failed.errors), (1, 1));
+    assert_eq!(failed.error_rate, 1.0);
+    assert_eq!(failed.latency_p50_ms, None);
+}
+
+#[test]
+fn test_aggregate_by_provider_and_render() {
+    let report = StatsReport::aggregate(&history(), TimeRange::default(), GroupBy::Provider);
+    assert_eq!(report.runs, 3);
+    let providers: Vec<(&str, usize, usize)> = report.groups.iter().map(|g| (g.provider.as_str(), g.requests, g.errors)).collect();
+    assert_eq!(providers, vec![("claude", 4, 0), ("gemini", 2, 1)]);
+
+    let table = report.to_string();
+    assert!(table.starts_with("PROVIDER"));
+    assert!(table.contains("gemini     -"));
+    assert!(table.contains("50.0%"));
+    assert!(table.ends_with("3 run(s), 6 request(s), $1.0185 total"));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["groups"][0]["provider"], "claude");
+    assert_eq!(json["groups"][1]["error_rate"], 0.5);
+
+    let empty = StatsReport::aggregate(&[], TimeRange::default(), GroupBy::Model);
+    assert_eq!(empty.to_string(), "No provider calls recorded in 0 run(s).");
+}
+
+#[test]
+fn test_runs_record_step_outcomes() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = RunStore::new(dir.path());
+    let mut run = store.create("pipeline", "claude:a -> gemini:b").unwrap();
+    let response = Response::new("ok")
+        .with_usage(Usage::new(10, 5))
+        .with_metadata("model", "claude-sonnet-4")
+        .with_metadata("latency_ms", "120");
+    run.record_step(0, &PipelineStep::new("claude", "a"), &response).unwrap();
+    run.record_failed_step("gemini");
+    run.finish(Some("step 2 failed".to_string())).unwrap();
+
+    let record = store.list().unwrap().remove(0);
+    assert_eq!(record.steps.len(), 2);
+    assert_eq!(record.steps[0].latency_ms, Some(120));
+    assert_eq!(record.steps[0].usage, Some(Usage::new(10, 5)));
+    assert!(record.steps[1].error);
+}
@@ -0,0 +1,89 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::gate::edit_with;
+use ai_cli::pipeline::{EditorGate, PipelineExecutor, PipelineStep, Review};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::{Context, Response};
+use clap::Parser;
+use std::sync::{Arc, Mutex};
+
+fn executor(claude: Arc<MockProvider>, gemini: Arc<MockProvider>) -> PipelineExecutor {
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", claude);
+    executor.register_provider("gemini", gemini);
+    executor
+}
+
+fn steps() -> Vec<PipelineStep> {
+    vec![PipelineStep::new("claude", "design"), PipelineStep::new("gemini", "implement")]
+}
+
+#[tokio::test]
+async fn test_edit_replaces_output_before_next_step() {
+    let claude = Arc::new(MockProvider::new("claude").with_reply("draft design"));
+    let gemini = Arc::new(MockProvider::new("gemini").with_reply("code"));
+    let mut executor = executor(claude, gemini);
+    let reviewed = Arc::new(Mutex::new(Vec::new()));
+    let seen = reviewed.clone();
+    executor.set_gate(Arc::new(move |index: usize, _: &PipelineStep, response: &Response| {
+        seen.lock().unwrap().push(index);
+        if index == 0 { Review::Edit(response.content.replace("draft", "final")) } else { Review::Accept }
+    }));
+
+    let (responses, context) = executor.execute_with_context(&steps(), Context::new()).await.unwrap();
+    assert_eq!(*reviewed.lock().unwrap(), vec![0, 1]);
+    assert_eq!(responses[0].content, "final design");
+    assert_eq!(responses[0].metadata["edited"], "true");
+    assert_eq!(context.conversation_history[0].content, "final design");
+}
+
+#[tokio::test]
+async fn test_retry_adds_tweak_and_abort_stops() {
+    let claude = Arc::new(MockProvider::new("claude").with_reply("too long").with_reply("short"));
+    let gemini = Arc::new(MockProvider::new("gemini").with_reply("code"));
+    let mut executor = executor(claude.clone(), gemini.clone());
+    executor.set_gate(Arc::new(|index: usize, _: &PipelineStep, response: &Response| match (index, response.content.as_str()) {
+        (0, "too long") => Review::Retry("Keep it short.".into()),
+        (0, _) => Review::Accept,
+        _ => Review::Abort,
+    }));
+
+    let err = executor.execute(&steps(), Context::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "Pipeline aborted after step 2");
+    let prompts = claude.prompts();
+    assert_eq!(prompts.len(), 2);
+    assert!(prompts[1].contains("design\n\nKeep it short."), "{}", prompts[1]);
+    assert_eq!(gemini.prompts().len(), 1);
+}
+
+#[test]
+fn test_edit_with_editor_command() {
+    assert_eq!(edit_with("sed -i s/draft/final/", "draft design\n").unwrap(), "final design\n");
+    assert!(edit_with("false", "text").unwrap_err().to_string().contains("exited with"));
+}
+
+#[tokio::test]
+async fn test_editor_gate_edits_all_but_last_step() {
+    let claude = Arc::new(MockProvider::new("claude").with_reply("draft design"));
+    let gemini = Arc::new(MockProvider::new("gemini").with_reply("draft code"));
+    let mut executor = executor(claude, gemini);
+    executor.set_gate(Arc::new(EditorGate::new(2).with_editor("sed -i s/draft/final/")));
+
+    let responses = executor.execute(&steps(), Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, "final design");
+    assert_eq!(responses[1].content, "draft code");
+
+    executor.set_gate(Arc::new(EditorGate::new(2).with_editor("false")));
+    assert!(executor.execute(&steps(), Context::new()).await.is_err());
+}
+
+#[test]
+fn test_confirm_each_step_flag() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--confirm-each-step"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Pipeline { confirm_each_step: true, .. })));
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "run", "review", "--confirm-each-step"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Run { confirm_each_step: true, .. })));
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--tui", "--confirm-each-step"]).is_err());
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--edit-before-next"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Pipeline { edit_before_next: true, confirm_each_step: false, .. })));
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "run", "x", "--edit-before-next", "--confirm-each-step"]).is_err());
+}
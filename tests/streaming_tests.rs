@@ -0,0 +1,172 @@
+use ai_cli::pipeline::{ExecutionConfig, PipelineExecutor, PipelineStep};
+use ai_cli::providers::streaming::{ByteStream, JsonAccumulator, ReconnectPolicy, SseParser, sse_events};
+use ai_cli::providers::{AIProvider, Capabilities, Context, Response, ResponseStream};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::{StreamExt, stream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn body(chunks: Vec<anyhow::Result<&'static str>>) -> ByteStream {
+    stream::iter(chunks.into_iter().map(|c| c.map(|s| s.as_bytes().to_vec()))).boxed()
+}
+
+fn policy(resumable: bool) -> ReconnectPolicy {
+    ReconnectPolicy { max_reconnects: 2, resumable, delay: Duration::ZERO }
+}
+
+#[test]
+fn test_parser_reassembles_events_split_across_chunks() {
+    let mut parser = SseParser::new();
+    assert!(parser.feed(b"event: delta\r\nda").is_empty());
+    assert!(parser.feed(b"ta: hel").is_empty());
+    assert!(parser.has_partial());
+
+    let events = parser.feed(b"lo\r\n\r\n: keep-alive\n\ndata: a\ndata:b\n\n");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event.as_deref(), Some("delta"));
+    assert_eq!(events[0].data, "hello");
+    assert_eq!(events[1].event, None);
+    assert_eq!(events[1].data, "a\nb");
+    assert!(!parser.has_partial());
+}
+
+#[test]
+fn test_parser_handles_split_utf8_and_crlf() {
+    let mut parser = SseParser::new();
+    let text = "data: héllo\r\n\r\n".as_bytes();
+    // Split inside the two-byte 'é' and between CR and LF
+    assert!(parser.feed(&text[..8]).is_empty());
+    assert!(parser.feed(&text[8..14]).is_empty());
+    let events = parser.feed(&text[14..]);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].data, "héllo");
+}
+
+#[test]
+fn test_parser_tracks_id_and_retry() {
+    let mut parser = SseParser::new();
+    let events = parser.feed(b"id: 7\nretry: 1500\ndata: x\n\n");
+    assert_eq!(events[0].id.as_deref(), Some("7"));
+    assert_eq!(parser.last_event_id(), Some("7"));
+    assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+}
+
+#[test]
+fn test_json_accumulator() {
+    let mut acc = JsonAccumulator::new();
+    acc.push(1, "{\"path\": \"src/");
+    acc.push(1, "main.rs\"}");
+    acc.push(2, "{\"broken\": ");
+    assert_eq!(acc.finish(1).unwrap()["path"], "src/main.rs");
+    assert!(acc.finish(2).is_err());
+    assert_eq!(acc.finish(3).unwrap(), serde_json::json!({}));
+    assert!(acc.is_empty());
+}
+
+#[tokio::test]
+async fn test_reconnects_when_failing_before_first_event() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let connect = move |_: Option<String>| {
+        let attempt = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            Ok(if attempt == 0 {
+                body(vec![Ok("data: par"), Err(anyhow!("connection reset"))])
+            } else {
+                body(vec![Ok("data: one\n\ndata: two\n\n")])
+            })
+        }
+    };
+
+    let events: Vec<_> = sse_events(connect, policy(false)).collect().await;
+    let data: Vec<String> = events.into_iter().map(|e| e.unwrap().data).collect();
+    assert_eq!(data, vec!["one", "two"]);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_non_resumable_stream_errors_after_output() {
+    let connect = |_: Option<String>| async {
+        Ok(body(vec![Ok("data: one\n\ndata: tw"), Err(anyhow!("connection reset"))]))
+    };
+
+    let events: Vec<_> = sse_events(connect, policy(false)).collect().await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].as_ref().unwrap().data, "one");
+    assert!(events[1].as_ref().unwrap_err().to_string().contains("connection reset"));
+}
+
+#[tokio::test]
+async fn test_truncated_stream_is_an_error() {
+    let connect = |_: Option<String>| async { Ok(body(vec![Ok("data: one\n\ndata: cut")])) };
+    let events: Vec<_> = sse_events(connect, policy(false)).collect().await;
+    assert_eq!(events.len(), 2);
+    assert!(events[1].as_ref().unwrap_err().to_string().contains("middle of an event"));
+}
+
+#[tokio::test]
+async fn test_resumable_stream_continues_from_last_event_id() {
+    let resumed_from = Arc::new(Mutex::new(Vec::new()));
+    let seen = resumed_from.clone();
+    let connect = move |last_id: Option<String>| {
+        let first = last_id.is_none();
+        seen.lock().unwrap().push(last_id);
+        async move {
+            Ok(if first {
+                body(vec![Ok("id: 1\ndata: one\n\nid: 2\ndata: tw"), Err(anyhow!("timeout"))])
+            } else {
+                body(vec![Ok("id: 2\ndata: two\n\n")])
+            })
+        }
+    };
+
+    let events: Vec<_> = sse_events(connect, policy(true)).collect().await;
+    let data: Vec<String> = events.into_iter().map(|e| e.unwrap().data).collect();
+    assert_eq!(data, vec!["one", "two"]);
+    assert_eq!(*resumed_from.lock().unwrap(), vec![None, Some("1".to_string())]);
+}
+
+// Streams half a response and then fails on its first call
+struct FlakyStreamProvider {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl AIProvider for FlakyStreamProvider {
+    async fn execute(&self, _prompt: &str, _context: &Context) -> anyhow::Result<Response> {
+        Err(anyhow!("execute should not be used when streaming"))
+    }
+
+    async fn stream(&self, _prompt: &str, _context: &Context) -> anyhow::Result<ResponseStream> {
+        let chunks = if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            vec![Ok("partial ".to_string()), Err(anyhow!("Stream interrupted: connection reset"))]
+        } else {
+            vec![Ok("complete ".to_string()), Ok("answer".to_string())]
+        };
+        Ok(Box::pin(stream::iter(chunks)))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { supports_streaming: true, ..Capabilities::default() }
+    }
+
+    fn name(&self) -> &str {
+        "flaky"
+    }
+}
+
+#[tokio::test]
+async fn test_interrupted_stream_is_retried_without_partial_output() {
+    let executor = PipelineExecutor::new();
+    executor.set_config(ExecutionConfig { max_retries: 1, retry_delay_ms: 0, ..ExecutionConfig::default() });
+    executor.register_provider("flaky", Arc::new(FlakyStreamProvider { calls: AtomicUsize::new(0) }));
+
+    let steps = vec![PipelineStep::new("flaky", "answer")];
+    let results = executor.execute_streaming(&steps, Context::new()).await.unwrap();
+
+    assert!(results[0].content.ends_with("complete answer"));
+    assert!(!results[0].content.contains("partial"));
+    assert_eq!(results[0].metadata.get("retries").map(String::as_str), Some("1"));
+}
@@ -0,0 +1,129 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineDefinition, PipelineExecutor, PipelineStep};
+use ai_cli::providers::claude::ClaudeProvider;
+use ai_cli::providers::gemini::GeminiProvider;
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::testing::FakeTransport;
+use ai_cli::providers::{AIProvider, Context, JsonMode, ProviderOptions};
+use clap::Parser;
+use serde_json::json;
+use std::sync::Arc;
+
+fn person_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" }, "age": { "type": "integer" } },
+        "required": ["name"],
+    })
+}
+
+fn json_options(retries: u32) -> ProviderOptions {
+    ProviderOptions { json: Some(JsonMode::new(person_schema()).with_retries(retries)), ..ProviderOptions::default() }
+}
+
+#[test]
+fn test_json_mode_validation() {
+    let mode = JsonMode::new(person_schema());
+    assert_eq!(mode.validate("```json\n{\"name\": \"Ada\"}\n```").unwrap(), json!({"name": "Ada"}));
+    assert!(mode.validate("Sure! Here it is").unwrap_err().contains("not valid JSON"));
+    let reason = mode.validate(r#"{"age": "old"}"#).unwrap_err();
+    assert!(reason.contains("\"name\" is a required property"), "{}", reason);
+    assert!(reason.contains("at '/age'"), "{}", reason);
+
+    assert!(JsonMode::new(json!({"type": "widget"})).check_schema().is_err());
+}
+
+#[tokio::test]
+async fn test_invalid_replies_retried_with_feedback() {
+    let mock = Arc::new(MockProvider::new("claude").with_reply("not json").with_reply(r#"{"age": 3}"#).with_reply(r#"{"name": "Ada"}"#));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", mock.clone());
+
+    let step = PipelineStep::new("claude", "Who wrote the first program?").with_options(json_options(2));
+    let responses = executor.execute(&[step], Context::new()).await.unwrap();
+    assert_eq!(responses[0].content, r#"{"name":"Ada"}"#);
+    assert_eq!(responses[0].metadata["json_retries"], "2");
+
+    let prompts = mock.prompts();
+    assert_eq!(prompts.len(), 3);
+    assert!(prompts[1].starts_with("Who wrote the first program?"));
+    assert!(prompts[1].contains("rejected because the reply is not valid JSON"));
+    assert!(prompts[2].contains("\"name\" is a required property"));
+}
+
+#[tokio::test]
+async fn test_invalid_replies_fail_after_retries() {
+    let mock = Arc::new(MockProvider::new("claude").with_reply("nope").with_reply("still nope"));
+    let executor = PipelineExecutor::new();
+    executor.register_provider("claude", mock.clone());
+
+    let step = PipelineStep::new("claude", "Name someone").with_options(json_options(1));
+    let err = executor.execute(&[step], Context::new()).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("does not match the JSON schema after 2 attempts"));
+    assert_eq!(mock.prompts().len(), 2);
+}
+
+#[tokio::test]
+async fn test_claude_forces_json_tool() {
+    let transport = Arc::new(FakeTransport::new().with_json(json!({
+        "content": [{"type": "tool_use", "id": "toolu_1", "name": "respond", "input": {"name": "Ada", "age": 36}}],
+        "stop_reason": "tool_use",
+        "usage": {"input_tokens": 12, "output_tokens": 9}
+    })));
+    let provider = ClaudeProvider::new("key".to_string()).with_transport(transport.clone());
+
+    let response = provider.execute_with_options("Describe Ada", &Context::new(), &json_options(0)).await.unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&response.content).unwrap(), json!({"name": "Ada", "age": 36}));
+
+    let body = transport.requests()[0].json().unwrap();
+    assert_eq!(body["tool_choice"], json!({"type": "tool", "name": "respond"}));
+    assert_eq!(body["tools"][0]["input_schema"], person_schema());
+}
+
+#[tokio::test]
+async fn test_gemini_requests_json_response() {
+    let transport = Arc::new(FakeTransport::new().with_json(json!({
+        "candidates": [{"content": {"parts": [{"text": "{\"name\": \"Ada\"}"}]}}]
+    })));
+    let provider = GeminiProvider::new("key".to_string()).with_transport(transport.clone());
+
+    provider.execute_with_options("Describe Ada", &Context::new(), &json_options(0)).await.unwrap();
+    let body = transport.requests()[0].json().unwrap();
+    assert_eq!(body["generationConfig"]["responseMimeType"], "application/json");
+    assert_eq!(body["generationConfig"]["responseJsonSchema"], person_schema());
+}
+
+#[test]
+fn test_json_schema_flag_and_step_option() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("person.json");
+    std::fs::write(&path, person_schema().to_string()).unwrap();
+
+    let args = <CliArgs as Parser>::try_parse_from([
+        "ai-cli", "execute", "-p", "claude", "-P", "Describe Ada", "--json-schema", path.to_str().unwrap(), "--json-retries", "4",
+    ])
+    .unwrap();
+    match args.command {
+        Some(Command::Execute { generation, .. }) => {
+            let json = generation.to_options().json.unwrap();
+            assert_eq!((json.schema, json.retries), (person_schema(), 4));
+        }
+        _ => panic!("expected execute command"),
+    }
+    let missing = <CliArgs as Parser>::try_parse_from(["ai-cli", "execute", "-p", "claude", "-P", "x", "--json-schema", "nope.json"]);
+    assert!(missing.unwrap_err().to_string().contains("Failed to read nope.json"));
+
+    let yaml = r#"
+name: people
+steps:
+  - provider: gemini
+    action: Describe Ada
+    options:
+      json:
+        schema: { type: object, required: [name] }
+"#;
+    let def: PipelineDefinition = serde_yaml::from_str(yaml).unwrap();
+    let json = def.to_steps().unwrap()[0].options().json.clone().unwrap();
+    assert_eq!(json.retries, 2);
+    assert_eq!(json.schema["required"], json!(["name"]));
+}
@@ -0,0 +1,117 @@
+use ai_cli::providers::tokenizer::{ApproxTokenizer, BpeTokenizer, MAX_PIECE_BYTES, Tokenizer, pre_tokenize, tokenizer_for};
+use ai_cli::providers::{Context, Message, MessageRole};
+use base64::Engine;
+use std::path::PathBuf;
+
+/// Rank file text for the given tokens, ranked in order
+fn ranks(tokens: &[&str]) -> String {
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(rank, token)| format!("{} {}\n", base64::engine::general_purpose::STANDARD.encode(token), rank))
+        .collect()
+}
+
+#[test]
+fn test_pre_tokenize_matches_cl100k_splits() {
+    assert_eq!(pre_tokenize("Hello world"), vec!["Hello", " world"]);
+    assert_eq!(pre_tokenize("I'll pay 12345!"), vec!["I", "'ll", " pay", " ", "123", "45", "!"]);
+    assert_eq!(pre_tokenize("fn main() {\n    x\n}"), vec!["fn", " main", "()", " {\n", "   ", " x", "\n", "}"]);
+    assert_eq!(pre_tokenize("a  \n\nb"), vec!["a", "  \n\n", "b"]);
+    assert_eq!(pre_tokenize("trailing   "), vec!["trailing", "   "]);
+    assert_eq!(pre_tokenize("日本語 テキスト"), vec!["日本語", " テキスト"]);
+    assert!(pre_tokenize("").is_empty());
+}
+
+#[test]
+fn test_bpe_merges_lowest_rank_first() {
+    let tokenizer = BpeTokenizer::parse("test", &ranks(&["h", "e", "l", "o", " ", "ll", "he", "hell"])).unwrap();
+    assert_eq!(tokenizer.name(), "test");
+    // h e l l o -> h e ll o -> he ll o -> hell o
+    assert_eq!(tokenizer.count("hello"), 2);
+    assert_eq!(tokenizer.count("hello hello"), 5);
+    assert_eq!(tokenizer.count("hell"), 1);
+    assert_eq!(tokenizer.count(""), 0);
+}
+
+#[test]
+fn test_bpe_long_pieces_stay_fast() {
+    let tokenizer = BpeTokenizer::parse("test", &ranks(&["a", "b", "ab", "abab", "abababab"])).unwrap();
+    // Ties go to the leftmost pair: ab ab ab a -> abab ab a
+    assert_eq!(tokenizer.count("abababa"), 3);
+    let blob = "ab".repeat(200_000);
+    let started = std::time::Instant::now();
+    assert_eq!(tokenizer.count(&blob), blob.len() / 8);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5), "{:?}", started.elapsed());
+    assert_eq!(MAX_PIECE_BYTES % 8, 0);
+
+    let cut = tokenizer.truncate(&blob, 10);
+    assert!(tokenizer.count(cut) <= 10 && cut.len() > 64, "{}", cut.len());
+}
+
+#[test]
+fn test_bpe_rank_file_errors() {
+    assert!(BpeTokenizer::parse("x", "").is_err());
+    let err = BpeTokenizer::parse("x", "aGk= one\n").unwrap_err();
+    assert!(err.to_string().contains("line 1: invalid rank"));
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("cl100k_base.tiktoken");
+    std::fs::write(&path, ranks(&["a", "b", "ab"])).unwrap();
+    let tokenizer = BpeTokenizer::from_file(&path).unwrap();
+    assert_eq!(tokenizer.name(), "cl100k_base");
+    assert_eq!(tokenizer.count("abab"), 2);
+}
+
+#[test]
+fn test_approx_tokenizers_per_provider() {
+    assert_eq!(tokenizer_for("claude").name(), "claude-approx");
+    assert_eq!(tokenizer_for("gemini").name(), "gemini-approx");
+    assert_eq!(tokenizer_for("codex").name(), "approx");
+
+    let approx = ApproxTokenizer::default();
+    assert_eq!(approx.count("the cat sat"), 3);
+    // Non-ASCII characters cost a token each
+    assert_eq!(approx.count("日本語"), 3);
+    let text = "fn parse_configuration(input: &str) -> Result<Configuration> { todo!() }";
+    assert!(ApproxTokenizer::claude().count(text) >= ApproxTokenizer::gemini().count(text));
+}
+
+#[test]
+fn test_truncate_to_token_count() {
+    let approx = ApproxTokenizer::default();
+    let text = "one two three four five";
+    assert_eq!(approx.truncate(text, 100), text);
+    let cut = approx.truncate(text, 3);
+    assert!(text.starts_with(cut));
+    assert!(approx.count(cut) <= 3);
+    assert!(cut.starts_with("one two"));
+    assert_eq!(approx.truncate("日本語", 2), "日本");
+    assert_eq!(approx.truncate(text, 0), "");
+}
+
+#[test]
+fn test_context_token_budget() {
+    let tokenizer = ApproxTokenizer::default();
+    let mut context = Context::new();
+    context.add_message(Message::new(MessageRole::System, "be brief"));
+    for i in 0..10 {
+        context.add_message(Message::new(MessageRole::User, format!("question number {}", i)));
+    }
+    context.add_file_with_content(PathBuf::from("src/lib.rs"), "pub fn answer() -> u32 { 42 }".to_string());
+
+    let total = context.count_tokens(&tokenizer);
+    assert_eq!(context.estimate_tokens(), total);
+
+    let dropped = context.truncate_to_token_budget(&tokenizer, total / 2);
+    assert!(dropped > 0);
+    assert!(context.count_tokens(&tokenizer) <= total / 2);
+    assert_eq!(context.conversation_history[0].role, MessageRole::System);
+    assert_eq!(context.conversation_history.last().unwrap().content, "question number 9");
+    assert_eq!(context.file_contents.len(), 1);
+
+    // An unreachable budget keeps system messages and files
+    context.truncate_to_token_budget(&tokenizer, 0);
+    assert_eq!(context.conversation_history.len(), 1);
+    assert_eq!(context.file_contents.len(), 1);
+}
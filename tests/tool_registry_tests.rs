@@ -0,0 +1,195 @@
+use ai_cli::context::Redactor;
+use ai_cli::history::audit::AuditLog;
+use ai_cli::pipeline::{
+    BuiltinTool, PipelineDefinition, PipelineExecutor, PipelineStep, SafetyAction, SafetyFilter, SafetyRule, SafetyScope, ToolRegistry,
+    ToolsDefinition,
+};
+use ai_cli::providers::mock::MockProvider;
+use ai_cli::providers::{Context, ToolCall, ToolHandler};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+fn call(name: &str, input: serde_json::Value) -> ToolCall {
+    ToolCall { id: "toolu_1".to_string(), name: name.to_string(), input }
+}
+
+fn project() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+    std::fs::write(dir.path().join("src/lib.rs"), "pub fn answer() -> u32 { 42 }\n").unwrap();
+    std::fs::write(dir.path().join("secret.env"), "TOKEN=abc\n").unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_file_tools_stay_inside_allowed_paths() {
+    let dir = project();
+    let registry = ToolRegistry::new(dir.path())
+        .enable(BuiltinTool::ReadFile)
+        .enable(BuiltinTool::ListDir)
+        .allow_path("src");
+
+    let content = registry.call(&call("read_file", json!({"path": "src/lib.rs"}))).await.unwrap();
+    assert!(content.contains("answer"));
+    assert_eq!(registry.call(&call("list_dir", json!({"path": "src"}))).await.unwrap(), "lib.rs\nnested/");
+
+    let err = registry.call(&call("read_file", json!({"path": "secret.env"}))).await.unwrap_err();
+    assert!(err.to_string().contains("outside the directories"));
+    let err = registry.call(&call("read_file", json!({"path": "src/../secret.env"}))).await.unwrap_err();
+    assert!(err.to_string().contains("outside the directories"));
+    assert!(registry.call(&call("list_dir", json!({}))).await.is_err());
+    assert!(registry.call(&call("read_file", json!({"path": "/etc/hostname"}))).await.is_err());
+}
+
+#[tokio::test]
+async fn test_allowed_paths_cannot_reach_outside_the_root() {
+    let outside = project();
+    let dir = project();
+    let escape = outside.path().to_str().unwrap();
+    let registry = ToolRegistry::new(dir.path().join("src")).enable(BuiltinTool::ReadFile).allow_path("..").allow_path(escape);
+
+    let err = registry.call(&call("read_file", json!({"path": "../secret.env"}))).await.unwrap_err();
+    assert!(err.to_string().contains("outside the directories"), "{}", err);
+    let err = registry.call(&call("read_file", json!({"path": format!("{}/secret.env", escape)}))).await.unwrap_err();
+    assert!(err.to_string().contains("outside the directories"), "{}", err);
+
+    for path in ["..", "/etc", "src/../.."] {
+        let definition = ToolsDefinition { allow_paths: vec![path.into()], ..ToolsDefinition::default() };
+        assert!(ToolRegistry::from_definition(dir.path(), &definition).is_err(), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_disabled_and_unknown_tools_are_refused() {
+    let dir = project();
+    let registry = ToolRegistry::new(dir.path()).enable(BuiltinTool::ListDir);
+    let err = registry.call(&call("read_file", json!({"path": "src/lib.rs"}))).await.unwrap_err();
+    assert!(err.to_string().contains("not enabled"));
+    assert!(registry.call(&call("delete_file", json!({}))).await.is_err());
+    assert_eq!(registry.toolset().names(), vec!["list_dir"]);
+}
+
+#[tokio::test]
+async fn test_run_command_needs_approval_and_allowlist() {
+    let dir = project();
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let seen = asked.clone();
+    let approve_ls = move |command: &str| {
+        seen.lock().unwrap().push(command.to_string());
+        command.starts_with("ls")
+    };
+    let registry = ToolRegistry::new(dir.path())
+        .enable(BuiltinTool::RunCommand)
+        .allow_command("ls")
+        .allow_command("rm")
+        .with_approver(Arc::new(approve_ls));
+
+    let output = registry.call(&call("run_command", json!({"program": "ls", "args": ["src"]}))).await.unwrap();
+    assert!(output.starts_with("exit status: 0"));
+    assert!(output.contains("lib.rs"));
+
+    let err = registry.call(&call("run_command", json!({"program": "rm", "args": ["secret.env"]}))).await.unwrap_err();
+    assert!(err.to_string().contains("declined"));
+    assert!(dir.path().join("secret.env").exists());
+
+    let err = registry.call(&call("run_command", json!({"program": "curl"}))).await.unwrap_err();
+    assert!(err.to_string().contains("not an allowed command"));
+    assert_eq!(*asked.lock().unwrap(), vec!["ls src", "rm secret.env"]);
+}
+
+#[tokio::test]
+async fn test_command_confirmation_quotes_each_argument() {
+    let dir = project();
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let seen = asked.clone();
+    let refuse = move |command: &str| {
+        seen.lock().unwrap().push(command.to_string());
+        false
+    };
+    let registry = ToolRegistry::new(dir.path()).enable(BuiltinTool::RunCommand).with_approver(Arc::new(refuse));
+
+    let args = json!(["src", "a b; rm -rf /", "it's", "", "line\nbreak"]);
+    assert!(registry.call(&call("run_command", json!({"program": "ls", "args": args}))).await.is_err());
+    assert_eq!(asked.lock().unwrap()[0], r"ls src 'a b; rm -rf /' 'it'\''s' '' $'line\nbreak'");
+}
+
+#[tokio::test]
+async fn test_tool_results_are_redacted_filtered_and_audited() {
+    let dir = project();
+    let key = "sk-ant-REDACTED";
+    std::fs::write(dir.path().join("src/config.rs"), format!("const KEY: &str = \"{}\";\n", key)).unwrap();
+    std::fs::write(dir.path().join("src/plan.md"), "Project Falcon ships in May\n").unwrap();
+    let tools = ToolRegistry::new(dir.path()).enable(BuiltinTool::ReadFile).toolset();
+
+    let log = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let mut executor = PipelineExecutor::new();
+    executor.set_redactor(Arc::new(Redactor::new()));
+    executor.set_audit_log(log.clone());
+    let rule = SafetyRule {
+        name: "codename".to_string(),
+        pattern: None,
+        terms: vec!["Project Falcon".to_string()],
+        action: SafetyAction::Block,
+        scope: SafetyScope::Prompt,
+    };
+    executor.set_safety_filter(Arc::new(SafetyFilter::new().with_rule(&rule).unwrap()));
+    let provider = Arc::new(
+        MockProvider::new("claude")
+            .with_tool_call("read_file", json!({"path": "src/config.rs"}))
+            .with_tool_call("read_file", json!({"path": "src/plan.md"}))
+            .with_reply("done"),
+    );
+    executor.register_provider("claude", provider.clone());
+
+    let step = PipelineStep::new("claude", "Review the config").with_tools(tools);
+    executor.execute(&[step], Context::new()).await.unwrap();
+    let results = provider.tool_results();
+    let config = results[0].as_ref().unwrap();
+    assert!(!config.contains(key), "{}", config);
+    assert!(config.contains("[REDACTED:anthropic_api_key]"), "{}", config);
+    let blocked = results[1].as_ref().unwrap_err();
+    assert!(blocked.contains("blocked by content filter 'codename'"), "{}", blocked);
+    assert!(!blocked.contains("Falcon"));
+
+    let entries = log.read().unwrap();
+    let tool_entries: Vec<_> = entries.iter().filter(|e| e.status == "tool:read_file").collect();
+    assert_eq!(tool_entries.len(), 2);
+    assert!(tool_entries[0].prompt.as_deref().unwrap().contains("[REDACTED:anthropic_api_key]"));
+    assert!(tool_entries[1].error.is_some());
+    assert_eq!(tool_entries[0].step, 1);
+}
+
+#[tokio::test]
+async fn test_step_tools_in_definition() {
+    let yaml = r#"
+name: explore
+steps:
+  - provider: claude
+    action: Summarise the crate layout
+    tools:
+      enable: [read_file, list_dir]
+      allow_paths: [src]
+"#;
+    let def: PipelineDefinition = serde_yaml::from_str(yaml).unwrap();
+    def.validate().unwrap();
+    assert_eq!(def.steps[0].tools.as_ref().unwrap().allow_paths, vec![std::path::PathBuf::from("src")]);
+    let steps = def.to_steps().unwrap();
+    assert_eq!(steps[0].tools().unwrap().names(), vec!["read_file", "list_dir"]);
+
+    // Tools are rooted at the directory the caller names, then the step's working_dir
+    let dir = project();
+    let mut packaged = def.clone();
+    packaged.steps[0].tools.as_mut().unwrap().working_dir = Some("src".into());
+    packaged.steps[0].tools.as_mut().unwrap().allow_paths.clear();
+    let steps = packaged.to_steps_in(dir.path()).unwrap();
+    let handler = steps[0].tools().unwrap().handler.clone();
+    assert_eq!(handler.call(&call("list_dir", json!({}))).await.unwrap(), "lib.rs\nnested/");
+    assert!(handler.call(&call("read_file", json!({"path": "../secret.env"}))).await.is_err());
+    packaged.steps[0].tools.as_mut().unwrap().working_dir = Some("../elsewhere".into());
+    assert!(packaged.to_steps_in(dir.path()).is_err());
+
+    let mut bad = def.clone();
+    bad.steps[0].tools = Some(ToolsDefinition { enable: vec!["shell".to_string()], ..Default::default() });
+    let err = bad.validate().unwrap_err();
+    assert!(err.to_string().contains("Step 1: Unknown tool 'shell'"));
+}
@@ -0,0 +1,77 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::pipeline::{PipelineEvent, PipelineStep};
+use ai_cli::providers::Response;
+use ai_cli::tui::{Action, StepStatus, TuiState};
+use clap::Parser;
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::KeyCode;
+
+fn steps() -> Vec<PipelineStep> {
+    vec![PipelineStep::new("claude", "draft"), PipelineStep::new("gemini", "review")]
+}
+
+fn screen(state: &TuiState) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+    terminal.draw(|frame| state.render(frame)).unwrap();
+    let buffer = terminal.backend().buffer().clone();
+    (0..buffer.area.height)
+        .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_state_follows_events() {
+    let mut state = TuiState::new(&steps());
+    state.apply(&PipelineEvent::StepStarted { step_index: 0, provider: "claude".into() });
+    state.apply(&PipelineEvent::Chunk { step_index: 0, text: "Once upon".into() });
+    state.apply(&PipelineEvent::Chunk { step_index: 0, text: " a time".into() });
+    assert_eq!(state.steps[0].status, StepStatus::Running);
+    assert_eq!(state.steps[0].output, "Once upon a time");
+
+    let response = Response::new("Once upon a time.")
+        .with_metadata("prompt_tokens", "12")
+        .with_metadata("completion_tokens", "5")
+        .with_metadata("cost_usd", "0.000250");
+    state.apply(&PipelineEvent::StepCompleted {
+        step_index: 0, provider: "claude".into(), elapsed_ms: 10, retries: 0, outcome: Ok(response),
+    });
+    state.apply(&PipelineEvent::StepStarted { step_index: 1, provider: "gemini".into() });
+    state.apply(&PipelineEvent::StepCompleted {
+        step_index: 1, provider: "gemini".into(), elapsed_ms: 10, retries: 1, outcome: Err("quota exceeded".into()),
+    });
+
+    assert_eq!(state.steps[0].status, StepStatus::Done);
+    assert_eq!(state.steps[1].status, StepStatus::Failed("quota exceeded".into()));
+    assert_eq!(state.selected, 1);
+    assert_eq!(state.totals(), (12, 5, 0.00025));
+
+    let text = screen(&state);
+    assert!(text.contains("1/2 steps  tokens 12 in / 5 out  $0.0003"), "{}", text);
+    assert!(text.contains("✓ 1. claude:draft"));
+    assert!(text.contains("✗ 2. gemini:review"));
+    assert!(text.contains("Error: quota exceeded"));
+    assert!(text.contains("r retry  s skip  q quit"));
+
+    state.select(Action::Up);
+    state.skip(1);
+    assert_eq!(state.selected, 0);
+    assert!(screen(&state).contains("Once upon a time."));
+}
+
+#[test]
+fn test_key_bindings() {
+    assert_eq!(Action::for_key(KeyCode::Char('r')), Some(Action::Retry));
+    assert_eq!(Action::for_key(KeyCode::Char('s')), Some(Action::Skip));
+    assert_eq!(Action::for_key(KeyCode::Esc), Some(Action::Quit));
+    assert_eq!(Action::for_key(KeyCode::Char('j')), Some(Action::Down));
+    assert_eq!(Action::for_key(KeyCode::Enter), None);
+}
+
+#[test]
+fn test_tui_flag() {
+    let args = <CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:draft", "--tui"]).unwrap();
+    assert!(matches!(args.command, Some(Command::Pipeline { tui: true, .. })));
+    assert!(<CliArgs as Parser>::try_parse_from(["ai-cli", "pipeline", "--chain", "claude:x", "--tui", "--input-file", "in.jsonl"]).is_err());
+}
@@ -0,0 +1,82 @@
+use ai_cli::cli::{CliArgs, Command};
+use ai_cli::context::{ContextLoader, FileWatcher};
+use clap::Parser;
+use std::time::Duration;
+
+#[test]
+fn test_watcher_reports_added_modified_and_removed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::create_dir(root.join("src")).unwrap();
+    std::fs::write(root.join("src/lib.rs"), "fn a() {}").unwrap();
+    std::fs::write(root.join("src/old.rs"), "fn old() {}").unwrap();
+    std::fs::write(root.join(".aiignore"), "*.log\n").unwrap();
+
+    let loader = ContextLoader::new(root).unwrap();
+    let mut watcher = FileWatcher::new(loader, root, vec!["src".to_string()]).unwrap();
+    assert_eq!(watcher.len(), 2);
+    assert_eq!(watcher.files(), vec![root.join("src/lib.rs"), root.join("src/old.rs")]);
+    assert!(watcher.poll().is_empty());
+
+    std::fs::write(root.join("src/lib.rs"), "fn a() { b() }").unwrap();
+    std::fs::write(root.join("src/new.rs"), "fn b() {}").unwrap();
+    std::fs::remove_file(root.join("src/old.rs")).unwrap();
+    std::fs::write(root.join("src/debug.log"), "ignored").unwrap();
+    assert_eq!(watcher.poll(), vec![root.join("src/lib.rs"), root.join("src/new.rs"), root.join("src/old.rs")]);
+    assert!(watcher.poll().is_empty());
+}
+
+#[test]
+fn test_watcher_rejects_a_path_that_does_not_exist() {
+    let dir = tempfile::tempdir().unwrap();
+    let loader = ContextLoader::new(dir.path()).unwrap();
+    assert!(FileWatcher::new(loader, dir.path(), vec!["missing".to_string()]).is_err());
+}
+
+#[tokio::test]
+async fn test_watcher_collects_a_burst_of_changes_into_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    std::fs::write(root.join("a.rs"), "a").unwrap();
+    std::fs::write(root.join("b.rs"), "b").unwrap();
+    let loader = ContextLoader::new(&root).unwrap();
+    let mut watcher = FileWatcher::new(loader, &root, vec![".".to_string()]).unwrap();
+
+    let writer = {
+        let root = root.clone();
+        tokio::spawn(async move {
+            std::fs::write(root.join("a.rs"), "aa").unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            std::fs::write(root.join("b.rs"), "bb").unwrap();
+        })
+    };
+    let changed = tokio::time::timeout(Duration::from_secs(5), watcher.next_change(Duration::from_millis(400)))
+        .await
+        .unwrap();
+    writer.await.unwrap();
+    assert_eq!(changed, vec![root.join("a.rs"), root.join("b.rs")]);
+}
+
+#[test]
+fn test_watch_subcommand_parses() {
+    let args = CliArgs::try_parse_from([
+        "ai-cli", "watch", "--chain", "claude:review", "--paths", "src/", "tests/", "--debounce", "1000",
+    ]).unwrap();
+    match args.command {
+        Some(Command::Watch { chain, paths, debounce, .. }) => {
+            assert_eq!(chain.as_deref(), Some("claude:review"));
+            assert_eq!(paths, vec!["src/", "tests/"]);
+            assert_eq!(debounce, 1000);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+
+    let args = CliArgs::try_parse_from(["ai-cli", "watch"]).unwrap();
+    match args.command {
+        Some(Command::Watch { paths, debounce, .. }) => {
+            assert_eq!(paths, vec!["."]);
+            assert_eq!(debounce, 500);
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+}
@@ -0,0 +1,70 @@
+use ai_cli::context::{PackageKind, Workspace};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write(root: &Path, rel: &str, text: &str) {
+    let path = root.join(rel);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+}
+
+#[test]
+fn test_cargo_workspace_members() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/legacy\"]\n");
+    write(root, "crates/core/Cargo.toml", "[package]\nname = \"app-core\"\n");
+    write(root, "crates/cli/Cargo.toml", "[package]\nname = \"app-cli\"\n");
+    write(root, "crates/legacy/Cargo.toml", "[package]\nname = \"legacy\"\n");
+
+    // Discovery from deep inside a member finds the workspace root
+    let ws = Workspace::discover(&root.join("crates/core")).unwrap();
+    assert_eq!(ws.root, root);
+    let names: Vec<&str> = ws.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["app-cli", "app-core"]);
+
+    let core = ws.package("app-core").unwrap();
+    assert_eq!(core.kind, PackageKind::Cargo);
+    assert!(core.contains(&root.join("crates/core/src/lib.rs")));
+    assert!(!core.contains(&root.join("crates/cli/src/main.rs")));
+
+    let err = ws.package("legacy").unwrap_err().to_string();
+    assert!(err.contains("Unknown package 'legacy'"));
+}
+
+#[test]
+fn test_npm_and_pnpm_workspaces() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "package.json", r#"{"name": "monorepo", "private": true, "workspaces": {"packages": ["apps/*"]}}"#);
+    write(root, "pnpm-workspace.yaml", "packages:\n  - 'libs/*'\n  - '!libs/skip'\n");
+    write(root, "apps/web/package.json", r#"{"name": "@acme/web"}"#);
+    write(root, "libs/ui/package.json", r#"{"name": "@acme/ui"}"#);
+
+    let ws = Workspace::discover(root).unwrap();
+    let names: Vec<&str> = ws.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["@acme/ui", "@acme/web", "monorepo"]);
+    assert_eq!(ws.package("@acme/ui").unwrap().kind, PackageKind::Npm);
+}
+
+#[test]
+fn test_uv_workspace_and_single_package_fallback() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write(root, "pyproject.toml", "[tool.uv.workspace]\nmembers = [\"packages/*\"]\n");
+    write(root, "packages/etl/pyproject.toml", "[project]\nname = \"etl\"\n");
+    let ws = Workspace::discover(&root.join("packages")).unwrap();
+    assert_eq!(ws.package("etl").unwrap().kind, PackageKind::Python);
+
+    let single = TempDir::new().unwrap();
+    write(single.path(), "pyproject.toml", "[tool.poetry]\nname = \"solo\"\n");
+    let ws = Workspace::discover(single.path()).unwrap();
+    assert_eq!(ws.package("solo").unwrap().root, single.path());
+}
+
+#[test]
+fn test_discover_without_manifest_fails() {
+    let dir = TempDir::new().unwrap();
+    assert!(Workspace::discover(dir.path()).is_err());
+}